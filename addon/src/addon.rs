@@ -1,37 +1,101 @@
 use std::{
     ffi::{c_void, CStr, CString},
-    mem::swap,
+    fs, mem,
     ops::{Deref, DerefMut},
     os::raw::{c_char, c_int, c_short},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+    rc::Rc,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use fabric_codegen::cstr;
-use fabric_runtime::load_module;
-use log::{info, warn};
+use fabric_runtime::{load_module, take_host_calls, take_host_panic, VMContext};
+use log::{error, info, warn};
 
 use crate::{
-    foreign::{create_interface, CreateInterfaceFn},
-    manager::{FabricListener, GameEventManager2},
-    module::{FabricEnv, Module},
+    bans, bot, command, config,
+    deferred::{self, Capability},
+    effects,
+    engine::{self, game_dir},
+    entity,
+    executor::{self, run_completions},
+    flood,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+    host::{
+        self, bus,
+        effects::precache_models,
+        globals,
+        job::run_jobs,
+        kv, menu, shared,
+        sound::precache_sounds,
+        timer::{advance_clock, run_timers},
+        vote,
+    },
+    inspect,
+    loader::{self, ModuleSource, EVAL_MODULE},
+    logging::{self, ModuleScope},
+    manager::{self, GameEventManager2, ListenerCallback, Subscription},
+    message, metrics,
+    module::{
+        find_module, refuel, release_externs, resume, set_paused, set_plugin_paused,
+        take_unload_request, FabricEnv, Module,
+    },
+    netprops, remote,
+    schema::EventSchema,
+    selftest, sound, stats, thread, tools, trace,
 };
 
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct Edict {
     state_flags: c_int,
-    edict_index: c_short,
-    network_serial_number: c_short,
+    /// Index and serial number of the edict, their order depends on the branch
+    numbers: [c_short; 2],
     networkable: *mut c_void,
     unk: *mut c_void,
     freetime: f32,
 }
 
+/// The edict slot is unused
+const FL_EDICT_FREE: c_int = 1 << 1;
+
+impl Edict {
+    fn number(&self, offset: usize) -> c_int {
+        let ptr = self as *const Edict as *const u8;
+        let number = unsafe { *(ptr.add(offset) as *const c_short) };
+        number.into()
+    }
+
+    /// Entity index of the edict
+    pub(crate) fn index(&self) -> c_int {
+        self.number(game::info().edict.index)
+    }
+
+    /// Serial number of the edict, incremented every time the slot is reused
+    pub(crate) fn serial_number(&self) -> c_int {
+        self.number(game::info().edict.serial_number)
+    }
+
+    pub(crate) fn is_free(&self) -> bool {
+        self.state_flags & FL_EDICT_FREE != 0
+    }
+
+    /// IServerUnknown of the entity, or null if the edict is free
+    pub(crate) fn unknown(&self) -> *mut c_void {
+        self.unk
+    }
+}
+
 const COMMAND_MAX_ARGC: usize = 64;
 const COMMAND_MAX_LENGTH: usize = 512;
 
 #[repr(C)]
 #[derive(Debug)]
+#[allow(dead_code)]
 pub(crate) struct CCommand {
     argc: c_int,
     argv0_size: c_int,
@@ -40,6 +104,108 @@ pub(crate) struct CCommand {
     argv: [*const c_char; COMMAND_MAX_ARGC],
 }
 
+/// Arguments of a console command, read from the CCommand object of the engine
+///
+/// The counts and offsets of the object are checked against the size of its
+/// buffers, an argument out of bounds or without a NUL terminator reads as an
+/// empty string like the missing arguments
+#[derive(Clone, Copy)]
+pub(crate) struct CommandArgs<'a> {
+    command: &'a CCommand,
+}
+
+impl<'a> CommandArgs<'a> {
+    /// Wrap the command passed by the engine, returns None if it's null
+    ///
+    /// # Safety
+    /// `command` must be null or point to a CCommand that stays valid for `'a`
+    pub(crate) unsafe fn from_ptr(command: *const CCommand) -> Option<Self> {
+        Some(CommandArgs {
+            command: command.as_ref()?,
+        })
+    }
+
+    /// Number of arguments, including the name of the command
+    pub(crate) fn argc(&self) -> usize {
+        (self.command.argc.max(0) as usize).min(COMMAND_MAX_ARGC)
+    }
+
+    /// Argument `index`, the name of the command being the argument 0
+    pub(crate) fn arg(&self, index: usize) -> &'a CStr {
+        if index >= self.argc() {
+            return cstr!("");
+        }
+
+        // The arguments point into `argv_buffer`
+        let buffer = &self.command.argv_buffer;
+        let offset = (self.command.argv[index] as usize).wrapping_sub(buffer.as_ptr() as usize);
+        match buffer.get(offset..) {
+            Some(arg) => until_nul(arg),
+            None => cstr!(""),
+        }
+    }
+
+    /// Arguments of the command as strings, the first one being the name of the command
+    pub(crate) fn args(&self) -> Vec<String> {
+        (0..self.argc())
+            .map(|index| self.arg(index).to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+/// The NUL-terminated string at the start of `buffer`, or an empty
+/// string if `buffer` doesn't contain a NUL character
+fn until_nul(buffer: &[c_char]) -> &CStr {
+    let bytes = unsafe { &*(buffer as *const [c_char] as *const [u8]) };
+    match bytes.iter().position(|byte| *byte == 0) {
+        Some(end) => unsafe { CStr::from_bytes_with_nul_unchecked(&bytes[..=end]) },
+        None => cstr!(""),
+    }
+}
+
+/// Bounded writer of the rejection message of `client_connect` into the
+/// buffer provided by the engine
+pub(crate) struct RejectWriter {
+    buffer: *mut c_char,
+    capacity: usize,
+}
+
+impl RejectWriter {
+    /// # Safety
+    /// `buffer` must be null or valid for writes of `len` bytes
+    pub(crate) unsafe fn new(buffer: *mut c_char, len: c_int) -> Self {
+        let capacity = if buffer.is_null() {
+            0
+        } else {
+            len.max(0) as usize
+        };
+        RejectWriter { buffer, capacity }
+    }
+
+    /// Write `message` to the buffer, stopping at its first NUL character
+    ///
+    /// The message is truncated to the last character fitting in the buffer
+    /// along with its NUL terminator, returns false if it was truncated
+    pub(crate) fn write(&mut self, message: &str) -> bool {
+        let message = message.split('\0').next().unwrap_or_default();
+        if self.capacity == 0 {
+            return message.is_empty();
+        }
+
+        let mut len = message.len().min(self.capacity - 1);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, self.buffer, len);
+            self.buffer.add(len).write(0);
+        }
+
+        len == message.len()
+    }
+}
+
 #[repr(C)]
 #[allow(dead_code)]
 pub(crate) enum PluginResult {
@@ -51,7 +217,14 @@ pub(crate) enum PluginResult {
     Stop,
 }
 
-type QueryCvarCookie = c_int;
+/// Returned to the engine by the callbacks that panicked
+impl Default for PluginResult {
+    fn default() -> Self {
+        PluginResult::Continue
+    }
+}
+
+pub(crate) type QueryCvarCookie = c_int;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -157,131 +330,1168 @@ pub(crate) trait ServerPluginCallbacks {
 /// in the addon host environment
 pub(crate) struct FabricAddon {
     modules: Vec<Module>,
+    /// Event descriptors loaded with the modules, reused by `fabric_eval`
+    schema: Option<Rc<EventSchema>>,
+    /// Engine and server factories the addon was loaded with,
+    /// probed by `fabric_dump_interfaces`
+    factories: Option<(CreateInterfaceFn, CreateInterfaceFn)>,
 }
 
-impl ServerPluginCallbacks for FabricAddon {
-    fn load(&mut self, factory: CreateInterfaceFn, server: CreateInterfaceFn) -> bool {
-        info!("load {:?} {:?}", factory, server);
+/// Compile and start a module, then register its event listeners if the
+/// game event manager was acquired
+fn load_source(
+    manager: Option<&mut Foreign<dyn GameEventManager2>>,
+    source: ModuleSource,
+    schema: &Rc<EventSchema>,
+) -> Option<Module> {
+    let module = Arc::new(Mutex::new(instantiate(&source, schema)?));
+    if let Some(manager) = manager {
+        register_listeners(manager, &module);
+    }
 
-        if let Some(mut manager) =
-            create_interface::<dyn GameEventManager2>(factory, cstr!("GAMEEVENTSMANAGER002"))
-        {
-            static SOURCE: &str = include_str!("../example.wat");
+    Some(module)
+}
 
-            let mut module = load_module(
-                FabricEnv {
-                    listeners: Vec::new(),
-                },
-                SOURCE,
-            );
+/// Compile and start a module, returns None if it panicked while starting
+fn instantiate(source: &ModuleSource, schema: &Rc<EventSchema>) -> Option<VMContext<FabricEnv>> {
+    match &source.manifest {
+        Some(manifest) => info!(
+            "loading module {} {} by {} from {}",
+            source.name,
+            manifest.version,
+            manifest.author,
+            source.path.display()
+        ),
+        None => info!(
+            "loading module {} from {}",
+            source.name,
+            source.path.display()
+        ),
+    }
 
-            // The `listeners` list wont be needed anymore in the environment,
-            // swap it with an empty one and consume it in the initialization loop
-            let mut listeners = Vec::new();
-            swap(&mut module.environment.listeners, &mut listeners);
-
-            let module = Arc::new(Mutex::new(module));
-
-            for listener in listeners {
-                let event = match CString::new(listener.event.as_bytes()) {
-                    Ok(event) => event,
-                    Err(err) => {
-                        warn!("CString::new({:?}): {}", listener.event, err);
-                        continue;
-                    }
-                };
-
-                let is_ok = manager.add_listener(
-                    Box::new(FabricListener {
-                        module: module.clone(),
-                        listener: listener.listener,
-                    }),
-                    &event,
+    thread::assert_game_thread("module instantiation");
+    let environment = FabricEnv::new(&source.name, source.desc(), schema.clone());
+
+    // A module that panics while it is being instantiated is not loaded
+    let module = catch_unwind(AssertUnwindSafe(|| {
+        let _scope = ModuleScope::enter(&source.name);
+        load_module(environment, &source.source)
+    }));
+    match module {
+        Ok(module) if !take_host_panic() => Some(module),
+        _ => {
+            warn!("module {} panicked while loading", source.name);
+            None
+        }
+    }
+}
+
+/// Register the event listeners declared by `module` while it was starting
+/// with the dispatchers of their events, in the order of its priority
+fn register_listeners(manager: &mut Foreign<dyn GameEventManager2>, module: &Module) {
+    // The listeners are kept in the environment with their
+    // dispatch counts, only the ones declared on load are registered
+    let (listeners, name, generation, priority) = {
+        let lock = module.lock().unwrap();
+        let listeners: Vec<_> = lock
+            .environment
+            .listeners
+            .iter()
+            .map(|listener| {
+                (
+                    listener.callback,
+                    listener.event.clone(),
                     listener.server_side,
-                );
+                )
+            })
+            .collect();
+
+        (
+            listeners,
+            lock.environment.name.clone(),
+            lock.environment.generation,
+            lock.environment.desc.priority,
+        )
+    };
+
+    for (index, (callback, event_name, server_side)) in listeners.into_iter().enumerate() {
+        let event = match CString::new(event_name.as_bytes()) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("CString::new({:?}): {}", event_name, err);
+                continue;
+            }
+        };
+
+        let subscription = Subscription {
+            module: module.clone(),
+            callback,
+            index,
+            name: name.clone(),
+            generation,
+            priority,
+        };
+
+        match manager::subscribe(manager, &event, server_side, subscription) {
+            Ok(()) => module.lock().unwrap().environment.listeners[index].registered = true,
+            Err(err) => warn!("could not add event listener for {}: {}", event_name, err),
+        }
+    }
+}
+
+/// Replace the running instance of `module` with a new instance compiled
+/// from `source`, handing the state saved by the old instance to the new one
+///
+/// The new instance replaces the old one in place so the references to
+/// the module held by the addon stay valid. Its start function registers
+/// its listeners, commands and callbacks again, the listeners of the old
+/// instance are removed from the dispatchers. The old instance keeps
+/// running if the new one can't be started
+fn reload(
+    manager: &mut Foreign<dyn GameEventManager2>,
+    module: &Module,
+    source: ModuleSource,
+    schema: &Rc<EventSchema>,
+) -> bool {
+    let mut instance = match instantiate(&source, schema) {
+        Some(instance) => instance,
+        None => return false,
+    };
+
+    let state = host::state::snapshot(module);
+
+    // Release what the old instance holds outside of its environment,
+    // the callbacks of its menus, votes and listeners can't be called anymore
+    kv::flush(module);
+    host::entity::remove_entities(module);
+    menu::forget(&source.name);
+    vote::forget(&source.name);
+    manager::unsubscribe(&source.name);
+
+    {
+        let mut lock = module.lock().unwrap();
+        instance.environment.generation = lock.environment.generation.wrapping_add(1);
+        let mut previous = mem::replace(&mut *lock, instance);
+        // The events deferred for the previous instance are ignored
+        // by their generation, nothing can call its functions anymore
+        release_externs(&mut previous);
+        unsafe { previous.unload() };
+    }
+
+    register_listeners(manager, module);
+
+    if let Some(state) = state {
+        host::state::restore_from(module, state);
+    }
+
+    true
+}
+
+/// Compile the module `name` again from its file and swap it with the running
+/// instance, returns a description of the error if the running instance is kept
+pub(crate) fn reload_by_name(
+    modules: &[Module],
+    schema: Option<&Rc<EventSchema>>,
+    name: &str,
+) -> Result<(), String> {
+    let module = match find_module(modules, name) {
+        Some(module) => module,
+        None => return Err(format!("module {} not found", name)),
+    };
+
+    let source = match loader::discover()
+        .into_iter()
+        .find(|source| source.name == *name)
+    {
+        Some(source) => source,
+        None => return Err(format!("could not find the source of module {}", name)),
+    };
+
+    let mut manager = match manager::manager() {
+        Some(manager) => manager,
+        None => return Err(String::from("the game event manager is not available")),
+    };
+
+    let schema = schema.cloned().unwrap_or_default();
+    if reload(&mut manager, module, source, &schema) {
+        Ok(())
+    } else {
+        Err(format!(
+            "could not reload module {}, the running instance is kept",
+            name
+        ))
+    }
+}
 
-                if !is_ok {
-                    warn!("could not add event listener for {}", listener.event);
+/// Handler of the `fabric_reload` console command, compiles the module
+/// again from its file and swaps it with the running instance
+fn reload_module(args: &[String]) {
+    let name = match args.get(1) {
+        Some(name) => name,
+        None => {
+            warn!("usage: fabric_reload <module>");
+            return;
+        }
+    };
+
+    let addon = unsafe { &INSTANCE.instance };
+    match reload_by_name(&addon.modules, addon.schema.as_ref(), name) {
+        Ok(()) => info!("reloaded module {}", name),
+        Err(err) => warn!("{}", err),
+    }
+}
+
+/// Handler of the `fabric_eval` console command, compiles a WAT snippet
+/// against the host environment and runs its start function
+///
+/// The snippet is either a complete `(module ...)` or a list of fields
+/// wrapped in one. The module is dropped once its start function returns,
+/// its listeners, timers and callbacks are never called
+fn eval_module(args: &[String]) {
+    let snippet = args[1..].join(" ");
+    let snippet = snippet.trim();
+    if snippet.is_empty() {
+        warn!("usage: fabric_eval \"<wat>\"");
+        return;
+    }
+
+    let source = if snippet.starts_with("(module") {
+        snippet.to_string()
+    } else {
+        format!("(module {})", snippet)
+    };
+
+    let source = ModuleSource::new(PathBuf::from("<eval>"), source);
+    if let Err(err) = source.check() {
+        warn!("could not evaluate snippet: {}", err);
+        return;
+    }
+
+    let schema = unsafe { INSTANCE.instance.schema.clone() }.unwrap_or_default();
+    let environment = FabricEnv::new(EVAL_MODULE, source.desc(), schema);
+
+    take_host_calls();
+    let start = Instant::now();
+
+    let module = catch_unwind(AssertUnwindSafe(|| {
+        let _scope = ModuleScope::enter(EVAL_MODULE);
+        load_module(environment, &source.source)
+    }));
+
+    let elapsed = start.elapsed();
+    let host_calls = take_host_calls();
+
+    let module = match module {
+        Ok(module) if !take_host_panic() => module,
+        _ => {
+            warn!("snippet panicked after {}", stats::format_ms(elapsed));
+            return;
+        }
+    };
+
+    info!(
+        "snippet ran in {} with {} host calls{}",
+        stats::format_ms(elapsed),
+        host_calls,
+        if module.is_out_of_fuel() {
+            ", out of fuel"
+        } else {
+            ""
+        }
+    );
+
+    if !module.environment.listeners.is_empty() {
+        info!(
+            "discarded {} event listeners",
+            module.environment.listeners.len()
+        );
+    }
+
+    // Entities created by the snippet are removed with the module
+    let module = Arc::new(Mutex::new(module));
+    host::entity::remove_entities(&module);
+
+    let mut lock = module.lock().unwrap();
+    release_externs(&mut lock);
+    unsafe { lock.unload() };
+}
+
+/// Handler of the `fabric_list` console command, the
+/// `-deps` flag also prints the dependency graph
+fn list_modules(args: &[String]) {
+    let show_deps = args.iter().skip(1).any(|arg| arg == "-deps");
+
+    // Modules that are currently running can't be inspected
+    let modules = unsafe { &INSTANCE.instance.modules };
+    let modules: Vec<_> = modules
+        .iter()
+        .filter_map(|module| module.try_lock().ok())
+        .collect();
+
+    info!("{} modules loaded", modules.len());
+
+    for module in &modules {
+        let env = &module.environment;
+        info!(
+            "{} {} by {} ({}, priority {}){}{}",
+            env.name,
+            env.desc.version,
+            env.desc.author,
+            env.desc.path.display(),
+            env.desc.priority,
+            if env.failed { " [failed]" } else { "" },
+            if env.paused { " [paused]" } else { "" }
+        );
+
+        if !show_deps {
+            continue;
+        }
+
+        for requirement in &env.desc.requires {
+            let version = modules
+                .iter()
+                .find(|other| other.environment.name == requirement.name)
+                .map_or("", |other| &other.environment.desc.version);
+
+            info!("  requires {} (found {:?})", requirement, version);
+        }
+
+        let dependents: Vec<_> = modules
+            .iter()
+            .map(|other| &other.environment)
+            .filter(|other| {
+                other
+                    .desc
+                    .requires
+                    .iter()
+                    .any(|requirement| requirement.name == env.name)
+            })
+            .map(|other| other.name.as_str())
+            .collect();
+
+        if !dependents.is_empty() {
+            info!("  required by {}", dependents.join(", "));
+        }
+    }
+}
+
+/// Handler of the `fabric_dump_events` console command, lists the event
+/// listeners of every module, or only of the module named in the arguments
+fn dump_events(args: &[String]) {
+    let filter = args.get(1);
+
+    let modules = unsafe { &INSTANCE.instance.modules };
+    for module in modules {
+        // Modules that are currently running can't be inspected
+        let module = match module.try_lock() {
+            Ok(module) => module,
+            Err(_) => continue,
+        };
+
+        let env = &module.environment;
+        if filter.is_some_and(|filter| *filter != env.name) {
+            continue;
+        }
+
+        info!("{}: {} event listeners", env.name, env.listeners.len());
+
+        let now = Instant::now();
+        for listener in &env.listeners {
+            let last_fired = match listener.last_fired {
+                Some(time) => format!(", last {:.1}s ago", now.duration_since(time).as_secs_f32()),
+                None => String::new(),
+            };
+
+            info!(
+                "  {} ({}) -> {} {}{}: fired {} times{}{}",
+                listener.event,
+                if listener.server_side {
+                    "server"
+                } else {
+                    "client"
+                },
+                if listener.callback.function().is_table() {
+                    "table"
+                } else {
+                    "func"
+                },
+                listener.callback.function().index(),
+                match listener.callback {
+                    ListenerCallback::Observe(_) => "",
+                    ListenerCallback::Consume(_) => " [consumes]",
+                },
+                listener.fired,
+                last_fired,
+                if listener.registered {
+                    ""
+                } else {
+                    " [not registered]"
                 }
+            );
+        }
+    }
+}
+
+/// Handler of the `fabric_dump_interfaces` console command, probes the engine
+/// and server factories for the known interface versions and prints the
+/// ones they expose, then the versions the addon selected on this branch
+fn dump_interfaces(_args: &[String]) {
+    let (engine, server) = match unsafe { INSTANCE.instance.factories } {
+        Some(factories) => factories,
+        None => {
+            warn!("the addon is not loaded");
+            return;
+        }
+    };
+
+    let factories = [("engine", engine), ("server", server)];
+    let info = game::info();
+
+    info!(
+        "{} engine, running {} {}",
+        info.branch,
+        info.product.as_deref().unwrap_or("(unknown game)"),
+        info.version.as_deref().unwrap_or("(unknown version)")
+    );
+
+    for (factory_name, factory) in &factories {
+        info!("{} factory:", factory_name);
+
+        for version in game::KNOWN_INTERFACES {
+            let version = match CString::new(*version) {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+
+            if let Some(pointer) = game::probe(*factory, &version) {
+                info!("  {} {:?}", version.to_string_lossy(), pointer);
             }
+        }
+    }
 
-            self.modules.push(module);
-        } else {
-            warn!("GAMEEVENTSMANAGER002 not found");
+    info!("interfaces used on {}:", info.branch);
+
+    for (interface, version) in &info.interfaces.entries() {
+        let version = match version {
+            Some(version) => version,
+            None => {
+                info!("  {}: not supported", interface);
+                continue;
+            }
+        };
+
+        let found = factories.iter().find_map(|(factory_name, factory)| {
+            game::probe(*factory, version).map(|pointer| (factory_name, pointer))
+        });
+
+        match found {
+            Some((factory_name, pointer)) => info!(
+                "  {}: {} {:?} ({} factory)",
+                interface,
+                version.to_string_lossy(),
+                pointer,
+                factory_name
+            ),
+            None => info!("  {}: {} [not found]", interface, version.to_string_lossy()),
         }
+    }
+}
+
+/// Handler of the `fabric_pause` console command
+fn pause_module(args: &[String]) {
+    set_module_paused(args, true);
+}
+
+/// Handler of the `fabric_unpause` console command
+fn unpause_module(args: &[String]) {
+    set_module_paused(args, false);
+}
+
+fn set_module_paused(args: &[String], paused: bool) {
+    let name = match args.get(1) {
+        Some(name) => name,
+        None => {
+            let command = if paused {
+                "fabric_pause"
+            } else {
+                "fabric_unpause"
+            };
+            info!("usage: {} <module>", command);
+            return;
+        }
+    };
+
+    let modules = unsafe { &INSTANCE.instance.modules };
+    for module in modules {
+        let mut module = match module.try_lock() {
+            Ok(module) => module,
+            Err(_) => continue,
+        };
+
+        if module.environment.name == *name {
+            set_paused(&mut module, paused);
+            info!("{} {}", if paused { "paused" } else { "unpaused" }, name);
+            return;
+        }
+    }
+
+    warn!("module {} not found", name);
+}
+
+/// Handler of the `fabric_trace` console command, toggles the logging of
+/// the host calls of a module with their arguments and of its callbacks
+fn trace_module(args: &[String]) {
+    let name = match args.get(1) {
+        Some(name) => name,
+        None => {
+            info!("usage: fabric_trace <module>");
+            return;
+        }
+    };
+
+    let modules = unsafe { &INSTANCE.instance.modules };
+    for module in modules {
+        let module = match module.try_lock() {
+            Ok(module) => module,
+            Err(_) => continue,
+        };
+
+        let env = &module.environment;
+        if env.name == *name {
+            let tracing = !env.tracing.get();
+            env.tracing.set(tracing);
+            info!("tracing {} {}", name, if tracing { "on" } else { "off" });
+            return;
+        }
+    }
+
+    warn!("module {} not found", name);
+}
+
+/// Handler of the `fabric_mem` console command
+fn inspect_memory(args: &[String]) {
+    inspect::command(unsafe { &INSTANCE.instance.modules }, args);
+}
+
+/// Handler of the `fabric_priority` console command, sets the priority of
+/// a module or resets it to its configured value with `reset`
+///
+/// The modules are already registered with the engine in load order,
+/// the new priority applies the next time the addon is loaded
+fn set_module_priority(args: &[String]) {
+    let (name, value) = match (args.get(1), args.get(2)) {
+        (Some(name), Some(value)) => (name, value),
+        _ => {
+            info!("usage: fabric_priority <module> <priority|reset>");
+            return;
+        }
+    };
+
+    let priority = if value == "reset" {
+        None
+    } else {
+        match value.parse() {
+            Ok(priority) => Some(priority),
+            Err(err) => {
+                warn!("invalid priority {:?}: {}", value, err);
+                return;
+            }
+        }
+    };
+
+    loader::set_priority(name, priority);
+
+    match priority {
+        Some(priority) => info!(
+            "module {} will be loaded with priority {} on the next start",
+            name, priority
+        ),
+        None => info!(
+            "module {} will be loaded with its configured priority on the next start",
+            name
+        ),
+    }
+}
+
+/// Handler of the `fabric_reload_config` console command, loads the
+/// configuration file again and notifies the modules of the change
+fn reload_config(_args: &[String]) {
+    if !config::reload() {
+        warn!("the configuration was never loaded");
+        return;
+    }
+
+    info!("reloaded the configuration");
+
+    let modules = unsafe { &INSTANCE.instance.modules };
+    for module in modules {
+        host::config::notify_changed(module);
+    }
+}
+
+/// Handler of the console commands registered by the modules, shared
+/// by all the commands and dispatched on the name of the command
+pub(crate) fn module_command(args: &[String]) {
+    let modules = unsafe { &INSTANCE.instance.modules };
+    if !host::command::dispatch(modules, args) {
+        warn!("no module handles the command {:?}", args.first());
+    }
+
+    // The engine only sets the command client for the commands of the clients
+    host::command::set_command_client(-1);
+}
+
+/// Log file used when the engine doesn't export the tier0
+/// logging system, relative to the addon directory
+const FALLBACK_LOG_FILE: &str = "logs/fabric.log";
+
+/// Name of the file the statistics are written to by `fabric_stats -json`
+const STATS_FILE: &str = "stats.json";
+
+/// Handler of the `fabric_stats` console command, the `-json` flag also
+/// writes the statistics to a file in the addon directory for monitoring
+fn module_stats(args: &[String]) {
+    let write_json = args.iter().skip(1).any(|arg| arg == "-json");
+
+    let modules = unsafe { &INSTANCE.instance.modules };
+    let config = config::get();
+    let mut dump = serde_json::Map::new();
+
+    for module in modules {
+        let module = match module.try_lock() {
+            Ok(module) => module,
+            Err(_) => continue,
+        };
+
+        let env = &module.environment;
+        let budget = config.budget(&env.name);
+
+        info!(
+            "{}: {} events, {} host calls, {} bytes of memory, {} live externs{}",
+            env.name,
+            env.stats.events(),
+            env.stats.host_calls,
+            module.memory.len(),
+            module.externs.len(),
+            if env.failed { " [failed]" } else { "" }
+        );
+
+        for (kind, timings) in &env.stats.callbacks {
+            info!(
+                "  {}: {} calls, average {}, 99p {}",
+                kind,
+                timings.calls,
+                stats::format_ms(timings.average()),
+                stats::format_ms(timings.percentile(0.99)),
+            );
+        }
+
+        if budget > 0 {
+            info!(
+                "  budget {}, exceeded on {} frames ({} consecutive)",
+                budget, env.budget.overruns, env.budget.consecutive_overruns
+            );
+        }
+
+        if let Some(quota) = &env.quota {
+            info!(
+                "  host call quota {}, {} calls skipped",
+                quota.limit(),
+                quota.skipped()
+            );
+        }
+
+        if env.stats.memory_grows > 0 || env.stats.refused_grows > 0 {
+            info!(
+                "  memory grew {} times, {} refused",
+                env.stats.memory_grows, env.stats.refused_grows
+            );
+        }
+
+        // Types of the externs created by the module, a count growing
+        // along with the live externs points to the ones that are leaked
+        for (type_name, count) in &env.stats.externs_created {
+            info!("  {} externs created: {}", count, type_name);
+        }
+
+        if write_json {
+            let mut value = env.stats.to_json();
+            value["memory"] = module.memory.len().into();
+            value["externs"] = module.externs.len().into();
+            value["budget"] = budget.into();
+            value["overruns"] = env.budget.overruns.into();
+            value["consecutive_overruns"] = env.budget.consecutive_overruns.into();
+            value["failed"] = env.failed.into();
+            if let Some(quota) = &env.quota {
+                value["host_call_quota"] = quota.limit().into();
+                value["skipped_host_calls"] = quota.skipped().into();
+            }
+            dump.insert(env.name.clone(), value);
+        }
+    }
+
+    if write_json {
+        let path = config.root().join(STATS_FILE);
+        let json = serde_json::Value::Object(dump).to_string();
+        match fs::write(&path, json) {
+            Ok(()) => info!("wrote statistics to {}", path.display()),
+            Err(err) => warn!("could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Acquire the interfaces of the engine and server factories used by the host
+/// functions, when the addon is loaded and again when the engine recreated them
+fn acquire_interfaces(factory: CreateInterfaceFn, server: CreateInterfaceFn) {
+    globals::init(server);
+    message::init(server);
+    sound::init(factory);
+    trace::init(factory);
+    effects::init(server);
+    bot::init(server);
+    entity::init(server);
+    netprops::init(server);
+    tools::init(server);
+}
+
+/// Notify the modules that `capability` became available after the
+/// addon was loaded, registering their listeners with the game event
+/// manager if it was the one missing
+fn enable_capability(modules: &[Module], capability: Capability) {
+    info!("{} are now available", capability);
+
+    if capability == Capability::GameEvents {
+        if let Some(mut manager) = manager::manager() {
+            for module in modules {
+                register_listeners(&mut manager, module);
+            }
+        }
+    }
+
+    for module in modules {
+        host::server::notify_capability(module, capability);
+    }
+}
+
+/// Unload all the modules and drop the state the addon kept for them
+fn unload_modules(modules: &mut Vec<Module>) {
+    for module in modules.iter() {
+        kv::flush(module);
+        host::entity::remove_entities(module);
+
+        // The events deferred for the module are
+        // dropped once its generation changes
+        let mut lock = module.lock().unwrap();
+        lock.environment.generation = lock.environment.generation.wrapping_add(1);
+        release_externs(&mut lock);
+        unsafe { lock.unload() };
+    }
+
+    modules.clear();
+    manager::clear_pending();
+    manager::clear_subscriptions();
+    bus::clear();
+    menu::clear();
+    vote::clear();
+    shared::clear();
+}
+
+impl ServerPluginCallbacks for FabricAddon {
+    fn load(&mut self, factory: CreateInterfaceFn, server: CreateInterfaceFn) -> bool {
+        info!("load {:?} {:?}", factory, server);
+        thread::init();
+
+        self.factories = Some((factory, server));
+        game::detect(factory);
+
+        let game_dir = match engine::init(factory) {
+            Some(mut engine) => {
+                logging::set_engine_output();
+                Some(PathBuf::from(game_dir(&mut engine)))
+            }
+            None => None,
+        };
+
+        if let Some(game_dir) = &game_dir {
+            game::load_steam_inf(game_dir);
+            config::load(game_dir);
+        }
+
+        let log_config = &config::get().logging;
+        logging::set_level(&log_config.level);
+        logging::set_targets(&log_config.targets);
+        logging::set_palette(&log_config.colors);
+        logging::set_fallback_file(&config::get().root().join(FALLBACK_LOG_FILE));
+        if !log_config.json.is_empty() {
+            logging::set_json_output(&config::get().root().join(&log_config.json));
+        }
+
+        executor::start(config::get().executor.threads);
+        remote::start();
+        metrics::start();
+        acquire_interfaces(factory, server);
+
+        // Load the event descriptors from the game directory, these are used
+        // to resolve the typed event fields imported by the modules
+        let schema = match &game_dir {
+            Some(game_dir) => EventSchema::load(game_dir),
+            None => EventSchema::default(),
+        };
+
+        let schema = Rc::new(schema);
+        self.schema = Some(schema.clone());
+
+        // Without a game event manager the modules are loaded anyway, their
+        // listeners are registered once the manager becomes available
+        let mut manager = manager::init(factory);
+        if manager.is_none() {
+            warn!("GAMEEVENTSMANAGER002 and GAMEEVENTSMANAGER001 not found");
+        }
+
+        for source in loader::discover() {
+            if let Some(module) = load_source(manager.as_mut(), source, &schema) {
+                self.modules.push(module);
+            }
+        }
+
+        deferred::init(factory, server);
+
+        command::init(factory);
+        command::register_variable(
+            cstr!("fabric_log_level"),
+            cstr!(
+                "Level of the messages logged by the addon: off, error, warn, info, debug or trace"
+            ),
+            &log_config.level,
+            logging::set_level,
+        );
+        command::register_variable(
+            cstr!("fabric_log_targets"),
+            cstr!("Comma-separated target=level list overriding fabric_log_level for some targets"),
+            &log_config.targets,
+            logging::set_targets,
+        );
+        command::register(
+            cstr!("fabric_list"),
+            cstr!("List the loaded modules, -deps also prints their dependencies"),
+            list_modules,
+        );
+        command::register(
+            cstr!("fabric_stats"),
+            cstr!(
+                "Print the execution statistics of the modules, -json also writes them to a file"
+            ),
+            module_stats,
+        );
+        command::register(
+            cstr!("fabric_log_dump"),
+            cstr!("Print the recent log lines of a module, or of the addon without arguments"),
+            logging::dump_history,
+        );
+        command::register(
+            cstr!("fabric_pause"),
+            cstr!("Pause a module, it stops receiving events, frames and timers"),
+            pause_module,
+        );
+        command::register(
+            cstr!("fabric_unpause"),
+            cstr!("Resume a module paused with fabric_pause"),
+            unpause_module,
+        );
+        command::register(
+            cstr!("fabric_trace"),
+            cstr!("Toggle the tracing of the host calls and callbacks of a module"),
+            trace_module,
+        );
+        command::register(
+            cstr!("fabric_mem"),
+            cstr!("Dump or watch a range of the memory of a module"),
+            inspect_memory,
+        );
+        command::register(
+            cstr!("fabric_dump_events"),
+            cstr!("List the event listeners of the modules and how many times they fired"),
+            dump_events,
+        );
+        command::register(
+            cstr!("fabric_dump_interfaces"),
+            cstr!("List the interfaces exposed by the engine and server factories"),
+            dump_interfaces,
+        );
+        command::register(
+            cstr!("fabric_priority"),
+            cstr!("Set the priority ordering the loading and events of a module, or reset it"),
+            set_module_priority,
+        );
+        command::register(
+            cstr!("fabric_reload_config"),
+            cstr!("Load fabric.cfg again and notify the modules of the new settings"),
+            reload_config,
+        );
+        command::register(
+            cstr!("fabric_reload"),
+            cstr!("Compile a module again from its file, keeping the state it saves"),
+            reload_module,
+        );
+        command::register(
+            cstr!("fabric_selftest"),
+            cstr!("Run built-in test modules through the runtime and report the failures"),
+            selftest::command,
+        );
+        command::register(
+            cstr!("fabric_eval"),
+            cstr!("Compile a WAT snippet against the host environment and run its start function"),
+            eval_module,
+        );
 
         true
     }
 
     fn unload(&mut self) {
-        self.modules.clear();
+        command::shutdown();
+        remote::stop();
+        metrics::stop();
+        executor::stop();
+
+        unload_modules(&mut self.modules);
+        self.schema = None;
+        self.factories = None;
     }
 
-    fn pause(&mut self) {}
+    fn pause(&mut self) {
+        set_plugin_paused(&self.modules, true);
+    }
 
-    fn unpause(&mut self) {}
+    fn unpause(&mut self) {
+        set_plugin_paused(&self.modules, false);
+    }
 
     fn get_plugin_description(&mut self) -> &CStr {
         cstr!("Fabric")
     }
 
-    fn level_init(&mut self, _map_name: &CStr) {}
+    fn level_init(&mut self, map_name: &CStr) {
+        config::set_map(&map_name.to_string_lossy());
 
-    fn server_activate(
-        &mut self,
-        _edict_list: *mut Edict,
-        _edict_count: c_int,
-        _client_max: c_int,
-    ) {
+        // The console registry holds the commands of
+        // the addon, it is checked but never replaced
+        let factories = self.factories;
+        if let Some((factory, server)) =
+            factories.filter(|_| config::get().runtime.check_interfaces)
+        {
+            let stale = game::stale_interfaces();
+            if !stale.is_empty() {
+                warn!(
+                    "{} changed, acquiring the interfaces again",
+                    stale.join(", ")
+                );
+                engine::init(factory);
+                acquire_interfaces(factory, server);
+            }
+        }
+
+        deferred::level_init();
+        host::map::level_init();
+        host::edict::reset_lifecycle();
+
+        for module in &self.modules {
+            precache_models(module);
+            precache_sounds(module);
+            host::state::restore(module);
+        }
+    }
+
+    fn server_activate(&mut self, edict_list: *mut Edict, edict_count: c_int, _client_max: c_int) {
+        entity::server_activate(edict_list, edict_count);
+    }
+
+    fn game_frame(&mut self, _simulating: bool) {
+        if take_unload_request() {
+            error!("a module panicked, unloading all the modules");
+            unload_modules(&mut self.modules);
+        }
+
+        for capability in deferred::take_available() {
+            enable_capability(&self.modules, capability);
+        }
+
+        let now = Instant::now();
+        let game_time = advance_clock();
+        for module in &self.modules {
+            refuel(module);
+            if !resume(module, game_time) {
+                continue;
+            }
+
+            manager::run_pending(module);
+            run_completions(module);
+            run_timers(module, game_time);
+            run_jobs(module);
+        }
+
+        bus::dispatch(&self.modules);
+        host::edict::run_lifecycle(&self.modules);
+        menu::run(&self.modules, now);
+        vote::run(&self.modules, now);
+        remote::run(&self.modules, self.schema.as_ref());
+        metrics::run(&self.modules, now);
+        host::map::run(now);
+        inspect::run(&self.modules);
     }
 
-    fn game_frame(&mut self, _simulating: bool) {}
+    fn level_shutdown(&mut self) {
+        entity::level_shutdown();
+        host::edict::reset_lifecycle();
 
-    fn level_shutdown(&mut self) {}
+        for module in &self.modules {
+            host::state::save(module);
+            kv::flush(module);
+            host::entity::level_shutdown(module);
+        }
+    }
 
     fn on_query_cvar_value_finished(
         &mut self,
-        _cookie: QueryCvarCookie,
+        cookie: QueryCvarCookie,
         _entity: *mut Edict,
-        _status: QueryCvarValueStatus,
+        status: QueryCvarValueStatus,
         _var_name: *mut c_char,
-        _var_value: *mut c_char,
+        var_value: *mut c_char,
     ) {
+        host::client::query_finished(&self.modules, cookie, status, var_value);
     }
 
-    fn on_edict_allocated(&mut self, _entity: *mut Edict) {}
+    fn on_edict_allocated(&mut self, entity: *mut Edict) {
+        for module in &self.modules {
+            host::edict::notify(module, entity, true);
+        }
+
+        host::edict::queue_lifecycle(entity, true);
+    }
+
+    fn on_edict_freed(&mut self, entity: *const Edict) {
+        for module in &self.modules {
+            host::edict::notify(module, entity, false);
+        }
 
-    fn on_edict_freed(&mut self, _entity: *const Edict) {}
+        host::edict::queue_lifecycle(entity, false);
+    }
 
     fn client_active(&mut self, _entity: *mut Edict) {}
 
     fn client_fully_connect(&mut self, _entity: *mut Edict) {}
 
-    fn client_disconnect(&mut self, _entity: *mut Edict) {}
+    fn client_disconnect(&mut self, entity: *mut Edict) {
+        menu::client_disconnect(entity);
+        vote::client_disconnect(entity);
+        host::client::client_disconnect(entity);
+
+        if let Some(entity) = unsafe { entity.as_ref() } {
+            bans::client_disconnect(entity.index());
+            flood::client_disconnect(entity.index());
+        }
+
+        for module in &self.modules {
+            host::bot::client_disconnect(module, entity);
+        }
+    }
 
-    fn client_put_in_server(&mut self, _entity: *mut Edict, _player_name: &CStr) {}
+    fn client_put_in_server(&mut self, entity: *mut Edict, _player_name: &CStr) {
+        host::client::client_put_in_server(entity);
+    }
 
-    fn set_command_client(&mut self, _index: c_int) {}
+    fn set_command_client(&mut self, index: c_int) {
+        host::command::set_command_client(index);
+    }
 
-    fn client_settings_changed(&mut self, _entity: *mut Edict) {}
+    fn client_settings_changed(&mut self, entity: *mut Edict) {
+        host::client::client_settings_changed(&self.modules, entity);
+    }
 
     fn client_connect(
         &mut self,
-        _allow_connect: *mut bool,
-        _entity: *mut Edict,
+        allow_connect: *mut bool,
+        entity: *mut Edict,
         _name: &CStr,
-        _address: &CStr,
-        _reject: *mut c_char,
-        _max_reject_len: c_int,
+        address: &CStr,
+        reject: *mut c_char,
+        max_reject_len: c_int,
     ) -> PluginResult {
-        PluginResult::Continue
+        let client = match unsafe { entity.as_ref() } {
+            Some(entity) => entity.index(),
+            None => return PluginResult::Continue,
+        };
+
+        let steam_id = bans::steam_id(client);
+        let reason = bans::client_connect(client, &address.to_string_lossy(), steam_id.as_deref());
+        let reason = match reason {
+            Some(reason) => reason,
+            None => match host::client::client_connect(&self.modules, client) {
+                Some((module, reason)) => {
+                    info!(
+                        "{} rejected the connection of {}: {}",
+                        module,
+                        address.to_string_lossy(),
+                        reason
+                    );
+                    reason
+                }
+                None => return PluginResult::Continue,
+            },
+        };
+
+        // The reject message is truncated to the buffer of the engine
+        let mut writer = unsafe { RejectWriter::new(reject, max_reject_len) };
+        if !writer.write(&reason) {
+            warn!("rejection message truncated to {} bytes", max_reject_len);
+        }
+
+        unsafe { allow_connect.write(false) };
+
+        PluginResult::Stop
     }
 
-    fn client_command(&mut self, _entity: *mut Edict, _args: *const CCommand) -> PluginResult {
+    fn client_command(&mut self, entity: *mut Edict, args: *const CCommand) -> PluginResult {
+        let command = match unsafe { CommandArgs::from_ptr(args) } {
+            Some(command) => command,
+            None => return PluginResult::Continue,
+        };
+
+        // The commands past their limit are blocked before the modules handle
+        // them, and before the game does as the chat is sent as commands
+        if let Some(entity) = unsafe { entity.as_ref() } {
+            let name = command.arg(0).to_string_lossy();
+            if !flood::check(entity.index(), &name, Instant::now()) {
+                return PluginResult::Stop;
+            }
+        }
+
+        // Keys pressed by the clients in the menus displayed by the modules
+        if command.argc() == 2 && command.arg(0) == cstr!("menuselect") {
+            let key = command
+                .arg(1)
+                .to_str()
+                .ok()
+                .and_then(|key| key.parse().ok());
+            if menu::select(&self.modules, entity, key.unwrap_or(-1)) {
+                return PluginResult::Stop;
+            }
+        }
+
+        let args = command.args();
+
+        // Commands registered by the modules, typed by the client in its console
+        if let Some(entity) = unsafe { entity.as_ref() } {
+            if host::command::client_command(&self.modules, entity.index(), &args) {
+                return PluginResult::Stop;
+            }
+        }
+
         PluginResult::Continue
     }
 
-    fn network_id_validated(&mut self, _user_name: &CStr, _network_id: &CStr) -> PluginResult {
+    fn network_id_validated(&mut self, _user_name: &CStr, network_id: &CStr) -> PluginResult {
+        bans::network_id_validated(&network_id.to_string_lossy());
         PluginResult::Continue
     }
 }
@@ -307,5 +1517,134 @@ pub(crate) static mut INSTANCE: CServerPluginCallbacks<FabricAddon> = CServerPlu
     vtable: &VTABLE,
     instance: FabricAddon {
         modules: Vec::new(),
+        schema: None,
+        factories: None,
     },
 };
+
+#[cfg(test)]
+mod tests {
+    use std::ptr::{null, null_mut};
+
+    use super::*;
+
+    /// Tokenized command, with the arguments in `argv_buffer` like the engine does
+    fn tokenize(args: &[&str]) -> Box<CCommand> {
+        let mut command = Box::new(CCommand {
+            argc: args.len() as c_int,
+            argv0_size: 0,
+            arg_s_buffer: [0; COMMAND_MAX_LENGTH],
+            argv_buffer: [0; COMMAND_MAX_LENGTH],
+            argv: [null(); COMMAND_MAX_ARGC],
+        });
+
+        let mut offset = 0;
+        for (index, arg) in args.iter().enumerate() {
+            for (position, byte) in arg.bytes().enumerate() {
+                command.argv_buffer[offset + position] = byte as c_char;
+            }
+
+            command.argv[index] = command.argv_buffer[offset..].as_ptr();
+            offset += arg.len() + 1;
+        }
+
+        command
+    }
+
+    fn args(command: &CCommand) -> CommandArgs<'_> {
+        unsafe { CommandArgs::from_ptr(command) }.unwrap()
+    }
+
+    #[test]
+    fn read_arguments() {
+        let command = tokenize(&["fabric_list", "-deps"]);
+
+        assert_eq!(args(&command).argc(), 2);
+        assert_eq!(args(&command).arg(0), cstr!("fabric_list"));
+        assert_eq!(args(&command).arg(1), cstr!("-deps"));
+        assert_eq!(args(&command).args(), ["fabric_list", "-deps"]);
+
+        assert!(unsafe { CommandArgs::from_ptr(null()) }.is_none());
+    }
+
+    #[test]
+    fn arguments_out_of_bounds_are_empty() {
+        let mut command = tokenize(&["fabric_list", "-deps"]);
+        assert_eq!(args(&command).arg(2), cstr!(""));
+        assert_eq!(args(&command).arg(usize::MAX), cstr!(""));
+
+        command.argc = -1;
+        assert_eq!(args(&command).argc(), 0);
+        assert_eq!(args(&command).arg(0), cstr!(""));
+        assert!(args(&command).args().is_empty());
+
+        // The count is clamped to the size of `argv`, the missing arguments are null
+        command.argc = 1000;
+        assert_eq!(args(&command).argc(), COMMAND_MAX_ARGC);
+        assert_eq!(args(&command).arg(1), cstr!("-deps"));
+        assert_eq!(args(&command).arg(2), cstr!(""));
+        assert_eq!(args(&command).arg(COMMAND_MAX_ARGC), cstr!(""));
+
+        // An argument pointing outside of `argv_buffer`
+        command.argc = 2;
+        command.argv[1] = command.arg_s_buffer.as_ptr();
+        assert_eq!(args(&command).arg(0), cstr!("fabric_list"));
+        assert_eq!(args(&command).arg(1), cstr!(""));
+
+        // An argument without a NUL terminator
+        command.argv_buffer = [b'a' as c_char; COMMAND_MAX_LENGTH];
+        assert_eq!(args(&command).arg(0), cstr!(""));
+    }
+
+    /// Write `message` with a writer of `capacity` bytes, returns whether it
+    /// fit and the contents of the buffer, the byte past it must be untouched
+    fn reject(capacity: usize, message: &str) -> (bool, Vec<u8>) {
+        let mut buffer = vec![0xffu8; capacity + 1];
+        let mut writer =
+            unsafe { RejectWriter::new(buffer.as_mut_ptr() as *mut c_char, capacity as c_int) };
+        let fits = writer.write(message);
+
+        assert_eq!(buffer.pop(), Some(0xff), "wrote past the capacity");
+        (fits, buffer)
+    }
+
+    #[test]
+    fn write_reject_message() {
+        assert_eq!(reject(8, "banned"), (true, b"banned\0\xff".to_vec()));
+        assert_eq!(reject(7, "banned"), (true, b"banned\0".to_vec()));
+        assert_eq!(reject(6, "banned"), (false, b"banne\0".to_vec()));
+    }
+
+    #[test]
+    fn truncate_reject_message_on_char_boundary() {
+        // "é" is encoded on 2 bytes, it doesn't fit before the terminator
+        assert_eq!(reject(3, "aé"), (false, b"a\0\xff".to_vec()));
+        assert_eq!(reject(4, "aé"), (true, "aé\0".as_bytes().to_vec()));
+
+        // "€" is encoded on 3 bytes
+        assert_eq!(reject(3, "€"), (false, b"\0\xff\xff".to_vec()));
+        assert_eq!(reject(4, "€"), (true, "€\0".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn reject_message_in_tiny_buffers() {
+        // Nothing can be written without room for the terminator
+        assert_eq!(reject(0, "banned"), (false, vec![]));
+        assert_eq!(reject(0, ""), (true, vec![]));
+
+        assert_eq!(reject(1, "banned"), (false, vec![0]));
+        assert_eq!(reject(1, ""), (true, vec![0]));
+
+        let mut writer = unsafe { RejectWriter::new(null_mut(), 16) };
+        assert!(!writer.write("banned"));
+    }
+
+    #[test]
+    fn reject_message_stops_at_nul() {
+        assert_eq!(
+            reject(8, "ban\0ned"),
+            (true, b"ban\0\xff\xff\xff\xff".to_vec())
+        );
+        assert_eq!(reject(1, "\0banned"), (true, vec![0]));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fs,
+    net::IpAddr,
+    os::raw::c_int,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    engine::{engine, VEngineServer},
+    entity::{connected_clients, edict},
+};
+
+/// Identity a ban applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Identity {
+    /// Network ID of the client, like `STEAM_1:0:1234`
+    SteamId(String),
+    /// IP address of the client, without the port
+    Ip(String),
+}
+
+impl Identity {
+    /// Parse an identity given by a module or an admin, any
+    /// string that doesn't parse as an IP address is a SteamID
+    pub(crate) fn parse(identity: &str) -> Option<Self> {
+        let identity = identity.trim();
+        if identity.is_empty() {
+            None
+        } else if identity.parse::<IpAddr>().is_ok() {
+            Some(Identity::Ip(identity.into()))
+        } else {
+            Some(Identity::SteamId(identity.into()))
+        }
+    }
+}
+
+/// Entry of the ban list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Ban {
+    pub(crate) identity: Identity,
+    pub(crate) reason: String,
+    /// Name of the module that added the ban
+    pub(crate) module: String,
+    /// UNIX time the ban was added at
+    pub(crate) created: u64,
+    /// UNIX time the ban expires at, None for a permanent ban
+    pub(crate) expires: Option<u64>,
+}
+
+impl Ban {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// Ban list shared by all the modules, persisted as a JSON array in the
+/// `data` directory of the addon. Only accessed from the game thread
+static mut BANS: Option<Vec<Ban>> = None;
+
+/// Addresses given by the clients when they connected, keyed by entity
+/// index, so the connected clients can be banned by IP
+static mut ADDRESSES: Option<HashMap<c_int, String>> = None;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn path() -> PathBuf {
+    config::get().root().join("data").join("bans.json")
+}
+
+fn bans() -> &'static mut Vec<Ban> {
+    unsafe {
+        BANS.get_or_insert_with(|| {
+            let path = path();
+            match fs::read(&path) {
+                Ok(source) => serde_json::from_slice(&source).unwrap_or_else(|err| {
+                    warn!("could not parse {}: {}", path.display(), err);
+                    Vec::new()
+                }),
+                Err(err) => {
+                    debug!("could not read {}: {}", path.display(), err);
+                    Vec::new()
+                }
+            }
+        })
+    }
+}
+
+/// Write the ban list to disk, the list is small and rarely
+/// modified so it's written back on every change
+fn save() {
+    let path = path();
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            warn!("could not create {}: {}", dir.display(), err);
+            return;
+        }
+    }
+
+    let result = serde_json::to_vec_pretty(bans())
+        .map_err(|err| err.to_string())
+        .and_then(|data| fs::write(&path, data).map_err(|err| err.to_string()));
+
+    if let Err(err) = result {
+        warn!("could not write {}: {}", path.display(), err);
+    }
+}
+
+/// Active ban applying to `identity`, dropping the expired bans
+pub(crate) fn find(identity: &Identity) -> Option<Ban> {
+    let now = now();
+    let bans = bans();
+
+    let count = bans.len();
+    bans.retain(|ban| !ban.is_expired(now));
+    if bans.len() != count {
+        save();
+    }
+
+    bans.iter().find(|ban| ban.identity == *identity).cloned()
+}
+
+/// Ban `identity` for `duration` seconds, or permanently for 0,
+/// replacing any existing ban of the same identity
+pub(crate) fn add(identity: Identity, duration: u64, reason: &str, module: &str) {
+    info!("{} banned {:?}: {}", module, identity, reason);
+
+    let created = now();
+    let bans = bans();
+    bans.retain(|ban| ban.identity != identity);
+    bans.push(Ban {
+        identity,
+        reason: reason.into(),
+        module: module.into(),
+        created,
+        expires: if duration > 0 {
+            Some(created + duration)
+        } else {
+            None
+        },
+    });
+
+    save();
+}
+
+/// Lift the ban of `identity`, returns false if it wasn't banned
+pub(crate) fn remove(identity: &Identity) -> bool {
+    let bans = bans();
+    let count = bans.len();
+    bans.retain(|ban| ban.identity != *identity);
+
+    if bans.len() == count {
+        false
+    } else {
+        save();
+        true
+    }
+}
+
+/// Network ID of the connected client at `client`
+pub(crate) fn steam_id(client: c_int) -> Option<String> {
+    let edict = edict(client)?;
+    let id = engine()?.get_player_network_id_string(edict);
+    if id.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(id) }.to_string_lossy().into_owned())
+    }
+}
+
+/// IP address the connected client at `client` connected from
+pub(crate) fn address(client: c_int) -> Option<String> {
+    unsafe { ADDRESSES.as_ref()?.get(&client).cloned() }
+}
+
+/// Disconnect the client at `client` with `reason`
+pub(crate) fn kick(client: c_int, reason: &str) -> bool {
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return false,
+    };
+
+    let user_id = match edict(client) {
+        Some(edict) => engine.get_player_user_id(edict),
+        None => return false,
+    };
+
+    if user_id == -1 {
+        return false;
+    }
+
+    // Quotes would end the reason early in the command line
+    let reason = reason.replace('"', "'");
+    let command = match CString::new(format!("kickid {} \"{}\"\n", user_id, reason)) {
+        Ok(command) => command,
+        Err(_) => return false,
+    };
+
+    engine.server_command(&command);
+    true
+}
+
+/// Reason of the ban applying to a client connecting from `address`
+/// with `steam_id`, recording the address of the client
+///
+/// The network ID of the client is usually not validated yet when it
+/// connects, `network_id_validated` checks it again afterwards
+pub(crate) fn client_connect(
+    client: c_int,
+    address: &str,
+    steam_id: Option<&str>,
+) -> Option<String> {
+    // Strip the port from the address
+    let ip = address
+        .rsplitn(2, ':')
+        .last()
+        .unwrap_or(address)
+        .to_string();
+
+    unsafe { ADDRESSES.get_or_insert_with(HashMap::new) }.insert(client, ip.clone());
+
+    let ban = find(&Identity::Ip(ip))
+        .or_else(|| steam_id.and_then(|id| find(&Identity::SteamId(id.into()))))?;
+
+    Some(ban.reason)
+}
+
+/// Kick the client whose network ID was just validated if it's banned
+pub(crate) fn network_id_validated(network_id: &str) {
+    let reason = match find(&Identity::SteamId(network_id.into())) {
+        Some(ban) => ban.reason,
+        None => return,
+    };
+
+    for client in connected_clients() {
+        if steam_id(client).as_deref() == Some(network_id) {
+            kick(client, &reason);
+        }
+    }
+}
+
+/// Forget the address of a disconnecting client
+pub(crate) fn client_disconnect(client: c_int) {
+    if let Some(addresses) = unsafe { ADDRESSES.as_mut() } {
+        addresses.remove(&client);
+    }
+}
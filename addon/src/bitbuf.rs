@@ -0,0 +1,475 @@
+use std::{
+    ffi::CStr,
+    mem::size_of,
+    os::raw::{c_char, c_int, c_uchar},
+    ptr::null,
+    slice,
+};
+
+/// Bit buffer used by the engine to serialize network messages,
+/// declared as in the Alien Swarm SDK's `bitbuf.h`
+///
+/// The methods of this class are not virtual and live in a static
+/// library, so the buffer is written directly from Rust with `BitWriter`
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub(crate) struct bf_write {
+    /// The current buffer.
+    data: *mut c_uchar,
+    data_bytes: c_int,
+    data_bits: c_int,
+
+    /// Where we are in the buffer.
+    cur_bit: c_int,
+
+    /// Errors?
+    overflow: bool,
+
+    assert_on_overflow: bool,
+    debug_name: *const c_char,
+}
+
+impl bf_write {
+    /// Create a write buffer over `data`, the buffer must
+    /// not be used after `data` is moved or dropped
+    fn for_buffer(data: &mut [u32]) -> Self {
+        let bytes = data.len() * size_of::<u32>();
+        bf_write {
+            data: data.as_mut_ptr() as *mut c_uchar,
+            data_bytes: bytes as c_int,
+            data_bits: (bytes * 8) as c_int,
+            cur_bit: 0,
+            overflow: false,
+            assert_on_overflow: false,
+            debug_name: null(),
+        }
+    }
+}
+
+/// Bit buffer used by the engine to deserialize network messages,
+/// declared as `CBitRead` in the Alien Swarm SDK's `bitbuf.h`
+///
+/// The engine reads the buffer one dword at a time, the
+/// data pointers must be aligned on 4 bytes
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub(crate) struct bf_read {
+    debug_name: *const c_char,
+    overflow: bool,
+    data_bits: c_int,
+    data_bytes: usize,
+
+    in_buf_word: u32,
+    bits_avail: c_int,
+    data_in: *const u32,
+    buffer_end: *const u32,
+    data: *const u32,
+}
+
+impl bf_read {
+    /// Create a read buffer positioned at the start of `data`, this is the
+    /// state `StartReading` leaves the buffer in for an aligned buffer whose
+    /// size is a multiple of 4 bytes. The buffer must not be used after
+    /// `data` is moved or dropped
+    fn for_buffer(data: &[u32], bits: usize) -> Self {
+        let range = data.as_ptr_range();
+        match data.first() {
+            Some(first) => bf_read {
+                debug_name: null(),
+                overflow: false,
+                data_bits: bits as c_int,
+                data_bytes: data.len() * size_of::<u32>(),
+                in_buf_word: u32::from_le(*first),
+                bits_avail: 32,
+                data_in: unsafe { range.start.add(1) },
+                buffer_end: range.end,
+                data: range.start,
+            },
+            None => bf_read {
+                debug_name: null(),
+                overflow: false,
+                data_bits: 0,
+                data_bytes: 0,
+                in_buf_word: 0,
+                bits_avail: 1,
+                data_in: range.start,
+                buffer_end: range.end,
+                data: range.start,
+            },
+        }
+    }
+}
+
+/// Safe writer for a `bf_write` owned by the engine
+///
+/// Bits are stored least significant first, since the engine stores the
+/// buffer as little endian dwords this is the same bit order as a byte
+/// buffer. Writing past the end of the buffer sets the overflow flag and
+/// all the following writes are ignored, like the engine implementation
+pub(crate) struct BitWriter<'a> {
+    buffer: &'a mut bf_write,
+}
+
+impl<'a> BitWriter<'a> {
+    /// Wrap a buffer returned by the engine
+    ///
+    /// # Safety
+    /// `buffer` must be null or point to a valid and initialized bf_write
+    /// that outlives the returned writer
+    pub(crate) unsafe fn new(buffer: *mut bf_write) -> Option<Self> {
+        let buffer = buffer.as_mut()?;
+        if buffer.data.is_null() || buffer.data_bits < 0 {
+            return None;
+        }
+
+        Some(BitWriter { buffer })
+    }
+
+    fn data(&mut self) -> &mut [u8] {
+        let len = (self.buffer.data_bits as usize + 7) / 8;
+        unsafe { slice::from_raw_parts_mut(self.buffer.data, len) }
+    }
+
+    pub(crate) fn is_overflowed(&self) -> bool {
+        self.buffer.overflow
+    }
+
+    /// Number of bits left in the buffer
+    pub(crate) fn bits_left(&self) -> usize {
+        (self.buffer.data_bits - self.buffer.cur_bit).max(0) as usize
+    }
+
+    /// Reserve `bits` bits in the buffer, returning the position of the first
+    /// bit or setting the overflow flag if the buffer is too small
+    fn reserve(&mut self, bits: usize) -> Option<usize> {
+        if self.buffer.overflow {
+            return None;
+        }
+
+        if bits > self.bits_left() {
+            self.buffer.overflow = true;
+            return None;
+        }
+
+        let position = self.buffer.cur_bit as usize;
+        self.buffer.cur_bit += bits as c_int;
+        Some(position)
+    }
+
+    pub(crate) fn write_bit(&mut self, value: bool) {
+        self.write_ubits(value as u32, 1);
+    }
+
+    /// Write the `bits` low bits of `value`
+    pub(crate) fn write_ubits(&mut self, value: u32, bits: usize) {
+        debug_assert!(bits <= 32);
+
+        let position = match self.reserve(bits) {
+            Some(position) => position,
+            None => return,
+        };
+
+        let data = self.data();
+        for bit in 0..bits {
+            let index = position + bit;
+            let mask = 1 << (index % 8);
+            if value & (1 << bit) != 0 {
+                data[index / 8] |= mask;
+            } else {
+                data[index / 8] &= !mask;
+            }
+        }
+    }
+
+    pub(crate) fn write_byte(&mut self, value: u8) {
+        self.write_ubits(value.into(), 8);
+    }
+
+    pub(crate) fn write_float(&mut self, value: f32) {
+        self.write_ubits(value.to_bits(), 32);
+    }
+
+    pub(crate) fn write_bytes(&mut self, value: &[u8]) {
+        // Check the whole slice fits up front so a
+        // truncated value is never written to the buffer
+        if value.len() * 8 > self.bits_left() {
+            self.buffer.overflow = true;
+            return;
+        }
+
+        for byte in value {
+            self.write_byte(*byte);
+        }
+    }
+
+    /// Write a null-terminated string
+    pub(crate) fn write_string(&mut self, value: &CStr) {
+        self.write_bytes(value.to_bytes_with_nul());
+    }
+
+    /// Write the first `bits` bits of `value`
+    pub(crate) fn write_bits_from(&mut self, value: &[u8], bits: usize) {
+        if bits > value.len() * 8 || bits > self.bits_left() {
+            self.buffer.overflow = true;
+            return;
+        }
+
+        let (bytes, rest) = (bits / 8, bits % 8);
+        self.write_bytes(&value[..bytes]);
+        if rest > 0 {
+            self.write_ubits(value[bytes].into(), rest);
+        }
+    }
+}
+
+/// Bit buffer owned by the addon, used by the modules to
+/// serialize and deserialize arbitrary network data
+///
+/// Bits are appended at the write position and consumed from the read
+/// position, reading past the written data sets the overflow flag of the
+/// reader the same way writing past the capacity does for the writer
+pub(crate) struct BitBuffer {
+    /// The storage is allocated as dwords for the engine, and
+    /// is never reallocated since `write` points into it
+    data: Vec<u32>,
+    write: bf_write,
+    read_bit: usize,
+    read_overflow: bool,
+}
+
+impl BitBuffer {
+    /// Create an empty buffer with a capacity of at least `bytes` bytes
+    pub(crate) fn new(bytes: usize) -> Self {
+        let mut data = vec![0; (bytes + 3) / 4];
+        let write = bf_write::for_buffer(&mut data);
+        BitBuffer {
+            data,
+            write,
+            read_bit: 0,
+            read_overflow: false,
+        }
+    }
+
+    /// Create a buffer containing `bytes`, positioned for reading
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        let mut buffer = BitBuffer::new(bytes.len());
+        buffer.writer().write_bytes(bytes);
+        buffer
+    }
+
+    pub(crate) fn writer(&mut self) -> BitWriter<'_> {
+        BitWriter {
+            buffer: &mut self.write,
+        }
+    }
+
+    /// Number of bits written to the buffer
+    pub(crate) fn bits_written(&self) -> usize {
+        self.write.cur_bit as usize
+    }
+
+    /// Bytes written to the buffer, the last byte is
+    /// padded with zeroes if it was partially written
+    pub(crate) fn bytes(&self) -> &[u8] {
+        let len = (self.bits_written() + 7) / 8;
+        let data = self.data.as_ptr() as *const u8;
+        unsafe { slice::from_raw_parts(data, len) }
+    }
+
+    pub(crate) fn is_overflowed(&self) -> bool {
+        self.write.overflow || self.read_overflow
+    }
+
+    /// Get an engine read buffer over the data written to this buffer
+    pub(crate) fn engine_reader(&self) -> bf_read {
+        bf_read::for_buffer(&self.data, self.bits_written())
+    }
+
+    /// Get an engine write buffer over the free space of this buffer,
+    /// the engine advances the write position of the buffer directly
+    pub(crate) fn engine_writer(&mut self) -> *mut bf_write {
+        &mut self.write
+    }
+
+    /// Read the next `bits` bits as an unsigned integer
+    pub(crate) fn read_ubits(&mut self, bits: usize) -> u32 {
+        debug_assert!(bits <= 32);
+
+        if self.read_overflow || self.read_bit + bits > self.bits_written() {
+            self.read_overflow = true;
+            return 0;
+        }
+
+        let data = self.bytes();
+        let mut value = 0;
+        for bit in 0..bits {
+            let index = self.read_bit + bit;
+            if data[index / 8] & (1 << (index % 8)) != 0 {
+                value |= 1 << bit;
+            }
+        }
+
+        self.read_bit += bits;
+        value
+    }
+
+    /// Read the next `bits` bits as a sign-extended integer
+    pub(crate) fn read_sbits(&mut self, bits: usize) -> i32 {
+        let value = self.read_ubits(bits);
+        if bits == 0 || bits >= 32 {
+            return value as i32;
+        }
+
+        let shift = 32 - bits;
+        ((value << shift) as i32) >> shift
+    }
+
+    pub(crate) fn read_float(&mut self) -> f32 {
+        f32::from_bits(self.read_ubits(32))
+    }
+
+    /// Read a null-terminated string, without the terminator
+    pub(crate) fn read_string(&mut self) -> Vec<u8> {
+        let mut value = Vec::new();
+        loop {
+            let byte = self.read_ubits(8) as u8;
+            if byte == 0 || self.read_overflow {
+                break;
+            }
+
+            value.push(byte);
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_at_unaligned_offsets() {
+        let mut buffer = BitBuffer::new(32);
+        let mut writer = buffer.writer();
+        writer.write_bit(true);
+        writer.write_ubits(0x1234, 13);
+        writer.write_byte(0xab);
+        writer.write_float(-1.5);
+        writer.write_bytes(&[1, 2, 3]);
+        writer.write_string(CStr::from_bytes_with_nul(b"fabric\0").unwrap());
+        writer.write_ubits(u32::MAX, 32);
+        assert!(!writer.is_overflowed());
+        assert_eq!(buffer.bits_written(), 1 + 13 + 8 + 32 + 24 + 56 + 32);
+
+        assert_eq!(buffer.read_ubits(1), 1);
+        assert_eq!(buffer.read_ubits(13), 0x1234);
+        assert_eq!(buffer.read_ubits(8), 0xab);
+        assert_eq!(buffer.read_float(), -1.5);
+        assert_eq!(buffer.read_ubits(24), 0x03_02_01);
+        assert_eq!(buffer.read_string(), b"fabric");
+        assert_eq!(buffer.read_ubits(32), u32::MAX);
+        assert!(!buffer.is_overflowed());
+    }
+
+    #[test]
+    fn write_bits_lsb_first() {
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_ubits(0b101, 3);
+        writer.write_byte(0xff);
+
+        // The last byte is padded with zeroes
+        assert_eq!(buffer.bytes(), [0b1111_1101, 0b0000_0111]);
+    }
+
+    #[test]
+    fn sign_extend_read_sbits() {
+        let mut buffer = BitBuffer::new(16);
+        let mut writer = buffer.writer();
+        writer.write_ubits(0b101, 3);
+        writer.write_ubits(0b011, 3);
+        writer.write_ubits(0x8000, 16);
+        writer.write_ubits(0xffff_ffff, 32);
+        writer.write_ubits(0x7fff_ffff, 32);
+
+        assert_eq!(buffer.read_sbits(3), -3);
+        assert_eq!(buffer.read_sbits(3), 3);
+        assert_eq!(buffer.read_sbits(16), -0x8000);
+        assert_eq!(buffer.read_sbits(32), -1);
+        assert_eq!(buffer.read_sbits(32), i32::MAX);
+        assert_eq!(buffer.read_sbits(0), 0);
+        assert!(!buffer.is_overflowed());
+    }
+
+    #[test]
+    fn overflow_ignores_following_writes() {
+        // The capacity is rounded up to a dword
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_ubits(0xdead, 16);
+        assert_eq!(writer.bits_left(), 16);
+
+        // A value that doesn't fit is not written at all
+        writer.write_bytes(&[1, 2, 3]);
+        assert!(writer.is_overflowed());
+        writer.write_bit(true);
+        assert_eq!(buffer.bits_written(), 16);
+        assert_eq!(buffer.bytes(), [0xad, 0xde]);
+
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_ubits(0, 31);
+        writer.write_ubits(0, 2);
+        assert!(writer.is_overflowed());
+        assert_eq!(writer.bits_left(), 1);
+    }
+
+    #[test]
+    fn overflow_reading_past_written_data() {
+        let mut buffer = BitBuffer::from_bytes(&[0xff]);
+        assert_eq!(buffer.read_ubits(4), 0xf);
+        assert_eq!(buffer.read_ubits(5), 0);
+        assert!(buffer.is_overflowed());
+
+        // The bits left aren't readable anymore once the reader overflowed
+        assert_eq!(buffer.read_ubits(4), 0);
+    }
+
+    #[test]
+    fn write_bits_from_partial_last_byte() {
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_bits_from(&[0xab, 0xcd], 12);
+        assert!(!writer.is_overflowed());
+        assert_eq!(buffer.bits_written(), 12);
+        assert_eq!(buffer.bytes(), [0xab, 0x0d]);
+
+        // At an unaligned offset
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_bit(true);
+        writer.write_bits_from(&[0xab, 0xcd], 12);
+        assert_eq!(buffer.bits_written(), 13);
+        assert_eq!(buffer.read_ubits(1), 1);
+        assert_eq!(buffer.read_ubits(12), 0xdab);
+
+        // More bits than the value has
+        let mut buffer = BitBuffer::new(4);
+        let mut writer = buffer.writer();
+        writer.write_bits_from(&[0xab], 9);
+        assert!(writer.is_overflowed());
+        assert_eq!(buffer.bits_written(), 0);
+    }
+
+    #[test]
+    fn read_string_without_terminator() {
+        let mut buffer = BitBuffer::from_bytes(b"abc");
+        assert_eq!(buffer.read_string(), b"abc");
+        assert!(buffer.is_overflowed());
+
+        let mut buffer = BitBuffer::from_bytes(b"");
+        assert_eq!(buffer.read_string(), b"");
+        assert!(buffer.is_overflowed());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::{c_int, c_short, c_uchar},
+    ptr::null_mut,
+};
+
+use crate::{
+    addon::Edict,
+    deferred::{self, Capability},
+    engine::Vector,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for IBotManager, exposed by the server DLL to create
+/// and control fake clients
+#[fabric_codegen::interface]
+pub(crate) trait BotManager {
+    fn get_bot_controller(&mut self, edict: *mut Edict) -> *mut c_void;
+
+    /// Create a new bot and spawn it into the server
+    fn create_bot(&mut self, name: &CStr) -> *mut Edict;
+}
+
+/// Binding for IBotController, the methods are declared in the same order
+/// as the Alien Swarm SDK's `iplayerinfo.h`
+///
+/// The methods returning a vector by value are declared with the explicit
+/// return pointer MSVC passes to thiscall methods returning a structure
+#[fabric_codegen::interface]
+pub(crate) trait BotController {
+    /// change the bots position
+    fn set_abs_origin(&mut self, origin: *mut Vector);
+    /// give the bot an item
+    fn remove_all_items(&mut self, remove_suit: bool);
+    /// set the bots angles
+    fn set_active_weapon(&mut self, name: &CStr);
+    fn get_local_origin(&mut self, result: *mut Vector) -> *mut Vector;
+    fn set_local_origin(&mut self, origin: *const Vector);
+    fn get_local_angles(&mut self, result: *mut Vector) -> *mut Vector;
+    fn set_local_angles(&mut self, angles: *const Vector);
+    fn is_eflag_set(&mut self, mask: c_int) -> bool;
+    /// Fire a command from the bot
+    fn run_player_move(&mut self, command: *mut BotCmd);
+}
+
+/// Movement command of a bot, declared as `CBotCmd` in the
+/// Alien Swarm SDK's `iplayerinfo.h`
+#[repr(C)]
+pub(crate) struct BotCmd {
+    /// The class has a virtual destructor, the
+    /// engine never calls it on a borrowed command
+    vtable: *const BotCmdVtable,
+    /// For matching server and client commands for debugging
+    pub(crate) command_number: c_int,
+    /// the tick the client created this command
+    pub(crate) tick_count: c_int,
+    /// Player instantaneous view angles.
+    pub(crate) view_angles: Vector,
+    /// Intended velocities
+    pub(crate) forward_move: f32,
+    pub(crate) side_move: f32,
+    pub(crate) up_move: f32,
+    /// Attack buttons
+    pub(crate) buttons: c_int,
+    /// Impulse command issued.
+    pub(crate) impulse: c_uchar,
+    /// Current weapon id
+    pub(crate) weapon_select: c_int,
+    pub(crate) weapon_subtype: c_int,
+    /// For shared random functions
+    pub(crate) random_seed: c_int,
+    /// mouse accum in x from create move
+    pub(crate) mouse_dx: c_short,
+    /// mouse accum in y from create move
+    pub(crate) mouse_dy: c_short,
+    /// Client only, tracks whether we've predicted this command at least once
+    pub(crate) has_been_predicted: bool,
+}
+
+#[repr(C)]
+struct BotCmdVtable {
+    destructor: extern "thiscall" fn(*mut BotCmd),
+}
+
+extern "thiscall" fn destroy_bot_cmd(_command: *mut BotCmd) {}
+
+static BOT_CMD_VTABLE: BotCmdVtable = BotCmdVtable {
+    destructor: destroy_bot_cmd,
+};
+
+impl BotCmd {
+    pub(crate) fn new() -> Self {
+        BotCmd {
+            vtable: &BOT_CMD_VTABLE,
+            command_number: 0,
+            tick_count: 0,
+            view_angles: Vector::default(),
+            forward_move: 0.0,
+            side_move: 0.0,
+            up_move: 0.0,
+            buttons: 0,
+            impulse: 0,
+            weapon_select: 0,
+            weapon_subtype: 0,
+            random_seed: 0,
+            mouse_dx: 0,
+            mouse_dy: 0,
+            has_been_predicted: false,
+        }
+    }
+}
+
+static mut BOT_MANAGER: *mut c_void = null_mut();
+
+/// Acquire the bot manager from the server factory, this must
+/// be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interface =
+        game::acquire::<dyn BotManager>(server, game::interfaces().bot_manager, "IBotManager");
+    unsafe {
+        BOT_MANAGER = interface.map_or(null_mut(), |manager| manager.0);
+    }
+}
+
+/// Get the bot manager, looked up again at most once per level while it is missing
+pub(crate) fn bot_manager() -> Option<Foreign<dyn BotManager>> {
+    let mut manager = unsafe { BOT_MANAGER };
+    if manager.is_null() && deferred::retry(Capability::Bots) {
+        manager = unsafe { BOT_MANAGER };
+    }
+
+    if manager.is_null() {
+        None
+    } else {
+        Some(Foreign::with(manager))
+    }
+}
+
+/// Get the controller of the bot `edict`
+pub(crate) fn bot_controller(edict: *mut Edict) -> Option<Foreign<dyn BotController>> {
+    let controller = bot_manager()?.get_bot_controller(edict);
+    if controller.is_null() {
+        None
+    } else {
+        Some(Foreign::with(controller))
+    }
+}
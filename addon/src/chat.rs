@@ -0,0 +1,136 @@
+//! Colors of the chat messages sent to the clients
+//!
+//! The messages are written with portable `{name}` tags like `{green}` or
+//! `{team}`, translated to the color codes of the game when they are sent.
+//! The colors the game doesn't support are removed from the message, the
+//! other tags are left as they are written
+
+use std::{
+    ffi::{CStr, CString},
+    str,
+};
+
+use crate::{config, game};
+
+/// Colors of the chat of all the Source games
+const BASIC_COLORS: &[(&str, &[u8])] =
+    &[("default", b"\x01"), ("team", b"\x03"), ("green", b"\x04")];
+
+/// Colors of the Orange Box games updated with RGB chat colors
+const RGB_COLORS: &[(&str, &[u8])] = &[
+    ("olive", b"\x05"),
+    ("red", b"\x07FF4040"),
+    ("blue", b"\x0799CCFF"),
+    ("yellow", b"\x07FFD700"),
+    ("orange", b"\x07FFA500"),
+    ("purple", b"\x07B980EF"),
+    ("white", b"\x07FFFFFF"),
+    ("gray", b"\x07CCCCCC"),
+];
+
+/// Whether the game with the `ProductName` `product` supports the
+/// RGB colors, written as `{#rrggbb}` or `{#rrggbbaa}` in the messages
+fn supports_rgb(product: &str) -> bool {
+    matches!(product, "tf" | "cstrike" | "dod" | "hl2mp")
+}
+
+/// Color code of an `#rrggbb` or `#rrggbbaa` color
+fn rgb_code(color: &str) -> Option<Vec<u8>> {
+    let hex = color.strip_prefix('#')?;
+    let prefix = match hex.len() {
+        6 => b'\x07',
+        8 => b'\x08',
+        _ => return None,
+    };
+
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut code = vec![prefix];
+    code.extend(hex.to_ascii_uppercase().bytes());
+    Some(code)
+}
+
+/// Resolve the tag `name` for the game `product`, returns None if it isn't a
+/// color tag and an empty code for a color the game doesn't support
+fn resolve(product: &str, name: &str) -> Option<Vec<u8>> {
+    let rgb = supports_rgb(product);
+
+    // Colors of the configuration, as a color code or an RGB color
+    let configured = config::get()
+        .chat
+        .colors
+        .get(product)
+        .and_then(|colors| colors.get(name));
+    if let Some(color) = configured {
+        return match rgb_code(color) {
+            Some(code) if rgb => Some(code),
+            Some(_) => Some(Vec::new()),
+            None => Some(color.bytes().filter(|byte| *byte != 0).collect()),
+        };
+    }
+
+    if name.starts_with('#') {
+        let code = rgb_code(name)?;
+        return Some(if rgb { code } else { Vec::new() });
+    }
+
+    if let Some((_, code)) = BASIC_COLORS.iter().find(|(color, _)| *color == name) {
+        return Some(code.to_vec());
+    }
+
+    let (_, code) = RGB_COLORS.iter().find(|(color, _)| *color == name)?;
+    Some(if rgb { code.to_vec() } else { Vec::new() })
+}
+
+/// Translate the color tags of `text` to the color codes of the running game
+pub(crate) fn format(text: &CStr) -> CString {
+    let product = game::info().product.as_deref().unwrap_or_default();
+    let text = text.to_bytes();
+
+    let mut output = Vec::with_capacity(text.len());
+    let mut colored = false;
+    let mut rest = text;
+
+    while let Some(start) = rest.iter().position(|byte| *byte == b'{') {
+        output.extend_from_slice(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest[1..]
+            .iter()
+            .position(|byte| *byte == b'{' || *byte == b'}')
+        {
+            Some(end) if rest[end + 1] == b'}' => end + 1,
+            // Not a tag, or another tag starts within it
+            _ => {
+                output.push(b'{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let code = str::from_utf8(&rest[1..end])
+            .ok()
+            .and_then(|name| resolve(product, name));
+        match code {
+            Some(code) => {
+                colored |= !code.is_empty();
+                output.extend(code);
+            }
+            None => output.extend_from_slice(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    output.extend_from_slice(rest);
+
+    // The chat only parses the colors of the messages starting with a color code
+    if colored && output.first().is_some_and(|byte| *byte > 0x08) {
+        output.insert(0, b'\x01');
+    }
+
+    // The codes are free of NUL bytes, like the text they replace tags of
+    unsafe { CString::from_vec_unchecked(output) }
+}
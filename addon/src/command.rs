@@ -0,0 +1,653 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    ops::{Deref, DerefMut},
+    os::raw::{c_char, c_int},
+    ptr::null_mut,
+};
+
+use log::warn;
+
+use crate::{
+    addon::{CCommand, CommandArgs},
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for ICvar, the console variable and command registry of the engine
+///
+/// Only the methods up to `FindCommand` are declared, in the same order as
+/// the Alien Swarm SDK's `icvar.h` (preceded by the methods of IAppSystem).
+/// MSVC places overloaded virtual methods in reverse declaration order, so
+/// the const variants of the `Find*` methods are declared first
+#[fabric_codegen::interface]
+pub(crate) trait Cvar {
+    fn connect(&mut self, factory: CreateInterfaceFn) -> bool;
+    fn disconnect(&mut self);
+    fn query_interface(&mut self, interface_name: &CStr) -> *mut c_void;
+    fn init(&mut self) -> c_int;
+    fn shutdown(&mut self);
+    fn get_dependencies(&mut self) -> *const c_void;
+    fn get_tier(&mut self) -> c_int;
+    fn reconnect(&mut self, factory: CreateInterfaceFn, interface_name: &CStr);
+
+    /// Allocate a unique DLL identifier
+    fn allocate_dll_identifier(&mut self) -> c_int;
+
+    /// Register, unregister commands
+    fn register_con_command(&mut self, command_base: *mut c_void);
+    fn unregister_con_command(&mut self, command_base: *mut c_void);
+    fn unregister_con_commands(&mut self, id: c_int);
+
+    /// If there is a +<varname> <value> on the command line, this returns the value.
+    /// Otherwise, it returns NULL.
+    fn get_command_line_value(&mut self, variable_name: &CStr) -> *const c_char;
+
+    /// Try to find the cvar pointer by name
+    fn find_command_base_const(&self, name: &CStr) -> *const c_void;
+    fn find_command_base(&mut self, name: &CStr) -> *mut c_void;
+    fn find_var_const(&self, var_name: &CStr) -> *const c_void;
+    fn find_var(&mut self, var_name: &CStr) -> *mut c_void;
+    fn find_command_const(&self, name: &CStr) -> *const c_void;
+    fn find_command(&mut self, name: &CStr) -> *mut c_void;
+}
+
+/// Binding for ConCommand, implemented by the addon to register
+/// its own console commands
+///
+/// The methods are declared in the same order as the Alien Swarm SDK's
+/// `convar.h`, starting with the ones inherited from ConCommandBase
+#[fabric_codegen::interface]
+pub(crate) trait ConCommand {
+    fn destructor(&mut self);
+
+    fn is_command(&self) -> bool;
+
+    /// Check flag
+    fn is_flag_set(&self, flag: c_int) -> bool;
+    /// Set flag
+    fn add_flags(&mut self, flags: c_int);
+    /// Clear flag
+    fn remove_flags(&mut self, flags: c_int);
+    fn get_flags(&self) -> c_int;
+
+    /// Return name of cvar
+    fn get_name(&self) -> &CStr;
+    /// Return help text for cvar
+    fn get_help_text(&self) -> &CStr;
+
+    /// Has this cvar been registered
+    fn is_registered(&self) -> bool;
+    /// Returns the DLL identifier
+    fn get_dll_identifier(&self) -> c_int;
+
+    fn create_base(&mut self, name: *const c_char, help_string: *const c_char, flags: c_int);
+    /// Used internally by OneTimeInit to initialize/shutdown
+    fn init(&mut self);
+
+    fn auto_complete_suggest(&mut self, partial: *const c_char, commands: *mut c_void) -> c_int;
+    fn can_auto_complete(&mut self) -> bool;
+
+    /// Invoke the function
+    fn dispatch(&mut self, command: *const CCommand);
+}
+
+/// Handler of a console command, called with the
+/// arguments of the command (including its name)
+pub(crate) type CommandHandler = fn(&[String]);
+
+/// Console command registered by the addon
+///
+/// The fields up to `callback_flags` have the same layout as the data members
+/// of ConCommandBase and ConCommand, the engine reads and writes them
+/// directly when the command is registered
+#[repr(C)]
+pub(crate) struct Command {
+    next: *mut c_void,
+    registered: bool,
+    name: *const c_char,
+    help_string: *const c_char,
+    flags: c_int,
+
+    /// The command callbacks are only called by the
+    /// original `Dispatch`, they are left null
+    callback: *mut c_void,
+    completion_callback: *mut c_void,
+    callback_flags: u8,
+
+    dll_identifier: c_int,
+    handler: CommandHandler,
+}
+
+impl ConCommand for Command {
+    fn destructor(&mut self) {}
+
+    fn is_command(&self) -> bool {
+        true
+    }
+
+    fn is_flag_set(&self, flag: c_int) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn add_flags(&mut self, flags: c_int) {
+        self.flags |= flags;
+    }
+
+    fn remove_flags(&mut self, flags: c_int) {
+        self.flags &= !flags;
+    }
+
+    fn get_flags(&self) -> c_int {
+        self.flags
+    }
+
+    fn get_name(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.name) }
+    }
+
+    fn get_help_text(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.help_string) }
+    }
+
+    fn is_registered(&self) -> bool {
+        self.registered
+    }
+
+    fn get_dll_identifier(&self) -> c_int {
+        self.dll_identifier
+    }
+
+    fn create_base(&mut self, name: *const c_char, help_string: *const c_char, flags: c_int) {
+        self.name = name;
+        self.help_string = help_string;
+        self.flags = flags;
+    }
+
+    fn init(&mut self) {}
+
+    fn auto_complete_suggest(&mut self, _partial: *const c_char, _commands: *mut c_void) -> c_int {
+        0
+    }
+
+    fn can_auto_complete(&mut self) -> bool {
+        false
+    }
+
+    fn dispatch(&mut self, command: *const CCommand) {
+        let args = unsafe { CommandArgs::from_ptr(command) }
+            .map(|command| command.args())
+            .unwrap_or_default();
+
+        (self.handler)(&args);
+    }
+}
+
+impl Deref for Command {
+    type Target = Self;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+impl DerefMut for Command {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+static COMMAND_VTABLE: IConCommand = <dyn ConCommand>::vtable::<Command, Command>();
+
+/// Binding for ConVar, implemented by the addon to register its own console variables
+///
+/// ConVar inherits from both ConCommandBase and IConVar, this is the vtable
+/// of its ConCommandBase part followed by the methods introduced by ConVar.
+/// The methods of IConVar are declared by `ConVarValue`
+#[fabric_codegen::interface]
+pub(crate) trait ConVar {
+    fn destructor(&mut self);
+
+    fn is_command(&self) -> bool;
+
+    /// Check flag
+    fn is_flag_set(&self, flag: c_int) -> bool;
+    /// Set flag
+    fn add_flags(&mut self, flags: c_int);
+    /// Clear flag
+    fn remove_flags(&mut self, flags: c_int);
+    fn get_flags(&self) -> c_int;
+
+    /// Return name of cvar
+    fn get_name(&self) -> &CStr;
+    /// Return help text for cvar
+    fn get_help_text(&self) -> &CStr;
+
+    /// Has this cvar been registered
+    fn is_registered(&self) -> bool;
+    /// Returns the DLL identifier
+    fn get_dll_identifier(&self) -> c_int;
+
+    fn create_base(&mut self, name: *const c_char, help_string: *const c_char, flags: c_int);
+    /// Used internally by OneTimeInit to initialize/shutdown
+    fn init(&mut self);
+
+    /// Called by CCvar when the value of a var is changing
+    fn internal_set_value(&mut self, value: *const c_char);
+    /// For CVARs marked FCVAR_NEVER_AS_STRING
+    fn internal_set_float_value(&mut self, value: f32);
+    fn internal_set_int_value(&mut self, value: c_int);
+
+    fn clamp_value(&mut self, value: *mut f32) -> bool;
+    fn change_string_value(&mut self, value: *const c_char, old_value: f32);
+
+    fn create(
+        &mut self,
+        name: *const c_char,
+        default_value: *const c_char,
+        flags: c_int,
+        help_string: *const c_char,
+        has_min: bool,
+        min: f32,
+        has_max: bool,
+        max: f32,
+        callback: *mut c_void,
+    );
+}
+
+/// Binding for IConVar, the interface through which the engine sets the
+/// value of a console variable
+///
+/// The overloads of `SetValue` are declared in reverse order for MSVC
+#[fabric_codegen::interface]
+pub(crate) trait ConVarValue {
+    fn set_value_int(&mut self, value: c_int);
+    fn set_value_float(&mut self, value: f32);
+    fn set_value_string(&mut self, value: &CStr);
+
+    fn get_name(&self) -> &CStr;
+    fn is_flag_set(&self, flag: c_int) -> bool;
+}
+
+/// Handler of a console variable, called with the new value of the variable
+/// when it is changed. Returning false rejects the value
+pub(crate) type VariableHandler = fn(&str) -> bool;
+
+/// Pointer from the IConVar part of a variable to the whole variable,
+/// this doubles as the `m_pParent` field of ConVar
+pub(crate) struct VariableRef(*mut CConVar<Variable>);
+
+impl Deref for VariableRef {
+    type Target = Variable;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &(*self.0).instance }
+    }
+}
+
+impl DerefMut for VariableRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut (*self.0).instance }
+    }
+}
+
+/// Console variable registered by the addon
+///
+/// The fields up to `change_callback` have the same layout as the data
+/// members of ConCommandBase, IConVar and ConVar, the engine reads the
+/// value of the variable directly from the `string`, `float_value` and
+/// `int_value` fields
+#[repr(C)]
+pub(crate) struct Variable {
+    next: *mut c_void,
+    registered: bool,
+    name: *const c_char,
+    help_string: *const c_char,
+    flags: c_int,
+
+    parent: CConVarValue<VariableRef>,
+    default_value: *const c_char,
+    string: *const c_char,
+    string_length: c_int,
+    float_value: f32,
+    int_value: c_int,
+    has_min: bool,
+    min: f32,
+    has_max: bool,
+    max: f32,
+    change_callback: *mut c_void,
+
+    dll_identifier: c_int,
+    /// Storage of `default_value` and `string`
+    _default: CString,
+    value: CString,
+    handler: VariableHandler,
+}
+
+/// Parse the value of a variable as a float like the engine does,
+/// falling back to 0 if it isn't a number
+fn parse_float(value: &CStr) -> f32 {
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Set the value of `variable`, unless it is rejected by its handler
+fn set_value(variable: &mut Variable, value: &CStr) {
+    if value == variable.value.as_c_str() {
+        return;
+    }
+
+    if !(variable.handler)(&value.to_string_lossy()) {
+        return;
+    }
+
+    variable.value = value.to_owned();
+    variable.string = variable.value.as_ptr();
+    variable.string_length = variable.value.as_bytes_with_nul().len() as c_int;
+
+    variable.float_value = parse_float(value);
+    variable.int_value = variable.float_value as c_int;
+}
+
+fn set_value_display(variable: &mut Variable, value: impl ToString) {
+    if let Ok(value) = CString::new(value.to_string()) {
+        set_value(variable, &value);
+    }
+}
+
+impl ConVar for Variable {
+    fn destructor(&mut self) {}
+
+    fn is_command(&self) -> bool {
+        false
+    }
+
+    fn is_flag_set(&self, flag: c_int) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn add_flags(&mut self, flags: c_int) {
+        self.flags |= flags;
+    }
+
+    fn remove_flags(&mut self, flags: c_int) {
+        self.flags &= !flags;
+    }
+
+    fn get_flags(&self) -> c_int {
+        self.flags
+    }
+
+    fn get_name(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.name) }
+    }
+
+    fn get_help_text(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.help_string) }
+    }
+
+    fn is_registered(&self) -> bool {
+        self.registered
+    }
+
+    fn get_dll_identifier(&self) -> c_int {
+        self.dll_identifier
+    }
+
+    fn create_base(&mut self, name: *const c_char, help_string: *const c_char, flags: c_int) {
+        self.name = name;
+        self.help_string = help_string;
+        self.flags = flags;
+    }
+
+    fn init(&mut self) {}
+
+    fn internal_set_value(&mut self, value: *const c_char) {
+        if !value.is_null() {
+            set_value(self, unsafe { CStr::from_ptr(value) });
+        }
+    }
+
+    fn internal_set_float_value(&mut self, value: f32) {
+        set_value_display(self, value);
+    }
+
+    fn internal_set_int_value(&mut self, value: c_int) {
+        set_value_display(self, value);
+    }
+
+    fn clamp_value(&mut self, _value: *mut f32) -> bool {
+        false
+    }
+
+    fn change_string_value(&mut self, _value: *const c_char, _old_value: f32) {}
+
+    fn create(
+        &mut self,
+        _name: *const c_char,
+        _default_value: *const c_char,
+        _flags: c_int,
+        _help_string: *const c_char,
+        _has_min: bool,
+        _min: f32,
+        _has_max: bool,
+        _max: f32,
+        _callback: *mut c_void,
+    ) {
+    }
+}
+
+impl ConVarValue for Variable {
+    fn set_value_int(&mut self, value: c_int) {
+        set_value_display(self, value);
+    }
+
+    fn set_value_float(&mut self, value: f32) {
+        set_value_display(self, value);
+    }
+
+    fn set_value_string(&mut self, value: &CStr) {
+        set_value(self, value);
+    }
+
+    fn get_name(&self) -> &CStr {
+        ConVar::get_name(self)
+    }
+
+    fn is_flag_set(&self, flag: c_int) -> bool {
+        ConVar::is_flag_set(self, flag)
+    }
+}
+
+impl Deref for Variable {
+    type Target = Self;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+impl DerefMut for Variable {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+static VARIABLE_VTABLE: IConVar = <dyn ConVar>::vtable::<Variable, Variable>();
+static VARIABLE_VALUE_VTABLE: IConVarValue = <dyn ConVarValue>::vtable::<VariableRef, Variable>();
+
+static mut CVAR: *mut c_void = null_mut();
+static mut DLL_IDENTIFIER: c_int = -1;
+
+/// Commands registered by the addon, the engine keeps pointers
+/// to the commands until they are unregistered
+static mut COMMANDS: Vec<Box<CConCommand<Command>>> = Vec::new();
+/// Variables registered by the addon, held the same way as `COMMANDS`
+static mut VARIABLES: Vec<Box<CConVar<Variable>>> = Vec::new();
+
+/// Find the console registry of the engine and allocate the
+/// DLL identifier of the commands registered by the addon
+pub(crate) fn init(factory: CreateInterfaceFn) {
+    let interfaces = game::interfaces();
+    let mut cvar = match game::acquire::<dyn Cvar>(factory, interfaces.engine_cvar, "ICvar") {
+        Some(cvar) => cvar,
+        None => return,
+    };
+
+    unsafe {
+        DLL_IDENTIFIER = cvar.allocate_dll_identifier();
+        CVAR = cvar.0;
+    }
+}
+
+pub(crate) fn cvar() -> Option<Foreign<dyn Cvar>> {
+    let cvar = unsafe { CVAR };
+    if cvar.is_null() {
+        None
+    } else {
+        Some(Foreign::with(cvar))
+    }
+}
+
+/// Data members of a console variable of the engine, laid out like a
+/// `CConVar<Variable>` up to the value of the variable
+#[repr(C)]
+struct EngineVariable {
+    vtable: *const c_void,
+    next: *mut c_void,
+    registered: bool,
+    name: *const c_char,
+    help_string: *const c_char,
+    flags: c_int,
+
+    value_vtable: *const c_void,
+    /// Variable holding the value, the variable itself unless it is a copy
+    parent: *const EngineVariable,
+    default_value: *const c_char,
+    string: *const c_char,
+}
+
+/// Read the value of the console variable `name` of the engine
+pub(crate) fn variable_value(name: &CStr) -> Option<String> {
+    let mut cvar = cvar()?;
+    let variable = cvar.find_var(name) as *const EngineVariable;
+    let variable = unsafe { variable.as_ref()? };
+    let variable = unsafe { variable.parent.as_ref() }.unwrap_or(variable);
+
+    if variable.string.is_null() {
+        None
+    } else {
+        let value = unsafe { CStr::from_ptr(variable.string) };
+        Some(value.to_string_lossy().into_owned())
+    }
+}
+
+/// Register the console command `name`, calling `handler` when it is executed
+pub(crate) fn register(name: &'static CStr, help_string: &'static CStr, handler: CommandHandler) {
+    let mut cvar = match cvar() {
+        Some(cvar) => cvar,
+        None => return,
+    };
+
+    let mut command = Box::new(CConCommand {
+        vtable: &COMMAND_VTABLE,
+        instance: Command {
+            next: null_mut(),
+            registered: false,
+            name: name.as_ptr(),
+            help_string: help_string.as_ptr(),
+            flags: 0,
+            callback: null_mut(),
+            completion_callback: null_mut(),
+            callback_flags: 0,
+            dll_identifier: unsafe { DLL_IDENTIFIER },
+            handler,
+        },
+    });
+
+    let ptr = &mut *command as *mut CConCommand<Command> as *mut c_void;
+    cvar.register_con_command(ptr);
+
+    unsafe {
+        COMMANDS.push(command);
+    }
+}
+
+/// Register the console variable `name` with the initial `value`,
+/// calling `handler` when its value is changed
+pub(crate) fn register_variable(
+    name: &'static CStr,
+    help_string: &'static CStr,
+    value: &str,
+    handler: VariableHandler,
+) {
+    let mut cvar = match cvar() {
+        Some(cvar) => cvar,
+        None => return,
+    };
+
+    let value = match CString::new(value) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("invalid value for {:?}: {}", name, err);
+            return;
+        }
+    };
+
+    // The heap buffers of the CStrings don't move with
+    // them, the variable can point to them directly
+    let default = value.clone();
+    let float_value = parse_float(&value);
+
+    let mut variable = Box::new(CConVar {
+        vtable: &VARIABLE_VTABLE,
+        instance: Variable {
+            next: null_mut(),
+            registered: false,
+            name: name.as_ptr(),
+            help_string: help_string.as_ptr(),
+            flags: 0,
+            parent: CConVarValue {
+                vtable: &VARIABLE_VALUE_VTABLE,
+                instance: VariableRef(null_mut()),
+            },
+            default_value: default.as_ptr(),
+            string: value.as_ptr(),
+            string_length: value.as_bytes_with_nul().len() as c_int,
+            float_value,
+            int_value: float_value as c_int,
+            has_min: false,
+            min: 0.0,
+            has_max: false,
+            max: 0.0,
+            change_callback: null_mut(),
+            dll_identifier: unsafe { DLL_IDENTIFIER },
+            _default: default,
+            value,
+            handler,
+        },
+    });
+
+    let ptr = &mut *variable as *mut CConVar<Variable>;
+    variable.instance.parent.instance = VariableRef(ptr);
+    cvar.register_con_command(ptr as *mut c_void);
+
+    unsafe {
+        VARIABLES.push(variable);
+    }
+}
+
+/// Unregister all the commands of the addon, this must
+/// be called before the addon is unloaded
+pub(crate) fn shutdown() {
+    if let Some(mut cvar) = cvar() {
+        cvar.unregister_con_commands(unsafe { DLL_IDENTIFIER });
+    }
+
+    unsafe {
+        COMMANDS.clear();
+        VARIABLES.clear();
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// Directory of the addon, relative to the game directory
+const ADDON_DIR: &str = "addons/fabric";
+/// Name of the configuration file in the addon directory
+const CONFIG_FILE: &str = "fabric.cfg";
+
+/// Addon configuration, loaded from a TOML file in the addon directory
+///
+/// All the keys are optional and missing values fall back to their defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Config {
+    #[serde(skip)]
+    root: PathBuf,
+
+    pub(crate) runtime: RuntimeConfig,
+    pub(crate) executor: ExecutorConfig,
+    pub(crate) http: HttpConfig,
+    pub(crate) logging: LoggingConfig,
+    pub(crate) remote: RemoteConfig,
+    pub(crate) metrics: MetricsConfig,
+    pub(crate) chat: ChatConfig,
+    pub(crate) flood: FloodConfig,
+    /// Per-module settings, keyed by module name
+    pub(crate) modules: HashMap<String, ModuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct RuntimeConfig {
+    /// Compile the modules for deterministic execution, this also seeds
+    /// the random number generator of all the modules with `seed`
+    pub(crate) deterministic: bool,
+    pub(crate) seed: u64,
+    /// Fuel given to each module on every frame, roughly the number of WASM
+    /// operators it can execute. Once a module runs out of fuel its calls are
+    /// aborted until the next frame. 0 lets the modules run without limits
+    pub(crate) budget: u32,
+    /// Disable the modules exceeding their budget on this
+    /// many consecutive frames, 0 never disables them
+    pub(crate) max_overruns: u32,
+    /// What happens to the events dispatched to a paused module
+    pub(crate) pause_policy: PausePolicy,
+    /// What happens when a module panics
+    pub(crate) panic_policy: PanicPolicy,
+    /// Refuse to load the modules without a `sha256` hash in their section
+    /// of `modules`, the modules with a hash are always verified
+    pub(crate) require_hashes: bool,
+    /// Maximum size in bytes the linear memory of a module can grow to,
+    /// 0 lets it grow up to the maximum declared by the module
+    pub(crate) max_memory: usize,
+    /// Host functions revoked for all the modules, as `Module::name`
+    /// or `Module::*` for all the functions of a host module
+    pub(crate) denied_imports: Vec<String>,
+    /// Host calls each module can make on every frame, the calls past this
+    /// quota are skipped and return zeroes. 0 lets the modules call the host
+    /// without limits
+    pub(crate) host_call_quota: u32,
+    /// Fuel left to a module when `Job::should_yield` asks its jobs to
+    /// suspend until the next frame, 0 keeps a quarter of the budget
+    pub(crate) yield_reserve: u32,
+    /// Check the interfaces of the engine look valid when they are acquired,
+    /// and acquire them again on level transitions if they were recreated
+    pub(crate) check_interfaces: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PausePolicy {
+    /// Deliver the events to the module once it is unpaused
+    Queue,
+    /// Discard the events
+    Drop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PanicPolicy {
+    /// Log the panic, the module keeps receiving its callbacks
+    LogOnly,
+    /// Never call the module again
+    DisableModule,
+    /// Unload all the modules on the next frame
+    UnloadPlugin,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            deterministic: false,
+            seed: 0,
+            budget: 0,
+            max_overruns: 10,
+            pause_policy: PausePolicy::Queue,
+            panic_policy: PanicPolicy::DisableModule,
+            require_hashes: false,
+            max_memory: 0,
+            denied_imports: Vec::new(),
+            host_call_quota: 0,
+            yield_reserve: 0,
+            check_interfaces: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct ExecutorConfig {
+    /// Number of threads in the background executor pool
+    pub(crate) threads: usize,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        ExecutorConfig { threads: 2 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct HttpConfig {
+    /// Hosts the modules are allowed to send requests to, either as an exact
+    /// name or as a `*.domain` wildcard. An empty list denies all requests
+    pub(crate) allowed_hosts: Vec<String>,
+    /// Maximum size of a response body in bytes
+    pub(crate) max_response_size: usize,
+    /// Timeout for a complete request in milliseconds
+    pub(crate) timeout_ms: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            allowed_hosts: Vec::new(),
+            max_response_size: 1024 * 1024,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub(crate) fn is_host_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            if pattern.starts_with("*.") {
+                host.ends_with(&pattern[1..])
+            } else {
+                host == pattern
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct LoggingConfig {
+    /// Initial value of the `fabric_log_level` console variable
+    pub(crate) level: String,
+    /// Initial value of the `fabric_log_targets` console variable
+    pub(crate) targets: String,
+    /// File the log records are written to as JSON lines, relative
+    /// to the addon directory. Empty disables the JSON output
+    pub(crate) json: String,
+    pub(crate) colors: ColorConfig,
+}
+
+/// Colors of the log lines in the `#rrggbb` or `#rrggbbaa` format,
+/// the missing levels keep their default color
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ColorConfig {
+    pub(crate) error: Option<String>,
+    pub(crate) warn: Option<String>,
+    pub(crate) info: Option<String>,
+    pub(crate) debug: Option<String>,
+    pub(crate) trace: Option<String>,
+    /// Colors of the info, debug and trace lines logged by modules, by module name
+    pub(crate) modules: HashMap<String, String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: String::from("debug"),
+            targets: String::new(),
+            json: String::new(),
+            colors: ColorConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct RemoteConfig {
+    /// Address the remote management channel listens on, like
+    /// `127.0.0.1:27099`. Empty disables the channel
+    pub(crate) listen: String,
+    /// Password sent by the clients of the channel, the
+    /// channel is disabled if no password is set
+    pub(crate) password: String,
+    /// Timeout for reading a request and writing its response in milliseconds
+    pub(crate) timeout_ms: u64,
+    /// Connections handled at the same time, the others are refused
+    pub(crate) max_connections: usize,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig {
+            listen: String::new(),
+            password: String::new(),
+            timeout_ms: 5000,
+            max_connections: 4,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct MetricsConfig {
+    /// Address of the HTTP endpoint serving the metrics of the modules
+    /// in the Prometheus format, like `127.0.0.1:9100`. Empty disables it
+    pub(crate) listen: String,
+    /// Address of the statsd server the metrics are pushed to, like
+    /// `127.0.0.1:8125`. Empty disables the push
+    pub(crate) statsd: String,
+    /// Interval between two pushes to statsd in milliseconds
+    pub(crate) interval_ms: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            listen: String::new(),
+            statsd: String::new(),
+            interval_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct FloodConfig {
+    /// Commands per second each client can issue, for each command.
+    /// 0 lets the clients issue the commands without limits
+    pub(crate) rate: f32,
+    /// Commands a client can issue at once before being limited
+    pub(crate) burst: u32,
+    /// Limits of specific commands, keyed by command name
+    pub(crate) commands: HashMap<String, LimitConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct LimitConfig {
+    pub(crate) rate: f32,
+    pub(crate) burst: u32,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        FloodConfig {
+            rate: 10.0,
+            burst: 20,
+            commands: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ChatConfig {
+    /// Colors of the chat tags keyed by the `ProductName` of the game then
+    /// by tag name, as a color code or in the `#rrggbb` or `#rrggbbaa` format
+    pub(crate) colors: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ModuleConfig {
+    /// Dependencies of the module, added to the ones declared in its manifest
+    pub(crate) requires: Vec<String>,
+    /// Overrides the `priority` of the manifest of the module
+    pub(crate) priority: Option<i32>,
+    /// Overrides `runtime.budget` for this module
+    pub(crate) budget: Option<u32>,
+    /// Overrides `runtime.max_memory` for this module
+    pub(crate) max_memory: Option<usize>,
+    /// Overrides `runtime.host_call_quota` for this module
+    pub(crate) host_call_quota: Option<u32>,
+    /// Permissions granted to the module in addition to the
+    /// ones of its manifest, see `Manifest::permissions`
+    pub(crate) permissions: Vec<String>,
+    /// Imports of the module resolved differently, keyed by `Module::name`:
+    /// "deny" revokes the import, a `Module::name` value resolves it to
+    /// that other host function
+    pub(crate) imports: HashMap<String, String>,
+    /// Hex-encoded SHA-256 hash of the module file, the module
+    /// is not loaded if its file doesn't match it
+    pub(crate) sha256: Option<String>,
+    /// Settings read by the module with the `Config` host module
+    pub(crate) settings: HashMap<String, toml::Value>,
+    /// Settings overriding `settings` on some maps, keyed by map name
+    pub(crate) maps: HashMap<String, HashMap<String, toml::Value>>,
+}
+
+impl Config {
+    /// Root directory of the addon, where the configuration and
+    /// the data of the modules is stored
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Fuel given to the module `name` on every frame, 0 if it's unlimited
+    pub(crate) fn budget(&self, name: &str) -> u32 {
+        self.modules
+            .get(name)
+            .and_then(|module| module.budget)
+            .unwrap_or(self.runtime.budget)
+    }
+
+    /// Host calls the module `name` can make on every frame, 0 if it's unlimited
+    pub(crate) fn host_call_quota(&self, name: &str) -> u32 {
+        self.modules
+            .get(name)
+            .and_then(|module| module.host_call_quota)
+            .unwrap_or(self.runtime.host_call_quota)
+    }
+
+    /// Size in bytes the memory of the module `name` can grow to, 0 if it's unlimited
+    pub(crate) fn max_memory(&self, name: &str) -> usize {
+        self.modules
+            .get(name)
+            .and_then(|module| module.max_memory)
+            .unwrap_or(self.runtime.max_memory)
+    }
+
+    /// Value of the setting `key` of the module `name` on the current map,
+    /// strings are returned as is and other values in the TOML format
+    pub(crate) fn setting(&self, name: &str, key: &str) -> Option<String> {
+        let module = self.modules.get(name)?;
+        let overlay = map().and_then(|map| module.maps.get(map));
+
+        let value = overlay
+            .and_then(|settings| settings.get(key))
+            .or_else(|| module.settings.get(key))?;
+
+        match value {
+            toml::Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+static mut CONFIG: Option<Config> = None;
+
+/// Name of the current map, selecting the overlay of the module settings
+static mut MAP: Option<String> = None;
+
+/// Load the configuration from the addon directory in `game_dir`,
+/// this must be called from the game thread before starting the executor
+pub(crate) fn load(game_dir: &Path) {
+    load_root(game_dir.join(ADDON_DIR));
+}
+
+fn load_root(root: PathBuf) {
+    let path = root.join(CONFIG_FILE);
+
+    let mut config = match fs::read_to_string(&path) {
+        Ok(source) => match toml::from_str(&source) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("could not parse {}: {}", path.display(), err);
+                Config::default()
+            }
+        },
+        Err(err) => {
+            debug!("could not read {}: {}", path.display(), err);
+            Config::default()
+        }
+    };
+
+    config.root = root;
+    debug!("loaded config {:?}", config);
+
+    unsafe {
+        CONFIG = Some(config);
+    }
+}
+
+/// Load the configuration again from the directory it was loaded from,
+/// returns false if it was never loaded
+pub(crate) fn reload() -> bool {
+    let root = get().root();
+    if root.as_os_str().is_empty() {
+        return false;
+    }
+
+    load_root(root.to_path_buf());
+    true
+}
+
+/// Set the current map when a new level starts
+pub(crate) fn set_map(map: &str) {
+    unsafe {
+        MAP = Some(map.into());
+    }
+}
+
+/// Name of the current map, if a level was started
+pub(crate) fn map() -> Option<&'static str> {
+    unsafe { MAP.as_deref() }
+}
+
+/// Get the current configuration, or the default
+/// configuration if it hasn't been loaded yet
+pub(crate) fn get() -> &'static Config {
+    unsafe { CONFIG.get_or_insert_with(Config::default) }
+}
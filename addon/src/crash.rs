@@ -0,0 +1,106 @@
+//! Crash reports of the modules, written to `crashes/` in the directory of
+//! the addon when a module panics or aborts and the `panic_policy` stops it
+//!
+//! A report holds what the module was doing when it crashed, the last host
+//! functions it called, the backtrace of the crash and the configuration of
+//! the runtime. The frames of the guest code are compiled by the runtime
+//! and appear unsymbolized in the backtrace, between the host frames
+
+use std::{
+    backtrace::Backtrace,
+    fmt::Write,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, warn};
+
+use crate::{config, logging::current_event, module::FabricEnv};
+
+const CRASH_DIR: &str = "crashes";
+/// Maximum number of reports written while the plugin is loaded, so a
+/// module crashing again after every reload doesn't fill the disk
+const MAX_REPORTS: u32 = 32;
+
+/// Only accessed from the game thread
+static mut REPORTS: u32 = 0;
+
+/// Format the report of the crash of the module `env`
+fn format_report(env: &FabricEnv, context: &str, reason: &str, backtrace: &str) -> String {
+    let mut report = String::new();
+
+    let _ = writeln!(report, "module: {}", env.name);
+    let _ = writeln!(report, "generation: {}", env.generation);
+    let _ = writeln!(report, "crash: {}", context);
+    let _ = writeln!(report, "reason: {}", reason);
+    let _ = writeln!(
+        report,
+        "event: {}",
+        current_event().as_deref().unwrap_or("none")
+    );
+
+    let _ = writeln!(report, "\nlast host calls, most recent last:");
+    let host_calls = env.host_calls.borrow();
+    let mut empty = true;
+    for call in host_calls.iter() {
+        let _ = writeln!(report, "  {}", call);
+        empty = false;
+    }
+
+    if empty {
+        let _ = writeln!(report, "  none");
+    }
+
+    let _ = writeln!(report, "\nbacktrace:\n{}", backtrace);
+
+    // Only the runtime section, the others can hold credentials
+    let _ = writeln!(
+        report,
+        "runtime configuration:\n{:#?}",
+        config::get().runtime
+    );
+
+    report
+}
+
+/// Write the report of the crash of the module `env`, `backtrace` is the
+/// backtrace captured by the panic hook, or None to capture it here
+pub(crate) fn write_report(env: &FabricEnv, context: &str, reason: &str, backtrace: Option<&str>) {
+    let reports = unsafe { &mut REPORTS };
+    if *reports >= MAX_REPORTS {
+        return;
+    }
+
+    *reports += 1;
+
+    let dir = config::get().root().join(CRASH_DIR);
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!("could not create {}: {}", dir.display(), err);
+        return;
+    }
+
+    let captured;
+    let backtrace = match backtrace {
+        Some(backtrace) => backtrace,
+        None => {
+            captured = Backtrace::force_capture().to_string();
+            &captured
+        }
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = dir.join(format!("{}-{}-{}.txt", env.name, time, reports));
+
+    let report = format_report(env, context, reason, backtrace);
+    match fs::write(&path, report) {
+        Ok(()) => error!(
+            "wrote the crash report of {} to {}",
+            env.name,
+            path.display()
+        ),
+        Err(err) => warn!("could not write {}: {}", path.display(), err),
+    }
+}
@@ -0,0 +1,206 @@
+//! Events defined and fired by the modules, delivered to the listeners of the
+//! other modules like the game events without going through the engine
+//!
+//! The names of the custom events are namespaced with the name of the module
+//! creating them as `module:event`, the names of the engine events never
+//! contain a colon. The events are `GameEvent` objects implemented by the
+//! addon, so the listeners and the field accessors work the same on both
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::c_int,
+    ptr,
+};
+
+use log::warn;
+
+use crate::manager::{CGameEvent, GameEvent, IGameEvent};
+
+/// Separator of the namespace and the name of a custom event
+pub(crate) const SEPARATOR: u8 = b':';
+
+/// Whether `name` is the name of a custom event rather than an engine event
+pub(crate) fn is_custom_name(name: &[u8]) -> bool {
+    name.contains(&SEPARATOR)
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Bool(bool),
+    Int(c_int),
+    Uint64(u64),
+    Float(f32),
+    String(CString),
+}
+
+/// Event created by a module, with the fields it set
+#[derive(Clone, Debug)]
+pub(crate) struct CustomEvent {
+    name: CString,
+    fields: Vec<(CString, Value)>,
+}
+
+impl CustomEvent {
+    /// Find the field `name`, compared case-insensitively like the engine does
+    fn find(&self, name: &CStr) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.to_bytes().eq_ignore_ascii_case(name.to_bytes()))
+            .map(|(_, value)| value)
+    }
+
+    fn set(&mut self, name: &CStr, value: Value) {
+        let field = self
+            .fields
+            .iter_mut()
+            .find(|(field, _)| field.to_bytes().eq_ignore_ascii_case(name.to_bytes()));
+
+        match field {
+            Some((_, field)) => *field = value,
+            None => self.fields.push((name.to_owned(), value)),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(value: &CStr) -> Option<T> {
+        value.to_str().ok()?.trim().parse().ok()
+    }
+}
+
+impl GameEvent for CustomEvent {
+    fn destructor(&self) {}
+
+    fn get_name(&self) -> &CStr {
+        &self.name
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn is_empty(&mut self, name: &CStr) -> bool {
+        self.find(name).is_none()
+    }
+
+    fn get_bool(&mut self, name: &CStr, default: bool) -> bool {
+        match self.find(name) {
+            Some(Value::Bool(value)) => *value,
+            Some(_) => self.get_int(name, default as c_int) != 0,
+            None => default,
+        }
+    }
+
+    fn get_int(&mut self, name: &CStr, default: c_int) -> c_int {
+        match self.find(name) {
+            Some(Value::Bool(value)) => *value as c_int,
+            Some(Value::Int(value)) => *value,
+            Some(Value::Uint64(value)) => *value as c_int,
+            Some(Value::Float(value)) => *value as c_int,
+            Some(Value::String(value)) => Self::parse(value).unwrap_or(0),
+            None => default,
+        }
+    }
+
+    fn get_uint64(&mut self, name: &CStr, default: u64) -> u64 {
+        match self.find(name) {
+            Some(Value::Bool(value)) => *value as u64,
+            Some(Value::Int(value)) => *value as u64,
+            Some(Value::Uint64(value)) => *value,
+            Some(Value::Float(value)) => *value as u64,
+            Some(Value::String(value)) => Self::parse(value).unwrap_or(0),
+            None => default,
+        }
+    }
+
+    fn get_float(&mut self, name: &CStr, default: f32) -> f32 {
+        match self.find(name) {
+            Some(Value::Bool(value)) => *value as c_int as f32,
+            Some(Value::Int(value)) => *value as f32,
+            Some(Value::Uint64(value)) => *value as f32,
+            Some(Value::Float(value)) => *value,
+            Some(Value::String(value)) => Self::parse(value).unwrap_or(0.0),
+            None => default,
+        }
+    }
+
+    fn get_string(&mut self, name: &CStr, default: &CStr) -> &CStr {
+        // Like the engine, only the fields holding a string are returned
+        match self.find(name) {
+            Some(Value::String(value)) => value,
+            _ => unsafe { CStr::from_ptr(default.as_ptr()) },
+        }
+    }
+
+    fn set_bool(&mut self, name: &CStr, value: bool) {
+        self.set(name, Value::Bool(value));
+    }
+
+    fn set_int(&mut self, name: &CStr, value: c_int) {
+        self.set(name, Value::Int(value));
+    }
+
+    fn set_uint64(&mut self, name: &CStr, value: u64) {
+        self.set(name, Value::Uint64(value));
+    }
+
+    fn set_float(&mut self, name: &CStr, value: f32) {
+        self.set(name, Value::Float(value));
+    }
+
+    fn set_string(&mut self, name: &CStr, value: &CStr) {
+        self.set(name, Value::String(value.to_owned()));
+    }
+}
+
+static EVENT_VTABLE: IGameEvent = <dyn GameEvent>::vtable::<Box<CustomEvent>, CustomEvent>();
+
+type CustomEventObject = CGameEvent<Box<CustomEvent>>;
+
+fn allocate(event: CustomEvent) -> *mut c_void {
+    let object = Box::new(CGameEvent {
+        vtable: &EVENT_VTABLE,
+        instance: Box::new(event),
+    });
+
+    Box::into_raw(object) as *mut c_void
+}
+
+/// Create the custom event `name` of the module `namespace`, returns None
+/// if the name is empty or already namespaced. The event is owned by the
+/// caller until it is fired or freed
+pub(crate) fn create(namespace: &str, name: &CStr) -> Option<*mut c_void> {
+    if name.to_bytes().is_empty() || is_custom_name(name.to_bytes()) {
+        warn!("invalid custom event name {:?}", name);
+        return None;
+    }
+
+    let mut full_name = namespace.as_bytes().to_vec();
+    full_name.push(SEPARATOR);
+    full_name.extend_from_slice(name.to_bytes());
+
+    let name = CString::new(full_name).ok()?;
+    Some(allocate(CustomEvent {
+        name,
+        fields: Vec::new(),
+    }))
+}
+
+/// Whether `event` is a custom event, rather than an event of the engine
+pub(crate) fn is_custom(event: *mut c_void) -> bool {
+    let vtable = unsafe { (*(event as *const CustomEventObject)).vtable };
+    ptr::eq(vtable, &EVENT_VTABLE)
+}
+
+/// Copy the custom event `event`, the copy must be freed
+pub(crate) fn duplicate(event: *mut c_void) -> *mut c_void {
+    let event = unsafe { &*(event as *const CustomEventObject) };
+    allocate((*event.instance).clone())
+}
+
+/// Free a custom event created by `create` or `duplicate`
+pub(crate) fn free(event: *mut c_void) {
+    unsafe { drop(Box::from_raw(event as *mut CustomEventObject)) };
+}
@@ -0,0 +1,183 @@
+//! Deferred acquisition of the optional interfaces of the engine
+//!
+//! Depending on the load order of the plugins, the factories may not expose
+//! some interfaces yet when the addon is loaded. The features depending on
+//! them are capabilities the modules can check with `Server::has_capability`:
+//! a missing one is looked up again on each level transition, and the first
+//! time a host function needs it during a level. Once it is found, the
+//! modules that registered with `Server::on_capability` are notified
+
+use std::fmt::{self, Display, Formatter};
+
+use log::{debug, info};
+
+use crate::{
+    bot, effects, entity, foreign::CreateInterfaceFn, host::globals, manager, sound, tools, trace,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Capability {
+    GameEvents,
+    Trace,
+    Sound,
+    Effects,
+    Bots,
+    Tools,
+    Entities,
+    Globals,
+}
+
+impl Capability {
+    pub(crate) const ALL: [Capability; 8] = [
+        Capability::GameEvents,
+        Capability::Trace,
+        Capability::Sound,
+        Capability::Effects,
+        Capability::Bots,
+        Capability::Tools,
+        Capability::Entities,
+        Capability::Globals,
+    ];
+
+    /// Identifier of the capability, one of the `CAPABILITY_*`
+    /// constants of the `Server` host module
+    pub(crate) fn id(self) -> i32 {
+        self as i32
+    }
+
+    pub(crate) fn from_id(id: i32) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    fn bit(self) -> u32 {
+        1 << self.id()
+    }
+
+    /// Whether the interface of the capability was acquired, the getters
+    /// used here call `retry` themselves when the interface is missing
+    pub(crate) fn is_available(self) -> bool {
+        match self {
+            Capability::GameEvents => manager::manager().is_some(),
+            Capability::Trace => trace::engine_trace().is_some(),
+            Capability::Sound => sound::sound().is_some(),
+            Capability::Effects => effects::effects().is_some(),
+            Capability::Bots => bot::bot_manager().is_some(),
+            Capability::Tools => tools::server_tools().is_some(),
+            Capability::Entities => entity::game_ents().is_some(),
+            Capability::Globals => globals::globals().is_some(),
+        }
+    }
+
+    /// Look the interface of the capability up in its factory
+    fn acquire(self, factory: CreateInterfaceFn, server: CreateInterfaceFn) {
+        match self {
+            Capability::GameEvents => {
+                manager::init(factory);
+            }
+            Capability::Trace => trace::init(factory),
+            Capability::Sound => sound::init(factory),
+            Capability::Effects => effects::init(server),
+            Capability::Bots => bot::init(server),
+            Capability::Tools => tools::init(server),
+            Capability::Entities => entity::init(server),
+            Capability::Globals => globals::init(server),
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::GameEvents => "game events",
+            Capability::Trace => "traces",
+            Capability::Sound => "sounds",
+            Capability::Effects => "effects",
+            Capability::Bots => "bots",
+            Capability::Tools => "server tools",
+            Capability::Entities => "entities",
+            Capability::Globals => "global variables",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Factories of the engine and of the server, set when the addon is loaded
+static mut FACTORIES: Option<(CreateInterfaceFn, CreateInterfaceFn)> = None;
+/// Capabilities whose interface is missing, as bit sets of `Capability::bit`
+static mut MISSING: u32 = 0;
+/// Capabilities looked up again since the start of the level
+static mut RETRIED: u32 = 0;
+/// Capabilities found since the last call to `take_available`
+static mut AVAILABLE: Vec<Capability> = Vec::new();
+
+/// Record the capabilities missing once the interfaces were acquired when
+/// the addon is loaded, they are looked up again later
+pub(crate) fn init(factory: CreateInterfaceFn, server: CreateInterfaceFn) {
+    unsafe {
+        FACTORIES = Some((factory, server));
+        MISSING = 0;
+        RETRIED = 0;
+        AVAILABLE.clear();
+    }
+
+    for capability in &Capability::ALL {
+        if !capability.is_available() {
+            info!(
+                "{} are unavailable, looking them up again later",
+                capability
+            );
+            unsafe { MISSING |= capability.bit() };
+        }
+    }
+}
+
+/// Look the interface of `capability` up again if it is missing and wasn't
+/// looked up yet on this level, returns true if it is now available
+pub(crate) fn retry(capability: Capability) -> bool {
+    let (factory, server) = match unsafe { FACTORIES } {
+        Some(factories) => factories,
+        None => return false,
+    };
+
+    let bit = capability.bit();
+    if unsafe { MISSING & bit == 0 || RETRIED & bit != 0 } {
+        return false;
+    }
+
+    // Set before the lookup, the getters checking the availability
+    // of the capability below don't look it up a second time
+    unsafe { RETRIED |= bit };
+    capability.acquire(factory, server);
+
+    if !capability.is_available() {
+        debug!("{} are still unavailable", capability);
+        return false;
+    }
+
+    unsafe {
+        MISSING &= !bit;
+        AVAILABLE.push(capability);
+    }
+
+    true
+}
+
+/// Look up the interfaces of all the missing capabilities again, including
+/// the ones that became unavailable since the addon was loaded
+pub(crate) fn level_init() {
+    unsafe { RETRIED = 0 };
+
+    for capability in &Capability::ALL {
+        if !capability.is_available() {
+            unsafe { MISSING |= capability.bit() };
+            retry(*capability);
+        }
+    }
+}
+
+/// Take the capabilities found since the last call, the modules
+/// are notified of them from the game frame
+pub(crate) fn take_available() -> Vec<Capability> {
+    unsafe { AVAILABLE.drain(..).collect() }
+}
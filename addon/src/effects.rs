@@ -0,0 +1,105 @@
+use std::{
+    ffi::c_void,
+    os::raw::{c_int, c_uchar},
+    ptr::null_mut,
+};
+
+use crate::{
+    deferred::{self, Capability},
+    engine::Vector,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for IEffects, the temp entity helpers exposed by the server DLL
+///
+/// The methods are declared in the same order as the Alien Swarm SDK's
+/// `IEffects.h`, after the virtual destructor of `IPredictionSystem`
+#[fabric_codegen::interface]
+pub(crate) trait Effects {
+    fn destructor(&self);
+
+    fn beam(
+        &mut self,
+        start: *const Vector,
+        end: *const Vector,
+        model_index: c_int,
+        halo_index: c_int,
+        frame_start: c_uchar,
+        frame_rate: c_uchar,
+        life: f32,
+        width: c_uchar,
+        end_width: c_uchar,
+        fade_length: c_uchar,
+        noise: c_uchar,
+        red: c_uchar,
+        green: c_uchar,
+        blue: c_uchar,
+        brightness: c_uchar,
+        speed: c_uchar,
+    );
+
+    /// Emits smoke sprites.
+    fn smoke(&mut self, origin: *const Vector, model_index: c_int, scale: f32, frame_rate: f32);
+
+    /// Spark effects
+    fn sparks(
+        &mut self,
+        position: *const Vector,
+        magnitude: c_int,
+        trail_length: c_int,
+        direction: *const Vector,
+    );
+
+    /// Dust effects
+    fn dust(&mut self, position: *const Vector, direction: *const Vector, size: f32, speed: f32);
+
+    /// Muzzle flash effects
+    fn muzzle_flash(
+        &mut self,
+        origin: *const Vector,
+        angles: *const Vector,
+        scale: f32,
+        kind: c_int,
+    );
+
+    /// Metal sparks
+    fn metal_sparks(&mut self, position: *const Vector, direction: *const Vector);
+
+    /// Energy splash
+    fn energy_splash(&mut self, position: *const Vector, direction: *const Vector, explosive: bool);
+
+    /// Ricochet sound
+    fn ricochet(&mut self, position: *const Vector, direction: *const Vector);
+
+    fn time(&mut self) -> f32;
+    fn is_server(&mut self) -> bool;
+
+    /// Used by the playback system to suppress sounds
+    fn suppress_effects_sounds(&mut self, suppress: bool);
+}
+
+static mut EFFECTS: *mut c_void = null_mut();
+
+/// Acquire the effects interface from the server factory, this
+/// must be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interface = game::acquire::<dyn Effects>(server, game::interfaces().effects, "IEffects");
+    unsafe {
+        EFFECTS = interface.map_or(null_mut(), |effects| effects.0);
+    }
+}
+
+/// Get the effects interface, looked up again at most once per level while it is missing
+pub(crate) fn effects() -> Option<Foreign<dyn Effects>> {
+    let mut effects = unsafe { EFFECTS };
+    if effects.is_null() && deferred::retry(Capability::Effects) {
+        effects = unsafe { EFFECTS };
+    }
+
+    if effects.is_null() {
+        None
+    } else {
+        Some(Foreign::with(effects))
+    }
+}
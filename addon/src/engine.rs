@@ -0,0 +1,512 @@
+use std::{
+    ffi::{c_void, CStr},
+    mem,
+    os::raw::{c_char, c_int, c_uchar},
+    ptr::null_mut,
+};
+
+use fabric_codegen::cstr;
+
+use crate::{
+    addon::Edict,
+    bitbuf::bf_write,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Vector {
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) z: f32,
+}
+
+/// Binding for IVEngineServer, the main interface exposed by the engine to
+/// the server DLL (and to server plugins)
+///
+/// The methods are declared in the same order as the Alien Swarm SDK's
+/// `eiface.h`, entries that are currently unused by the addon are declared
+/// with opaque pointer types. Variadic methods (`ClientCommand`, `Con_NPrintf`,
+/// `Con_NXPrintf`) use the cdecl calling convention and must not be called
+/// through this vtable, `client_command` calls `ClientCommand` with it.
+#[fabric_codegen::interface]
+pub(crate) trait VEngineServer {
+    /// Tell engine to change level ( "changelevel s1\n" or "changelevel2 s1 s2\n" )
+    fn change_level(&mut self, level: &CStr, landmark: *const c_char);
+
+    /// Ask engine whether the specified map is a valid map file (exists and has valid version number).
+    fn is_map_valid(&mut self, filename: &CStr) -> c_int;
+
+    /// Is this a dedicated server?
+    fn is_dedicated_server(&mut self) -> bool;
+
+    /// Is in Hammer editing mode?
+    fn is_in_edit_mode(&mut self) -> c_int;
+
+    /// get arbitrary launch options
+    fn get_launch_options(&mut self) -> *mut c_void;
+
+    /// Add to the server/client lookup/precache table, the specified string is given a unique index
+    fn precache_model(&mut self, name: &CStr, preload: bool) -> c_int;
+    fn precache_sentence_file(&mut self, name: &CStr, preload: bool) -> c_int;
+    fn precache_decal(&mut self, name: &CStr, preload: bool) -> c_int;
+    fn precache_generic(&mut self, name: &CStr, preload: bool) -> c_int;
+
+    /// Check's if the name is precached, but doesn't actually precache the name if not...
+    fn is_model_precached(&self, name: &CStr) -> bool;
+    fn is_decal_precached(&self, name: &CStr) -> bool;
+    fn is_generic_precached(&self, name: &CStr) -> bool;
+
+    /// Note that sv_leafs_pvs and mod_pvs are now used for the new PVS/PHS
+    fn get_cluster_for_origin(&mut self, origin: *const Vector) -> c_int;
+    fn get_pvs_for_cluster(
+        &mut self,
+        cluster: c_int,
+        output_length: c_int,
+        output: *mut c_uchar,
+    ) -> c_int;
+    fn check_origin_in_pvs(
+        &mut self,
+        origin: *const Vector,
+        pvs: *const c_uchar,
+        pvs_size: c_int,
+    ) -> bool;
+    fn check_box_in_pvs(
+        &mut self,
+        mins: *const Vector,
+        maxs: *const Vector,
+        pvs: *const c_uchar,
+        pvs_size: c_int,
+    ) -> bool;
+
+    /// Returns the server assigned userid for this player. Useful for logging frags, etc.
+    /// returns -1 if the edict couldn't be found in the list of players.
+    fn get_player_user_id(&mut self, edict: *const Edict) -> c_int;
+    fn get_player_network_id_string(&mut self, edict: *const Edict) -> *const c_char;
+    fn is_user_id_in_use(&mut self, user_id: c_int) -> bool;
+    fn get_loading_progress_for_user_id(&mut self, user_id: c_int) -> c_int;
+
+    /// Return the current number of used edict slots
+    fn get_entity_count(&mut self) -> c_int;
+
+    /// Get stats info interface for a client netchannel
+    fn get_player_net_info(&mut self, player_index: c_int) -> *mut c_void;
+
+    /// Allocate space for string and return index/offset of string in global string list
+    /// If iForceEdictIndex is not -1, then it will return the edict with that index. If that edict index
+    /// is already used, it'll return null.
+    fn create_edict(&mut self, force_edict_index: c_int) -> *mut Edict;
+    /// Remove the specified edict and place back into the free edict list
+    fn remove_edict(&mut self, edict: *mut Edict);
+
+    /// Memory allocation for entity class data
+    fn pv_alloc_ent_private_data(&mut self, size: c_int) -> *mut c_void;
+    fn free_ent_private_data(&mut self, entity: *mut c_void);
+
+    /// Save/restore uses a special memory allocator (which zeroes newly allocated memory, etc.)
+    fn save_alloc_memory(&mut self, num: usize, size: usize) -> *mut c_void;
+    fn save_free_memory(&mut self, memory: *mut c_void);
+
+    /// Emit an ambient sound associated with the specified entity
+    fn emit_ambient_sound(
+        &mut self,
+        entity_index: c_int,
+        position: *const Vector,
+        sample: &CStr,
+        volume: f32,
+        sound_level: c_int,
+        flags: c_int,
+        pitch: c_int,
+        delay: f32,
+    );
+
+    /// Fade out the client's volume level toward silence (or fadePercent)
+    fn fade_client_volume(
+        &mut self,
+        edict: *const Edict,
+        fade_percent: f32,
+        fade_out_seconds: f32,
+        hold_time: f32,
+        fade_in_seconds: f32,
+    );
+
+    /// Sentences / sentence groups
+    fn sentence_group_pick(&mut self, group_index: c_int, name: *mut c_char, len: c_int) -> c_int;
+    fn sentence_group_pick_sequential(
+        &mut self,
+        group_index: c_int,
+        name: *mut c_char,
+        len: c_int,
+        sentence_index: c_int,
+        reset: c_int,
+    ) -> c_int;
+    fn sentence_index_from_name(&mut self, name: &CStr) -> c_int;
+    fn sentence_name_from_index(&mut self, index: c_int) -> *const c_char;
+    fn sentence_group_index_from_name(&mut self, name: &CStr) -> c_int;
+    fn sentence_group_name_from_index(&mut self, index: c_int) -> *const c_char;
+    fn sentence_length(&mut self, index: c_int) -> f32;
+
+    /// Issue a command to the command parser as if it was typed at the server console.
+    fn server_command(&mut self, command: &CStr);
+    /// Execute any commands currently in the command parser immediately (instead of once per frame)
+    fn server_execute(&mut self);
+    /// Issue the specified command to the specified client (mimics that client typing the command at the console).
+    fn client_command_variadic(&mut self);
+
+    /// Set the lightstyle to the specified value and network the change to any connected clients.
+    fn light_style(&mut self, style: c_int, value: &CStr);
+
+    /// Project a static decal onto the specified entity / model (for level placed decals in the .bsp)
+    fn static_decal(
+        &mut self,
+        origin: *const Vector,
+        decal_index: c_int,
+        entity_index: c_int,
+        model_index: c_int,
+        low_priority: bool,
+    );
+
+    /// Given the current PVS(or PAS) and origin, determine which players should hear/receive the message
+    fn message_determine_multicast_recipients(
+        &mut self,
+        use_pas: bool,
+        origin: *const Vector,
+        player_bits: *mut c_void,
+    );
+
+    /// Begin a message from a server side entity to its client side counterpart (func_breakable glass, e.g.)
+    fn entity_message_begin(
+        &mut self,
+        entity_index: c_int,
+        entity_class: *mut c_void,
+        reliable: bool,
+    ) -> *mut c_void;
+    /// Begin a usermessage from the server to the client .dll
+    fn user_message_begin(
+        &mut self,
+        filter: *mut c_void,
+        message_type: c_int,
+        message_name: &CStr,
+    ) -> *mut bf_write;
+    /// Finish the Entity or UserMessage and dispatch to network layer
+    fn message_end(&mut self);
+
+    /// Print szMsg to the client console.
+    fn client_printf(&mut self, edict: *mut Edict, message: &CStr);
+
+    /// SINGLE PLAYER/LISTEN SERVER ONLY (just matching the client .dll api for this)
+    /// Prints the formatted string to the notification area of the screen ( down the right hand edge
+    /// numbered lines starting at position 0
+    fn con_nprintf_variadic(&mut self);
+    fn con_nxprintf_variadic(&mut self);
+
+    /// Change a specified player's "view entity" (i.e., use the view entity position/orientation for rendering the client view)
+    fn set_view(&mut self, client: *const Edict, view_entity: *const Edict);
+
+    /// Set the player's crosshair angle
+    fn crosshair_angle(&mut self, client: *const Edict, pitch: f32, yaw: f32);
+
+    /// Get the current game directory (hl2, tf2, hl1, cstrike, etc.)
+    fn get_game_dir(&mut self, buffer: *mut c_char, max_length: c_int);
+
+    /// Used by AI node graph code to determine if .bsp and .ain files are out of date
+    fn compare_file_time(
+        &mut self,
+        filename1: &CStr,
+        filename2: &CStr,
+        compare: *mut c_int,
+    ) -> c_int;
+
+    /// Locks/unlocks the network string tables (.e.g, when adding bots to server, this needs to happen).
+    /// Be sure to reset the lock after executing your code!!!
+    fn lock_network_string_tables(&mut self, lock: bool) -> bool;
+
+    /// Create a bot with the given name.  Returns NULL if fake client can't be created
+    fn create_fake_client(&mut self, name: &CStr) -> *mut Edict;
+
+    /// Get a convar keyvalue for s specified client
+    fn get_client_con_var_value(&mut self, client_index: c_int, name: &CStr) -> *const c_char;
+
+    /// Parse a token from a file
+    fn parse_file(
+        &mut self,
+        data: *const c_char,
+        token: *mut c_char,
+        max_len: c_int,
+    ) -> *const c_char;
+    /// Copies a file
+    fn copy_file(&mut self, source: &CStr, destination: &CStr) -> bool;
+
+    /// Reset the pvs, pvssize is the size in bytes of the buffer pointed to by pvs.
+    /// This should be called right before any calls to AddOriginToPVS
+    fn reset_pvs(&mut self, pvs: *mut c_uchar, pvs_size: c_int);
+    /// Merge the pvs bits into the current accumulated pvs based on the specified origin ( not that each pvs origin has an 8 world unit fudge factor )
+    fn add_origin_to_pvs(&mut self, origin: *const Vector);
+
+    /// Mark a specified area portal as open/closed.
+    fn set_area_portal_state(&mut self, portal_number: c_int, is_open: c_int);
+
+    /// Queue a temp entity for transmission
+    fn playback_temp_entity(
+        &mut self,
+        filter: *mut c_void,
+        delay: f32,
+        sender: *const c_void,
+        send_table: *const c_void,
+        class_id: c_int,
+    );
+
+    /// Given a node number and the specified PVS, return with the node is in the PVS
+    fn check_headnode_visible(
+        &mut self,
+        node: c_int,
+        pvs: *const c_uchar,
+        vis_size: c_int,
+    ) -> c_int;
+    /// Using area bits, cheeck whether area1 flows into area2 and vice versa (depends on area portal state)
+    fn check_areas_connected(&mut self, area1: c_int, area2: c_int) -> c_int;
+    /// Given an origin, determine which area index the origin is within
+    fn get_area(&mut self, origin: *const Vector) -> c_int;
+    /// Get area portal bit set
+    fn get_area_bits(&mut self, area: c_int, bits: *mut c_uchar, buffer_length: c_int);
+    /// Given a view origin (which tells us the area to start looking in) and a portal key,
+    /// fill in the plane that leads out of this area (it points into whatever area it leads to).
+    fn get_area_portal_plane(
+        &mut self,
+        view_origin: *const Vector,
+        portal_key: c_int,
+        plane: *mut c_void,
+    ) -> bool;
+
+    /// Save/restore wrapper - FIXME:  At some point we should move this to it's own interface
+    fn load_game_state(&mut self, map_name: &CStr, create_players: bool) -> bool;
+    fn load_adjacent_ents(&mut self, old_level: &CStr, landmark_name: &CStr);
+    fn clear_save_dir(&mut self);
+
+    /// Get the pristine map entity lump string.  (e.g., used by CS to reload the map entities when restarting a round.)
+    fn get_map_entities_string(&mut self) -> *const c_char;
+
+    /// Text message system -- lookup the text message of the specified name
+    fn text_message_get(&mut self, name: &CStr) -> *mut c_void;
+
+    /// Print a message to the server log file
+    fn log_print(&mut self, message: &CStr);
+    fn is_log_enabled(&mut self) -> bool;
+
+    /// Builds PVS information for an entity
+    fn build_entity_cluster_list(&mut self, edict: *mut Edict, pvs_info: *mut c_void);
+
+    /// A solid entity moved, update spatial partition
+    fn solid_moved(
+        &mut self,
+        solid: *mut Edict,
+        collideable: *mut c_void,
+        previous_origin: *const Vector,
+        test_surrounding_bounds_only: bool,
+    );
+    /// A trigger entity moved, update spatial partition
+    fn trigger_moved(&mut self, trigger: *mut Edict, test_surrounding_bounds_only: bool);
+
+    /// Create/destroy a custom spatial partition
+    fn create_spatial_partition(&mut self, min: *const Vector, max: *const Vector) -> *mut c_void;
+    fn destroy_spatial_partition(&mut self, partition: *mut c_void);
+
+    /// Draw the brush geometry in the map into the scratch pad.
+    /// Flags is currently unused.
+    fn draw_map_to_scratch_pad(&mut self, pad: *mut c_void, flags: u32);
+
+    /// This returns which entities, to the best of the server's knowledge, the client currently knows about.
+    /// This is really which entities were in the snapshot that this client last acked.
+    /// This returns a bit vector with one bit for each entity.
+    fn get_entity_transmit_bits_for_client(&mut self, client_index: c_int) -> *const c_void;
+
+    /// Is the game paused?
+    fn is_paused(&mut self) -> bool;
+
+    /// What is the game timescale multiplied with the host_timescale?
+    fn get_timescale(&self) -> f32;
+
+    /// Marks the filename for consistency checking.  This should be called after precaching the file.
+    fn force_exact_file(&mut self, name: &CStr);
+    fn force_model_bounds(&mut self, name: &CStr, mins: *const Vector, maxs: *const Vector);
+    fn clear_save_dir_after_client_load(&mut self);
+
+    /// Sets a USERINFO client ConVar for a fakeclient
+    fn set_fake_client_con_var_value(&mut self, edict: *mut Edict, cvar: &CStr, value: &CStr);
+
+    /// Marks the material (vmt file) for consistency checking.  If the client and server have different
+    /// contents for the file, the client's vmt can only use the VertexLitGeneric shader, and can only
+    /// contain $baseTexture and $bumpmap vars.
+    fn force_simple_material(&mut self, name: &CStr);
+
+    /// Is the engine in Commentary mode?
+    fn is_in_commentary_mode(&mut self) -> c_int;
+
+    /// Is this level loaded as just the background to the main menu? (active, but unplayable)
+    fn is_level_main_menu_background(&mut self) -> bool;
+
+    /// Mark some area portals as open/closed. It's more efficient to use this
+    /// than a bunch of individual SetAreaPortalState calls.
+    fn set_area_portal_states(
+        &mut self,
+        portal_numbers: *const c_int,
+        is_open: *const c_int,
+        portals: c_int,
+    );
+
+    /// Called when relevant edict state flags change.
+    fn notify_edict_flags_change(&mut self, edict: c_int);
+
+    /// Only valid during CheckTransmit. Also, only the PVS, networked areas, and
+    /// m_pTransmitInfo are valid in the returned strucutre.
+    fn get_prev_check_transmit_info(&mut self, player: *mut Edict) -> *const c_void;
+
+    fn get_shared_edict_change_info(&mut self) -> *mut c_void;
+
+    /// Tells the engine we can immdiately re-use all edict indices
+    /// even though we may not have waited enough time
+    fn allow_immediate_edict_reuse(&mut self);
+
+    /// Returns true if the engine is an internal build. i.e. is using the internal bugreporter.
+    fn is_internal_build(&mut self) -> bool;
+
+    fn get_change_accessor(&mut self, edict: *const Edict) -> *mut c_void;
+
+    /// Name of most recently load .sav file
+    fn get_most_recently_loaded_file_name(&mut self) -> *const c_char;
+    fn get_save_file_name(&mut self) -> *const c_char;
+
+    /// Cleans up the cluster list
+    fn clean_up_entity_cluster_list(&mut self, pvs_info: *mut c_void);
+
+    fn set_achievement_mgr(&mut self, manager: *mut c_void);
+    fn get_achievement_mgr(&mut self) -> *mut c_void;
+
+    fn get_app_id(&mut self) -> c_int;
+
+    fn is_low_violence(&mut self) -> bool;
+    fn is_any_client_low_violence(&mut self) -> bool;
+
+    /// Call this to find out the value of a cvar on the client.
+    ///
+    /// It is an asynchronous query, and it will call IServerGameDLL::OnQueryCvarValueFinished when
+    /// the value comes in from the client.
+    ///
+    /// Store the return value if you want to match this specific query to the OnQueryCvarValueFinished call.
+    /// Returns InvalidQueryCvarCookie if the entity is invalid.
+    fn start_query_cvar_value(&mut self, player: *mut Edict, name: &CStr) -> c_int;
+
+    fn insert_server_command(&mut self, command: &CStr);
+
+    /// Fill in the player info structure for the specified player index (name, model, etc.)
+    fn get_player_info(&mut self, entity_number: c_int, info: *mut c_void) -> bool;
+
+    /// Returns true if this client has been fully authenticated by Steam
+    fn is_client_fully_authenticated(&mut self, edict: *mut Edict) -> bool;
+
+    /// This makes the host run 1 tick per frame instead of checking the system timer to see how many ticks to run in a certain frame.
+    /// i.e. it does the same thing timedemo does.
+    fn set_dedicated_server_benchmark_mode(&mut self, benchmark_mode: bool);
+
+    fn is_split_screen_player(&mut self, entity_number: c_int) -> bool;
+    fn get_split_screen_player_attach_to_edict(&mut self, entity_number: c_int) -> *mut Edict;
+    fn get_num_split_screen_users_attached_to_edict(&mut self, entity_number: c_int) -> c_int;
+    fn get_split_screen_player_for_edict(
+        &mut self,
+        entity_number: c_int,
+        slot: c_int,
+    ) -> *mut Edict;
+
+    fn is_override_load_game_ents_on(&mut self) -> bool;
+
+    fn force_flush_entity(&mut self, entity: c_int);
+
+    fn get_single_player_shared_memory_space(
+        &mut self,
+        name: &CStr,
+        entity_number: c_int,
+    ) -> *mut c_void;
+
+    fn alloc_level_static_data(&mut self, bytes: usize) -> *mut c_void;
+
+    fn get_cluster_count(&mut self) -> c_int;
+    fn get_all_cluster_bounds(&mut self, bbox_list: *mut c_void, max_bbox: c_int) -> c_int;
+
+    fn is_creating_reslist(&mut self) -> bool;
+    fn is_creating_xbox_reslist(&mut self) -> bool;
+    fn is_dedicated_server_for_xbox(&mut self) -> bool;
+
+    fn pause(&mut self, pause: bool, force: bool);
+
+    fn set_timescale(&mut self, timescale: f32);
+
+    /// Methods to set/get a gamestats data container so client & server running in same process can send combined data
+    fn set_gamestats_data(&mut self, data: *mut c_void);
+    fn get_gamestats_data(&mut self) -> *mut c_void;
+
+    /// Returns the SteamID of the specified player. It'll be NULL if the player hasn't authenticated yet.
+    fn get_client_steam_id(&mut self, player: *mut Edict) -> *const u64;
+}
+
+static mut ENGINE: *mut c_void = null_mut();
+
+/// Acquire the engine interface from the engine factory, this
+/// must be called from the game thread when the addon is loaded
+pub(crate) fn init(factory: CreateInterfaceFn) -> Option<Foreign<dyn VEngineServer>> {
+    let interfaces = game::interfaces();
+    let engine =
+        game::acquire::<dyn VEngineServer>(factory, interfaces.engine_server, "IVEngineServer");
+    unsafe {
+        ENGINE = engine.as_ref().map_or(null_mut(), |engine| engine.0);
+    }
+
+    engine
+}
+
+/// Get the engine interface, if it was acquired when the addon was loaded
+pub(crate) fn engine() -> Option<Foreign<dyn VEngineServer>> {
+    let engine = unsafe { ENGINE };
+    if engine.is_null() {
+        None
+    } else {
+        Some(Foreign::with(engine))
+    }
+}
+
+/// Reads the absolute path of the current game directory
+pub(crate) fn game_dir(engine: &mut dyn VEngineServer) -> String {
+    let mut buffer = [0 as c_char; 260];
+    engine.get_game_dir(buffer.as_mut_ptr(), buffer.len() as c_int);
+
+    // Ensure the buffer is always terminated even if the engine filled it entirely
+    if let Some(last) = buffer.last_mut() {
+        *last = 0;
+    }
+
+    let value = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+    value.to_string_lossy().into()
+}
+
+/// Signature of the variadic `ClientCommand`, a cdecl method taking the
+/// object as its first argument
+type ClientCommandFn = unsafe extern "C" fn(*mut c_void, *mut Edict, *const c_char, ...);
+
+/// Execute `command` in the console of the client of `edict`, returns
+/// false if the engine interface wasn't acquired
+pub(crate) fn client_command(edict: *mut Edict, command: &CStr) -> bool {
+    let engine = match engine() {
+        Some(engine) => engine,
+        None => return false,
+    };
+
+    unsafe {
+        let vtable = &*(*(engine.0 as *const CVEngineServer<()>)).vtable;
+        let method: ClientCommandFn = mem::transmute(vtable.client_command_variadic);
+
+        // The command is passed as an argument so it isn't read as a format string
+        method(engine.0, edict, cstr!("%s").as_ptr(), command.as_ptr());
+    }
+
+    true
+}
@@ -0,0 +1,251 @@
+use std::{
+    ffi::c_void,
+    os::raw::{c_char, c_int, c_ushort},
+    ptr::null_mut,
+};
+
+use log::debug;
+
+use crate::{
+    addon::Edict,
+    deferred::{self, Capability},
+    engine::{engine, VEngineServer},
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+    host::globals::globals,
+    netprops::ServerClass,
+};
+
+/// Binding for IServerGameEnts, exposed by the server DLL to convert between
+/// the edicts used by the engine and the entity objects of the game
+///
+/// The methods are declared in the same order as the Alien Swarm SDK's `eiface.h`
+#[fabric_codegen::interface]
+pub(crate) trait ServerGameEnts {
+    fn destructor(&mut self);
+
+    /// The engine wants to mark two entities as touching
+    fn mark_entities_as_touching(&mut self, first: *mut Edict, second: *mut Edict);
+    /// Frees the entity attached to this edict
+    fn free_containing_entity(&mut self, edict: *mut Edict);
+
+    /// This allows the engine to get at edicts in a CGameTrace.
+    fn base_entity_to_edict(&mut self, entity: *mut c_void) -> *mut Edict;
+    /// This allows the engine to get at CBaseEntity objects from an edict.
+    fn edict_to_base_entity(&mut self, edict: *mut Edict) -> *mut c_void;
+
+    /// This sets a bit in pInfo for each edict in the list that wants to be transmitted to the
+    /// client specified in pInfo.
+    fn check_transmit(&mut self, info: *mut c_void, edict_indices: *const c_ushort, edicts: c_int);
+
+    /// TERROR: Perform any PVS cleanup before a full update
+    fn prepare_for_full_update(&mut self, edict: *mut Edict);
+}
+
+/// Binding for IServerUnknown, the base interface of all the server entities
+///
+/// The methods are declared in the same order as the Alien Swarm SDK's
+/// `iserverunknown.h`, after the methods inherited from IHandleEntity
+#[fabric_codegen::interface]
+pub(crate) trait ServerUnknown {
+    fn destructor(&mut self);
+    fn set_ref_ehandle(&mut self, handle: *const c_void);
+    fn get_ref_ehandle(&self) -> *const c_void;
+
+    fn get_collideable(&mut self) -> *mut c_void;
+    fn get_networkable(&mut self) -> *mut c_void;
+    fn get_base_entity(&mut self) -> *mut c_void;
+}
+
+/// Binding for IServerNetworkable, declared in the same
+/// order as the Alien Swarm SDK's `iservernetworkable.h`
+#[fabric_codegen::interface]
+pub(crate) trait ServerNetworkable {
+    /// Gets at the entity handle associated with the collideable
+    fn get_entity_handle(&mut self) -> *mut c_void;
+    /// Tell the engine which class this object is.
+    fn get_server_class(&mut self) -> *mut ServerClass;
+    fn get_edict(&self) -> *mut Edict;
+    fn get_class_name(&self) -> *const c_char;
+    fn release(&mut self);
+    fn area_num(&self) -> c_int;
+}
+
+/// Get the networkable object of the entity at `index`
+fn networkable(index: c_int) -> Option<Foreign<dyn ServerNetworkable>> {
+    let unknown = unsafe { (*edict(index)?).unknown() };
+    if unknown.is_null() {
+        return None;
+    }
+
+    let networkable = Foreign::<dyn ServerUnknown>::with(unknown).get_networkable();
+    if networkable.is_null() {
+        None
+    } else {
+        Some(Foreign::with(networkable))
+    }
+}
+
+/// Get the class name of the entity at `index`
+pub(crate) fn class_name(index: c_int) -> Option<*const c_char> {
+    let name = networkable(index)?.get_class_name();
+    if name.is_null() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Get the ServerClass of the entity at `index`
+pub(crate) fn server_class(index: c_int) -> Option<*mut ServerClass> {
+    let class = networkable(index)?.get_server_class();
+    if class.is_null() {
+        None
+    } else {
+        Some(class)
+    }
+}
+
+/// Get the entity index of a server entity, `entity` is
+/// either null or points to an IServerUnknown object
+pub(crate) fn entity_index(entity: *mut c_void) -> Option<c_int> {
+    if entity.is_null() {
+        return None;
+    }
+
+    let networkable = Foreign::<dyn ServerUnknown>::with(entity).get_networkable();
+    if networkable.is_null() {
+        return None;
+    }
+
+    let edict = Foreign::<dyn ServerNetworkable>::with(networkable).get_edict();
+    unsafe { edict.as_ref() }.map(Edict::index)
+}
+
+static mut GAME_ENTS: *mut c_void = null_mut();
+
+/// Edict list of the current level, received in `ServerActivate`
+static mut EDICTS: (*mut Edict, c_int) = (null_mut(), 0);
+
+/// Acquire the entity interface from the server factory, this
+/// must be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interface = game::acquire::<dyn ServerGameEnts>(
+        server,
+        game::interfaces().server_game_ents,
+        "IServerGameEnts",
+    );
+    unsafe {
+        GAME_ENTS = interface.map_or(null_mut(), |game_ents| game_ents.0);
+    }
+}
+
+/// Get the entity interface, looked up again at most once per level while it is missing
+pub(crate) fn game_ents() -> Option<Foreign<dyn ServerGameEnts>> {
+    let mut game_ents = unsafe { GAME_ENTS };
+    if game_ents.is_null() && deferred::retry(Capability::Entities) {
+        game_ents = unsafe { GAME_ENTS };
+    }
+
+    if game_ents.is_null() {
+        None
+    } else {
+        Some(Foreign::with(game_ents))
+    }
+}
+
+/// Store the edict list of the level being activated
+pub(crate) fn server_activate(edicts: *mut Edict, count: c_int) {
+    debug!("server_activate {:?} {}", edicts, count);
+    unsafe {
+        EDICTS = (edicts, count);
+    }
+}
+
+/// Forget the edict list when the level is unloaded
+pub(crate) fn level_shutdown() {
+    unsafe {
+        EDICTS = (null_mut(), 0);
+    }
+}
+
+/// Number of edict slots in the current level, or 0 if no level is loaded
+pub(crate) fn max_edicts() -> c_int {
+    unsafe { EDICTS.1 }
+}
+
+/// Get the edict at `index`, if it's currently in use by an entity
+pub(crate) fn edict(index: c_int) -> Option<*mut Edict> {
+    let edict = edict_slot(index)?;
+    if unsafe { (*edict).is_free() } {
+        None
+    } else {
+        Some(edict)
+    }
+}
+
+/// Entity indices of the clients connected to the server
+pub(crate) fn connected_clients() -> Vec<c_int> {
+    let max_clients = globals().map_or(0, |globals| globals.max_clients());
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return Vec::new(),
+    };
+
+    (1..=max_clients)
+        .filter(|index| match edict(*index) {
+            Some(edict) => engine.get_player_user_id(edict) != -1,
+            None => false,
+        })
+        .collect()
+}
+
+/// Get the edict slot at `index`, whether it's in use or not
+pub(crate) fn edict_slot(index: c_int) -> Option<*mut Edict> {
+    let (edicts, count) = unsafe { EDICTS };
+    if edicts.is_null() || index < 0 || index >= count {
+        None
+    } else {
+        Some(unsafe { edicts.add(index as usize) })
+    }
+}
+
+/// Get the entity index of a CBaseEntity object
+pub(crate) fn base_entity_index(entity: *mut c_void) -> Option<c_int> {
+    if entity.is_null() {
+        return None;
+    }
+
+    let edict = game_ents()?.base_entity_to_edict(entity);
+    unsafe { edict.as_ref() }.map(Edict::index)
+}
+
+/// Get the CBaseEntity object of the entity at `index`
+pub(crate) fn base_entity(index: c_int) -> Option<*mut c_void> {
+    let entity = game_ents()?.edict_to_base_entity(edict(index)?);
+    if entity.is_null() {
+        None
+    } else {
+        Some(entity)
+    }
+}
+
+/// Build a handle identifying the entity currently at `index`
+///
+/// The handle packs the serial number of the edict in its high bits, so it
+/// stops resolving once the entity is removed and the slot is reused
+pub(crate) fn entity_handle(index: c_int) -> Option<c_int> {
+    let edict = unsafe { &*edict(index)? };
+    Some(((edict.serial_number() & 0xFFFF) << 16) | (index & 0xFFFF))
+}
+
+/// Resolve an entity handle back to an entity index
+pub(crate) fn handle_index(handle: c_int) -> Option<c_int> {
+    let index = handle & 0xFFFF;
+    let edict = unsafe { &*edict(index)? };
+    if edict.serial_number() & 0xFFFF == (handle >> 16) & 0xFFFF {
+        Some(index)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use fabric_runtime::VMContext;
+use log::{debug, warn};
+
+use crate::module::{call_guest, FabricEnv, Module};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Continuation of a background task, executed on the game thread
+/// with exclusive access to the module that spawned the task
+pub(crate) type Completion = Box<dyn FnOnce(&mut VMContext<FabricEnv>) + Send>;
+
+/// Thread pool running blocking work (file I/O, network, database)
+/// on behalf of the host modules
+struct Executor {
+    sender: Sender<Job>,
+    workers: Vec<JoinHandle<()>>,
+    /// Set when the executor is stopped, the queued jobs are then discarded
+    stopped: Arc<AtomicBool>,
+}
+
+static mut EXECUTOR: Option<Executor> = None;
+
+/// Start the worker threads, this must be called from the game thread
+pub(crate) fn start(threads: usize) {
+    let (sender, receiver) = channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    let workers = (0..threads.max(1))
+        .filter_map(|index| {
+            let receiver = receiver.clone();
+            let stopped = stopped.clone();
+            let worker = thread::Builder::new()
+                .name(format!("fabric-worker-{}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job,
+                    // the channel is closed when the executor is stopped
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+
+                    // The jobs still queued when the executor is stopped are dropped
+                    if !stopped.load(Ordering::SeqCst) {
+                        job();
+                    }
+                });
+
+            match worker {
+                Ok(worker) => Some(worker),
+                Err(err) => {
+                    warn!("could not spawn worker thread: {}", err);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    unsafe {
+        EXECUTOR = Some(Executor {
+            sender,
+            workers,
+            stopped,
+        });
+    }
+}
+
+/// Stop accepting new jobs, discard the queued ones and wait for the
+/// running ones to finish, their completions are never executed anyway
+pub(crate) fn stop() {
+    let executor = unsafe { EXECUTOR.take() };
+    if let Some(Executor {
+        sender,
+        workers,
+        stopped,
+    }) = executor
+    {
+        stopped.store(true, Ordering::SeqCst);
+        drop(sender);
+
+        debug!("waiting for {} worker threads", workers.len());
+        for worker in workers {
+            if worker.join().is_err() {
+                warn!("worker thread panicked");
+            }
+        }
+    }
+}
+
+fn submit(job: Job) {
+    let executor = unsafe { EXECUTOR.as_ref() };
+    let job = match executor {
+        Some(executor) => match executor.sender.send(job) {
+            Ok(()) => return,
+            Err(err) => err.0,
+        },
+        None => job,
+    };
+
+    // Fallback on running the job synchronously if the pool
+    // isn't running, the completion is still deferred
+    warn!("executor is not running, running job on the game thread");
+    job();
+}
+
+/// Run `job` on the thread pool on behalf of the addon itself,
+/// for the blocking work that doesn't complete into a module
+pub(crate) fn spawn<F>(job: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    submit(Box::new(job));
+}
+
+/// Per-module queue of background tasks
+///
+/// Tasks are executed on the worker threads, and their completion is queued
+/// until the next server frame where it gets executed on the game thread,
+/// so guest code is never invoked from a worker thread
+pub(crate) struct Tasks {
+    sender: Sender<Completion>,
+    receiver: Receiver<Completion>,
+}
+
+impl Tasks {
+    pub(crate) fn new() -> Self {
+        let (sender, receiver) = channel();
+        Tasks { sender, receiver }
+    }
+
+    /// Run `work` on the thread pool, the returned Completion
+    /// will be executed on the game thread on the next frame
+    pub(crate) fn spawn<F>(&mut self, work: F)
+    where
+        F: FnOnce() -> Completion + Send + 'static,
+    {
+        let sender = self.sender.clone();
+
+        submit(Box::new(move || {
+            let completion = work();
+            // The module may have been unloaded while the task was running
+            sender.send(completion).ok();
+        }));
+    }
+}
+
+/// Execute the completions of all the finished tasks for `module`
+pub(crate) fn run_completions(module: &Module) {
+    let mut lock = module.lock().unwrap();
+
+    let completions: Vec<_> = lock.environment.tasks.receiver.try_iter().collect();
+    for completion in completions {
+        call_guest(&mut lock, "completion", completion);
+    }
+}
@@ -0,0 +1,124 @@
+//! Rate limiting of the commands issued by the clients
+//!
+//! Each client has a token bucket for each command it issues, refilled at the
+//! `rate` of the command up to its `burst`. The commands issued past their
+//! limit are blocked before reaching the modules and the game, so a client
+//! spamming commands can't make the slow handlers of the modules stall the
+//! server. The limits of the configuration can be overridden by the modules
+
+use std::{collections::HashMap, os::raw::c_int, time::Instant};
+
+use log::debug;
+
+use crate::config;
+
+/// Name of the limit applying to the commands without a limit of their own
+pub(crate) const DEFAULT_LIMIT: &str = "*";
+
+/// Limit of a command, `rate` commands per second with bursts of `burst`
+/// commands. A rate of 0 lets the clients issue the command without limits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Limit {
+    pub(crate) rate: f32,
+    pub(crate) burst: u32,
+}
+
+struct Bucket {
+    tokens: f32,
+    updated: Instant,
+}
+
+/// Buckets of the clients keyed by client and command,
+/// only accessed from the game thread like the other statics
+static mut BUCKETS: Option<HashMap<(c_int, String), Bucket>> = None;
+
+/// Limits set by the modules, keyed by command or `DEFAULT_LIMIT`
+static mut OVERRIDES: Option<HashMap<String, Limit>> = None;
+
+/// Limit of `command`, from the modules, the configuration, then the default
+/// limit set by the modules and finally the default limit of the configuration
+pub(crate) fn limit(command: &str) -> Limit {
+    let overrides = unsafe { OVERRIDES.get_or_insert_with(HashMap::new) };
+    let config = &config::get().flood;
+
+    let configured = config.commands.get(command).map(|limit| Limit {
+        rate: limit.rate,
+        burst: limit.burst,
+    });
+
+    overrides
+        .get(command)
+        .copied()
+        .or(configured)
+        .or_else(|| overrides.get(DEFAULT_LIMIT).copied())
+        .unwrap_or(Limit {
+            rate: config.rate,
+            burst: config.burst,
+        })
+}
+
+/// Override the limit of `command` or `DEFAULT_LIMIT`, None restores the
+/// limit of the configuration
+pub(crate) fn set_limit(command: &str, limit: Option<Limit>) {
+    let overrides = unsafe { OVERRIDES.get_or_insert_with(HashMap::new) };
+    match limit {
+        Some(limit) => overrides.insert(command.to_ascii_lowercase(), limit),
+        None => overrides.remove(&command.to_ascii_lowercase()),
+    };
+}
+
+/// Refill the bucket of `client` for `command` and return it, with the
+/// limit of the command. Returns None if the command has no limit
+fn bucket(client: c_int, command: &str, now: Instant) -> Option<(&'static mut Bucket, Limit)> {
+    let limit = limit(command);
+    if limit.rate <= 0.0 {
+        return None;
+    }
+
+    let burst = limit.burst.max(1) as f32;
+    let buckets = unsafe { BUCKETS.get_or_insert_with(HashMap::new) };
+    let bucket = buckets.entry((client, command.into())).or_insert(Bucket {
+        tokens: burst,
+        updated: now,
+    });
+
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f32();
+    bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(burst);
+    bucket.updated = now;
+
+    Some((bucket, limit))
+}
+
+/// Take a token from the bucket of `client` for `command`,
+/// returns false if the client exceeded the limit of the command
+pub(crate) fn check(client: c_int, command: &str, now: Instant) -> bool {
+    let command = command.to_ascii_lowercase();
+    let (bucket, limit) = match bucket(client, &command, now) {
+        Some(bucket) => bucket,
+        None => return true,
+    };
+
+    if bucket.tokens < 1.0 {
+        debug!(
+            "client {} exceeded the limit of {} ({}/s)",
+            client, command, limit.rate
+        );
+        return false;
+    }
+
+    bucket.tokens -= 1.0;
+    true
+}
+
+/// Whether `client` is currently blocked from issuing `command`
+pub(crate) fn is_limited(client: c_int, command: &str, now: Instant) -> bool {
+    let command = command.to_ascii_lowercase();
+    bucket(client, &command, now).is_some_and(|(bucket, _)| bucket.tokens < 1.0)
+}
+
+/// Forget the buckets of a disconnecting client
+pub(crate) fn client_disconnect(client: c_int) {
+    if let Some(buckets) = unsafe { BUCKETS.as_mut() } {
+        buckets.retain(|(owner, _), _| *owner != client);
+    }
+}
@@ -1,26 +1,48 @@
 use std::{
     ffi::{c_void, CStr},
     marker::PhantomData,
+    mem::size_of,
     os::raw::{c_char, c_int},
 };
 
-use log::debug;
+use fabric_runtime::record_host_panic;
+use log::{debug, error};
+use region::Protection;
+
+use crate::{logging, thread::assert_game_thread};
 
 #[repr(transparent)]
 pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut c_void, PhantomData<*mut T>);
 
 impl<T: ?Sized> Foreign<T> {
+    /// Wrap an engine object, engine objects are only used from the game thread
+    #[track_caller]
     pub(crate) fn with(ptr: *mut c_void) -> Self {
+        assert_game_thread("engine interface");
         Foreign(ptr, PhantomData)
     }
 }
 
+/// Called by the vtable shims generated by `#[interface]` when the addon's
+/// implementation of `method` panicked. If a module is running the panic is
+/// recorded like the one of a host function, and the module gets disabled
+/// when its callback returns
+pub(crate) fn shim_panicked(method: &str) {
+    error!("panic in {}", method);
+    if logging::in_module() {
+        record_host_panic();
+    }
+}
+
 pub(crate) type CreateInterfaceFn = extern "C" fn(*const c_char, *mut c_int) -> *mut c_void;
 
+#[track_caller]
 pub(crate) fn create_interface<T: ?Sized>(
     factory: CreateInterfaceFn,
     name: &CStr,
 ) -> Option<Foreign<T>> {
+    assert_game_thread("create_interface");
+
     let mut is_ok = 0;
     let pointer = factory(name.as_ptr(), &mut is_ok);
 
@@ -31,3 +53,73 @@ pub(crate) fn create_interface<T: ?Sized>(
         None
     }
 }
+
+/// Whether the `len` bytes at `pointer` are mapped with the `protection` flags
+fn is_mapped(pointer: *const u8, len: usize, protection: Protection) -> bool {
+    match region::query_range(pointer, len) {
+        Ok(regions) => regions
+            .iter()
+            .all(|region| region.protection.contains(protection)),
+        Err(_) => false,
+    }
+}
+
+/// Check that `pointer` looks like a live object whose vtable holds `slots`
+/// functions: the object and its vtable are readable, and every slot points
+/// to executable memory. Returns a description of the first problem found
+///
+/// This queries the memory maps of the process, it is only meant to run
+/// when the interfaces are acquired
+pub(crate) fn check_object(pointer: *mut c_void, slots: usize) -> Result<(), String> {
+    if pointer.is_null() {
+        return Err(String::from("null pointer"));
+    }
+
+    let size = size_of::<*const c_void>();
+    if !is_mapped(pointer as *const u8, size, Protection::READ) {
+        return Err(format!("object at {:?} is not readable", pointer));
+    }
+
+    let vtable = unsafe { *(pointer as *const *const *const u8) };
+    if vtable.is_null() {
+        return Err(String::from("null vtable"));
+    }
+
+    if slots == 0 {
+        return Ok(());
+    }
+
+    if !is_mapped(vtable as *const u8, slots * size, Protection::READ) {
+        return Err(format!("vtable at {:?} is not readable", vtable));
+    }
+
+    // The functions of a vtable are usually in the same few regions,
+    // the last executable one is kept to avoid querying each slot
+    let mut code = None;
+    for index in 0..slots {
+        let slot = unsafe { *vtable.add(index) };
+        if slot.is_null() {
+            return Err(format!("slot {} of the vtable is null", index));
+        }
+
+        let address = slot as usize;
+        let known = code.is_some_and(|(lower, upper)| address >= lower && address < upper);
+        if known {
+            continue;
+        }
+
+        match region::query(slot) {
+            Ok(region) if region.protection.contains(Protection::EXECUTE) => {
+                code = Some((region.lower(), region.upper()));
+            }
+            _ => {
+                return Err(format!(
+                    "slot {} of the vtable points to {:?}, which isn't code",
+                    index, slot
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,451 @@
+//! Detection of the engine branch the addon is loaded in
+//!
+//! The bindings of the addon follow the Alien Swarm SDK, other branches expose
+//! different versions of the interfaces and lay out the shared structures
+//! differently. The branch is detected when the addon is loaded and selects an
+//! entry of the data table below, the interfaces the addon has no binding for on
+//! a branch are left out instead of being called through a mismatched vtable
+
+use std::{
+    ffi::{c_void, CStr},
+    fmt::{self, Display, Formatter},
+    fs,
+    mem::size_of,
+    path::Path,
+};
+
+use fabric_codegen::cstr;
+use log::{info, warn};
+
+use crate::{
+    bot::{BotManager, IBotManager},
+    command::{Cvar, ICvar},
+    config,
+    effects::{Effects, IEffects},
+    engine::{IVEngineServer, VEngineServer},
+    entity::{IServerGameEnts, ServerGameEnts},
+    foreign::{check_object, create_interface, CreateInterfaceFn, Foreign},
+    server::{IPlayerInfoManager, IServerGameDLL, PlayerInfoManager, ServerGameDLL},
+    sound::{EngineSound, IEngineSound},
+    tools::{IServerTools, ServerTools},
+    trace::{EngineTrace, IEngineTrace},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Branch {
+    AlienSwarm,
+    OrangeBox,
+}
+
+impl Display for Branch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Branch::AlienSwarm => write!(f, "Alien Swarm"),
+            Branch::OrangeBox => write!(f, "Orange Box"),
+        }
+    }
+}
+
+/// Branches in detection order, with the version of IVEngineServer identifying them
+const BRANCHES: &[(Branch, &str)] = &[
+    (Branch::AlienSwarm, "VEngineServer022\0"),
+    (Branch::OrangeBox, "VEngineServer021\0"),
+];
+
+/// Versions of the interfaces used by the addon, `None` if the
+/// addon has no binding for their vtable layout on the branch
+pub(crate) struct Interfaces {
+    pub(crate) engine_server: Option<&'static CStr>,
+    pub(crate) engine_cvar: Option<&'static CStr>,
+    pub(crate) engine_trace: Option<&'static CStr>,
+    pub(crate) engine_sound: Option<&'static CStr>,
+    pub(crate) game_events: Option<&'static CStr>,
+    pub(crate) server_game_dll: Option<&'static CStr>,
+    pub(crate) server_game_ents: Option<&'static CStr>,
+    pub(crate) server_tools: Option<&'static CStr>,
+    pub(crate) bot_manager: Option<&'static CStr>,
+    pub(crate) effects: Option<&'static CStr>,
+    pub(crate) player_info_manager: Option<&'static CStr>,
+}
+
+impl Interfaces {
+    /// Name of each interface with the version selected on the branch
+    pub(crate) fn entries(&self) -> [(&'static str, Option<&'static CStr>); 11] {
+        [
+            ("IVEngineServer", self.engine_server),
+            ("ICvar", self.engine_cvar),
+            ("IEngineTrace", self.engine_trace),
+            ("IEngineSound", self.engine_sound),
+            ("IGameEventManager2", self.game_events),
+            ("IServerGameDLL", self.server_game_dll),
+            ("IServerGameEnts", self.server_game_ents),
+            ("IServerTools", self.server_tools),
+            ("IBotManager", self.bot_manager),
+            ("IEffects", self.effects),
+            ("IPlayerInfoManager", self.player_info_manager),
+        ]
+    }
+}
+
+/// Interface versions exposed by the factories of the known branches,
+/// probed by `fabric_dump_interfaces` along with the ones of the table
+pub(crate) const KNOWN_INTERFACES: &[&str] = &[
+    "VEngineServer021",
+    "VEngineServer022",
+    "VEngineServer023",
+    "VEngineCvar004",
+    "VEngineCvar007",
+    "EngineTraceServer003",
+    "EngineTraceServer004",
+    "IEngineSoundServer003",
+    "GAMEEVENTSMANAGER001",
+    "GAMEEVENTSMANAGER002",
+    "ISERVERPLUGINHELPERS001",
+    "VFileSystem017",
+    "VFileSystem022",
+    "VModelInfoServer002",
+    "VModelInfoServer003",
+    "VEngineServerStringTable001",
+    "SpatialPartition001",
+    "VoiceServer002",
+    "NetworkStringTableServer001",
+    "ServerGameDLL005",
+    "ServerGameDLL008",
+    "ServerGameEnts001",
+    "ServerGameClients003",
+    "ServerGameClients004",
+    "VSERVERTOOLS001",
+    "VSERVERTOOLS002",
+    "BOTMANAGER001",
+    "BOTMANAGER002",
+    "IEffects001",
+    "PlayerInfoManager002",
+];
+
+/// Interface acquired from a factory with `acquire`
+pub(crate) trait Interface {
+    /// Number of functions in the vtable of the bindings of the
+    /// interface, the vtable of the engine has at least as many
+    const SLOTS: usize;
+}
+
+macro_rules! interface_slots {
+    ($($interface:ident => $vtable:ident),* $(,)?) => {
+        $(
+            impl Interface for dyn $interface {
+                const SLOTS: usize = size_of::<$vtable>() / size_of::<usize>();
+            }
+        )*
+    };
+}
+
+interface_slots! {
+    VEngineServer => IVEngineServer,
+    Cvar => ICvar,
+    EngineTrace => IEngineTrace,
+    EngineSound => IEngineSound,
+    ServerGameDLL => IServerGameDLL,
+    ServerGameEnts => IServerGameEnts,
+    ServerTools => IServerTools,
+    BotManager => IBotManager,
+    Effects => IEffects,
+    PlayerInfoManager => IPlayerInfoManager,
+}
+
+/// Byte offsets of the fields of `edict_t`
+pub(crate) struct EdictLayout {
+    pub(crate) index: usize,
+    pub(crate) serial_number: usize,
+}
+
+/// Byte offsets of the fields of `CGlobalVarsBase`
+pub(crate) struct GlobalsLayout {
+    pub(crate) curtime: usize,
+    pub(crate) frametime: usize,
+    pub(crate) max_clients: usize,
+    pub(crate) tick_count: usize,
+    pub(crate) interval_per_tick: usize,
+}
+
+pub(crate) struct GameInfo {
+    pub(crate) branch: Branch,
+    pub(crate) interfaces: Interfaces,
+    pub(crate) edict: EdictLayout,
+    pub(crate) globals: GlobalsLayout,
+    /// Name of the game from `steam.inf`
+    pub(crate) product: Option<String>,
+    /// Version of the game from `steam.inf`
+    pub(crate) version: Option<String>,
+}
+
+fn table(branch: Branch) -> GameInfo {
+    match branch {
+        Branch::AlienSwarm => GameInfo {
+            branch,
+            interfaces: Interfaces {
+                engine_server: Some(cstr!("VEngineServer022")),
+                engine_cvar: Some(cstr!("VEngineCvar007")),
+                engine_trace: Some(cstr!("EngineTraceServer003")),
+                engine_sound: Some(cstr!("IEngineSoundServer003")),
+                game_events: Some(cstr!("GAMEEVENTSMANAGER002")),
+                server_game_dll: Some(cstr!("ServerGameDLL005")),
+                server_game_ents: Some(cstr!("ServerGameEnts001")),
+                server_tools: Some(cstr!("VSERVERTOOLS002")),
+                bot_manager: Some(cstr!("BOTMANAGER002")),
+                effects: Some(cstr!("IEffects001")),
+                player_info_manager: Some(cstr!("PlayerInfoManager002")),
+            },
+            edict: EdictLayout {
+                index: 4,
+                serial_number: 6,
+            },
+            globals: GlobalsLayout {
+                curtime: 16,
+                frametime: 20,
+                max_clients: 24,
+                tick_count: 28,
+                interval_per_tick: 32,
+            },
+            product: None,
+            version: None,
+        },
+
+        // The engine and server interfaces of the Orange Box have their own
+        // vtable layouts, only the game events and the global variables are
+        // shared with Alien Swarm
+        Branch::OrangeBox => GameInfo {
+            branch,
+            interfaces: Interfaces {
+                engine_server: None,
+                engine_cvar: None,
+                engine_trace: None,
+                engine_sound: None,
+                game_events: Some(cstr!("GAMEEVENTSMANAGER002")),
+                server_game_dll: None,
+                server_game_ents: None,
+                server_tools: None,
+                bot_manager: None,
+                effects: None,
+                player_info_manager: Some(cstr!("PlayerInfoManager002")),
+            },
+            edict: EdictLayout {
+                index: 6,
+                serial_number: 4,
+            },
+            globals: GlobalsLayout {
+                curtime: 12,
+                frametime: 16,
+                max_clients: 20,
+                tick_count: 24,
+                interval_per_tick: 28,
+            },
+            product: None,
+            version: None,
+        },
+    }
+}
+
+/// Branch guessed from the `ProductName` of `steam.inf`
+fn product_branch(product: &str) -> Option<Branch> {
+    match product {
+        "swarm" => Some(Branch::AlienSwarm),
+        "tf" | "hl2mp" | "cstrike" | "dod" | "hl2" | "episodic" => Some(Branch::OrangeBox),
+        _ => None,
+    }
+}
+
+static mut GAME: Option<GameInfo> = None;
+
+/// Detect the branch by probing the engine factory for the interfaces
+/// identifying it, this must be called when the addon is loaded before
+/// acquiring any interface
+pub(crate) fn detect(factory: CreateInterfaceFn) -> &'static GameInfo {
+    let branch = BRANCHES.iter().find_map(|(branch, engine)| {
+        let engine = CStr::from_bytes_with_nul(engine.as_bytes()).ok()?;
+        create_interface::<()>(factory, engine).map(|_| *branch)
+    });
+
+    let branch = match branch {
+        Some(branch) => branch,
+        None => {
+            warn!("could not detect the engine branch, assuming Alien Swarm");
+            Branch::AlienSwarm
+        }
+    };
+
+    info!("detected {} engine", branch);
+
+    unsafe {
+        GAME = Some(table(branch));
+    }
+
+    info()
+}
+
+/// Read the name and version of the game from the `steam.inf` of `game_dir`
+pub(crate) fn load_steam_inf(game_dir: &Path) {
+    let path = game_dir.join("steam.inf");
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => {
+            warn!("could not read {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    let game = unsafe { GAME.get_or_insert_with(|| table(Branch::AlienSwarm)) };
+
+    for line in source.lines() {
+        let mut parts = line.splitn(2, '=');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim().to_string()),
+            _ => continue,
+        };
+
+        match key {
+            "ProductName" => game.product = Some(value),
+            "PatchVersion" => game.version = Some(value),
+            _ => {}
+        }
+    }
+
+    if let Some(product) = &game.product {
+        info!(
+            "running {} {}",
+            product,
+            game.version.as_deref().unwrap_or("(unknown version)")
+        );
+
+        match product_branch(product) {
+            Some(branch) if branch != game.branch => warn!(
+                "{} usually runs on the {} engine, detected {}",
+                product, branch, game.branch
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Look up `version` in `factory` without keeping the interface,
+/// returns the pointer of the interface if the factory exposes it
+pub(crate) fn probe(factory: CreateInterfaceFn, version: &CStr) -> Option<*mut c_void> {
+    create_interface::<()>(factory, version).map(|interface| interface.0)
+}
+
+/// Get the information of the detected branch, Alien Swarm is
+/// assumed if the detection hasn't run yet
+pub(crate) fn info() -> &'static GameInfo {
+    unsafe { GAME.get_or_insert_with(|| table(Branch::AlienSwarm)) }
+}
+
+pub(crate) fn interfaces() -> &'static Interfaces {
+    &info().interfaces
+}
+
+/// Interface acquired with `acquire`, checked again on level transitions
+struct Acquired {
+    interface: &'static str,
+    version: &'static CStr,
+    factory: CreateInterfaceFn,
+    pointer: *mut c_void,
+    slots: usize,
+}
+
+/// Only accessed from the game thread
+static mut ACQUIRED: Vec<Acquired> = Vec::new();
+/// Interfaces reported missing by `acquire`, the missing interfaces are
+/// looked up again on each level and are only reported the first time
+static mut MISSING: Vec<&'static str> = Vec::new();
+
+/// Returns true the first time `interface` is reported missing
+fn report_missing(interface: &'static str) -> bool {
+    let missing = unsafe { &mut MISSING };
+    if missing.contains(&interface) {
+        return false;
+    }
+
+    missing.push(interface);
+    true
+}
+
+/// Acquire the interface with version `version` of the current branch from
+/// `factory`, `interface` is the name of the interface used in the warnings
+///
+/// With `check_interfaces` the interface is only returned if its pointer
+/// passes the health checks of `check_object`
+pub(crate) fn acquire<T: ?Sized + Interface>(
+    factory: CreateInterfaceFn,
+    version: Option<&'static CStr>,
+    interface: &'static str,
+) -> Option<Foreign<T>> {
+    let version = match version {
+        Some(version) => version,
+        None => {
+            if report_missing(interface) {
+                warn!("{} is not supported on {}", interface, info().branch);
+            }
+            return None;
+        }
+    };
+
+    let result: Option<Foreign<T>> = create_interface(factory, version);
+    let pointer = match &result {
+        Some(result) => result.0,
+        None => {
+            if report_missing(interface) {
+                warn!("{} not found", version.to_string_lossy());
+            }
+            return None;
+        }
+    };
+
+    let acquired = unsafe { &mut ACQUIRED };
+    acquired.retain(|acquired| acquired.version != version);
+    acquired.push(Acquired {
+        interface,
+        version,
+        factory,
+        pointer,
+        slots: T::SLOTS,
+    });
+
+    if config::get().runtime.check_interfaces {
+        if let Err(err) = check_object(pointer, T::SLOTS) {
+            warn!("{} failed its health check: {}", interface, err);
+            return None;
+        }
+    }
+
+    result
+}
+
+/// Find the interfaces acquired with `acquire` that the factories now return
+/// at a different address, or that fail their health checks. These must be
+/// acquired again, the engine recreated them since they were acquired
+pub(crate) fn stale_interfaces() -> Vec<&'static str> {
+    let acquired = unsafe { &ACQUIRED };
+    acquired
+        .iter()
+        .filter(|acquired| match probe(acquired.factory, acquired.version) {
+            Some(pointer) if pointer != acquired.pointer => {
+                info!(
+                    "{} moved from {:?} to {:?}",
+                    acquired.interface, acquired.pointer, pointer
+                );
+                true
+            }
+            Some(pointer) => match check_object(pointer, acquired.slots) {
+                Ok(()) => false,
+                Err(err) => {
+                    warn!("{} failed its health check: {}", acquired.interface, err);
+                    true
+                }
+            },
+            None => {
+                warn!("{} is no longer exposed", acquired.interface);
+                true
+            }
+        })
+        .map(|acquired| acquired.interface)
+        .collect()
+}
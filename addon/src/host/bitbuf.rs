@@ -0,0 +1,305 @@
+use std::ffi::CStr;
+
+use fabric_runtime::{with_abi, ExternRef, Function, VMContext};
+use log::warn;
+
+use crate::{
+    bitbuf::BitBuffer,
+    module::{get_extern, get_extern_mut, names, store_truncated, take_extern, FabricEnv},
+};
+
+/// Maximum capacity of a buffer created by a module, this is the
+/// size of the largest network message supported by the engine
+const MAX_BUFFER_SIZE: i32 = 0x40000;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::bit_buffer::CREATE => Some(Function::new(
+            create as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> ExternRef),
+        )),
+        names::bit_buffer::FROM_BYTES => Some(Function::new(
+            from_bytes as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> ExternRef),
+        )),
+        names::bit_buffer::FREE => Some(Function::new(
+            free as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef)),
+        )),
+        names::bit_buffer::IS_OVERFLOWED => Some(Function::new(
+            is_overflowed as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::bit_buffer::BITS_WRITTEN => Some(Function::new(
+            bits_written as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::bit_buffer::BYTES => Some(Function::new(
+            bytes as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::bit_buffer::WRITE_BITS => Some(Function::new(
+            write_bits as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::bit_buffer::WRITE_FLOAT => Some(Function::new(
+            write_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, f32) -> i32),
+        )),
+        names::bit_buffer::WRITE_STRING => Some(Function::new(
+            write_string as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+        )),
+        names::bit_buffer::WRITE_BYTES => Some(Function::new(
+            write_bytes as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::bit_buffer::READ_UBITS => Some(Function::new(
+            read_ubits as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+        )),
+        names::bit_buffer::READ_SBITS => Some(Function::new(
+            read_sbits as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+        )),
+        names::bit_buffer::READ_FLOAT => Some(Function::new(
+            read_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> f32),
+        )),
+        names::bit_buffer::READ_STRING => Some(Function::new(
+            read_string as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+fn check_bits(bits: i32) -> Option<usize> {
+    if bits < 0 || bits > 32 {
+        warn!("invalid bit count {}", bits);
+        None
+    } else {
+        Some(bits as usize)
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut VMContext<FabricEnv>, size: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        if size < 0 || size > MAX_BUFFER_SIZE {
+            warn!("invalid buffer size {}", size);
+            return ExternRef::null();
+        }
+
+        ctx.create_extern(BitBuffer::new(size as usize))
+    }
+}
+
+with_abi! {
+    fn from_bytes(ctx: *mut VMContext<FabricEnv>, data: i32, len: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        if len < 0 || len > MAX_BUFFER_SIZE {
+            warn!("invalid buffer size {}", len);
+            return ExternRef::null();
+        }
+
+        let buffer = match ctx.memory.bytes(data as usize, len as usize) {
+            Ok(data) => BitBuffer::from_bytes(data),
+            Err(()) => {
+                warn!("could not load buffer data at {}+{}", data, len);
+                return ExternRef::null();
+            }
+        };
+
+        ctx.create_extern(buffer)
+    }
+}
+
+with_abi! {
+    fn free(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) {
+        let ctx = unsafe { &mut *ctx };
+        take_extern::<BitBuffer>(&mut ctx.externs, buffer, "buffer");
+    }
+}
+
+with_abi! {
+    fn is_overflowed(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern::<BitBuffer>(&ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        buffer.is_overflowed() as i32
+    }
+}
+
+with_abi! {
+    fn bits_written(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern::<BitBuffer>(&ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        buffer.bits_written() as i32
+    }
+}
+
+with_abi! {
+    fn bytes(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern::<BitBuffer>(&ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return -1,
+        };
+        let bytes = buffer.bytes();
+
+        // Truncated to the guest buffer, returning the full length
+        store_truncated(&mut ctx.memory, bytes, data, len, "buffer data")
+    }
+}
+
+with_abi! {
+    fn write_bits(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, value: i32, bits: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let bits = match check_bits(bits) {
+            Some(bits) => bits,
+            None => return 0,
+        };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let mut writer = buffer.writer();
+        writer.write_ubits(value as u32, bits);
+        !writer.is_overflowed() as i32
+    }
+}
+
+with_abi! {
+    fn write_float(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, value: f32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let mut writer = buffer.writer();
+        writer.write_float(value);
+        !writer.is_overflowed() as i32
+    }
+}
+
+with_abi! {
+    fn write_string(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, value: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match ctx.memory.load::<CStr>(value as usize) {
+            Ok(value) => value,
+            Err(()) => {
+                warn!("could not load string at {}", value);
+                return 0;
+            }
+        };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let mut writer = buffer.writer();
+        writer.write_string(value);
+        !writer.is_overflowed() as i32
+    }
+}
+
+with_abi! {
+    fn write_bytes(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, value: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match ctx.memory.bytes(value as usize, len as usize) {
+            Ok(value) if len >= 0 => value,
+            _ => {
+                warn!("could not load data at {}+{}", value, len);
+                return 0;
+            }
+        };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let mut writer = buffer.writer();
+        writer.write_bytes(value);
+        !writer.is_overflowed() as i32
+    }
+}
+
+with_abi! {
+    fn read_ubits(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, bits: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let bits = match check_bits(bits) {
+            Some(bits) => bits,
+            None => return 0,
+        };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        buffer.read_ubits(bits) as i32
+    }
+}
+
+with_abi! {
+    fn read_sbits(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, bits: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let bits = match check_bits(bits) {
+            Some(bits) => bits,
+            None => return 0,
+        };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        buffer.read_sbits(bits)
+    }
+}
+
+with_abi! {
+    fn read_float(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) -> f32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0.0,
+        };
+        buffer.read_float()
+    }
+}
+
+with_abi! {
+    fn read_string(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return -1,
+        };
+        let value = buffer.read_string();
+
+        // The string is always null-terminated in guest memory, so at
+        // most `len - 1` bytes of the value are copied to the buffer
+        if len <= 0 {
+            return value.len() as i32;
+        }
+
+        let copied = value.len().min(len as usize - 1);
+        let result = ctx
+            .memory
+            .store(data as usize, &value[..copied])
+            .and_then(|()| ctx.memory.store(data as usize + copied, &[0]));
+
+        match result {
+            Ok(()) => value.len() as i32,
+            Err(()) => {
+                warn!("could not store string at {}+{}", data, len);
+                -1
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::ffi::{CStr, CString};
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    addon::Edict,
+    bot::{bot_controller, bot_manager, BotCmd, BotController, BotManager},
+    engine::{engine, VEngineServer},
+    host::{effects::load_vector, globals::globals},
+    module::{names, FabricEnv, Module},
+};
+
+/// Bots created by a module, the module can only control its own bots
+pub(crate) struct Bots(Vec<*mut Edict>);
+
+impl Bots {
+    pub(crate) fn new() -> Self {
+        Bots(Vec::new())
+    }
+
+    /// Find the edict of a bot from its entity index
+    fn find(&self, index: i32) -> Option<*mut Edict> {
+        let edict = self
+            .0
+            .iter()
+            .copied()
+            .find(|edict| unsafe { (**edict).index() } == index);
+
+        if edict.is_none() {
+            warn!("entity {} is not a bot of this module", index);
+        }
+
+        edict
+    }
+
+    fn remove(&mut self, edict: *const Edict) {
+        self.0.retain(|bot| *bot as *const Edict != edict);
+    }
+}
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::bot::CREATE => Some(Function::new(
+            create as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::bot::REMOVE => Some(Function::new(
+            remove as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::bot::RUN_MOVE => Some(Function::new(
+            run_move
+                as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, f32, f32, f32, i32) -> i32),
+        )),
+        names::bot::SET_ORIGIN => Some(Function::new(
+            set_origin as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::bot::SET_ANGLES => Some(Function::new(
+            set_angles as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::bot::SET_ACTIVE_WEAPON => Some(Function::new(
+            set_active_weapon as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::bot::REMOVE_ALL_ITEMS => Some(Function::new(
+            remove_all_items as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Forget about a client that left the server,
+/// in case it was a bot created by `module`
+pub(crate) fn client_disconnect(module: &Module, edict: *const Edict) {
+    let mut lock = module.lock().unwrap();
+    lock.environment.bots.remove(edict);
+}
+
+with_abi! {
+    fn create(ctx: *mut VMContext<FabricEnv>, name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name,
+            Err(()) => {
+                warn!("could not load bot name at {}", name);
+                return 0;
+            }
+        };
+
+        let mut manager = match bot_manager() {
+            Some(manager) => manager,
+            None => return 0,
+        };
+
+        let edict = manager.create_bot(name);
+        match unsafe { edict.as_ref() } {
+            Some(bot) => {
+                debug!("Bot::create({:?}) = {}", name, bot.index());
+                ctx.environment.bots.0.push(edict);
+                bot.index()
+            }
+            None => {
+                warn!("could not create bot {:?}", name);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn remove(ctx: *mut VMContext<FabricEnv>, bot: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let edict = match ctx.environment.bots.find(bot) {
+            Some(edict) => edict,
+            None => return 0,
+        };
+
+        let mut engine = match engine() {
+            Some(engine) => engine,
+            None => return 0,
+        };
+
+        // Bots are fake clients, and are removed by kicking them
+        ctx.environment.bots.remove(edict);
+        let user_id = engine.get_player_user_id(edict);
+        if user_id == -1 {
+            return 0;
+        }
+
+        let command = CString::new(format!("kickid {}\n", user_id)).unwrap();
+        engine.server_command(&command);
+        1
+    }
+}
+
+with_abi! {
+    fn run_move(
+        ctx: *mut VMContext<FabricEnv>,
+        bot: i32,
+        angles: i32,
+        forward_move: f32,
+        side_move: f32,
+        up_move: f32,
+        buttons: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let view_angles = match load_vector(ctx, angles) {
+            Some(angles) => angles,
+            None => return 0,
+        };
+
+        if !forward_move.is_finite() || !side_move.is_finite() || !up_move.is_finite() {
+            warn!("invalid bot move {} {} {}", forward_move, side_move, up_move);
+            return 0;
+        }
+
+        let mut controller = match ctx.environment.bots.find(bot).and_then(bot_controller) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+
+        let tick_count = globals().map_or(0, |globals| globals.tick_count());
+        let mut command = BotCmd::new();
+        command.command_number = tick_count;
+        command.tick_count = tick_count;
+        command.view_angles = view_angles;
+        command.forward_move = forward_move;
+        command.side_move = side_move;
+        command.up_move = up_move;
+        command.buttons = buttons;
+
+        controller.run_player_move(&mut command);
+        1
+    }
+}
+
+with_abi! {
+    fn set_origin(ctx: *mut VMContext<FabricEnv>, bot: i32, origin: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let mut origin = match load_vector(ctx, origin) {
+            Some(origin) => origin,
+            None => return 0,
+        };
+
+        let mut controller = match ctx.environment.bots.find(bot).and_then(bot_controller) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+
+        controller.set_abs_origin(&mut origin);
+        1
+    }
+}
+
+with_abi! {
+    fn set_angles(ctx: *mut VMContext<FabricEnv>, bot: i32, angles: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let angles = match load_vector(ctx, angles) {
+            Some(angles) => angles,
+            None => return 0,
+        };
+
+        let mut controller = match ctx.environment.bots.find(bot).and_then(bot_controller) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+
+        controller.set_local_angles(&angles);
+        1
+    }
+}
+
+with_abi! {
+    fn set_active_weapon(ctx: *mut VMContext<FabricEnv>, bot: i32, weapon: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let weapon = match ctx.memory.load::<CStr>(weapon as usize) {
+            Ok(weapon) => weapon,
+            Err(()) => {
+                warn!("could not load weapon name at {}", weapon);
+                return 0;
+            }
+        };
+
+        let mut controller = match ctx.environment.bots.find(bot).and_then(bot_controller) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+
+        controller.set_active_weapon(weapon);
+        1
+    }
+}
+
+with_abi! {
+    fn remove_all_items(ctx: *mut VMContext<FabricEnv>, bot: i32, remove_suit: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let mut controller = match ctx.environment.bots.find(bot).and_then(bot_controller) {
+            Some(controller) => controller,
+            None => return 0,
+        };
+
+        controller.remove_all_items(remove_suit != 0);
+        1
+    }
+}
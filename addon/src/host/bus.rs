@@ -0,0 +1,261 @@
+use std::{ffi::CStr, rc::Rc};
+
+use fabric_runtime::{with_abi, ExternRef, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::module::{
+    dispatch_guest, get_extern, names, store_truncated, take_extern, FabricEnv, Module,
+};
+
+/// Callback invoked on the game thread for each message
+/// published on a topic, with the handle of the subscription
+pub(crate) type MessageFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, ExternRef));
+
+/// Maximum size of the content of a message in bytes
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// Maximum number of messages waiting to be delivered
+const MAX_PENDING_MESSAGES: usize = 1024;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::bus::PUBLISH => Some(Function::new(
+            publish as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::bus::SUBSCRIBE => Some(Function::new(
+            subscribe as with_abi!(fn(*mut VMContext<FabricEnv>, i32, FuncRef) -> i32),
+        )),
+        names::bus::UNSUBSCRIBE => Some(Function::new(
+            unsubscribe as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::bus::LENGTH => Some(Function::new(
+            length as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::bus::READ => Some(Function::new(
+            read as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::bus::TOPIC => Some(Function::new(
+            topic as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::bus::SENDER => Some(Function::new(
+            sender as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Message published by a module, only valid for the duration of the callback
+struct Message {
+    /// Name of the module that published the message
+    sender: String,
+    topic: String,
+    data: Vec<u8>,
+}
+
+struct Subscription {
+    handle: i32,
+    topic: String,
+    callback: MessageFunc,
+}
+
+/// Topics a module is subscribed to
+pub(crate) struct Subscriptions {
+    next_handle: i32,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Subscriptions {
+    pub(crate) fn new() -> Self {
+        Subscriptions {
+            next_handle: 1,
+            subscriptions: Vec::new(),
+        }
+    }
+}
+
+/// Messages waiting to be delivered, the bus is only accessed from the game thread
+static mut PENDING: Vec<Rc<Message>> = Vec::new();
+
+/// Deliver the messages published since the last frame to all the
+/// modules subscribed to their topic, in publication order
+///
+/// Messages published by the callbacks are delivered on the next frame
+pub(crate) fn dispatch(modules: &[Module]) {
+    let messages = unsafe { std::mem::take(&mut PENDING) };
+
+    for message in messages {
+        for module in modules {
+            let mut lock = module.lock().unwrap();
+
+            let callbacks: Vec<_> = lock
+                .environment
+                .subscriptions
+                .subscriptions
+                .iter()
+                .filter(|subscription| subscription.topic == message.topic)
+                .map(|subscription| (subscription.handle, subscription.callback))
+                .collect();
+
+            for (handle, callback) in callbacks {
+                let message = message.clone();
+                dispatch_guest(&mut lock, "message", move |ctx| {
+                    let extern_ref = ctx.create_extern(message);
+                    callback(ctx, handle, extern_ref);
+                    take_extern::<Rc<Message>>(&mut ctx.externs, extern_ref, "message");
+                });
+            }
+        }
+    }
+}
+
+/// Drop the messages that haven't been delivered yet
+pub(crate) fn clear() {
+    unsafe {
+        PENDING.clear();
+    }
+}
+
+fn load_topic(ctx: &VMContext<FabricEnv>, topic: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(topic as usize) {
+        Ok(topic) => Some(topic.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load topic at {}", topic);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn publish(ctx: *mut VMContext<FabricEnv>, topic: i32, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let topic = match load_topic(ctx, topic) {
+            Some(topic) => topic,
+            None => return 0,
+        };
+
+        if len < 0 || len as usize > MAX_MESSAGE_SIZE {
+            warn!("invalid message length {}", len);
+            return 0;
+        }
+
+        let data = match ctx.memory.bytes(data as usize, len as usize) {
+            Ok(data) => data.to_vec(),
+            Err(()) => {
+                warn!("could not load message at {}+{}", data, len);
+                return 0;
+            }
+        };
+
+        let pending = unsafe { &mut PENDING };
+        if pending.len() >= MAX_PENDING_MESSAGES {
+            warn!("message bus is full, dropping message on {:?}", topic);
+            return 0;
+        }
+
+        debug!("Bus::publish({:?}, {})", topic, len);
+
+        pending.push(Rc::new(Message {
+            sender: ctx.environment.name.clone(),
+            topic,
+            data,
+        }));
+
+        1
+    }
+}
+
+with_abi! {
+    fn subscribe(ctx: *mut VMContext<FabricEnv>, topic: i32, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback: MessageFunc = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        let topic = match load_topic(ctx, topic) {
+            Some(topic) => topic,
+            None => return 0,
+        };
+
+        let subscriptions = &mut ctx.environment.subscriptions;
+        let handle = subscriptions.next_handle;
+        subscriptions.next_handle = subscriptions.next_handle.wrapping_add(1).max(1);
+
+        subscriptions.subscriptions.push(Subscription {
+            handle,
+            topic,
+            callback,
+        });
+
+        handle
+    }
+}
+
+with_abi! {
+    fn unsubscribe(ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let subscriptions = &mut ctx.environment.subscriptions.subscriptions;
+        let len = subscriptions.len();
+        subscriptions.retain(|subscription| subscription.handle != handle);
+
+        if subscriptions.len() != len {
+            1
+        } else {
+            warn!("unknown subscription {}", handle);
+            0
+        }
+    }
+}
+
+with_abi! {
+    fn length(ctx: *mut VMContext<FabricEnv>, message: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let message = match get_extern::<Rc<Message>>(&ctx.externs, message, "message") {
+            Some(message) => message,
+            None => return 0,
+        };
+        message.data.len() as i32
+    }
+}
+
+with_abi! {
+    fn read(ctx: *mut VMContext<FabricEnv>, message: ExternRef, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let message = match get_extern::<Rc<Message>>(&ctx.externs, message, "message") {
+            Some(message) => message.clone(),
+            None => return -1,
+        };
+        store_truncated(&mut ctx.memory, &message.data, buffer, len, "message data")
+    }
+}
+
+with_abi! {
+    fn topic(ctx: *mut VMContext<FabricEnv>, message: ExternRef, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let message = match get_extern::<Rc<Message>>(&ctx.externs, message, "message") {
+            Some(message) => message.clone(),
+            None => return -1,
+        };
+        store_truncated(&mut ctx.memory, message.topic.as_bytes(), buffer, len, "topic")
+    }
+}
+
+with_abi! {
+    fn sender(ctx: *mut VMContext<FabricEnv>, message: ExternRef, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let message = match get_extern::<Rc<Message>>(&ctx.externs, message, "message") {
+            Some(message) => message.clone(),
+            None => return -1,
+        };
+        store_truncated(&mut ctx.memory, message.sender.as_bytes(), buffer, len, "sender")
+    }
+}
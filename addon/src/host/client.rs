@@ -0,0 +1,675 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+};
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    addon::{Edict, QueryCvarCookie, QueryCvarValueStatus},
+    bans::{self, Identity},
+    engine::{client_command, engine, VEngineServer},
+    entity::edict,
+    host::usermessage::{hint, recipients, say, text_msg},
+    module::{
+        call_guest, dispatch_guest, find_module, is_paused, names, store_truncated, FabricEnv,
+        Module,
+    },
+};
+
+/// Callback invoked with the client whose settings changed and
+/// the `SETTING_*` flags of the settings that changed
+pub(crate) type SettingsFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32));
+
+/// Callback invoked with the cookie of a query of a client variable,
+/// the client and the `QUERY_*` status of the query
+pub(crate) type QueryFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32));
+
+/// Callback invoked with a connecting client, returns the address of
+/// the rejection message in the memory of the module or 0 to accept it
+pub(crate) type ConnectFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32);
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::client::KICK => Some(Function::new(
+            kick as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::client::BAN => Some(Function::new(
+            ban as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32) -> i32),
+        )),
+        names::client::BAN_IDENTITY => Some(Function::new(
+            ban_identity as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::client::UNBAN => Some(Function::new(
+            unban as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::client::BAN_REASON => Some(Function::new(
+            ban_reason as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::client::ON_SETTINGS_CHANGED => Some(Function::new(
+            on_settings_changed as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::client::ON_CONNECT => Some(Function::new(
+            on_connect as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::client::PRINT => Some(Function::new(
+            print as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::client::SET_CVAR => Some(Function::new(
+            set_cvar as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::client::CVAR => Some(Function::new(
+            cvar as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32) -> i32),
+        )),
+        names::client::QUERY_CVAR => Some(Function::new(
+            query_cvar as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, FuncRef) -> i32),
+        )),
+        names::client::QUERY_VALUE => Some(Function::new(
+            query_value as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::client::SETTING => Some(Function::new(
+            setting as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Ban the network ID of the client
+const BAN_BY_STEAMID: i32 = 1 << 0;
+/// Ban the IP address of the client
+const BAN_BY_IP: i32 = 1 << 1;
+
+/// Small text in the notification area at the top left of the screen
+const PRINT_NOTIFY: i32 = 1;
+/// Line of text in the console of the client
+const PRINT_CONSOLE: i32 = 2;
+/// Message in the chat area, with the colors of the game
+const PRINT_CHAT: i32 = 3;
+/// Text at the center of the screen
+const PRINT_CENTER: i32 = 4;
+/// Text in the hint box
+const PRINT_HINT: i32 = 5;
+
+/// Replicated client variables tracked for each client, the index of
+/// each variable in the list is the bit of its `SETTING_*` flag
+const SETTINGS: [&CStr; 5] = [
+    cstr!("name"),
+    cstr!("rate"),
+    cstr!("cl_updaterate"),
+    cstr!("cl_cmdrate"),
+    cstr!("cl_interp"),
+];
+
+/// Query of a client variable waiting for the answer of the client
+struct Query {
+    module: String,
+    /// Generation of the module the callback belongs to
+    generation: u32,
+    client: c_int,
+    callback: QueryFunc,
+}
+
+/// Queries of client variables keyed by cookie, only accessed from the game thread
+static mut QUERIES: Option<HashMap<QueryCvarCookie, Query>> = None;
+
+/// Value of the variable of the query callback being called
+static mut QUERY_VALUE: Option<CString> = None;
+
+/// Values of the tracked variables of a client, before and after the last change
+struct Snapshot {
+    previous: Vec<String>,
+    current: Vec<String>,
+}
+
+/// Snapshots of the connected clients keyed by entity index,
+/// only accessed from the game thread
+static mut SNAPSHOTS: Option<HashMap<c_int, Snapshot>> = None;
+
+fn read_settings(client: c_int) -> Option<Vec<String>> {
+    let mut engine = engine()?;
+    let values = SETTINGS
+        .iter()
+        .map(|name| {
+            let value = engine.get_client_con_var_value(client, name);
+            if value.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(value) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        })
+        .collect();
+
+    Some(values)
+}
+
+/// Take the first snapshot of the settings of a client entering the server
+pub(crate) fn client_put_in_server(entity: *const Edict) {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return,
+    };
+
+    if let Some(values) = read_settings(client) {
+        let snapshots = unsafe { SNAPSHOTS.get_or_insert_with(HashMap::new) };
+        snapshots.insert(
+            client,
+            Snapshot {
+                previous: values.clone(),
+                current: values,
+            },
+        );
+    }
+}
+
+/// Compare the settings of a client with its last snapshot and notify
+/// the modules of the values that changed, a client without a snapshot
+/// has all its settings reported as changed
+pub(crate) fn client_settings_changed(modules: &[Module], entity: *const Edict) {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return,
+    };
+
+    let values = match read_settings(client) {
+        Some(values) => values,
+        None => return,
+    };
+
+    let snapshots = unsafe { SNAPSHOTS.get_or_insert_with(HashMap::new) };
+    let changed = match snapshots.get(&client) {
+        Some(snapshot) => snapshot
+            .current
+            .iter()
+            .zip(&values)
+            .enumerate()
+            .filter(|(_, (current, value))| current != value)
+            .fold(0, |changed, (index, _)| changed | 1 << index),
+        None => (1 << SETTINGS.len()) - 1,
+    };
+
+    if changed == 0 {
+        return;
+    }
+
+    let previous = snapshots
+        .remove(&client)
+        .map_or_else(|| values.clone(), |snapshot| snapshot.current);
+
+    snapshots.insert(
+        client,
+        Snapshot {
+            previous,
+            current: values,
+        },
+    );
+
+    for module in modules {
+        let mut lock = match module.try_lock() {
+            Ok(lock) => lock,
+            Err(_) => continue,
+        };
+
+        if let Some(callback) = lock.environment.settings_changed {
+            dispatch_guest(&mut lock, "settings", move |ctx| {
+                callback(ctx, client, changed)
+            });
+        }
+    }
+}
+
+/// Ask the modules whether the client at `client` can connect, returns the
+/// name of the first module rejecting it along with its rejection message
+///
+/// The connection can't wait for the paused modules, they are skipped
+pub(crate) fn client_connect(modules: &[Module], client: c_int) -> Option<(String, String)> {
+    for module in modules {
+        let mut lock = match module.try_lock() {
+            Ok(lock) => lock,
+            Err(_) => continue,
+        };
+
+        let callback = match lock.environment.connect {
+            Some(callback) if !is_paused(&lock) => callback,
+            _ => continue,
+        };
+
+        let reason = call_guest(&mut lock, "connect", |ctx| match callback(ctx, client) {
+            0 => None,
+            reason => Some(load_string(ctx, reason).unwrap_or_default()),
+        });
+
+        if let Some(Some(reason)) = reason {
+            return Some((lock.environment.name.clone(), reason));
+        }
+    }
+
+    None
+}
+
+/// Forget the settings and the queries of a disconnecting client
+pub(crate) fn client_disconnect(entity: *const Edict) {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return,
+    };
+
+    if let Some(snapshots) = unsafe { SNAPSHOTS.as_mut() } {
+        snapshots.remove(&client);
+    }
+
+    if let Some(queries) = unsafe { QUERIES.as_mut() } {
+        queries.retain(|_, query| query.client != client);
+    }
+}
+
+/// Call the callback of the query `cookie` with the value sent by the client
+pub(crate) fn query_finished(
+    modules: &[Module],
+    cookie: QueryCvarCookie,
+    status: QueryCvarValueStatus,
+    value: *const c_char,
+) {
+    let query = match unsafe { QUERIES.as_mut() }.and_then(|queries| queries.remove(&cookie)) {
+        Some(query) => query,
+        None => return,
+    };
+
+    let module = match find_module(modules, &query.module) {
+        Some(module) => module,
+        None => return,
+    };
+
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!(
+                "module {} is busy, dropping the query {}",
+                query.module, cookie
+            );
+            return;
+        }
+    };
+
+    // The module was reloaded since the query was sent
+    if lock.environment.generation != query.generation {
+        return;
+    }
+
+    let value = if value.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(value) }.to_owned())
+    };
+
+    let Query {
+        client, callback, ..
+    } = query;
+    let status = status as i32;
+    dispatch_guest(&mut lock, "query", move |ctx| {
+        unsafe { QUERY_VALUE = value };
+        callback(ctx, cookie, client, status);
+        unsafe { QUERY_VALUE = None };
+    });
+}
+
+/// Whether `name` can be sent in a console command as the name of a variable
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// Edict of a human client, the bots have no console to run commands in
+fn human_client(client: i32) -> Option<*mut Edict> {
+    if bans::steam_id(client).as_deref() == Some("BOT") {
+        return None;
+    }
+
+    edict(client)
+}
+
+fn load_string(ctx: &VMContext<FabricEnv>, ptr: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(ptr as usize) {
+        Ok(value) => Some(value.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", ptr);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn kick(ctx: *mut VMContext<FabricEnv>, client: i32, reason: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let reason = match load_string(ctx, reason) {
+            Some(reason) => reason,
+            None => return 0,
+        };
+
+        bans::kick(client, &reason) as i32
+    }
+}
+
+with_abi! {
+    fn ban(ctx: *mut VMContext<FabricEnv>, client: i32, duration: i32, reason: i32, flags: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let reason = match load_string(ctx, reason) {
+            Some(reason) => reason,
+            None => return 0,
+        };
+
+        let mut identities = Vec::new();
+        if flags & BAN_BY_STEAMID != 0 {
+            match bans::steam_id(client) {
+                // Bots and clients that aren't validated yet have no usable ID
+                Some(id) if id.starts_with("STEAM_") || id.starts_with("[U:") => {
+                    identities.push(Identity::SteamId(id))
+                }
+                _ => warn!("client {} has no SteamID to ban", client),
+            }
+        }
+
+        if flags & BAN_BY_IP != 0 {
+            match bans::address(client) {
+                Some(address) => identities.push(Identity::Ip(address)),
+                None => warn!("client {} has no address to ban", client),
+            }
+        }
+
+        if identities.is_empty() {
+            return 0;
+        }
+
+        for identity in identities {
+            bans::add(identity, duration.max(0) as u64, &reason, &ctx.environment.name);
+        }
+
+        bans::kick(client, &reason);
+        1
+    }
+}
+
+with_abi! {
+    fn ban_identity(ctx: *mut VMContext<FabricEnv>, identity: i32, duration: i32, reason: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let identity = match load_string(ctx, identity).as_deref().and_then(Identity::parse) {
+            Some(identity) => identity,
+            None => return 0,
+        };
+
+        let reason = match load_string(ctx, reason) {
+            Some(reason) => reason,
+            None => return 0,
+        };
+
+        bans::add(identity, duration.max(0) as u64, &reason, &ctx.environment.name);
+        1
+    }
+}
+
+with_abi! {
+    fn unban(ctx: *mut VMContext<FabricEnv>, identity: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match load_string(ctx, identity).as_deref().and_then(Identity::parse) {
+            Some(identity) => bans::remove(&identity) as i32,
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn ban_reason(ctx: *mut VMContext<FabricEnv>, identity: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let identity = match load_string(ctx, identity).as_deref().and_then(Identity::parse) {
+            Some(identity) => identity,
+            None => return -1,
+        };
+
+        let reason = match bans::find(&identity) {
+            Some(ban) => ban.reason,
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, reason.as_bytes(), buffer, len, "reason")
+    }
+}
+
+with_abi! {
+    fn on_settings_changed(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        ctx.environment.settings_changed = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn on_connect(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        ctx.environment.connect = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn print(ctx: *mut VMContext<FabricEnv>, client: i32, destination: i32, text: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let text = match ctx.memory.load::<CStr>(text as usize) {
+            Ok(text) => text.to_owned(),
+            Err(()) => {
+                warn!("could not load string at {}", text);
+                return 0;
+            }
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        let is_ok = match destination {
+            PRINT_CHAT => say(recipients, &text),
+            PRINT_CENTER => text_msg(recipients, PRINT_CENTER as u8, &text),
+            PRINT_HINT => hint(recipients, &text),
+            PRINT_NOTIFY | PRINT_CONSOLE => {
+                // The lines of the console and the notification area
+                // are only broken by the newlines in the text
+                let mut text = text.into_bytes();
+                if text.last() != Some(&b'\n') {
+                    text.push(b'\n');
+                }
+
+                // The text was loaded from a C string, it has no NUL bytes
+                let text = unsafe { CString::from_vec_unchecked(text) };
+                text_msg(recipients, destination as u8, &text)
+            }
+            _ => {
+                warn!("invalid print destination {}", destination);
+                return 0;
+            }
+        };
+
+        is_ok as i32
+    }
+}
+
+with_abi! {
+    fn set_cvar(ctx: *mut VMContext<FabricEnv>, client: i32, name: i32, value: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (name, value) = match (load_string(ctx, name), load_string(ctx, value)) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return 0,
+        };
+
+        // The command can't be split or end the value early
+        if !is_variable_name(&name) || value.contains(|c| matches!(c, '"' | ';' | '\n' | '\r')) {
+            warn!("invalid client variable {} = {:?}", name, value);
+            return 0;
+        }
+
+        let edict = match human_client(client) {
+            Some(edict) => edict,
+            None => return 0,
+        };
+
+        let command = match CString::new(format!("{} \"{}\"\n", name, value)) {
+            Ok(command) => command,
+            Err(_) => return 0,
+        };
+
+        client_command(edict, &command) as i32
+    }
+}
+
+with_abi! {
+    fn cvar(ctx: *mut VMContext<FabricEnv>, client: i32, name: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name.to_owned(),
+            Err(()) => {
+                warn!("could not load string at {}", name);
+                return -1;
+            }
+        };
+
+        let mut engine = match engine() {
+            Some(engine) => engine,
+            None => return -1,
+        };
+
+        // Only the variables replicated to the server have a value
+        let value = engine.get_client_con_var_value(client, &name);
+        if value.is_null() {
+            return -1;
+        }
+
+        let value = unsafe { CStr::from_ptr(value) }.to_bytes();
+        store_truncated(&mut ctx.memory, value, buffer, len, "value")
+    }
+}
+
+with_abi! {
+    fn query_cvar(ctx: *mut VMContext<FabricEnv>, client: i32, name: i32, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name.to_owned(),
+            Err(()) => {
+                warn!("could not load string at {}", name);
+                return -1;
+            }
+        };
+
+        let callback = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return -1;
+            }
+        };
+
+        let (edict, mut engine) = match (human_client(client), engine()) {
+            (Some(edict), Some(engine)) => (edict, engine),
+            _ => return -1,
+        };
+
+        // InvalidQueryCvarCookie is -1
+        let cookie = engine.start_query_cvar_value(edict, &name);
+        if cookie == -1 {
+            return -1;
+        }
+
+        let queries = unsafe { QUERIES.get_or_insert_with(HashMap::new) };
+        queries.insert(
+            cookie,
+            Query {
+                module: ctx.environment.name.clone(),
+                generation: ctx.environment.generation,
+                client,
+                callback,
+            },
+        );
+
+        cookie
+    }
+}
+
+with_abi! {
+    fn query_value(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // Only set during the query callbacks
+        let value = match unsafe { QUERY_VALUE.as_ref() } {
+            Some(value) => value.to_bytes(),
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value, buffer, len, "value")
+    }
+}
+
+with_abi! {
+    fn setting(
+        ctx: *mut VMContext<FabricEnv>,
+        client: i32,
+        setting: i32,
+        previous: i32,
+        buffer: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // The setting is given as its `SETTING_*` flag
+        let index = match (0..SETTINGS.len()).find(|index| setting == 1 << index) {
+            Some(index) => index,
+            None => {
+                warn!("invalid setting {}", setting);
+                return -1;
+            }
+        };
+
+        let snapshot = match unsafe { SNAPSHOTS.as_ref() }.and_then(|snapshots| snapshots.get(&client)) {
+            Some(snapshot) => snapshot,
+            None => return -1,
+        };
+
+        let value = if previous != 0 {
+            &snapshot.previous[index]
+        } else {
+            &snapshot.current[index]
+        };
+
+        store_truncated(&mut ctx.memory, value.as_bytes(), buffer, len, "setting")
+    }
+}
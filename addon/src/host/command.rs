@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::c_int,
+    time::Instant,
+};
+
+use fabric_runtime::{
+    with_abi, Callback, CallbackRegistry, ExternRef, FuncRef, Function, VMContext,
+};
+use log::{debug, warn};
+
+use crate::{
+    addon::module_command,
+    command,
+    flood::{self, Limit},
+    module::{dispatch_guest, get_extern, names, store_truncated, take_extern, FabricEnv, Module},
+};
+
+/// Callback invoked with the arguments of the command, including its name
+pub(crate) type CommandFunc = with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef));
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::command::REGISTER => Some(Function::new(
+            register as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, FuncRef) -> i32),
+        )),
+        names::command::ISSUER => Some(Function::new(
+            issuer as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::command::ARG_COUNT => Some(Function::new(
+            arg_count as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::command::ARG => Some(Function::new(
+            arg as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        names::command::SET_RATE_LIMIT => Some(Function::new(
+            set_rate_limit as with_abi!(fn(*mut VMContext<FabricEnv>, i32, f32, i32) -> i32),
+        )),
+        names::command::RESET_RATE_LIMIT => Some(Function::new(
+            reset_rate_limit as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::command::RATE_LIMIT => Some(Function::new(
+            rate_limit as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::command::IS_LIMITED => Some(Function::new(
+            is_limited as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Console commands registered by a module, keyed by name
+pub(crate) struct Commands {
+    commands: CallbackRegistry<String, CommandFunc>,
+}
+
+impl Commands {
+    pub(crate) fn new() -> Self {
+        Commands {
+            commands: CallbackRegistry::new(),
+        }
+    }
+}
+
+/// Entity index of the client issuing the command being executed, 0 for
+/// the server console and RCON. Only accessed from the game thread
+static mut COMMAND_CLIENT: c_int = 0;
+
+/// Commands registered with the engine on behalf of the modules, with the
+/// name of the module owning each command. The commands stay registered
+/// when the modules are unloaded, and can only be handled by their owner
+static mut REGISTERED: Option<HashMap<String, String>> = None;
+
+/// Record the client issuing the next command, from the `SetCommandClient`
+/// callback of the engine with the slot of the client or -1 for the server
+pub(crate) fn set_command_client(index: c_int) {
+    unsafe {
+        COMMAND_CLIENT = if index >= 0 { index + 1 } else { 0 };
+    }
+}
+
+/// Call the modules that registered the command `args[0]` with its arguments,
+/// returns false if no module handles the command
+pub(crate) fn dispatch(modules: &[Module], args: &[String]) -> bool {
+    let name = match args.first() {
+        Some(name) => name.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    let mut is_handled = false;
+    for module in modules {
+        // The command can be issued by a module through the engine,
+        // the module is already locked in that case and is skipped
+        let mut lock = match module.try_lock() {
+            Ok(lock) => lock,
+            Err(_) => continue,
+        };
+
+        let callback = match lock.environment.commands.commands.get(&name) {
+            Some(callback) => callback,
+            None => continue,
+        };
+
+        is_handled = true;
+        let args = args.to_vec();
+        dispatch_guest(&mut lock, "command", move |ctx| {
+            let extern_ref = ctx.create_extern(args);
+            callback(ctx, extern_ref);
+            take_extern::<Vec<String>>(&mut ctx.externs, extern_ref, "command arguments");
+        });
+    }
+
+    is_handled
+}
+
+/// Dispatch a command typed by the client at `client` in its console
+pub(crate) fn client_command(modules: &[Module], client: c_int, args: &[String]) -> bool {
+    unsafe {
+        COMMAND_CLIENT = client;
+    }
+
+    let is_handled = dispatch(modules, args);
+
+    unsafe {
+        COMMAND_CLIENT = 0;
+    }
+
+    is_handled
+}
+
+fn load_string(ctx: &VMContext<FabricEnv>, ptr: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(ptr as usize) {
+        Ok(value) => Some(value.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", ptr);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn register(ctx: *mut VMContext<FabricEnv>, name: i32, help: i32, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match Callback::resolve(ctx, callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        let name = match load_string(ctx, name) {
+            Some(name) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+                name.to_ascii_lowercase()
+            }
+            _ => return 0,
+        };
+
+        let help = match load_string(ctx, help) {
+            Some(help) => help,
+            None => return 0,
+        };
+
+        let registered = unsafe { REGISTERED.get_or_insert_with(HashMap::new) };
+        if let Some(owner) = registered.get(&name) {
+            if *owner != ctx.environment.name {
+                warn!("command {} is owned by module {}", name, owner);
+                return 0;
+            }
+        } else {
+            let (engine_name, engine_help) = match (CString::new(name.clone()), CString::new(help)) {
+                (Ok(name), Ok(help)) => (name, help),
+                _ => return 0,
+            };
+
+            // The engine keeps the pointers for as long as the command is registered
+            let engine_name = Box::leak(engine_name.into_boxed_c_str());
+            let engine_help = Box::leak(engine_help.into_boxed_c_str());
+            command::register(engine_name, engine_help, module_command);
+
+            debug!("registered command {}", name);
+            registered.insert(name.clone(), ctx.environment.name.clone());
+        }
+
+        ctx.environment.commands.commands.insert(name, callback);
+        1
+    }
+}
+
+with_abi! {
+    fn issuer(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        unsafe { COMMAND_CLIENT }
+    }
+}
+
+with_abi! {
+    fn arg_count(ctx: *mut VMContext<FabricEnv>, args: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        let args = match get_extern::<Vec<String>>(&ctx.externs, args, "command arguments") {
+            Some(args) => args,
+            None => return 0,
+        };
+        args.len() as i32
+    }
+}
+
+with_abi! {
+    fn arg(ctx: *mut VMContext<FabricEnv>, args: ExternRef, index: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let args = match get_extern::<Vec<String>>(&ctx.externs, args, "command arguments") {
+            Some(args) => args,
+            None => return -1,
+        };
+
+        let value = match args.get(index.max(0) as usize) {
+            Some(value) if index >= 0 => value.clone(),
+            _ => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value.as_bytes(), buffer, len, "argument")
+    }
+}
+
+with_abi! {
+    fn set_rate_limit(ctx: *mut VMContext<FabricEnv>, name: i32, rate: f32, burst: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match load_string(ctx, name) {
+            Some(name) if !name.is_empty() => name,
+            _ => return 0,
+        };
+
+        if !rate.is_finite() || rate < 0.0 || burst < 0 {
+            warn!("invalid rate limit {}/s with bursts of {}", rate, burst);
+            return 0;
+        }
+
+        debug!(
+            "module {} limited {} to {}/s with bursts of {}",
+            ctx.environment.name, name, rate, burst
+        );
+
+        let limit = Limit {
+            rate,
+            burst: burst as u32,
+        };
+        flood::set_limit(&name, Some(limit));
+        1
+    }
+}
+
+with_abi! {
+    fn reset_rate_limit(ctx: *mut VMContext<FabricEnv>, name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match load_string(ctx, name) {
+            Some(name) => {
+                flood::set_limit(&name, None);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn rate_limit(ctx: *mut VMContext<FabricEnv>, name: i32, result: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match load_string(ctx, name) {
+            Some(name) => name.to_ascii_lowercase(),
+            None => return 0,
+        };
+
+        // The limit is stored as { f32 rate, i32 burst }
+        let limit = flood::limit(&name);
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&limit.rate.to_le_bytes());
+        bytes[4..].copy_from_slice(&(limit.burst.min(i32::MAX as u32) as i32).to_le_bytes());
+
+        match ctx.memory.store(result as usize, &bytes) {
+            Ok(()) => 1,
+            Err(()) => {
+                warn!("could not store rate limit at {}", result);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn is_limited(ctx: *mut VMContext<FabricEnv>, client: i32, name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match load_string(ctx, name) {
+            Some(name) => flood::is_limited(client, &name, Instant::now()) as i32,
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::tests::load_test_module;
+
+    /// Module registering the `isolated` command from its `register` export,
+    /// the funcref of the command callback is its index after the import
+    const SOURCE: &str = r#"(module
+        (import "Command" "register" (func $register (param i32 i32 i32) (result i32)))
+        (memory 1)
+        (data (i32.const 16) "isolated\00")
+        (func $on_command (param externref))
+        (func (export "register") (result i32)
+            (call $register (i32.const 16) (i32.const 0) (i32.const 1))))"#;
+
+    const NAME: &str = "isolated";
+
+    type RegisterFunc = with_abi!(fn(*mut VMContext<FabricEnv>) -> i32);
+
+    fn register_command(ctx: &mut VMContext<FabricEnv>) -> i32 {
+        let register: RegisterFunc = ctx.export("register").unwrap().try_get().unwrap();
+        register(ctx)
+    }
+
+    fn has_command(ctx: &VMContext<FabricEnv>) -> bool {
+        ctx.environment.commands.commands.contains_key(&NAME.into())
+    }
+
+    #[test]
+    fn commands_are_owned_by_their_module() {
+        let mut first = load_test_module("first", SOURCE);
+        let mut second = load_test_module("second", SOURCE);
+
+        assert_eq!(register_command(&mut first), 1);
+        assert!(has_command(&first));
+
+        // The command belongs to the first module, even once it is unloaded
+        assert_eq!(register_command(&mut second), 0);
+        assert!(!has_command(&second));
+        drop(first);
+        assert_eq!(register_command(&mut second), 0);
+
+        // Its owner can register it again, after a reload
+        let mut first = load_test_module("first", SOURCE);
+        assert_eq!(register_command(&mut first), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::ffi::CStr;
+
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    config,
+    module::{dispatch_guest, names, store_truncated, FabricEnv, Module},
+};
+
+pub(crate) type ConfigFunc = with_abi!(fn(*mut VMContext<FabricEnv>));
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::config::GET => Some(Function::new(
+            get as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::config::ON_CHANGED => Some(Function::new(
+            on_changed as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Notify `module` that the configuration was reloaded
+/// with `fabric_reload_config`, if it registered a callback
+///
+/// The command can be issued by the module itself through the engine,
+/// the module is already locked in that case and isn't notified
+pub(crate) fn notify_changed(module: &Module) {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping configuration notification");
+            return;
+        }
+    };
+
+    if let Some(callback) = lock.environment.config_changed {
+        dispatch_guest(&mut lock, "config", move |ctx| callback(ctx));
+    }
+}
+
+with_abi! {
+    fn get(ctx: *mut VMContext<FabricEnv>, key: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match ctx.memory.load::<CStr>(key as usize) {
+            Ok(key) => key.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load key string at {}", key);
+                return -1;
+            }
+        };
+
+        let value = match config::get().setting(&ctx.environment.name, &key) {
+            Some(value) => value,
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value.as_bytes(), buffer, len, "value")
+    }
+}
+
+with_abi! {
+    fn on_changed(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        ctx.environment.config_changed = Some(callback);
+        1
+    }
+}
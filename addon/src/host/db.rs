@@ -0,0 +1,541 @@
+use std::{
+    ffi::{c_void, CStr},
+    fs,
+    os::raw::{c_char, c_int},
+    path::Path,
+    ptr::null_mut,
+};
+
+use fabric_runtime::{with_abi, ExternRef, Function, VMContext};
+use log::{debug, warn};
+use rusqlite::{ffi, limits::Limit, types::Value, Connection};
+
+use crate::{
+    config,
+    module::{get_extern, get_extern_mut, names, store_truncated, take_extern, FabricEnv},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::db::EXEC => Some(Function::new(
+            exec as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::db::PREPARE => Some(Function::new(
+            prepare as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> ExternRef),
+        )),
+        names::db::FINALIZE => Some(Function::new(
+            finalize as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef)),
+        )),
+        names::db::BIND_INT => Some(Function::new(
+            bind_int as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i64) -> i32),
+        )),
+        names::db::BIND_FLOAT => Some(Function::new(
+            bind_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, f64) -> i32),
+        )),
+        names::db::BIND_TEXT => Some(Function::new(
+            bind_text as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::db::BIND_BLOB => Some(Function::new(
+            bind_blob as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        names::db::EXECUTE => Some(Function::new(
+            execute as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::db::QUERY => Some(Function::new(
+            query as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::db::NEXT => Some(Function::new(
+            next as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::db::COLUMN_INT => Some(Function::new(
+            column_int as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i64),
+        )),
+        names::db::COLUMN_FLOAT => Some(Function::new(
+            column_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> f64),
+        )),
+        names::db::COLUMN_BYTES => Some(Function::new(
+            column_bytes
+                as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Database connection of a module
+///
+/// Each module gets its own database file in the `data` directory of the addon,
+/// the file is only created the first time the module accesses its database
+pub(crate) struct Database {
+    connection: Option<Connection>,
+}
+
+impl Database {
+    pub(crate) fn new() -> Self {
+        Database { connection: None }
+    }
+
+    fn connection(&mut self, module: &str) -> Option<&Connection> {
+        if self.connection.is_none() {
+            let dir = config::get().root().join("data");
+            if let Err(err) = fs::create_dir_all(&dir) {
+                warn!("could not create {}: {}", dir.display(), err);
+                return None;
+            }
+
+            let path = dir.join(format!("{}.sqlite", module));
+            match open(&path) {
+                Ok(connection) => {
+                    debug!("opened database {}", path.display());
+                    self.connection = Some(connection);
+                }
+                Err(err) => {
+                    warn!("could not open {}: {}", path.display(), err);
+                    return None;
+                }
+            }
+        }
+
+        self.connection.as_ref()
+    }
+}
+
+/// Open the database at `path` for the SQL of a module, which
+/// must not reach any other file than the database itself
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    restrict(&connection)?;
+    Ok(connection)
+}
+
+/// Prevent `connection` from attaching other database files, which would
+/// let the guests create and write files anywhere. `VACUUM INTO` attaches
+/// its output file the same way, so it is denied as well
+fn restrict(connection: &Connection) -> rusqlite::Result<()> {
+    connection.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0);
+
+    let result =
+        unsafe { ffi::sqlite3_set_authorizer(connection.handle(), Some(authorize), null_mut()) };
+    if result != ffi::SQLITE_OK {
+        return Err(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(result),
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Authorizer of the connections, called by SQLite for each action of the statements it compiles
+extern "C" fn authorize(
+    _data: *mut c_void,
+    action: c_int,
+    _arg1: *const c_char,
+    _arg2: *const c_char,
+    _database: *const c_char,
+    _trigger: *const c_char,
+) -> c_int {
+    match action {
+        ffi::SQLITE_ATTACH | ffi::SQLITE_DETACH => ffi::SQLITE_DENY,
+        _ => ffi::SQLITE_OK,
+    }
+}
+
+/// A prepared statement, along with its parameters and the rows of the last query
+///
+/// The rows are fetched all at once when the query is executed, guests then
+/// iterate over them with `next` and read the columns of the current row
+struct Statement {
+    sql: String,
+    /// Number of parameters of the statement, the highest index that can be bound
+    parameters: usize,
+    params: Vec<Value>,
+    rows: Vec<Vec<Value>>,
+    /// Index of the current row plus one, 0 before the first call to `next`
+    cursor: usize,
+}
+
+impl Statement {
+    fn bind(&mut self, index: i32, value: Value) -> i32 {
+        // Parameters are 1-indexed, like in the SQLite API
+        if index < 1 || index as usize > self.parameters {
+            warn!("invalid parameter index {} for {:?}", index, self.sql);
+            return 0;
+        }
+
+        let index = index as usize - 1;
+        if self.params.len() <= index {
+            self.params.resize(index + 1, Value::Null);
+        }
+
+        self.params[index] = value;
+        1
+    }
+
+    fn column(&self, index: i32) -> Option<&Value> {
+        let row = match self
+            .cursor
+            .checked_sub(1)
+            .and_then(|row| self.rows.get(row))
+        {
+            Some(row) => row,
+            None => {
+                warn!("no current row for {:?}", self.sql);
+                return None;
+            }
+        };
+
+        let column = row.get(index as usize);
+        if column.is_none() {
+            warn!("invalid column index {} for {:?}", index, self.sql);
+        }
+
+        column
+    }
+}
+
+fn load_sql(ctx: &VMContext<FabricEnv>, sql: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(sql as usize) {
+        Ok(sql) => Some(sql.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load sql string at {}", sql);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn exec(ctx: *mut VMContext<FabricEnv>, sql: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let sql = match load_sql(ctx, sql) {
+            Some(sql) => sql,
+            None => return 0,
+        };
+
+        let env = &mut ctx.environment;
+        let connection = match env.database.connection(&env.name) {
+            Some(connection) => connection,
+            None => return 0,
+        };
+
+        match connection.execute_batch(&sql) {
+            Ok(()) => 1,
+            Err(err) => {
+                warn!("{:?}: {}", sql, err);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn prepare(ctx: *mut VMContext<FabricEnv>, sql: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        let sql = match load_sql(ctx, sql) {
+            Some(sql) => sql,
+            None => return ExternRef::null(),
+        };
+
+        let env = &mut ctx.environment;
+        let connection = match env.database.connection(&env.name) {
+            Some(connection) => connection,
+            None => return ExternRef::null(),
+        };
+
+        // The statement is compiled once here to report syntax errors early,
+        // and kept in the statement cache of the connection for later queries
+        let parameters = match connection.prepare_cached(&sql) {
+            Ok(cached) => cached.parameter_count(),
+            Err(err) => {
+                warn!("{:?}: {}", sql, err);
+                return ExternRef::null();
+            }
+        };
+
+        ctx.create_extern(Statement {
+            sql,
+            parameters,
+            params: Vec::new(),
+            rows: Vec::new(),
+            cursor: 0,
+        })
+    }
+}
+
+with_abi! {
+    fn finalize(ctx: *mut VMContext<FabricEnv>, statement: ExternRef) {
+        let ctx = unsafe { &mut *ctx };
+        take_extern::<Statement>(&mut ctx.externs, statement, "statement");
+    }
+}
+
+with_abi! {
+    fn bind_int(ctx: *mut VMContext<FabricEnv>, statement: ExternRef, index: i32, value: i64) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        statement.bind(index, Value::Integer(value))
+    }
+}
+
+with_abi! {
+    fn bind_float(ctx: *mut VMContext<FabricEnv>, statement: ExternRef, index: i32, value: f64) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        statement.bind(index, Value::Real(value))
+    }
+}
+
+with_abi! {
+    fn bind_text(ctx: *mut VMContext<FabricEnv>, statement: ExternRef, index: i32, value: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match ctx.memory.load::<CStr>(value as usize) {
+            Ok(value) => value.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load string at {}", value);
+                return 0;
+            }
+        };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        statement.bind(index, Value::Text(value))
+    }
+}
+
+with_abi! {
+    fn bind_blob(
+        ctx: *mut VMContext<FabricEnv>,
+        statement: ExternRef,
+        index: i32,
+        value: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match ctx.memory.bytes(value as usize, len as usize) {
+            Ok(value) if len >= 0 => value.to_vec(),
+            _ => {
+                warn!("could not load blob at {}+{}", value, len);
+                return 0;
+            }
+        };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        statement.bind(index, Value::Blob(value))
+    }
+}
+
+with_abi! {
+    fn execute(ctx: *mut VMContext<FabricEnv>, statement: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let env = &mut ctx.environment;
+        let connection = match env.database.connection(&env.name) {
+            Some(connection) => connection,
+            None => return -1,
+        };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return -1,
+        };
+        let result = connection
+            .prepare_cached(&statement.sql)
+            .and_then(|mut cached| cached.execute(&statement.params));
+
+        match result {
+            Ok(changes) => changes as i32,
+            Err(err) => {
+                warn!("{:?}: {}", statement.sql, err);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn query(ctx: *mut VMContext<FabricEnv>, statement: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let env = &mut ctx.environment;
+        let connection = match env.database.connection(&env.name) {
+            Some(connection) => connection,
+            None => return -1,
+        };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return -1,
+        };
+        statement.rows.clear();
+        statement.cursor = 0;
+
+        let result = connection.prepare_cached(&statement.sql).and_then(|mut cached| {
+            let columns = cached.column_count();
+            let mut rows = cached.query(&statement.params)?;
+
+            let mut result = Vec::new();
+            while let Some(row) = rows.next()? {
+                let row = (0..columns)
+                    .map(|index| row.get::<_, Value>(index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                result.push(row);
+            }
+
+            Ok(result)
+        });
+
+        match result {
+            Ok(rows) => {
+                statement.rows = rows;
+                statement.rows.len() as i32
+            }
+            Err(err) => {
+                warn!("{:?}: {}", statement.sql, err);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn next(ctx: *mut VMContext<FabricEnv>, statement: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern_mut::<Statement>(&mut ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        if statement.cursor < statement.rows.len() {
+            statement.cursor += 1;
+            1
+        } else {
+            0
+        }
+    }
+}
+
+with_abi! {
+    fn column_int(ctx: *mut VMContext<FabricEnv>, statement: ExternRef, index: i32) -> i64 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern::<Statement>(&ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0,
+        };
+        match statement.column(index) {
+            Some(Value::Integer(value)) => *value,
+            Some(Value::Real(value)) => *value as i64,
+            _ => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn column_float(ctx: *mut VMContext<FabricEnv>, statement: ExternRef, index: i32) -> f64 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern::<Statement>(&ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return 0.0,
+        };
+        match statement.column(index) {
+            Some(Value::Integer(value)) => *value as f64,
+            Some(Value::Real(value)) => *value,
+            _ => 0.0,
+        }
+    }
+}
+
+with_abi! {
+    fn column_bytes(
+        ctx: *mut VMContext<FabricEnv>,
+        statement: ExternRef,
+        index: i32,
+        buffer: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let statement = match get_extern::<Statement>(&ctx.externs, statement, "statement") {
+            Some(statement) => statement,
+            None => return -1,
+        };
+        let value = match statement.column(index) {
+            Some(Value::Text(value)) => value.as_bytes(),
+            Some(Value::Blob(value)) => value.as_slice(),
+            Some(Value::Null) => &[],
+            Some(_) => {
+                warn!("column {} of {:?} is not a string or blob", index, statement.sql);
+                return -1;
+            }
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value, buffer, len, "column value")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restricted() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        restrict(&connection).unwrap();
+        connection
+    }
+
+    #[test]
+    fn attach_is_denied() {
+        let path = std::env::temp_dir().join("fabric-db-attach-test.sqlite");
+        fs::remove_file(&path).ok();
+        let connection = restricted();
+
+        let sql = format!("ATTACH DATABASE '{}' AS other", path.display());
+        assert!(connection.execute_batch(&sql).is_err());
+
+        let sql = format!("VACUUM INTO '{}'", path.display());
+        assert!(connection.execute_batch(&sql).is_err());
+        assert!(!path.exists());
+
+        // The database of the module itself is still usable
+        connection
+            .execute_batch("CREATE TABLE scores (name TEXT); INSERT INTO scores VALUES ('a')")
+            .unwrap();
+    }
+
+    #[test]
+    fn bind_checks_parameter_index() {
+        let mut statement = Statement {
+            sql: String::from("SELECT ?1, ?2"),
+            parameters: 2,
+            params: Vec::new(),
+            rows: Vec::new(),
+            cursor: 0,
+        };
+
+        assert_eq!(statement.bind(2, Value::Integer(2)), 1);
+        assert_eq!(statement.params, [Value::Null, Value::Integer(2)]);
+
+        assert_eq!(statement.bind(0, Value::Integer(0)), 0);
+        assert_eq!(statement.bind(3, Value::Integer(3)), 0);
+        assert_eq!(statement.bind(i32::MAX, Value::Integer(3)), 0);
+        assert_eq!(statement.params.len(), 2);
+    }
+}
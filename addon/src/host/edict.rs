@@ -0,0 +1,316 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{CStr, CString},
+};
+
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    addon::Edict,
+    entity::{class_name, edict_slot, max_edicts},
+    module::{dispatch_guest, names, store_truncated, FabricEnv, Module},
+};
+
+pub(crate) type EdictFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32));
+
+/// Callbacks registered by a module to be notified of edict allocations
+/// and of the entities created and removed
+pub(crate) struct EdictHooks {
+    allocated: Option<EdictFunc>,
+    freed: Option<EdictFunc>,
+    created: Option<EdictFunc>,
+    removed: Option<EdictFunc>,
+}
+
+impl EdictHooks {
+    pub(crate) fn new() -> Self {
+        EdictHooks {
+            allocated: None,
+            freed: None,
+            created: None,
+            removed: None,
+        }
+    }
+}
+
+/// Maximum number of entity lifecycle events delivered on each frame, the
+/// thousands of entities created when a map loads are spread over frames
+const MAX_LIFECYCLE_EVENTS_PER_FRAME: usize = 64;
+
+/// Entity created or removed, delivered to the modules on a later frame
+struct LifecycleEvent {
+    index: i32,
+    created: bool,
+}
+
+/// Lifecycle events waiting to be delivered, only accessed from the game thread
+static mut LIFECYCLE: Option<VecDeque<LifecycleEvent>> = None;
+
+/// Class names of the entities reported as created, by edict index,
+/// the class name of an entity can't be read once it is removed
+static mut CLASS_NAMES: Option<HashMap<i32, CString>> = None;
+
+/// Class name of the entity of the lifecycle callback being called
+static mut CURRENT_CLASS_NAME: Option<CString> = None;
+
+/// Edicts are referenced by index, the functions
+/// return -1 for an index outside of the edict list
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::edict::SERIAL_NUMBER => Some(Function::new(
+            serial_number as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::edict::IS_FREE => Some(Function::new(
+            is_free as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::edict::NEXT_ALLOCATED => Some(Function::new(
+            next_allocated as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::edict::ON_ALLOCATED => Some(Function::new(
+            on_allocated as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::edict::ON_FREED => Some(Function::new(
+            on_freed as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::edict::ON_ENTITY_CREATED => Some(Function::new(
+            on_entity_created as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::edict::ON_ENTITY_REMOVED => Some(Function::new(
+            on_entity_removed as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::edict::CLASS_NAME => Some(Function::new(
+            lifecycle_class_name as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Notify `module` that `edict` was allocated or freed
+///
+/// The engine allocates edicts synchronously when an entity is created, which
+/// can happen from a host function called by the module itself. The module is
+/// already locked in that case and the notification is skipped
+pub(crate) fn notify(module: &Module, edict: *const Edict, allocated: bool) {
+    let index = match unsafe { edict.as_ref() } {
+        Some(edict) => edict.index(),
+        None => return,
+    };
+
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping edict notification for {}", index);
+            return;
+        }
+    };
+
+    let hooks = &lock.environment.edict_hooks;
+    let callback = if allocated {
+        hooks.allocated
+    } else {
+        hooks.freed
+    };
+    if let Some(callback) = callback {
+        dispatch_guest(&mut lock, "edict", move |ctx| callback(ctx, index));
+    }
+}
+
+/// Queue the lifecycle event of the entity of `edict`
+///
+/// The class name of an entity is only set once it spawned, so the
+/// creations are reported on the next frames instead of right away.
+/// An entity removed before its creation was reported is never reported
+pub(crate) fn queue_lifecycle(edict: *const Edict, created: bool) {
+    let index = match unsafe { edict.as_ref() } {
+        Some(edict) => edict.index(),
+        None => return,
+    };
+
+    let queue = unsafe { LIFECYCLE.get_or_insert_with(VecDeque::new) };
+    if !created {
+        let pending = queue
+            .iter()
+            .rposition(|event| event.created && event.index == index);
+        if let Some(position) = pending {
+            queue.remove(position);
+            return;
+        }
+    }
+
+    queue.push_back(LifecycleEvent { index, created });
+}
+
+/// Deliver the lifecycle events of the entities to the modules,
+/// up to `MAX_LIFECYCLE_EVENTS_PER_FRAME` of them
+///
+/// The modules can create and remove entities from their callbacks, which
+/// queues more events, so the queue isn't borrowed across the callbacks
+pub(crate) fn run_lifecycle(modules: &[Module]) {
+    for _ in 0..MAX_LIFECYCLE_EVENTS_PER_FRAME {
+        let event = unsafe { LIFECYCLE.as_mut() }.and_then(VecDeque::pop_front);
+        let LifecycleEvent { index, created } = match event {
+            Some(event) => event,
+            None => return,
+        };
+
+        let class_names = unsafe { CLASS_NAMES.get_or_insert_with(HashMap::new) };
+        let name = if created {
+            let name = class_name(index).map(|name| unsafe { CStr::from_ptr(name) }.to_owned());
+            if let Some(name) = &name {
+                class_names.insert(index, name.clone());
+            }
+            name
+        } else {
+            class_names.remove(&index)
+        };
+
+        for module in modules {
+            notify_lifecycle(module, index, created, name.clone());
+        }
+    }
+}
+
+/// Forget the lifecycle events of the entities of the previous map, the
+/// entities of a map aren't reported as removed when it ends
+pub(crate) fn reset_lifecycle() {
+    unsafe {
+        LIFECYCLE = None;
+        CLASS_NAMES = None;
+    }
+}
+
+fn notify_lifecycle(module: &Module, index: i32, created: bool, name: Option<CString>) {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping entity notification for {}", index);
+            return;
+        }
+    };
+
+    let hooks = &lock.environment.edict_hooks;
+    let callback = if created {
+        hooks.created
+    } else {
+        hooks.removed
+    };
+    if let Some(callback) = callback {
+        dispatch_guest(&mut lock, "entity", move |ctx| {
+            unsafe { CURRENT_CLASS_NAME = name };
+            callback(ctx, index);
+            unsafe { CURRENT_CLASS_NAME = None };
+        });
+    }
+}
+
+fn resolve(ctx: &VMContext<FabricEnv>, callback: FuncRef) -> Option<EdictFunc> {
+    match ctx.typed_function(callback) {
+        Ok(callback) => Some(callback),
+        Err(err) => {
+            warn!("could not resolve {:?}: {}", callback, err);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn serial_number(_ctx: *mut VMContext<FabricEnv>, index: i32) -> i32 {
+        match edict_slot(index) {
+            Some(edict) => unsafe { (*edict).serial_number() },
+            None => -1,
+        }
+    }
+}
+
+with_abi! {
+    fn is_free(_ctx: *mut VMContext<FabricEnv>, index: i32) -> i32 {
+        match edict_slot(index) {
+            Some(edict) => unsafe { (*edict).is_free() as i32 },
+            None => -1,
+        }
+    }
+}
+
+with_abi! {
+    fn next_allocated(_ctx: *mut VMContext<FabricEnv>, index: i32) -> i32 {
+        // Iteration starts from -1, and ends when -1 is returned
+        let start = index.max(-1) + 1;
+        (start..max_edicts())
+            .find(|index| match edict_slot(*index) {
+                Some(edict) => unsafe { !(*edict).is_free() },
+                None => false,
+            })
+            .unwrap_or(-1)
+    }
+}
+
+with_abi! {
+    fn on_allocated(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match resolve(ctx, callback) {
+            Some(callback) => callback,
+            None => return 0,
+        };
+
+        ctx.environment.edict_hooks.allocated = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn on_freed(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match resolve(ctx, callback) {
+            Some(callback) => callback,
+            None => return 0,
+        };
+
+        ctx.environment.edict_hooks.freed = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn on_entity_created(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match resolve(ctx, callback) {
+            Some(callback) => callback,
+            None => return 0,
+        };
+
+        ctx.environment.edict_hooks.created = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn on_entity_removed(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match resolve(ctx, callback) {
+            Some(callback) => callback,
+            None => return 0,
+        };
+
+        ctx.environment.edict_hooks.removed = Some(callback);
+        1
+    }
+}
+
+with_abi! {
+    fn lifecycle_class_name(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // Only set during the lifecycle callbacks
+        let name = match unsafe { CURRENT_CLASS_NAME.as_ref() } {
+            Some(name) => name.to_bytes(),
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, name, buffer, len, "class name")
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    convert::TryInto,
+    ffi::{CStr, CString},
+    ptr::null,
+};
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    effects::{effects, Effects},
+    engine::{engine, VEngineServer, Vector},
+    host::globals::globals,
+    module::{names, FabricEnv, Module},
+};
+
+/// Maximum number of effects a module can spawn in a single server tick,
+/// temp entities are sent unreliably and the engine drops the ones that
+/// don't fit in the snapshot of the clients
+const MAX_EFFECTS_PER_TICK: u32 = 32;
+
+/// Counts the effects spawned by a module during the current tick
+#[derive(Default)]
+pub(crate) struct RateLimit {
+    tick: i32,
+    count: u32,
+}
+
+impl RateLimit {
+    pub(crate) fn new() -> Self {
+        RateLimit::default()
+    }
+
+    /// Try to spawn an effect, returns false if
+    /// the limit has been reached for this tick
+    fn acquire(&mut self) -> bool {
+        let tick = globals().map_or(0, |globals| globals.tick_count());
+        if tick != self.tick {
+            self.tick = tick;
+            self.count = 0;
+        }
+
+        if self.count >= MAX_EFFECTS_PER_TICK {
+            return false;
+        }
+
+        self.count += 1;
+        true
+    }
+}
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::effects::PRECACHE_MODEL => Some(Function::new(
+            precache_model as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::effects::BEAM => Some(Function::new(
+            beam as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, f32, i32, i32) -> i32),
+        )),
+        names::effects::SMOKE => Some(Function::new(
+            smoke as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, f32, f32) -> i32),
+        )),
+        names::effects::SPARKS => Some(Function::new(
+            sparks as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::effects::METAL_SPARKS => Some(Function::new(
+            metal_sparks as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::effects::DUST => Some(Function::new(
+            dust as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, f32, f32) -> i32),
+        )),
+        names::effects::ENERGY_SPLASH => Some(Function::new(
+            energy_splash as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Precache all the models used by `module`, the precache
+/// tables of the engine are reset when the level changes
+pub(crate) fn precache_models(module: &Module) {
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return,
+    };
+
+    let lock = module.lock().unwrap();
+    for model in &lock.environment.models {
+        if engine.precache_model(model, true) == 0 {
+            warn!("could not precache model {:?}", model);
+        }
+    }
+}
+
+/// Load a vector stored as 3 consecutive f32 in guest memory,
+/// only finite coordinates are accepted
+pub(crate) fn load_vector(ctx: &VMContext<FabricEnv>, offset: i32) -> Option<Vector> {
+    let bytes = match ctx.memory.bytes(offset as usize, 12) {
+        Ok(bytes) => bytes,
+        Err(()) => {
+            warn!("could not load vector at {}", offset);
+            return None;
+        }
+    };
+
+    let coord = |index: usize| {
+        let bytes = bytes[index * 4..index * 4 + 4].try_into().unwrap();
+        f32::from_le_bytes(bytes)
+    };
+
+    let vector = Vector {
+        x: coord(0),
+        y: coord(1),
+        z: coord(2),
+    };
+
+    if vector.x.is_finite() && vector.y.is_finite() && vector.z.is_finite() {
+        Some(vector)
+    } else {
+        warn!("invalid vector {:?}", vector);
+        None
+    }
+}
+
+/// Resolve the index of a model precached by the module
+fn model_index(ctx: &VMContext<FabricEnv>, model: i32) -> Option<i32> {
+    let model = match ctx.memory.load::<CStr>(model as usize) {
+        Ok(model) => model,
+        Err(()) => {
+            warn!("could not load model string at {}", model);
+            return None;
+        }
+    };
+
+    if !ctx.environment.models.iter().any(|name| &**name == model) {
+        warn!("model {:?} was not precached", model);
+        return None;
+    }
+
+    // Precaching a model that's already in the table
+    // only returns its index without modifying it
+    let index = engine()?.precache_model(model, true);
+    if index > 0 {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Check the rate limit of the module and get the effects interface
+fn acquire(ctx: &mut VMContext<FabricEnv>) -> Option<impl Effects> {
+    if !ctx.environment.effects.acquire() {
+        debug!("effect rate limit reached for {}", ctx.environment.name);
+        return None;
+    }
+
+    effects()
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(name: &str, value: T, min: T, max: T) -> bool {
+    if value >= min && value <= max {
+        true
+    } else {
+        warn!("invalid {} {}", name, value);
+        false
+    }
+}
+
+with_abi! {
+    fn precache_model(ctx: *mut VMContext<FabricEnv>, model: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let model = match ctx.memory.load::<CStr>(model as usize) {
+            Ok(model) => CString::from(model),
+            Err(()) => {
+                warn!("could not load model string at {}", model);
+                return 0;
+            }
+        };
+
+        let models = &mut ctx.environment.models;
+        if !models.contains(&model) {
+            debug!("Effects::precache_model({:?})", model);
+
+            // Modules are usually loaded before the first level, in which
+            // case the model will be precached when the level is loaded
+            if let Some(mut engine) = engine() {
+                engine.precache_model(&model, true);
+            }
+
+            models.push(model);
+        }
+
+        1
+    }
+}
+
+with_abi! {
+    fn beam(
+        ctx: *mut VMContext<FabricEnv>,
+        start: i32,
+        end: i32,
+        model: i32,
+        life: f32,
+        width: i32,
+        color: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (start, end) = match (load_vector(ctx, start), load_vector(ctx, end)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return 0,
+        };
+
+        let model = match model_index(ctx, model) {
+            Some(model) => model,
+            None => return 0,
+        };
+
+        // The life of the beam is sent in tenths of seconds in a single byte
+        if !check_range("beam life", life, 0.0, 25.5) || !check_range("beam width", width, 0, 255) {
+            return 0;
+        }
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        // The color is packed as 0xRRGGBBAA
+        let [red, green, blue, alpha] = (color as u32).to_be_bytes();
+        effects.beam(
+            &start, &end, model, model, 0, 10, life, width as u8, width as u8, 0, 0, red, green,
+            blue, alpha, 0,
+        );
+
+        1
+    }
+}
+
+with_abi! {
+    fn smoke(ctx: *mut VMContext<FabricEnv>, origin: i32, model: i32, scale: f32, frame_rate: f32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let origin = match load_vector(ctx, origin) {
+            Some(origin) => origin,
+            None => return 0,
+        };
+
+        let model = match model_index(ctx, model) {
+            Some(model) => model,
+            None => return 0,
+        };
+
+        if !check_range("smoke scale", scale, 0.0, 25.5)
+            || !check_range("smoke frame rate", frame_rate, 0.0, 255.0)
+        {
+            return 0;
+        }
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        effects.smoke(&origin, model, scale, frame_rate);
+        1
+    }
+}
+
+with_abi! {
+    fn sparks(ctx: *mut VMContext<FabricEnv>, position: i32, magnitude: i32, trail_length: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let position = match load_vector(ctx, position) {
+            Some(position) => position,
+            None => return 0,
+        };
+
+        if !check_range("spark magnitude", magnitude, 1, 8)
+            || !check_range("spark trail length", trail_length, 1, 8)
+        {
+            return 0;
+        }
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        effects.sparks(&position, magnitude, trail_length, null());
+        1
+    }
+}
+
+with_abi! {
+    fn metal_sparks(ctx: *mut VMContext<FabricEnv>, position: i32, direction: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (position, direction) = match (
+            load_vector(ctx, position),
+            load_vector(ctx, direction),
+        ) {
+            (Some(position), Some(direction)) => (position, direction),
+            _ => return 0,
+        };
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        effects.metal_sparks(&position, &direction);
+        1
+    }
+}
+
+with_abi! {
+    fn dust(ctx: *mut VMContext<FabricEnv>, position: i32, direction: i32, size: f32, speed: f32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (position, direction) = match (
+            load_vector(ctx, position),
+            load_vector(ctx, direction),
+        ) {
+            (Some(position), Some(direction)) => (position, direction),
+            _ => return 0,
+        };
+
+        if !check_range("dust size", size, 0.0, 255.0) || !check_range("dust speed", speed, 0.0, 4096.0) {
+            return 0;
+        }
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        effects.dust(&position, &direction, size, speed);
+        1
+    }
+}
+
+with_abi! {
+    fn energy_splash(ctx: *mut VMContext<FabricEnv>, position: i32, direction: i32, explosive: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (position, direction) = match (
+            load_vector(ctx, position),
+            load_vector(ctx, direction),
+        ) {
+            (Some(position), Some(direction)) => (position, direction),
+            _ => return 0,
+        };
+
+        let mut effects = match acquire(ctx) {
+            Some(effects) => effects,
+            None => return 0,
+        };
+
+        // An explosive splash also spawns an explosion with its sound
+        effects.energy_splash(&position, &direction, explosive != 0);
+        1
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CStr},
+    os::raw::c_int,
+};
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    engine::Vector,
+    entity::{
+        base_entity, base_entity_index, class_name, edict, entity_handle, handle_index, max_edicts,
+    },
+    module::{names, FabricEnv, Module},
+    netprops::{find_prop, read_int, PropInfo, PropKind},
+    tools::{server_tools, ServerTools},
+};
+
+/// Handles of the entities created by a module, these are
+/// removed automatically when the module is unloaded
+pub(crate) struct Entities(Vec<i32>);
+
+impl Entities {
+    pub(crate) fn new() -> Self {
+        Entities(Vec::new())
+    }
+}
+
+/// Name of the module that created each entity still held by a module, keyed
+/// by handle, so a module can't modify or remove the entities created by
+/// another module. Only accessed from the game thread
+static mut OWNERS: Option<HashMap<i32, String>> = None;
+
+fn owners() -> &'static mut HashMap<i32, String> {
+    unsafe { OWNERS.get_or_insert_with(HashMap::new) }
+}
+
+/// Returns false if the entity at `handle` was created by another module
+/// than the module of `ctx`, the entities of the map can be used by all
+fn is_allowed(ctx: &VMContext<FabricEnv>, handle: i32) -> bool {
+    match owners().get(&handle) {
+        Some(owner) if *owner != ctx.environment.name => {
+            warn!("entity {:#x} belongs to module {}", handle, owner);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Entities are exposed to the modules by entity index, and by handles
+/// for references that need to be held across frames. Functions returning
+/// an entity return -1 if the entity doesn't exist
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::entity::MAX_ENTITIES => Some(Function::new(
+            max_entities as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::entity::IS_VALID => Some(Function::new(
+            is_valid as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::entity::HANDLE => Some(Function::new(
+            handle as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::entity::FROM_HANDLE => Some(Function::new(
+            from_handle as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::entity::CLASS_NAME => Some(Function::new(
+            get_class_name as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::entity::GET_PROP_INT => Some(Function::new(
+            get_prop_int as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::entity::GET_PROP_FLOAT => Some(Function::new(
+            get_prop_float as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> f32),
+        )),
+        names::entity::GET_PROP_VECTOR => Some(Function::new(
+            get_prop_vector as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::entity::CREATE => Some(Function::new(
+            create as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::entity::SET_KEYVALUE => Some(Function::new(
+            set_keyvalue as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::entity::SPAWN => Some(Function::new(
+            spawn as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::entity::REMOVE => Some(Function::new(
+            remove as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Remove all the entities created by `module` that still exist
+pub(crate) fn remove_entities(module: &Module) {
+    let mut lock = module.lock().unwrap();
+    let handles = std::mem::replace(&mut lock.environment.entities.0, Vec::new());
+    for handle in &handles {
+        owners().remove(handle);
+    }
+
+    let mut tools = match server_tools() {
+        Some(tools) => tools,
+        None => return,
+    };
+
+    for index in handles.into_iter().filter_map(handle_index) {
+        if let Some(entity) = base_entity(index) {
+            tools.remove_entity(entity);
+        }
+    }
+}
+
+/// Forget the entities created by `module`, the
+/// engine removes all of them when the level ends
+pub(crate) fn level_shutdown(module: &Module) {
+    let mut lock = module.lock().unwrap();
+    for handle in lock.environment.entities.0.drain(..) {
+        owners().remove(&handle);
+    }
+}
+
+fn load_string<'a>(ctx: &'a VMContext<FabricEnv>, value: i32) -> Option<&'a CStr> {
+    match ctx.memory.load::<CStr>(value as usize) {
+        Ok(value) => Some(value),
+        Err(()) => {
+            warn!("could not load string at {}", value);
+            None
+        }
+    }
+}
+
+/// Resolve an entity handle to its CBaseEntity object
+fn handle_entity(handle: i32) -> Option<*mut c_void> {
+    let entity = handle_index(handle).and_then(base_entity);
+    if entity.is_none() {
+        warn!("invalid entity handle {:#x}", handle);
+    }
+
+    entity
+}
+
+/// Load the name of a property from guest memory and find it in the
+/// entity at `index`, checking the property has the expected type
+fn load_prop(
+    ctx: &VMContext<FabricEnv>,
+    index: c_int,
+    name: i32,
+    kind: fn(&PropKind) -> bool,
+) -> Option<(*mut u8, PropInfo)> {
+    let name = match ctx.memory.load::<CStr>(name as usize) {
+        Ok(name) => name,
+        Err(()) => {
+            warn!("could not load property name at {}", name);
+            return None;
+        }
+    };
+
+    let (prop, info) = find_prop(index, name)?;
+    if kind(&info.kind) {
+        Some((prop, info))
+    } else {
+        warn!("property {:?} is a {:?}", name, info.kind);
+        None
+    }
+}
+
+with_abi! {
+    fn max_entities(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        max_edicts()
+    }
+}
+
+with_abi! {
+    fn is_valid(_ctx: *mut VMContext<FabricEnv>, index: i32) -> i32 {
+        edict(index).is_some() as i32
+    }
+}
+
+with_abi! {
+    fn handle(_ctx: *mut VMContext<FabricEnv>, index: i32) -> i32 {
+        entity_handle(index).unwrap_or(-1)
+    }
+}
+
+with_abi! {
+    fn from_handle(_ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        handle_index(handle).unwrap_or(-1)
+    }
+}
+
+with_abi! {
+    fn get_class_name(ctx: *mut VMContext<FabricEnv>, index: i32, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match class_name(index) {
+            Some(name) => unsafe { CStr::from_ptr(name) },
+            None => return -1,
+        };
+
+        // The string is always null-terminated in guest memory, so at
+        // most `len - 1` bytes of the value are copied to the buffer
+        let value = name.to_bytes();
+        if len <= 0 {
+            return value.len() as i32;
+        }
+
+        let copied = value.len().min(len as usize - 1);
+        let result = ctx
+            .memory
+            .store(data as usize, &value[..copied])
+            .and_then(|()| ctx.memory.store(data as usize + copied, &[0]));
+
+        match result {
+            Ok(()) => value.len() as i32,
+            Err(()) => {
+                warn!("could not store class name at {}+{}", data, len);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn get_prop_int(ctx: *mut VMContext<FabricEnv>, index: i32, name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let is_int = |kind: &PropKind| matches!(kind, PropKind::Int { .. });
+        match load_prop(ctx, index, name, is_int) {
+            Some((prop, PropInfo { kind: PropKind::Int { bits, unsigned }, .. })) => unsafe {
+                read_int(prop, bits, unsigned)
+            },
+            _ => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn get_prop_float(ctx: *mut VMContext<FabricEnv>, index: i32, name: i32) -> f32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let is_float = |kind: &PropKind| *kind == PropKind::Float;
+        match load_prop(ctx, index, name, is_float) {
+            Some((prop, _)) => unsafe { (prop as *const f32).read_unaligned() },
+            None => 0.0,
+        }
+    }
+}
+
+with_abi! {
+    fn get_prop_vector(ctx: *mut VMContext<FabricEnv>, index: i32, name: i32, result: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let is_vector = |kind: &PropKind| *kind == PropKind::Vector;
+        let vector = match load_prop(ctx, index, name, is_vector) {
+            Some((prop, _)) => unsafe { (prop as *const Vector).read_unaligned() },
+            None => return 0,
+        };
+
+        let bytes: Vec<u8> = [vector.x, vector.y, vector.z]
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+
+        match ctx.memory.store(result as usize, &bytes) {
+            Ok(()) => 1,
+            Err(()) => {
+                warn!("could not store vector at {}", result);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut VMContext<FabricEnv>, class_name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let class_name = match load_string(ctx, class_name) {
+            Some(class_name) => class_name,
+            None => return -1,
+        };
+
+        let mut tools = match server_tools() {
+            Some(tools) => tools,
+            None => return -1,
+        };
+
+        let entity = tools.create_entity_by_name(class_name);
+        if entity.is_null() {
+            warn!("could not create entity {:?}", class_name);
+            return -1;
+        }
+
+        // Server-only entities don't have an edict, and
+        // cannot be referenced by index or by handle
+        let handle = base_entity_index(entity).and_then(entity_handle);
+        let handle = match handle {
+            Some(handle) => handle,
+            None => {
+                warn!("entity {:?} is not networked", class_name);
+                tools.remove_entity_immediate(entity);
+                return -1;
+            }
+        };
+
+        debug!("Entity::create({:?}) = {:#x}", class_name, handle);
+        ctx.environment.entities.0.push(handle);
+        owners().insert(handle, ctx.environment.name.clone());
+        handle
+    }
+}
+
+with_abi! {
+    fn set_keyvalue(ctx: *mut VMContext<FabricEnv>, handle: i32, key: i32, value: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (key, value) = match (load_string(ctx, key), load_string(ctx, value)) {
+            (Some(key), Some(value)) => (key, value),
+            _ => return 0,
+        };
+
+        if !is_allowed(ctx, handle) {
+            return 0;
+        }
+
+        let entity = match handle_entity(handle) {
+            Some(entity) => entity,
+            None => return 0,
+        };
+
+        match server_tools() {
+            Some(mut tools) => tools.set_key_value(entity, key, value) as i32,
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn spawn(ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if !is_allowed(ctx, handle) {
+            return 0;
+        }
+
+        let entity = match handle_entity(handle) {
+            Some(entity) => entity,
+            None => return 0,
+        };
+
+        match server_tools() {
+            Some(mut tools) => {
+                tools.dispatch_spawn(entity);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn remove(ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if !is_allowed(ctx, handle) {
+            return 0;
+        }
+
+        let entity = match handle_entity(handle) {
+            Some(entity) => entity,
+            None => return 0,
+        };
+
+        let mut tools = match server_tools() {
+            Some(tools) => tools,
+            None => return 0,
+        };
+
+        // The entity is deleted at the end of the frame
+        tools.remove_entity(entity);
+        ctx.environment.entities.0.retain(|entity| *entity != handle);
+        owners().remove(&handle);
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::tests::load_test_module;
+
+    #[test]
+    fn entities_are_owned_by_their_module() {
+        let first = load_test_module("first", "(module)");
+        let second = load_test_module("second", "(module)");
+
+        owners().insert(0x1001, first.environment.name.clone());
+        owners().insert(0x1002, second.environment.name.clone());
+
+        assert!(is_allowed(&first, 0x1001));
+        assert!(!is_allowed(&first, 0x1002));
+        assert!(is_allowed(&second, 0x1002));
+        assert!(!is_allowed(&second, 0x1001));
+
+        // The entities of the map don't belong to any module
+        assert!(is_allowed(&first, 0x2001));
+        assert!(is_allowed(&second, 0x2001));
+
+        // A removed entity can be reused by the module creating the next one
+        owners().remove(&0x1001);
+        assert!(is_allowed(&second, 0x1001));
+    }
+}
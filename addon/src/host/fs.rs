@@ -0,0 +1,248 @@
+use std::{
+    ffi::CStr,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::warn;
+
+use crate::{
+    config,
+    module::{names, store_truncated, FabricEnv},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::fs::READ => Some(Function::new(
+            read as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::fs::WRITE => Some(Function::new(
+            write as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::fs::LIST => Some(Function::new(
+            list as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Resolve a path relative to the data directory of `module`
+///
+/// Paths must be relative and may not contain `..` components or symbolic
+/// links, so they can't point outside of the data directory. With `create`
+/// set the parent directories of the path are created if they don't exist yet
+fn resolve(module: &str, path: &str, create: bool) -> Option<PathBuf> {
+    let root = config::get().root().join("data").join(module);
+    if let Err(err) = fs::create_dir_all(&root) {
+        warn!("could not create {}: {}", root.display(), err);
+        return None;
+    }
+
+    let root = match root.canonicalize() {
+        Ok(root) => root,
+        Err(err) => {
+            warn!("could not resolve {}: {}", root.display(), err);
+            return None;
+        }
+    };
+
+    resolve_in(&root, path, create)
+}
+
+/// Resolve `path` relative to the directory `root`, see `resolve`
+fn resolve_in(root: &Path, path: &str, create: bool) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    let is_valid = relative.components().all(|component| match component {
+        Component::Normal(_) | Component::CurDir => true,
+        Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+    });
+
+    if !is_valid {
+        warn!("path {:?} is outside of the module directory", path);
+        return None;
+    }
+
+    // Each component is checked before creating the next one, so neither the
+    // path nor the directories created for it can follow a link, even a
+    // dangling one, outside of the module directory
+    let components: Vec<_> = relative.components().collect();
+    let mut full = root.to_path_buf();
+    for (index, component) in components.iter().enumerate() {
+        full.push(component);
+
+        match fs::symlink_metadata(&full) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                warn!("path {:?} goes through a symbolic link", path);
+                return None;
+            }
+            Ok(_) => {}
+            // Only the parent directories are created, not the file itself
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if create && index + 1 < components.len() {
+                    if let Err(err) = fs::create_dir(&full) {
+                        warn!("could not create {}: {}", full.display(), err);
+                        return None;
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", path, err);
+                return None;
+            }
+        }
+    }
+
+    Some(full)
+}
+
+fn load_path(ctx: &VMContext<FabricEnv>, path: i32, create: bool) -> Option<PathBuf> {
+    let path = match ctx.memory.load::<CStr>(path as usize) {
+        Ok(path) => path.to_string_lossy(),
+        Err(()) => {
+            warn!("could not load path string at {}", path);
+            return None;
+        }
+    };
+
+    resolve(&ctx.environment.name, &path, create)
+}
+
+with_abi! {
+    fn read(ctx: *mut VMContext<FabricEnv>, path: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let path = match load_path(ctx, path, false) {
+            Some(path) => path,
+            None => return -1,
+        };
+
+        match fs::read(&path) {
+            Ok(data) => store_truncated(&mut ctx.memory, &data, buffer, len, "data"),
+            Err(err) => {
+                warn!("could not read {}: {}", path.display(), err);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn write(ctx: *mut VMContext<FabricEnv>, path: i32, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let path = match load_path(ctx, path, true) {
+            Some(path) => path,
+            None => return 0,
+        };
+
+        let data = match ctx.memory.bytes(data as usize, len as usize) {
+            Ok(data) if len >= 0 => data,
+            _ => {
+                warn!("could not load data at {}+{}", data, len);
+                return 0;
+            }
+        };
+
+        match fs::write(&path, data) {
+            Ok(()) => 1,
+            Err(err) => {
+                warn!("could not write {}: {}", path.display(), err);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn list(ctx: *mut VMContext<FabricEnv>, path: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let path = match load_path(ctx, path, false) {
+            Some(path) => path,
+            None => return -1,
+        };
+
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("could not list {}: {}", path.display(), err);
+                return -1;
+            }
+        };
+
+        // Entries are returned as a list of names separated by newlines,
+        // with a trailing slash for the directories
+        let mut names: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let mut name = entry.file_name().into_string().ok()?;
+                if entry.file_type().ok()?.is_dir() {
+                    name.push('/');
+                }
+                Some(name)
+            })
+            .collect();
+
+        names.sort();
+
+        let mut data = String::new();
+        for name in names {
+            data.push_str(&name);
+            data.push('\n');
+        }
+
+        store_truncated(&mut ctx.memory, data.as_bytes(), buffer, len, "entries")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for a test, removed first if a previous run left it
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fabric-fs-{}", name));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn resolve_creates_parents() {
+        let root = test_dir("parents");
+
+        let path = resolve_in(&root, "scores/2024/top.txt", true).unwrap();
+        assert_eq!(path, root.join("scores/2024/top.txt"));
+        assert!(root.join("scores/2024").is_dir());
+        assert!(!path.exists());
+
+        assert_eq!(resolve_in(&root, "", false), Some(root.clone()));
+        assert_eq!(resolve_in(&root, "../top.txt", true), None);
+        assert_eq!(resolve_in(&root, "/tmp/top.txt", true), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_dangling_links() {
+        let root = test_dir("dangling");
+        let outside = test_dir("dangling-outside").join("evil");
+        std::os::unix::fs::symlink(&outside, root.join("evil")).unwrap();
+
+        assert_eq!(resolve_in(&root, "evil", true), None);
+        assert_eq!(resolve_in(&root, "evil", false), None);
+        assert!(!outside.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_directory_links() {
+        let root = test_dir("directory");
+        let outside = test_dir("directory-outside");
+        std::os::unix::fs::symlink(&outside, root.join("dir")).unwrap();
+
+        assert_eq!(resolve_in(&root, "dir/nested/file.txt", true), None);
+        assert_eq!(resolve_in(&root, "dir", false), None);
+        assert!(!outside.join("nested").exists());
+    }
+}
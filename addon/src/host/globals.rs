@@ -0,0 +1,84 @@
+use std::ptr::null;
+
+use fabric_runtime::{with_abi, Function, VMContext};
+
+use crate::{
+    deferred::{self, Capability},
+    foreign::CreateInterfaceFn,
+    game,
+    module::{names, FabricEnv},
+    server::{GlobalVars, PlayerInfoManager},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::globals::CURTIME => Some(Function::new(
+            curtime as with_abi!(fn(*mut VMContext<FabricEnv>) -> f32),
+        )),
+        names::globals::FRAMETIME => Some(Function::new(
+            frametime as with_abi!(fn(*mut VMContext<FabricEnv>) -> f32),
+        )),
+        names::globals::TICK_COUNT => Some(Function::new(
+            tick_count as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::globals::MAX_CLIENTS => Some(Function::new(
+            max_clients as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+static mut GLOBALS: *const GlobalVars = null();
+
+/// Locate the global variables of the server DLL, this must be
+/// called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interfaces = game::interfaces();
+    let manager = game::acquire::<dyn PlayerInfoManager>(
+        server,
+        interfaces.player_info_manager,
+        "IPlayerInfoManager",
+    );
+    let globals = match manager {
+        Some(mut manager) => manager.get_global_vars(),
+        None => null(),
+    };
+
+    unsafe {
+        GLOBALS = globals;
+    }
+}
+
+/// Get the global variables of the server, located again at most
+/// once per level while they are missing
+pub(crate) fn globals() -> Option<&'static GlobalVars> {
+    if unsafe { GLOBALS.is_null() } {
+        deferred::retry(Capability::Globals);
+    }
+
+    unsafe { GLOBALS.as_ref() }
+}
+
+with_abi! {
+    fn curtime(_ctx: *mut VMContext<FabricEnv>) -> f32 {
+        globals().map_or(0.0, |globals| globals.curtime())
+    }
+}
+
+with_abi! {
+    fn frametime(_ctx: *mut VMContext<FabricEnv>) -> f32 {
+        globals().map_or(0.0, |globals| globals.frametime())
+    }
+}
+
+with_abi! {
+    fn tick_count(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        globals().map_or(0, |globals| globals.tick_count())
+    }
+}
+
+with_abi! {
+    fn max_clients(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        globals().map_or(0, |globals| globals.max_clients())
+    }
+}
@@ -0,0 +1,282 @@
+use std::{ffi::CStr, io::Read, time::Duration};
+
+use fabric_runtime::{with_abi, ExternRef, FuncRef, Function, VMContext};
+use log::{debug, warn};
+use url::Url;
+
+use crate::{
+    config::{self, HttpConfig},
+    executor::Completion,
+    module::{get_extern, names, store_truncated, take_extern, FabricEnv},
+};
+
+/// Callback invoked on the game thread once a request completes, with the
+/// HTTP status code of the response or a negative error code
+pub(crate) type ResponseFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, ExternRef));
+
+/// The request could not be sent or the connection failed
+const ERROR_TRANSPORT: i32 = -1;
+/// The response body is larger than `http.max_response_size`
+const ERROR_TOO_LARGE: i32 = -2;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::http::REQUEST => Some(Function::new(
+            request
+                as with_abi!(
+                    fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32, FuncRef) -> i32
+                ),
+        )),
+        names::http::BODY_LENGTH => Some(Function::new(
+            body_length as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        names::http::READ_BODY => Some(Function::new(
+            read_body as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::http::HEADER => Some(Function::new(
+            header as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Response to a request, only valid for the duration of the callback
+struct Response {
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// Parse a list of `Name: value` headers separated by newlines
+fn parse_headers(headers: &str) -> Vec<(String, String)> {
+    headers
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if name.is_empty() {
+                None
+            } else {
+                Some((name.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// Execute a request on a worker thread, returning the status code
+/// and the response if the request succeeded
+///
+/// `config` is a copy taken on the game thread when the request was made,
+/// the configuration may be reloaded while the request is running
+fn send(
+    config: HttpConfig,
+    method: String,
+    url: Url,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+) -> (i32, Option<Response>) {
+    let mut request = ureq::request(&method, url.as_str());
+    request
+        .timeout(Duration::from_millis(config.timeout_ms))
+        // Redirects could point to a host outside of the allowlist
+        .redirects(0);
+
+    for (name, value) in &headers {
+        request.set(name, value);
+    }
+
+    let response = request.send_bytes(&body);
+    if let Some(err) = response.synthetic_error() {
+        warn!("{} {}: {}", method, url, err);
+        return (ERROR_TRANSPORT, None);
+    }
+
+    let status = i32::from(response.status());
+    let headers = response
+        .headers_names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = response.header(&name)?.to_string();
+            Some((name, value))
+        })
+        .collect();
+
+    // Read one byte past the limit to detect oversized responses
+    let limit = config.max_response_size;
+    let mut body = Vec::new();
+    let mut reader = response.into_reader().take(limit as u64 + 1);
+    if let Err(err) = reader.read_to_end(&mut body) {
+        warn!("{} {}: {}", method, url, err);
+        return (ERROR_TRANSPORT, None);
+    }
+
+    if body.len() > limit {
+        warn!("{} {}: response exceeds {} bytes", method, url, limit);
+        return (ERROR_TOO_LARGE, None);
+    }
+
+    (status, Some(Response { headers, body }))
+}
+
+with_abi! {
+    fn request(
+        ctx: *mut VMContext<FabricEnv>,
+        method: i32,
+        url: i32,
+        headers: i32,
+        body: i32,
+        body_len: i32,
+        callback: FuncRef,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback: ResponseFunc = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        let method = match ctx.memory.load::<CStr>(method as usize) {
+            Ok(method) => method.to_string_lossy().to_ascii_uppercase(),
+            Err(()) => {
+                warn!("could not load method string at {}", method);
+                return 0;
+            }
+        };
+
+        let url = match ctx.memory.load::<CStr>(url as usize) {
+            Ok(url) => url.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load url string at {}", url);
+                return 0;
+            }
+        };
+
+        let url = match Url::parse(&url) {
+            Ok(url) => url,
+            Err(err) => {
+                warn!("invalid url {:?}: {}", url, err);
+                return 0;
+            }
+        };
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            warn!("unsupported url scheme {:?}", url.scheme());
+            return 0;
+        }
+
+        let config = config::get().http.clone();
+        match url.host_str() {
+            Some(host) if config.is_host_allowed(host) => {}
+            host => {
+                warn!("host {:?} is not in http.allowed_hosts", host);
+                return 0;
+            }
+        }
+
+        let headers = if headers != 0 {
+            match ctx.memory.load::<CStr>(headers as usize) {
+                Ok(headers) => parse_headers(&headers.to_string_lossy()),
+                Err(()) => {
+                    warn!("could not load headers string at {}", headers);
+                    return 0;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let body = match ctx.memory.bytes(body as usize, body_len as usize) {
+            Ok(body) if body_len >= 0 => body.to_vec(),
+            _ => {
+                warn!("could not load request body at {}+{}", body, body_len);
+                return 0;
+            }
+        };
+
+        debug!("Http::request({}, {})", method, url);
+
+        ctx.environment.tasks.spawn(move || {
+            let (status, response) = send(config, method, url, headers, body);
+            Box::new(move |ctx: &mut VMContext<FabricEnv>| {
+                let handle = response.map(|response| ctx.create_extern(response));
+                callback(ctx, status, handle.unwrap_or_else(ExternRef::null));
+
+                if let Some(handle) = handle {
+                    take_extern::<Response>(&mut ctx.externs, handle, "response");
+                }
+            }) as Completion
+        });
+
+        1
+    }
+}
+
+with_abi! {
+    fn body_length(ctx: *mut VMContext<FabricEnv>, response: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let response = match get_extern::<Response>(&ctx.externs, response, "response") {
+            Some(response) => response,
+            None => return 0,
+        };
+        response.body.len() as i32
+    }
+}
+
+with_abi! {
+    fn read_body(ctx: *mut VMContext<FabricEnv>, response: ExternRef, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let response = match get_extern::<Response>(&ctx.externs, response, "response") {
+            Some(response) => response,
+            None => return -1,
+        };
+        let len = response.body.len().min(len.max(0) as usize);
+
+        match ctx.memory.store(buffer as usize, &response.body[..len]) {
+            Ok(()) => len as i32,
+            Err(()) => {
+                warn!("could not store response body at {}+{}", buffer, len);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn header(
+        ctx: *mut VMContext<FabricEnv>,
+        response: ExternRef,
+        name: i32,
+        buffer: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load header name at {}", name);
+                return -1;
+            }
+        };
+
+        let response = match get_extern::<Response>(&ctx.externs, response, "response") {
+            Some(response) => response,
+            None => return -1,
+        };
+        let value = match response
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name))
+        {
+            Some((_, value)) => value.as_bytes(),
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value, buffer, len, "header value")
+    }
+}
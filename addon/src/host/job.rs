@@ -0,0 +1,152 @@
+use fabric_runtime::{with_abi, Callback, CallbackRegistry, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    config,
+    module::{call_guest, names, FabricEnv, Module},
+};
+
+/// Step of a job, called with the handle of the job and its data,
+/// returns non-zero while the job has more work to do
+pub(crate) type JobFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32);
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::job::SPAWN => Some(Function::new(
+            spawn as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef, i32) -> i32),
+        )),
+        names::job::CANCEL => Some(Function::new(
+            cancel as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::job::SHOULD_YIELD => Some(Function::new(
+            should_yield as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Long computations of a module, split in steps run on successive frames
+///
+/// A step is expected to save its progress in the memory of the module and
+/// return once `Job::should_yield` tells it the budget of the frame is
+/// running out. A step aborted because it ran out of fuel is called again
+/// on the next frame, resuming the job from the progress saved last, and
+/// doesn't count as an overrun of the budget of the module
+pub(crate) struct Jobs {
+    next_handle: i32,
+    /// Handles of the running jobs, in the order they were spawned
+    jobs: Vec<(i32, i32)>,
+    /// Steps of the running jobs, by handle
+    steps: CallbackRegistry<i32, JobFunc>,
+}
+
+impl Jobs {
+    pub(crate) fn new() -> Self {
+        Jobs {
+            next_handle: 1,
+            jobs: Vec::new(),
+            steps: CallbackRegistry::new(),
+        }
+    }
+
+    fn spawn(&mut self, step: Callback<JobFunc>, data: i32) -> i32 {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        self.jobs.push((handle, data));
+        self.steps.insert(handle, step);
+
+        handle
+    }
+
+    fn cancel(&mut self, handle: i32) -> bool {
+        self.steps.remove(&handle);
+
+        let len = self.jobs.len();
+        self.jobs.retain(|(job, _)| *job != handle);
+        self.jobs.len() != len
+    }
+}
+
+/// Run one step of each job of `module`, with the fuel left to it for this frame
+///
+/// The jobs spawned by the steps first run on the next frame
+pub(crate) fn run_jobs(module: &Module) {
+    let mut lock = module.lock().unwrap();
+
+    let jobs = lock.environment.jobs.jobs.clone();
+    for (handle, data) in jobs {
+        if lock.is_out_of_fuel() {
+            break;
+        }
+
+        // The job may have been cancelled by a previous step
+        let step = match lock.environment.jobs.steps.get(&handle) {
+            Some(step) => step,
+            None => continue,
+        };
+
+        let more = call_guest(&mut lock, "job", |ctx| step(ctx, handle, data));
+        match more {
+            // An aborted step runs again on the next frame
+            Some(_) if lock.is_out_of_fuel() => break,
+            Some(0) | None => {
+                lock.environment.jobs.cancel(handle);
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Fuel below which the jobs of the module `name` are asked to yield
+fn yield_reserve(name: &str) -> isize {
+    let config = config::get();
+    match config.runtime.yield_reserve {
+        0 => (config.budget(name) / 4) as isize,
+        reserve => reserve as isize,
+    }
+}
+
+with_abi! {
+    fn spawn(ctx: *mut VMContext<FabricEnv>, step: FuncRef, data: i32) -> i32 {
+        debug!("Job::spawn({:?}, {:?}, {})", ctx, step, data);
+
+        let ctx = unsafe { &mut *ctx };
+
+        let step = match Callback::resolve(ctx, step) {
+            Ok(step) => step,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", step, err);
+                return 0;
+            }
+        };
+
+        ctx.environment.jobs.spawn(step, data)
+    }
+}
+
+with_abi! {
+    fn cancel(ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if ctx.environment.jobs.cancel(handle) {
+            1
+        } else {
+            warn!("unknown job {}", handle);
+            0
+        }
+    }
+}
+
+with_abi! {
+    fn should_yield(ctx: *mut VMContext<FabricEnv>) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // Modules without a budget run their jobs to completion
+        if config::get().budget(&ctx.environment.name) == 0 {
+            return 0;
+        }
+
+        (ctx.fuel() < yield_reserve(&ctx.environment.name)) as i32
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    config,
+    module::{names, store_truncated, FabricEnv, Module},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::kv::SET => Some(Function::new(
+            set as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::kv::GET => Some(Function::new(
+            get as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::kv::DELETE => Some(Function::new(
+            delete as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Key-value store of a module
+///
+/// The store is persisted as a JSON object in the `data` directory of the
+/// addon, with the values encoded in base64. It is loaded the first time the
+/// module accesses it, and only written back to disk when flushed
+///
+/// A store whose file can't be parsed is read-only, so the values it still
+/// holds aren't overwritten before someone looks at the file
+pub(crate) struct Store {
+    values: Option<BTreeMap<String, String>>,
+    dirty: bool,
+    read_only: bool,
+}
+
+impl Store {
+    pub(crate) fn new() -> Self {
+        Store {
+            values: None,
+            dirty: false,
+            read_only: false,
+        }
+    }
+
+    fn path(module: &str) -> PathBuf {
+        config::get()
+            .root()
+            .join("data")
+            .join(format!("{}.kv.json", module))
+    }
+
+    fn values(&mut self, module: &str) -> &mut BTreeMap<String, String> {
+        if self.values.is_none() {
+            let path = Store::path(module);
+            let values = load(&path).unwrap_or_else(|err| {
+                warn!(
+                    "could not parse {}, the store is read-only: {}",
+                    path.display(),
+                    err
+                );
+                self.read_only = true;
+                BTreeMap::new()
+            });
+
+            self.values = Some(values);
+        }
+
+        self.values.get_or_insert_with(BTreeMap::new)
+    }
+
+    /// The values of the store to modify them, None if the store is read-only
+    fn values_mut(&mut self, module: &str) -> Option<&mut BTreeMap<String, String>> {
+        self.values(module);
+        if self.read_only {
+            warn!("the key-value store of {} is read-only", module);
+            return None;
+        }
+
+        self.values.as_mut()
+    }
+
+    /// Write the store to disk if it was modified since the last flush
+    fn flush(&mut self, module: &str) {
+        let values = match &self.values {
+            Some(values) if self.dirty => values,
+            _ => return,
+        };
+
+        let path = Store::path(module);
+        if let Some(dir) = path.parent() {
+            if let Err(err) = fs::create_dir_all(dir) {
+                warn!("could not create {}: {}", dir.display(), err);
+                return;
+            }
+        }
+
+        let result = serde_json::to_vec_pretty(values)
+            .map_err(|err| err.to_string())
+            .and_then(|data| save(&path, &data).map_err(|err| err.to_string()));
+
+        match result {
+            Ok(()) => {
+                debug!("flushed {}", path.display());
+                self.dirty = false;
+            }
+            Err(err) => warn!("could not write {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Read the values of the store at `path`, a missing file is an empty store
+fn load(path: &Path) -> Result<BTreeMap<String, String>, serde_json::Error> {
+    match fs::read(path) {
+        Ok(source) => serde_json::from_slice(&source),
+        Err(err) => {
+            debug!("could not read {}: {}", path.display(), err);
+            Ok(BTreeMap::new())
+        }
+    }
+}
+
+/// Replace the store at `path` with `data`, through a temporary file
+/// so a failed write leaves the previous version of the store intact
+fn save(path: &Path, data: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path)
+}
+
+/// Persist the key-value store of `module` to disk
+pub(crate) fn flush(module: &Module) {
+    let mut lock = module.lock().unwrap();
+    let env = &mut lock.environment;
+    env.store.flush(&env.name);
+}
+
+fn load_key(ctx: &VMContext<FabricEnv>, key: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(key as usize) {
+        Ok(key) => Some(key.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load key string at {}", key);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn set(ctx: *mut VMContext<FabricEnv>, key: i32, value: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_key(ctx, key) {
+            Some(key) => key,
+            None => return 0,
+        };
+
+        let value = match ctx.memory.bytes(value as usize, len as usize) {
+            Ok(value) if len >= 0 => base64::encode(value),
+            _ => {
+                warn!("could not load value at {}+{}", value, len);
+                return 0;
+            }
+        };
+
+        let env = &mut ctx.environment;
+        match env.store.values_mut(&env.name) {
+            Some(values) => {
+                values.insert(key, value);
+                env.store.dirty = true;
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn get(ctx: *mut VMContext<FabricEnv>, key: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_key(ctx, key) {
+            Some(key) => key,
+            None => return -1,
+        };
+
+        let env = &mut ctx.environment;
+        let value = match env.store.values(&env.name).get(&key) {
+            Some(value) => value,
+            None => return -1,
+        };
+
+        let value = match base64::decode(value) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("invalid value for key {:?}: {}", key, err);
+                return -1;
+            }
+        };
+
+        store_truncated(&mut ctx.memory, &value, buffer, len, "value")
+    }
+}
+
+with_abi! {
+    fn delete(ctx: *mut VMContext<FabricEnv>, key: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_key(ctx, key) {
+            Some(key) => key,
+            None => return 0,
+        };
+
+        let env = &mut ctx.environment;
+        let removed = match env.store.values_mut(&env.name) {
+            Some(values) => values.remove(&key).is_some(),
+            None => false,
+        };
+
+        if removed {
+            env.store.dirty = true;
+            1
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_replaces_the_store() {
+        let path = std::env::temp_dir().join("fabric-kv-save-test.kv.json");
+        save(&path, b"{\"a\": \"MQ==\"}").unwrap();
+        save(&path, b"{\"b\": \"Mg==\"}").unwrap();
+
+        let values = load(&path).unwrap();
+        assert_eq!(values.keys().collect::<Vec<_>>(), ["b"]);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_store_is_an_error() {
+        let path = std::env::temp_dir().join("fabric-kv-truncated-test.kv.json");
+        fs::write(&path, b"{\"a\": \"MQ").unwrap();
+
+        assert!(load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use std::{collections::HashMap, ffi::CStr, fs, path::PathBuf};
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    config,
+    engine::{engine, VEngineServer},
+    host::globals::globals,
+    keyvalues::{parse_keyvalues, KeyValue},
+    module::{names, store_truncated, FabricEnv},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::lang::FORMAT => Some(Function::new(
+            format as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Directory of the phrase files, relative to the addon directory
+const PHRASES_DIR: &str = "phrases";
+
+/// Language the phrases are translated to for the server console, and
+/// for the clients whose language has no translation of a phrase
+const DEFAULT_LANGUAGE: &str = "english";
+
+/// Translated phrases of a module, keyed by phrase then by language
+///
+/// The phrases are read from `<module>.phrases` in the phrases directory of
+/// the addon, a KeyValues file with a section for each phrase holding one
+/// translation for each value of the `cl_language` client variable:
+///
+/// ```text
+/// "Phrases"
+/// {
+///     "welcome"
+///     {
+///         "english"   "Welcome {1}!"
+///         "french"    "Bienvenue {1} !"
+///     }
+/// }
+/// ```
+///
+/// The file is loaded the first time the module formats a phrase
+pub(crate) struct Phrases {
+    phrases: Option<HashMap<String, HashMap<String, String>>>,
+}
+
+impl Phrases {
+    pub(crate) fn new() -> Self {
+        Phrases { phrases: None }
+    }
+
+    fn path(module: &str) -> PathBuf {
+        config::get()
+            .root()
+            .join(PHRASES_DIR)
+            .join(format!("{}.phrases", module))
+    }
+
+    fn phrases(&mut self, module: &str) -> &HashMap<String, HashMap<String, String>> {
+        self.phrases.get_or_insert_with(|| {
+            let path = Phrases::path(module);
+            let source = match fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(err) => {
+                    debug!("could not read {}: {}", path.display(), err);
+                    return HashMap::new();
+                }
+            };
+
+            match parse_keyvalues(&source) {
+                Ok(root) => read_phrases(root),
+                Err(err) => {
+                    warn!("could not parse {}: {}", path.display(), err);
+                    HashMap::new()
+                }
+            }
+        })
+    }
+
+    /// Translation of `phrase` in `language`, or in the default language
+    fn translate(&mut self, module: &str, phrase: &str, language: &str) -> Option<&str> {
+        let translations = self.phrases(module).get(phrase)?;
+        translations
+            .get(language)
+            .or_else(|| translations.get(DEFAULT_LANGUAGE))
+            .map(String::as_str)
+    }
+}
+
+fn read_phrases(root: Vec<(String, KeyValue)>) -> HashMap<String, HashMap<String, String>> {
+    let mut phrases = HashMap::new();
+
+    // The phrases are all in a single root section, whatever its name
+    for (_, section) in root {
+        let section = match section {
+            KeyValue::Section(section) => section,
+            KeyValue::Value(_) => continue,
+        };
+
+        for (phrase, translations) in section {
+            let translations = match translations {
+                KeyValue::Section(translations) => translations,
+                KeyValue::Value(_) => {
+                    warn!("phrase {:?} has no translations", phrase);
+                    continue;
+                }
+            };
+
+            let translations = translations
+                .into_iter()
+                .filter_map(|(language, text)| match text {
+                    KeyValue::Value(text) => Some((language.to_ascii_lowercase(), text)),
+                    KeyValue::Section(_) => None,
+                })
+                .collect();
+
+            phrases.insert(phrase, translations);
+        }
+    }
+
+    phrases
+}
+
+/// Language of the client at `client`, from its `cl_language` variable
+fn client_language(client: i32) -> String {
+    let max_clients = globals().map_or(0, |globals| globals.max_clients());
+    if client < 1 || client > max_clients {
+        return DEFAULT_LANGUAGE.into();
+    }
+
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return DEFAULT_LANGUAGE.into(),
+    };
+
+    let value = engine.get_client_con_var_value(client, cstr!("cl_language"));
+    if value.is_null() {
+        return DEFAULT_LANGUAGE.into();
+    }
+
+    let value = unsafe { CStr::from_ptr(value) }.to_string_lossy();
+    if value.is_empty() {
+        DEFAULT_LANGUAGE.into()
+    } else {
+        value.to_ascii_lowercase()
+    }
+}
+
+/// Replace the `{1}` to `{9}` placeholders of `text` with `args`,
+/// the placeholders without a matching argument are kept as is
+fn substitute(text: &str, args: &[&str]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let arg = rest
+            .get(1..3)
+            .filter(|placeholder| placeholder.ends_with('}'))
+            .and_then(|placeholder| placeholder[..1].parse::<usize>().ok())
+            .and_then(|index| args.get(index.checked_sub(1)?));
+
+        match arg {
+            Some(arg) => {
+                result.push_str(arg);
+                rest = &rest[3..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+with_abi! {
+    fn format(
+        ctx: *mut VMContext<FabricEnv>,
+        client: i32,
+        phrase: i32,
+        args: i32,
+        args_len: i32,
+        buffer: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let phrase = match ctx.memory.load::<CStr>(phrase as usize) {
+            Ok(phrase) => phrase.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load phrase string at {}", phrase);
+                return -1;
+            }
+        };
+
+        // The arguments are a sequence of null-terminated strings
+        let args = match ctx.memory.bytes(args as usize, args_len.max(0) as usize) {
+            Ok(args) => String::from_utf8_lossy(args).into_owned(),
+            Err(()) => {
+                warn!("could not load arguments at {}+{}", args, args_len);
+                return -1;
+            }
+        };
+
+        let args: Vec<_> = args.split_terminator('\0').collect();
+
+        let language = client_language(client);
+        let env = &mut ctx.environment;
+        let text = match env.phrases.translate(&env.name, &phrase, &language) {
+            Some(text) => substitute(text, &args),
+            None => {
+                warn!("phrase {:?} has no translation in {}", phrase, language);
+                return -1;
+            }
+        };
+
+        store_truncated(&mut ctx.memory, text.as_bytes(), buffer, len, "text")
+    }
+}
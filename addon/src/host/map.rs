@@ -0,0 +1,205 @@
+use std::{
+    ffi::{CStr, CString},
+    ptr::null,
+    time::{Duration, Instant},
+};
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{info, warn};
+
+use crate::{
+    command,
+    engine::{engine, VEngineServer},
+    module::{names, store_truncated, FabricEnv},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::map::CHANGE => Some(Function::new(
+            change as with_abi!(fn(*mut VMContext<FabricEnv>, i32, f32) -> i32),
+        )),
+        names::map::CANCEL_CHANGE => Some(Function::new(
+            cancel_change as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::map::IS_VALID => Some(Function::new(
+            is_valid as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::map::SET_NEXT => Some(Function::new(
+            set_next as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::map::NEXT => Some(Function::new(
+            next as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Maximum delay of a level change in seconds
+const MAX_DELAY: f32 = 24.0 * 60.0 * 60.0;
+
+/// Level change requested by a module
+struct PendingChange {
+    map: CString,
+    at: Instant,
+    module: String,
+}
+
+/// Level change to run once its delay elapsed, a new
+/// request replaces it. Only accessed from the game thread
+static mut PENDING: Option<PendingChange> = None;
+
+/// Next map chosen by the modules for the current map
+static mut NEXT_MAP: Option<CString> = None;
+
+/// Change the level once the delay of the pending change elapsed
+///
+/// The level is never changed from a module callback, the
+/// changes without delay happen on the next frame
+pub(crate) fn run(now: Instant) {
+    let is_due = unsafe { PENDING.as_ref() }.is_some_and(|change| change.at <= now);
+    if !is_due {
+        return;
+    }
+
+    let change = match unsafe { PENDING.take() } {
+        Some(change) => change,
+        None => return,
+    };
+
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return,
+    };
+
+    info!(
+        "changing level to {} for module {}",
+        change.map.to_string_lossy(),
+        change.module
+    );
+    engine.change_level(&change.map, null());
+}
+
+/// Forget the pending change and the next map of the previous map
+pub(crate) fn level_init() {
+    unsafe {
+        PENDING = None;
+        NEXT_MAP = None;
+    }
+}
+
+/// Whether `map` is a map the server can load
+fn is_map_valid(map: &CStr) -> bool {
+    // The map name is also passed in console commands
+    let is_name = map
+        .to_bytes()
+        .iter()
+        .all(|byte| byte.is_ascii_graphic() && !b"\";'".contains(byte));
+    if map.to_bytes().is_empty() || !is_name {
+        return false;
+    }
+
+    engine().is_some_and(|mut engine| engine.is_map_valid(map) != 0)
+}
+
+fn load_map(ctx: &VMContext<FabricEnv>, map: i32) -> Option<CString> {
+    match ctx.memory.load::<CStr>(map as usize) {
+        Ok(map) => Some(map.to_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", map);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn change(ctx: *mut VMContext<FabricEnv>, map: i32, delay: f32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let map = match load_map(ctx, map) {
+            Some(map) => map,
+            None => return 0,
+        };
+
+        if !is_map_valid(&map) {
+            warn!("invalid map {}", map.to_string_lossy());
+            return 0;
+        }
+
+        // A change isn't kept across maps, longer delays never elapse
+        let delay = if delay.is_finite() {
+            delay.max(0.0).min(MAX_DELAY)
+        } else {
+            0.0
+        };
+        unsafe {
+            PENDING = Some(PendingChange {
+                map,
+                at: Instant::now() + Duration::from_secs_f32(delay),
+                module: ctx.environment.name.clone(),
+            });
+        }
+
+        1
+    }
+}
+
+with_abi! {
+    fn cancel_change(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        unsafe { PENDING.take() }.is_some() as i32
+    }
+}
+
+with_abi! {
+    fn is_valid(ctx: *mut VMContext<FabricEnv>, map: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        load_map(ctx, map).is_some_and(|map| is_map_valid(&map)) as i32
+    }
+}
+
+with_abi! {
+    fn set_next(ctx: *mut VMContext<FabricEnv>, map: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let map = match load_map(ctx, map) {
+            Some(map) => map,
+            None => return 0,
+        };
+
+        if !is_map_valid(&map) {
+            warn!("invalid map {}", map.to_string_lossy());
+            return 0;
+        }
+
+        // The games with a `nextlevel` variable change to it at the end of the map
+        if command::variable_value(cstr!("nextlevel")).is_some() {
+            let command = format!("nextlevel {}\n", map.to_string_lossy());
+            if let (Some(mut engine), Ok(command)) = (engine(), CString::new(command)) {
+                engine.server_command(&command);
+            }
+        }
+
+        unsafe {
+            NEXT_MAP = Some(map);
+        }
+
+        1
+    }
+}
+
+with_abi! {
+    fn next(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // Without a choice of the modules, the next map is the one of the game
+        let map = match unsafe { NEXT_MAP.as_ref() } {
+            Some(map) => map.to_string_lossy().into_owned(),
+            None => match command::variable_value(cstr!("nextlevel")) {
+                Some(map) if !map.is_empty() => map,
+                _ => return -1,
+            },
+        };
+
+        store_truncated(&mut ctx.memory, map.as_bytes(), buffer, len, "map")
+    }
+}
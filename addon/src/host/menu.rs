@@ -0,0 +1,574 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    time::{Duration, Instant},
+};
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    addon::Edict,
+    host::usermessage::recipients,
+    message::send,
+    module::{dispatch_guest, find_module, names, FabricEnv, Module},
+};
+
+/// Callback invoked with the handle of the menu, the client and the
+/// index of the selected item, or one of the negative `MENU_*` reasons
+pub(crate) type MenuFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32));
+
+/// The client closed the menu with the exit key
+const MENU_EXIT: i32 = -1;
+/// The menu was displayed for longer than its display time
+const MENU_TIMEOUT: i32 = -2;
+/// The menu was replaced by another menu or the client disconnected
+const MENU_INTERRUPTED: i32 = -3;
+
+/// Number of items on each page, the keys 8, 9 and 0
+/// are used for the previous, next and exit entries
+const ITEMS_PER_PAGE: usize = 7;
+/// Maximum length of the text sent in a single `ShowMenu` message,
+/// longer menus are split across several messages
+const MAX_CHUNK_LEN: usize = 240;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::menu::CREATE => Some(Function::new(
+            create as with_abi!(fn(*mut VMContext<FabricEnv>, i32, FuncRef) -> i32),
+        )),
+        names::menu::ADD_ITEM => Some(Function::new(
+            add_item as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::menu::DISPLAY => Some(Function::new(
+            display as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32) -> i32),
+        )),
+        names::menu::DESTROY => Some(Function::new(
+            destroy as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+struct Menu {
+    title: String,
+    items: Vec<String>,
+    callback: MenuFunc,
+}
+
+impl Menu {
+    fn pages(&self) -> usize {
+        pages(&self.items)
+    }
+}
+
+fn pages(items: &[String]) -> usize {
+    ((items.len() + ITEMS_PER_PAGE - 1) / ITEMS_PER_PAGE).max(1)
+}
+
+/// Render `page` of a menu, returning its text and the bitmask of the
+/// valid keys, with bit 0 for the key 1 up to bit 9 for the key 0
+fn render(title: &str, items: &[String], page: usize) -> (String, u16) {
+    let mut text = format!("{}\n \n", title);
+    let mut keys = 0;
+
+    let start = page * ITEMS_PER_PAGE;
+    let page_items = items.iter().skip(start).take(ITEMS_PER_PAGE);
+    for (slot, item) in page_items.enumerate() {
+        text.push_str(&format!("{}. {}\n", slot + 1, item));
+        keys |= 1 << slot;
+    }
+
+    text.push_str(" \n");
+
+    if page > 0 {
+        text.push_str("8. Back\n");
+        keys |= 1 << 7;
+    }
+
+    if page + 1 < pages(items) {
+        text.push_str("9. Next\n");
+        keys |= 1 << 8;
+    }
+
+    text.push_str("0. Exit");
+    keys |= 1 << 9;
+
+    (text, keys)
+}
+
+/// Menus created by a module
+pub(crate) struct Menus {
+    next_handle: i32,
+    menus: HashMap<i32, Menu>,
+}
+
+impl Menus {
+    pub(crate) fn new() -> Self {
+        Menus {
+            next_handle: 1,
+            menus: HashMap::new(),
+        }
+    }
+}
+
+/// What a menu displayed to a client was created for
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    /// A menu created by the module
+    Menu(i32),
+    /// The ballot of a vote started by the module
+    Vote(i32),
+}
+
+/// Menu currently displayed to a client
+struct Active {
+    client: i32,
+    /// Name of the module that created the menu
+    module: String,
+    owner: Owner,
+    page: usize,
+    deadline: Option<Instant>,
+}
+
+/// Callback call deferred to the next frame, for menus closed
+/// from a host function while their module may be running
+struct Notification {
+    module: String,
+    menu: i32,
+    client: i32,
+    reason: i32,
+}
+
+/// Menus displayed to the clients and pending notifications,
+/// these are only accessed from the game thread
+static mut ACTIVE: Vec<Active> = Vec::new();
+static mut NOTIFICATIONS: Vec<Notification> = Vec::new();
+
+/// Send the `ShowMenu` user message displaying `page` of `menu` to `client`
+fn show(client: i32, menu: &Menu, page: usize) -> bool {
+    let (text, keys) = render(&menu.title, &menu.items, page);
+    show_text(client, &text, keys)
+}
+
+fn show_text(client: i32, text: &str, keys: u16) -> bool {
+    // Split the text on character boundaries, every chunk
+    // but the last one tells the client more text follows
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > MAX_CHUNK_LEN {
+        let mut end = MAX_CHUNK_LEN;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        chunks.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    chunks.push(rest);
+
+    let count = chunks.len();
+    chunks.into_iter().enumerate().all(|(index, chunk)| {
+        let chunk = match CString::new(chunk) {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                warn!("invalid menu text: {}", err);
+                return false;
+            }
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return false,
+        };
+
+        send(recipients, cstr!("ShowMenu"), |writer| {
+            writer.write_ubits(keys.into(), 16);
+            // Displayed until closed, the timeouts are handled by the addon
+            writer.write_byte(-1i8 as u8);
+            writer.write_bit(index + 1 < count);
+            writer.write_string(&chunk);
+        })
+    })
+}
+
+/// Close the menu displayed to `client` by sending it an empty menu
+fn hide(client: i32) {
+    show_text(client, "", 0);
+}
+
+/// Stop displaying the menu of `client`, returning it if there was one
+fn take_active(client: i32) -> Option<Active> {
+    let active = unsafe { &mut ACTIVE };
+    let index = active.iter().position(|active| active.client == client)?;
+    Some(active.remove(index))
+}
+
+/// Notify the module that its menu was closed, the ballots of the
+/// votes are tracked by the vote itself and aren't notified
+fn notify(active: Active, reason: i32) {
+    let menu = match active.owner {
+        Owner::Menu(menu) => menu,
+        Owner::Vote(_) => return,
+    };
+
+    unsafe {
+        NOTIFICATIONS.push(Notification {
+            module: active.module,
+            menu,
+            client: active.client,
+            reason,
+        });
+    }
+}
+
+/// Call the callback of `menu` in `module` with `client` and `item`
+fn call_menu(module: &Module, menu: i32, client: i32, item: i32) {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping menu callback for {}", client);
+            return;
+        }
+    };
+
+    let callback = match lock.environment.menus.menus.get(&menu) {
+        Some(menu) => menu.callback,
+        None => return,
+    };
+
+    dispatch_guest(&mut lock, "menu", move |ctx| {
+        callback(ctx, menu, client, item)
+    });
+}
+
+/// Handle the `menuselect` command sent by the client at `entity` when it
+/// presses a key, returns false if the client has no menu displayed
+pub(crate) fn select(modules: &[Module], entity: *const Edict, key: i32) -> bool {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return false,
+    };
+
+    let mut active = match take_active(client) {
+        Some(active) => active,
+        None => return false,
+    };
+
+    let handle = match active.owner {
+        Owner::Menu(menu) => menu,
+        Owner::Vote(vote) => {
+            // The exit key abstains from the vote
+            let option = if key > 0 {
+                Some(key as usize - 1)
+            } else {
+                None
+            };
+            if !crate::host::vote::cast(vote, client, option) {
+                unsafe { ACTIVE.push(active) };
+            }
+
+            return true;
+        }
+    };
+
+    let module = match find_module(modules, &active.module) {
+        Some(module) => module,
+        None => return true,
+    };
+
+    let lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => return true,
+    };
+
+    let menu = match lock.environment.menus.menus.get(&handle) {
+        Some(menu) => menu,
+        None => return true,
+    };
+
+    let item = match key {
+        1..=7 => {
+            let item = active.page * ITEMS_PER_PAGE + (key as usize - 1);
+            if item >= menu.items.len() {
+                unsafe { ACTIVE.push(active) };
+                return true;
+            }
+
+            item as i32
+        }
+        8 | 9 => {
+            let page = if key == 8 {
+                active.page.saturating_sub(1)
+            } else {
+                (active.page + 1).min(menu.pages() - 1)
+            };
+
+            if show(client, menu, page) {
+                active.page = page;
+                unsafe { ACTIVE.push(active) };
+            }
+
+            return true;
+        }
+        0 => MENU_EXIT,
+        _ => {
+            unsafe { ACTIVE.push(active) };
+            return true;
+        }
+    };
+
+    drop(lock);
+    call_menu(module, handle, client, item);
+    true
+}
+
+/// Stop displaying the menu of a disconnecting client
+pub(crate) fn client_disconnect(entity: *const Edict) {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return,
+    };
+
+    if let Some(active) = take_active(client) {
+        notify(active, MENU_INTERRUPTED);
+    }
+}
+
+/// Close the menus whose display time elapsed at `now`, then deliver
+/// the notifications of the menus closed since the last frame
+pub(crate) fn run(modules: &[Module], now: Instant) {
+    let active = unsafe { &mut ACTIVE };
+    let (expired, remaining): (Vec<_>, Vec<_>) = std::mem::take(active)
+        .into_iter()
+        .partition(|active| active.deadline.is_some_and(|deadline| deadline <= now));
+
+    *active = remaining;
+
+    for active in expired {
+        hide(active.client);
+        notify(active, MENU_TIMEOUT);
+    }
+
+    let notifications = unsafe { std::mem::take(&mut NOTIFICATIONS) };
+    for notification in notifications {
+        if let Some(module) = find_module(modules, &notification.module) {
+            call_menu(
+                module,
+                notification.menu,
+                notification.client,
+                notification.reason,
+            );
+        }
+    }
+}
+
+/// Display the ballot of `vote`, started by `module`, to `client`
+///
+/// The ballot has a single page so it can't hold more than `ITEMS_PER_PAGE`
+/// options, the key pressed by the client is forwarded to the vote
+pub(crate) fn show_ballot(
+    client: i32,
+    module: &str,
+    vote: i32,
+    question: &str,
+    options: &[String],
+) -> bool {
+    let (text, keys) = render(question, &options[..options.len().min(ITEMS_PER_PAGE)], 0);
+    if !show_text(client, &text, keys) {
+        return false;
+    }
+
+    if let Some(previous) = take_active(client) {
+        notify(previous, MENU_INTERRUPTED);
+    }
+
+    unsafe {
+        ACTIVE.push(Active {
+            client,
+            module: module.into(),
+            owner: Owner::Vote(vote),
+            page: 0,
+            deadline: None,
+        });
+    }
+
+    true
+}
+
+/// Close the ballots of `vote` still displayed to the clients
+pub(crate) fn close_ballots(vote: i32) {
+    let active = unsafe { &mut ACTIVE };
+    active.retain(|active| {
+        let is_ballot = active.owner == Owner::Vote(vote);
+        if is_ballot {
+            hide(active.client);
+        }
+
+        !is_ballot
+    });
+}
+
+/// Close the menus of the module named `module` and drop its pending
+/// notifications, the handles of its menus are reused by a reloaded instance
+pub(crate) fn forget(module: &str) {
+    let active = unsafe { &mut ACTIVE };
+    active.retain(|active| {
+        let is_owned = active.module == module;
+        if is_owned {
+            hide(active.client);
+        }
+
+        !is_owned
+    });
+
+    unsafe { &mut NOTIFICATIONS }.retain(|notification| notification.module != module);
+}
+
+/// Drop all the displayed menus and pending notifications
+pub(crate) fn clear() {
+    unsafe {
+        ACTIVE.clear();
+        NOTIFICATIONS.clear();
+    }
+}
+
+fn load_text(ctx: &VMContext<FabricEnv>, text: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(text as usize) {
+        Ok(text) => Some(text.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", text);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut VMContext<FabricEnv>, title: i32, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback: MenuFunc = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        let title = match load_text(ctx, title) {
+            Some(title) => title,
+            None => return 0,
+        };
+
+        let menus = &mut ctx.environment.menus;
+        let handle = menus.next_handle;
+        menus.next_handle = menus.next_handle.wrapping_add(1).max(1);
+
+        menus.menus.insert(
+            handle,
+            Menu {
+                title,
+                items: Vec::new(),
+                callback,
+            },
+        );
+
+        handle
+    }
+}
+
+with_abi! {
+    fn add_item(ctx: *mut VMContext<FabricEnv>, menu: i32, text: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let text = match load_text(ctx, text) {
+            Some(text) => text,
+            None => return -1,
+        };
+
+        match ctx.environment.menus.menus.get_mut(&menu) {
+            Some(menu) => {
+                menu.items.push(text);
+                menu.items.len() as i32 - 1
+            }
+            None => {
+                warn!("unknown menu {}", menu);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn display(ctx: *mut VMContext<FabricEnv>, menu: i32, client: i32, time: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let env = &ctx.environment;
+        let handle = menu;
+        let menu = match env.menus.menus.get(&menu) {
+            Some(menu) => menu,
+            None => {
+                warn!("unknown menu {}", menu);
+                return 0;
+            }
+        };
+
+        if client <= 0 {
+            warn!("menus can only be displayed to a single client, not {}", client);
+            return 0;
+        }
+
+        if !show(client, menu, 0) {
+            return 0;
+        }
+
+        if let Some(previous) = take_active(client) {
+            notify(previous, MENU_INTERRUPTED);
+        }
+
+        let deadline = if time > 0 {
+            Some(Instant::now() + Duration::from_secs(time as u64))
+        } else {
+            None
+        };
+
+        unsafe {
+            ACTIVE.push(Active {
+                client,
+                module: env.name.clone(),
+                owner: Owner::Menu(handle),
+                page: 0,
+                deadline,
+            });
+        }
+
+        1
+    }
+}
+
+with_abi! {
+    fn destroy(ctx: *mut VMContext<FabricEnv>, menu: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if ctx.environment.menus.menus.remove(&menu).is_none() {
+            warn!("unknown menu {}", menu);
+            return 0;
+        }
+
+        // Close the menu for the clients it is displayed to, their
+        // callback can't be called anymore so they aren't notified
+        let name = &ctx.environment.name;
+        let active = unsafe { &mut ACTIVE };
+        active.retain(|active| {
+            let is_destroyed = active.module == *name && active.owner == Owner::Menu(menu);
+            if is_destroyed {
+                hide(active.client);
+            }
+
+            !is_destroyed
+        });
+
+        1
+    }
+}
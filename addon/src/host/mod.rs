@@ -0,0 +1,32 @@
+//! Host modules importable by the WASM guests, in addition to the
+//! `GameEvent` / `GameEventsManager` / `LoggingSystem` base modules
+//! implemented in `crate::module`
+
+pub(crate) mod bitbuf;
+pub(crate) mod bot;
+pub(crate) mod bus;
+pub(crate) mod client;
+pub(crate) mod command;
+pub(crate) mod config;
+pub(crate) mod db;
+pub(crate) mod edict;
+pub(crate) mod effects;
+pub(crate) mod entity;
+pub(crate) mod fs;
+pub(crate) mod globals;
+pub(crate) mod http;
+pub(crate) mod job;
+pub(crate) mod kv;
+pub(crate) mod lang;
+pub(crate) mod map;
+pub(crate) mod menu;
+pub(crate) mod random;
+pub(crate) mod server;
+pub(crate) mod shared;
+pub(crate) mod sound;
+pub(crate) mod state;
+pub(crate) mod target;
+pub(crate) mod timer;
+pub(crate) mod trace;
+pub(crate) mod usermessage;
+pub(crate) mod vote;
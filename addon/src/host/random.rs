@@ -0,0 +1,63 @@
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::warn;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::{config, module::names, module::FabricEnv};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::random::I32 => Some(Function::new(
+            random_i32 as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::random::F32 => Some(Function::new(
+            random_f32 as with_abi!(fn(*mut VMContext<FabricEnv>) -> f32),
+        )),
+        names::random::SEED => Some(Function::new(
+            seed as with_abi!(fn(*mut VMContext<FabricEnv>, i64)),
+        )),
+        _ => None,
+    }
+}
+
+/// Create the random number generator for a module
+///
+/// In deterministic mode all the modules start from the seed set in the
+/// configuration, otherwise the generator is seeded from the OS entropy
+pub(crate) fn create_rng() -> Pcg32 {
+    let config = &config::get().runtime;
+    if config.deterministic {
+        Pcg32::seed_from_u64(config.seed)
+    } else {
+        Pcg32::from_entropy()
+    }
+}
+
+with_abi! {
+    fn random_i32(ctx: *mut VMContext<FabricEnv>, min: i32, max: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // Both bounds are inclusive, like the engine's RandomInt
+        if min > max {
+            warn!("invalid random range {}..={}", min, max);
+            return min;
+        }
+
+        let rng = &mut ctx.environment.rng;
+        rng.gen_range(i64::from(min), i64::from(max) + 1) as i32
+    }
+}
+
+with_abi! {
+    fn random_f32(ctx: *mut VMContext<FabricEnv>) -> f32 {
+        let ctx = unsafe { &mut *ctx };
+        ctx.environment.rng.gen()
+    }
+}
+
+with_abi! {
+    fn seed(ctx: *mut VMContext<FabricEnv>, seed: i64) {
+        let ctx = unsafe { &mut *ctx };
+        ctx.environment.rng = Pcg32::seed_from_u64(seed as u64);
+    }
+}
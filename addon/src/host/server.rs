@@ -0,0 +1,165 @@
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    bans, command, config,
+    deferred::Capability,
+    entity::connected_clients,
+    host::globals::globals,
+    module::{dispatch_guest, names, store_truncated, FabricEnv, Module},
+};
+
+pub(crate) type CapabilityFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32));
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::server::HOSTNAME => Some(Function::new(
+            hostname as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::server::MAP => Some(Function::new(
+            map as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::server::ADDRESS => Some(Function::new(
+            address as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::server::PORT => Some(Function::new(
+            port as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::server::PLAYER_COUNT => Some(Function::new(
+            player_count as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::server::BOT_COUNT => Some(Function::new(
+            bot_count as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        names::server::TICK_INTERVAL => Some(Function::new(
+            tick_interval as with_abi!(fn(*mut VMContext<FabricEnv>) -> f32),
+        )),
+        names::server::HAS_CAPABILITY => Some(Function::new(
+            has_capability as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::server::ON_CAPABILITY => Some(Function::new(
+            on_capability as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Copy `value` to `buffer` truncated to `len` bytes, returning its full
+/// length, or -1 if there is no value or it can't be stored
+fn store_value(ctx: &mut VMContext<FabricEnv>, value: Option<&str>, buffer: i32, len: i32) -> i32 {
+    let value = match value {
+        Some(value) => value.as_bytes(),
+        None => return -1,
+    };
+
+    store_truncated(&mut ctx.memory, value, buffer, len, "value")
+}
+
+/// Connected clients whose network ID is the one of the bots
+fn bots(clients: &[i32]) -> usize {
+    clients
+        .iter()
+        .filter(|client| bans::steam_id(**client).as_deref() == Some("BOT"))
+        .count()
+}
+
+with_abi! {
+    fn hostname(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        let hostname = command::variable_value(cstr!("hostname"));
+        store_value(ctx, hostname.as_deref(), buffer, len)
+    }
+}
+
+with_abi! {
+    fn map(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        store_value(ctx, config::map(), buffer, len)
+    }
+}
+
+with_abi! {
+    fn address(ctx: *mut VMContext<FabricEnv>, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // The address the server is bound to, the public address
+        // of the servers bound to all the interfaces is unknown
+        let address = command::variable_value(cstr!("ip"))
+            .filter(|address| !address.is_empty() && address != "0.0.0.0");
+        store_value(ctx, address.as_deref(), buffer, len)
+    }
+}
+
+with_abi! {
+    fn port(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        command::variable_value(cstr!("hostport"))
+            .and_then(|port| port.trim().parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+with_abi! {
+    fn player_count(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        let clients = connected_clients();
+        (clients.len() - bots(&clients)) as i32
+    }
+}
+
+with_abi! {
+    fn bot_count(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        bots(&connected_clients()) as i32
+    }
+}
+
+with_abi! {
+    fn tick_interval(_ctx: *mut VMContext<FabricEnv>) -> f32 {
+        globals().map_or(0.0, |globals| globals.interval_per_tick())
+    }
+}
+
+with_abi! {
+    fn has_capability(_ctx: *mut VMContext<FabricEnv>, capability: i32) -> i32 {
+        match Capability::from_id(capability) {
+            Some(capability) => capability.is_available() as i32,
+            None => {
+                warn!("unknown capability {}", capability);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn on_capability(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        ctx.environment.capability_available = Some(callback);
+        1
+    }
+}
+
+/// Notify `module` that `capability` became available, if it registered
+/// a callback. The module is skipped if it is running
+pub(crate) fn notify_capability(module: &Module, capability: Capability) {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping capability notification");
+            return;
+        }
+    };
+
+    if let Some(callback) = lock.environment.capability_available {
+        let id = capability.id();
+        dispatch_guest(&mut lock, "capability", move |ctx| callback(ctx, id));
+    }
+}
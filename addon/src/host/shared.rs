@@ -0,0 +1,214 @@
+use std::{collections::HashMap, ffi::CStr};
+
+use fabric_runtime::{with_abi, ExternRef, Function, VMContext};
+use log::warn;
+
+use crate::module::{get_extern, names, store_truncated, take_extern, FabricEnv};
+
+/// Maximum size of a value in bytes
+const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::shared::OPEN => Some(Function::new(
+            open as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> ExternRef),
+        )),
+        names::shared::CLOSE => Some(Function::new(
+            close as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef)),
+        )),
+        names::shared::GET => Some(Function::new(
+            get as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        names::shared::SET => Some(Function::new(
+            set as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        names::shared::DELETE => Some(Function::new(
+            delete as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+        )),
+        names::shared::COMPARE_AND_SWAP => Some(Function::new(
+            compare_and_swap
+                as with_abi!(
+                    fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32, i32, i32) -> i32
+                ),
+        )),
+        _ => None,
+    }
+}
+
+/// Handle to a namespace of the shared store, held by a module
+struct Namespace {
+    name: String,
+}
+
+/// Values of the shared store, by namespace then by key
+///
+/// The store is kept in memory for the lifetime of the addon and is
+/// only accessed from the game thread, where all the modules run
+static mut STORE: Option<HashMap<String, HashMap<String, Vec<u8>>>> = None;
+
+fn values(namespace: &str) -> &'static mut HashMap<String, Vec<u8>> {
+    let store = unsafe { STORE.get_or_insert_with(HashMap::new) };
+    store.entry(namespace.to_string()).or_default()
+}
+
+/// Drop all the values of the shared store
+pub(crate) fn clear() {
+    unsafe {
+        STORE = None;
+    }
+}
+
+fn load_string(ctx: &VMContext<FabricEnv>, ptr: i32) -> Option<String> {
+    match ctx.memory.load::<CStr>(ptr as usize) {
+        Ok(value) => Some(value.to_string_lossy().into_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", ptr);
+            None
+        }
+    }
+}
+
+fn load_value(ctx: &VMContext<FabricEnv>, value: i32, len: i32) -> Option<Vec<u8>> {
+    if len < 0 || len as usize > MAX_VALUE_SIZE {
+        warn!("invalid value length {}", len);
+        return None;
+    }
+
+    match ctx.memory.bytes(value as usize, len as usize) {
+        Ok(value) => Some(value.to_vec()),
+        Err(()) => {
+            warn!("could not load value at {}+{}", value, len);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn open(ctx: *mut VMContext<FabricEnv>, name: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match load_string(ctx, name) {
+            Some(name) if !name.is_empty() => name,
+            _ => return ExternRef::null(),
+        };
+
+        ctx.create_extern(Namespace { name })
+    }
+}
+
+with_abi! {
+    fn close(ctx: *mut VMContext<FabricEnv>, namespace: ExternRef) {
+        let ctx = unsafe { &mut *ctx };
+        take_extern::<Namespace>(&mut ctx.externs, namespace, "namespace");
+    }
+}
+
+with_abi! {
+    fn get(ctx: *mut VMContext<FabricEnv>, namespace: ExternRef, key: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_string(ctx, key) {
+            Some(key) => key,
+            None => return -1,
+        };
+
+        let namespace = match get_extern::<Namespace>(&ctx.externs, namespace, "namespace") {
+            Some(namespace) => namespace,
+            None => return -1,
+        };
+        let value = match values(&namespace.name).get(&key) {
+            Some(value) => value,
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, value, buffer, len, "value")
+    }
+}
+
+with_abi! {
+    fn set(ctx: *mut VMContext<FabricEnv>, namespace: ExternRef, key: i32, value: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_string(ctx, key) {
+            Some(key) => key,
+            None => return 0,
+        };
+
+        let value = match load_value(ctx, value, len) {
+            Some(value) => value,
+            None => return 0,
+        };
+
+        let namespace = match get_extern::<Namespace>(&ctx.externs, namespace, "namespace") {
+            Some(namespace) => namespace,
+            None => return 0,
+        };
+        values(&namespace.name).insert(key, value);
+        1
+    }
+}
+
+with_abi! {
+    fn delete(ctx: *mut VMContext<FabricEnv>, namespace: ExternRef, key: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let key = match load_string(ctx, key) {
+            Some(key) => key,
+            None => return 0,
+        };
+
+        let namespace = match get_extern::<Namespace>(&ctx.externs, namespace, "namespace") {
+            Some(namespace) => namespace,
+            None => return 0,
+        };
+        values(&namespace.name).remove(&key).is_some() as i32
+    }
+}
+
+with_abi! {
+    fn compare_and_swap(
+        ctx: *mut VMContext<FabricEnv>,
+        namespace: ExternRef,
+        key: i32,
+        expected: i32,
+        expected_len: i32,
+        value: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // The value of `key` is only replaced if it is currently `expected`,
+        // a negative `expected_len` expects the key to be absent. Returns 1
+        // on success, 0 if the value didn't match and -1 on invalid arguments
+        let key = match load_string(ctx, key) {
+            Some(key) => key,
+            None => return -1,
+        };
+
+        let expected = if expected_len < 0 {
+            None
+        } else {
+            match load_value(ctx, expected, expected_len) {
+                Some(expected) => Some(expected),
+                None => return -1,
+            }
+        };
+
+        let value = match load_value(ctx, value, len) {
+            Some(value) => value,
+            None => return -1,
+        };
+
+        let namespace = match get_extern::<Namespace>(&ctx.externs, namespace, "namespace") {
+            Some(namespace) => namespace,
+            None => return -1,
+        };
+        let values = values(&namespace.name);
+        if values.get(&key) != expected.as_ref() {
+            return 0;
+        }
+
+        values.insert(key, value);
+        1
+    }
+}
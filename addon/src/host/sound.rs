@@ -0,0 +1,125 @@
+use std::ffi::{CStr, CString};
+use std::ptr::null;
+
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    host::usermessage::recipients,
+    message::with_filter,
+    module::{names, FabricEnv, Module},
+    sound::{sound, EngineSound},
+};
+
+/// Play the sound on any available channel
+const CHAN_AUTO: i32 = 0;
+/// The sound is heard at the same volume from anywhere on the map
+const ATTN_NONE: f32 = 0.0;
+const PITCH_NORM: i32 = 100;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::sound::PRECACHE => Some(Function::new(
+            precache as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::sound::EMIT => Some(Function::new(
+            emit as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, f32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Precache all the sounds used by `module`, the precache
+/// tables of the engine are reset when the level changes
+pub(crate) fn precache_sounds(module: &Module) {
+    let mut sound = match sound() {
+        Some(sound) => sound,
+        None => return,
+    };
+
+    let lock = module.lock().unwrap();
+    for sample in &lock.environment.sounds {
+        if !sound.precache_sound(sample, true, false) {
+            warn!("could not precache sound {:?}", sample);
+        }
+    }
+}
+
+with_abi! {
+    fn precache(ctx: *mut VMContext<FabricEnv>, sample: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let sample = match ctx.memory.load::<CStr>(sample as usize) {
+            Ok(sample) => CString::from(sample),
+            Err(()) => {
+                warn!("could not load sample string at {}", sample);
+                return 0;
+            }
+        };
+
+        let sounds = &mut ctx.environment.sounds;
+        if !sounds.contains(&sample) {
+            debug!("Sound::precache({:?})", sample);
+
+            // Modules are usually loaded before the first level, in which
+            // case the sound will be precached when the level is loaded
+            if let Some(mut sound) = sound() {
+                sound.precache_sound(&sample, true, false);
+            }
+
+            sounds.push(sample);
+        }
+
+        1
+    }
+}
+
+with_abi! {
+    fn emit(ctx: *mut VMContext<FabricEnv>, client: i32, sample: i32, volume: f32, pitch: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let sample = match ctx.memory.load::<CStr>(sample as usize) {
+            Ok(sample) => sample,
+            Err(()) => {
+                warn!("could not load sample string at {}", sample);
+                return 0;
+            }
+        };
+
+        if !ctx.environment.sounds.iter().any(|sound| &**sound == sample) {
+            warn!("sound {:?} was not precached", sample);
+            return 0;
+        }
+
+        if !(0.0..=1.0).contains(&volume) {
+            warn!("invalid sound volume {}", volume);
+            return 0;
+        }
+
+        let pitch = if pitch == 0 { PITCH_NORM } else { pitch };
+        if !(1..=255).contains(&pitch) {
+            warn!("invalid sound pitch {}", pitch);
+            return 0;
+        }
+
+        // Client 0 plays the sound from the world for all the clients
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        let mut sound = match sound() {
+            Some(sound) => sound,
+            None => return 0,
+        };
+
+        with_filter(recipients, |filter| {
+            sound.emit_sound(
+                filter, client, CHAN_AUTO, sample, volume, ATTN_NONE, 0, pitch, null(), null(),
+                std::ptr::null_mut(), true, 0.0, -1,
+            );
+        });
+
+        1
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use fabric_runtime::{with_abi, ExternRef, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::module::{
+    call_guest, dispatch_guest, get_extern, get_extern_mut, names, take_extern, FabricEnv, Module,
+};
+
+/// Callback invoked with the buffer the state of the module is written to
+/// when the level shuts down, or read from after the next level is loaded
+pub(crate) type StateFunc = with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef));
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::state::ON_SAVE => Some(Function::new(
+            on_save as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::state::ON_RESTORE => Some(Function::new(
+            on_restore as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef) -> i32),
+        )),
+        names::state::WRITE => Some(Function::new(
+            write as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+        )),
+        names::state::READ => Some(Function::new(
+            read as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+        )),
+        names::state::SIZE => Some(Function::new(
+            size as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Save and restore callbacks registered by a module
+pub(crate) struct State {
+    save: Option<StateFunc>,
+    restore: Option<StateFunc>,
+}
+
+impl State {
+    pub(crate) fn new() -> Self {
+        State {
+            save: None,
+            restore: None,
+        }
+    }
+}
+
+/// Maximum size of the state saved by a module
+const MAX_STATE_SIZE: usize = 16 * 1024 * 1024;
+
+/// States saved by the modules on the last level shutdown, keyed by module
+/// name, until they are restored. Only accessed from the game thread
+static mut SAVED: Option<HashMap<String, Vec<u8>>> = None;
+
+/// Call the save callback of `module`, if it registered one, and return
+/// the state it wrote
+///
+/// The callback is called even if the module is paused, the state
+/// would be stale by the time a deferred call is run
+pub(crate) fn snapshot(module: &Module) -> Option<Vec<u8>> {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping state save");
+            return None;
+        }
+    };
+
+    let callback = lock.environment.state.save?;
+    let state = call_guest(&mut lock, "save", move |ctx| {
+        let extern_ref = ctx.create_extern(Vec::<u8>::new());
+        callback(ctx, extern_ref);
+        take_extern::<Vec<u8>>(&mut ctx.externs, extern_ref, "state buffer")
+    })
+    .flatten()?;
+
+    debug!(
+        "saved {} bytes of state for module {}",
+        state.len(),
+        lock.environment.name
+    );
+
+    Some(state)
+}
+
+/// Call the restore callback of `module`, if it registered one, with `state`
+pub(crate) fn restore_from(module: &Module, state: Vec<u8>) {
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            debug!("module is busy, skipping state restore");
+            return;
+        }
+    };
+
+    if let Some(callback) = lock.environment.state.restore {
+        dispatch_guest(&mut lock, "restore", move |ctx| {
+            let extern_ref = ctx.create_extern(state);
+            callback(ctx, extern_ref);
+            take_extern::<Vec<u8>>(&mut ctx.externs, extern_ref, "state buffer");
+        });
+    }
+}
+
+/// Ask `module` for its state as the level shuts down and
+/// keep it until the next level is loaded
+pub(crate) fn save(module: &Module) {
+    let state = match snapshot(module) {
+        Some(state) => state,
+        None => return,
+    };
+
+    let name = match module.try_lock() {
+        Ok(lock) => lock.environment.name.clone(),
+        Err(_) => return,
+    };
+
+    unsafe { SAVED.get_or_insert_with(HashMap::new) }.insert(name, state);
+}
+
+/// Feed the state saved on the last level shutdown back to `module`
+pub(crate) fn restore(module: &Module) {
+    let name = match module.try_lock() {
+        Ok(lock) => lock.environment.name.clone(),
+        Err(_) => return,
+    };
+
+    // The state is only restored once, even if the module doesn't restore it
+    if let Some(state) = unsafe { SAVED.as_mut() }.and_then(|saved| saved.remove(&name)) {
+        restore_from(module, state);
+    }
+}
+
+fn resolve(ctx: &mut VMContext<FabricEnv>, callback: FuncRef) -> Option<StateFunc> {
+    match ctx.typed_function(callback) {
+        Ok(callback) => Some(callback),
+        Err(err) => {
+            warn!("could not resolve {:?}: {}", callback, err);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn on_save(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match resolve(ctx, callback) {
+            Some(callback) => {
+                ctx.environment.state.save = Some(callback);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn on_restore(ctx: *mut VMContext<FabricEnv>, callback: FuncRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match resolve(ctx, callback) {
+            Some(callback) => {
+                ctx.environment.state.restore = Some(callback);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+with_abi! {
+    fn write(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, data: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let data = match ctx.memory.bytes(data as usize, len.max(0) as usize) {
+            Ok(data) => data.to_vec(),
+            Err(()) => {
+                warn!("could not load state at {}+{}", data, len);
+                return 0;
+            }
+        };
+
+        let state = match get_extern_mut::<Vec<u8>>(&mut ctx.externs, buffer, "state buffer") {
+            Some(state) => state,
+            None => return 0,
+        };
+        if state.len() + data.len() > MAX_STATE_SIZE {
+            warn!(
+                "state of module {} exceeds {} bytes",
+                ctx.environment.name, MAX_STATE_SIZE
+            );
+            return 0;
+        }
+
+        state.extend_from_slice(&data);
+        1
+    }
+}
+
+with_abi! {
+    fn read(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef, offset: i32, dest: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let state = match get_extern::<Vec<u8>>(&ctx.externs, buffer, "state buffer") {
+            Some(state) => state,
+            None => return -1,
+        };
+        let offset = offset.max(0) as usize;
+        if offset > state.len() {
+            return -1;
+        }
+
+        // The state is truncated to the buffer and the number of bytes copied is returned
+        let copied = (state.len() - offset).min(len.max(0) as usize);
+        let data = state[offset..offset + copied].to_vec();
+        match ctx.memory.store(dest as usize, &data) {
+            Ok(()) => copied as i32,
+            Err(()) => {
+                warn!("could not store state at {}+{}", dest, copied);
+                -1
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn size(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        match get_extern::<Vec<u8>>(&ctx.externs, buffer, "state buffer") {
+            Some(state) => state.len() as i32,
+            None => -1,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::ffi::CStr;
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::warn;
+
+use crate::{
+    engine::{engine, VEngineServer},
+    entity::{connected_clients, edict},
+    module::{names, FabricEnv},
+    netprops::{find_prop, read_int, PropKind},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::target::RESOLVE => Some(Function::new(
+            resolve as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Only keep the alive clients
+const TARGET_FILTER_ALIVE: i32 = 1 << 0;
+/// Only keep the dead clients
+const TARGET_FILTER_DEAD: i32 = 1 << 1;
+/// Reject the patterns that can match more than one client
+const TARGET_FILTER_NO_MULTI: i32 = 1 << 2;
+/// Ignore the bots
+const TARGET_FILTER_NO_BOTS: i32 = 1 << 3;
+
+/// Reasons a target pattern can't be resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TargetError {
+    /// No client matches the pattern
+    NoMatch,
+    /// Several clients match a name that should designate a single one
+    Ambiguous,
+    /// Clients match the pattern but are all excluded by the filters
+    Filtered,
+    /// The pattern designates a group of clients and `TARGET_FILTER_NO_MULTI` is set
+    Multiple,
+}
+
+impl TargetError {
+    fn code(self) -> i32 {
+        match self {
+            TargetError::NoMatch => -1,
+            TargetError::Ambiguous => -2,
+            TargetError::Filtered => -3,
+            TargetError::Multiple => -4,
+        }
+    }
+}
+
+/// Connected client, with the information the patterns are matched against
+struct Client {
+    index: i32,
+    user_id: i32,
+    name: String,
+    steam_id: Option<u64>,
+    is_bot: bool,
+}
+
+fn clients() -> Vec<Client> {
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return Vec::new(),
+    };
+
+    connected_clients()
+        .into_iter()
+        .filter_map(|index| {
+            let edict = edict(index)?;
+            let user_id = engine.get_player_user_id(edict);
+
+            let network_id = engine.get_player_network_id_string(edict);
+            let network_id = if network_id.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(network_id) }
+                    .to_string_lossy()
+                    .into_owned()
+            };
+
+            let name = engine.get_client_con_var_value(index, cstr!("name"));
+            let name = if name.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned()
+            };
+
+            Some(Client {
+                index,
+                user_id,
+                name,
+                steam_id: account_id(&network_id),
+                is_bot: network_id == "BOT",
+            })
+        })
+        .collect()
+}
+
+/// Account number of a SteamID in the `STEAM_X:Y:Z` or `[U:1:N]` formats,
+/// both formats are accepted in the patterns whatever the engine uses
+fn account_id(steam_id: &str) -> Option<u64> {
+    if let Some(rest) = steam_id.strip_prefix("STEAM_") {
+        let mut parts = rest.split(':').skip(1);
+        let low: u64 = parts.next()?.parse().ok()?;
+        let high: u64 = parts.next()?.parse().ok()?;
+        return Some(high * 2 + low);
+    }
+
+    let rest = steam_id.strip_prefix("[U:1:")?.strip_suffix(']')?;
+    rest.parse().ok()
+}
+
+/// Whether the client at `index` is alive, from the `m_lifeState` property
+/// of its player entity. Clients without the property count as alive
+fn is_alive(index: i32) -> bool {
+    match find_prop(index, cstr!("m_lifeState")) {
+        Some((prop, info)) => match info.kind {
+            PropKind::Int { bits, unsigned } => unsafe { read_int(prop, bits, unsigned) == 0 },
+            _ => true,
+        },
+        None => true,
+    }
+}
+
+/// Resolve `pattern` to the entity indices of the matching clients, following
+/// the SourceMod targeting rules, `issuer` being the client using the pattern
+/// or 0 for the server console:
+///
+/// - `@all`, `@bots`, `@humans`, `@alive`, `@dead`, `@me` and `@!me`
+/// - `#<userid>` for the client with this user ID
+/// - a SteamID in the `STEAM_X:Y:Z` or `[U:1:N]` formats, optionally prefixed with `#`
+/// - `#<name>` for the client with exactly this name
+/// - a part of a name, an exact match winning over the partial ones
+///
+/// Names are compared without case
+pub(crate) fn find_targets(
+    issuer: i32,
+    pattern: &str,
+    flags: i32,
+) -> Result<Vec<i32>, TargetError> {
+    let clients = clients();
+    let pattern = pattern.trim();
+
+    let group: Option<Box<dyn Fn(&Client) -> bool>> = match pattern {
+        "@all" => Some(Box::new(|_| true)),
+        "@bots" => Some(Box::new(|client| client.is_bot)),
+        "@humans" => Some(Box::new(|client| !client.is_bot)),
+        "@alive" => Some(Box::new(|client| is_alive(client.index))),
+        "@dead" => Some(Box::new(|client| !is_alive(client.index))),
+        "@!me" => Some(Box::new(|client| client.index != issuer)),
+        _ => None,
+    };
+
+    let matches: Vec<&Client> = if let Some(group) = group {
+        if flags & TARGET_FILTER_NO_MULTI != 0 {
+            return Err(TargetError::Multiple);
+        }
+
+        clients.iter().filter(|client| group(client)).collect()
+    } else if pattern == "@me" {
+        clients
+            .iter()
+            .filter(|client| client.index == issuer)
+            .collect()
+    } else if let Some(steam_id) = account_id(pattern.trim_start_matches('#')) {
+        clients
+            .iter()
+            .filter(|client| client.steam_id == Some(steam_id))
+            .collect()
+    } else if let Some(rest) = pattern.strip_prefix('#') {
+        match rest.parse::<i32>() {
+            Ok(user_id) => clients
+                .iter()
+                .filter(|client| client.user_id == user_id)
+                .collect(),
+            Err(_) => clients
+                .iter()
+                .filter(|client| client.name.eq_ignore_ascii_case(rest))
+                .collect(),
+        }
+    } else {
+        let exact: Vec<_> = clients
+            .iter()
+            .filter(|client| client.name.eq_ignore_ascii_case(pattern))
+            .collect();
+
+        if exact.is_empty() {
+            let pattern = pattern.to_lowercase();
+            let partial: Vec<_> = clients
+                .iter()
+                .filter(|client| client.name.to_lowercase().contains(&pattern))
+                .collect();
+
+            if partial.len() > 1 {
+                return Err(TargetError::Ambiguous);
+            }
+
+            partial
+        } else {
+            exact
+        }
+    };
+
+    if matches.is_empty() {
+        return Err(TargetError::NoMatch);
+    }
+
+    let targets: Vec<_> = matches
+        .into_iter()
+        .filter(|client| flags & TARGET_FILTER_NO_BOTS == 0 || !client.is_bot)
+        .filter(|client| flags & TARGET_FILTER_ALIVE == 0 || is_alive(client.index))
+        .filter(|client| flags & TARGET_FILTER_DEAD == 0 || !is_alive(client.index))
+        .map(|client| client.index)
+        .collect();
+
+    if targets.is_empty() {
+        Err(TargetError::Filtered)
+    } else {
+        Ok(targets)
+    }
+}
+
+with_abi! {
+    fn resolve(
+        ctx: *mut VMContext<FabricEnv>,
+        issuer: i32,
+        pattern: i32,
+        flags: i32,
+        clients: i32,
+        len: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let pattern = match ctx.memory.load::<CStr>(pattern as usize) {
+            Ok(pattern) => pattern.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load pattern string at {}", pattern);
+                return TargetError::NoMatch.code();
+            }
+        };
+
+        let targets = match find_targets(issuer, &pattern, flags) {
+            Ok(targets) => targets,
+            Err(err) => return err.code(),
+        };
+
+        // The list is truncated to the buffer and the number of targets is returned
+        let bytes: Vec<u8> = targets
+            .iter()
+            .take(len.max(0) as usize)
+            .flat_map(|index| index.to_le_bytes().to_vec())
+            .collect();
+
+        match ctx.memory.store(clients as usize, &bytes) {
+            Ok(()) => targets.len() as i32,
+            Err(()) => {
+                warn!("could not store targets at {}+{}", clients, bytes.len());
+                TargetError::NoMatch.code()
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::os::raw::c_int;
+
+use fabric_runtime::{with_abi, Callback, CallbackRegistry, FuncRef, Function, VMContext};
+use log::{debug, warn};
+
+use crate::{
+    host::globals::globals,
+    module::{call_guest, names, FabricEnv, Module},
+    server::GlobalVars,
+};
+
+pub(crate) type TimerFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32));
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::timer::CREATE => Some(Function::new(
+            create as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, FuncRef) -> i32),
+        )),
+        names::timer::CANCEL => Some(Function::new(
+            cancel as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Game time in simulation ticks
+///
+/// The timers follow the simulation of the server rather than the wall clock,
+/// they stop while the game is paused and keep firing on the right tick when
+/// the server hitches
+pub(crate) type Ticks = u64;
+
+/// Tick interval used before the globals of the server are located
+const DEFAULT_TICK_INTERVAL: f32 = 0.015;
+
+struct Clock {
+    /// Tick count of the server at the last frame, it restarts on each map
+    last_tick: Option<c_int>,
+    /// Game time of the last frame, it keeps increasing across maps
+    now: Ticks,
+}
+
+static mut CLOCK: Clock = Clock {
+    last_tick: None,
+    now: 0,
+};
+
+/// Advance the game time to the tick count of the current server frame,
+/// this must be called once per frame from the game thread
+pub(crate) fn advance_clock() -> Ticks {
+    let clock = unsafe { &mut CLOCK };
+
+    if let Some(tick) = globals().map(GlobalVars::tick_count) {
+        let elapsed = match clock.last_tick {
+            Some(last) if tick >= last => tick - last,
+            // The tick count of the server restarted with a new map
+            Some(_) => tick,
+            None => 0,
+        };
+
+        clock.now += elapsed as Ticks;
+        clock.last_tick = Some(tick);
+    }
+
+    clock.now
+}
+
+/// Game time of the current server frame
+pub(crate) fn now() -> Ticks {
+    unsafe { CLOCK.now }
+}
+
+/// Number of ticks covering `delay` milliseconds, rounded up
+/// so the timers never fire before their delay elapsed
+fn to_ticks(delay: u32) -> Ticks {
+    let interval = globals()
+        .map(GlobalVars::interval_per_tick)
+        .filter(|interval| *interval > 0.0)
+        .unwrap_or(DEFAULT_TICK_INTERVAL);
+
+    (f64::from(delay) / 1000.0 / f64::from(interval)).ceil() as Ticks
+}
+
+struct Timer {
+    handle: i32,
+    deadline: Ticks,
+    interval: Option<Ticks>,
+}
+
+/// Timers scheduled by a module
+///
+/// All the timers are evaluated against the game time of the current server
+/// frame, so timers created from a callback are scheduled relative to the
+/// frame that triggered it and repeating timers do not drift over time
+pub(crate) struct Timers {
+    frame_time: Ticks,
+    next_handle: i32,
+    timers: Vec<Timer>,
+    /// Callbacks of the scheduled timers, by handle
+    callbacks: CallbackRegistry<i32, TimerFunc>,
+}
+
+impl Timers {
+    pub(crate) fn new() -> Self {
+        Timers {
+            frame_time: now(),
+            next_handle: 1,
+            timers: Vec::new(),
+            callbacks: CallbackRegistry::new(),
+        }
+    }
+
+    fn create(&mut self, delay: Ticks, repeat: bool, callback: Callback<TimerFunc>) -> i32 {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        self.timers.push(Timer {
+            handle,
+            deadline: self.frame_time + delay,
+            interval: if repeat { Some(delay) } else { None },
+        });
+        self.callbacks.insert(handle, callback);
+
+        handle
+    }
+
+    fn cancel(&mut self, handle: i32) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.handle != handle);
+        self.callbacks.remove(&handle);
+        self.timers.len() != len
+    }
+
+    /// Push back the deadline of all the timers by `ticks`
+    pub(crate) fn delay(&mut self, ticks: Ticks) {
+        for timer in &mut self.timers {
+            timer.deadline += ticks;
+        }
+    }
+
+    /// Advance the timers to the frame at the game time `now`,
+    /// returning the handles of the timers to fire
+    fn begin_frame(&mut self, now: Ticks) -> Vec<i32> {
+        self.frame_time = now;
+        self.timers
+            .iter()
+            .filter(|timer| timer.deadline <= now)
+            .map(|timer| timer.handle)
+            .collect()
+    }
+
+    /// Mark a due timer as fired, rescheduling it if it repeats
+    ///
+    /// Returns None if the timer was cancelled since the beginning of the frame
+    fn fire(&mut self, handle: i32) -> Option<TimerFunc> {
+        let index = self
+            .timers
+            .iter()
+            .position(|timer| timer.handle == handle)?;
+        let timer = &mut self.timers[index];
+        let callback = self.callbacks.get(&handle)?;
+
+        match timer.interval {
+            // Skip the intervals that were missed if the server hitched,
+            // a timer only fires once per frame
+            Some(interval) if interval > 0 => {
+                while timer.deadline <= self.frame_time {
+                    timer.deadline += interval;
+                }
+            }
+            Some(_) => timer.deadline = self.frame_time + 1,
+            None => {
+                self.timers.remove(index);
+                self.callbacks.remove(&handle);
+            }
+        }
+
+        Some(callback)
+    }
+}
+
+/// Run all the timers of `module` that are due at the game time `now`
+pub(crate) fn run_timers(module: &Module, now: Ticks) {
+    let mut lock = module.lock().unwrap();
+
+    let due = lock.environment.timers.begin_frame(now);
+    for handle in due {
+        if let Some(callback) = lock.environment.timers.fire(handle) {
+            call_guest(&mut lock, "timer", |ctx| callback(ctx, handle));
+        }
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut VMContext<FabricEnv>, delay: i32, repeat: i32, callback: FuncRef) -> i32 {
+        debug!("Timer::create({:?}, {}, {}, {:?})", ctx, delay, repeat, callback);
+
+        let ctx = unsafe { &mut *ctx };
+
+        let callback = match Callback::resolve(ctx, callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        if delay < 0 {
+            warn!("invalid timer delay {}", delay);
+            return 0;
+        }
+
+        ctx.environment.timers.create(to_ticks(delay as u32), repeat != 0, callback)
+    }
+}
+
+with_abi! {
+    fn cancel(ctx: *mut VMContext<FabricEnv>, handle: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if ctx.environment.timers.cancel(handle) {
+            1
+        } else {
+            warn!("unknown timer {}", handle);
+            0
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use fabric_runtime::{with_abi, Function, VMContext};
+use log::warn;
+
+use crate::{
+    engine::Vector,
+    entity::base_entity_index,
+    host::effects::load_vector,
+    module::{names, FabricEnv},
+    trace::{engine_trace, trace_ray, EngineTrace, IgnoreEntity, Ray, Trace},
+};
+
+/// Size of the trace result written to guest memory, the structure is laid
+/// out as 14 consecutive 32 bits values:
+///
+/// ```text
+/// f32 fraction, i32 entity, f32 end_pos[3], f32 normal[3], i32 contents,
+/// i32 surface_flags, i32 surface_props, i32 hitgroup, i32 start_solid, i32 all_solid
+/// ```
+///
+/// `entity` is the index of the entity that was hit, or -1 if the trace didn't
+/// hit anything. The world is entity 0
+const TRACE_RESULT_SIZE: usize = 14 * 4;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::trace::LINE => Some(Function::new(
+            line as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32) -> i32),
+        )),
+        names::trace::HULL => Some(Function::new(
+            hull as with_abi!(
+                fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32, i32, i32, i32) -> i32
+            ),
+        )),
+        names::trace::POINT_CONTENTS => Some(Function::new(
+            point_contents as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Run the trace and store its result in guest memory at `result`
+fn run_trace(ctx: &mut VMContext<FabricEnv>, ray: Ray, mask: i32, ignore: i32, result: i32) -> i32 {
+    let trace = match trace_ray(&ray, mask as u32, IgnoreEntity { index: ignore }) {
+        Some(trace) => trace,
+        None => return 0,
+    };
+
+    match ctx.memory.store(result as usize, &encode_trace(&trace)) {
+        Ok(()) => 1,
+        Err(()) => {
+            warn!("could not store trace result at {}", result);
+            0
+        }
+    }
+}
+
+fn encode_trace(trace: &Trace) -> Vec<u8> {
+    let entity = base_entity_index(trace.entity).unwrap_or(-1);
+    let Vector { x, y, z } = trace.end_pos;
+    let normal = &trace.plane.normal;
+
+    let floats = [trace.fraction];
+    let ints = [entity];
+    let vectors = [x, y, z, normal.x, normal.y, normal.z];
+    let values = [
+        trace.contents,
+        trace.surface.flags.into(),
+        trace.surface.surface_props.into(),
+        trace.hitgroup,
+        trace.start_solid as i32,
+        trace.all_solid as i32,
+    ];
+
+    let mut bytes = Vec::with_capacity(TRACE_RESULT_SIZE);
+    bytes.extend(floats.iter().flat_map(|value| value.to_le_bytes().to_vec()));
+    bytes.extend(ints.iter().flat_map(|value| value.to_le_bytes().to_vec()));
+    bytes.extend(
+        vectors
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec()),
+    );
+    bytes.extend(values.iter().flat_map(|value| value.to_le_bytes().to_vec()));
+    bytes
+}
+
+with_abi! {
+    fn line(ctx: *mut VMContext<FabricEnv>, start: i32, end: i32, mask: i32, ignore: i32, result: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let (start, end) = match (load_vector(ctx, start), load_vector(ctx, end)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return 0,
+        };
+
+        let ray = Ray::new(start, end, Vector::default(), Vector::default());
+        run_trace(ctx, ray, mask, ignore, result)
+    }
+}
+
+with_abi! {
+    fn hull(
+        ctx: *mut VMContext<FabricEnv>,
+        start: i32,
+        end: i32,
+        mins: i32,
+        maxs: i32,
+        mask: i32,
+        ignore: i32,
+        result: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let vectors = (
+            load_vector(ctx, start),
+            load_vector(ctx, end),
+            load_vector(ctx, mins),
+            load_vector(ctx, maxs),
+        );
+
+        let ray = match vectors {
+            (Some(start), Some(end), Some(mins), Some(maxs)) => Ray::new(start, end, mins, maxs),
+            _ => return 0,
+        };
+
+        run_trace(ctx, ray, mask, ignore, result)
+    }
+}
+
+with_abi! {
+    fn point_contents(ctx: *mut VMContext<FabricEnv>, position: i32, mask: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let position = match load_vector(ctx, position) {
+            Some(position) => position,
+            None => return 0,
+        };
+
+        match engine_trace() {
+            Some(mut trace) => trace.get_point_contents(&position, mask, std::ptr::null_mut()),
+            None => 0,
+        }
+    }
+}
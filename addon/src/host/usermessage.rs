@@ -0,0 +1,287 @@
+use std::ffi::{CStr, CString};
+
+use fabric_codegen::cstr;
+use fabric_runtime::{with_abi, ExternRef, Function, VMContext};
+use log::warn;
+
+use crate::{
+    bitbuf::BitBuffer,
+    chat,
+    host::globals::globals,
+    message::{send, Recipients},
+    module::{get_extern, names, FabricEnv},
+};
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::user_message::CHAT => Some(Function::new(
+            chat as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::user_message::HINT_TEXT => Some(Function::new(
+            hint_text as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32) -> i32),
+        )),
+        names::user_message::HUD_TEXT => Some(Function::new(
+            hud_text
+                as with_abi!(
+                    fn(*mut VMContext<FabricEnv>, i32, i32, f32, f32, i32, f32, i32) -> i32
+                ),
+        )),
+        names::user_message::MOTD => Some(Function::new(
+            motd as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32) -> i32),
+        )),
+        names::user_message::SEND => Some(Function::new(
+            send_buffer as with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, ExternRef) -> i32),
+        )),
+        names::user_message::SHAKE => Some(Function::new(
+            shake as with_abi!(fn(*mut VMContext<FabricEnv>, i32, f32, f32, f32) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// Content of the MOTD panel is a text or HTML document (`TYPE_TEXT`)
+const MOTD_TEXT: i32 = 0;
+/// Content of the MOTD panel is a URL loaded by the client (`TYPE_URL`)
+const MOTD_URL: i32 = 2;
+
+/// Resolve the recipients of a message from a client entity index,
+/// with 0 broadcasting the message to all the clients
+pub(crate) fn recipients(client: i32) -> Option<Recipients> {
+    let max_clients = globals().map_or(0, |globals| globals.max_clients());
+    if client == 0 {
+        Some(Recipients::all(max_clients))
+    } else if client >= 1 && client <= max_clients {
+        Some(Recipients::single(client))
+    } else {
+        warn!("invalid client index {}", client);
+        None
+    }
+}
+
+/// Print `text` in the chat of `recipients`, as sent by the server,
+/// with its color tags translated for the game
+pub(crate) fn say(recipients: Recipients, text: &CStr) -> bool {
+    let text = chat::format(text);
+    send(recipients, cstr!("SayText"), |writer| {
+        // Sent by the server (entity 0), without the chat sound
+        writer.write_byte(0);
+        writer.write_string(&text);
+        writer.write_bit(false);
+    })
+}
+
+/// Print `text` at the `HUD_PRINT*` `destination` of the TextMsg user message
+pub(crate) fn text_msg(recipients: Recipients, destination: u8, text: &CStr) -> bool {
+    send(recipients, cstr!("TextMsg"), |writer| {
+        writer.write_byte(destination);
+        writer.write_string(text);
+        // Parameters of the localized messages, unused for plain text
+        for _ in 0..4 {
+            writer.write_string(cstr!(""));
+        }
+    })
+}
+
+/// Display `text` in the hint box of `recipients`
+pub(crate) fn hint(recipients: Recipients, text: &CStr) -> bool {
+    send(recipients, cstr!("HintText"), |writer| {
+        writer.write_string(text);
+    })
+}
+
+fn load_text(ctx: &VMContext<FabricEnv>, text: i32) -> Option<CString> {
+    match ctx.memory.load::<CStr>(text as usize) {
+        Ok(text) => Some(text.to_owned()),
+        Err(()) => {
+            warn!("could not load string at {}", text);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn chat(ctx: *mut VMContext<FabricEnv>, client: i32, text: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let text = match load_text(ctx, text) {
+            Some(text) => text,
+            None => return 0,
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        say(recipients, &text) as i32
+    }
+}
+
+with_abi! {
+    fn hint_text(ctx: *mut VMContext<FabricEnv>, client: i32, text: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let text = match load_text(ctx, text) {
+            Some(text) => text,
+            None => return 0,
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        hint(recipients, &text) as i32
+    }
+}
+
+with_abi! {
+    fn hud_text(
+        ctx: *mut VMContext<FabricEnv>,
+        client: i32,
+        channel: i32,
+        x: f32,
+        y: f32,
+        color: i32,
+        hold_time: f32,
+        text: i32,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let text = match load_text(ctx, text) {
+            Some(text) => text,
+            None => return 0,
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        // The engine only has 6 text channels (MAX_NETMESSAGE)
+        if channel < 0 || channel > 5 {
+            warn!("invalid hud text channel {}", channel);
+            return 0;
+        }
+
+        // The color is packed as 0xRRGGBBAA
+        let color = (color as u32).to_be_bytes();
+
+        let is_ok = send(recipients, cstr!("HudMsg"), |writer| {
+            writer.write_byte(channel as u8);
+            writer.write_float(x);
+            writer.write_float(y);
+            for _ in 0..2 {
+                writer.write_bytes(&color);
+            }
+            // Fade in / fade out effect
+            writer.write_byte(0);
+            writer.write_float(0.1);
+            writer.write_float(0.2);
+            writer.write_float(hold_time);
+            writer.write_float(0.0);
+            writer.write_string(&text);
+        });
+
+        is_ok as i32
+    }
+}
+
+with_abi! {
+    fn shake(
+        _ctx: *mut VMContext<FabricEnv>,
+        client: i32,
+        amplitude: f32,
+        frequency: f32,
+        duration: f32,
+    ) -> i32 {
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        let is_ok = send(recipients, cstr!("Shake"), |writer| {
+            // SHAKE_START
+            writer.write_byte(0);
+            writer.write_float(amplitude);
+            writer.write_float(frequency);
+            writer.write_float(duration);
+        });
+
+        is_ok as i32
+    }
+}
+
+with_abi! {
+    fn motd(ctx: *mut VMContext<FabricEnv>, client: i32, title: i32, content: i32, kind: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let title = match load_text(ctx, title) {
+            Some(title) => title,
+            None => return 0,
+        };
+
+        let content = match load_text(ctx, content) {
+            Some(content) => content,
+            None => return 0,
+        };
+
+        let kind = match kind {
+            MOTD_TEXT => cstr!("0"),
+            MOTD_URL => cstr!("2"),
+            _ => {
+                warn!("invalid motd kind {}", kind);
+                return 0;
+            }
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        // The MOTD is the "info" panel of the VGUI menus, opened
+        // with its settings given as a list of key-value pairs
+        let is_ok = send(recipients, cstr!("VGUIMenu"), |writer| {
+            writer.write_string(cstr!("info"));
+            writer.write_byte(1);
+            writer.write_byte(3);
+            writer.write_string(cstr!("title"));
+            writer.write_string(&title);
+            writer.write_string(cstr!("type"));
+            writer.write_string(kind);
+            writer.write_string(cstr!("msg"));
+            writer.write_string(&content);
+        });
+
+        is_ok as i32
+    }
+}
+
+with_abi! {
+    fn send_buffer(ctx: *mut VMContext<FabricEnv>, client: i32, name: i32, buffer: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match load_text(ctx, name) {
+            Some(name) => name,
+            None => return 0,
+        };
+
+        let recipients = match recipients(client) {
+            Some(recipients) => recipients,
+            None => return 0,
+        };
+
+        // The content of the message is written by the module
+        // in a BitBuffer, and copied to the engine buffer as is
+        let buffer = match get_extern::<BitBuffer>(&ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+        let is_ok = send(recipients, &name, |writer| {
+            writer.write_bits_from(buffer.bytes(), buffer.bits_written());
+        });
+
+        is_ok as i32
+    }
+}
@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    time::{Duration, Instant},
+};
+
+use fabric_runtime::{with_abi, FuncRef, Function, VMContext};
+use log::{debug, warn};
+use rand::Rng;
+
+use crate::{
+    addon::Edict,
+    entity::connected_clients,
+    host::{
+        menu::{close_ballots, show_ballot},
+        usermessage::{recipients, say},
+    },
+    module::{dispatch_guest, find_module, names, FabricEnv, Module},
+};
+
+/// Callback invoked with the handle of the vote, the index of the winning
+/// option or one of the negative `VOTE_*` results, the number of votes for
+/// the winning option and the total number of votes
+pub(crate) type VoteFunc = with_abi!(fn(*mut VMContext<FabricEnv>, i32, i32, i32, i32));
+
+/// The vote ended with a tie and the tie policy is `VOTE_TIE_NONE`
+const VOTE_TIED: i32 = -1;
+/// Nobody voted
+const VOTE_NO_VOTES: i32 = -2;
+/// The vote was cancelled by the module
+const VOTE_CANCELLED: i32 = -3;
+
+/// Maximum number of options of a vote, the ballot has a single page
+const MAX_OPTIONS: usize = 7;
+
+pub(crate) fn import_function(name: &str) -> Option<Function> {
+    match name {
+        names::vote::START => Some(Function::new(
+            start
+                as with_abi!(
+                    fn(
+                        *mut VMContext<FabricEnv>,
+                        i32,
+                        i32,
+                        i32,
+                        i32,
+                        i32,
+                        i32,
+                        i32,
+                        FuncRef,
+                    ) -> i32
+                ),
+        )),
+        names::vote::CANCEL => Some(Function::new(
+            cancel as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+        )),
+        names::vote::IN_PROGRESS => Some(Function::new(
+            in_progress as with_abi!(fn(*mut VMContext<FabricEnv>) -> i32),
+        )),
+        _ => None,
+    }
+}
+
+/// How a tie between the options with the most votes is resolved
+#[derive(Debug, Clone, Copy)]
+enum TiePolicy {
+    /// The vote has no winner
+    None,
+    /// The first of the tied options in the order of the vote wins
+    First,
+    /// One of the tied options is picked with the random number generator of the module
+    Random,
+}
+
+impl TiePolicy {
+    fn from_i32(value: i32) -> Option<Self> {
+        match value {
+            0 => Some(TiePolicy::None),
+            1 => Some(TiePolicy::First),
+            2 => Some(TiePolicy::Random),
+            _ => None,
+        }
+    }
+}
+
+/// Vote started by a module, a single vote runs at a time
+struct Vote {
+    handle: i32,
+    /// Name of the module that started the vote
+    module: String,
+    question: String,
+    options: Vec<String>,
+    /// Entity indices of the clients allowed to vote
+    voters: Vec<i32>,
+    /// Option chosen by each client that voted, None if it abstained
+    ballots: HashMap<i32, Option<usize>>,
+    deadline: Instant,
+    tie_policy: TiePolicy,
+    callback: VoteFunc,
+    cancelled: bool,
+}
+
+impl Vote {
+    fn is_complete(&self, now: Instant) -> bool {
+        self.cancelled || now >= self.deadline || self.ballots.len() >= self.voters.len()
+    }
+
+    /// Number of votes for each option
+    fn tally(&self) -> Vec<i32> {
+        let mut counts = vec![0; self.options.len()];
+        for option in self.ballots.values().flatten() {
+            counts[*option] += 1;
+        }
+
+        counts
+    }
+}
+
+/// Vote in progress, only accessed from the game thread
+static mut VOTE: Option<Vote> = None;
+static mut NEXT_HANDLE: i32 = 1;
+
+fn announce(text: &str) {
+    let text = match CString::new(text) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    if let Some(recipients) = recipients(0) {
+        say(recipients, &text);
+    }
+}
+
+/// Record the option chosen by `client` in `vote`, None to abstain
+///
+/// Returns false if the option is invalid and the ballot should stay open
+pub(crate) fn cast(vote: i32, client: i32, option: Option<usize>) -> bool {
+    let current = match unsafe { VOTE.as_mut() } {
+        Some(current) if current.handle == vote && !current.cancelled => current,
+        _ => return true,
+    };
+
+    if !current.voters.contains(&client) {
+        return true;
+    }
+
+    match option {
+        Some(option) if option >= current.options.len() => return false,
+        _ => {}
+    }
+
+    debug!("client {} voted {:?} in vote {}", client, option, vote);
+    current.ballots.insert(client, option);
+    true
+}
+
+/// Remove a disconnecting client from the voters of the current vote
+pub(crate) fn client_disconnect(entity: *const Edict) {
+    let client = match unsafe { entity.as_ref() } {
+        Some(entity) => entity.index(),
+        None => return,
+    };
+
+    if let Some(vote) = unsafe { VOTE.as_mut() } {
+        vote.voters.retain(|voter| *voter != client);
+        vote.ballots.remove(&client);
+    }
+}
+
+/// End the current vote if its time elapsed or all the voters voted,
+/// then announce the result and call the callback of its module
+pub(crate) fn run(modules: &[Module], now: Instant) {
+    let is_complete = unsafe { VOTE.as_ref() }.is_some_and(|vote| vote.is_complete(now));
+    if !is_complete {
+        return;
+    }
+
+    let vote = match unsafe { VOTE.take() } {
+        Some(vote) => vote,
+        None => return,
+    };
+
+    close_ballots(vote.handle);
+
+    let module = match find_module(modules, &vote.module) {
+        Some(module) => module,
+        None => return,
+    };
+
+    let mut lock = match module.try_lock() {
+        Ok(lock) => lock,
+        Err(_) => {
+            warn!(
+                "module {} is busy, dropping the result of its vote",
+                vote.module
+            );
+            return;
+        }
+    };
+
+    let counts = vote.tally();
+    let total: i32 = counts.iter().sum();
+    let most = counts.iter().copied().max().unwrap_or(0);
+    let tied: Vec<_> = (0..counts.len())
+        .filter(|option| counts[*option] == most)
+        .collect();
+
+    let winner = if vote.cancelled {
+        VOTE_CANCELLED
+    } else if total == 0 {
+        VOTE_NO_VOTES
+    } else if tied.len() == 1 {
+        tied[0] as i32
+    } else {
+        match vote.tie_policy {
+            TiePolicy::None => VOTE_TIED,
+            TiePolicy::First => tied[0] as i32,
+            TiePolicy::Random => tied[lock.environment.rng.gen_range(0, tied.len())] as i32,
+        }
+    };
+
+    if !vote.cancelled {
+        let result = match vote.options.get(winner.max(0) as usize) {
+            Some(option) if winner >= 0 => format!(
+                "Vote \"{}\" ended: {} ({} of {} votes)",
+                vote.question, option, most, total
+            ),
+            _ => format!("Vote \"{}\" ended without a winner", vote.question),
+        };
+
+        announce(&result);
+    }
+
+    let votes = if winner >= 0 { most } else { 0 };
+    let callback = vote.callback;
+    let handle = vote.handle;
+    dispatch_guest(&mut lock, "vote", move |ctx| {
+        callback(ctx, handle, winner, votes, total)
+    });
+}
+
+/// Drop the vote started by the module named `module` without calling its
+/// callback, the callback belongs to an instance of the module being replaced
+pub(crate) fn forget(module: &str) {
+    let vote = unsafe { &mut VOTE };
+    if vote.as_ref().is_some_and(|vote| vote.module == module) {
+        *vote = None;
+    }
+}
+
+/// Drop the vote in progress without calling its callback
+pub(crate) fn clear() {
+    unsafe {
+        VOTE = None;
+    }
+}
+
+/// Load a sequence of null-terminated strings of `len` bytes at `ptr`
+fn load_strings(ctx: &VMContext<FabricEnv>, ptr: i32, len: i32) -> Option<Vec<String>> {
+    match ctx.memory.bytes(ptr as usize, len.max(0) as usize) {
+        Ok(bytes) => Some(
+            String::from_utf8_lossy(bytes)
+                .split_terminator('\0')
+                .map(String::from)
+                .collect(),
+        ),
+        Err(()) => {
+            warn!("could not load strings at {}+{}", ptr, len);
+            None
+        }
+    }
+}
+
+/// Load an array of `len` client indices at `ptr`
+fn load_clients(ctx: &VMContext<FabricEnv>, ptr: i32, len: i32) -> Option<Vec<i32>> {
+    let size = (len.max(0) as usize).checked_mul(4)?;
+    match ctx.memory.bytes(ptr as usize, size) {
+        Ok(bytes) => Some(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        ),
+        Err(()) => {
+            warn!("could not load clients at {}+{}", ptr, size);
+            None
+        }
+    }
+}
+
+with_abi! {
+    fn start(
+        ctx: *mut VMContext<FabricEnv>,
+        question: i32,
+        options: i32,
+        options_len: i32,
+        duration: i32,
+        voters: i32,
+        voters_len: i32,
+        tie_policy: i32,
+        callback: FuncRef,
+    ) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        if unsafe { VOTE.is_some() } {
+            warn!("a vote is already in progress");
+            return 0;
+        }
+
+        let callback: VoteFunc = match ctx.typed_function(callback) {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", callback, err);
+                return 0;
+            }
+        };
+
+        let question = match ctx.memory.load::<CStr>(question as usize) {
+            Ok(question) => question.to_string_lossy().into_owned(),
+            Err(()) => {
+                warn!("could not load question at {}", question);
+                return 0;
+            }
+        };
+
+        let options = match load_strings(ctx, options, options_len) {
+            Some(options) if (2..=MAX_OPTIONS).contains(&options.len()) => options,
+            Some(options) => {
+                warn!("a vote needs 2 to {} options, not {}", MAX_OPTIONS, options.len());
+                return 0;
+            }
+            None => return 0,
+        };
+
+        let tie_policy = match TiePolicy::from_i32(tie_policy) {
+            Some(tie_policy) => tie_policy,
+            None => {
+                warn!("invalid tie policy {}", tie_policy);
+                return 0;
+            }
+        };
+
+        if duration <= 0 {
+            warn!("invalid vote duration {}", duration);
+            return 0;
+        }
+
+        // An empty list of voters lets all the connected clients vote
+        let connected = connected_clients();
+        let voters = if voters_len > 0 {
+            match load_clients(ctx, voters, voters_len) {
+                Some(voters) => voters
+                    .into_iter()
+                    .filter(|voter| connected.contains(voter))
+                    .collect(),
+                None => return 0,
+            }
+        } else {
+            connected
+        };
+
+        let handle = unsafe { NEXT_HANDLE };
+        unsafe {
+            NEXT_HANDLE = NEXT_HANDLE.wrapping_add(1).max(1);
+        }
+
+        let module = &ctx.environment.name;
+        let voters: Vec<_> = voters
+            .into_iter()
+            .filter(|voter| show_ballot(*voter, module, handle, &question, &options))
+            .collect();
+
+        announce(&format!("Vote started: {}", question));
+
+        unsafe {
+            VOTE = Some(Vote {
+                handle,
+                module: module.clone(),
+                question,
+                options,
+                voters,
+                ballots: HashMap::new(),
+                deadline: Instant::now() + Duration::from_secs(duration as u64),
+                tie_policy,
+                callback,
+                cancelled: false,
+            });
+        }
+
+        handle
+    }
+}
+
+with_abi! {
+    fn cancel(ctx: *mut VMContext<FabricEnv>, vote: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // The callback is called with `VOTE_CANCELLED` on the next frame
+        match unsafe { VOTE.as_mut() } {
+            Some(current) if current.handle == vote && current.module == ctx.environment.name => {
+                current.cancelled = true;
+                1
+            }
+            _ => {
+                warn!("unknown vote {}", vote);
+                0
+            }
+        }
+    }
+}
+
+with_abi! {
+    fn in_progress(_ctx: *mut VMContext<FabricEnv>) -> i32 {
+        unsafe { VOTE.is_some() as i32 }
+    }
+}
@@ -0,0 +1,221 @@
+//! The `fabric_mem` console command, to inspect the linear memory of the
+//! modules while they run: `dump` prints a range of the memory and `watch`
+//! logs the changes of a range, checked on every frame
+
+use std::fmt::Write;
+
+use log::{info, warn};
+
+use crate::module::{find_module, Module};
+
+/// Maximum number of bytes printed by `dump`
+const MAX_DUMP_LEN: usize = 4096;
+/// Default and maximum number of bytes of a watch
+const DEFAULT_WATCH_LEN: usize = 4;
+const MAX_WATCH_LEN: usize = 64;
+/// Maximum number of watches on all the modules
+const MAX_WATCHES: usize = 32;
+
+const USAGE: &str = "usage: fabric_mem <module> dump <offset> <len>\n       \
+                     fabric_mem <module> watch <offset> [len]\n       \
+                     fabric_mem <module> unwatch <offset|all>";
+
+/// Range of the memory of a module logged when it changes
+struct Watch {
+    module: String,
+    offset: usize,
+    len: usize,
+    /// Content of the range when it was last checked
+    value: Vec<u8>,
+}
+
+/// Only accessed from the game thread
+static mut WATCHES: Vec<Watch> = Vec::new();
+
+/// Parse an offset or a length, in decimal or in hexadecimal with a `0x` prefix
+fn parse_number(value: &str) -> Option<usize> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len() * 3);
+    for byte in bytes {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+
+        let _ = write!(output, "{:02x}", byte);
+    }
+
+    output
+}
+
+/// Print `bytes` read at `offset` as lines of 16 bytes, with their ASCII characters
+fn print_dump(offset: usize, bytes: &[u8]) {
+    for (index, line) in bytes.chunks(16).enumerate() {
+        let text: String = line
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        info!("{:08x}  {:<47}  {}", offset + index * 16, hex(line), text);
+    }
+}
+
+/// Handler of the `fabric_mem` console command
+pub(crate) fn command(modules: &[Module], args: &[String]) {
+    let (name, action) = match (args.get(1), args.get(2)) {
+        (Some(name), Some(action)) => (name, action.as_str()),
+        _ => {
+            info!("{}", USAGE);
+            return;
+        }
+    };
+
+    let module = match find_module(modules, name) {
+        Some(module) => module,
+        None => {
+            warn!("module {} not found", name);
+            return;
+        }
+    };
+
+    let offset = args.get(3).map(|offset| (offset, parse_number(offset)));
+    let len = args.get(4).map(|len| (len, parse_number(len)));
+
+    match (action, offset, len) {
+        ("dump", Some((_, Some(offset))), Some((_, Some(len)))) => {
+            let lock = module.lock().unwrap();
+            let memory = &lock.memory;
+            if len > MAX_DUMP_LEN {
+                warn!("can't dump more than {} bytes", MAX_DUMP_LEN);
+                return;
+            }
+
+            match memory.bytes(offset, len) {
+                Ok(bytes) => print_dump(offset, bytes),
+                Err(()) => warn!(
+                    "{:#x}+{} is out of the memory of {} ({} bytes)",
+                    offset,
+                    len,
+                    name,
+                    memory.len()
+                ),
+            }
+        }
+
+        ("watch", Some((_, Some(offset))), len) => {
+            let len = match len {
+                None => DEFAULT_WATCH_LEN,
+                Some((_, Some(len))) if len > 0 && len <= MAX_WATCH_LEN => len,
+                Some((len, _)) => {
+                    warn!("invalid watch length {:?}, up to {}", len, MAX_WATCH_LEN);
+                    return;
+                }
+            };
+
+            let watches = unsafe { &mut WATCHES };
+            if watches.len() >= MAX_WATCHES {
+                warn!("too many watches, remove one with unwatch");
+                return;
+            }
+
+            let lock = module.lock().unwrap();
+            let value = match lock.memory.bytes(offset, len) {
+                Ok(value) => value.to_vec(),
+                Err(()) => {
+                    warn!("{:#x}+{} is out of the memory of {}", offset, len, name);
+                    return;
+                }
+            };
+
+            info!("watching {} {:#x}+{}: {}", name, offset, len, hex(&value));
+            watches.retain(|watch| watch.module != *name || watch.offset != offset);
+            watches.push(Watch {
+                module: name.clone(),
+                offset,
+                len,
+                value,
+            });
+        }
+
+        ("unwatch", Some((all, _)), None) if all == "all" => {
+            unsafe { &mut WATCHES }.retain(|watch| watch.module != *name);
+            info!("removed the watches of {}", name);
+        }
+
+        ("unwatch", Some((_, Some(offset))), None) => {
+            let watches = unsafe { &mut WATCHES };
+            let count = watches.len();
+            watches.retain(|watch| watch.module != *name || watch.offset != offset);
+            if watches.len() == count {
+                warn!("{} has no watch at {:#x}", name, offset);
+            }
+        }
+
+        _ => info!("{}", USAGE),
+    }
+}
+
+/// Log the changes of the watched ranges since the last frame, the watches
+/// of the modules that were unloaded are removed
+pub(crate) fn run(modules: &[Module]) {
+    let watches = unsafe { &mut WATCHES };
+
+    let mut index = 0;
+    while index < watches.len() {
+        if check(modules, &mut watches[index]) {
+            index += 1;
+        } else {
+            watches.remove(index);
+        }
+    }
+}
+
+/// Log the change of `watch`, returns false if its module is gone
+fn check(modules: &[Module], watch: &mut Watch) -> bool {
+    for module in modules {
+        let lock = match module.try_lock() {
+            Ok(lock) => lock,
+            Err(_) => continue,
+        };
+
+        if lock.environment.name != watch.module {
+            continue;
+        }
+
+        let value = match lock.memory.bytes(watch.offset, watch.len) {
+            Ok(value) => value,
+            Err(()) => return false,
+        };
+
+        if value != watch.value.as_slice() {
+            info!(
+                "{} {:#x}+{}: {} -> {}",
+                watch.module,
+                watch.offset,
+                watch.len,
+                hex(&watch.value),
+                hex(value)
+            );
+            watch.value = value.to_vec();
+        }
+
+        return true;
+    }
+
+    // A running module is still loaded, the others were unloaded
+    modules.iter().any(|module| module.try_lock().is_err())
+}
@@ -0,0 +1,128 @@
+use std::{iter::Peekable, vec::IntoIter};
+
+/// A node in a KeyValues text document, either a string
+/// value or a list of named children
+#[derive(Debug)]
+pub(crate) enum KeyValue {
+    Value(String),
+    Section(Vec<(String, KeyValue)>),
+}
+
+/// Parse a KeyValues text document (the format used by `.res` and `.vdf` files)
+///
+/// Conditional blocks (`[$X360]`) and `#include` / `#base` directives are
+/// not supported and are skipped / treated as regular keys respectively
+pub(crate) fn parse_keyvalues(source: &str) -> Result<Vec<(String, KeyValue)>, String> {
+    let tokens = tokenize(source)?;
+    let mut tokens = tokens.into_iter().peekable();
+    let root = parse_section(&mut tokens)?;
+
+    match tokens.next() {
+        None => Ok(root),
+        Some(token) => Err(format!("unexpected token {:?}", token)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    String(String),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+
+            '/' if chars.peek() == Some(&'/') => {
+                while let Some(ch) = chars.next() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+
+            // Platform conditionals
+            '[' => {
+                while let Some(ch) = chars.next() {
+                    if ch == ']' {
+                        break;
+                    }
+                }
+            }
+
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(ch) => value.push(ch),
+                            None => return Err(String::from("unterminated escape sequence")),
+                        },
+                        Some(ch) => value.push(ch),
+                        None => return Err(String::from("unterminated string")),
+                    }
+                }
+
+                tokens.push(Token::String(value));
+            }
+
+            ch if ch.is_whitespace() => {}
+
+            ch => {
+                let mut value = String::new();
+                value.push(ch);
+
+                while let Some(ch) = chars.peek() {
+                    if ch.is_whitespace() || *ch == '{' || *ch == '}' || *ch == '"' {
+                        break;
+                    }
+
+                    value.push(*ch);
+                    chars.next();
+                }
+
+                tokens.push(Token::String(value));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_section(
+    tokens: &mut Peekable<IntoIter<Token>>,
+) -> Result<Vec<(String, KeyValue)>, String> {
+    let mut entries = Vec::new();
+
+    loop {
+        let key = match tokens.peek() {
+            Some(Token::String(_)) => match tokens.next() {
+                Some(Token::String(key)) => key,
+                _ => unreachable!(),
+            },
+            Some(Token::Close) | None => return Ok(entries),
+            Some(Token::Open) => return Err(String::from("expected a key, found '{'")),
+        };
+
+        match tokens.next() {
+            Some(Token::String(value)) => entries.push((key, KeyValue::Value(value))),
+            Some(Token::Open) => {
+                let children = parse_section(tokens)?;
+                match tokens.next() {
+                    Some(Token::Close) => entries.push((key, KeyValue::Section(children))),
+                    _ => return Err(format!("unterminated section {:?}", key)),
+                }
+            }
+            Some(Token::Close) | None => return Err(format!("missing value for key {:?}", key)),
+        }
+    }
+}
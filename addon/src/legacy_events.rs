@@ -0,0 +1,479 @@
+//! Adapter for GAMEEVENTSMANAGER001, the KeyValues based event manager of the
+//! engine branches predating IGameEventManager2
+//!
+//! The adapter implements `GameEventManager2` on top of the older interface, and
+//! wraps the KeyValues of the events in a `GameEvent` object for the duration of
+//! the listener callbacks so the rest of the addon only deals with the newer API.
+//! KeyValues are allocated and freed by tier1 code the addon doesn't link with,
+//! so the events can be read and serialized but not created, copied or fired
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    os::raw::{c_char, c_int},
+    ptr::null_mut,
+};
+
+use fabric_codegen::cstr;
+use log::warn;
+
+use crate::{
+    bitbuf::{bf_read, bf_write},
+    foreign::{create_interface, CreateInterfaceFn, Foreign},
+    logging::find_symbol,
+    manager::{
+        CGameEvent, CGameEventManager2, GameEvent, GameEventListener2, GameEventManager2,
+        IGameEvent, IGameEventManager2,
+    },
+};
+
+/// Binding for IGameEventManager
+///
+/// The overloads of a virtual method are grouped in the vtable in the reverse
+/// order of their declaration, `AddListener(listener, server_side)` comes first
+#[fabric_codegen::interface]
+pub(crate) trait GameEventManager {
+    fn destructor(&self);
+
+    // load game event descriptions from a file eg "resource\gameevents.res"
+    fn load_events_from_file(&mut self, file_name: &CStr) -> c_int;
+
+    // removes all and anything
+    fn reset(&mut self);
+
+    // returns keys for event
+    fn get_event(&mut self, name: &CStr) -> *mut c_void;
+
+    // adds a listener for all events
+    fn add_listener_all(&mut self, listener: Box<dyn GameEventListener>, server_side: bool)
+        -> bool;
+
+    // adds a listener for a particular event
+    fn add_listener(&mut self, listener: *mut c_void, name: &CStr, server_side: bool) -> bool;
+
+    // removes a listener
+    fn remove_listener(&mut self, listener: *mut c_void);
+
+    // fires a global event, the KeyValues will be deleted by the manager
+    fn fire_event(&mut self, event: *mut c_void) -> bool;
+    fn fire_event_server_only(&mut self, event: *mut c_void) -> bool;
+    fn fire_event_client_only(&mut self, event: *mut c_void) -> bool;
+
+    // write/read event to/from bitbuffer
+    fn serialize_key_values(
+        &mut self,
+        event: *mut c_void,
+        buf: *mut bf_write,
+        event_type: *mut c_void,
+    ) -> bool;
+    // create new KeyValues, must be deleted
+    fn unserialize_key_value(&mut self, buf: *mut bf_read) -> *mut c_void;
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait GameEventListener {
+    fn destructor(&self);
+
+    /// FireEvent is called by EventManager if event just occured
+    /// KeyValue memory will be freed by manager if not needed anymore
+    fn fire_game_event(&mut self, event: *mut c_void);
+}
+
+/// Binding for IKeyValuesSystem, only the methods
+/// shared by all the engine branches are declared
+#[fabric_codegen::interface]
+pub(crate) trait KeyValuesSystem {
+    fn register_sizeof_key_values(&mut self, size: c_int);
+    fn alloc_key_values_memory(&mut self, size: c_int) -> *mut c_void;
+    fn free_key_values_memory(&mut self, memory: *mut c_void);
+    fn get_symbol_for_string(&mut self, name: &CStr) -> c_int;
+    fn get_string_for_symbol(&mut self, symbol: c_int) -> &CStr;
+}
+
+/// Name of the vstdlib library, it is loaded by the engine
+const VSTDLIB: &CStr = cstr!("vstdlib.dll");
+
+type KeyValuesSystemFn = unsafe extern "C" fn() -> *mut c_void;
+
+static mut KEYVALUES_SYSTEM: *mut c_void = null_mut();
+
+fn keyvalues_system() -> Foreign<dyn KeyValuesSystem> {
+    Foreign::with(unsafe { KEYVALUES_SYSTEM })
+}
+
+const TYPE_NONE: u8 = 0;
+const TYPE_STRING: u8 = 1;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOAT: u8 = 3;
+const TYPE_UINT64: u8 = 7;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union KeyValuesData {
+    int: c_int,
+    float: f32,
+    ptr: *mut c_void,
+    color: [u8; 4],
+}
+
+/// Memory layout of a KeyValues node
+#[repr(C)]
+struct KeyValues {
+    key_name: c_int,
+    string: *mut c_char,
+    wide_string: *mut u16,
+    data: KeyValuesData,
+    data_type: u8,
+    has_escape_sequences: u8,
+    evaluate_conditionals: u8,
+    unused: u8,
+    peer: *mut KeyValues,
+    sub: *mut KeyValues,
+    chain: *mut KeyValues,
+}
+
+/// Event of GAMEEVENTSMANAGER001, borrowing the KeyValues
+/// received by the listener
+pub(crate) struct KeyValuesEvent {
+    keys: *mut KeyValues,
+}
+
+impl KeyValuesEvent {
+    fn name(&self) -> &CStr {
+        // The strings of the symbols live as long as the KeyValues system
+        let keys = unsafe { &*self.keys };
+        let name = keyvalues_system()
+            .get_string_for_symbol(keys.key_name)
+            .as_ptr();
+        unsafe { CStr::from_ptr(name) }
+    }
+
+    /// Find the field `name` of the event, the names of the
+    /// keys are compared case-insensitively like KeyValues does
+    fn find(&self, name: &CStr) -> Option<&mut KeyValues> {
+        let mut system = keyvalues_system();
+        let mut key = unsafe { (*self.keys).sub };
+
+        while let Some(node) = unsafe { key.as_mut() } {
+            let key_name = system.get_string_for_symbol(node.key_name);
+            if key_name.to_bytes().eq_ignore_ascii_case(name.to_bytes()) {
+                return Some(node);
+            }
+
+            key = node.peer;
+        }
+
+        None
+    }
+
+    fn string(key: &KeyValues) -> Option<&str> {
+        if key.string.is_null() {
+            return None;
+        }
+
+        let value = unsafe { CStr::from_ptr(key.string) };
+        value.to_str().ok().map(str::trim)
+    }
+}
+
+impl GameEvent for KeyValuesEvent {
+    fn destructor(&self) {}
+
+    fn get_name(&self) -> &CStr {
+        self.name()
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn is_empty(&mut self, name: &CStr) -> bool {
+        match self.find(name) {
+            Some(key) => key.data_type == TYPE_NONE,
+            None => true,
+        }
+    }
+
+    fn get_bool(&mut self, name: &CStr, default: bool) -> bool {
+        self.get_int(name, default as c_int) != 0
+    }
+
+    fn get_int(&mut self, name: &CStr, default: c_int) -> c_int {
+        let key = match self.find(name) {
+            Some(key) => key,
+            None => return default,
+        };
+
+        match key.data_type {
+            TYPE_INT => unsafe { key.data.int },
+            TYPE_FLOAT => unsafe { key.data.float as c_int },
+            TYPE_UINT64 => unsafe { *(key.string as *const u64) as c_int },
+            TYPE_STRING => Self::string(key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0),
+            _ => default,
+        }
+    }
+
+    fn get_uint64(&mut self, name: &CStr, default: u64) -> u64 {
+        let key = match self.find(name) {
+            Some(key) => key,
+            None => return default,
+        };
+
+        match key.data_type {
+            TYPE_UINT64 => unsafe { *(key.string as *const u64) },
+            TYPE_INT => unsafe { key.data.int as u64 },
+            TYPE_FLOAT => unsafe { key.data.float as u64 },
+            TYPE_STRING => Self::string(key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0),
+            _ => default,
+        }
+    }
+
+    fn get_float(&mut self, name: &CStr, default: f32) -> f32 {
+        let key = match self.find(name) {
+            Some(key) => key,
+            None => return default,
+        };
+
+        match key.data_type {
+            TYPE_FLOAT => unsafe { key.data.float },
+            TYPE_INT => unsafe { key.data.int as f32 },
+            TYPE_UINT64 => unsafe { *(key.string as *const u64) as f32 },
+            TYPE_STRING => Self::string(key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0.0),
+            _ => default,
+        }
+    }
+
+    fn get_string(&mut self, name: &CStr, default: &CStr) -> &CStr {
+        // Numeric fields would need to be formatted in a buffer owned by
+        // the KeyValues, only the fields holding a string are returned
+        match self.find(name) {
+            Some(key) if key.data_type == TYPE_STRING && !key.string.is_null() => unsafe {
+                CStr::from_ptr(key.string)
+            },
+            _ => unsafe { CStr::from_ptr(default.as_ptr()) },
+        }
+    }
+
+    // Only the existing numeric fields can be modified in place,
+    // changing the type of a field would reallocate its value
+    fn set_bool(&mut self, name: &CStr, value: bool) {
+        self.set_int(name, value as c_int);
+    }
+
+    fn set_int(&mut self, name: &CStr, value: c_int) {
+        match self.find(name) {
+            Some(key) if key.data_type == TYPE_INT => key.data.int = value,
+            _ => warn!("cannot set {:?} on legacy event", name),
+        }
+    }
+
+    fn set_uint64(&mut self, name: &CStr, value: u64) {
+        match self.find(name) {
+            Some(key) if key.data_type == TYPE_UINT64 => unsafe {
+                *(key.string as *mut u64) = value;
+            },
+            _ => warn!("cannot set {:?} on legacy event", name),
+        }
+    }
+
+    fn set_float(&mut self, name: &CStr, value: f32) {
+        match self.find(name) {
+            Some(key) if key.data_type == TYPE_FLOAT => key.data.float = value,
+            _ => warn!("cannot set {:?} on legacy event", name),
+        }
+    }
+
+    fn set_string(&mut self, name: &CStr, _value: &CStr) {
+        warn!("cannot set {:?} on legacy event", name);
+    }
+}
+
+static EVENT_VTABLE: IGameEvent = <dyn GameEvent>::vtable::<Box<KeyValuesEvent>, KeyValuesEvent>();
+
+/// Wrapper implementing GameEventListener for a listener of the addon,
+/// forwarding the events to it as IGameEvent objects
+struct LegacyListener {
+    listener: Foreign<dyn GameEventListener2>,
+}
+
+static LISTENER_VTABLE: IGameEventListener =
+    <dyn GameEventListener>::vtable::<Box<LegacyListener>, LegacyListener>();
+
+/// Listener of the addon added to GAMEEVENTSMANAGER001, which has no
+/// FindListener method: the adapter keeps track of its listeners itself
+struct Registration {
+    /// Address of the listener given by the addon
+    listener: *mut c_void,
+    name: CString,
+    /// Wrapper registered with the engine in place of the listener
+    wrapper: *mut CGameEventListener<Box<LegacyListener>>,
+}
+
+impl GameEventListener for LegacyListener {
+    fn destructor(&self) {}
+
+    fn fire_game_event(&mut self, keys: *mut c_void) {
+        let mut event = CGameEvent {
+            vtable: &EVENT_VTABLE,
+            instance: Box::new(KeyValuesEvent {
+                keys: keys as *mut KeyValues,
+            }),
+        };
+
+        // The wrapper is dropped once the listener returns, like
+        // the engine frees the KeyValues after firing the event
+        let event = &mut event as *mut CGameEvent<Box<KeyValuesEvent>> as *mut c_void;
+        self.listener.fire_game_event(event);
+    }
+}
+
+/// Implementation of GameEventManager2 forwarding to GAMEEVENTSMANAGER001
+pub(crate) struct LegacyManager {
+    manager: Foreign<dyn GameEventManager>,
+    registrations: Vec<Registration>,
+}
+
+impl GameEventManager2 for LegacyManager {
+    fn destructor(&self) {}
+
+    fn load_events_from_file(&mut self, file_name: &CStr) -> c_int {
+        self.manager.load_events_from_file(file_name)
+    }
+
+    fn reset(&mut self) {
+        self.manager.reset();
+
+        for registration in self.registrations.drain(..) {
+            unsafe { drop(Box::from_raw(registration.wrapper)) };
+        }
+    }
+
+    fn add_listener(&mut self, listener: *mut c_void, name: &CStr, server_side: bool) -> bool {
+        if self.find_listener(listener, name) {
+            return true;
+        }
+
+        let wrapper = Box::into_raw(Box::new(CGameEventListener {
+            vtable: &LISTENER_VTABLE,
+            instance: Box::new(LegacyListener {
+                listener: Foreign::with(listener),
+            }),
+        }));
+
+        if !self
+            .manager
+            .add_listener(wrapper as *mut c_void, name, server_side)
+        {
+            unsafe { drop(Box::from_raw(wrapper)) };
+            return false;
+        }
+
+        self.registrations.push(Registration {
+            listener,
+            name: name.to_owned(),
+            wrapper,
+        });
+        true
+    }
+
+    fn find_listener(&mut self, listener: *mut c_void, name: &CStr) -> bool {
+        self.registrations
+            .iter()
+            .any(|registration| registration.listener == listener && *registration.name == *name)
+    }
+
+    fn remove_listener(&mut self, listener: *mut c_void) {
+        let manager = &mut self.manager;
+        self.registrations.retain(|registration| {
+            if registration.listener != listener {
+                return true;
+            }
+
+            manager.remove_listener(registration.wrapper as *mut c_void);
+            unsafe { drop(Box::from_raw(registration.wrapper)) };
+            false
+        });
+    }
+
+    fn create_event(&mut self, name: &CStr, _force: bool, _cookie: *mut c_int) -> *mut c_void {
+        warn!("GAMEEVENTSMANAGER001 cannot create event {:?}", name);
+        null_mut()
+    }
+
+    // The only events handed out by the adapter are the ones received by the
+    // listeners, they are owned by the engine and can't be fired or freed
+    fn fire_event(&mut self, _event: *mut c_void, _dont_broadcast: bool) -> bool {
+        warn!("GAMEEVENTSMANAGER001 cannot fire events received by a listener");
+        false
+    }
+
+    fn fire_event_client_side(&mut self, _event: &mut dyn GameEvent) -> bool {
+        false
+    }
+
+    fn duplicate_event(&mut self, _event: *mut c_void) -> *mut c_void {
+        null_mut()
+    }
+
+    fn free_event(&mut self, _event: *mut c_void) {}
+
+    fn serialize_event(&mut self, event: *mut c_void, buf: *mut bf_write) -> bool {
+        let event = unsafe { &*(event as *mut CGameEvent<Box<KeyValuesEvent>>) };
+        let keys = event.instance.keys as *mut c_void;
+        self.manager.serialize_key_values(keys, buf, null_mut())
+    }
+
+    fn unserialize_event(&mut self, _buf: *mut bf_read) -> *mut c_void {
+        warn!("GAMEEVENTSMANAGER001 cannot unserialize events");
+        null_mut()
+    }
+}
+
+static MANAGER_VTABLE: IGameEventManager2 =
+    <dyn GameEventManager2>::vtable::<Box<LegacyManager>, LegacyManager>();
+
+static mut LEGACY_MANAGER: Option<Box<CGameEventManager2<Box<LegacyManager>>>> = None;
+
+/// Acquire GAMEEVENTSMANAGER001 from the engine factory and
+/// return an IGameEventManager2 adapter forwarding to it
+pub(crate) fn init(factory: CreateInterfaceFn) -> Option<Foreign<dyn GameEventManager2>> {
+    let manager = create_interface::<dyn GameEventManager>(factory, cstr!("GAMEEVENTSMANAGER001"))?;
+
+    // The names of the keys are symbols of the KeyValues system
+    let system = match find_symbol(VSTDLIB, cstr!("KeyValuesSystem")) {
+        Some(system) => unsafe {
+            let system: KeyValuesSystemFn = std::mem::transmute(system);
+            system()
+        },
+        None => {
+            warn!("KeyValuesSystem not found, legacy game events are unavailable");
+            return None;
+        }
+    };
+
+    let mut adapter = Box::new(CGameEventManager2 {
+        vtable: &MANAGER_VTABLE,
+        instance: Box::new(LegacyManager {
+            manager,
+            registrations: Vec::new(),
+        }),
+    });
+
+    let ptr = &mut *adapter as *mut CGameEventManager2<Box<LegacyManager>> as *mut c_void;
+
+    unsafe {
+        KEYVALUES_SYSTEM = system;
+        LEGACY_MANAGER = Some(adapter);
+    }
+
+    Some(Foreign::with(ptr))
+}
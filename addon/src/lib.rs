@@ -10,10 +10,44 @@ use std::{
 };
 
 mod addon;
+mod bans;
+mod bitbuf;
+mod bot;
+mod chat;
+mod command;
+mod config;
+mod crash;
+mod custom_events;
+mod deferred;
+mod effects;
+mod engine;
+mod entity;
+mod executor;
+mod flood;
 mod foreign;
+mod game;
+mod host;
+mod inspect;
+mod keyvalues;
+mod legacy_events;
+mod loader;
 mod logging;
 mod manager;
+mod message;
+mod metrics;
+mod middleware;
 mod module;
+mod netprops;
+mod plugin;
+mod remote;
+mod schema;
+mod selftest;
+mod server;
+mod sound;
+mod stats;
+mod thread;
+mod tools;
+mod trace;
 
 #[ctor::ctor]
 fn __init_logs() {
@@ -25,15 +59,18 @@ pub extern "C" fn CreateInterface(name: *const c_char, return_code: *mut c_int)
     let name = unsafe { CStr::from_ptr(name) };
     let name = name.to_string_lossy();
 
-    match &*name {
-        "ISERVERPLUGINCALLBACKS003" => {
-            let return_code = unsafe { return_code.as_mut() };
-            if let Some(return_code) = return_code {
-                *return_code = 0;
-            }
-
-            unsafe { &mut crate::addon::INSTANCE as *mut _ as *mut c_void }
-        }
+    // Older engine branches request an older version of the interface,
+    // all the versions share the same instance of the addon
+    let instance = match &*name {
+        "ISERVERPLUGINCALLBACKS003" => unsafe {
+            &mut crate::addon::INSTANCE as *mut _ as *mut c_void
+        },
+        "ISERVERPLUGINCALLBACKS002" => unsafe {
+            &mut crate::plugin::INSTANCE_002 as *mut _ as *mut c_void
+        },
+        "ISERVERPLUGINCALLBACKS001" => unsafe {
+            &mut crate::plugin::INSTANCE_001 as *mut _ as *mut c_void
+        },
         name => {
             warn!("Unknown interface {}", name);
 
@@ -42,7 +79,14 @@ pub extern "C" fn CreateInterface(name: *const c_char, return_code: *mut c_int)
                 *return_code = 1;
             }
 
-            null_mut()
+            return null_mut();
         }
+    };
+
+    let return_code = unsafe { return_code.as_mut() };
+    if let Some(return_code) = return_code {
+        *return_code = 0;
     }
+
+    instance
 }
@@ -0,0 +1,712 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use fabric_runtime::{inspect_module, ModuleInfo, RUNTIME_MODULE};
+use log::{debug, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config,
+    module::{names, HOST_MODULES},
+};
+
+/// Directory of the modules, relative to the addon directory
+const MODULES_DIR: &str = "modules";
+/// Name of the custom section holding the manifest of a module
+const MANIFEST_SECTION: &str = "fabric:manifest";
+
+/// Name of the throwaway modules compiled by `fabric_eval`,
+/// reserved so they can't share the data of a loaded module
+pub(crate) const EVAL_MODULE: &str = "eval";
+
+/// Manifest of a module, stored as TOML in the `fabric:manifest`
+/// custom section of the module binary
+///
+/// All the keys are optional and missing values fall back to their defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Manifest {
+    /// Name of the module, defaults to the name of the module file
+    pub(crate) name: Option<String>,
+    pub(crate) version: String,
+    pub(crate) author: String,
+    /// Modules that must be loaded before this module, as a list
+    /// of requirements like `"lib_util >= 0.2"`
+    pub(crate) requires: Vec<String>,
+    /// Host modules that must be provided by the addon for this module to load
+    pub(crate) requires_host: Vec<String>,
+    /// Capabilities granted to the module, gating the host
+    /// modules that have side effects outside of the server
+    pub(crate) permissions: Vec<String>,
+    /// Modules are loaded and receive the events in ascending priority
+    /// order, after their dependencies. See `ModuleSource::priority`
+    pub(crate) priority: i32,
+}
+
+/// Host modules that can only be imported with the matching permission
+const PERMISSIONS: &[(&str, &str)] = &[
+    (names::http::MODULE, "http"),
+    (names::fs::MODULE, "fs"),
+    (names::db::MODULE, "db"),
+    (names::shared::MODULE, "shared"),
+];
+
+/// Get the permission required to import from the host module `module`
+pub(crate) fn required_permission(module: &str) -> Option<&'static str> {
+    PERMISSIONS
+        .iter()
+        .find(|(name, _)| *name == module)
+        .map(|(_, permission)| *permission)
+}
+
+/// Version of a module, as a list of numeric components
+///
+/// Trailing zero components are ignored, so `0.2` and `0.2.0` are equal
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version(Vec<u64>);
+
+impl Version {
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let mut parts = text
+            .trim()
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+
+        while parts.last() == Some(&0) {
+            parts.pop();
+        }
+
+        Some(Version(parts))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparator {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Operators of the version constraints, the two characters
+/// operators must be matched before their prefixes
+const COMPARATORS: &[(&str, Comparator)] = &[
+    (">=", Comparator::Ge),
+    ("<=", Comparator::Le),
+    ("==", Comparator::Eq),
+    (">", Comparator::Gt),
+    ("<", Comparator::Lt),
+    ("=", Comparator::Eq),
+];
+
+/// Dependency of a module on another module, written as `name` or as
+/// `name <op> version` with `op` one of `=`, `==`, `<`, `<=`, `>` or `>=`
+#[derive(Debug, Clone)]
+pub(crate) struct Requirement {
+    pub(crate) name: String,
+    constraint: Option<(Comparator, Version)>,
+    /// Normalized text of the requirement, for display
+    text: String,
+}
+
+impl Requirement {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let invalid = || format!("invalid requirement {:?}", text);
+
+        let end = text
+            .find(|c: char| c.is_whitespace() || "<>=".contains(c))
+            .unwrap_or_else(|| text.len());
+
+        let (name, rest) = text.split_at(end);
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(Requirement {
+                name: name.into(),
+                constraint: None,
+                text: name.into(),
+            });
+        }
+
+        let (op, comparator) = COMPARATORS
+            .iter()
+            .find(|(op, _)| rest.starts_with(op))
+            .ok_or_else(invalid)?;
+
+        let version = &rest[op.len()..];
+        let parsed = Version::parse(version).ok_or_else(invalid)?;
+
+        Ok(Requirement {
+            name: name.into(),
+            constraint: Some((*comparator, parsed)),
+            text: format!("{} {} {}", name, op, version.trim()),
+        })
+    }
+
+    /// Check a module at `version` satisfies this requirement, modules
+    /// without a valid version only satisfy unconstrained requirements
+    fn matches(&self, version: Option<&Version>) -> bool {
+        let (comparator, required) = match &self.constraint {
+            Some(constraint) => constraint,
+            None => return true,
+        };
+
+        let version = match version {
+            Some(version) => version,
+            None => return false,
+        };
+
+        match comparator {
+            Comparator::Eq => version == required,
+            Comparator::Lt => version < required,
+            Comparator::Le => version <= required,
+            Comparator::Gt => version > required,
+            Comparator::Ge => version >= required,
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Description of a loaded module, displayed by `fabric_list`
+#[derive(Debug, Default)]
+pub(crate) struct ModuleDesc {
+    pub(crate) path: PathBuf,
+    pub(crate) version: String,
+    pub(crate) author: String,
+    pub(crate) requires: Vec<Requirement>,
+    pub(crate) priority: i32,
+}
+
+/// A module found by the loader, with its parsed manifest
+pub(crate) struct ModuleSource {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) source: String,
+    /// None if the source could not be parsed
+    info: Option<ModuleInfo>,
+    /// None for modules without a manifest section, these modules are
+    /// only granted the permissions of their section of the configuration
+    pub(crate) manifest: Option<Manifest>,
+    /// Dependencies of the module, parsed when the load order is resolved
+    requires: Vec<Requirement>,
+}
+
+impl ModuleSource {
+    pub(crate) fn new(path: PathBuf, source: String) -> Self {
+        let info = inspect_module(&source);
+        let manifest = info.as_ref().and_then(|info| read_manifest(&path, info));
+
+        let name = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.name.clone())
+            .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        ModuleSource {
+            name,
+            path,
+            source,
+            info,
+            manifest,
+            requires: Vec::new(),
+        }
+    }
+
+    fn version(&self) -> Option<Version> {
+        Version::parse(&self.manifest.as_ref()?.version)
+    }
+
+    pub(crate) fn desc(&self) -> ModuleDesc {
+        let (version, author) = match &self.manifest {
+            Some(manifest) => (manifest.version.clone(), manifest.author.clone()),
+            None => Default::default(),
+        };
+
+        ModuleDesc {
+            path: self.path.clone(),
+            version,
+            author,
+            requires: self.requires.clone(),
+            priority: self.priority(),
+        }
+    }
+
+    /// Priority of the module: the one set with `fabric_priority`, then
+    /// the one in its section of the configuration, then its manifest
+    pub(crate) fn priority(&self) -> i32 {
+        if let Some(priority) = priorities().get(&self.name) {
+            return *priority;
+        }
+
+        config::get()
+            .modules
+            .get(&self.name)
+            .and_then(|module| module.priority)
+            .or_else(|| Some(self.manifest.as_ref()?.priority))
+            .unwrap_or(0)
+    }
+
+    /// Check this module is allowed to import from the host module `module`,
+    /// with a permission of its manifest or of its section of the configuration
+    fn is_allowed(&self, module: &str) -> bool {
+        let permission = match required_permission(module) {
+            Some(permission) => permission,
+            None => return true,
+        };
+
+        let config = config::get();
+        let configured = config
+            .modules
+            .get(&self.name)
+            .map_or(&[][..], |module| &module.permissions);
+        let declared = self
+            .manifest
+            .as_ref()
+            .map_or(&[][..], |manifest| &manifest.permissions);
+
+        declared
+            .iter()
+            .chain(configured)
+            .any(|name| name == permission)
+    }
+
+    /// Check the module can be loaded: all its imports must be provided
+    /// by the addon and granted by the permissions of its manifest
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let info = match &self.info {
+            Some(info) => info,
+            None => return Err("invalid module source".into()),
+        };
+
+        let required = self
+            .manifest
+            .iter()
+            .flat_map(|manifest| &manifest.requires_host);
+        let imported = info.imports.iter().map(|(module, _)| module);
+
+        for module in required.chain(imported) {
+            if module == RUNTIME_MODULE {
+                continue;
+            }
+
+            if !HOST_MODULES.contains(&module.as_str()) {
+                return Err(format!("unknown host module {}", module));
+            }
+
+            if !self.is_allowed(module) {
+                let permission = required_permission(module).unwrap_or_default();
+                return Err(format!(
+                    "host module {} requires the {:?} permission",
+                    module, permission
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the name of the module can be used as the name of its data
+    /// directory and files, and isn't the name of the `fabric_eval` modules
+    fn check_name(&self) -> Result<(), String> {
+        let is_valid = !self.name.starts_with('.')
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+
+        if self.name.is_empty() || !is_valid {
+            Err(format!("invalid module name {:?}", self.name))
+        } else if self.name == EVAL_MODULE {
+            Err(format!("the module name {:?} is reserved", self.name))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check the hash of the module file matches the one listed for it in
+    /// the configuration, or that it isn't required when there is none
+    fn verify(&self) -> Result<(), String> {
+        let config = config::get();
+        let expected = config
+            .modules
+            .get(&self.name)
+            .and_then(|module| module.sha256.as_ref());
+
+        let hash = sha256(self.source.as_bytes());
+        debug!("module {} has hash {}", self.name, hash);
+
+        match expected {
+            Some(expected) if hash.eq_ignore_ascii_case(expected.trim()) => Ok(()),
+            Some(_) => Err(format!("hash {} does not match the configuration", hash)),
+            None if config.runtime.require_hashes => {
+                Err(format!("hash {} is not listed in the configuration", hash))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Parse the dependencies of the module, declared in its
+    /// manifest and in the configuration of the addon
+    fn requirements(&self) -> Result<Vec<Requirement>, String> {
+        let manifest = self.manifest.iter().flat_map(|manifest| &manifest.requires);
+        let config = config::get()
+            .modules
+            .get(&self.name)
+            .into_iter()
+            .flat_map(|config| &config.requires);
+
+        manifest
+            .chain(config)
+            .map(|text| Requirement::parse(text))
+            .collect()
+    }
+
+    /// Check all the dependencies of the module are available with a
+    /// compatible version, either in `pending` or in `sorted`
+    fn unsatisfied(
+        &self,
+        pending: &[ModuleSource],
+        sorted: &[ModuleSource],
+        failed: &[String],
+    ) -> Option<String> {
+        for requirement in &self.requires {
+            let dependency = pending
+                .iter()
+                .chain(sorted)
+                .find(|module| module.name == requirement.name);
+
+            let dependency = match dependency {
+                Some(dependency) => dependency,
+                None if failed.contains(&requirement.name) => {
+                    return Some(format!(
+                        "requires {}, which could not be loaded",
+                        requirement
+                    ));
+                }
+                None => {
+                    return Some(format!("requires {}, which is not installed", requirement));
+                }
+            };
+
+            if !requirement.matches(dependency.version().as_ref()) {
+                let version = dependency.manifest.as_ref().map_or("", |m| &m.version);
+                return Some(format!(
+                    "requires {}, but version {:?} is installed",
+                    requirement, version
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// Priorities of the modules set with `fabric_priority`, persisted as a JSON
+/// object in the `data` directory of the addon. Only accessed from the game thread
+static mut PRIORITIES: Option<HashMap<String, i32>> = None;
+
+fn priorities_path() -> PathBuf {
+    config::get().root().join("data").join("priorities.json")
+}
+
+fn priorities() -> &'static mut HashMap<String, i32> {
+    unsafe {
+        PRIORITIES.get_or_insert_with(|| {
+            let path = priorities_path();
+            match fs::read(&path) {
+                Ok(source) => serde_json::from_slice(&source).unwrap_or_else(|err| {
+                    warn!("could not parse {}: {}", path.display(), err);
+                    HashMap::new()
+                }),
+                Err(err) => {
+                    debug!("could not read {}: {}", path.display(), err);
+                    HashMap::new()
+                }
+            }
+        })
+    }
+}
+
+/// Set the priority of the module `name`, or go back to the priority from
+/// its configuration and manifest for None. The priorities are written
+/// back to disk and apply the next time the modules are discovered
+pub(crate) fn set_priority(name: &str, priority: Option<i32>) {
+    let priorities = priorities();
+    match priority {
+        Some(priority) => priorities.insert(name.into(), priority),
+        None => priorities.remove(name),
+    };
+
+    let path = priorities_path();
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            warn!("could not create {}: {}", dir.display(), err);
+            return;
+        }
+    }
+
+    let result = serde_json::to_vec_pretty(priorities)
+        .map_err(|err| err.to_string())
+        .and_then(|data| fs::write(&path, data).map_err(|err| err.to_string()));
+
+    if let Err(err) = result {
+        warn!("could not write {}: {}", path.display(), err);
+    }
+}
+
+/// Hex-encoded SHA-256 hash of `data`
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn report(failed: &mut Vec<String>, module: ModuleSource, err: String) {
+    warn!(
+        "could not load module {} from {}: {}",
+        module.name,
+        module.path.display(),
+        err
+    );
+
+    failed.push(module.name);
+}
+
+/// Find a dependency cycle in `pending`, assuming all the modules in
+/// `pending` have at least one of their dependencies in `pending`
+fn find_cycle(pending: &[ModuleSource]) -> Vec<String> {
+    let mut path: Vec<&str> = Vec::new();
+    let mut current = match pending.first() {
+        Some(module) => module,
+        None => return Vec::new(),
+    };
+
+    loop {
+        if let Some(start) = path.iter().position(|name| *name == current.name) {
+            let mut cycle: Vec<_> = path[start..].iter().map(|name| name.to_string()).collect();
+            cycle.push(current.name.clone());
+            return cycle;
+        }
+
+        path.push(&current.name);
+
+        let next = current.requires.iter().find_map(|requirement| {
+            pending
+                .iter()
+                .find(|module| module.name == requirement.name)
+        });
+
+        current = match next {
+            Some(next) => next,
+            None => return Vec::new(),
+        };
+    }
+}
+
+/// Sort `modules` in load order: each module is loaded after the modules it
+/// requires, and independent modules keep their order in `modules`
+///
+/// Modules that can't be loaded, or whose dependencies are missing,
+/// incompatible or cyclic, are reported and removed from the list
+fn resolve(modules: Vec<ModuleSource>) -> Vec<ModuleSource> {
+    let mut pending: Vec<ModuleSource> = Vec::new();
+    // Names of the modules that could not be loaded
+    let mut failed = Vec::new();
+
+    for mut module in modules {
+        let requires = module
+            .check_name()
+            .and_then(|()| module.verify())
+            .and_then(|()| module.check())
+            .and_then(|()| module.requirements());
+        let requires = requires.and_then(|requires| {
+            if pending.iter().any(|other| other.name == module.name) {
+                Err(format!("another module is named {:?}", module.name))
+            } else {
+                Ok(requires)
+            }
+        });
+
+        match requires {
+            Ok(requires) => {
+                module.requires = requires;
+                pending.push(module);
+            }
+            Err(err) => report(&mut failed, module, err),
+        }
+    }
+
+    let mut sorted = Vec::new();
+
+    loop {
+        // Failing a module can leave the modules requiring it unsatisfied
+        // in turn, repeat until all the pending requirements can be resolved
+        while let Some((index, err)) = pending.iter().enumerate().find_map(|(index, module)| {
+            let err = module.unsatisfied(&pending, &sorted, &failed)?;
+            Some((index, err))
+        }) {
+            let module = pending.remove(index);
+            report(&mut failed, module, err);
+        }
+
+        // Always pick the first module whose dependencies are all loaded
+        // to keep the original order between independent modules
+        while let Some(index) = pending.iter().position(|module| {
+            module.requires.iter().all(|requirement| {
+                sorted
+                    .iter()
+                    .any(|other: &ModuleSource| other.name == requirement.name)
+            })
+        }) {
+            sorted.push(pending.remove(index));
+        }
+
+        if pending.is_empty() {
+            return sorted;
+        }
+
+        // Every remaining module is either part of a dependency
+        // cycle or requires a module that is part of a cycle
+        let cycle = find_cycle(&pending);
+        if cycle.is_empty() {
+            for module in pending.drain(..) {
+                report(&mut failed, module, "unresolved dependencies".into());
+            }
+
+            return sorted;
+        }
+
+        let err = format!("dependency cycle {}", cycle.join(" -> "));
+        while let Some(index) = pending
+            .iter()
+            .position(|module| cycle.contains(&module.name))
+        {
+            let module = pending.remove(index);
+            report(&mut failed, module, err.clone());
+        }
+    }
+}
+
+fn read_manifest(path: &Path, info: &ModuleInfo) -> Option<Manifest> {
+    let data = info.custom_section(MANIFEST_SECTION)?;
+
+    let manifest = match std::str::from_utf8(data) {
+        Ok(data) => toml::from_str(data).map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+
+    match manifest {
+        Ok(manifest) => Some(manifest),
+        Err(err) => {
+            // An invalid manifest grants no permissions
+            warn!("invalid manifest in {}: {}", path.display(), err);
+            Some(Manifest::default())
+        }
+    }
+}
+
+/// Find all the modules in the modules directory of the addon that can be
+/// loaded, sorted in load order. The bundled example module is loaded
+/// instead if the directory doesn't exist
+pub(crate) fn discover() -> Vec<ModuleSource> {
+    let dir = config::get().root().join(MODULES_DIR);
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            debug!("could not read {}: {}", dir.display(), err);
+
+            static SOURCE: &str = include_str!("../example.wat");
+            return resolve(vec![ModuleSource::new("example.wat".into(), SOURCE.into())]);
+        }
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wat"))
+        .collect();
+
+    paths.sort();
+
+    let mut modules: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| match fs::read_to_string(&path) {
+            Ok(source) => Some(ModuleSource::new(path, source)),
+            Err(err) => {
+                warn!("could not read {}: {}", path.display(), err);
+                None
+            }
+        })
+        .collect();
+
+    // The sort is stable, so modules with the same priority stay sorted by path
+    modules.sort_by_key(ModuleSource::priority);
+    resolve(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Module importing from the Http host module, which requires a permission
+    const HTTP_SOURCE: &str = r#"(module
+        (import "Http" "body_length" (func (param i32) (result i32))))"#;
+
+    fn http_source(manifest: Option<Manifest>) -> ModuleSource {
+        let mut source = ModuleSource::new(PathBuf::from("http_client.wat"), HTTP_SOURCE.into());
+        source.manifest = manifest;
+        source
+    }
+
+    fn manifest(permissions: &[&str]) -> Manifest {
+        Manifest {
+            permissions: permissions.iter().map(|name| name.to_string()).collect(),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn missing_manifest_grants_no_permissions() {
+        assert_eq!(
+            http_source(None).check(),
+            Err(String::from(
+                "host module Http requires the \"http\" permission"
+            ))
+        );
+    }
+
+    #[test]
+    fn manifest_grants_its_permissions() {
+        assert_eq!(http_source(Some(manifest(&["http"]))).check(), Ok(()));
+        assert!(http_source(Some(manifest(&["db"]))).check().is_err());
+        assert!(http_source(Some(manifest(&[]))).check().is_err());
+    }
+
+    #[test]
+    fn unrestricted_host_modules_need_no_permission() {
+        let source = ModuleSource::new(
+            PathBuf::from("countdown.wat"),
+            r#"(module (import "Timer" "cancel" (func (param i32) (result i32))))"#.into(),
+        );
+
+        assert!(source.manifest.is_none());
+        assert_eq!(source.check(), Ok(()));
+    }
+}
@@ -1,13 +1,30 @@
 #![allow(non_camel_case_types, dead_code)]
 
 use std::{
-    ffi::CString,
-    os::raw::{c_char, c_int, c_uint},
+    backtrace::Backtrace,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    ffi::{c_void, CStr, CString},
+    fs::{self, File, OpenOptions},
+    io::{self, LineWriter, Write},
+    os::raw::{c_char, c_int},
     panic::{set_hook, PanicInfo},
+    path::Path,
+    sync::{Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use fabric_codegen::cstr;
-use log::{set_logger_racy, set_max_level, trace, Level, LevelFilter, Log, Metadata, Record};
+use log::{
+    debug, set_logger_racy, set_max_level, trace, warn, Level, LevelFilter, Log, Metadata, Record,
+};
+use serde_json::json;
+
+use crate::{
+    config::ColorConfig,
+    engine::{engine, VEngineServer},
+    thread::is_game_thread,
+};
 
 type LoggingChannelID = c_int;
 
@@ -54,62 +71,591 @@ type RegisterTagsFunc = extern "C" fn();
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 struct Color {
-    _color: [c_uint; 4],
+    _color: [u8; 4],
 }
 
-// For linking purpose the symbols are loaded from the Alien Swarm SDK,
-// at runtime this will call into the tier0.dll of whatever game loaded this
-#[link(
-    name = "D:/SteamLibrary/SteamApps/common/Alien Swarm/sdk_src/lib/public/tier0",
-    kind = "dylib"
-)]
-extern "C" {
-    fn LoggingSystem_RegisterLoggingChannel(
-        pName: *const c_char,
-        registerTagsFunc: RegisterTagsFunc,
-        flags: c_int,
-        severity: LoggingSeverity,
-        color: Color,
-    ) -> LoggingChannelID;
+impl Color {
+    const fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Color {
+            _color: [red, green, blue, 255],
+        }
+    }
+
+    /// Parse a color in the `#rrggbb` or `#rrggbbaa` format
+    fn parse(value: &str) -> Option<Self> {
+        let digits = value.strip_prefix('#')?;
+        if (digits.len() != 6 && digits.len() != 8) || !digits.is_ascii() {
+            return None;
+        }
+
+        let mut color = [255; 4];
+        for (index, component) in color.iter_mut().enumerate().take(digits.len() / 2) {
+            *component = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).ok()?;
+        }
 
-    fn LoggingSystem_Log(
-        channelID: LoggingChannelID,
-        severity: LoggingSeverity,
-        pMessageFormat: *const c_char,
-    ) -> LoggingResponse;
+        Some(Color { _color: color })
+    }
 }
 
-struct Logger(LoggingChannelID);
+type LoggingSystem_RegisterLoggingChannel = extern "C" fn(
+    name: *const c_char,
+    register_tags_func: RegisterTagsFunc,
+    flags: c_int,
+    severity: LoggingSeverity,
+    color: Color,
+) -> LoggingChannelID;
+
+type LoggingSystem_LogDirect = extern "C" fn(
+    channel_id: LoggingChannelID,
+    severity: LoggingSeverity,
+    color: Color,
+    message: *const c_char,
+) -> LoggingResponse;
+
+/// Spew functions of the engines predating the logging system, `Msg` and
+/// `Warning`. These take a format string, the messages are passed as an
+/// argument to a `%s` format so they are printed verbatim
+type SpewFunc = unsafe extern "C" fn(format: *const c_char, ...);
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetModuleHandleA(lpModuleName: *const c_char) -> *mut c_void;
+    fn GetProcAddress(hModule: *mut c_void, lpProcName: *const c_char) -> *mut c_void;
+}
+
+/// Name of the tier0 library, it is loaded by the engine
+/// before any plugin so it is never loaded by the addon itself
+const TIER0: &CStr = cstr!("tier0.dll");
+
+/// Resolve `symbol` in the library `library` already loaded in the process
+pub(crate) fn find_symbol(library: &CStr, symbol: &CStr) -> Option<*mut c_void> {
+    let module = unsafe { GetModuleHandleA(library.as_ptr()) };
+    if module.is_null() {
+        return None;
+    }
+
+    let symbol = unsafe { GetProcAddress(module, symbol.as_ptr()) };
+    if symbol.is_null() {
+        None
+    } else {
+        Some(symbol)
+    }
+}
+
+/// Default colors of the log lines, by level from Error to Trace
+const DEFAULT_COLORS: [Color; 5] = [
+    Color::rgb(255, 64, 64),
+    Color::rgb(255, 192, 64),
+    Color::rgb(200, 200, 200),
+    Color::rgb(150, 150, 150),
+    Color::rgb(110, 110, 110),
+];
+
+/// Colors of the log lines, by level and by module
+struct Palette {
+    levels: [Color; 5],
+    /// Colors of the Info, Debug and Trace lines logged by specific
+    /// modules, errors and warnings always use the color of their level
+    modules: HashMap<String, Color>,
+}
+
+/// Read by the logger on every thread logging a record and replaced on
+/// the game thread when the configuration changes, set by `init_logger`
+static mut PALETTE: Option<RwLock<Palette>> = None;
+
+fn parse_color(name: &str, value: &str) -> Option<Color> {
+    let color = Color::parse(value);
+    if color.is_none() {
+        warn!("invalid color {:?} for {}", value, name);
+    }
+
+    color
+}
+
+/// Set the colors of the log lines from the configuration
+pub(crate) fn set_palette(config: &ColorConfig) {
+    let mut levels = DEFAULT_COLORS;
+    let colors = [
+        ("error", &config.error),
+        ("warn", &config.warn),
+        ("info", &config.info),
+        ("debug", &config.debug),
+        ("trace", &config.trace),
+    ];
+
+    for (color, (name, value)) in levels.iter_mut().zip(colors.iter()) {
+        if let Some(value) = value {
+            *color = parse_color(name, value).unwrap_or(*color);
+        }
+    }
+
+    let modules = config
+        .modules
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), parse_color(name, value)?)))
+        .collect();
+
+    if let Some(palette) = unsafe { &PALETTE } {
+        if let Ok(mut palette) = palette.write() {
+            *palette = Palette { levels, modules };
+        }
+    }
+}
+
+/// Color of the lines logged by `module` at `level`
+fn color(level: Level, module: Option<&str>) -> Color {
+    let palette = match unsafe { PALETTE.as_ref() }.and_then(|palette| palette.read().ok()) {
+        Some(palette) => palette,
+        None => return DEFAULT_COLORS[level as usize - 1],
+    };
+
+    let module = match (level, module) {
+        (Level::Error, _) | (Level::Warn, _) | (_, None) => None,
+        (_, Some(module)) => palette.modules.get(module),
+    };
+
+    module
+        .copied()
+        .unwrap_or(palette.levels[level as usize - 1])
+}
+
+/// Destination of the log lines
+enum Output {
+    /// Logging channel of the tier0 logging system
+    LoggingSystem {
+        channel: LoggingChannelID,
+        log: LoggingSystem_LogDirect,
+    },
+    /// The `Msg` and `Warning` functions of tier0, for the engine
+    /// branches that don't export the logging system
+    Spew { msg: SpewFunc, warning: SpewFunc },
+    /// `IVEngineServer::LogPrint`, once the engine interface is
+    /// acquired if tier0 doesn't export any of the above
+    Engine,
+    /// The standard error of the process and the fallback log
+    /// file, used until a better output is available
+    Fallback,
+}
+
+struct Logger {
+    output: Output,
+}
+
+/// Log file used by the fallback output, in addition to the standard error
+static mut FALLBACK_FILE: Option<Mutex<LineWriter<File>>> = None;
+
+/// Print the log lines with `IVEngineServer::LogPrint` if tier0 doesn't
+/// export any logging function, this must be called once the engine
+/// interface has been acquired
+pub(crate) fn set_engine_output() {
+    let logger = unsafe { &mut LOGGER };
+    if matches!(logger.output, Output::Fallback) && engine().is_some() {
+        logger.output = Output::Engine;
+        warn!("the engine doesn't export the tier0 logging functions, logging to the server log");
+    }
+}
+
+/// Also write the log lines to `path` if the logger is using the fallback
+/// output, since the standard error of the server isn't always visible
+pub(crate) fn set_fallback_file(path: &Path) {
+    let logger = unsafe { &LOGGER };
+    if !matches!(logger.output, Output::Fallback) {
+        return;
+    }
+
+    match open_append(path) {
+        Ok(file) => unsafe {
+            FALLBACK_FILE = Some(Mutex::new(LineWriter::new(file)));
+        },
+        Err(err) => warn!("could not open {}: {}", path.display(), err),
+    }
+}
+
+/// Open `path` for appending, creating it and its parent directories if needed
+fn open_append(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    OpenOptions::new().create(true).append(true).open(path)
+}
 
 impl Logger {
-    /// Print `message` into the logger's channel at `severity` level
-    ///
-    /// If message is too long it will be split into several successive call
-    /// to the logging function
-    fn print(&self, severity: LoggingSeverity, mut message: &str) {
-        while !message.is_empty() {
-            let mut index = message.len().min(254);
-            while !message.is_char_boundary(index) {
-                index -= 1;
+    /// Print `message` to the output of the logger at `severity` level
+    fn print(&self, severity: LoggingSeverity, color: Color, message: &str) {
+        match &self.output {
+            Output::LoggingSystem { channel, log } => {
+                print_channel(*log, *channel, severity, color, message)
             }
+            Output::Spew { msg, warning } => {
+                let spew = match severity {
+                    LoggingSeverity::Message => msg,
+                    _ => warning,
+                };
 
-            let (head, tail) = message.split_at(index);
-            message = tail;
+                if let Ok(message) = CString::new(message) {
+                    unsafe {
+                        spew(cstr!("%s").as_ptr(), message.as_ptr());
+                    }
+                }
+            }
+            // The engine interface can't be used from the worker threads
+            Output::Engine if !is_game_thread() => eprint!("{}", message),
+            Output::Engine => {
+                let mut engine = match engine() {
+                    Some(engine) => engine,
+                    None => return,
+                };
 
-            if let Ok(line) = CString::new(head) {
-                unsafe {
-                    LoggingSystem_Log(self.0, severity, line.as_ptr());
+                if let Ok(message) = CString::new(message) {
+                    engine.log_print(&message);
                 }
             }
+            Output::Fallback => {
+                eprint!("{}", message);
+
+                if let Some(file) = unsafe { &FALLBACK_FILE } {
+                    if let Ok(mut file) = file.lock() {
+                        let _ = file.write_all(message.as_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Print `message` into the logging channel `channel` at `severity` level
+///
+/// If message is too long it will be split into several successive call
+/// to the logging function
+fn print_channel(
+    log: LoggingSystem_LogDirect,
+    channel: LoggingChannelID,
+    severity: LoggingSeverity,
+    color: Color,
+    mut message: &str,
+) {
+    while !message.is_empty() {
+        let mut index = message.len().min(254);
+        while !message.is_char_boundary(index) {
+            index -= 1;
+        }
+
+        let (head, tail) = message.split_at(index);
+        message = tail;
+
+        if let Ok(line) = CString::new(head) {
+            log(channel, severity, color, line.as_ptr());
+        }
+    }
+}
+
+/// Level of the records printed by the logger, in total and per target
+struct Filter {
+    level: LevelFilter,
+    /// Targets with a different level than `level`, the
+    /// longest matching target prefix takes precedence
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || (target.starts_with(prefix.as_str())
+                        && target[prefix.len()..].starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    /// Most verbose level of the filter, used as the maximum level of
+    /// the log facade to skip the records that would be ignored anyway
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Ord::max)
+    }
+}
+
+/// Level of the records printed before the logger is initialized
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// Read by the logger on every thread logging a record and updated on
+/// the game thread when the configuration changes, set by `init_logger`
+static mut FILTER: Option<RwLock<Filter>> = None;
+
+/// Apply `update` to the filter, then lower the maximum level of the
+/// log facade to the most verbose level of the new filter
+fn update_filter(update: impl FnOnce(&mut Filter)) {
+    let filter = match unsafe { &FILTER } {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    if let Ok(mut filter) = filter.write() {
+        update(&mut filter);
+        set_max_level(filter.max_level());
+    }
+}
+
+/// Set the level of the records printed by the logger, returns
+/// false if `level` isn't the name of a log level
+pub(crate) fn set_level(level: &str) -> bool {
+    let level = match level.trim().parse() {
+        Ok(level) => level,
+        Err(_) => {
+            warn!("invalid log level {:?}", level);
+            return false;
+        }
+    };
+
+    update_filter(|filter| filter.level = level);
+    true
+}
+
+/// Set the level of specific targets from a comma-separated list of
+/// `target=level` directives, a target without a level is turned off.
+/// Returns false if one of the levels is invalid
+pub(crate) fn set_targets(targets: &str) -> bool {
+    let mut directives = Vec::new();
+    for directive in targets.split(',').map(str::trim) {
+        if directive.is_empty() {
+            continue;
+        }
+
+        let (target, level) = match directive.find('=') {
+            Some(index) => (&directive[..index], &directive[index + 1..]),
+            None => (directive, "off"),
+        };
+
+        match level.trim().parse() {
+            Ok(level) => directives.push((target.trim().to_string(), level)),
+            Err(_) => {
+                warn!("invalid log level {:?} for {:?}", level, target);
+                return false;
+            }
         }
     }
+
+    update_filter(|filter| filter.targets = directives);
+    true
+}
+
+/// Number of log lines kept in memory for each module and for the addon itself
+const HISTORY_LINES: usize = 256;
+
+thread_local! {
+    /// Name of the module whose code is running on this thread, if any
+    static CURRENT_MODULE: Cell<Option<*const str>> = Cell::new(None);
+    /// Name of the game event being dispatched on this thread, if any
+    static CURRENT_EVENT: RefCell<Option<String>> = RefCell::new(None);
+    /// Last panic on this thread, kept for the crash reports
+    static LAST_PANIC: RefCell<Option<PanicReport>> = RefCell::new(None);
+}
+
+/// Description of a panic, recorded by the panic hook
+pub(crate) struct PanicReport {
+    pub(crate) message: String,
+    pub(crate) backtrace: String,
+}
+
+/// Take the last panic that happened on this thread
+pub(crate) fn take_last_panic() -> Option<PanicReport> {
+    LAST_PANIC.with(|panic| panic.borrow_mut().take())
+}
+
+/// Attributes the records logged on this thread to a module until it is dropped
+pub(crate) struct ModuleScope(Option<*const str>);
+
+impl ModuleScope {
+    /// The scope must be dropped before `name`, it is only used to run a
+    /// module for the duration of a call while its environment is locked
+    pub(crate) fn enter(name: &str) -> Self {
+        let previous = CURRENT_MODULE.with(|current| current.replace(Some(name as *const str)));
+        ModuleScope(previous)
+    }
+}
+
+impl Drop for ModuleScope {
+    fn drop(&mut self) {
+        CURRENT_MODULE.with(|current| current.set(self.0));
+    }
+}
+
+/// Attributes the records logged on this thread to
+/// the dispatch of a game event until it is dropped
+pub(crate) struct EventScope(Option<String>);
+
+impl EventScope {
+    pub(crate) fn enter(name: String) -> Self {
+        let previous = CURRENT_EVENT.with(|current| current.replace(Some(name)));
+        EventScope(previous)
+    }
+}
+
+impl Drop for EventScope {
+    fn drop(&mut self) {
+        CURRENT_EVENT.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+/// Name of the game event being dispatched on this thread, if any
+pub(crate) fn current_event() -> Option<String> {
+    CURRENT_EVENT.with(|event| event.borrow().clone())
+}
+
+/// Returns true if the code of a module is running on this thread
+pub(crate) fn in_module() -> bool {
+    with_current_module(|name| name.is_some())
+}
+
+/// Call `func` with the name of the module running on this thread
+fn with_current_module<R>(func: impl FnOnce(Option<&str>) -> R) -> R {
+    let name = CURRENT_MODULE.with(Cell::get);
+    func(name.map(|name| unsafe { &*name }))
+}
+
+/// Recent log lines, keyed by module name, with the lines logged
+/// outside of a module stored under the `None` key
+type History = HashMap<Option<String>, VecDeque<String>>;
+
+static mut HISTORY: Option<Mutex<History>> = None;
+
+fn record_history(module: Option<&str>, line: &str) {
+    let history = match unsafe { &HISTORY } {
+        Some(history) => history,
+        None => return,
+    };
+
+    if let Ok(mut history) = history.lock() {
+        let lines = history.entry(module.map(String::from)).or_default();
+        if lines.len() >= HISTORY_LINES {
+            lines.pop_front();
+        }
+
+        lines.push_back(line.trim_end().to_string());
+    }
+}
+
+/// Recent log lines of `module`, or of the addon itself for None
+pub(crate) fn history(module: Option<&str>) -> Vec<String> {
+    let history = match unsafe { &HISTORY } {
+        Some(history) => history,
+        None => return Vec::new(),
+    };
+
+    match history.lock() {
+        Ok(history) => history
+            .get(&module.map(String::from))
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Handler of the `fabric_log_dump` console command, prints the recent log
+/// lines of a module or of the addon itself if no module name is given
+///
+/// The lines are printed directly to the logging channel,
+/// so they are not added to the history again
+pub(crate) fn dump_history(args: &[String]) {
+    let module = args.get(1).cloned();
+    let logger = unsafe { &LOGGER };
+
+    let lines = history(module.as_deref());
+
+    let name = module.as_deref().unwrap_or("fabric");
+    let color = color(Level::Info, module.as_deref());
+    logger.print(
+        LoggingSeverity::Message,
+        color,
+        &format!("{} recent log lines for {}\n", lines.len(), name),
+    );
+
+    for line in lines {
+        logger.print(LoggingSeverity::Message, color, &format!("{}\n", line));
+    }
+}
+
+/// Structured output of the logger, every record is written
+/// to it as a JSON object on its own line
+static mut JSON_OUTPUT: Option<Mutex<LineWriter<File>>> = None;
+
+/// Write the log records as JSON lines to `path` in addition to the
+/// logging channel of the engine, appending them to the file if it exists
+pub(crate) fn set_json_output(path: &Path) {
+    match open_append(path) {
+        Ok(file) => unsafe {
+            JSON_OUTPUT = Some(Mutex::new(LineWriter::new(file)));
+        },
+        Err(err) => warn!("could not open {}: {}", path.display(), err),
+    }
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC with millisecond precision
+fn format_timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Convert the number of days since the epoch to a date of the
+    // proleptic Gregorian calendar, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+fn write_json(record: &Record, module: Option<&str>) {
+    let output = match unsafe { &JSON_OUTPUT } {
+        Some(output) => output,
+        None => return,
+    };
+
+    let event = current_event();
+    let line = json!({
+        "timestamp": format_timestamp(SystemTime::now()),
+        "module": module,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "event": event,
+    });
+
+    // Errors can't be logged from the logger itself and are ignored
+    if let Ok(mut output) = output.lock() {
+        let _ = writeln!(output, "{}", line);
+    }
 }
 
 impl Log for Logger {
-    fn enabled(&self, _meta: &Metadata) -> bool {
-        true
-        // let target = meta.target();
-        // target.starts_with("fabric")
+    fn enabled(&self, meta: &Metadata) -> bool {
+        let filter = unsafe { FILTER.as_ref() }.and_then(|filter| filter.read().ok());
+        match filter {
+            Some(filter) => meta.level() <= filter.level(meta.target()),
+            None => meta.level() <= DEFAULT_LEVEL,
+        }
     }
 
     fn log(&self, record: &Record) {
@@ -117,32 +663,65 @@ impl Log for Logger {
             return;
         }
 
-        let line = format!(
-            "[{} {}] {}\n",
-            record.level(),
-            record.target(),
-            record.args()
-        );
+        let (line, color) = with_current_module(|module| {
+            let line = match module {
+                Some(module) => format!(
+                    "[{} {} {}] {}\n",
+                    record.level(),
+                    module,
+                    record.target(),
+                    record.args()
+                ),
+                None => format!(
+                    "[{} {}] {}\n",
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ),
+            };
+
+            record_history(module, &line);
+            write_json(record, module);
+            (line, color(record.level(), module))
+        });
 
         let severity = match record.level() {
-            Level::Error => LoggingSeverity::Warning,
+            Level::Error => LoggingSeverity::Error,
             Level::Warn => LoggingSeverity::Warning,
             Level::Info => LoggingSeverity::Message,
             Level::Debug => LoggingSeverity::Message,
             Level::Trace => LoggingSeverity::Message,
         };
 
-        self.print(severity, &line);
+        self.print(severity, color, &line);
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(output) = unsafe { &JSON_OUTPUT } {
+            if let Ok(mut output) = output.lock() {
+                let _ = output.flush();
+            }
+        }
+    }
 }
 
-static mut LOGGER: Logger = Logger(0);
+static mut LOGGER: Logger = Logger {
+    output: Output::Fallback,
+};
 
 fn log_panic(info: &PanicInfo) {
     let logger = unsafe { &LOGGER };
-    logger.print(LoggingSeverity::Error, &info.to_string());
+    logger.print(
+        LoggingSeverity::Error,
+        color(Level::Error, None),
+        &info.to_string(),
+    );
+
+    let report = PanicReport {
+        message: info.to_string(),
+        backtrace: Backtrace::force_capture().to_string(),
+    };
+    LAST_PANIC.with(|panic| *panic.borrow_mut() = Some(report));
 }
 
 /// Initialize the logging facade
@@ -154,20 +733,66 @@ pub(crate) fn init_logger() {
     extern "C" fn register() {}
 
     unsafe {
-        LOGGER.0 = LoggingSystem_RegisterLoggingChannel(
+        HISTORY = Some(Mutex::new(HashMap::new()));
+        FILTER = Some(RwLock::new(Filter {
+            level: DEFAULT_LEVEL,
+            targets: Vec::new(),
+        }));
+        PALETTE = Some(RwLock::new(Palette {
+            levels: DEFAULT_COLORS,
+            modules: HashMap::new(),
+        }));
+    }
+
+    // The logging system is resolved at runtime from the tier0 library of
+    // the engine, the logger falls back to the older spew functions if the
+    // engine predates it, then to the standard error if neither is exported
+    let register_channel = find_symbol(TIER0, cstr!("LoggingSystem_RegisterLoggingChannel"));
+    let log_direct = find_symbol(TIER0, cstr!("LoggingSystem_LogDirect"));
+    let msg = find_symbol(TIER0, cstr!("Msg"));
+    let warning = find_symbol(TIER0, cstr!("Warning"));
+
+    if let (Some(register_channel), Some(log_direct)) = (register_channel, log_direct) {
+        let register_channel: LoggingSystem_RegisterLoggingChannel =
+            unsafe { std::mem::transmute(register_channel) };
+        let channel = register_channel(
             cstr!("fabric").as_ptr(),
             register,
             0,
             LoggingSeverity::Message,
-            Color { _color: [0; 4] },
+            DEFAULT_COLORS[Level::Info as usize - 1],
         );
+
+        unsafe {
+            LOGGER.output = Output::LoggingSystem {
+                channel,
+                log: std::mem::transmute(log_direct),
+            };
+        }
+    } else if let (Some(msg), Some(warning)) = (msg, warning) {
+        unsafe {
+            LOGGER.output = Output::Spew {
+                msg: std::mem::transmute(msg),
+                warning: std::mem::transmute(warning),
+            };
+        }
     }
 
     if let Err(err) = unsafe { set_logger_racy(&LOGGER) } {
         println!("Failed to set logger: {:?}", err);
     } else {
-        set_max_level(LevelFilter::Debug);
+        set_max_level(DEFAULT_LEVEL);
         trace!("Logger initialized");
+
+        match unsafe { &LOGGER.output } {
+            Output::Spew { .. } => {
+                debug!("the engine doesn't export the tier0 logging system, using Msg")
+            }
+            Output::Fallback => {
+                warn!("the engine doesn't export the tier0 logging functions, logging to stderr")
+            }
+            _ => {}
+        }
     }
 
     set_hook(Box::new(log_panic));
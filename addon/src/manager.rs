@@ -1,12 +1,23 @@
 use std::{
-    ffi::{c_void, CStr},
-    os::raw::{c_char, c_int, c_uchar},
+    ffi::{c_void, CStr, CString},
+    os::raw::c_int,
+    ptr::null_mut,
+    sync::TryLockError,
+    time::Instant,
 };
 
-use fabric_runtime::{with_abi, ExternRef, VMContext};
-use log::info;
-
-use crate::module::{FabricEnv, Module};
+use fabric_runtime::{with_abi, Callback, ExternRef, FuncRef, VMContext};
+use log::{debug, info, warn};
+
+use crate::{
+    bitbuf::{bf_read, bf_write},
+    custom_events,
+    deferred::{self, Capability},
+    foreign::{create_interface, CreateInterfaceFn, Foreign},
+    game,
+    logging::EventScope,
+    module::{call_guest, is_paused, queue_guest, FabricEnv, Module},
+};
 
 #[fabric_codegen::interface]
 pub(crate) trait GameEvent {
@@ -35,27 +46,15 @@ pub(crate) trait GameEvent {
     fn set_string(&mut self, name: &CStr, value: &CStr);
 }
 
-#[repr(C)]
-#[allow(dead_code)]
-pub(crate) struct bf_write {
-    /// The current buffer.
-    data: *mut c_uchar,
-    data_bytes: c_int,
-    data_bits: c_int,
-
-    /// Where we are in the buffer.
-    cur_bit: c_int,
-
-    /// Errors?
-    overflow: bool,
-
-    assert_on_overflow: bool,
-    debug_name: *const c_char,
-}
-
-#[allow(non_camel_case_types)]
-type bf_read = c_void;
-
+/// Binding for IGameEventManager2
+///
+/// The methods receiving or returning engine events use the raw pointer to the event object: the
+/// engine accesses the internals of its own event class, so the events can't be passed
+/// back through the wrappers generated for `&mut dyn GameEvent` arguments
+///
+/// The listeners are passed as raw pointers too, the engine finds and removes them by
+/// comparing their address with the ones it was given. The generated wrappers would
+/// allocate a new object on each call, see `FabricListener` for the wrapper the addon keeps
 #[fabric_codegen::interface]
 pub(crate) trait GameEventManager2 {
     fn destructor(&self);
@@ -67,39 +66,34 @@ pub(crate) trait GameEventManager2 {
     fn reset(&mut self);
 
     // adds a listener for a particular event
-    fn add_listener(
-        &mut self,
-        listener: Box<dyn GameEventListener2>,
-        name: &CStr,
-        server_side: bool,
-    ) -> bool;
+    fn add_listener(&mut self, listener: *mut c_void, name: &CStr, server_side: bool) -> bool;
 
     // returns true if this listener is listens to given event
-    fn find_listener(&mut self, listener: &mut dyn GameEventListener2, name: &CStr) -> bool;
+    fn find_listener(&mut self, listener: *mut c_void, name: &CStr) -> bool;
 
     // removes a listener
-    fn remove_listener(&mut self, listener: &mut dyn GameEventListener2);
+    fn remove_listener(&mut self, listener: *mut c_void);
 
     // create an event by name, but doesn't fire it. returns NULL is event is not
     // known or no listener is registered for it. bForce forces the creation even if no listener is active
-    fn create_event(&mut self, name: &CStr, force: bool, cookie: *mut c_int) -> Box<dyn GameEvent>;
+    fn create_event(&mut self, name: &CStr, force: bool, cookie: *mut c_int) -> *mut c_void;
 
     // fires a server event created earlier, if bDontBroadcast is set, event is not send to clients
-    fn fire_event(&mut self, event: &mut dyn GameEvent, dont_broadcast: bool) -> bool;
+    fn fire_event(&mut self, event: *mut c_void, dont_broadcast: bool) -> bool;
 
     // fires an event for the local client only, should be used only by client code
     fn fire_event_client_side(&mut self, event: &mut dyn GameEvent) -> bool;
 
     // create a new copy of this event, must be free later
-    fn duplicate_event(&mut self, event: &mut dyn GameEvent) -> Box<dyn GameEvent>;
+    fn duplicate_event(&mut self, event: *mut c_void) -> *mut c_void;
 
     // if an event was created but not fired for some reason, it has to bee freed, same UnserializeEvent
-    fn free_event(&mut self, event: &mut dyn GameEvent);
+    fn free_event(&mut self, event: *mut c_void);
 
     // write/read event to/from bitbuffer
-    fn serialize_event(&mut self, event: &mut dyn GameEvent, buf: *mut bf_write) -> bool;
+    fn serialize_event(&mut self, event: *mut c_void, buf: *mut bf_write) -> bool;
     // create new KeyValues, must be deleted
-    fn unserialize_event(&mut self, buf: *mut bf_read) -> Box<dyn GameEvent>;
+    fn unserialize_event(&mut self, buf: *mut bf_read) -> *mut c_void;
 }
 
 #[fabric_codegen::interface]
@@ -108,33 +102,497 @@ pub(crate) trait GameEventListener2 {
 
     /// FireEvent is called by EventManager if event just occured
     /// KeyValue memory will be freed by manager if not needed anymore
-    fn fire_game_event(&mut self, event: Box<dyn GameEvent>);
+    fn fire_game_event(&mut self, event: *mut c_void);
 
     fn get_event_debug_id(&mut self) -> c_int;
 }
 
+static mut MANAGER: *mut c_void = null_mut();
+
+/// Acquire the game event manager from the engine factory, this
+/// must be called from the game thread when the addon is loaded
+///
+/// Older engine branches only provide GAMEEVENTSMANAGER001, it is
+/// then used through an adapter implementing GameEventManager2
+pub(crate) fn init(factory: CreateInterfaceFn) -> Option<Foreign<dyn GameEventManager2>> {
+    let version = game::interfaces().game_events;
+    let manager = match version.and_then(|version| create_interface(factory, version)) {
+        Some(manager) => manager,
+        None => crate::legacy_events::init(factory)?,
+    };
+    unsafe {
+        MANAGER = manager.0;
+    }
+
+    Some(manager)
+}
+
+/// Get the game event manager, looked up again at most once per level while it is missing
+pub(crate) fn manager() -> Option<Foreign<dyn GameEventManager2>> {
+    let mut manager = unsafe { MANAGER };
+    if manager.is_null() && deferred::retry(Capability::GameEvents) {
+        manager = unsafe { MANAGER };
+    }
+
+    if manager.is_null() {
+        None
+    } else {
+        Some(Foreign::with(manager))
+    }
+}
+
+/// Copy of an event fired while the listening module was paused,
+/// freed once it has been delivered or dropped
+struct QueuedEvent(*mut c_void);
+
+impl QueuedEvent {
+    /// Copy `event`, the copy is null if the event could not be copied
+    fn duplicate(event: *mut c_void) -> Self {
+        if custom_events::is_custom(event) {
+            return QueuedEvent(custom_events::duplicate(event));
+        }
+
+        match manager() {
+            Some(mut manager) => QueuedEvent(manager.duplicate_event(event)),
+            None => QueuedEvent(null_mut()),
+        }
+    }
+}
+
+impl Drop for QueuedEvent {
+    fn drop(&mut self) {
+        if self.0.is_null() {
+            return;
+        }
+
+        if custom_events::is_custom(self.0) {
+            custom_events::free(self.0);
+        } else if let Some(mut manager) = manager() {
+            manager.free_event(self.0);
+        }
+    }
+}
+
+/// Event owned by the engine and lent to a listener for the duration of the
+/// call, the module can read and modify it but not fire or free it
+pub(crate) struct BorrowedEvent(pub(crate) Foreign<dyn GameEvent>);
+
 pub(crate) type ListenerFunc = with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef));
+/// Listener registered with `LISTENER_CONSUME`, returning non-zero to consume the event
+pub(crate) type ConsumingListenerFunc = with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32);
+
+/// Flags of the `add_listener` import
+pub(crate) const LISTENER_SERVER_SIDE: i32 = 1 << 0;
+pub(crate) const LISTENER_CONSUME: i32 = 1 << 1;
+
+/// Guest function called with the events a module listens to
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ListenerCallback {
+    Observe(Callback<ListenerFunc>),
+    /// The listener can consume the event, the modules following
+    /// it in the dispatch order don't receive it
+    Consume(Callback<ConsumingListenerFunc>),
+}
 
-/// Wrapper implementing GameEventListener2 for a listener function declared in WASM,
-pub(crate) struct FabricListener {
+impl ListenerCallback {
+    pub(crate) fn function(&self) -> FuncRef {
+        match self {
+            ListenerCallback::Observe(callback) => callback.function(),
+            ListenerCallback::Consume(callback) => callback.function(),
+        }
+    }
+
+    /// Call the listener with `event`, returns true if it consumed the event
+    fn call(self, ctx: &mut VMContext<FabricEnv>, event: Foreign<dyn GameEvent>) -> bool {
+        let handle = ctx.create_extern(BorrowedEvent(event));
+        let consumed = match self {
+            ListenerCallback::Observe(callback) => {
+                callback.native()(ctx, handle);
+                false
+            }
+            ListenerCallback::Consume(callback) => callback.native()(ctx, handle) != 0,
+        };
+
+        // Missing if the module was unloaded by the call
+        ctx.externs.try_take_extern::<BorrowedEvent>(handle).ok();
+        consumed
+    }
+}
+
+/// Listener declared by a module, called by the dispatcher of its event
+#[derive(Clone)]
+pub(crate) struct Subscription {
     pub(crate) module: Module,
-    pub(crate) listener: ListenerFunc,
+    pub(crate) callback: ListenerCallback,
+    /// Index of the listener in the `listeners` of the module
+    pub(crate) index: usize,
+    /// Name of the module, the module can't be locked to read it while it runs
+    pub(crate) name: String,
+    /// Generation of the instance of the module that declared the listener,
+    /// the events deferred for a previous instance are dropped
+    pub(crate) generation: u32,
+    /// Priority of the module, see `Manifest::priority`
+    pub(crate) priority: i32,
 }
 
-impl GameEventListener2 for FabricListener {
-    fn destructor(&self) {
-        info!("destructor");
+/// Listeners of the modules for an event on one side of the engine,
+/// in dispatch order: ascending priority, then registration order
+struct Dispatcher {
+    event: CString,
+    server_side: bool,
+    /// Wrapper registered with the engine, the engine identifies the
+    /// listener by this address in `find_listener` and `remove_listener`
+    listener: *mut CGameEventListener2<Box<FabricListener>>,
+    subscriptions: Vec<Subscription>,
+}
+
+/// Only accessed from the game thread
+static mut DISPATCHERS: Vec<Dispatcher> = Vec::new();
+
+/// Wrapper implementing GameEventListener2 for the dispatcher of an event,
+/// registered with the engine the first time a module listens to the event
+pub(crate) struct FabricListener {
+    event: CString,
+    server_side: bool,
+}
+
+static LISTENER_VTABLE: IGameEventListener2 =
+    <dyn GameEventListener2>::vtable::<Box<FabricListener>, FabricListener>();
+
+impl Dispatcher {
+    fn new(event: &CStr, server_side: bool) -> Self {
+        let listener = Box::new(CGameEventListener2 {
+            vtable: &LISTENER_VTABLE,
+            instance: Box::new(FabricListener {
+                event: event.to_owned(),
+                server_side,
+            }),
+        });
+
+        Dispatcher {
+            event: event.to_owned(),
+            server_side,
+            listener: Box::into_raw(listener),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    fn pointer(&self) -> *mut c_void {
+        self.listener as *mut c_void
     }
 
-    fn fire_game_event(&mut self, event: Box<dyn GameEvent>) {
-        info!("fire_game_event {:?}", event.get_name().to_string_lossy());
+    /// Remove the listener of the dispatcher from the engine and free it,
+    /// the listener is leaked if the engine still holds it afterwards
+    fn unregister(self, manager: &mut Foreign<dyn GameEventManager2>) {
+        if manager.find_listener(self.pointer(), &self.event) {
+            manager.remove_listener(self.pointer());
 
-        let mut lock = self.module.lock().unwrap();
-        let handle = lock.externs.create_extern(event);
+            if manager.find_listener(self.pointer(), &self.event) {
+                warn!("could not remove the listener for {:?}", self.event);
+                return;
+            }
+        }
 
-        (self.listener)(&mut *lock, handle);
+        unsafe { drop(Box::from_raw(self.listener)) };
+    }
+}
+
+/// Add the listener of a module to the dispatcher of `event`, registering
+/// the dispatcher with the engine if it isn't registered yet
+///
+/// Returns a description of the error if the engine refused the dispatcher,
+/// or if the module already listens to the event with the same function
+pub(crate) fn subscribe(
+    manager: &mut Foreign<dyn GameEventManager2>,
+    event: &CStr,
+    server_side: bool,
+    subscription: Subscription,
+) -> Result<(), String> {
+    let dispatchers = unsafe { &mut DISPATCHERS };
+
+    let index = match dispatchers
+        .iter()
+        .position(|dispatcher| *dispatcher.event == *event && dispatcher.server_side == server_side)
+    {
+        Some(index) => index,
+        None => {
+            dispatchers.push(Dispatcher::new(event, server_side));
+            dispatchers.len() - 1
+        }
+    };
+
+    let dispatcher = &mut dispatchers[index];
+    let function = subscription.callback.function();
+    if dispatcher
+        .subscriptions
+        .iter()
+        .any(|other| other.name == subscription.name && other.callback.function() == function)
+    {
+        return Err(format!("{:?} already listens to the event", function));
+    }
+
+    // The engine drops all its listeners when it is reset, the dispatcher
+    // is registered again in that case. The custom events of the modules
+    // are dispatched by the addon, the engine doesn't know them
+    let pointer = dispatcher.pointer();
+    if !custom_events::is_custom_name(event.to_bytes())
+        && !manager.find_listener(pointer, event)
+        && !manager.add_listener(pointer, event, server_side)
+    {
+        if dispatcher.subscriptions.is_empty() {
+            dispatchers.remove(index).unregister(manager);
+        }
+
+        return Err("the game events manager refused the listener".into());
+    }
+
+    // Keep the subscriptions sorted, after the ones with the same priority
+    let subscriptions = &mut dispatcher.subscriptions;
+    let index = subscriptions
+        .iter()
+        .position(|other| other.priority > subscription.priority)
+        .unwrap_or_else(|| subscriptions.len());
+    subscriptions.insert(index, subscription);
+
+    Ok(())
+}
+
+/// Remove the listeners of the module `name` from the dispatchers, the
+/// dispatchers left without listeners are removed from the engine
+pub(crate) fn unsubscribe(name: &str) {
+    let dispatchers = unsafe { &mut DISPATCHERS };
+    for dispatcher in dispatchers.iter_mut() {
+        dispatcher
+            .subscriptions
+            .retain(|subscription| subscription.name != name);
+    }
+
+    let (unused, used): (Vec<_>, Vec<_>) = dispatchers
+        .drain(..)
+        .partition(|dispatcher| dispatcher.subscriptions.is_empty());
+    *dispatchers = used;
+    unregister_all(unused);
+}
+
+/// Remove the listeners of all the modules and the dispatchers from the engine
+pub(crate) fn clear_subscriptions() {
+    let dispatchers = unsafe { std::mem::take(&mut DISPATCHERS) };
+    unregister_all(dispatchers);
+}
+
+fn unregister_all(dispatchers: Vec<Dispatcher>) {
+    let mut manager = match manager() {
+        Some(manager) => manager,
+        // Without a manager the listeners were never registered
+        None => return,
+    };
+
+    for dispatcher in dispatchers {
+        dispatcher.unregister(&mut manager);
+    }
+}
+
+/// Event fired to a module while it was running, delivered by `call_guest`
+/// once the callback of the module returns
+struct PendingEvent {
+    module: String,
+    generation: u32,
+    callback: ListenerCallback,
+    index: usize,
+    name: String,
+    event: QueuedEvent,
+}
+
+/// Only accessed from the game thread
+static mut PENDING: Vec<PendingEvent> = Vec::new();
+
+impl Subscription {
+    /// Keep a copy of `event` until the module can receive it
+    fn defer(&self, event: *mut c_void, name: String) {
+        let event = QueuedEvent::duplicate(event);
+
+        if event.0.is_null() {
+            warn!(
+                "could not copy event {:?} fired to a running module, dropping it",
+                name
+            );
+            return;
+        }
+
+        unsafe {
+            PENDING.push(PendingEvent {
+                module: self.name.clone(),
+                generation: self.generation,
+                callback: self.callback,
+                index: self.index,
+                name,
+                event,
+            });
+        }
+    }
+
+    /// Call the listener of the module with `event`, returns true if it
+    /// consumed the event
+    ///
+    /// A module that is running or paused receives a copy of the event
+    /// later, it can't consume the event for the following modules anymore
+    fn deliver(&self, event: *mut c_void, name: &str) -> bool {
+        let mut lock = match self.module.try_lock() {
+            Ok(lock) => lock,
+            // The module is running and fired the event from one of its callbacks,
+            // it receives a copy of the event once the callback returns
+            Err(TryLockError::WouldBlock) => {
+                self.defer(event, name.into());
+                return false;
+            }
+            Err(TryLockError::Poisoned(err)) => panic!("{}", err),
+        };
+
+        if lock.environment.generation != self.generation {
+            return false;
+        }
+
+        let callback = self.callback;
+
+        if let Some(listener) = lock.environment.listeners.get_mut(self.index) {
+            listener.fired += 1;
+            listener.last_fired = Some(Instant::now());
+        }
+
+        // The event is freed by the engine once it has been fired,
+        // a paused module receives a copy of it when it is unpaused
+        if is_paused(&lock) {
+            let queued = QueuedEvent::duplicate(event);
+
+            if queued.0.is_null() {
+                warn!("could not copy event {:?}, dropping it", name);
+                return false;
+            }
+
+            let name = name.to_string();
+            queue_guest(&mut lock, "event", move |ctx| {
+                let _scope = EventScope::enter(name);
+                callback.call(ctx, Foreign::with(queued.0));
+            });
+            return false;
+        }
+
+        call_guest(&mut lock, "event", |ctx| {
+            callback.call(ctx, Foreign::with(event))
+        })
+        .unwrap_or(false)
+    }
+}
+
+/// Deliver the events fired to the module of `ctx` while it was running,
+/// including the ones fired by the listeners being called
+pub(crate) fn deliver_pending(ctx: &mut VMContext<FabricEnv>) {
+    loop {
+        let pending = unsafe { &mut PENDING };
+        let event = match pending
+            .iter()
+            .position(|event| event.module == ctx.environment.name)
+        {
+            Some(index) => pending.remove(index),
+            None => return,
+        };
+
+        if event.generation != ctx.environment.generation {
+            continue;
+        }
+
+        if let Some(listener) = ctx.environment.listeners.get_mut(event.index) {
+            listener.fired += 1;
+            listener.last_fired = Some(Instant::now());
+        }
+
+        let PendingEvent {
+            callback,
+            name,
+            event,
+            ..
+        } = event;
+
+        let deliver = move |ctx: &mut VMContext<FabricEnv>| {
+            let _scope = EventScope::enter(name);
+            callback.call(ctx, Foreign::with(event.0));
+        };
+
+        if is_paused(ctx) {
+            queue_guest(ctx, "event", deliver);
+        } else {
+            call_guest(ctx, "event", deliver);
+        }
+    }
+}
+
+/// Deliver the events fired to `module` while it was held by the addon
+/// outside of a callback, on the next frame
+pub(crate) fn run_pending(module: &Module) {
+    if let Ok(mut lock) = module.try_lock() {
+        deliver_pending(&mut lock);
+    }
+}
+
+/// Drop the events that weren't delivered yet
+pub(crate) fn clear_pending() {
+    unsafe {
+        PENDING.clear();
+    }
+}
+
+/// Deliver `event` to the `subscriptions` in order, until one consumes it
+fn deliver_all(subscriptions: &[Subscription], event: *mut c_void, name: &str) {
+    for subscription in subscriptions {
+        if subscription.deliver(event, name) {
+            debug!("event {:?} consumed by {}", name, subscription.name);
+            break;
+        }
+    }
+}
+
+/// Fire a custom event created by a module, delivered to the listeners of
+/// both sides of the engine in priority order, then free the event
+pub(crate) fn fire_custom(event: *mut c_void) {
+    let name = Foreign::<dyn GameEvent>::with(event).get_name().to_owned();
+    let name_string = name.to_string_lossy().into_owned();
+    let _scope = EventScope::enter(name_string.clone());
+
+    let mut subscriptions: Vec<_> = unsafe { &DISPATCHERS }
+        .iter()
+        .filter(|dispatcher| dispatcher.event == name)
+        .flat_map(|dispatcher| dispatcher.subscriptions.iter().cloned())
+        .collect();
+    subscriptions.sort_by_key(|subscription| subscription.priority);
+
+    deliver_all(&subscriptions, event, &name_string);
+    custom_events::free(event);
+}
+
+impl GameEventListener2 for FabricListener {
+    fn destructor(&self) {
+        info!("destructor");
+    }
 
-        lock.externs.take_extern::<Box<dyn GameEvent>>(handle);
+    fn fire_game_event(&mut self, event: *mut c_void) {
+        let name = Foreign::<dyn GameEvent>::with(event)
+            .get_name()
+            .to_string_lossy()
+            .into_owned();
+        let _scope = EventScope::enter(name.clone());
+        info!("fire_game_event {:?}", name);
+
+        // The subscriptions are copied so the dispatchers
+        // aren't borrowed while the modules are running
+        let subscriptions = unsafe { &DISPATCHERS }
+            .iter()
+            .find(|dispatcher| {
+                dispatcher.event == self.event && dispatcher.server_side == self.server_side
+            })
+            .map(|dispatcher| dispatcher.subscriptions.clone())
+            .unwrap_or_default();
+
+        deliver_all(&subscriptions, event, &name);
     }
 
     fn get_event_debug_id(&mut self) -> c_int {
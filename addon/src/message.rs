@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int},
+};
+
+use log::{debug, warn};
+
+use crate::{
+    bitbuf::BitWriter,
+    engine::{engine, VEngineServer},
+    foreign::CreateInterfaceFn,
+    game,
+    server::ServerGameDLL,
+};
+
+/// Binding for IRecipientFilter, implemented by the addon to
+/// select the clients a message is sent to
+#[fabric_codegen::interface]
+pub(crate) trait RecipientFilter {
+    fn destructor(&self);
+
+    fn is_reliable(&self) -> bool;
+    fn is_init_message(&self) -> bool;
+
+    fn get_recipient_count(&self) -> c_int;
+    fn get_recipient_index(&self, slot: c_int) -> c_int;
+}
+
+/// List of the entity indices of the clients receiving a message
+pub(crate) struct Recipients {
+    clients: Vec<c_int>,
+    reliable: bool,
+}
+
+impl Recipients {
+    /// A single client, identified by its entity index
+    pub(crate) fn single(client: c_int) -> Self {
+        Recipients {
+            clients: vec![client],
+            reliable: true,
+        }
+    }
+
+    /// All the client slots of the server
+    pub(crate) fn all(max_clients: c_int) -> Self {
+        Recipients {
+            clients: (1..=max_clients).collect(),
+            reliable: true,
+        }
+    }
+}
+
+impl RecipientFilter for Recipients {
+    fn destructor(&self) {}
+
+    fn is_reliable(&self) -> bool {
+        self.reliable
+    }
+
+    fn is_init_message(&self) -> bool {
+        false
+    }
+
+    fn get_recipient_count(&self) -> c_int {
+        self.clients.len() as c_int
+    }
+
+    fn get_recipient_index(&self, slot: c_int) -> c_int {
+        self.clients.get(slot as usize).copied().unwrap_or(-1)
+    }
+}
+
+static FILTER_VTABLE: IRecipientFilter =
+    <dyn RecipientFilter>::vtable::<Box<Recipients>, Recipients>();
+
+/// Call `func` with a pointer to an IRecipientFilter selecting `recipients`,
+/// the pointer must not be used after `func` returns
+pub(crate) fn with_filter<F, R>(recipients: Recipients, func: F) -> R
+where
+    F: FnOnce(*mut c_void) -> R,
+{
+    let mut filter = CRecipientFilter {
+        vtable: &FILTER_VTABLE,
+        instance: Box::new(recipients),
+    };
+
+    func(&mut filter as *mut CRecipientFilter<Box<Recipients>> as *mut c_void)
+}
+
+/// Identifiers of the user messages registered by the server DLL
+static mut MESSAGE_TYPES: Option<HashMap<String, c_int>> = None;
+
+/// Enumerate the user messages registered by the server DLL,
+/// this must be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interfaces = game::interfaces();
+    let mut game = match game::acquire::<dyn ServerGameDLL>(
+        server,
+        interfaces.server_game_dll,
+        "IServerGameDLL",
+    ) {
+        Some(game) => game,
+        None => return,
+    };
+
+    let mut types = HashMap::new();
+    let mut buffer = [0 as c_char; 256];
+
+    for index in 0.. {
+        let mut size = 0;
+        let is_ok = game.get_user_message_info(
+            index,
+            buffer.as_mut_ptr(),
+            buffer.len() as c_int,
+            &mut size,
+        );
+
+        if !is_ok {
+            break;
+        }
+
+        if let Some(last) = buffer.last_mut() {
+            *last = 0;
+        }
+
+        let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        types.insert(name.to_string_lossy().into_owned(), index);
+    }
+
+    debug!("found {} user messages", types.len());
+
+    unsafe {
+        MESSAGE_TYPES = Some(types);
+    }
+}
+
+/// Send the user message `name` to `recipients`, with the content of the
+/// message written by `write`
+///
+/// Returns false if the message type isn't registered by the server DLL, or
+/// if the content of the message overflowed the engine buffer
+pub(crate) fn send<F>(recipients: Recipients, name: &CStr, write: F) -> bool
+where
+    F: FnOnce(&mut BitWriter),
+{
+    let message_type = unsafe { MESSAGE_TYPES.as_ref() }
+        .and_then(|types| types.get(&*name.to_string_lossy()).copied());
+
+    let message_type = match message_type {
+        Some(message_type) => message_type,
+        None => {
+            warn!("unknown user message {:?}", name);
+            return false;
+        }
+    };
+
+    let mut engine = match engine() {
+        Some(engine) => engine,
+        None => return false,
+    };
+
+    // The engine keeps a reference to the filter until the message is ended
+    let is_ok = with_filter(recipients, |filter| {
+        let buffer = engine.user_message_begin(filter, message_type, name);
+
+        // The message must always be ended once started, even if it
+        // could not be written, to keep the engine state consistent
+        let is_ok = match unsafe { BitWriter::new(buffer) } {
+            Some(mut writer) => {
+                write(&mut writer);
+                !writer.is_overflowed()
+            }
+            None => false,
+        };
+
+        engine.message_end();
+        is_ok
+    });
+
+    if !is_ok {
+        warn!("user message {:?} overflowed", name);
+    }
+
+    is_ok
+}
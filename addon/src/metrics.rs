@@ -0,0 +1,422 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+
+use crate::{config, executor, module::Module};
+
+/// Exporter of the execution metrics of the modules, configured by the
+/// `metrics` section of the configuration
+///
+/// The metrics are either scraped by Prometheus from an HTTP endpoint polled
+/// on every frame, or pushed to a statsd server at a regular interval. They
+/// are collected on the game thread and the network I/O runs on the executor
+struct Exporter {
+    listener: Option<TcpListener>,
+    statsd: Option<(UdpSocket, String)>,
+    interval: Duration,
+    last_push: Instant,
+}
+
+/// Only accessed from the game thread
+static mut EXPORTER: Option<Exporter> = None;
+
+/// Maximum size of the HTTP request read before answering a scrape
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// Metrics of a module at the time they are collected
+struct Sample {
+    module: String,
+    events: u64,
+    host_calls: u64,
+    /// Kind, calls, average and 99th percentile of the execution time in seconds
+    callbacks: Vec<(&'static str, u64, f64, f64)>,
+    memory: usize,
+    externs: usize,
+    overruns: u32,
+    failed: bool,
+    paused: bool,
+}
+
+fn collect(modules: &[Module]) -> Vec<Sample> {
+    modules
+        .iter()
+        .filter_map(|module| module.try_lock().ok())
+        .map(|module| {
+            let env = &module.environment;
+            Sample {
+                module: env.name.clone(),
+                events: env.stats.events(),
+                host_calls: env.stats.host_calls,
+                callbacks: env
+                    .stats
+                    .callbacks
+                    .iter()
+                    .map(|(kind, timings)| {
+                        (
+                            *kind,
+                            timings.calls,
+                            timings.average().as_secs_f64(),
+                            timings.percentile(0.99).as_secs_f64(),
+                        )
+                    })
+                    .collect(),
+                memory: module.memory.len(),
+                externs: module.externs.len(),
+                overruns: env.budget.overruns,
+                failed: env.failed,
+                paused: env.paused,
+            }
+        })
+        .collect()
+}
+
+/// Escape a label value of the Prometheus text exposition format,
+/// the module names are file names that can contain any character
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Render the samples in the Prometheus text exposition format
+fn prometheus(samples: &[Sample]) -> String {
+    let mut output = String::new();
+
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(&Sample) -> String| {
+        writeln!(output, "# HELP {} {}", name, help).ok();
+        writeln!(output, "# TYPE {} {}", name, kind).ok();
+        for sample in samples {
+            writeln!(
+                output,
+                "{}{{module=\"{}\"}} {}",
+                name,
+                escape_label(&sample.module),
+                value(sample)
+            )
+            .ok();
+        }
+    };
+
+    metric(
+        "fabric_module_events_total",
+        "counter",
+        "Game events handled by the module",
+        &|sample| sample.events.to_string(),
+    );
+    metric(
+        "fabric_module_host_calls_total",
+        "counter",
+        "Host functions called by the module",
+        &|sample| sample.host_calls.to_string(),
+    );
+    metric(
+        "fabric_module_memory_bytes",
+        "gauge",
+        "Size of the linear memory of the module",
+        &|sample| sample.memory.to_string(),
+    );
+    metric(
+        "fabric_module_externs",
+        "gauge",
+        "Live externs held by the module",
+        &|sample| sample.externs.to_string(),
+    );
+    metric(
+        "fabric_module_budget_overruns_total",
+        "counter",
+        "Frames on which the module exceeded its budget",
+        &|sample| sample.overruns.to_string(),
+    );
+    metric(
+        "fabric_module_failed",
+        "gauge",
+        "Whether the module was disabled after a failure",
+        &|sample| (sample.failed as u8).to_string(),
+    );
+    metric(
+        "fabric_module_paused",
+        "gauge",
+        "Whether the module is paused",
+        &|sample| (sample.paused as u8).to_string(),
+    );
+
+    // The callback metrics have one series per kind of callback
+    let callbacks: [(&str, &str, &str, fn(&(&str, u64, f64, f64)) -> String); 3] = [
+        (
+            "fabric_callback_calls_total",
+            "counter",
+            "Guest callbacks executed by kind",
+            |callback| callback.1.to_string(),
+        ),
+        (
+            "fabric_callback_average_seconds",
+            "gauge",
+            "Average execution time of the guest callbacks by kind",
+            |callback| callback.2.to_string(),
+        ),
+        (
+            "fabric_callback_p99_seconds",
+            "gauge",
+            "99th percentile of the execution time of the recent guest callbacks by kind",
+            |callback| callback.3.to_string(),
+        ),
+    ];
+
+    for (name, kind, help, value) in &callbacks {
+        writeln!(output, "# HELP {} {}", name, help).ok();
+        writeln!(output, "# TYPE {} {}", name, kind).ok();
+        for sample in samples {
+            for callback in &sample.callbacks {
+                writeln!(
+                    output,
+                    "{}{{module=\"{}\",kind=\"{}\"}} {}",
+                    name,
+                    escape_label(&sample.module),
+                    escape_label(callback.0),
+                    value(callback)
+                )
+                .ok();
+            }
+        }
+    }
+
+    output
+}
+
+/// Render the samples as statsd gauges, one per line
+fn statsd(samples: &[Sample]) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for sample in samples {
+        let prefix = format!("fabric.{}", sample.module);
+        lines.push(format!("{}.events:{}|g", prefix, sample.events));
+        lines.push(format!("{}.host_calls:{}|g", prefix, sample.host_calls));
+        lines.push(format!("{}.memory:{}|g", prefix, sample.memory));
+        lines.push(format!("{}.externs:{}|g", prefix, sample.externs));
+        lines.push(format!("{}.overruns:{}|g", prefix, sample.overruns));
+        lines.push(format!("{}.failed:{}|g", prefix, sample.failed as u8));
+        lines.push(format!("{}.paused:{}|g", prefix, sample.paused as u8));
+
+        for (kind, calls, average, p99) in &sample.callbacks {
+            lines.push(format!("{}.{}.calls:{}|g", prefix, kind, calls));
+            lines.push(format!(
+                "{}.{}.average_ms:{}|g",
+                prefix,
+                kind,
+                average * 1000.0
+            ));
+            lines.push(format!("{}.{}.p99_ms:{}|g", prefix, kind, p99 * 1000.0));
+        }
+    }
+
+    lines
+}
+
+/// Start the exporters enabled in the `metrics` configuration
+pub(crate) fn start() {
+    let config = &config::get().metrics;
+
+    let listener = if config.listen.is_empty() {
+        None
+    } else {
+        let listener = TcpListener::bind(&config.listen)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener));
+
+        match listener {
+            Ok(listener) => {
+                info!("metrics endpoint listening on {}", config.listen);
+                Some(listener)
+            }
+            Err(err) => {
+                warn!("could not listen on {}: {}", config.listen, err);
+                None
+            }
+        }
+    };
+
+    let statsd = if config.statsd.is_empty() {
+        None
+    } else {
+        match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => Some((socket, config.statsd.clone())),
+            Err(err) => {
+                warn!("could not create the statsd socket: {}", err);
+                None
+            }
+        }
+    };
+
+    if listener.is_none() && statsd.is_none() {
+        return;
+    }
+
+    unsafe {
+        EXPORTER = Some(Exporter {
+            listener,
+            statsd,
+            interval: Duration::from_millis(config.interval_ms.max(1000)),
+            last_push: Instant::now(),
+        });
+    }
+}
+
+pub(crate) fn stop() {
+    unsafe {
+        EXPORTER = None;
+    }
+}
+
+/// Answer the pending scrapes and push the metrics to statsd if the
+/// interval elapsed, collecting the metrics at most once per frame
+pub(crate) fn run(modules: &[Module], now: Instant) {
+    let exporter = match unsafe { EXPORTER.as_mut() } {
+        Some(exporter) => exporter,
+        None => return,
+    };
+
+    let mut samples = None;
+
+    if let Some(listener) = &exporter.listener {
+        loop {
+            match listener.accept() {
+                Ok((stream, address)) => {
+                    debug!("metrics scrape from {}", address);
+                    let body = prometheus(samples.get_or_insert_with(|| collect(modules)));
+                    executor::spawn(move || answer_scrape(stream, &body));
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("could not accept metrics scrape: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some((socket, address)) = &exporter.statsd {
+        if now.duration_since(exporter.last_push) >= exporter.interval {
+            exporter.last_push = now;
+
+            let lines = statsd(samples.get_or_insert_with(|| collect(modules)));
+            let (socket, address) = match socket.try_clone() {
+                Ok(socket) => (socket, address.clone()),
+                Err(err) => {
+                    warn!("could not use the statsd socket: {}", err);
+                    return;
+                }
+            };
+
+            executor::spawn(move || push_statsd(&socket, &address, &lines));
+        }
+    }
+}
+
+/// Write the metrics as the response to the HTTP request of a scrape,
+/// whatever the path of the request
+fn answer_scrape(mut stream: TcpStream, body: &str) {
+    let result = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(Duration::from_secs(5))))
+        .and_then(|()| stream.set_write_timeout(Some(Duration::from_secs(5))));
+
+    if let Err(err) = result {
+        debug!("could not configure metrics connection: {}", err);
+        return;
+    }
+
+    // Read the request headers before answering so the client doesn't
+    // see the connection reset, the content of the request is ignored
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && (request.len() as u64) < MAX_REQUEST_SIZE {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+
+    if let Err(err) = stream.write_all(response.as_bytes()) {
+        debug!("could not write metrics response: {}", err);
+    }
+}
+
+/// Send the statsd lines in datagrams small enough to not be fragmented
+fn push_statsd(socket: &UdpSocket, address: &str, lines: &[String]) {
+    const MAX_DATAGRAM_SIZE: usize = 1400;
+
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+            if let Err(err) = socket.send_to(datagram.as_bytes(), address) {
+                debug!("could not push metrics to {}: {}", address, err);
+                return;
+            }
+
+            datagram.clear();
+        }
+
+        datagram.push_str(line);
+        datagram.push('\n');
+    }
+
+    if !datagram.is_empty() {
+        if let Err(err) = socket.send_to(datagram.as_bytes(), address) {
+            debug!("could not push metrics to {}: {}", address, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape_label("example"), "example");
+        assert_eq!(escape_label(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label("a\nb"), r"a\nb");
+    }
+
+    #[test]
+    fn render_escaped_labels() {
+        let sample = Sample {
+            module: String::from("my \"module\"\n"),
+            events: 3,
+            host_calls: 0,
+            callbacks: vec![("timer", 1, 0.5, 0.5)],
+            memory: 0,
+            externs: 0,
+            overruns: 0,
+            failed: false,
+            paused: false,
+        };
+
+        let output = prometheus(&[sample]);
+        let lines: Vec<_> = output.lines().collect();
+        assert!(lines.contains(&r#"fabric_module_events_total{module="my \"module\"\n"} 3"#));
+        assert!(lines
+            .contains(&r#"fabric_callback_calls_total{module="my \"module\"\n",kind="timer"} 1"#));
+
+        // Each sample is on a single line
+        assert_eq!(lines.iter().filter(|line| line.contains("my")).count(), 10);
+    }
+}
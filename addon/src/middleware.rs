@@ -0,0 +1,227 @@
+//! Middleware wrapping the host calls of the modules, added to
+//! their linker before they are compiled
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    ffi::CStr,
+    rc::Rc,
+    time::Duration,
+};
+
+use fabric_runtime::{ExternError, HostCall, HostValue, Linker, Middleware};
+use log::{info, warn};
+
+use crate::{
+    config,
+    module::{split_import, HOST_PARAMS},
+    stats::format_ms,
+};
+
+/// Number of host calls a module can make on each frame
+pub(crate) struct HostCallQuota {
+    limit: u32,
+    remaining: Cell<u32>,
+    /// Number of host calls skipped since the module was loaded
+    skipped: Cell<u64>,
+    /// The module exceeded its quota during the current frame
+    exceeded: Cell<bool>,
+}
+
+impl HostCallQuota {
+    pub(crate) fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub(crate) fn skipped(&self) -> u64 {
+        self.skipped.get()
+    }
+
+    /// Refill the quota at the start of a frame
+    pub(crate) fn reset(&self) {
+        self.remaining.set(self.limit);
+        self.exceeded.set(false);
+    }
+}
+
+/// Skips the host calls of a module past its quota for the current frame
+struct QuotaMiddleware {
+    module: String,
+    quota: Rc<HostCallQuota>,
+}
+
+impl Middleware for QuotaMiddleware {
+    fn before_call(&mut self, call: &HostCall<'_>) -> bool {
+        let quota = &self.quota;
+        match quota.remaining.get() {
+            0 => {
+                if !quota.exceeded.replace(true) {
+                    warn!(
+                        "{} exceeded its quota of {} host calls for this frame, skipping {}",
+                        self.module,
+                        quota.limit,
+                        call.name()
+                    );
+                }
+
+                quota.skipped.set(quota.skipped.get() + 1);
+                false
+            }
+            remaining => {
+                quota.remaining.set(remaining - 1);
+                true
+            }
+        }
+    }
+}
+
+/// Add the host call quota configured for `module` to its linker,
+/// returns None if the module can make unlimited host calls
+pub(crate) fn add_quota(linker: &mut Linker, module: &str) -> Option<Rc<HostCallQuota>> {
+    let limit = match config::get().host_call_quota(module) {
+        0 => return None,
+        limit => limit,
+    };
+
+    let quota = Rc::new(HostCallQuota {
+        limit,
+        remaining: Cell::new(limit),
+        skipped: Cell::new(0),
+        exceeded: Cell::new(false),
+    });
+
+    linker.add_middleware(QuotaMiddleware {
+        module: module.into(),
+        quota: quota.clone(),
+    });
+
+    Some(quota)
+}
+
+/// Logs the host calls of a module with their decoded arguments while
+/// tracing is toggled on with `fabric_trace`
+struct TraceMiddleware {
+    module: String,
+    tracing: Rc<Cell<bool>>,
+}
+
+impl Middleware for TraceMiddleware {
+    fn after_call(&mut self, call: &HostCall<'_>, elapsed: Duration) {
+        if !self.tracing.get() {
+            return;
+        }
+
+        let params = split_import(call.name()).and_then(|(module, name)| {
+            HOST_PARAMS
+                .iter()
+                .find(|(param_module, param_name, _)| {
+                    *param_module == module && *param_name == name
+                })
+                .map(|(_, _, params)| *params)
+        });
+
+        let args: Vec<_> = call
+            .args()
+            .enumerate()
+            .map(|(index, value)| {
+                let param = params.and_then(|params| params.get(index));
+                match param {
+                    Some((name, kind)) => format!("{}: {}", name, decode_arg(call, kind, value)),
+                    None => value.to_string(),
+                }
+            })
+            .collect();
+
+        info!(
+            "[trace] {}: {}({}) in {}",
+            self.module,
+            call.name(),
+            args.join(", "),
+            format_ms(elapsed)
+        );
+    }
+}
+
+/// Describe an argument of a host call from the kind of its parameter
+fn decode_arg(call: &HostCall<'_>, kind: &str, value: HostValue) -> String {
+    match (kind, value) {
+        ("string", HostValue::I32(offset)) => {
+            match call.memory().load::<CStr>(offset as u32 as usize) {
+                Ok(string) => format!("{:?}", string.to_string_lossy()),
+                Err(_) => format!("<invalid string at {:#x}>", offset),
+            }
+        }
+        ("pointer", HostValue::I32(offset)) => format!("{:#x}", offset),
+        (_, HostValue::ExternRef(extern_ref)) => match call.externs().extern_type(extern_ref) {
+            Ok(type_name) => format!("{} {:?}", type_name, extern_ref),
+            Err(ExternError::NotAnObject(constant)) => format!("constant {}", constant),
+            Err(error) => format!("<{}>", error),
+        },
+        (_, value) => value.to_string(),
+    }
+}
+
+/// Add the tracing of the host calls to the linker of `module`, returns
+/// the flag toggling it, which is off until `fabric_trace` is used
+pub(crate) fn add_trace(linker: &mut Linker, module: &str) -> Rc<Cell<bool>> {
+    let tracing = Rc::new(Cell::new(false));
+
+    linker.add_middleware(TraceMiddleware {
+        module: module.into(),
+        tracing: tracing.clone(),
+    });
+
+    tracing
+}
+
+/// Number of host calls kept in the `HostCallLog` of a module
+const HOST_CALL_LOG_LEN: usize = 16;
+
+/// Names of the last host functions called by a module, most recent last,
+/// included in the reports of its panics
+#[derive(Default)]
+pub(crate) struct HostCallLog {
+    calls: VecDeque<String>,
+}
+
+impl HostCallLog {
+    pub(crate) fn last(&self) -> Option<&str> {
+        self.calls.back().map(String::as_str)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &str> {
+        self.calls.iter().map(String::as_str)
+    }
+}
+
+/// Records the host functions called by a module in its `HostCallLog`
+struct BreadcrumbMiddleware {
+    log: Rc<RefCell<HostCallLog>>,
+}
+
+impl Middleware for BreadcrumbMiddleware {
+    fn before_call(&mut self, call: &HostCall<'_>) -> bool {
+        let calls = &mut self.log.borrow_mut().calls;
+
+        // The names of the oldest calls are reused for the new ones
+        let mut name = if calls.len() >= HOST_CALL_LOG_LEN {
+            calls.pop_front().unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        name.clear();
+        name.push_str(call.name());
+        calls.push_back(name);
+        true
+    }
+}
+
+/// Add the recording of the host calls to the linker of a module
+pub(crate) fn add_breadcrumbs(linker: &mut Linker) -> Rc<RefCell<HostCallLog>> {
+    let log = Rc::new(RefCell::new(HostCallLog::default()));
+
+    linker.add_middleware(BreadcrumbMiddleware { log: log.clone() });
+
+    log
+}
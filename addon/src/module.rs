@@ -1,67 +1,783 @@
 use std::{
-    ffi::CStr,
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    ffi::{CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    rc::Rc,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
-use fabric_runtime::{with_abi, Environment, ExternRef, FuncRef, Function, GlobalValue, VMContext};
-use log::{debug, log, warn, Level};
+use fabric_codegen::cstr;
+use fabric_runtime::{
+    take_host_calls, take_host_panic, with_abi, Abort, Callback, Environment, ExternRef, Externs,
+    FuncRef, Function, GlobalValue, Linker, Memory, VMContext,
+};
+use log::{debug, error, info, log, warn, Level};
+use rand_pcg::Pcg32;
 
-use crate::manager::{GameEvent, ListenerFunc};
+use crate::{
+    bitbuf::BitBuffer,
+    config::{self, PanicPolicy, PausePolicy},
+    crash, custom_events,
+    executor::Tasks,
+    foreign::Foreign,
+    host::{
+        bot::Bots,
+        bus::Subscriptions,
+        client::{ConnectFunc, SettingsFunc},
+        command::Commands,
+        config::ConfigFunc,
+        db::Database,
+        edict::EdictHooks,
+        effects::RateLimit,
+        entity::Entities,
+        job::Jobs,
+        kv::Store,
+        lang::Phrases,
+        menu::Menus,
+        random::create_rng,
+        server::CapabilityFunc,
+        state::State,
+        timer::{self, Ticks, Timers},
+    },
+    loader::ModuleDesc,
+    logging::{current_event, take_last_panic, ModuleScope},
+    manager::{
+        deliver_pending, fire_custom, manager, BorrowedEvent, GameEvent, GameEventManager2,
+        ListenerCallback, LISTENER_CONSUME, LISTENER_SERVER_SIDE,
+    },
+    middleware::{self, HostCallLog, HostCallQuota},
+    schema::{EventSchema, Field, FieldType},
+    stats::Stats,
+    thread::assert_game_thread,
+};
 
 pub(crate) type Module = Arc<Mutex<VMContext<FabricEnv>>>;
 
+// Defines `HOST_MODULES`, `HOST_FUNCTIONS`, `HOST_PARAMS` and `HOST_CONSTANTS` from
+// the import list shared with the bindings of the guest crate
+fabric_codegen::host_imports!();
+
+/// Names of the host modules and their imports, shared with the guest crate
+pub(crate) mod names {
+    fabric_codegen::host_names!();
+}
+
 /// Implementation of the WASM host environment for a Source addon DLL
 pub(crate) struct FabricEnv {
+    /// Name of the module, used to locate its persistent data
+    pub(crate) name: String,
+    pub(crate) desc: ModuleDesc,
+    /// Number of times the module was reloaded with `fabric_reload`, the
+    /// listeners registered by the previous instances are ignored
+    pub(crate) generation: u32,
+    /// Resolves the imports of the module, layers can be pushed on
+    /// it before loading the module to stub or revoke host functions
+    pub(crate) linker: Linker,
+    /// Host calls the module can make during the current frame
+    pub(crate) quota: Option<Rc<HostCallQuota>>,
+    /// Set while the host calls and callbacks of the module
+    /// are traced with `fabric_trace`
+    pub(crate) tracing: Rc<Cell<bool>>,
+    /// Last host functions called by the module
+    pub(crate) host_calls: Rc<RefCell<HostCallLog>>,
+    /// Set when the module panicked, its code is never called again
+    pub(crate) failed: bool,
+    pub(crate) budget: Budget,
+    pub(crate) stats: Stats,
+    /// Set when the module was paused with `fabric_pause`
+    pub(crate) paused: bool,
+    /// Time at which the module stopped running, either because it
+    /// or the whole plugin was paused
+    paused_at: Option<Ticks>,
+    /// Calls dispatched to the module while it was paused
+    queued: VecDeque<QueuedCall>,
     pub(crate) listeners: Vec<Listener>,
+    pub(crate) schema: Rc<EventSchema>,
+    pub(crate) timers: Timers,
+    pub(crate) jobs: Jobs,
+    pub(crate) tasks: Tasks,
+    pub(crate) database: Database,
+    pub(crate) store: Store,
+    pub(crate) phrases: Phrases,
+    pub(crate) rng: Pcg32,
+    /// Sounds precached by the module, these are precached
+    /// again by the addon when a new level is loaded
+    pub(crate) sounds: Vec<CString>,
+    /// Models precached by the module, handled the same way as `sounds`
+    pub(crate) models: Vec<CString>,
+    pub(crate) effects: RateLimit,
+    pub(crate) bots: Bots,
+    pub(crate) entities: Entities,
+    pub(crate) edict_hooks: EdictHooks,
+    pub(crate) subscriptions: Subscriptions,
+    pub(crate) menus: Menus,
+    pub(crate) commands: Commands,
+    pub(crate) state: State,
+    /// Callback called when the configuration is reloaded
+    pub(crate) config_changed: Option<ConfigFunc>,
+    /// Callback called when a capability of `Server` becomes available
+    pub(crate) capability_available: Option<CapabilityFunc>,
+    /// Callback called when the replicated settings of a client change
+    pub(crate) settings_changed: Option<SettingsFunc>,
+    /// Callback called when a client connects, to accept or reject it
+    pub(crate) connect: Option<ConnectFunc>,
 }
 
-impl Environment for FabricEnv {
-    fn import_function(&mut self, module: &str, name: &str) -> Option<Function> {
-        match module {
-            "GameEventsManager" => match name {
-                "add_listener" => Some(Function::new(
-                    add_listener as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef, i32, i32)),
-                )),
-                _ => None,
-            },
-            "GameEvent" => match name {
-                "get_int" => Some(Function::new(
-                    get_int as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
-                )),
-                "get_bool" => Some(Function::new(
-                    get_bool as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
-                )),
-                _ => None,
-            },
-            "LoggingSystem" => match name {
-                "log" => Some(Function::new(
-                    print_log as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32)),
-                )),
-                _ => None,
-            },
+impl FabricEnv {
+    pub(crate) fn new(name: &str, desc: ModuleDesc, schema: Rc<EventSchema>) -> Self {
+        let mut linker = create_linker(name);
+        let tracing = middleware::add_trace(&mut linker, name);
+        let host_calls = middleware::add_breadcrumbs(&mut linker);
+        let quota = middleware::add_quota(&mut linker, name);
+
+        FabricEnv {
+            name: name.into(),
+            desc,
+            generation: 0,
+            linker,
+            quota,
+            tracing,
+            host_calls,
+            failed: false,
+            budget: Budget::default(),
+            stats: Stats::default(),
+            paused: false,
+            paused_at: None,
+            queued: VecDeque::new(),
+            listeners: Vec::new(),
+            schema,
+            timers: Timers::new(),
+            jobs: Jobs::new(),
+            tasks: Tasks::new(),
+            database: Database::new(),
+            store: Store::new(),
+            phrases: Phrases::new(),
+            rng: create_rng(),
+            sounds: Vec::new(),
+            models: Vec::new(),
+            effects: RateLimit::new(),
+            bots: Bots::new(),
+            entities: Entities::new(),
+            edict_hooks: EdictHooks::new(),
+            subscriptions: Subscriptions::new(),
+            menus: Menus::new(),
+            commands: Commands::new(),
+            state: State::new(),
+            config_changed: None,
+            capability_available: None,
+            settings_changed: None,
+            connect: None,
+        }
+    }
+}
+
+/// Find the module named `name` in `modules`, the modules
+/// that are currently running can't be matched
+pub(crate) fn find_module<'a>(modules: &'a [Module], name: &str) -> Option<&'a Module> {
+    modules.iter().find(|module| match module.try_lock() {
+        Ok(module) => module.environment.name == name,
+        Err(_) => false,
+    })
+}
+
+/// Drop the externs a module still holds before it is unloaded, logging
+/// each of them as a leak of the module with the type of the object
+///
+/// The objects owned by the addon are freed with the arena and the events
+/// the module created are freed with the manager, the other engine objects
+/// wrapped in `Foreign`, like the events lent to an aborted listener, are
+/// only forgotten
+pub(crate) fn release_externs(ctx: &mut VMContext<FabricEnv>) {
+    let live = ctx.externs.drain();
+    if live.is_empty() {
+        return;
+    }
+
+    warn!(
+        "module {} leaked {} externs",
+        ctx.environment.name,
+        live.len()
+    );
+
+    for leak in live {
+        warn!("  {:?}: {}", leak.extern_ref, leak.type_name);
+        if let Some(backtrace) = &leak.backtrace {
+            debug!("created at:\n{}", backtrace);
+        }
+
+        if let Ok(event) = leak.value.downcast::<Foreign<dyn GameEvent>>() {
+            free_owned_event(*event);
+        }
+    }
+}
+
+/// Maximum number of calls queued for a paused module,
+/// the oldest calls are dropped past this limit
+const MAX_QUEUED_CALLS: usize = 1024;
+
+/// Size of a WASM memory page in bytes
+const WASM_PAGE_SIZE: usize = 0x10000;
+
+/// Guest call deferred until its module is unpaused
+struct QueuedCall {
+    kind: &'static str,
+    call: Box<dyn FnOnce(&mut VMContext<FabricEnv>)>,
+}
+
+/// Set while the whole plugin is paused by the engine
+static mut PLUGIN_PAUSED: bool = false;
+
+/// Pause or unpause the whole plugin, the per-module
+/// pause state is kept when the plugin is unpaused
+pub(crate) fn set_plugin_paused(modules: &[Module], paused: bool) {
+    unsafe {
+        PLUGIN_PAUSED = paused;
+    }
+
+    if paused {
+        let now = timer::now();
+        for module in modules {
+            mark_paused(&mut module.lock().unwrap(), now);
+        }
+    }
+}
+
+/// Pause or unpause a single module
+pub(crate) fn set_paused(ctx: &mut VMContext<FabricEnv>, paused: bool) {
+    ctx.environment.paused = paused;
+    if paused {
+        mark_paused(ctx, timer::now());
+    }
+}
+
+fn mark_paused(ctx: &mut VMContext<FabricEnv>, now: Ticks) {
+    ctx.environment.paused_at.get_or_insert(now);
+}
+
+/// Returns true if the module or the whole plugin is paused
+pub(crate) fn is_paused(ctx: &VMContext<FabricEnv>) -> bool {
+    let plugin_paused = unsafe { PLUGIN_PAUSED };
+    plugin_paused || ctx.environment.paused
+}
+
+/// Defer a call into the guest code of the paused module `ctx` until it is
+/// unpaused, or drop it, according to `runtime.pause_policy`
+pub(crate) fn queue_guest<F>(ctx: &mut VMContext<FabricEnv>, kind: &'static str, func: F)
+where
+    F: FnOnce(&mut VMContext<FabricEnv>) + 'static,
+{
+    if config::get().runtime.pause_policy == PausePolicy::Drop {
+        return;
+    }
+
+    let queued = &mut ctx.environment.queued;
+    if queued.len() >= MAX_QUEUED_CALLS {
+        warn!(
+            "too many calls queued for paused module {}, dropping the oldest {}",
+            ctx.environment.name, queued[0].kind
+        );
+        queued.pop_front();
+    }
+
+    queued.push_back(QueuedCall {
+        kind,
+        call: Box::new(func),
+    });
+}
+
+/// Call into the guest code of `ctx` with `func` like `call_guest`,
+/// or defer the call with `queue_guest` if the module is paused
+pub(crate) fn dispatch_guest<F>(ctx: &mut VMContext<FabricEnv>, kind: &'static str, func: F)
+where
+    F: FnOnce(&mut VMContext<FabricEnv>) + 'static,
+{
+    if is_paused(ctx) {
+        queue_guest(ctx, kind, func);
+    } else {
+        call_guest(ctx, kind, func);
+    }
+}
+
+/// Resume `module` at the start of the frame at the game time `now` if it was paused
+///
+/// The timers of the module are delayed by the time it spent paused and the
+/// calls queued in the meantime are run in order. Returns false if the module
+/// is still paused, in which case it shouldn't run for this frame
+pub(crate) fn resume(module: &Module, now: Ticks) -> bool {
+    let mut lock = module.lock().unwrap();
+    if is_paused(&lock) {
+        return false;
+    }
+
+    let paused_at = match lock.environment.paused_at.take() {
+        Some(paused_at) => paused_at,
+        None => return true,
+    };
+
+    lock.environment.timers.delay(now.saturating_sub(paused_at));
+
+    let queued = std::mem::take(&mut lock.environment.queued);
+    debug!(
+        "resuming module {} with {} queued calls",
+        lock.environment.name,
+        queued.len()
+    );
+
+    for queued in queued {
+        call_guest(&mut lock, queued.kind, queued.call);
+    }
+
+    true
+}
+
+/// Execution budget accounting of a module, see `RuntimeConfig::budget`
+#[derive(Default)]
+pub(crate) struct Budget {
+    /// The module ran out of fuel during the current frame
+    exhausted: bool,
+    /// Number of frames the module ran out of fuel in
+    pub(crate) overruns: u32,
+    /// Number of consecutive frames the module ran out of fuel in,
+    /// including the current one
+    pub(crate) consecutive_overruns: u32,
+}
+
+/// Refill the fuel of `module` at the start of a frame
+pub(crate) fn refuel(module: &Module) {
+    let mut lock = module.lock().unwrap();
+
+    if let Some(quota) = &lock.environment.quota {
+        quota.reset();
+    }
+
+    let budget = config::get().budget(&lock.environment.name);
+    if budget == 0 {
+        return;
+    }
+
+    let accounting = &mut lock.environment.budget;
+    if accounting.exhausted {
+        accounting.exhausted = false;
+    } else {
+        accounting.consecutive_overruns = 0;
+    }
+
+    lock.set_fuel(budget_fuel(budget));
+}
+
+/// Convert a budget to fuel, the fuel is pointer-sized
+/// so the budget is clamped to fit on 32-bit targets
+fn budget_fuel(budget: u32) -> isize {
+    budget.min(isize::MAX as u32) as isize
+}
+
+/// Call into the guest code of the locked module `ctx` with `func`, `kind`
+/// is the kind of callback being called and is used to collect statistics
+///
+/// Returns None without calling `func` if the module already failed. A panic
+/// in `func` or in a host function called by the guest is reported with what
+/// the module was doing, then the `panic_policy` of the configuration is
+/// applied: by default the module is marked as failed, so a faulty module
+/// can't take down the whole server
+pub(crate) fn call_guest<F, R>(
+    ctx: &mut VMContext<FabricEnv>,
+    kind: &'static str,
+    func: F,
+) -> Option<R>
+where
+    F: FnOnce(&mut VMContext<FabricEnv>) -> R,
+{
+    assert_game_thread("guest callback");
+
+    // The listeners of an unloaded module are ignored by their generation,
+    // any other callback reaching it kept a stale reference to the module
+    debug_assert!(
+        !ctx.is_unloaded(),
+        "{} callback called on the unloaded module {}",
+        kind,
+        ctx.environment.name
+    );
+
+    if ctx.environment.failed || ctx.is_unloaded() {
+        return None;
+    }
+
+    // Discard the host calls made outside of a callback
+    take_host_calls();
+
+    let _scope = ModuleScope::enter(&ctx.environment.name);
+
+    if ctx.environment.tracing.get() {
+        match current_event() {
+            Some(event) => info!(
+                "[trace] {}: {} callback for {}",
+                ctx.environment.name, kind, event
+            ),
+            None => info!("[trace] {}: {} callback", ctx.environment.name, kind),
+        }
+    }
+
+    let start = Instant::now();
+    let result = catch_unwind(AssertUnwindSafe(|| func(&mut *ctx)));
+    ctx.environment
+        .stats
+        .record(kind, start.elapsed(), take_host_calls());
+
+    if take_host_panic() || result.is_err() {
+        let context = format!("panicked in a {} callback", kind);
+        match take_last_panic() {
+            Some(panic) => handle_crash(
+                &mut ctx.environment,
+                &context,
+                &panic.message,
+                Some(&panic.backtrace),
+            ),
+            None => handle_crash(&mut ctx.environment, &context, "unknown panic", None),
+        }
+    }
+
+    // The steps of the jobs are expected to run out of fuel, they are
+    // resumed on the next frame rather than counted as budget overruns
+    if ctx.is_out_of_fuel() && !ctx.environment.budget.exhausted && kind != "job" {
+        let env = &mut ctx.environment;
+        env.budget.exhausted = true;
+        env.budget.overruns += 1;
+        env.budget.consecutive_overruns += 1;
+
+        warn!(
+            "module {} exceeded its execution budget, its calls are aborted until the next frame",
+            env.name
+        );
+
+        let max_overruns = config::get().runtime.max_overruns;
+        if max_overruns > 0 && env.budget.consecutive_overruns >= max_overruns {
+            warn!(
+                "module {} exceeded its execution budget on {} consecutive frames and was disabled",
+                env.name, env.budget.consecutive_overruns
+            );
+            env.failed = true;
+        }
+    }
+
+    // Deliver the events fired to the module while it was running, like
+    // the ones it fired itself, now that it can receive them
+    deliver_pending(ctx);
+
+    result.ok()
+}
+
+/// Set when a module panicked with the `unload-plugin` policy, the addon
+/// unloads all the modules on the next frame. Only accessed from the game thread
+static mut UNLOAD_REQUESTED: bool = false;
+
+/// Returns true once if a panic requested the modules to be unloaded
+pub(crate) fn take_unload_request() -> bool {
+    unsafe { std::mem::replace(&mut UNLOAD_REQUESTED, false) }
+}
+
+/// Describe what the module was doing when it panicked
+fn breadcrumbs(env: &FabricEnv) -> String {
+    format!(
+        "last event: {}, last host call: {}",
+        current_event().as_deref().unwrap_or("none"),
+        env.host_calls.borrow().last().unwrap_or("none")
+    )
+}
+
+/// Report a panic or an abort of the module and apply the configured
+/// `PanicPolicy`, the crashes disabling the module are written to a report
+fn handle_crash(env: &mut FabricEnv, context: &str, reason: &str, backtrace: Option<&str>) {
+    error!(
+        "module {} {}: {}, {}",
+        env.name,
+        context,
+        reason,
+        breadcrumbs(env)
+    );
+
+    let policy = config::get().runtime.panic_policy;
+    if policy != PanicPolicy::LogOnly {
+        crash::write_report(env, context, reason, backtrace);
+    }
+
+    match policy {
+        PanicPolicy::LogOnly => warn!("module {} keeps running", env.name),
+        PanicPolicy::DisableModule => {
+            warn!("module {} was disabled", env.name);
+            env.failed = true;
+        }
+        PanicPolicy::UnloadPlugin => {
+            warn!(
+                "module {} was disabled, unloading all the modules",
+                env.name
+            );
+            env.failed = true;
+            unsafe { UNLOAD_REQUESTED = true };
+        }
+    }
+}
+
+/// Resolve the function `name` of the host module `module`
+fn host_function(module: &str, name: &str) -> Option<Function> {
+    if !HOST_FUNCTIONS.contains(&(module, name)) {
+        return None;
+    }
+
+    match module {
+        names::game_events_manager::MODULE => match name {
+            names::game_events_manager::ADD_LISTENER => Some(Function::new(
+                add_listener as with_abi!(fn(*mut VMContext<FabricEnv>, FuncRef, i32, i32)),
+            )),
+            names::game_events_manager::CREATE_EVENT => Some(Function::new(
+                create_event as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> ExternRef),
+            )),
+            names::game_events_manager::SERIALIZE_EVENT => Some(Function::new(
+                serialize_event
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, ExternRef) -> i32),
+            )),
+            names::game_events_manager::UNSERIALIZE_EVENT => Some(Function::new(
+                unserialize_event
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> ExternRef),
+            )),
+            names::game_events_manager::FIRE_EVENT => Some(Function::new(
+                fire_event as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+            )),
+            names::game_events_manager::FREE_EVENT => Some(Function::new(
+                free_event as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef) -> i32),
+            )),
+            _ => None,
+        },
+        names::game_event::MODULE => match name {
+            names::game_event::GET_INT => Some(Function::new(
+                get_int as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+            )),
+            names::game_event::GET_BOOL => Some(Function::new(
+                get_bool as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> i32),
+            )),
+            names::game_event::GET_FLOAT => Some(Function::new(
+                get_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32) -> f32),
+            )),
+            names::game_event::GET_STRING => Some(Function::new(
+                get_string
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32, i32) -> i32),
+            )),
+            names::game_event::SET_INT => Some(Function::new(
+                set_int as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32)),
+            )),
+            names::game_event::SET_BOOL => Some(Function::new(
+                set_bool as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32)),
+            )),
+            names::game_event::SET_FLOAT => Some(Function::new(
+                set_float as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, f32)),
+            )),
+            names::game_event::SET_STRING => Some(Function::new(
+                set_string as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32)),
+            )),
+            names::game_event::GET_FIELD_INT => Some(Function::new(
+                get_field_int
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, ExternRef) -> i32),
+            )),
+            names::game_event::GET_FIELD_BOOL => Some(Function::new(
+                get_field_bool
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, ExternRef) -> i32),
+            )),
+            names::game_event::GET_FIELD_FLOAT => Some(Function::new(
+                get_field_float
+                    as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, ExternRef) -> f32),
+            )),
+            names::game_event::FIELD_ID => Some(Function::new(
+                field_id as with_abi!(fn(*mut VMContext<FabricEnv>, i32) -> i32),
+            )),
+            names::game_event::SERIALIZE => Some(Function::new(
+                serialize as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+            )),
+            names::game_event::READ_FIELDS => Some(Function::new(
+                read_fields as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32, i32) -> i32),
+            )),
             _ => None,
+        },
+        names::timer::MODULE => crate::host::timer::import_function(name),
+        names::job::MODULE => crate::host::job::import_function(name),
+        names::bit_buffer::MODULE => crate::host::bitbuf::import_function(name),
+        names::http::MODULE => crate::host::http::import_function(name),
+        names::db::MODULE => crate::host::db::import_function(name),
+        names::kv::MODULE => crate::host::kv::import_function(name),
+        names::lang::MODULE => crate::host::lang::import_function(name),
+        names::fs::MODULE => crate::host::fs::import_function(name),
+        names::config::MODULE => crate::host::config::import_function(name),
+        names::globals::MODULE => crate::host::globals::import_function(name),
+        names::random::MODULE => crate::host::random::import_function(name),
+        names::server::MODULE => crate::host::server::import_function(name),
+        names::map::MODULE => crate::host::map::import_function(name),
+        names::user_message::MODULE => crate::host::usermessage::import_function(name),
+        names::client::MODULE => crate::host::client::import_function(name),
+        names::command::MODULE => crate::host::command::import_function(name),
+        names::menu::MODULE => crate::host::menu::import_function(name),
+        names::vote::MODULE => crate::host::vote::import_function(name),
+        names::target::MODULE => crate::host::target::import_function(name),
+        names::sound::MODULE => crate::host::sound::import_function(name),
+        names::state::MODULE => crate::host::state::import_function(name),
+        names::effects::MODULE => crate::host::effects::import_function(name),
+        names::bot::MODULE => crate::host::bot::import_function(name),
+        names::trace::MODULE => crate::host::trace::import_function(name),
+        names::entity::MODULE => crate::host::entity::import_function(name),
+        names::edict::MODULE => crate::host::edict::import_function(name),
+        names::bus::MODULE => crate::host::bus::import_function(name),
+        names::shared::MODULE => crate::host::shared::import_function(name),
+        names::logging_system::MODULE => match name {
+            names::logging_system::LOG => Some(Function::new(
+                print_log as with_abi!(fn(*mut VMContext<FabricEnv>, ExternRef, i32)),
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Split an import written as `Module::name`
+pub(crate) fn split_import(import: &str) -> Option<(&str, &str)> {
+    let mut parts = import.splitn(2, "::");
+    match (parts.next(), parts.next()) {
+        (Some(module), Some(name)) if !module.is_empty() && !name.is_empty() => {
+            Some((module, name))
         }
+        _ => None,
+    }
+}
+
+/// Create the import linker of the module `module_name`: the overrides of its
+/// configuration are layered on top of the host modules, then the imports
+/// revoked for all the modules on top of them
+fn create_linker(module_name: &str) -> Linker {
+    let config = config::get();
+    let mut linker = Linker::new();
+
+    if let Some(module_config) = config.modules.get(module_name) {
+        let layer = linker.layer("config");
+        for (import, target) in &module_config.imports {
+            let (module, name) = match split_import(import) {
+                Some(import) => import,
+                None => {
+                    warn!("invalid import {:?} for {}", import, module_name);
+                    continue;
+                }
+            };
+
+            if target == "deny" {
+                layer.deny(module, name);
+                continue;
+            }
+
+            // The signature of the host function is checked against
+            // the import when the module is compiled
+            match split_import(target).and_then(|(module, name)| host_function(module, name)) {
+                Some(function) => {
+                    layer.define(module, name, function);
+                }
+                None => warn!(
+                    "{} can't import {} as {}, it's not a host function",
+                    module_name, import, target
+                ),
+            }
+        }
+    }
+
+    let layer = linker.layer("revoked");
+    for import in &config.runtime.denied_imports {
+        match split_import(import) {
+            Some((module, "*")) => {
+                layer.deny_module(module);
+            }
+            Some((module, name)) => {
+                layer.deny(module, name);
+            }
+            None => warn!("invalid denied import {:?}", import),
+        }
+    }
+
+    linker
+}
+
+impl Environment for FabricEnv {
+    fn import_function(&mut self, module: &str, name: &str) -> Option<Function> {
+        self.linker
+            .resolve_function(module, name, || host_function(module, name))
     }
 
     fn import_global(&mut self, module: &str, name: &str) -> Option<GlobalValue> {
-        match module {
-            "LoggingSystem" => match name {
-                "Level::Error" => Some(GlobalValue::Const(0)),
-                "Level::Warn" => Some(GlobalValue::Const(1)),
-                "Level::Info" => Some(GlobalValue::Const(2)),
-                "Level::Debug" => Some(GlobalValue::Const(3)),
-                "Level::Trace" => Some(GlobalValue::Const(4)),
-                _ => None,
-            },
-            _ => None,
+        let schema = &self.schema;
+        self.linker.resolve_global(module, name, || match module {
+            // Event fields are imported as `event::field` constants
+            // resolved to their identifier in the event schema
+            names::game_event::MODULE => schema.field_id(name).map(GlobalValue::Const),
+            _ => HOST_CONSTANTS
+                .iter()
+                .find(|(host_module, host_name, _)| *host_module == module && *host_name == name)
+                .map(|(_, _, value)| GlobalValue::Const(*value)),
+        })
+    }
+
+    fn deterministic(&self) -> bool {
+        config::get().runtime.deterministic
+    }
+
+    fn initial_fuel(&self) -> Option<isize> {
+        // The start function of the module is run with the budget
+        // of a frame, it gets refueled at the start of the next frame
+        match config::get().budget(&self.name) {
+            0 => None,
+            budget => Some(budget_fuel(budget)),
+        }
+    }
+
+    fn on_memory_grow(&mut self, current: u32, delta: u32) -> bool {
+        let size = (current as usize + delta as usize) * WASM_PAGE_SIZE;
+        match config::get().max_memory(&self.name) {
+            limit if limit > 0 && size > limit => {
+                warn!(
+                    "{} can't grow its memory to {} bytes, over its limit of {} bytes",
+                    self.name, size, limit
+                );
+                self.stats.refused_grows += 1;
+                false
+            }
+            _ => {
+                debug!("{} grows its memory to {} bytes", self.name, size);
+                self.stats.memory_grows += 1;
+                true
+            }
         }
     }
+
+    fn on_extern_created(&mut self, type_name: &'static str, _live: usize) {
+        self.stats.record_extern(type_name);
+    }
+
+    fn on_abort(&mut self, abort: &Abort) {
+        // The guest returns to the host with zeroes, the policy
+        // is applied to the module once its callback returned
+        handle_crash(self, "aborted", &abort.to_string(), None);
+    }
+
+    fn linker(&mut self) -> Option<&mut Linker> {
+        Some(&mut self.linker)
+    }
 }
 
+/// Event listener declared by a module, kept for `fabric_dump_events`
+/// once it has been registered with the game events manager
 pub(crate) struct Listener {
-    pub(crate) listener: ListenerFunc,
+    pub(crate) callback: ListenerCallback,
     pub(crate) event: String,
     pub(crate) server_side: bool,
+    /// Set once the listener was added to the game events manager
+    pub(crate) registered: bool,
+    /// Number of events dispatched to the listener
+    pub(crate) fired: u64,
+    pub(crate) last_fired: Option<Instant>,
 }
 
 with_abi! {
@@ -69,16 +785,23 @@ with_abi! {
         ctx: *mut VMContext<FabricEnv>,
         listener: FuncRef,
         event: i32,
-        server_side: i32,
+        flags: i32,
     ) {
-        debug!("add_listener({:?}, {:?}, {}, {})", ctx, listener, event, server_side);
+        debug!("add_listener({:?}, {:?}, {}, {})", ctx, listener, event, flags);
 
         let ctx = unsafe { &mut *ctx };
 
-        let listener = match ctx.function(listener) {
-            Some(listener) => listener.get(),
-            None => {
-                warn!("could not resolve {:?}", listener);
+        // Consuming listeners return whether they consumed the event
+        let callback = if flags & LISTENER_CONSUME != 0 {
+            Callback::resolve(ctx, listener).map(ListenerCallback::Consume)
+        } else {
+            Callback::resolve(ctx, listener).map(ListenerCallback::Observe)
+        };
+
+        let callback = match callback {
+            Ok(callback) => callback,
+            Err(err) => {
+                warn!("could not resolve {:?}: {}", listener, err);
                 return;
             }
         };
@@ -95,19 +818,249 @@ with_abi! {
 
         let env = &mut ctx.environment;
         env.listeners.push(Listener {
-            listener,
+            callback,
             event,
-            server_side: server_side != 0,
+            server_side: flags & LISTENER_SERVER_SIDE != 0,
+            registered: false,
+            fired: 0,
+            last_fired: None,
         });
     }
 }
 
+with_abi! {
+    fn serialize_event(ctx: *mut VMContext<FabricEnv>, event: ExternRef, buffer: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let mut manager = match manager() {
+            Some(manager) => manager,
+            None => return 0,
+        };
+
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event.0,
+            None => return 0,
+        };
+
+        if custom_events::is_custom(event) {
+            warn!("custom events can't be serialized");
+            return 0;
+        }
+
+        let buffer = match get_extern_mut::<BitBuffer>(&mut ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return 0,
+        };
+
+        let is_ok = manager.serialize_event(event, buffer.engine_writer());
+        (is_ok && !buffer.is_overflowed()) as i32
+    }
+}
+
+with_abi! {
+    fn create_event(ctx: *mut VMContext<FabricEnv>, name: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name,
+            Err(()) => {
+                warn!("could not load event name at {}", name);
+                return ExternRef::null();
+            }
+        };
+
+        match custom_events::create(&ctx.environment.name, name) {
+            Some(event) => ctx.create_extern(Foreign::<dyn GameEvent>::with(event)),
+            None => ExternRef::null(),
+        }
+    }
+}
+
+with_abi! {
+    fn unserialize_event(ctx: *mut VMContext<FabricEnv>, buffer: ExternRef) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+
+        let mut manager = match manager() {
+            Some(manager) => manager,
+            None => return ExternRef::null(),
+        };
+
+        let buffer = match get_extern::<BitBuffer>(&ctx.externs, buffer, "buffer") {
+            Some(buffer) => buffer,
+            None => return ExternRef::null(),
+        };
+        let mut reader = buffer.engine_reader();
+
+        let event = manager.unserialize_event(&mut reader);
+        if event.is_null() {
+            warn!("could not unserialize event");
+            return ExternRef::null();
+        }
+
+        ctx.create_extern(Foreign::<dyn GameEvent>::with(event))
+    }
+}
+
+with_abi! {
+    fn fire_event(ctx: *mut VMContext<FabricEnv>, event: ExternRef, dont_broadcast: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let event = match take_owned_event(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
+
+        // The custom events of the modules are dispatched by the addon
+        if custom_events::is_custom(event.0) {
+            fire_custom(event.0);
+            return 1;
+        }
+
+        let mut manager = match manager() {
+            Some(manager) => manager,
+            None => return 0,
+        };
+
+        // The engine takes ownership of the event, even if it couldn't be fired
+        manager.fire_event(event.0, dont_broadcast != 0) as i32
+    }
+}
+
+with_abi! {
+    fn free_event(ctx: *mut VMContext<FabricEnv>, event: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let event = match take_owned_event(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
+
+        free_owned_event(event);
+        1
+    }
+}
+
+/// Free an event created by a module
+fn free_owned_event(event: Foreign<dyn GameEvent>) {
+    if custom_events::is_custom(event.0) {
+        custom_events::free(event.0);
+    } else if let Some(mut manager) = manager() {
+        manager.free_event(event.0);
+    }
+}
+
+/// Resolve `handle` to an object of type `T` owned by the module, logging
+/// a warning naming the object as `what` if the handle is invalid
+pub(crate) fn get_extern<'a, T: Any>(
+    externs: &'a Externs,
+    handle: ExternRef,
+    what: &str,
+) -> Option<&'a T> {
+    match externs.try_get_extern(handle) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("invalid {} {:?}: {}", what, handle, err);
+            None
+        }
+    }
+}
+
+/// Mutable version of `get_extern`
+pub(crate) fn get_extern_mut<'a, T: Any>(
+    externs: &'a mut Externs,
+    handle: ExternRef,
+    what: &str,
+) -> Option<&'a mut T> {
+    match externs.try_get_extern_mut(handle) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("invalid {} {:?}: {}", what, handle, err);
+            None
+        }
+    }
+}
+
+/// Take the object of type `T` designated by `handle` out of the externs
+/// of the module, logging a warning like `get_extern` if the handle is invalid
+pub(crate) fn take_extern<T: Any>(
+    externs: &mut Externs,
+    handle: ExternRef,
+    what: &str,
+) -> Option<T> {
+    match externs.try_take_extern(handle) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("invalid {} {:?}: {}", what, handle, err);
+            None
+        }
+    }
+}
+
+/// Copy `data` to the guest buffer at `buffer` truncated to its `len`, logging a
+/// warning naming the data as `what` if the buffer is invalid. Returns the full
+/// length of `data` so the guest can retry with a larger buffer, or -1
+pub(crate) fn store_truncated(
+    memory: &mut Memory,
+    data: &[u8],
+    buffer: i32,
+    len: i32,
+    what: &str,
+) -> i32 {
+    let copied = data.len().min(len.max(0) as usize);
+    match memory.store(buffer as usize, &data[..copied]) {
+        Ok(()) => data.len() as i32,
+        Err(()) => {
+            warn!("could not store {} at {}+{}", what, buffer, copied);
+            -1
+        }
+    }
+}
+
+/// Resolve `handle` to an event created by the module or lent to one of
+/// its listeners, logging a warning if it doesn't designate an event
+fn event_mut(externs: &mut Externs, handle: ExternRef) -> Option<&mut Foreign<dyn GameEvent>> {
+    if externs.try_get_extern::<BorrowedEvent>(handle).is_ok() {
+        return externs
+            .try_get_extern_mut::<BorrowedEvent>(handle)
+            .ok()
+            .map(|event| &mut event.0);
+    }
+
+    match externs.try_get_extern_mut::<Foreign<dyn GameEvent>>(handle) {
+        Ok(event) => Some(event),
+        Err(err) => {
+            warn!("invalid event {:?}: {}", handle, err);
+            None
+        }
+    }
+}
+
+/// Take an event created by the module out of its externs, the events
+/// lent to its listeners still belong to the engine and are left in place
+fn take_owned_event(externs: &mut Externs, handle: ExternRef) -> Option<Foreign<dyn GameEvent>> {
+    if externs.try_get_extern::<BorrowedEvent>(handle).is_ok() {
+        warn!("the event passed to a listener can't be fired or freed, create a new event instead");
+        return None;
+    }
+
+    match externs.try_take_extern::<Foreign<dyn GameEvent>>(handle) {
+        Ok(event) => Some(event),
+        Err(err) => {
+            warn!("invalid event {:?}: {}", handle, err);
+            None
+        }
+    }
+}
+
 with_abi! {
     fn get_int(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32) -> i32 {
         let ctx = unsafe { &mut *ctx };
 
         let evt_id = event;
-        let event = ctx.externs.get_extern_mut::<Box<dyn GameEvent>>(event);
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
 
         let name = match ctx.memory.load::<CStr>(name as usize) {
             Ok(name) => name,
@@ -130,7 +1083,10 @@ with_abi! {
         let ctx = unsafe { &mut *ctx };
 
         let evt_id = event;
-        let event = ctx.externs.get_extern_mut::<Box<dyn GameEvent>>(event);
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
 
         let name = match ctx.memory.load::<CStr>(name as usize) {
             Ok(name) => name,
@@ -146,20 +1102,346 @@ with_abi! {
     }
 }
 
+with_abi! {
+    fn field_id(ctx: *mut VMContext<FabricEnv>, name: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let name = match ctx.memory.load::<CStr>(name as usize) {
+            Ok(name) => name,
+            Err(()) => {
+                warn!("could not load string at {}", name);
+                return -1;
+            }
+        };
+
+        // Same identifiers as the `event::field` globals of the module
+        match ctx.environment.schema.field_id(&name.to_string_lossy()) {
+            Some(id) => id as i32,
+            None => {
+                warn!("unknown event field {:?}", name);
+                -1
+            }
+        }
+    }
+}
+
+/// Load the field name at `name` for an accessor of `event`
+fn event_field<'a>(
+    ctx: &'a mut VMContext<FabricEnv>,
+    event: ExternRef,
+    name: i32,
+) -> Option<(&'a mut Foreign<dyn GameEvent>, &'a CStr)> {
+    let name = match ctx.memory.load::<CStr>(name as usize) {
+        Ok(name) => name,
+        Err(()) => {
+            warn!("could not load string at {}", name);
+            return None;
+        }
+    };
+
+    let event = event_mut(&mut ctx.externs, event)?;
+    Some((event, name))
+}
+
+with_abi! {
+    fn get_float(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32) -> f32 {
+        let ctx = unsafe { &mut *ctx };
+
+        match event_field(ctx, event, name) {
+            Some((event, name)) => event.get_float(name, 0.0),
+            None => 0.0,
+        }
+    }
+}
+
+with_abi! {
+    fn get_string(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32, buffer: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match event_field(ctx, event, name) {
+            Some((event, name)) => event.get_string(name, cstr!("")).to_bytes().to_vec(),
+            None => return -1,
+        };
+
+        store_truncated(&mut ctx.memory, &value, buffer, len, "string")
+    }
+}
+
+with_abi! {
+    fn set_int(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32, value: i32) {
+        let ctx = unsafe { &mut *ctx };
+
+        if let Some((event, name)) = event_field(ctx, event, name) {
+            event.set_int(name, value);
+        }
+    }
+}
+
+with_abi! {
+    fn set_bool(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32, value: i32) {
+        let ctx = unsafe { &mut *ctx };
+
+        if let Some((event, name)) = event_field(ctx, event, name) {
+            event.set_bool(name, value != 0);
+        }
+    }
+}
+
+with_abi! {
+    fn set_float(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32, value: f32) {
+        let ctx = unsafe { &mut *ctx };
+
+        if let Some((event, name)) = event_field(ctx, event, name) {
+            event.set_float(name, value);
+        }
+    }
+}
+
+with_abi! {
+    fn set_string(ctx: *mut VMContext<FabricEnv>, event: ExternRef, name: i32, value: i32) {
+        let ctx = unsafe { &mut *ctx };
+
+        let value = match ctx.memory.load::<CStr>(value as usize) {
+            Ok(value) => value.to_owned(),
+            Err(()) => {
+                warn!("could not load string at {}", value);
+                return;
+            }
+        };
+
+        if let Some((event, name)) = event_field(ctx, event, name) {
+            event.set_string(name, &value);
+        }
+    }
+}
+
+/// Size of a `FieldRead` in guest memory: the field identifier, its type and its value
+const FIELD_READ_SIZE: usize = 12;
+
+// Types of the fields read with `read_fields`
+const FIELD_INT: i32 = 0;
+const FIELD_BOOL: i32 = 1;
+const FIELD_FLOAT: i32 = 2;
+
+with_abi! {
+    fn read_fields(ctx: *mut VMContext<FabricEnv>, event: ExternRef, fields: i32, count: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let size = match (count.max(0) as usize).checked_mul(FIELD_READ_SIZE) {
+            Some(size) => size,
+            None => return -1,
+        };
+
+        let mut buffer = match ctx.memory.bytes(fields as usize, size) {
+            Ok(bytes) => bytes.to_vec(),
+            Err(()) => {
+                warn!("could not load event fields at {}+{}", fields, size);
+                return -1;
+            }
+        };
+
+        let schema = ctx.environment.schema.clone();
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return -1,
+        };
+
+        let mut read = 0;
+
+        for entry in buffer.chunks_exact_mut(FIELD_READ_SIZE) {
+            let field = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let ty = i32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+
+            let name = match resolve_field(&schema, event, field) {
+                Some(field) => &field.name,
+                None => {
+                    entry[8..].copy_from_slice(&[0; 4]);
+                    continue;
+                }
+            };
+
+            let value = match ty {
+                FIELD_INT => event.get_int(name, 0) as u32,
+                FIELD_BOOL => event.get_bool(name, false) as u32,
+                FIELD_FLOAT => event.get_float(name, 0.0).to_bits(),
+                _ => {
+                    warn!("unknown event field type {} for {:?}", ty, name);
+                    entry[8..].copy_from_slice(&[0; 4]);
+                    continue;
+                }
+            };
+
+            entry[8..].copy_from_slice(&value.to_le_bytes());
+            read += 1;
+        }
+
+        match ctx.memory.store(fields as usize, &buffer) {
+            Ok(()) => read,
+            Err(()) => -1,
+        }
+    }
+}
+
+with_abi! {
+    fn serialize(ctx: *mut VMContext<FabricEnv>, event: ExternRef, ptr: i32, len: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let schema = ctx.environment.schema.clone();
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return -1,
+        };
+
+        let encoded = schema.encode(event);
+
+        store_truncated(&mut ctx.memory, &encoded, ptr, len, "encoded event")
+    }
+}
+
+/// Resolve a field identifier for `event`, checking it was declared for this event
+fn resolve_field<'a>(
+    schema: &'a EventSchema,
+    event: &mut Foreign<dyn GameEvent>,
+    id: u32,
+) -> Option<&'a Field> {
+    let field = match schema.field(id) {
+        Some(field) => field,
+        None => {
+            warn!("unknown event field {}", id);
+            return None;
+        }
+    };
+
+    let event_name = event.get_name();
+    let expected = schema.event_name(field);
+    if !event_name
+        .to_bytes()
+        .eq_ignore_ascii_case(expected.as_bytes())
+    {
+        warn!(
+            "field {:?} belongs to event {:?}, not {:?}",
+            field.name, expected, event_name
+        );
+        return None;
+    }
+
+    Some(field)
+}
+
+with_abi! {
+    fn get_field_int(ctx: *mut VMContext<FabricEnv>, event: ExternRef, field: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let schema = ctx.environment.schema.clone();
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
+
+        let field = match field.try_value() {
+            Ok(field) => field,
+            Err(err) => {
+                warn!("invalid event field {:?}: {}", field, err);
+                return 0;
+            }
+        };
+
+        let field = match resolve_field(&schema, event, field) {
+            Some(field) => field,
+            None => return 0,
+        };
+
+        if !field.ty.is_integer() {
+            warn!("field {:?} is not an integer ({:?})", field.name, field.ty);
+            return 0;
+        }
+
+        event.get_int(&field.name, 0)
+    }
+}
+
+with_abi! {
+    fn get_field_bool(ctx: *mut VMContext<FabricEnv>, event: ExternRef, field: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let schema = ctx.environment.schema.clone();
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0,
+        };
+
+        let field = match field.try_value() {
+            Ok(field) => field,
+            Err(err) => {
+                warn!("invalid event field {:?}: {}", field, err);
+                return 0;
+            }
+        };
+
+        let field = match resolve_field(&schema, event, field) {
+            Some(field) => field,
+            None => return 0,
+        };
+
+        if field.ty != FieldType::Bool {
+            warn!("field {:?} is not a boolean ({:?})", field.name, field.ty);
+            return 0;
+        }
+
+        if event.get_bool(&field.name, false) { 1 } else { 0 }
+    }
+}
+
+with_abi! {
+    fn get_field_float(ctx: *mut VMContext<FabricEnv>, event: ExternRef, field: ExternRef) -> f32 {
+        let ctx = unsafe { &mut *ctx };
+
+        let schema = ctx.environment.schema.clone();
+        let event = match event_mut(&mut ctx.externs, event) {
+            Some(event) => event,
+            None => return 0.0,
+        };
+
+        let field = match field.try_value() {
+            Ok(field) => field,
+            Err(err) => {
+                warn!("invalid event field {:?}: {}", field, err);
+                return 0.0;
+            }
+        };
+
+        let field = match resolve_field(&schema, event, field) {
+            Some(field) => field,
+            None => return 0.0,
+        };
+
+        if field.ty != FieldType::Float {
+            warn!("field {:?} is not a float ({:?})", field.name, field.ty);
+            return 0.0;
+        }
+
+        event.get_float(&field.name, 0.0)
+    }
+}
+
 with_abi! {
     fn print_log(ctx: *mut VMContext<FabricEnv>, level: ExternRef, value: i32) {
         let ctx = unsafe { &mut *ctx };
 
-        let level = match level.value() {
-            0 => Level::Error,
-            1 => Level::Warn,
-            2 => Level::Info,
-            3 => Level::Debug,
-            4 => Level::Trace,
-            level => {
+        let level = match level.try_value() {
+            Ok(0) => Level::Error,
+            Ok(1) => Level::Warn,
+            Ok(2) => Level::Info,
+            Ok(3) => Level::Debug,
+            Ok(4) => Level::Trace,
+            Ok(level) => {
                 warn!("invalid logging level {}", level);
                 return;
             }
+            Err(err) => {
+                warn!("invalid logging level {:?}: {}", level, err);
+                return;
+            }
         };
 
         let message = match ctx.memory.load::<CStr>(value as usize) {
@@ -173,3 +1455,19 @@ with_abi! {
         log!(level, "{}", message.to_string_lossy());
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use fabric_runtime::load_module;
+
+    use super::*;
+    use crate::thread;
+
+    /// Load the WAT `source` as the module `name`, from the current
+    /// thread which becomes the game thread of the test
+    pub(crate) fn load_test_module(name: &str, source: &str) -> VMContext<FabricEnv> {
+        thread::init();
+        let environment = FabricEnv::new(name, ModuleDesc::default(), Rc::default());
+        load_module(environment, source)
+    }
+}
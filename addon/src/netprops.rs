@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int, c_uchar},
+    slice,
+};
+
+use log::{debug, warn};
+
+use crate::{
+    entity::{base_entity, server_class},
+    foreign::CreateInterfaceFn,
+    game,
+    server::ServerGameDLL,
+};
+
+/// Networked class of the server DLL, declared as in
+/// the Alien Swarm SDK's `server_class.h`
+#[repr(C)]
+#[allow(dead_code)]
+pub(crate) struct ServerClass {
+    network_name: *const c_char,
+    table: *mut SendTable,
+    next: *mut ServerClass,
+    class_id: c_int,
+    instance_baseline_index: c_int,
+}
+
+impl ServerClass {
+    pub(crate) fn network_name(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.network_name) }
+    }
+}
+
+/// Declared as in the Alien Swarm SDK's `dt_send.h`
+#[repr(C)]
+#[allow(dead_code)]
+struct SendTable {
+    props: *mut SendProp,
+    prop_count: c_int,
+    net_table_name: *const c_char,
+    precalc: *mut c_void,
+    flags: c_uchar,
+}
+
+impl SendTable {
+    fn props(&self) -> &[SendProp] {
+        if self.props.is_null() || self.prop_count <= 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.props, self.prop_count as usize) }
+        }
+    }
+}
+
+/// Declared as in the Alien Swarm SDK's `dt_send.h`, the class has a virtual
+/// destructor and starts with a vtable pointer
+#[repr(C)]
+#[allow(dead_code)]
+struct SendProp {
+    vtable: *const c_void,
+    matching_recv_prop: *mut c_void,
+    kind: c_int,
+    bits: c_int,
+    low_value: f32,
+    high_value: f32,
+    array_prop: *mut SendProp,
+    array_length_proxy: *const c_void,
+    elements: c_int,
+    element_stride: c_int,
+    exclude_dt_name: *const c_char,
+    parent_array_prop_name: *const c_char,
+    var_name: *const c_char,
+    high_low_mul: f32,
+    priority: c_uchar,
+    flags: c_int,
+    proxy_fn: *const c_void,
+    data_table_proxy_fn: *const c_void,
+    data_table: *mut SendTable,
+    offset: c_int,
+    extra_data: *const c_void,
+}
+
+/// `SendPropType` values
+const DPT_INT: c_int = 0;
+const DPT_FLOAT: c_int = 1;
+const DPT_VECTOR: c_int = 2;
+const DPT_DATA_TABLE: c_int = 6;
+
+/// Unsigned integer data
+const SPROP_UNSIGNED: c_int = 1 << 0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum PropKind {
+    Int { bits: c_int, unsigned: bool },
+    Float,
+    Vector,
+}
+
+/// Location of a networked property in the entity objects
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PropInfo {
+    pub(crate) offset: usize,
+    pub(crate) kind: PropKind,
+}
+
+/// Properties of each server class, indexed by network class name then
+/// property name. The properties of the base classes and embedded tables
+/// are flattened in the index of each class, the first match wins for
+/// properties with the same name like the SourceMod lookup
+///
+/// Only the send tables are indexed, datamaps are reached through a virtual
+/// method of CBaseEntity whose position in the vtable depends on the game
+static mut PROPS: Option<HashMap<String, HashMap<String, PropInfo>>> = None;
+
+/// Walk the send tables of all the server classes, this must
+/// be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interfaces = game::interfaces();
+    let mut game = match game::acquire::<dyn ServerGameDLL>(
+        server,
+        interfaces.server_game_dll,
+        "IServerGameDLL",
+    ) {
+        Some(game) => game,
+        None => return,
+    };
+
+    let mut classes = HashMap::new();
+    let mut class = game.get_all_server_classes();
+
+    while let Some(server_class) = unsafe { class.as_ref() } {
+        let mut props = HashMap::new();
+        if let Some(table) = unsafe { server_class.table.as_ref() } {
+            index_table(table, 0, &mut props);
+        }
+
+        let name = server_class.network_name().to_string_lossy().into_owned();
+        classes.insert(name, props);
+        class = server_class.next;
+    }
+
+    debug!(
+        "indexed the send tables of {} server classes",
+        classes.len()
+    );
+
+    unsafe {
+        PROPS = Some(classes);
+    }
+}
+
+fn index_table(table: &SendTable, base: usize, props: &mut HashMap<String, PropInfo>) {
+    for prop in table.props() {
+        if prop.var_name.is_null() || prop.offset < 0 {
+            continue;
+        }
+
+        let offset = base + prop.offset as usize;
+        let kind = match prop.kind {
+            DPT_INT => PropKind::Int {
+                bits: prop.bits,
+                unsigned: prop.flags & SPROP_UNSIGNED != 0,
+            },
+            DPT_FLOAT => PropKind::Float,
+            DPT_VECTOR => PropKind::Vector,
+            DPT_DATA_TABLE => {
+                if let Some(table) = unsafe { prop.data_table.as_ref() } {
+                    index_table(table, offset, props);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+
+        let name = unsafe { CStr::from_ptr(prop.var_name) };
+        props
+            .entry(name.to_string_lossy().into_owned())
+            .or_insert(PropInfo { offset, kind });
+    }
+}
+
+/// Find the property `name` of the entity at `index`, returning
+/// a pointer to the property and its description
+pub(crate) fn find_prop(index: c_int, name: &CStr) -> Option<(*mut u8, PropInfo)> {
+    let class = unsafe { &*server_class(index)? };
+    let classes = unsafe { PROPS.as_ref()? };
+
+    let prop = classes
+        .get(&*class.network_name().to_string_lossy())
+        .and_then(|props| props.get(&*name.to_string_lossy()))
+        .copied();
+
+    let prop = match prop {
+        Some(prop) => prop,
+        None => {
+            warn!(
+                "property {:?} not found in class {:?}",
+                name,
+                class.network_name()
+            );
+            return None;
+        }
+    };
+
+    let entity = base_entity(index)? as *mut u8;
+    Some((unsafe { entity.add(prop.offset) }, prop))
+}
+
+/// Read an integer property, the size of the field in the entity
+/// object is deduced from the number of bits sent over the network
+pub(crate) unsafe fn read_int(prop: *const u8, bits: c_int, unsigned: bool) -> i32 {
+    match (bits, unsigned) {
+        (bits, _) if bits < 1 || bits >= 17 => (prop as *const i32).read_unaligned(),
+        (bits, true) if bits >= 9 => (prop as *const u16).read_unaligned().into(),
+        (bits, false) if bits >= 9 => (prop as *const i16).read_unaligned().into(),
+        (bits, true) if bits >= 2 => prop.read().into(),
+        (bits, false) if bits >= 2 => (prop as *const i8).read().into(),
+        _ => (prop.read() != 0) as i32,
+    }
+}
@@ -0,0 +1,255 @@
+//! Older versions of the server plugin interface, for the engine branches
+//! that predate `ISERVERPLUGINCALLBACKS003`
+//!
+//! Each version is implemented by an adapter object sharing the instance
+//! of the addon, and forwarding the callbacks it has to `ServerPluginCallbacks`
+
+use std::{
+    ffi::CStr,
+    ops::{Deref, DerefMut},
+    os::raw::{c_char, c_int},
+    ptr::null,
+};
+
+use crate::{
+    addon::{
+        CCommand, Edict, FabricAddon, PluginResult, QueryCvarCookie, QueryCvarValueStatus,
+        ServerPluginCallbacks, INSTANCE,
+    },
+    foreign::CreateInterfaceFn,
+};
+
+/// Binding for ISERVERPLUGINCALLBACKS002, the Orange Box version of the
+/// interface: it lacks `ClientFullyConnect` and the edict callbacks
+#[fabric_codegen::interface]
+pub(crate) trait ServerPluginCallbacks002 {
+    fn load(
+        &mut self,
+        interface_factory: CreateInterfaceFn,
+        game_server_factory: CreateInterfaceFn,
+    ) -> bool;
+    fn unload(&mut self);
+    fn pause(&mut self);
+    fn unpause(&mut self);
+    fn get_plugin_description(&mut self) -> &CStr;
+    fn level_init(&mut self, map_name: &CStr);
+    fn server_activate(&mut self, edict_list: *mut Edict, edict_count: c_int, client_max: c_int);
+    fn game_frame(&mut self, simulating: bool);
+    fn level_shutdown(&mut self);
+    fn client_active(&mut self, entity: *mut Edict);
+    fn client_disconnect(&mut self, entity: *mut Edict);
+    fn client_put_in_server(&mut self, entity: *mut Edict, player_name: &CStr);
+    fn set_command_client(&mut self, index: c_int);
+    fn client_settings_changed(&mut self, entity: *mut Edict);
+    fn client_connect(
+        &mut self,
+        allow_connect: *mut bool,
+        entity: *mut Edict,
+        name: &CStr,
+        address: &CStr,
+        reject: *mut c_char,
+        max_reject_len: c_int,
+    ) -> PluginResult;
+    fn client_command(&mut self, entity: *mut Edict, args: *const CCommand) -> PluginResult;
+    fn network_id_validated(&mut self, user_name: &CStr, network_id: &CStr) -> PluginResult;
+    fn on_query_cvar_value_finished(
+        &mut self,
+        cookie: QueryCvarCookie,
+        entity: *mut Edict,
+        status: QueryCvarValueStatus,
+        cvar_name: *mut c_char,
+        cvar_value: *mut c_char,
+    );
+}
+
+/// Binding for ISERVERPLUGINCALLBACKS001, the Episode One version of the
+/// interface: `ClientCommand` doesn't receive the arguments of the command
+/// and cvar queries don't exist yet
+#[fabric_codegen::interface]
+pub(crate) trait ServerPluginCallbacks001 {
+    fn load(
+        &mut self,
+        interface_factory: CreateInterfaceFn,
+        game_server_factory: CreateInterfaceFn,
+    ) -> bool;
+    fn unload(&mut self);
+    fn pause(&mut self);
+    fn unpause(&mut self);
+    fn get_plugin_description(&mut self) -> &CStr;
+    fn level_init(&mut self, map_name: &CStr);
+    fn server_activate(&mut self, edict_list: *mut Edict, edict_count: c_int, client_max: c_int);
+    fn game_frame(&mut self, simulating: bool);
+    fn level_shutdown(&mut self);
+    fn client_active(&mut self, entity: *mut Edict);
+    fn client_disconnect(&mut self, entity: *mut Edict);
+    fn client_put_in_server(&mut self, entity: *mut Edict, player_name: &CStr);
+    fn set_command_client(&mut self, index: c_int);
+    fn client_settings_changed(&mut self, entity: *mut Edict);
+    fn client_connect(
+        &mut self,
+        allow_connect: *mut bool,
+        entity: *mut Edict,
+        name: &CStr,
+        address: &CStr,
+        reject: *mut c_char,
+        max_reject_len: c_int,
+    ) -> PluginResult;
+    fn client_command(&mut self, entity: *mut Edict) -> PluginResult;
+    fn network_id_validated(&mut self, user_name: &CStr, network_id: &CStr) -> PluginResult;
+}
+
+/// Forward the callbacks shared by all the versions of the interface
+macro_rules! forward_callbacks {
+    () => {
+        fn load(
+            &mut self,
+            interface_factory: CreateInterfaceFn,
+            game_server_factory: CreateInterfaceFn,
+        ) -> bool {
+            ServerPluginCallbacks::load(self, interface_factory, game_server_factory)
+        }
+
+        fn unload(&mut self) {
+            ServerPluginCallbacks::unload(self)
+        }
+
+        fn pause(&mut self) {
+            ServerPluginCallbacks::pause(self)
+        }
+
+        fn unpause(&mut self) {
+            ServerPluginCallbacks::unpause(self)
+        }
+
+        fn get_plugin_description(&mut self) -> &CStr {
+            ServerPluginCallbacks::get_plugin_description(self)
+        }
+
+        fn level_init(&mut self, map_name: &CStr) {
+            ServerPluginCallbacks::level_init(self, map_name)
+        }
+
+        fn server_activate(
+            &mut self,
+            edict_list: *mut Edict,
+            edict_count: c_int,
+            client_max: c_int,
+        ) {
+            ServerPluginCallbacks::server_activate(self, edict_list, edict_count, client_max)
+        }
+
+        fn game_frame(&mut self, simulating: bool) {
+            ServerPluginCallbacks::game_frame(self, simulating)
+        }
+
+        fn level_shutdown(&mut self) {
+            ServerPluginCallbacks::level_shutdown(self)
+        }
+
+        fn client_active(&mut self, entity: *mut Edict) {
+            ServerPluginCallbacks::client_active(self, entity)
+        }
+
+        fn client_disconnect(&mut self, entity: *mut Edict) {
+            ServerPluginCallbacks::client_disconnect(self, entity)
+        }
+
+        fn client_put_in_server(&mut self, entity: *mut Edict, player_name: &CStr) {
+            ServerPluginCallbacks::client_put_in_server(self, entity, player_name)
+        }
+
+        fn set_command_client(&mut self, index: c_int) {
+            ServerPluginCallbacks::set_command_client(self, index)
+        }
+
+        fn client_settings_changed(&mut self, entity: *mut Edict) {
+            ServerPluginCallbacks::client_settings_changed(self, entity)
+        }
+
+        fn client_connect(
+            &mut self,
+            allow_connect: *mut bool,
+            entity: *mut Edict,
+            name: &CStr,
+            address: &CStr,
+            reject: *mut c_char,
+            max_reject_len: c_int,
+        ) -> PluginResult {
+            ServerPluginCallbacks::client_connect(
+                self,
+                allow_connect,
+                entity,
+                name,
+                address,
+                reject,
+                max_reject_len,
+            )
+        }
+
+        fn network_id_validated(&mut self, user_name: &CStr, network_id: &CStr) -> PluginResult {
+            ServerPluginCallbacks::network_id_validated(self, user_name, network_id)
+        }
+    };
+}
+
+impl ServerPluginCallbacks002 for FabricAddon {
+    forward_callbacks!();
+
+    fn client_command(&mut self, entity: *mut Edict, args: *const CCommand) -> PluginResult {
+        ServerPluginCallbacks::client_command(self, entity, args)
+    }
+
+    fn on_query_cvar_value_finished(
+        &mut self,
+        cookie: QueryCvarCookie,
+        entity: *mut Edict,
+        status: QueryCvarValueStatus,
+        cvar_name: *mut c_char,
+        cvar_value: *mut c_char,
+    ) {
+        ServerPluginCallbacks::on_query_cvar_value_finished(
+            self, cookie, entity, status, cvar_name, cvar_value,
+        )
+    }
+}
+
+impl ServerPluginCallbacks001 for FabricAddon {
+    forward_callbacks!();
+
+    fn client_command(&mut self, entity: *mut Edict) -> PluginResult {
+        ServerPluginCallbacks::client_command(self, entity, null())
+    }
+}
+
+/// Handle to the instance of the addon shared by all the adapters
+pub(crate) struct AddonRef;
+
+impl Deref for AddonRef {
+    type Target = FabricAddon;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &INSTANCE.instance }
+    }
+}
+
+impl DerefMut for AddonRef {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut INSTANCE.instance }
+    }
+}
+
+static VTABLE_002: IServerPluginCallbacks002 =
+    <dyn ServerPluginCallbacks002>::vtable::<AddonRef, FabricAddon>();
+static VTABLE_001: IServerPluginCallbacks001 =
+    <dyn ServerPluginCallbacks001>::vtable::<AddonRef, FabricAddon>();
+
+pub(crate) static mut INSTANCE_002: CServerPluginCallbacks002<AddonRef> =
+    CServerPluginCallbacks002 {
+        vtable: &VTABLE_002,
+        instance: AddonRef,
+    };
+pub(crate) static mut INSTANCE_001: CServerPluginCallbacks001<AddonRef> =
+    CServerPluginCallbacks001 {
+        vtable: &VTABLE_001,
+        instance: AddonRef,
+    };
@@ -0,0 +1,514 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    addon::reload_by_name,
+    config, host, logging,
+    module::{set_paused, Module},
+    schema::EventSchema,
+};
+
+/// Remote management channel, a TCP listener accepting the fabric management
+/// commands from outside of the game console
+///
+/// Each connection sends the password of the channel and a single command on
+/// two lines, and receives the output of the command before being closed:
+///
+/// ```text
+/// $ printf 'secret\nlist\n' | nc 127.0.0.1 27099
+/// OK
+/// example 0.1.0 [paused]
+/// ```
+///
+/// The listener runs on its own thread and reads and writes each connection
+/// on a thread of its own, up to `max_connections` at once. The commands are
+/// executed on the game thread on the next frame
+struct Remote {
+    running: Arc<AtomicBool>,
+    listener: JoinHandle<()>,
+    receiver: Receiver<Request>,
+}
+
+/// Authenticated command received on a connection, the
+/// output of the command is sent back to the connection
+struct Request {
+    args: Vec<String>,
+    reply: Sender<String>,
+}
+
+/// Settings of the channel, read from the configuration when it starts
+struct Settings {
+    password: String,
+    timeout: Duration,
+    max_connections: usize,
+}
+
+/// Only accessed from the game thread
+static mut REMOTE: Option<Remote> = None;
+
+/// Maximum length of a line of a request
+const MAX_LINE_LEN: u64 = 1024;
+
+/// Interval between two polls of the listener for new connections
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Delay before an address can connect again after its first failed
+/// attempt, doubled on each failed attempt up to `MAX_BACKOFF`
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Start listening on the address of the `remote` configuration, if any
+pub(crate) fn start() {
+    let config = &config::get().remote;
+    if config.listen.is_empty() {
+        return;
+    }
+
+    if config.password.is_empty() {
+        warn!("remote management channel is disabled, remote.password is not set");
+        return;
+    }
+
+    let listener = match TcpListener::bind(&config.listen) {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("could not listen on {}: {}", config.listen, err);
+            return;
+        }
+    };
+
+    // The listener is polled so its thread notices when the channel is stopped
+    if let Err(err) = listener.set_nonblocking(true) {
+        warn!("could not configure the remote listener: {}", err);
+        return;
+    }
+
+    let settings = Settings {
+        password: config.password.clone(),
+        timeout: Duration::from_millis(config.timeout_ms),
+        max_connections: config.max_connections.max(1),
+    };
+
+    let running = Arc::new(AtomicBool::new(true));
+    let (sender, receiver) = channel();
+
+    let listener = {
+        let running = running.clone();
+        thread::Builder::new()
+            .name(String::from("fabric-remote"))
+            .spawn(move || listen(listener, settings, &running, sender))
+    };
+
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("could not spawn the remote listener thread: {}", err);
+            return;
+        }
+    };
+
+    info!("remote management channel listening on {}", config.listen);
+
+    unsafe {
+        REMOTE = Some(Remote {
+            running,
+            listener,
+            receiver,
+        });
+    }
+}
+
+/// Close the listener, the connections being handled are
+/// answered with an error once their command is dropped
+pub(crate) fn stop() {
+    let remote = unsafe { REMOTE.take() };
+    if let Some(Remote {
+        running, listener, ..
+    }) = remote
+    {
+        running.store(false, Ordering::Relaxed);
+        if listener.join().is_err() {
+            warn!("remote listener thread panicked");
+        }
+    }
+}
+
+/// Execute the commands received since the last frame
+pub(crate) fn run(modules: &[Module], schema: Option<&Rc<EventSchema>>) {
+    let remote = match unsafe { REMOTE.as_ref() } {
+        Some(remote) => remote,
+        None => return,
+    };
+
+    let requests: Vec<_> = remote.receiver.try_iter().collect();
+    for Request { args, reply } in requests {
+        let response = match execute(modules, schema, &args) {
+            Ok(output) => format!("OK\n{}", output),
+            Err(err) => format!("ERR {}\n", err),
+        };
+
+        // The connection may have timed out in the meantime
+        reply.send(response).ok();
+    }
+}
+
+/// Accept the connections until the channel is stopped, on the listener thread
+fn listen(
+    listener: TcpListener,
+    settings: Settings,
+    running: &AtomicBool,
+    sender: Sender<Request>,
+) {
+    let settings = Arc::new(settings);
+    let connections = Arc::new(AtomicUsize::new(0));
+    let failures = Arc::new(Mutex::new(Failures::default()));
+
+    while running.load(Ordering::Relaxed) {
+        let (stream, address) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    warn!("could not accept remote connection: {}", err);
+                }
+
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        debug!("remote connection from {}", address);
+
+        let address = address.ip();
+        let retry_in = failures.lock().unwrap().retry_in(address);
+        if let Some(delay) = retry_in {
+            debug!("refusing remote connection from {}", address);
+            let response = format!(
+                "ERR too many failed attempts, retry in {}s\n",
+                delay.as_secs() + 1
+            );
+            write_response(stream, &response);
+            continue;
+        }
+
+        // Only the listener thread increments the count of connections
+        if connections.load(Ordering::SeqCst) >= settings.max_connections {
+            warn!(
+                "refusing remote connection from {}, too many connections",
+                address
+            );
+            write_response(stream, "ERR too many connections\n");
+            continue;
+        }
+
+        let slot = Slot::acquire(&connections);
+        let settings = settings.clone();
+        let failures = failures.clone();
+        let sender = sender.clone();
+        let connection = thread::Builder::new()
+            .name(format!("fabric-remote-{}", address))
+            .spawn(move || {
+                let _slot = slot;
+                handle_connection(stream, address, &settings, &failures, &sender);
+            });
+
+        if let Err(err) = connection {
+            warn!("could not spawn remote connection thread: {}", err);
+        }
+    }
+}
+
+/// Connection counted against `max_connections` until it is dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn acquire(connections: &Arc<AtomicUsize>) -> Self {
+        connections.fetch_add(1, Ordering::SeqCst);
+        Slot(connections.clone())
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Failed password attempts of an address
+struct Failure {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// Addresses that sent a wrong password, refused until their backoff expires
+#[derive(Default)]
+struct Failures(HashMap<IpAddr, Failure>);
+
+impl Failures {
+    /// Time left before `address` can connect again, if it is refused
+    fn retry_in(&self, address: IpAddr) -> Option<Duration> {
+        let failure = self.0.get(&address)?;
+        let now = Instant::now();
+        if failure.retry_at > now {
+            Some(failure.retry_at - now)
+        } else {
+            None
+        }
+    }
+
+    /// Record a failed attempt of `address`, returns the delay before it can connect again
+    fn record(&mut self, address: IpAddr) -> Duration {
+        let now = Instant::now();
+        // The addresses that could connect again for long are forgotten
+        self.0
+            .retain(|_, failure| failure.retry_at + MAX_BACKOFF > now);
+
+        let failure = self.0.entry(address).or_insert(Failure {
+            attempts: 0,
+            retry_at: now,
+        });
+
+        failure.attempts = failure.attempts.saturating_add(1);
+        let delay = backoff(failure.attempts);
+        failure.retry_at = now + delay;
+        delay
+    }
+
+    /// Forget the failed attempts of `address` once it sent the right password
+    fn clear(&mut self, address: IpAddr) {
+        self.0.remove(&address);
+    }
+}
+
+/// Delay before connecting again after `attempts` failed attempts in a row
+fn backoff(attempts: u32) -> Duration {
+    let factor = 1 << attempts.saturating_sub(1).min(16);
+    (BASE_BACKOFF * factor).min(MAX_BACKOFF)
+}
+
+/// Compare the digests of the passwords, so the time taken depends
+/// neither on their first difference nor on their length
+fn is_password(password: &str, expected: &str) -> bool {
+    let password = Sha256::digest(password.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+
+    password
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Read the password and the command of a connection and write the
+/// output of the command, on the thread of the connection
+fn handle_connection(
+    stream: TcpStream,
+    address: IpAddr,
+    settings: &Settings,
+    failures: &Mutex<Failures>,
+    sender: &Sender<Request>,
+) {
+    let result = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(settings.timeout)))
+        .and_then(|()| stream.set_write_timeout(Some(settings.timeout)))
+        .and_then(|()| stream.try_clone());
+
+    let reader = match result {
+        Ok(reader) => reader,
+        Err(err) => {
+            warn!("could not configure remote connection: {}", err);
+            return;
+        }
+    };
+
+    let mut lines = BufReader::new(reader.take(MAX_LINE_LEN * 2)).lines();
+    let password = match lines.next() {
+        Some(Ok(password)) => password,
+        _ => return,
+    };
+
+    if !is_password(password.trim_end_matches('\r'), &settings.password) {
+        let delay = failures.lock().unwrap().record(address);
+        warn!(
+            "remote connection from {} with an invalid password, refused for {}s",
+            address,
+            delay.as_secs()
+        );
+        write_response(stream, "ERR invalid password\n");
+        return;
+    }
+
+    failures.lock().unwrap().clear(address);
+
+    let args: Vec<String> = match lines.next() {
+        Some(Ok(command)) => command.split_whitespace().map(String::from).collect(),
+        _ => return,
+    };
+
+    if args.is_empty() {
+        write_response(stream, "ERR empty command\n");
+        return;
+    }
+
+    // The command is dropped if the channel is stopped before the next frame
+    let (reply, response) = channel();
+    if sender.send(Request { args, reply }).is_err() {
+        return;
+    }
+
+    let response = match response.recv_timeout(settings.timeout) {
+        Ok(response) => response,
+        Err(_) => String::from("ERR the server did not answer\n"),
+    };
+
+    write_response(stream, &response);
+}
+
+fn write_response(mut stream: TcpStream, response: &str) {
+    if let Err(err) = stream.write_all(response.as_bytes()) {
+        debug!("could not write remote response: {}", err);
+    }
+}
+
+/// Execute a management command on the game thread, returning its output
+fn execute(
+    modules: &[Module],
+    schema: Option<&Rc<EventSchema>>,
+    args: &[String],
+) -> Result<String, String> {
+    info!("remote command {:?}", args);
+
+    match args[0].as_str() {
+        "list" => {
+            let mut output = String::new();
+            for module in modules {
+                let module = match module.try_lock() {
+                    Ok(module) => module,
+                    Err(_) => continue,
+                };
+
+                let env = &module.environment;
+                output.push_str(&format!(
+                    "{} {}{}{}\n",
+                    env.name,
+                    env.desc.version,
+                    if env.failed { " [failed]" } else { "" },
+                    if env.paused { " [paused]" } else { "" }
+                ));
+            }
+
+            Ok(output)
+        }
+        "reload" => {
+            let name = args.get(1).ok_or("missing module name")?;
+            reload_by_name(modules, schema, name)?;
+            Ok(String::new())
+        }
+        "stats" => {
+            let mut dump = serde_json::Map::new();
+            for module in modules {
+                let module = match module.try_lock() {
+                    Ok(module) => module,
+                    Err(_) => continue,
+                };
+
+                let env = &module.environment;
+                let mut value = env.stats.to_json();
+                value["memory"] = module.memory.len().into();
+                value["externs"] = module.externs.len().into();
+                value["failed"] = env.failed.into();
+                value["paused"] = env.paused.into();
+                dump.insert(env.name.clone(), value);
+            }
+
+            Ok(format!("{}\n", serde_json::Value::Object(dump)))
+        }
+        "log" => {
+            let lines = logging::history(args.get(1).map(String::as_str));
+            Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+        }
+        "pause" | "unpause" => {
+            let name = args.get(1).ok_or("missing module name")?;
+            let paused = args[0] == "pause";
+
+            for module in modules {
+                let mut module = match module.try_lock() {
+                    Ok(module) => module,
+                    Err(_) => continue,
+                };
+
+                if module.environment.name == *name {
+                    set_paused(&mut module, paused);
+                    return Ok(String::new());
+                }
+            }
+
+            Err(format!("module {} not found", name))
+        }
+        "reload_config" => {
+            if !config::reload() {
+                return Err("the configuration was never loaded".into());
+            }
+
+            for module in modules {
+                host::config::notify_changed(module);
+            }
+
+            Ok(String::new())
+        }
+        command => Err(format!("unknown command {}", command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn compare_passwords() {
+        assert!(is_password("secret", "secret"));
+        assert!(!is_password("secreT", "secret"));
+        assert!(!is_password("secret ", "secret"));
+        assert!(!is_password("", "secret"));
+        assert!(is_password("", ""));
+    }
+
+    #[test]
+    fn back_off_failed_attempts() {
+        assert_eq!(backoff(1), BASE_BACKOFF);
+        assert_eq!(backoff(2), BASE_BACKOFF * 2);
+        assert_eq!(backoff(5), BASE_BACKOFF * 16);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+
+        let address = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        let mut failures = Failures::default();
+        assert_eq!(failures.retry_in(address), None);
+
+        assert_eq!(failures.record(address), BASE_BACKOFF);
+        assert_eq!(failures.record(address), BASE_BACKOFF * 2);
+        assert!(failures.retry_in(address).unwrap() <= BASE_BACKOFF * 2);
+        assert_eq!(failures.retry_in(other), None);
+
+        failures.clear(address);
+        assert_eq!(failures.retry_in(address), None);
+        assert_eq!(failures.record(address), BASE_BACKOFF);
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashMap, ffi::CString, fs, path::Path};
+
+use fabric_codegen::cstr;
+use log::{debug, warn};
+
+use crate::{
+    foreign::Foreign,
+    keyvalues::{parse_keyvalues, KeyValue},
+    manager::GameEvent,
+};
+
+/// Type of a game event field, as declared in the event resource files
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FieldType {
+    Local,
+    String,
+    Float,
+    Long,
+    Short,
+    Byte,
+    Bool,
+    Uint64,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Option<Self> {
+        match &*name.to_ascii_lowercase() {
+            "local" => Some(FieldType::Local),
+            "string" => Some(FieldType::String),
+            "float" => Some(FieldType::Float),
+            "long" => Some(FieldType::Long),
+            "short" => Some(FieldType::Short),
+            "byte" => Some(FieldType::Byte),
+            "bool" => Some(FieldType::Bool),
+            "uint64" => Some(FieldType::Uint64),
+            _ => None,
+        }
+    }
+
+    /// Type code of the field in the encoded events, the `TYPE_*` value of the engine
+    fn code(self) -> u8 {
+        match self {
+            FieldType::Local => 0,
+            FieldType::String => 1,
+            FieldType::Float => 2,
+            FieldType::Long => 3,
+            FieldType::Short => 4,
+            FieldType::Byte => 5,
+            FieldType::Bool => 6,
+            FieldType::Uint64 => 7,
+        }
+    }
+
+    /// Returns true if the field can be read with `GameEvent::get_int`
+    pub(crate) fn is_integer(self) -> bool {
+        match self {
+            FieldType::Long | FieldType::Short | FieldType::Byte | FieldType::Bool => true,
+            _ => false,
+        }
+    }
+}
+
+/// A single field of a game event descriptor
+#[derive(Debug)]
+pub(crate) struct Field {
+    pub(crate) event: usize,
+    pub(crate) name: CString,
+    pub(crate) ty: FieldType,
+}
+
+/// Descriptors for all the game events known to the engine, loaded from the
+/// event resource files of the game directory
+///
+/// Fields are stored in a single flat table so they can be referenced by the
+/// guests through a numeric identifier instead of passing the field name as a
+/// string on every access
+#[derive(Debug, Default)]
+pub(crate) struct EventSchema {
+    events: Vec<CString>,
+    fields: Vec<Field>,
+    field_ids: HashMap<(String, String), u32>,
+}
+
+/// Event resource files loaded by the engine, in load order
+const EVENT_FILES: &[&str] = &[
+    "resource/serverevents.res",
+    "resource/gameevents.res",
+    "resource/modevents.res",
+];
+
+impl EventSchema {
+    /// Load the event descriptors from all the event resource files in `game_dir`
+    pub(crate) fn load(game_dir: &Path) -> Self {
+        let mut schema = EventSchema::default();
+
+        for file in EVENT_FILES {
+            let path = game_dir.join(file);
+            let source = match fs::read(&path) {
+                Ok(source) => source,
+                Err(err) => {
+                    debug!("could not read {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            let source = String::from_utf8_lossy(&source);
+            match parse_keyvalues(&source) {
+                Ok(document) => schema.insert_document(document),
+                Err(err) => warn!("could not parse {}: {}", path.display(), err),
+            }
+        }
+
+        debug!(
+            "loaded {} events with {} fields",
+            schema.events.len(),
+            schema.fields.len()
+        );
+
+        schema
+    }
+
+    fn insert_document(&mut self, document: Vec<(String, KeyValue)>) {
+        for (_, root) in document {
+            let events = match root {
+                KeyValue::Section(events) => events,
+                KeyValue::Value(_) => continue,
+            };
+
+            for (event, fields) in events {
+                let fields = match fields {
+                    KeyValue::Section(fields) => fields,
+                    KeyValue::Value(_) => continue,
+                };
+
+                let event_name = match CString::new(event.to_ascii_lowercase()) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+
+                // Later files override the definition of existing events
+                let event_index = match self.events.iter().position(|name| *name == event_name) {
+                    Some(index) => index,
+                    None => {
+                        self.events.push(event_name);
+                        self.events.len() - 1
+                    }
+                };
+
+                for (field, ty) in fields {
+                    let ty = match ty {
+                        KeyValue::Value(ty) => ty,
+                        KeyValue::Section(_) => continue,
+                    };
+
+                    let ty = match FieldType::parse(&ty) {
+                        Some(ty) => ty,
+                        None => {
+                            warn!("unknown type {:?} for field {}::{}", ty, event, field);
+                            continue;
+                        }
+                    };
+
+                    let name = match CString::new(field.clone()) {
+                        Ok(name) => name,
+                        Err(_) => continue,
+                    };
+
+                    let key = (event.to_ascii_lowercase(), field);
+                    if let Some(id) = self.field_ids.get(&key) {
+                        self.fields[*id as usize].ty = ty;
+                        continue;
+                    }
+
+                    let id = self.fields.len() as u32;
+                    self.fields.push(Field {
+                        event: event_index,
+                        name,
+                        ty,
+                    });
+                    self.field_ids.insert(key, id);
+                }
+            }
+        }
+    }
+
+    /// Resolve a global field identifier from a `event::field` import name
+    pub(crate) fn field_id(&self, name: &str) -> Option<u32> {
+        let mut parts = name.splitn(2, "::");
+        let event = parts.next()?.to_ascii_lowercase();
+        let field = parts.next()?.to_string();
+
+        self.field_ids.get(&(event, field)).copied()
+    }
+
+    pub(crate) fn field(&self, id: u32) -> Option<&Field> {
+        self.fields.get(id as usize)
+    }
+
+    pub(crate) fn event_name(&self, field: &Field) -> &CString {
+        &self.events[field.event]
+    }
+
+    /// Encode the name and all the networked fields of `event` declared in
+    /// the schema, the local fields are left out
+    ///
+    /// The event is written as its name, a little endian u16 with the number
+    /// of fields, then each field as its name, its type code and its value.
+    /// Names are prefixed with their length as a u8 and strings with their
+    /// length as a u16, the numbers are written with the size of their type
+    pub(crate) fn encode(&self, event: &mut Foreign<dyn GameEvent>) -> Vec<u8> {
+        let event_name = event.get_name().to_owned();
+        let event_index = self
+            .events
+            .iter()
+            .position(|name| name.as_bytes().eq_ignore_ascii_case(event_name.to_bytes()));
+
+        let mut output = Vec::new();
+        write_name(&mut output, event_name.to_bytes());
+
+        let count_offset = output.len();
+        output.extend_from_slice(&[0; 2]);
+
+        let fields = self.fields.iter().filter(|field| {
+            Some(field.event) == event_index
+                && field.ty != FieldType::Local
+                && field.name.as_bytes().len() <= usize::from(u8::MAX)
+        });
+
+        let mut count: u16 = 0;
+        for field in fields.take(usize::from(u16::MAX)) {
+            let name = field.name.as_c_str();
+            write_name(&mut output, name.to_bytes());
+            output.push(field.ty.code());
+
+            match field.ty {
+                FieldType::String => {
+                    let value = event.get_string(name, cstr!("")).to_bytes();
+                    let value = &value[..value.len().min(usize::from(u16::MAX))];
+                    output.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                    output.extend_from_slice(value);
+                }
+                FieldType::Float => {
+                    output.extend_from_slice(&event.get_float(name, 0.0).to_le_bytes())
+                }
+                FieldType::Long => output.extend_from_slice(&event.get_int(name, 0).to_le_bytes()),
+                FieldType::Short => {
+                    output.extend_from_slice(&(event.get_int(name, 0) as i16).to_le_bytes())
+                }
+                FieldType::Byte => output.push(event.get_int(name, 0) as u8),
+                FieldType::Bool => output.push(event.get_bool(name, false) as u8),
+                FieldType::Uint64 => {
+                    output.extend_from_slice(&event.get_uint64(name, 0).to_le_bytes())
+                }
+                FieldType::Local => unreachable!(),
+            }
+
+            count += 1;
+        }
+
+        output[count_offset..count_offset + 2].copy_from_slice(&count.to_le_bytes());
+        output
+    }
+}
+
+/// Write a name prefixed with its length, truncated to 255 bytes
+fn write_name(output: &mut Vec<u8>, name: &[u8]) {
+    let name = &name[..name.len().min(usize::from(u8::MAX))];
+    output.push(name.len() as u8);
+    output.extend_from_slice(name);
+}
@@ -0,0 +1,287 @@
+//! The `fabric_selftest` console command, running small built-in modules
+//! through the runtime on the live server
+//!
+//! The modules only import the functions of a `selftest` host module defined
+//! here and never touch the engine, so a test failing on a server while it
+//! passes elsewhere points to the runtime rather than to the game
+
+use std::{
+    fmt::Debug,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::Instant,
+};
+
+use fabric_runtime::{
+    load_module, take_host_panic, with_abi, Abort, Environment, ExternRef, Function, GlobalValue,
+    VMContext,
+};
+use log::{info, warn};
+
+use crate::stats;
+
+/// Environment of the self-test modules
+#[derive(Default)]
+struct SelfTestEnv {
+    /// Aborts reported by the module, formatted as with `Display`
+    aborts: Vec<String>,
+}
+
+type SelfTestContext = VMContext<SelfTestEnv>;
+type RunFunc = with_abi!(fn(*mut SelfTestContext) -> i32);
+
+with_abi! {
+    fn add(_ctx: *mut SelfTestContext, a: i32, b: i32) -> i32 {
+        a.wrapping_add(b)
+    }
+}
+
+// Write a NUL-terminated string to the memory of the module at `ptr`
+with_abi! {
+    fn greet(ctx: *mut SelfTestContext, ptr: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        match ctx.memory.store(ptr as u32 as usize, b"fabric\0") {
+            Ok(()) => 0,
+            Err(()) => -1,
+        }
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut SelfTestContext, value: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+        ctx.create_extern(value)
+    }
+}
+
+with_abi! {
+    fn read(ctx: *mut SelfTestContext, value: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        ctx.externs.try_get_extern::<i32>(value).copied().unwrap_or(-1)
+    }
+}
+
+impl Environment for SelfTestEnv {
+    fn import_function(&mut self, module: &str, name: &str) -> Option<Function> {
+        match (module, name) {
+            ("selftest", "add") => Some(Function::new(
+                add as with_abi!(fn(*mut SelfTestContext, i32, i32) -> i32),
+            )),
+            ("selftest", "greet") => Some(Function::new(
+                greet as with_abi!(fn(*mut SelfTestContext, i32) -> i32),
+            )),
+            ("selftest", "create") => Some(Function::new(
+                create as with_abi!(fn(*mut SelfTestContext, i32) -> ExternRef),
+            )),
+            ("selftest", "read") => Some(Function::new(
+                read as with_abi!(fn(*mut SelfTestContext, ExternRef) -> i32),
+            )),
+            _ => None,
+        }
+    }
+
+    fn import_global(&mut self, _module: &str, _name: &str) -> Option<GlobalValue> {
+        None
+    }
+
+    fn on_abort(&mut self, abort: &Abort) {
+        self.aborts.push(abort.to_string());
+    }
+}
+
+/// A module exporting a `run` function, and the checks of its result
+struct Test {
+    name: &'static str,
+    source: &'static str,
+    check: fn(&SelfTestContext, i32) -> Result<(), String>,
+}
+
+fn expect<T: PartialEq + Debug>(name: &str, actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("{} is {:?}, expected {:?}", name, actual, expected))
+    }
+}
+
+fn check_aborts(context: &SelfTestContext, expected: &[&str]) -> Result<(), String> {
+    let aborts: Vec<_> = context
+        .environment
+        .aborts
+        .iter()
+        .map(String::as_str)
+        .collect();
+    expect("aborts", aborts.as_slice(), expected)
+}
+
+const TESTS: &[Test] = &[
+    Test {
+        name: "memory",
+        source: r#"(module
+            (memory 1)
+            (data (i32.const 64) "\01\02\03\04")
+            (func (export "run") (result i32)
+                (i32.store (i32.const 16) (i32.const 0x12345678))
+                (i64.store8 (i32.const 20) (i64.const 0xff))
+                (i32.add (i32.load (i32.const 16)) (i32.load (i32.const 64)))))"#,
+        check: |context, result| {
+            expect("result", result, 0x12345678 + 0x04030201)?;
+            expect(
+                "memory",
+                context.memory.bytes(16, 5),
+                Ok(&[0x78, 0x56, 0x34, 0x12, 0xff][..]),
+            )
+        },
+    },
+    Test {
+        name: "memory growth",
+        source: r#"(module
+            (memory 1 4)
+            (func (export "run") (result i32)
+                (drop (memory.grow (i32.const 2)))
+                (i32.store (i32.const 0x2fffc) (i32.const 42))
+                (i32.add
+                    (i32.mul (memory.grow (i32.const 8)) (i32.const 100))
+                    (memory.size))))"#,
+        check: |context, result| {
+            // The second growth exceeds the maximum and fails with -1
+            expect("result", result, -100 + 3)?;
+            expect("pages", context.memory.pages(), 3)?;
+            expect(
+                "memory",
+                context.memory.bytes(0x2fffc, 4),
+                Ok(&[42, 0, 0, 0][..]),
+            )
+        },
+    },
+    Test {
+        name: "host calls",
+        source: r#"(module
+            (import "selftest" "add" (func $add (param i32 i32) (result i32)))
+            (func (export "run") (result i32)
+                (call $add (call $add (i32.const 40) (i32.const 1)) (i32.const 1))))"#,
+        check: |_, result| expect("result", result, 42),
+    },
+    Test {
+        name: "host memory access",
+        source: r#"(module
+            (import "selftest" "greet" (func $greet (param i32) (result i32)))
+            (memory 1)
+            (func (export "run") (result i32)
+                (drop (call $greet (i32.const 32)))
+                (i32.add
+                    (call $greet (i32.const 0x10000))
+                    (i32.load8_u (i32.const 32)))))"#,
+        check: |context, result| {
+            // The second call is out of the memory and fails with -1
+            expect("result", result, i32::from(b'f') - 1)?;
+            expect("memory", context.memory.bytes(32, 7), Ok(&b"fabric\0"[..]))
+        },
+    },
+    Test {
+        name: "externs",
+        source: r#"(module
+            (import "selftest" "create" (func $create (param i32) (result externref)))
+            (import "selftest" "read" (func $read (param externref) (result i32)))
+            (func (export "run") (result i32)
+                (local externref)
+                (local.set 0 (call $create (i32.const 7)))
+                (i32.add
+                    (call $read (local.get 0))
+                    (call $read (call $create (i32.const 35))))))"#,
+        check: |context, result| {
+            expect("result", result, 42)?;
+            expect("live externs", context.externs.len(), 2)
+        },
+    },
+    Test {
+        name: "unreachable trap",
+        source: r#"(module
+            (func (export "run") (result i32)
+                unreachable))"#,
+        check: |context, result| {
+            expect("result", result, 0)?;
+            check_aborts(context, &["unreachable code executed"])
+        },
+    },
+    Test {
+        name: "abort trap",
+        source: r#"(module
+            (import "fabric" "abort" (func $abort (param i32 i32 i32)))
+            (memory 1)
+            (data (i32.const 16) "failed\00")
+            (data (i32.const 32) "selftest.rs\00")
+            (func (export "run") (result i32)
+                (call $abort (i32.const 16) (i32.const 32) (i32.const 12))
+                unreachable))"#,
+        check: |context, result| {
+            expect("result", result, 0)?;
+            check_aborts(context, &["failed at selftest.rs:12"])
+        },
+    },
+    Test {
+        name: "indirect call trap",
+        source: r#"(module
+            (type $unary (func (param i32) (result i32)))
+            (table 1 funcref)
+            (elem (i32.const 0) $answer)
+            (func $answer (result i32) (i32.const 42))
+            (func (export "run") (result i32)
+                (i32.add
+                    (call_indirect (result i32) (i32.const 0))
+                    (call_indirect (type $unary) (i32.const 5) (i32.const 0)))))"#,
+        check: |context, result| {
+            // The second call, with the wrong type, returns 0 instead
+            expect("result", result, 42)?;
+            expect("aborts", context.environment.aborts.len(), 1)
+        },
+    },
+];
+
+/// Load the module of `test`, call its `run` function and check the results
+fn run_test(test: &Test) -> Result<(), String> {
+    let context = catch_unwind(AssertUnwindSafe(|| {
+        load_module(SelfTestEnv::default(), test.source)
+    }));
+
+    let mut context = match context {
+        Ok(context) => context,
+        Err(_) => return Err(String::from("could not compile the module")),
+    };
+
+    take_host_panic();
+    let run = context.exports().typed::<RunFunc>("run");
+    let result = run.map(|run| run(&mut context));
+
+    let result = match result {
+        Ok(_) if take_host_panic() => Err(String::from("a host function panicked")),
+        Ok(result) => (test.check)(&context, result),
+        Err(err) => Err(err),
+    };
+
+    drop(context.externs.drain());
+    unsafe { context.unload() };
+    result
+}
+
+/// Handler of the `fabric_selftest` console command
+pub(crate) fn command(_args: &[String]) {
+    let start = Instant::now();
+    let mut failed = 0;
+
+    for test in TESTS {
+        match run_test(test) {
+            Ok(()) => info!("{}: pass", test.name),
+            Err(err) => {
+                warn!("{}: FAIL, {}", test.name, err);
+                failed += 1;
+            }
+        }
+    }
+
+    let elapsed = stats::format_ms(start.elapsed());
+    if failed == 0 {
+        info!("{} tests passed in {}", TESTS.len(), elapsed);
+    } else {
+        warn!("{} of {} tests failed in {}", failed, TESTS.len(), elapsed);
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int},
+};
+
+use crate::{addon::Edict, foreign::CreateInterfaceFn, game, netprops::ServerClass};
+
+/// Shared global variables of the server, `CGlobalVarsBase` in the
+/// SDK's `globalvars_base.h`
+///
+/// The object is owned by the server DLL and is only ever accessed through a
+/// pointer, the offsets of its fields depend on the branch of the engine
+pub(crate) struct GlobalVars {
+    _private: [u8; 0],
+}
+
+impl GlobalVars {
+    fn field<T: Copy>(&self, offset: usize) -> T {
+        let ptr = self as *const GlobalVars as *const u8;
+        unsafe { *(ptr.add(offset) as *const T) }
+    }
+
+    /// Current time
+    pub(crate) fn curtime(&self) -> f32 {
+        self.field(game::info().globals.curtime)
+    }
+
+    /// Time spent on last server or client frame (has nothing to do with think intervals)
+    pub(crate) fn frametime(&self) -> f32 {
+        self.field(game::info().globals.frametime)
+    }
+
+    /// current maxplayers
+    pub(crate) fn max_clients(&self) -> c_int {
+        self.field(game::info().globals.max_clients)
+    }
+
+    /// Simulation ticks - does not increase when game is paused
+    pub(crate) fn tick_count(&self) -> c_int {
+        self.field(game::info().globals.tick_count)
+    }
+
+    /// Simulation tick interval
+    pub(crate) fn interval_per_tick(&self) -> f32 {
+        self.field(game::info().globals.interval_per_tick)
+    }
+}
+
+/// Binding for IPlayerInfoManager, exposed by the server DLL
+#[fabric_codegen::interface]
+pub(crate) trait PlayerInfoManager {
+    fn get_player_info(&mut self, edict: *mut Edict) -> *mut c_void;
+    fn get_global_vars(&mut self) -> *mut GlobalVars;
+}
+
+/// Binding for IServerGameDLL, the main interface exposed by the server DLL
+/// to the engine
+///
+/// Only the methods up to `GetUserMessageInfo` are declared, in the same
+/// order as the Alien Swarm SDK's `eiface.h`
+#[fabric_codegen::interface]
+pub(crate) trait ServerGameDLL {
+    /// Initialize the game (one-time call when the DLL is first loaded )
+    /// Return false if there is an error during startup.
+    fn dll_init(
+        &mut self,
+        engine_factory: CreateInterfaceFn,
+        physics_factory: CreateInterfaceFn,
+        file_system_factory: CreateInterfaceFn,
+        globals: *mut GlobalVars,
+    ) -> bool;
+
+    /// This is called when a new game is started. (restart, map)
+    fn game_init(&mut self) -> bool;
+
+    /// Called any time a new level is started (after GameInit() also on level transitions within a game)
+    fn level_init(
+        &mut self,
+        map_name: &CStr,
+        map_entities: &CStr,
+        old_level: *const c_char,
+        landmark_name: *const c_char,
+        load_game: bool,
+        background: bool,
+    ) -> bool;
+
+    /// The server is about to activate
+    fn server_activate(&mut self, edict_list: *mut Edict, edict_count: c_int, client_max: c_int);
+
+    /// The server should run physics/think on all edicts
+    fn game_frame(&mut self, simulating: bool);
+
+    /// Called once per simulation frame on the final tick
+    fn pre_client_update(&mut self, simulating: bool);
+
+    /// Called when a level is shutdown (including changing levels)
+    fn level_shutdown(&mut self);
+    /// This is called when a game ends (server disconnect, death, restart, load)
+    /// NOT on level transitions within a game
+    fn game_shutdown(&mut self);
+
+    /// Called once during DLL shutdown
+    fn dll_shutdown(&mut self);
+
+    /// Get the simulation interval (must be compiled with identical values into both client and game .dll for MOD!!!)
+    /// Right now this is only requested at server startup time so it can't be changed on the fly, etc.
+    fn get_tick_interval(&self) -> f32;
+
+    /// Give the list of datatable classes to the engine.  The engine matches class names from here with
+    /// edict_t::classname to figure out how to encode a class's data for networking
+    fn get_all_server_classes(&mut self) -> *mut ServerClass;
+
+    /// Returns string describing current .dll.  e.g., TeamFortress 2, Half-Life 2.
+    /// Hey, it's more descriptive than just the name of the game directory
+    fn get_game_description(&mut self) -> &CStr;
+
+    /// Let the game.dll allocate it's own network/shared string tables
+    fn create_network_string_tables(&mut self);
+
+    // Save/restore system hooks
+    fn save_init(&mut self, size: c_int) -> *mut c_void;
+    fn save_write_fields(
+        &mut self,
+        save: *mut c_void,
+        name: *const c_char,
+        base: *mut c_void,
+        map: *mut c_void,
+        fields: *mut c_void,
+        count: c_int,
+    );
+    fn save_read_fields(
+        &mut self,
+        save: *mut c_void,
+        name: *const c_char,
+        base: *mut c_void,
+        map: *mut c_void,
+        fields: *mut c_void,
+        count: c_int,
+    );
+    fn save_global_state(&mut self, save: *mut c_void);
+    fn restore_global_state(&mut self, save: *mut c_void);
+    fn pre_save(&mut self, save: *mut c_void);
+    fn save(&mut self, save: *mut c_void);
+    fn get_save_comment(
+        &mut self,
+        comment: *mut c_char,
+        max_length: c_int,
+        minutes: f32,
+        seconds: f32,
+        no_time: bool,
+    );
+    fn write_save_headers(&mut self, save: *mut c_void);
+    fn read_restore_headers(&mut self, save: *mut c_void);
+    fn restore(&mut self, save: *mut c_void, create_players: bool);
+    fn is_restoring(&mut self) -> bool;
+    fn supports_save_restore(&mut self) -> bool;
+
+    /// Returns the number of entities moved across the transition
+    fn create_entity_transition_list(&mut self, save: *mut c_void, level_mask: c_int) -> c_int;
+    /// Build the list of maps adjacent to the current map
+    fn build_adjacent_map_list(&mut self);
+
+    /// Retrieve info needed for parsing the specified user message
+    fn get_user_message_info(
+        &mut self,
+        message_type: c_int,
+        name: *mut c_char,
+        max_name_length: c_int,
+        size: *mut c_int,
+    ) -> bool;
+}
@@ -0,0 +1,96 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_int,
+    ptr::null_mut,
+};
+
+use crate::{
+    deferred::{self, Capability},
+    engine::Vector,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for IEngineSound, the sound emission interface of the engine
+///
+/// Only the methods up to `EmitSound` are declared, in the same order as the
+/// Alien Swarm SDK's `IEngineSound.h`. MSVC places overloaded virtual methods
+/// in reverse declaration order, so the `soundlevel_t` variant of EmitSound
+/// comes before the attenuation variant in the vtable
+#[fabric_codegen::interface]
+pub(crate) trait EngineSound {
+    /// Precache a particular sample
+    fn precache_sound(&mut self, sample: &CStr, preload: bool, is_ui_sound: bool) -> bool;
+    fn is_sound_precached(&mut self, sample: &CStr) -> bool;
+    fn prefetch_sound(&mut self, sample: &CStr);
+    fn is_looping_sound(&mut self, sample: &CStr) -> bool;
+
+    /// Just loads the file header and checks for duration (not hooked up for .mp3's yet)
+    /// Is accessible to server and client though
+    fn get_sound_duration(&mut self, sample: &CStr) -> f32;
+
+    /// Pitch of 100 is no pitch shift.  Pitch > 100 up to 255 is a higher pitch, pitch < 100
+    /// down to 1 is a lower pitch.   150 to 70 is the realistic range.
+    /// EmitSound with pitch != 100 should be used sparingly, as it's not quite as
+    /// fast (the pitchshift mixer is not native coded).
+    fn emit_sound_level(
+        &mut self,
+        filter: *mut c_void,
+        entity_index: c_int,
+        channel: c_int,
+        sample: &CStr,
+        volume: f32,
+        sound_level: c_int,
+        flags: c_int,
+        pitch: c_int,
+        origin: *const Vector,
+        direction: *const Vector,
+        origins: *mut c_void,
+        update_positions: bool,
+        sound_time: f32,
+        speaker_entity: c_int,
+    );
+    fn emit_sound(
+        &mut self,
+        filter: *mut c_void,
+        entity_index: c_int,
+        channel: c_int,
+        sample: &CStr,
+        volume: f32,
+        attenuation: f32,
+        flags: c_int,
+        pitch: c_int,
+        origin: *const Vector,
+        direction: *const Vector,
+        origins: *mut c_void,
+        update_positions: bool,
+        sound_time: f32,
+        speaker_entity: c_int,
+    );
+}
+
+static mut SOUND: *mut c_void = null_mut();
+
+/// Acquire the sound interface from the engine factory, this
+/// must be called from the game thread when the addon is loaded
+pub(crate) fn init(factory: CreateInterfaceFn) {
+    let interface =
+        game::acquire::<dyn EngineSound>(factory, game::interfaces().engine_sound, "IEngineSound");
+    unsafe {
+        SOUND = interface.map_or(null_mut(), |sound| sound.0);
+    }
+}
+
+/// Get the sound interface, looked up again at most once per level while it is missing
+pub(crate) fn sound() -> Option<Foreign<dyn EngineSound>> {
+    let mut sound = unsafe { SOUND };
+    if sound.is_null() && deferred::retry(Capability::Sound) {
+        sound = unsafe { SOUND };
+    }
+
+    if sound.is_null() {
+        None
+    } else {
+        Some(Foreign::with(sound))
+    }
+}
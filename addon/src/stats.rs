@@ -0,0 +1,112 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+/// Number of execution time samples kept for each kind of callback,
+/// the percentiles are computed over the most recent calls only
+const MAX_SAMPLES: usize = 1024;
+
+/// Execution times of a kind of guest callback
+#[derive(Default)]
+pub(crate) struct Timings {
+    pub(crate) calls: u64,
+    total: Duration,
+    samples: VecDeque<Duration>,
+}
+
+impl Timings {
+    fn record(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.total += elapsed;
+
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(elapsed);
+    }
+
+    pub(crate) fn average(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::default()
+        } else {
+            self.total / self.calls as u32
+        }
+    }
+
+    /// Returns the execution time under which the fraction
+    /// `percentile` of the recent calls completed
+    pub(crate) fn percentile(&self, percentile: f64) -> Duration {
+        let mut samples: Vec<_> = self.samples.iter().copied().collect();
+        samples.sort_unstable();
+
+        let index = ((samples.len() as f64 * percentile).ceil() as usize).saturating_sub(1);
+        samples.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// Execution metrics of a module, collected by `call_guest`
+#[derive(Default)]
+pub(crate) struct Stats {
+    /// Number of host functions called by the module
+    pub(crate) host_calls: u64,
+    /// Execution times of the guest callbacks, keyed by kind of callback
+    pub(crate) callbacks: BTreeMap<&'static str, Timings>,
+    /// Number of times the memory of the module grew
+    pub(crate) memory_grows: u64,
+    /// Number of times the memory was refused to grow over the configured limit
+    pub(crate) refused_grows: u64,
+    /// Number of externs created by the module, keyed by type name
+    pub(crate) externs_created: BTreeMap<&'static str, u64>,
+}
+
+impl Stats {
+    pub(crate) fn record(&mut self, kind: &'static str, elapsed: Duration, host_calls: u64) {
+        self.host_calls += host_calls;
+        self.callbacks.entry(kind).or_default().record(elapsed);
+    }
+
+    /// Number of game events handled by the module
+    pub(crate) fn events(&self) -> u64 {
+        self.callbacks
+            .get("event")
+            .map_or(0, |timings| timings.calls)
+    }
+
+    pub(crate) fn record_extern(&mut self, type_name: &'static str) {
+        *self.externs_created.entry(type_name).or_default() += 1;
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        let callbacks: serde_json::Map<_, _> = self
+            .callbacks
+            .iter()
+            .map(|(kind, timings)| {
+                let value = json!({
+                    "calls": timings.calls,
+                    "average_us": timings.average().as_micros() as u64,
+                    "p99_us": timings.percentile(0.99).as_micros() as u64,
+                });
+
+                (kind.to_string(), value)
+            })
+            .collect();
+
+        json!({
+            "events": self.events(),
+            "host_calls": self.host_calls,
+            "callbacks": callbacks,
+            "memory_grows": self.memory_grows,
+            "refused_grows": self.refused_grows,
+            "externs_created": self.externs_created,
+        })
+    }
+}
+
+/// Format a duration in milliseconds for the console
+pub(crate) fn format_ms(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,40 @@
+use std::{cell::Cell, thread};
+
+use log::error;
+
+thread_local! {
+    /// Set on the thread the addon was loaded from
+    static IS_GAME_THREAD: Cell<bool> = Cell::new(false);
+}
+
+/// Record the current thread as the game thread, this must be
+/// called from the `load` callback of the plugin
+pub(crate) fn init() {
+    IS_GAME_THREAD.with(|is_game_thread| is_game_thread.set(true));
+}
+
+pub(crate) fn is_game_thread() -> bool {
+    IS_GAME_THREAD.with(Cell::get)
+}
+
+/// Panic if the current thread isn't the game thread, `what` describes the
+/// operation being attempted
+///
+/// The engine interfaces and the modules aren't thread safe, calling them
+/// from a worker of the executor would corrupt their state and crash the
+/// server much later. The panic kills the offending thread instead
+#[track_caller]
+pub(crate) fn assert_game_thread(what: &str) {
+    if !is_game_thread() {
+        let current = thread::current();
+        let name = current.name().unwrap_or("<unnamed>");
+        error!(
+            "{} called from thread {} instead of the game thread",
+            what, name
+        );
+        panic!(
+            "{} called from thread {} instead of the game thread",
+            what, name
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int},
+    ptr::null_mut,
+};
+
+use crate::{
+    deferred::{self, Capability},
+    engine::Vector,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for IServerTools, exposed by the server DLL to the tools framework
+///
+/// Only the methods up to `RemoveEntityImmediate` are declared, in the same
+/// order as the Alien Swarm SDK's `itoolentity.h`. MSVC places overloaded
+/// virtual methods in reverse declaration order, so the `SetKeyValue`
+/// variants are declared from last to first
+#[fabric_codegen::interface]
+pub(crate) trait ServerTools {
+    fn destructor(&mut self);
+
+    fn get_iserver_entity(&mut self, client_entity: *mut c_void) -> *mut c_void;
+    fn snap_player_to_position(
+        &mut self,
+        origin: *const Vector,
+        angles: *const Vector,
+        client_player: *mut c_void,
+    ) -> bool;
+    fn get_player_position(
+        &mut self,
+        origin: *mut Vector,
+        angles: *mut Vector,
+        client_player: *mut c_void,
+    ) -> bool;
+    fn set_player_fov(&mut self, fov: c_int, client_player: *mut c_void) -> bool;
+    fn get_player_fov(&mut self, client_player: *mut c_void) -> c_int;
+    fn is_in_no_clip_mode(&mut self, client_player: *mut c_void) -> bool;
+
+    /// entity searching
+    fn first_entity(&mut self) -> *mut c_void;
+    fn next_entity(&mut self, entity: *mut c_void) -> *mut c_void;
+    fn find_entity_by_hammer_id(&mut self, hammer_id: c_int) -> *mut c_void;
+
+    /// entity query
+    fn get_key_value(
+        &mut self,
+        entity: *mut c_void,
+        field: &CStr,
+        value: *mut c_char,
+        max_len: c_int,
+    ) -> bool;
+    fn set_key_value_vector(
+        &mut self,
+        entity: *mut c_void,
+        field: &CStr,
+        value: *const Vector,
+    ) -> bool;
+    fn set_key_value_float(&mut self, entity: *mut c_void, field: &CStr, value: f32) -> bool;
+    fn set_key_value(&mut self, entity: *mut c_void, field: &CStr, value: &CStr) -> bool;
+
+    /// entity spawning
+    fn create_entity_by_name(&mut self, class_name: &CStr) -> *mut c_void;
+    fn dispatch_spawn(&mut self, entity: *mut c_void);
+
+    /// This reloads a portion or all of a particle definition file.
+    fn reload_particle_definitions(&mut self, file_name: &CStr, data: *const c_void, len: c_int);
+
+    fn add_origin_to_pvs(&mut self, origin: *const Vector);
+    fn move_engine_view_to(&mut self, position: *const Vector, angles: *const Vector);
+
+    fn destroy_entity_by_hammer_id(&mut self, hammer_id: c_int) -> bool;
+    fn get_base_entity_by_ent_index(&mut self, index: c_int) -> *mut c_void;
+    fn remove_entity(&mut self, entity: *mut c_void);
+    fn remove_entity_immediate(&mut self, entity: *mut c_void);
+}
+
+static mut SERVER_TOOLS: *mut c_void = null_mut();
+
+/// Acquire the server tools from the server factory, this must
+/// be called from the game thread when the addon is loaded
+pub(crate) fn init(server: CreateInterfaceFn) {
+    let interface =
+        game::acquire::<dyn ServerTools>(server, game::interfaces().server_tools, "IServerTools");
+    unsafe {
+        SERVER_TOOLS = interface.map_or(null_mut(), |tools| tools.0);
+    }
+}
+
+/// Get the server tools, looked up again at most once per level while it is missing
+pub(crate) fn server_tools() -> Option<Foreign<dyn ServerTools>> {
+    let mut tools = unsafe { SERVER_TOOLS };
+    if tools.is_null() && deferred::retry(Capability::Tools) {
+        tools = unsafe { SERVER_TOOLS };
+    }
+
+    if tools.is_null() {
+        None
+    } else {
+        Some(Foreign::with(tools))
+    }
+}
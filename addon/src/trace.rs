@@ -0,0 +1,312 @@
+use std::{
+    ffi::c_void,
+    os::raw::{c_char, c_int, c_short, c_uint, c_ushort},
+    ptr::{null, null_mut},
+};
+
+use crate::{
+    deferred::{self, Capability},
+    engine::Vector,
+    entity::entity_index,
+    foreign::{CreateInterfaceFn, Foreign},
+    game,
+};
+
+/// Binding for IEngineTrace, the collision queries of the engine
+///
+/// Only the methods up to `TraceRay` are declared, in the
+/// same order as the Alien Swarm SDK's `IEngineTrace.h`
+#[fabric_codegen::interface]
+pub(crate) trait EngineTrace {
+    /// Returns the contents mask + entity at a particular world-space position
+    fn get_point_contents(
+        &mut self,
+        position: *const Vector,
+        contents_mask: c_int,
+        entity: *mut *mut c_void,
+    ) -> c_int;
+    /// Returns the contents mask of the world only @ the world-space position (static props are ignored)
+    fn get_point_contents_world_only(
+        &mut self,
+        position: *const Vector,
+        contents_mask: c_int,
+    ) -> c_int;
+    /// Get the point contents, but only test the specific entity. This works
+    /// on static props and brush models.
+    fn get_point_contents_collideable(
+        &mut self,
+        collideable: *mut c_void,
+        position: *const Vector,
+    ) -> c_int;
+
+    /// Traces a ray against a particular entity
+    fn clip_ray_to_entity(
+        &mut self,
+        ray: *const Ray,
+        mask: c_uint,
+        entity: *mut c_void,
+        trace: *mut Trace,
+    );
+    /// Traces a ray against a particular entity
+    fn clip_ray_to_collideable(
+        &mut self,
+        ray: *const Ray,
+        mask: c_uint,
+        collideable: *mut c_void,
+        trace: *mut Trace,
+    );
+
+    /// A version that simply accepts a ray (can work as a traceline or tracehull)
+    fn trace_ray(&mut self, ray: *const Ray, mask: c_uint, filter: *mut c_void, trace: *mut Trace);
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct VectorAligned {
+    x: f32,
+    y: f32,
+    z: f32,
+    w: f32,
+}
+
+impl From<Vector> for VectorAligned {
+    fn from(vector: Vector) -> Self {
+        VectorAligned {
+            x: vector.x,
+            y: vector.y,
+            z: vector.z,
+            w: 0.0,
+        }
+    }
+}
+
+/// Ray or swept box used in collision queries, declared
+/// as `Ray_t` in the Alien Swarm SDK's `cmodel.h`
+#[repr(C)]
+pub(crate) struct Ray {
+    /// starting point, centered within the extents
+    start: VectorAligned,
+    /// direction + length of the ray
+    delta: VectorAligned,
+    /// Add this to m_Start to get the actual ray start
+    start_offset: VectorAligned,
+    /// Describes an axis aligned box extruded along a ray
+    extents: VectorAligned,
+    world_axis_transform: *const c_void,
+    /// are the extents zero?
+    is_ray: bool,
+    /// is delta != 0?
+    is_swept: bool,
+}
+
+impl Ray {
+    /// Build a ray sweeping the box `mins..maxs` from `start` to `end`,
+    /// this is the same as `Ray_t::Init` in the SDK
+    pub(crate) fn new(start: Vector, end: Vector, mins: Vector, maxs: Vector) -> Self {
+        let delta = Vector {
+            x: end.x - start.x,
+            y: end.y - start.y,
+            z: end.z - start.z,
+        };
+
+        let extents = Vector {
+            x: (maxs.x - mins.x) * 0.5,
+            y: (maxs.y - mins.y) * 0.5,
+            z: (maxs.z - mins.z) * 0.5,
+        };
+
+        let offset = Vector {
+            x: (mins.x + maxs.x) * 0.5,
+            y: (mins.y + maxs.y) * 0.5,
+            z: (mins.z + maxs.z) * 0.5,
+        };
+
+        let length_sqr = |v: &Vector| v.x * v.x + v.y * v.y + v.z * v.z;
+
+        Ray {
+            start: Vector {
+                x: start.x + offset.x,
+                y: start.y + offset.y,
+                z: start.z + offset.z,
+            }
+            .into(),
+            delta: delta.into(),
+            start_offset: Vector {
+                x: -offset.x,
+                y: -offset.y,
+                z: -offset.z,
+            }
+            .into(),
+            extents: extents.into(),
+            world_axis_transform: null(),
+            is_ray: length_sqr(&extents) < 1e-6,
+            is_swept: length_sqr(&delta) != 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Plane {
+    pub(crate) normal: Vector,
+    pub(crate) dist: f32,
+    /// for fast side tests
+    kind: u8,
+    /// signx + (signy<<1) + (signz<<1)
+    sign_bits: u8,
+    pad: [u8; 2],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Surface {
+    pub(crate) name: *const c_char,
+    pub(crate) surface_props: c_short,
+    pub(crate) flags: c_ushort,
+}
+
+/// Result of a collision query, declared as `CGameTrace` in
+/// the Alien Swarm SDK's `gametrace.h`
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct Trace {
+    /// start position
+    pub(crate) start_pos: Vector,
+    /// final position
+    pub(crate) end_pos: Vector,
+    /// surface normal at impact
+    pub(crate) plane: Plane,
+    /// time completed, 1.0 = didn't hit anything
+    pub(crate) fraction: f32,
+    /// contents on other side of surface hit
+    pub(crate) contents: c_int,
+    /// displacement flags for marking surfaces with data
+    pub(crate) disp_flags: c_ushort,
+    /// if true, plane is not valid
+    pub(crate) all_solid: bool,
+    /// if true, the initial point was in a solid area
+    pub(crate) start_solid: bool,
+
+    /// time we left a solid, only valid if we started in solid
+    pub(crate) fraction_left_solid: f32,
+    /// surface hit (impact surface)
+    pub(crate) surface: Surface,
+    /// 0 == generic, non-zero is specific body part
+    pub(crate) hitgroup: c_int,
+    /// physics bone hit by trace in studio
+    pub(crate) physics_bone: c_short,
+    /// Index of the msurface2_t, if applicable
+    pub(crate) world_surface_index: c_ushort,
+    /// The entity that was hit, as a CBaseEntity
+    pub(crate) entity: *mut c_void,
+    /// box hit by trace in studio
+    pub(crate) hitbox: c_int,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace {
+            start_pos: Vector::default(),
+            end_pos: Vector::default(),
+            plane: Plane {
+                normal: Vector::default(),
+                dist: 0.0,
+                kind: 0,
+                sign_bits: 0,
+                pad: [0; 2],
+            },
+            fraction: 0.0,
+            contents: 0,
+            disp_flags: 0,
+            all_solid: false,
+            start_solid: false,
+            fraction_left_solid: 0.0,
+            surface: Surface {
+                name: null(),
+                surface_props: 0,
+                flags: 0,
+            },
+            hitgroup: 0,
+            physics_bone: 0,
+            world_surface_index: 0,
+            entity: null_mut(),
+            hitbox: 0,
+        }
+    }
+}
+
+/// Binding for ITraceFilter, implemented by the addon
+/// to select the entities a trace collides with
+#[fabric_codegen::interface]
+pub(crate) trait TraceFilter {
+    fn should_hit_entity(&self, entity: *mut c_void, contents_mask: c_int) -> bool;
+    fn get_trace_type(&self) -> c_int;
+}
+
+/// Trace filter colliding with everything except a single entity
+pub(crate) struct IgnoreEntity {
+    /// Index of the ignored entity, or -1 to collide with all the entities
+    pub(crate) index: c_int,
+}
+
+/// TRACE_EVERYTHING
+const TRACE_EVERYTHING: c_int = 0;
+
+impl TraceFilter for IgnoreEntity {
+    fn should_hit_entity(&self, entity: *mut c_void, _contents_mask: c_int) -> bool {
+        self.index < 0 || entity_index(entity) != Some(self.index)
+    }
+
+    fn get_trace_type(&self) -> c_int {
+        TRACE_EVERYTHING
+    }
+}
+
+static TRACE_FILTER_VTABLE: ITraceFilter =
+    <dyn TraceFilter>::vtable::<Box<IgnoreEntity>, IgnoreEntity>();
+
+static mut ENGINE_TRACE: *mut c_void = null_mut();
+
+/// Acquire the trace interface from the engine factory, this
+/// must be called from the game thread when the addon is loaded
+pub(crate) fn init(factory: CreateInterfaceFn) {
+    let interface =
+        game::acquire::<dyn EngineTrace>(factory, game::interfaces().engine_trace, "IEngineTrace");
+    unsafe {
+        ENGINE_TRACE = interface.map_or(null_mut(), |trace| trace.0);
+    }
+}
+
+/// Get the trace interface, looked up again at most once per level while it is missing
+pub(crate) fn engine_trace() -> Option<Foreign<dyn EngineTrace>> {
+    let mut trace = unsafe { ENGINE_TRACE };
+    if trace.is_null() && deferred::retry(Capability::Trace) {
+        trace = unsafe { ENGINE_TRACE };
+    }
+
+    if trace.is_null() {
+        None
+    } else {
+        Some(Foreign::with(trace))
+    }
+}
+
+/// Trace `ray` against the world and all the entities except `filter`
+pub(crate) fn trace_ray(ray: &Ray, mask: c_uint, filter: IgnoreEntity) -> Option<Trace> {
+    let mut engine_trace = engine_trace()?;
+
+    let mut filter = CTraceFilter {
+        vtable: &TRACE_FILTER_VTABLE,
+        instance: Box::new(filter),
+    };
+
+    let mut trace = Trace::default();
+    engine_trace.trace_ray(
+        ray,
+        mask,
+        &mut filter as *mut CTraceFilter<Box<IgnoreEntity>> as *mut c_void,
+        &mut trace,
+    );
+
+    Some(trace)
+}
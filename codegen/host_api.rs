@@ -0,0 +1,531 @@
+//! Functions and constants of the host modules that can be imported by
+//! the guest modules, shared between `FabricEnv` in the addon and the
+//! bindings of the `fabric-guest` crate
+//!
+//! Each `extern` block declares a host module with the types seen by a
+//! guest compiled for wasm32: pointers into the linear memory, externrefs
+//! passed as `ExternRef` (i64) and callbacks passed as `FuncRef` (i32).
+//! Constants are declared as statics with their import name and value
+
+#[link(wasm_import_module = "GameEventsManager")]
+extern "C" {
+    pub fn add_listener(listener: FuncRef, event: *const u8, flags: i32);
+    pub fn serialize_event(event: ExternRef, buffer: ExternRef) -> i32;
+    pub fn unserialize_event(buffer: ExternRef) -> ExternRef;
+    pub fn fire_event(event: ExternRef, dont_broadcast: i32) -> i32;
+    pub fn free_event(event: ExternRef) -> i32;
+    pub fn create_event(name: *const u8) -> ExternRef;
+
+    // Flags of add_listener, listeners without LISTENER_SERVER_SIDE listen on
+    // the client side. LISTENER_CONSUME listeners return non-zero to consume
+    // the event, the modules following them in priority order skip it
+    #[value = 1]
+    pub static LISTENER_SERVER_SIDE: i32;
+    #[value = 2]
+    pub static LISTENER_CONSUME: i32;
+}
+
+#[link(wasm_import_module = "GameEvent")]
+extern "C" {
+    pub fn get_int(event: ExternRef, name: *const u8) -> i32;
+    pub fn get_bool(event: ExternRef, name: *const u8) -> i32;
+    pub fn get_float(event: ExternRef, name: *const u8) -> f32;
+    pub fn get_string(event: ExternRef, name: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn set_int(event: ExternRef, name: *const u8, value: i32);
+    pub fn set_bool(event: ExternRef, name: *const u8, value: i32);
+    pub fn set_float(event: ExternRef, name: *const u8, value: f32);
+    pub fn set_string(event: ExternRef, name: *const u8, value: *const u8);
+    pub fn get_field_int(event: ExternRef, field: ExternRef) -> i32;
+    pub fn get_field_bool(event: ExternRef, field: ExternRef) -> i32;
+    pub fn get_field_float(event: ExternRef, field: ExternRef) -> f32;
+    pub fn field_id(name: *const u8) -> i32;
+    pub fn read_fields(event: ExternRef, fields: *mut FieldRead, count: i32) -> i32;
+    pub fn serialize(event: ExternRef, buffer: *mut u8, len: i32) -> i32;
+
+    // Types of the fields read with `read_fields`
+    #[value = 0]
+    pub static FIELD_INT: i32;
+    #[value = 1]
+    pub static FIELD_BOOL: i32;
+    #[value = 2]
+    pub static FIELD_FLOAT: i32;
+}
+
+#[link(wasm_import_module = "LoggingSystem")]
+extern "C" {
+    pub fn log(level: ExternRef, message: *const u8);
+
+    #[link_name = "Level::Error"]
+    #[value = 0]
+    pub static LEVEL_ERROR: ExternRef;
+    #[link_name = "Level::Warn"]
+    #[value = 1]
+    pub static LEVEL_WARN: ExternRef;
+    #[link_name = "Level::Info"]
+    #[value = 2]
+    pub static LEVEL_INFO: ExternRef;
+    #[link_name = "Level::Debug"]
+    #[value = 3]
+    pub static LEVEL_DEBUG: ExternRef;
+    #[link_name = "Level::Trace"]
+    #[value = 4]
+    pub static LEVEL_TRACE: ExternRef;
+}
+
+#[link(wasm_import_module = "Timer")]
+extern "C" {
+    pub fn create(delay: i32, repeat: i32, callback: FuncRef) -> i32;
+    pub fn cancel(handle: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Job")]
+extern "C" {
+    pub fn spawn(step: FuncRef, data: i32) -> i32;
+    pub fn cancel(handle: i32) -> i32;
+    pub fn should_yield() -> i32;
+}
+
+#[link(wasm_import_module = "BitBuffer")]
+extern "C" {
+    pub fn create(size: i32) -> ExternRef;
+    pub fn from_bytes(data: *const u8, len: i32) -> ExternRef;
+    pub fn free(buffer: ExternRef);
+    pub fn is_overflowed(buffer: ExternRef) -> i32;
+    pub fn bits_written(buffer: ExternRef) -> i32;
+    pub fn bytes(buffer: ExternRef, data: *mut u8, len: i32) -> i32;
+    pub fn write_bits(buffer: ExternRef, value: i32, bits: i32) -> i32;
+    pub fn write_float(buffer: ExternRef, value: f32) -> i32;
+    pub fn write_string(buffer: ExternRef, value: *const u8) -> i32;
+    pub fn write_bytes(buffer: ExternRef, value: *const u8, len: i32) -> i32;
+    pub fn read_ubits(buffer: ExternRef, bits: i32) -> i32;
+    pub fn read_sbits(buffer: ExternRef, bits: i32) -> i32;
+    pub fn read_float(buffer: ExternRef) -> f32;
+    pub fn read_string(buffer: ExternRef, data: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Http")]
+extern "C" {
+    pub fn request(
+        method: *const u8,
+        url: *const u8,
+        headers: *const u8,
+        body: *const u8,
+        body_len: i32,
+        callback: FuncRef,
+    ) -> i32;
+    pub fn body_length(response: ExternRef) -> i32;
+    pub fn read_body(response: ExternRef, buffer: *mut u8, len: i32) -> i32;
+    pub fn header(response: ExternRef, name: *const u8, buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Db")]
+extern "C" {
+    pub fn exec(sql: *const u8) -> i32;
+    pub fn prepare(sql: *const u8) -> ExternRef;
+    pub fn finalize(statement: ExternRef);
+    pub fn bind_int(statement: ExternRef, index: i32, value: i64) -> i32;
+    pub fn bind_float(statement: ExternRef, index: i32, value: f64) -> i32;
+    pub fn bind_text(statement: ExternRef, index: i32, value: *const u8) -> i32;
+    pub fn bind_blob(statement: ExternRef, index: i32, value: *const u8, len: i32) -> i32;
+    pub fn execute(statement: ExternRef) -> i32;
+    pub fn query(statement: ExternRef) -> i32;
+    pub fn next(statement: ExternRef) -> i32;
+    pub fn column_int(statement: ExternRef, index: i32) -> i64;
+    pub fn column_float(statement: ExternRef, index: i32) -> f64;
+    pub fn column_bytes(statement: ExternRef, index: i32, buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Kv")]
+extern "C" {
+    pub fn set(key: *const u8, value: *const u8, len: i32) -> i32;
+    pub fn get(key: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn delete(key: *const u8) -> i32;
+}
+
+#[link(wasm_import_module = "Fs")]
+extern "C" {
+    pub fn read(path: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn write(path: *const u8, data: *const u8, len: i32) -> i32;
+    pub fn list(path: *const u8, buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Config")]
+extern "C" {
+    pub fn get(key: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn on_changed(callback: FuncRef) -> i32;
+}
+
+#[link(wasm_import_module = "Lang")]
+extern "C" {
+    pub fn format(
+        client: i32,
+        phrase: *const u8,
+        args: *const u8,
+        args_len: i32,
+        buffer: *mut u8,
+        len: i32,
+    ) -> i32;
+}
+
+#[link(wasm_import_module = "Globals")]
+extern "C" {
+    pub fn curtime() -> f32;
+    pub fn frametime() -> f32;
+    pub fn tick_count() -> i32;
+    pub fn max_clients() -> i32;
+}
+
+#[link(wasm_import_module = "Server")]
+extern "C" {
+    pub fn hostname(buffer: *mut u8, len: i32) -> i32;
+    pub fn map(buffer: *mut u8, len: i32) -> i32;
+    pub fn address(buffer: *mut u8, len: i32) -> i32;
+    pub fn port() -> i32;
+    pub fn player_count() -> i32;
+    pub fn bot_count() -> i32;
+    pub fn tick_interval() -> f32;
+    pub fn has_capability(capability: i32) -> i32;
+    pub fn on_capability(callback: FuncRef) -> i32;
+
+    // Features depending on interfaces the engine may only expose after the
+    // addon is loaded, the callback of `on_capability` is called with the
+    // ones that become available
+    #[value = 0]
+    pub static CAPABILITY_GAME_EVENTS: i32;
+    #[value = 1]
+    pub static CAPABILITY_TRACE: i32;
+    #[value = 2]
+    pub static CAPABILITY_SOUND: i32;
+    #[value = 3]
+    pub static CAPABILITY_EFFECTS: i32;
+    #[value = 4]
+    pub static CAPABILITY_BOTS: i32;
+    #[value = 5]
+    pub static CAPABILITY_TOOLS: i32;
+    #[value = 6]
+    pub static CAPABILITY_ENTITIES: i32;
+    #[value = 7]
+    pub static CAPABILITY_GLOBALS: i32;
+}
+
+#[link(wasm_import_module = "Map")]
+extern "C" {
+    pub fn change(map: *const u8, delay: f32) -> i32;
+    pub fn cancel_change() -> i32;
+    pub fn is_valid(map: *const u8) -> i32;
+    pub fn set_next(map: *const u8) -> i32;
+    pub fn next(buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Random")]
+extern "C" {
+    pub fn i32(min: i32, max: i32) -> i32;
+    pub fn f32() -> f32;
+    pub fn seed(seed: i64);
+}
+
+#[link(wasm_import_module = "UserMessage")]
+extern "C" {
+    pub fn chat(client: i32, text: *const u8) -> i32;
+    pub fn hint_text(client: i32, text: *const u8) -> i32;
+    pub fn hud_text(
+        client: i32,
+        channel: i32,
+        x: f32,
+        y: f32,
+        color: i32,
+        hold_time: f32,
+        text: *const u8,
+    ) -> i32;
+    pub fn motd(client: i32, title: *const u8, content: *const u8, kind: i32) -> i32;
+    pub fn send(client: i32, name: *const u8, buffer: ExternRef) -> i32;
+    pub fn shake(client: i32, amplitude: f32, frequency: f32, duration: f32) -> i32;
+
+    // Content of the MOTD panel
+    #[value = 0]
+    pub static MOTD_TEXT: i32;
+    #[value = 2]
+    pub static MOTD_URL: i32;
+}
+
+#[link(wasm_import_module = "Client")]
+extern "C" {
+    pub fn kick(client: i32, reason: *const u8) -> i32;
+    pub fn ban(client: i32, duration: i32, reason: *const u8, flags: i32) -> i32;
+    pub fn ban_identity(identity: *const u8, duration: i32, reason: *const u8) -> i32;
+    pub fn unban(identity: *const u8) -> i32;
+    pub fn ban_reason(identity: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn on_settings_changed(callback: FuncRef) -> i32;
+    pub fn on_connect(callback: FuncRef) -> i32;
+    pub fn print(client: i32, destination: i32, text: *const u8) -> i32;
+    pub fn set_cvar(client: i32, name: *const u8, value: *const u8) -> i32;
+    pub fn cvar(client: i32, name: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn query_cvar(client: i32, name: *const u8, callback: FuncRef) -> i32;
+    pub fn query_value(buffer: *mut u8, len: i32) -> i32;
+    pub fn setting(client: i32, setting: i32, previous: i32, buffer: *mut u8, len: i32) -> i32;
+
+    // Identities of a connected client to ban
+    #[value = 1]
+    pub static BAN_BY_STEAMID: i32;
+    #[value = 2]
+    pub static BAN_BY_IP: i32;
+
+    // Destinations of the text printed to the clients
+    #[value = 1]
+    pub static PRINT_NOTIFY: i32;
+    #[value = 2]
+    pub static PRINT_CONSOLE: i32;
+    #[value = 3]
+    pub static PRINT_CHAT: i32;
+    #[value = 4]
+    pub static PRINT_CENTER: i32;
+    #[value = 5]
+    pub static PRINT_HINT: i32;
+
+    // Status of the queries of client variables
+    #[value = 0]
+    pub static QUERY_OK: i32;
+    #[value = 1]
+    pub static QUERY_NOT_FOUND: i32;
+    #[value = 2]
+    pub static QUERY_NOT_A_CVAR: i32;
+    #[value = 3]
+    pub static QUERY_PROTECTED: i32;
+
+    // Replicated settings of the clients tracked by the addon
+    #[value = 1]
+    pub static SETTING_NAME: i32;
+    #[value = 2]
+    pub static SETTING_RATE: i32;
+    #[value = 4]
+    pub static SETTING_UPDATE_RATE: i32;
+    #[value = 8]
+    pub static SETTING_CMD_RATE: i32;
+    #[value = 16]
+    pub static SETTING_INTERP: i32;
+}
+
+#[link(wasm_import_module = "Command")]
+extern "C" {
+    pub fn register(name: *const u8, help: *const u8, callback: FuncRef) -> i32;
+    pub fn issuer() -> i32;
+    pub fn arg_count(args: ExternRef) -> i32;
+    pub fn arg(args: ExternRef, index: i32, buffer: *mut u8, len: i32) -> i32;
+    pub fn set_rate_limit(name: *const u8, rate: f32, burst: i32) -> i32;
+    pub fn reset_rate_limit(name: *const u8) -> i32;
+    pub fn rate_limit(name: *const u8, result: *mut RateLimit) -> i32;
+    pub fn is_limited(client: i32, name: *const u8) -> i32;
+}
+
+#[link(wasm_import_module = "State")]
+extern "C" {
+    pub fn on_save(callback: FuncRef) -> i32;
+    pub fn on_restore(callback: FuncRef) -> i32;
+    pub fn write(buffer: ExternRef, data: *const u8, len: i32) -> i32;
+    pub fn read(buffer: ExternRef, offset: i32, dest: *mut u8, len: i32) -> i32;
+    pub fn size(buffer: ExternRef) -> i32;
+}
+
+#[link(wasm_import_module = "Menu")]
+extern "C" {
+    pub fn create(title: *const u8, callback: FuncRef) -> i32;
+    pub fn add_item(menu: i32, text: *const u8) -> i32;
+    pub fn display(menu: i32, client: i32, time: i32) -> i32;
+    pub fn destroy(menu: i32) -> i32;
+
+    // Reasons given to the callback instead of an item when the menu is closed
+    #[value = 0xFFFF_FFFF]
+    pub static MENU_EXIT: i32;
+    #[value = 0xFFFF_FFFE]
+    pub static MENU_TIMEOUT: i32;
+    #[value = 0xFFFF_FFFD]
+    pub static MENU_INTERRUPTED: i32;
+}
+
+#[link(wasm_import_module = "Vote")]
+extern "C" {
+    pub fn start(
+        question: *const u8,
+        options: *const u8,
+        options_len: i32,
+        duration: i32,
+        voters: *const i32,
+        voters_len: i32,
+        tie_policy: i32,
+        callback: FuncRef,
+    ) -> i32;
+    pub fn cancel(vote: i32) -> i32;
+    pub fn in_progress() -> i32;
+
+    // How a tie between the options with the most votes is resolved
+    #[value = 0]
+    pub static VOTE_TIE_NONE: i32;
+    #[value = 1]
+    pub static VOTE_TIE_FIRST: i32;
+    #[value = 2]
+    pub static VOTE_TIE_RANDOM: i32;
+
+    // Results given to the callback instead of the winning option
+    #[value = 0xFFFF_FFFF]
+    pub static VOTE_TIED: i32;
+    #[value = 0xFFFF_FFFE]
+    pub static VOTE_NO_VOTES: i32;
+    #[value = 0xFFFF_FFFD]
+    pub static VOTE_CANCELLED: i32;
+}
+
+#[link(wasm_import_module = "Target")]
+extern "C" {
+    pub fn resolve(issuer: i32, pattern: *const u8, flags: i32, clients: *mut i32, len: i32) -> i32;
+
+    // Filters applied to the clients matching a pattern
+    #[value = 1]
+    pub static TARGET_FILTER_ALIVE: i32;
+    #[value = 2]
+    pub static TARGET_FILTER_DEAD: i32;
+    #[value = 4]
+    pub static TARGET_FILTER_NO_MULTI: i32;
+    #[value = 8]
+    pub static TARGET_FILTER_NO_BOTS: i32;
+
+    // Errors returned instead of the number of targets
+    #[value = 0xFFFF_FFFF]
+    pub static TARGET_NO_MATCH: i32;
+    #[value = 0xFFFF_FFFE]
+    pub static TARGET_AMBIGUOUS: i32;
+    #[value = 0xFFFF_FFFD]
+    pub static TARGET_FILTERED: i32;
+    #[value = 0xFFFF_FFFC]
+    pub static TARGET_MULTIPLE: i32;
+}
+
+#[link(wasm_import_module = "Sound")]
+extern "C" {
+    pub fn precache(sample: *const u8) -> i32;
+    pub fn emit(client: i32, sample: *const u8, volume: f32, pitch: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Effects")]
+extern "C" {
+    pub fn precache_model(model: *const u8) -> i32;
+    pub fn beam(
+        start: *const Vector,
+        end: *const Vector,
+        model: *const u8,
+        life: f32,
+        width: i32,
+        color: i32,
+    ) -> i32;
+    pub fn smoke(origin: *const Vector, model: *const u8, scale: f32, frame_rate: f32) -> i32;
+    pub fn sparks(position: *const Vector, magnitude: i32, trail_length: i32) -> i32;
+    pub fn metal_sparks(position: *const Vector, direction: *const Vector) -> i32;
+    pub fn dust(position: *const Vector, direction: *const Vector, size: f32, speed: f32) -> i32;
+    pub fn energy_splash(position: *const Vector, direction: *const Vector, explosive: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Bot")]
+extern "C" {
+    pub fn create(name: *const u8) -> i32;
+    pub fn remove(bot: i32) -> i32;
+    pub fn run_move(
+        bot: i32,
+        angles: *const Vector,
+        forward_move: f32,
+        side_move: f32,
+        up_move: f32,
+        buttons: i32,
+    ) -> i32;
+    pub fn set_origin(bot: i32, origin: *const Vector) -> i32;
+    pub fn set_angles(bot: i32, angles: *const Vector) -> i32;
+    pub fn set_active_weapon(bot: i32, weapon: *const u8) -> i32;
+    pub fn remove_all_items(bot: i32, remove_suit: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Trace")]
+extern "C" {
+    pub fn line(
+        start: *const Vector,
+        end: *const Vector,
+        mask: i32,
+        ignore: i32,
+        result: *mut TraceResult,
+    ) -> i32;
+    pub fn hull(
+        start: *const Vector,
+        end: *const Vector,
+        mins: *const Vector,
+        maxs: *const Vector,
+        mask: i32,
+        ignore: i32,
+        result: *mut TraceResult,
+    ) -> i32;
+    pub fn point_contents(position: *const Vector, mask: i32) -> i32;
+
+    // Contents masks from the Alien Swarm SDK's `bspflags.h`
+    #[value = 0xFFFF_FFFF]
+    pub static MASK_ALL: i32;
+    #[value = 0x0200_400B]
+    pub static MASK_SOLID: i32;
+    #[value = 0x0201_400B]
+    pub static MASK_PLAYERSOLID: i32;
+    #[value = 0x4600_4003]
+    pub static MASK_SHOT: i32;
+    #[value = 0x0000_2080]
+    pub static MASK_VISIBLE: i32;
+}
+
+#[link(wasm_import_module = "Entity")]
+extern "C" {
+    pub fn max_entities() -> i32;
+    pub fn is_valid(index: i32) -> i32;
+    pub fn handle(index: i32) -> i32;
+    pub fn from_handle(handle: i32) -> i32;
+    pub fn class_name(index: i32, data: *mut u8, len: i32) -> i32;
+    pub fn get_prop_int(index: i32, name: *const u8) -> i32;
+    pub fn get_prop_float(index: i32, name: *const u8) -> f32;
+    pub fn get_prop_vector(index: i32, name: *const u8, result: *mut Vector) -> i32;
+    pub fn create(class_name: *const u8) -> i32;
+    pub fn set_keyvalue(handle: i32, key: *const u8, value: *const u8) -> i32;
+    pub fn spawn(handle: i32) -> i32;
+    pub fn remove(handle: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Edict")]
+extern "C" {
+    pub fn serial_number(index: i32) -> i32;
+    pub fn is_free(index: i32) -> i32;
+    pub fn next_allocated(index: i32) -> i32;
+    pub fn on_allocated(callback: FuncRef) -> i32;
+    pub fn on_freed(callback: FuncRef) -> i32;
+    pub fn on_entity_created(callback: FuncRef) -> i32;
+    pub fn on_entity_removed(callback: FuncRef) -> i32;
+    pub fn class_name(buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Bus")]
+extern "C" {
+    pub fn publish(topic: *const u8, data: *const u8, len: i32) -> i32;
+    pub fn subscribe(topic: *const u8, callback: FuncRef) -> i32;
+    pub fn unsubscribe(handle: i32) -> i32;
+    pub fn length(message: ExternRef) -> i32;
+    pub fn read(message: ExternRef, buffer: *mut u8, len: i32) -> i32;
+    pub fn topic(message: ExternRef, buffer: *mut u8, len: i32) -> i32;
+    pub fn sender(message: ExternRef, buffer: *mut u8, len: i32) -> i32;
+}
+
+#[link(wasm_import_module = "Shared")]
+extern "C" {
+    pub fn open(name: *const u8) -> ExternRef;
+    pub fn close(namespace: ExternRef);
+    pub fn get(namespace: ExternRef, key: *const u8, buffer: *mut u8, len: i32) -> i32;
+    pub fn set(namespace: ExternRef, key: *const u8, value: *const u8, len: i32) -> i32;
+    pub fn delete(namespace: ExternRef, key: *const u8) -> i32;
+    pub fn compare_and_swap(
+        namespace: ExternRef,
+        key: *const u8,
+        expected: *const u8,
+        expected_len: i32,
+        value: *const u8,
+        len: i32,
+    ) -> i32;
+}
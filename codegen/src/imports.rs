@@ -0,0 +1,336 @@
+use proc_macro::TokenStream;
+use quote::{__private::Span, format_ident, quote};
+use syn::{
+    Attribute, FnArg, ForeignItem, ForeignItemFn, ForeignItemStatic, Ident, Item, Lit, LitInt,
+    Meta, NestedMeta, Pat, Type,
+};
+
+/// Shared description of the host modules
+const HOST_API: &str = include_str!("../host_api.rs");
+
+struct HostModule {
+    name: String,
+    functions: Vec<ForeignItemFn>,
+    constants: Vec<HostConstant>,
+}
+
+struct HostConstant {
+    attrs: Vec<Attribute>,
+    ident: Ident,
+    /// Name of the global imported by the guests
+    name: String,
+    ty: Type,
+    value: u32,
+}
+
+fn name_value(attrs: &[Attribute], name: &str) -> Option<Lit> {
+    attrs.iter().find_map(|attr| match attr.parse_meta() {
+        Ok(Meta::NameValue(meta)) if meta.path.is_ident(name) => Some(meta.lit),
+        _ => None,
+    })
+}
+
+fn import_module(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) if list.path.is_ident("link") => {
+            list.nested.iter().find_map(|nested| match nested {
+                NestedMeta::Meta(Meta::NameValue(meta))
+                    if meta.path.is_ident("wasm_import_module") =>
+                {
+                    match &meta.lit {
+                        Lit::Str(name) => Some(name.value()),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+        }
+        _ => None,
+    })
+}
+
+fn host_constant(item: ForeignItemStatic) -> HostConstant {
+    let name = match name_value(&item.attrs, "link_name") {
+        Some(Lit::Str(name)) => name.value(),
+        Some(_) => panic!("invalid link_name for {}", item.ident),
+        None => item.ident.to_string(),
+    };
+
+    let value = match name_value(&item.attrs, "value") {
+        Some(Lit::Int(value)) => value.base10_parse().unwrap(),
+        _ => panic!("missing value for {}", item.ident),
+    };
+
+    let attrs = item
+        .attrs
+        .into_iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .collect();
+
+    HostConstant {
+        attrs,
+        ident: item.ident,
+        name,
+        ty: *item.ty,
+        value,
+    }
+}
+
+fn host_modules() -> Vec<HostModule> {
+    let file = syn::parse_file(HOST_API).expect("could not parse host_api.rs");
+
+    file.items
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::ForeignMod(item) => Some(item),
+            _ => None,
+        })
+        .map(|item| {
+            let name = import_module(&item.attrs).expect("missing wasm_import_module");
+
+            let mut functions = Vec::new();
+            let mut constants = Vec::new();
+
+            for item in item.items {
+                match item {
+                    ForeignItem::Fn(item) => functions.push(item),
+                    ForeignItem::Static(item) => constants.push(host_constant(item)),
+                    item => panic!("unsupported item in {}: {:?}", name, item),
+                }
+            }
+
+            HostModule {
+                name,
+                functions,
+                constants,
+            }
+        })
+        .collect()
+}
+
+/// Convert the name of a host module to the name of its guest module
+fn snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for (index, ch) in name.chars().enumerate() {
+        if ch.is_uppercase() {
+            if index > 0 {
+                result.push('_');
+            }
+            result.extend(ch.to_lowercase());
+        } else {
+            result.push(ch);
+        }
+    }
+
+    result
+}
+
+fn is_extern_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.is_ident("ExternRef"),
+        _ => false,
+    }
+}
+
+fn is_func_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.is_ident("FuncRef"),
+        _ => false,
+    }
+}
+
+/// Names and kinds of the parameters of a host function, the `*const u8`
+/// parameters are NUL-terminated strings unless they are followed by a length
+fn host_params(function: &ForeignItemFn) -> Vec<(String, &'static str)> {
+    let params: Vec<_> = function
+        .sig
+        .inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Typed(input) => match &*input.pat {
+                Pat::Ident(pat) => (pat.ident.to_string(), &*input.ty),
+                pat => panic!("unsupported parameter in {}: {:?}", function.sig.ident, pat),
+            },
+            FnArg::Receiver(_) => panic!("unsupported receiver in {}", function.sig.ident),
+        })
+        .collect();
+
+    params
+        .iter()
+        .enumerate()
+        .map(|(index, (name, ty))| {
+            let kind = match ty {
+                Type::Ptr(ptr) if ptr.mutability.is_none() => {
+                    let is_bytes = match &*ptr.elem {
+                        Type::Path(path) => path.path.is_ident("u8"),
+                        _ => false,
+                    };
+                    let has_len = params.get(index + 1).is_some_and(|(next, _)| next == "len");
+
+                    if is_bytes && !has_len {
+                        "string"
+                    } else {
+                        "pointer"
+                    }
+                }
+                Type::Ptr(_) => "pointer",
+                ty if is_extern_ref(ty) => "externref",
+                ty if is_func_ref(ty) => "funcref",
+                _ => "value",
+            };
+
+            (name.clone(), kind)
+        })
+        .collect()
+}
+
+pub(crate) fn guest_imports(_input: TokenStream) -> TokenStream {
+    let modules = host_modules().into_iter().map(|module| {
+        let HostModule {
+            name,
+            functions,
+            constants,
+        } = module;
+
+        let ident = format_ident!("{}", snake_case(&name));
+
+        // Rust can't import WASM globals, the constants are defined
+        // inline in the guest with the value they are imported with
+        let constants = constants.into_iter().map(|constant| {
+            let HostConstant {
+                attrs,
+                ident,
+                ty,
+                value,
+                ..
+            } = constant;
+
+            let value = LitInt::new(&format!("{}u32", value), Span::call_site());
+            let value = if is_extern_ref(&ty) {
+                quote! { ExternRef::constant(#value) }
+            } else {
+                quote! { #value as #ty }
+            };
+
+            quote! {
+                #( #attrs )*
+                pub const #ident: #ty = #value;
+            }
+        });
+
+        quote! {
+            pub mod #ident {
+                use super::*;
+
+                #[link(wasm_import_module = #name)]
+                extern "C" {
+                    #( #functions )*
+                }
+
+                #( #constants )*
+            }
+        }
+    });
+
+    let tokens = quote! {
+        #( #modules )*
+    };
+
+    tokens.into()
+}
+
+pub(crate) fn host_names(_input: TokenStream) -> TokenStream {
+    let modules = host_modules().into_iter().map(|module| {
+        let HostModule {
+            name,
+            functions,
+            constants,
+        } = module;
+
+        let ident = format_ident!("{}", snake_case(&name));
+
+        let functions = functions.into_iter().map(|function| {
+            let name = function.sig.ident.to_string();
+            let ident = format_ident!("{}", name.to_uppercase());
+            quote! { pub const #ident: &str = #name; }
+        });
+
+        let constants = constants.into_iter().map(|constant| {
+            let HostConstant { ident, name, .. } = constant;
+            quote! { pub const #ident: &str = #name; }
+        });
+
+        quote! {
+            pub mod #ident {
+                /// Name of the host module
+                pub const MODULE: &str = #name;
+
+                #( #functions )*
+                #( #constants )*
+            }
+        }
+    });
+
+    let tokens = quote! {
+        #( #modules )*
+    };
+
+    tokens.into()
+}
+
+pub(crate) fn host_imports(_input: TokenStream) -> TokenStream {
+    let modules = host_modules();
+
+    let names = modules.iter().map(|module| &module.name);
+
+    let functions = modules.iter().flat_map(|module| {
+        let name = &module.name;
+        module.functions.iter().map(move |function| {
+            let function = function.sig.ident.to_string();
+            quote! { (#name, #function) }
+        })
+    });
+
+    let params = modules.iter().flat_map(|module| {
+        let name = &module.name;
+        module.functions.iter().map(move |function| {
+            let params = host_params(function).into_iter().map(|(param, kind)| {
+                quote! { (#param, #kind) }
+            });
+
+            let function = function.sig.ident.to_string();
+            quote! { (#name, #function, &[ #( #params ),* ]) }
+        })
+    });
+
+    let constants = modules.iter().flat_map(|module| {
+        let name = &module.name;
+        module.constants.iter().map(move |constant| {
+            let HostConstant {
+                name: constant,
+                value,
+                ..
+            } = constant;
+
+            quote! { (#name, #constant, #value) }
+        })
+    });
+
+    let tokens = quote! {
+        /// Names of the host modules that can be imported by the modules
+        pub(crate) const HOST_MODULES: &[&str] = &[ #( #names ),* ];
+
+        /// Functions of the host modules as (module, name) pairs
+        pub(crate) const HOST_FUNCTIONS: &[(&str, &str)] = &[ #( #functions ),* ];
+
+        /// Parameters of the host functions as (module, name, parameters) tuples, with
+        /// the name and kind of each parameter: "string" for the NUL-terminated strings,
+        /// "pointer" for the other addresses in memory, "externref", "funcref" or "value"
+        pub(crate) const HOST_PARAMS: &[(&str, &str, &[(&str, &str)])] = &[ #( #params ),* ];
+
+        /// Constants of the host modules as (module, name, value) tuples
+        pub(crate) const HOST_CONSTANTS: &[(&str, &str, u32)] = &[ #( #constants ),* ];
+    };
+
+    tokens.into()
+}
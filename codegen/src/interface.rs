@@ -452,6 +452,48 @@ fn is_mutable(method: &TraitItemMethod) -> bool {
     })
 }
 
+/// Returns true if the all-zero bit pattern is a valid value of `ty`, the
+/// shims of the methods returning any other type fall back on `Default`
+fn is_zeroable(ty: &Type) -> bool {
+    match ty {
+        Type::Ptr(_) => true,
+        Type::Tuple(tuple) => tuple.elems.is_empty(),
+        Type::Path(path) if path.qself.is_none() => match path.path.segments.last() {
+            Some(seg) if seg.arguments.is_empty() => matches!(
+                &seg.ident.to_string() as &str,
+                "bool"
+                    | "i8"
+                    | "i16"
+                    | "i32"
+                    | "i64"
+                    | "isize"
+                    | "u8"
+                    | "u16"
+                    | "u32"
+                    | "u64"
+                    | "usize"
+                    | "f32"
+                    | "f64"
+                    | "c_char"
+                    | "c_schar"
+                    | "c_uchar"
+                    | "c_short"
+                    | "c_ushort"
+                    | "c_int"
+                    | "c_uint"
+                    | "c_long"
+                    | "c_ulong"
+                    | "c_longlong"
+                    | "c_ulonglong"
+                    | "c_float"
+                    | "c_double"
+            ),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 fn vtable_shim(method: &TraitItemMethod, trait_name: &Ident, class_name: &Ident) -> ItemFn {
     let mut container_bounds = vec![TypeParamBound::Trait(TraitBound {
         paren_token: None,
@@ -503,6 +545,70 @@ fn vtable_shim(method: &TraitItemMethod, trait_name: &Ident, class_name: &Ident)
         }));
     }
 
+    let name = method.sig.ident.clone();
+    let call = Expr::Unsafe(ExprUnsafe {
+        attrs: Vec::new(),
+        unsafe_token: Token![unsafe](Span::call_site()),
+        block: Block {
+            brace_token: Brace(Span::call_site()),
+            stmts: vec![
+                Stmt::Expr(Expr::Verbatim(quote! {
+                    log::trace!(concat!(stringify!(#trait_name), "::", stringify!(#name)));
+                })),
+                Stmt::Expr({
+                    let expr = Expr::Call(ExprCall {
+                        attrs: Vec::new(),
+                        func: Box::new(Expr::Path(ExprPath {
+                            attrs: Vec::new(),
+                            qself: None,
+                            path: path(vec![
+                                segment(ident("T"), None),
+                                segment(method.sig.ident.clone(), None),
+                            ]),
+                        })),
+                        paren_token: Paren(method.sig.ident.span()),
+                        args: method
+                            .sig
+                            .inputs
+                            .iter()
+                            .map(|input| match input {
+                                FnArg::Receiver(input) => map_self_output(input, &class_name),
+                                FnArg::Typed(input) => match &*input.pat {
+                                    Pat::Ident(id) => map_output(
+                                        Expr::Path(ExprPath {
+                                            attrs: Vec::new(),
+                                            qself: None,
+                                            path: path(vec![segment(id.ident.clone(), None)]),
+                                        }),
+                                        &input.ty,
+                                    ),
+                                    pat => panic!("{:?}", pat),
+                                },
+                            })
+                            .collect(),
+                    });
+
+                    match &method.sig.output {
+                        ReturnType::Default => expr,
+                        ReturnType::Type(_, ty) => map_input(expr, ty),
+                    }
+                }),
+            ],
+        },
+    });
+
+    let fallback = match &method.sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => {
+            let ty = map_type(ty);
+            if is_zeroable(&ty) {
+                quote! { unsafe { std::mem::zeroed::<#ty>() } }
+            } else {
+                quote! { <#ty as std::default::Default>::default() }
+            }
+        }
+    };
+
     ItemFn {
         attrs: Vec::new(),
         vis: Visibility::Inherited,
@@ -580,63 +686,16 @@ fn vtable_shim(method: &TraitItemMethod, trait_name: &Ident, class_name: &Ident)
         },
         block: Box::new(Block {
             brace_token: Brace(Span::call_site()),
-            stmts: vec![Stmt::Expr(Expr::Unsafe(ExprUnsafe {
-                attrs: Vec::new(),
-                unsafe_token: Token![unsafe](Span::call_site()),
-                block: Block {
-                    brace_token: Brace(Span::call_site()),
-                    stmts: vec![
-                        Stmt::Expr(Expr::Verbatim({
-                            let name = method.sig.ident.clone();
-                            quote! {
-                                log::trace!(concat!(stringify!(#trait_name), "::", stringify!(#name)));
-                            }
-                        })),
-                        Stmt::Expr({
-                            let expr = Expr::Call(ExprCall {
-                                attrs: Vec::new(),
-                                func: Box::new(Expr::Path(ExprPath {
-                                    attrs: Vec::new(),
-                                    qself: None,
-                                    path: path(vec![
-                                        segment(ident("T"), None),
-                                        segment(method.sig.ident.clone(), None),
-                                    ]),
-                                })),
-                                paren_token: Paren(method.sig.ident.span()),
-                                args: method
-                                    .sig
-                                    .inputs
-                                    .iter()
-                                    .map(|input| match input {
-                                        FnArg::Receiver(input) => {
-                                            map_self_output(input, &class_name)
-                                        }
-                                        FnArg::Typed(input) => match &*input.pat {
-                                            Pat::Ident(id) => map_output(
-                                                Expr::Path(ExprPath {
-                                                    attrs: Vec::new(),
-                                                    qself: None,
-                                                    path: path(vec![segment(
-                                                        id.ident.clone(),
-                                                        None,
-                                                    )]),
-                                                }),
-                                                &input.ty,
-                                            ),
-                                            pat => panic!("{:?}", pat),
-                                        },
-                                    })
-                                    .collect(),
-                            });
-
-                            match &method.sig.output {
-                                ReturnType::Default => expr,
-                                ReturnType::Type(_, ty) => map_input(expr, ty),
-                            }
-                        }),
-                    ],
-                },
+            stmts: vec![Stmt::Expr(Expr::Verbatim(quote! {
+                // Unwinding into the engine is undefined behavior, panics are
+                // reported to the host and the shim returns a fallback value
+                match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| #call)) {
+                    Ok(value) => value,
+                    Err(_) => {
+                        crate::foreign::shim_panicked(concat!(stringify!(#trait_name), "::", stringify!(#name)));
+                        #fallback
+                    }
+                }
             }))],
         }),
     }
@@ -6,6 +6,7 @@ use proc_macro::TokenStream;
 use quote::{__private::Span, quote};
 use syn::{parse_macro_input, LitByteStr, LitStr};
 
+mod imports;
 mod interface;
 
 #[proc_macro]
@@ -34,3 +35,18 @@ pub fn cstr(input: TokenStream) -> TokenStream {
 pub fn interface(_args: TokenStream, input: TokenStream) -> TokenStream {
     crate::interface::interface(input)
 }
+
+#[proc_macro]
+pub fn guest_imports(input: TokenStream) -> TokenStream {
+    crate::imports::guest_imports(input)
+}
+
+#[proc_macro]
+pub fn host_imports(input: TokenStream) -> TokenStream {
+    crate::imports::host_imports(input)
+}
+
+#[proc_macro]
+pub fn host_names(input: TokenStream) -> TokenStream {
+    crate::imports::host_names(input)
+}
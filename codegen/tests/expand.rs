@@ -0,0 +1,12 @@
+//! Snapshots of the code generated by `#[interface]`
+//!
+//! Each file of `tests/expand` is expanded with `cargo expand` and compared with
+//! the `.expanded.rs` file next to it. A missing snapshot is written on the first
+//! run, `MACROTEST=overwrite` rewrites them after an intended change of the macro:
+//! the diff of the snapshots is then reviewed like the change itself, since the
+//! vtable layout and the argument mapping are the ABI seen by the engine
+
+#[test]
+fn interface() {
+    macrotest::expand("tests/expand/*.rs");
+}
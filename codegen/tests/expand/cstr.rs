@@ -0,0 +1,18 @@
+//! `&CStr` arguments and return values, passed as C string pointers
+
+use std::ffi::CStr;
+
+mod foreign {
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut std::ffi::c_void, std::marker::PhantomData<*mut T>);
+
+    pub(crate) fn shim_panicked(_method: &str) {}
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Strings {
+    fn name(&self) -> &CStr;
+    fn find(&mut self, key: &CStr, default: &CStr) -> &CStr;
+    fn set(&mut self, key: &CStr, value: &CStr);
+}
+
+fn main() {}
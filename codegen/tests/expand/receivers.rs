@@ -0,0 +1,19 @@
+//! Shared, exclusive and owned receivers, and plain arguments passed by value
+
+use std::os::raw::c_int;
+
+mod foreign {
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut std::ffi::c_void, std::marker::PhantomData<*mut T>);
+
+    pub(crate) fn shim_panicked(_method: &str) {}
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Receivers {
+    fn shared(&self) -> c_int;
+    fn exclusive(&mut self, value: c_int);
+    fn owned(self);
+    fn values(&mut self, a: bool, b: u64, c: f32, d: *mut c_int) -> u64;
+}
+
+fn main() {}
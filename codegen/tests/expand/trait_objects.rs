@@ -0,0 +1,21 @@
+//! Trait objects passed as pointers to objects with a vtable, either owned by
+//! the callee (`Box<dyn Trait>`) or borrowed for the duration of the call
+
+mod foreign {
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut std::ffi::c_void, std::marker::PhantomData<*mut T>);
+
+    pub(crate) fn shim_panicked(_method: &str) {}
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Listener {
+    fn fire(&mut self, value: i32);
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Manager {
+    fn add(&mut self, listener: Box<dyn Listener>) -> bool;
+    fn find(&mut self, listener: &mut dyn Listener) -> bool;
+}
+
+fn main() {}
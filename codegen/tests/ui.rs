@@ -0,0 +1,11 @@
+//! Programs using `#[interface]` like the addon does, calling the generated
+//! vtables both ways and checking the values that cross them
+//!
+//! The vtables use the thiscall ABI, these tests only build for the
+//! 32-bit Windows target the addon is compiled for
+
+#[test]
+fn interface() {
+    let tests = trybuild::TestCases::new();
+    tests.pass("tests/ui/*.rs");
+}
@@ -0,0 +1,118 @@
+//! Panics of a Rust object called through its vtable, reported to the host
+//! and replaced by a value that is valid for the return type of the method
+
+#![feature(abi_thiscall)]
+#![feature(const_fn)]
+#![feature(const_fn_fn_ptr_basics)]
+
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_int,
+};
+
+mod foreign {
+    use std::{cell::RefCell, ffi::c_void, marker::PhantomData};
+
+    #[repr(transparent)]
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut c_void, PhantomData<*mut T>);
+
+    impl<T: ?Sized> Foreign<T> {
+        pub(crate) fn with(ptr: *mut c_void) -> Self {
+            Foreign(ptr, PhantomData)
+        }
+    }
+
+    thread_local! {
+        pub(crate) static PANICKED: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+    }
+
+    pub(crate) fn shim_panicked(method: &'static str) {
+        PANICKED.with(|panicked| panicked.borrow_mut().push(method));
+    }
+}
+
+use crate::foreign::{Foreign, PANICKED};
+
+/// Not valid when zeroed, the shims fall back on its `Default` value
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+#[allow(dead_code)]
+pub(crate) enum Outcome {
+    Handled = 1,
+    Ignored = 2,
+}
+
+impl Default for Outcome {
+    fn default() -> Self {
+        Outcome::Ignored
+    }
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Faulty {
+    fn count(&self) -> c_int;
+    fn ratio(&self) -> f32;
+    fn name(&self) -> &CStr;
+    fn outcome(&mut self) -> Outcome;
+    fn reset(&mut self);
+}
+
+struct Local;
+
+impl Faulty for Local {
+    fn count(&self) -> c_int {
+        panic!("count")
+    }
+
+    fn ratio(&self) -> f32 {
+        panic!("ratio")
+    }
+
+    fn name(&self) -> &CStr {
+        panic!("name")
+    }
+
+    fn outcome(&mut self) -> Outcome {
+        panic!("outcome")
+    }
+
+    fn reset(&mut self) {
+        panic!("reset")
+    }
+}
+
+static VTABLE: IFaulty = <dyn Faulty>::vtable::<Box<Local>, Local>();
+
+fn main() {
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let mut object = CFaulty {
+        vtable: &VTABLE,
+        instance: Box::new(Local),
+    };
+
+    let ptr = &mut object as *mut CFaulty<Box<Local>> as *mut c_void;
+    let mut faulty = Foreign::<dyn Faulty>::with(ptr);
+
+    assert_eq!(faulty.count(), 0);
+    assert_eq!(faulty.ratio(), 0.0);
+    assert_eq!(faulty.outcome(), Outcome::Ignored);
+    faulty.reset();
+
+    // The C string pointer is null, call the shim directly
+    // instead of the binding which would wrap it in a `&CStr`
+    assert!((VTABLE.name)(ptr).is_null());
+
+    PANICKED.with(|panicked| {
+        assert_eq!(
+            *panicked.borrow(),
+            [
+                "Faulty::count",
+                "Faulty::ratio",
+                "Faulty::outcome",
+                "Faulty::reset",
+                "Faulty::name"
+            ]
+        );
+    });
+}
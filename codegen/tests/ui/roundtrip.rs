@@ -0,0 +1,112 @@
+//! Calls through the vtable generated for a Rust object, with the receivers
+//! and the argument types used by the bindings of the addon
+
+#![feature(abi_thiscall)]
+#![feature(const_fn)]
+#![feature(const_fn_fn_ptr_basics)]
+
+use std::{
+    ffi::{c_void, CStr},
+    mem::size_of,
+    os::raw::c_int,
+};
+
+use fabric_codegen::cstr;
+
+mod foreign {
+    use std::{ffi::c_void, marker::PhantomData};
+
+    #[repr(transparent)]
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut c_void, PhantomData<*mut T>);
+
+    impl<T: ?Sized> Foreign<T> {
+        pub(crate) fn with(ptr: *mut c_void) -> Self {
+            Foreign(ptr, PhantomData)
+        }
+    }
+
+    pub(crate) fn shim_panicked(_method: &str) {}
+}
+
+use crate::foreign::Foreign;
+
+#[fabric_codegen::interface]
+pub(crate) trait Counter {
+    fn get(&self) -> c_int;
+    fn add(&mut self, value: c_int) -> c_int;
+    fn name(&self) -> &CStr;
+    fn starts_with(&mut self, prefix: &CStr) -> bool;
+    fn or_default(&mut self, value: &CStr, default: &CStr) -> &CStr;
+    fn mix(&mut self, a: bool, b: u64, c: f32, d: *mut c_int) -> u64;
+}
+
+struct Local {
+    value: c_int,
+}
+
+impl Counter for Local {
+    fn get(&self) -> c_int {
+        self.value
+    }
+
+    fn add(&mut self, value: c_int) -> c_int {
+        self.value += value;
+        self.value
+    }
+
+    fn name(&self) -> &CStr {
+        cstr!("counter")
+    }
+
+    fn starts_with(&mut self, prefix: &CStr) -> bool {
+        self.name().to_bytes().starts_with(prefix.to_bytes())
+    }
+
+    fn or_default(&mut self, value: &CStr, default: &CStr) -> &CStr {
+        if value.to_bytes().is_empty() {
+            unsafe { CStr::from_ptr(default.as_ptr()) }
+        } else {
+            unsafe { CStr::from_ptr(value.as_ptr()) }
+        }
+    }
+
+    fn mix(&mut self, a: bool, b: u64, c: f32, d: *mut c_int) -> u64 {
+        unsafe { *d = self.value };
+        if a {
+            b + c as u64
+        } else {
+            0
+        }
+    }
+}
+
+static VTABLE: ICounter = <dyn Counter>::vtable::<Box<Local>, Local>();
+
+fn main() {
+    // One entry per method, in the order of declaration
+    assert_eq!(size_of::<ICounter>(), 6 * size_of::<usize>());
+
+    let mut object = CCounter {
+        vtable: &VTABLE,
+        instance: Box::new(Local { value: 1 }),
+    };
+
+    let ptr = &mut object as *mut CCounter<Box<Local>> as *mut c_void;
+    let mut counter = Foreign::<dyn Counter>::with(ptr);
+
+    assert_eq!(counter.get(), 1);
+    assert_eq!(counter.add(2), 3);
+    assert_eq!(counter.get(), 3);
+
+    assert_eq!(counter.name(), cstr!("counter"));
+    assert!(counter.starts_with(cstr!("count")));
+    assert!(!counter.starts_with(cstr!("other")));
+    assert_eq!(counter.or_default(cstr!(""), cstr!("default")), cstr!("default"));
+    assert_eq!(counter.or_default(cstr!("value"), cstr!("default")), cstr!("value"));
+
+    let mut out = 0;
+    assert_eq!(counter.mix(true, 1 << 40, 2.5, &mut out), (1 << 40) + 2);
+    assert_eq!(out, 3);
+
+    assert_eq!(object.instance.value, 3);
+}
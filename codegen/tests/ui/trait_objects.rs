@@ -0,0 +1,103 @@
+//! Rust objects passed through a vtable as trait objects, owned by
+//! the callee or borrowed for the duration of the call
+
+#![feature(abi_thiscall)]
+#![feature(const_fn)]
+#![feature(const_fn_fn_ptr_basics)]
+
+use std::{cell::Cell, ffi::c_void, rc::Rc};
+
+mod foreign {
+    use std::{ffi::c_void, marker::PhantomData};
+
+    #[repr(transparent)]
+    pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut c_void, PhantomData<*mut T>);
+
+    impl<T: ?Sized> Foreign<T> {
+        pub(crate) fn with(ptr: *mut c_void) -> Self {
+            Foreign(ptr, PhantomData)
+        }
+    }
+
+    pub(crate) fn shim_panicked(_method: &str) {}
+}
+
+use crate::foreign::Foreign;
+
+#[fabric_codegen::interface]
+pub(crate) trait Listener {
+    fn fire(&mut self, value: i32);
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait Manager {
+    fn add(&mut self, listener: Box<dyn Listener>) -> bool;
+    fn notify(&mut self, listener: &mut dyn Listener, value: i32);
+    fn fire(&mut self, value: i32) -> usize;
+}
+
+/// Listener of the caller, recording the values it received
+struct Recorder {
+    total: Rc<Cell<i32>>,
+}
+
+impl Listener for Recorder {
+    fn fire(&mut self, value: i32) {
+        self.total.set(self.total.get() + value);
+    }
+}
+
+/// Manager of the callee, only seeing the listeners through their vtable
+struct Local {
+    listeners: Vec<Box<dyn Listener>>,
+}
+
+impl Manager for Local {
+    fn add(&mut self, listener: Box<dyn Listener>) -> bool {
+        self.listeners.push(listener);
+        true
+    }
+
+    fn notify(&mut self, listener: &mut dyn Listener, value: i32) {
+        listener.fire(value);
+    }
+
+    fn fire(&mut self, value: i32) -> usize {
+        for listener in &mut self.listeners {
+            listener.fire(value);
+        }
+
+        self.listeners.len()
+    }
+}
+
+static VTABLE: IManager = <dyn Manager>::vtable::<Box<Local>, Local>();
+
+fn main() {
+    let mut object = CManager {
+        vtable: &VTABLE,
+        instance: Box::new(Local {
+            listeners: Vec::new(),
+        }),
+    };
+
+    let ptr = &mut object as *mut CManager<Box<Local>> as *mut c_void;
+    let mut manager = Foreign::<dyn Manager>::with(ptr);
+
+    let total = Rc::new(Cell::new(0));
+    assert!(manager.add(Box::new(Recorder {
+        total: total.clone(),
+    })));
+    assert!(manager.add(Box::new(Recorder {
+        total: total.clone(),
+    })));
+
+    assert_eq!(manager.fire(2), 2);
+    assert_eq!(total.get(), 4);
+
+    let mut borrowed = Recorder {
+        total: total.clone(),
+    };
+    manager.notify(&mut borrowed, 10);
+    assert_eq!(total.get(), 14);
+}
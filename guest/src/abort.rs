@@ -0,0 +1,94 @@
+//! Reporting of the panics of the module to the host, through the
+//! `fabric::abort` import provided by the runtime to all the modules
+//!
+//! The `#[panic_handler]` of a module forwards the panic to `report_panic`:
+//!
+//! ```ignore
+//! #[panic_handler]
+//! fn panic(info: &core::panic::PanicInfo) -> ! {
+//!     fabric_guest::abort::report_panic(info)
+//! }
+//! ```
+//!
+//! The host logs the message with the name of the module and the location
+//! of the panic, then disables the module
+
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+
+use crate::CStr;
+
+mod sys {
+    #[link(wasm_import_module = "fabric")]
+    extern "C" {
+        pub fn abort(message: *const u8, file: *const u8, line: i32);
+    }
+}
+
+/// Abort the execution of the module with `message`, reported
+/// by the host along with the location `file` and `line`
+pub fn abort(message: &CStr, file: &CStr, line: u32) -> ! {
+    unsafe {
+        sys::abort(message.as_ptr(), file.as_ptr(), line as i32);
+        core::arch::wasm32::unreachable()
+    }
+}
+
+/// Report a panic to the host and abort
+pub fn report_panic(info: &PanicInfo) -> ! {
+    let mut message = Buffer::new();
+    let _ = write!(message, "{}", info);
+
+    match info.location() {
+        Some(location) => {
+            let mut file = Buffer::new();
+            let _ = file.write_str(location.file());
+            abort(message.as_cstr(), file.as_cstr(), location.line())
+        }
+        None => unsafe {
+            sys::abort(message.as_cstr().as_ptr(), core::ptr::null(), 0);
+            core::arch::wasm32::unreachable()
+        },
+    }
+}
+
+/// Size of the buffers the panic is formatted in, longer messages are truncated
+const BUFFER_SIZE: usize = 256;
+
+/// Null-terminated string formatted without allocating
+struct Buffer {
+    bytes: [u8; BUFFER_SIZE],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Buffer {
+            bytes: [0; BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    fn as_cstr(&self) -> &CStr {
+        // The last byte is never written, so the content is always terminated
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.bytes[..=self.len]) }
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, value: &str) -> fmt::Result {
+        for &byte in value.as_bytes() {
+            if self.len == BUFFER_SIZE - 1 {
+                break;
+            }
+
+            // The null bytes of the message would end the string early
+            self.bytes[self.len] = if byte == 0 { b'?' } else { byte };
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+}
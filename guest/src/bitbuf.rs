@@ -0,0 +1,85 @@
+//! Bit buffers used to serialize events and write user messages, through the `BitBuffer` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef};
+
+/// A buffer owned by the host, freed when dropped
+#[derive(Debug)]
+pub struct BitBuffer(ExternRef);
+
+impl BitBuffer {
+    /// Create an empty buffer of `size` bytes
+    pub fn new(size: usize) -> Option<Self> {
+        Self::from_extern(unsafe { sys::bit_buffer::create(size as i32) })
+    }
+
+    /// Create a buffer holding a copy of `data`, to be read from
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        Self::from_extern(unsafe { sys::bit_buffer::from_bytes(data.as_ptr(), buffer_len(data)) })
+    }
+
+    fn from_extern(buffer: ExternRef) -> Option<Self> {
+        if buffer.is_null() {
+            None
+        } else {
+            Some(BitBuffer(buffer))
+        }
+    }
+
+    pub(crate) fn as_extern(&self) -> ExternRef {
+        self.0
+    }
+
+    pub fn is_overflowed(&self) -> bool {
+        unsafe { sys::bit_buffer::is_overflowed(self.0) != 0 }
+    }
+
+    pub fn bits_written(&self) -> usize {
+        unsafe { sys::bit_buffer::bits_written(self.0) as usize }
+    }
+
+    /// Copy the content of the buffer to `data`, truncating it to the size
+    /// of `data` and returning the full length of the content in bytes
+    pub fn bytes(&self, data: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::bit_buffer::bytes(self.0, data.as_mut_ptr(), buffer_len(data)) })
+    }
+
+    pub fn write_bits(&mut self, value: i32, bits: u32) -> bool {
+        unsafe { sys::bit_buffer::write_bits(self.0, value, bits as i32) != 0 }
+    }
+
+    pub fn write_float(&mut self, value: f32) -> bool {
+        unsafe { sys::bit_buffer::write_float(self.0, value) != 0 }
+    }
+
+    pub fn write_string(&mut self, value: &CStr) -> bool {
+        unsafe { sys::bit_buffer::write_string(self.0, value.as_ptr()) != 0 }
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) -> bool {
+        unsafe { sys::bit_buffer::write_bytes(self.0, value.as_ptr(), buffer_len(value)) != 0 }
+    }
+
+    pub fn read_ubits(&mut self, bits: u32) -> u32 {
+        unsafe { sys::bit_buffer::read_ubits(self.0, bits as i32) as u32 }
+    }
+
+    pub fn read_sbits(&mut self, bits: u32) -> i32 {
+        unsafe { sys::bit_buffer::read_sbits(self.0, bits as i32) }
+    }
+
+    pub fn read_float(&mut self) -> f32 {
+        unsafe { sys::bit_buffer::read_float(self.0) }
+    }
+
+    /// Read a string into `data`, always null-terminated and truncated
+    /// to the size of `data`, returning the full length of the string
+    pub fn read_string(&mut self, data: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::bit_buffer::read_string(self.0, data.as_mut_ptr(), buffer_len(data)) })
+    }
+}
+
+impl Drop for BitBuffer {
+    fn drop(&mut self) {
+        unsafe { sys::bit_buffer::free(self.0) }
+    }
+}
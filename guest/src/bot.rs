@@ -0,0 +1,55 @@
+//! Bots controlled by the module, through the `Bot` host module
+
+use crate::{sys, CStr, Vector};
+
+/// A bot, identified by its client index
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bot(i32);
+
+impl Bot {
+    pub fn create(name: &CStr) -> Option<Bot> {
+        match unsafe { sys::bot::create(name.as_ptr()) } {
+            0 => None,
+            index => Some(Bot(index)),
+        }
+    }
+
+    pub fn index(self) -> i32 {
+        self.0
+    }
+
+    /// Kick the bot from the server
+    pub fn remove(self) -> bool {
+        unsafe { sys::bot::remove(self.0) != 0 }
+    }
+
+    /// Run a movement command for the bot, `buttons` is a mask of the `IN_*` flags
+    pub fn run_move(
+        self,
+        angles: &Vector,
+        forward_move: f32,
+        side_move: f32,
+        up_move: f32,
+        buttons: i32,
+    ) -> bool {
+        unsafe {
+            sys::bot::run_move(self.0, angles, forward_move, side_move, up_move, buttons) != 0
+        }
+    }
+
+    pub fn set_origin(self, origin: &Vector) -> bool {
+        unsafe { sys::bot::set_origin(self.0, origin) != 0 }
+    }
+
+    pub fn set_angles(self, angles: &Vector) -> bool {
+        unsafe { sys::bot::set_angles(self.0, angles) != 0 }
+    }
+
+    pub fn set_active_weapon(self, weapon: &CStr) -> bool {
+        unsafe { sys::bot::set_active_weapon(self.0, weapon.as_ptr()) != 0 }
+    }
+
+    pub fn remove_all_items(self, remove_suit: bool) -> bool {
+        unsafe { sys::bot::remove_all_items(self.0, remove_suit as i32) != 0 }
+    }
+}
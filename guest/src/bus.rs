@@ -0,0 +1,66 @@
+//! Messages exchanged between the modules, through the `Bus` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef, FuncRef};
+
+/// Handle to a subscription to a topic
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Subscription(i32);
+
+/// Message delivered to a subscription, only valid for the duration of the callback
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct Message(ExternRef);
+
+/// Callback invoked on the game thread for each message published on a topic
+pub type MessageCallback = extern "C" fn(Subscription, Message);
+
+/// Publish `data` on `topic`, the message is delivered on the next frame
+pub fn publish(topic: &CStr, data: &[u8]) -> bool {
+    unsafe { sys::bus::publish(topic.as_ptr(), data.as_ptr(), buffer_len(data)) != 0 }
+}
+
+impl Subscription {
+    pub fn subscribe(topic: &CStr, callback: MessageCallback) -> Option<Subscription> {
+        let handle = unsafe {
+            let callback = FuncRef::from_address(callback as usize);
+            sys::bus::subscribe(topic.as_ptr(), callback)
+        };
+
+        if handle > 0 {
+            Some(Subscription(handle))
+        } else {
+            None
+        }
+    }
+
+    pub fn unsubscribe(self) -> bool {
+        unsafe { sys::bus::unsubscribe(self.0) != 0 }
+    }
+}
+
+impl Message {
+    pub fn len(self) -> usize {
+        unsafe { sys::bus::length(self.0) as usize }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the content of the message to `buffer`, truncated
+    /// to its size, returning the full length of the content
+    pub fn read(self, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::bus::read(self.0, buffer.as_mut_ptr(), buffer_len(buffer)) })
+    }
+
+    /// Copy the topic of the message to `buffer`, like `read`
+    pub fn topic(self, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::bus::topic(self.0, buffer.as_mut_ptr(), buffer_len(buffer)) })
+    }
+
+    /// Copy the name of the module that published the message to `buffer`, like `read`
+    pub fn sender(self, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::bus::sender(self.0, buffer.as_mut_ptr(), buffer_len(buffer)) })
+    }
+}
@@ -0,0 +1,140 @@
+//! Kicks and bans of the clients, through the `Client` host module
+//!
+//! The ban list is shared by all the modules and persisted by the addon,
+//! banned clients are rejected when they connect
+
+pub use crate::sys::client::{
+    BAN_BY_IP, BAN_BY_STEAMID, PRINT_CENTER, PRINT_CHAT, PRINT_CONSOLE, PRINT_HINT, PRINT_NOTIFY,
+    QUERY_NOT_A_CVAR, QUERY_NOT_FOUND, QUERY_OK, QUERY_PROTECTED, SETTING_CMD_RATE, SETTING_INTERP,
+    SETTING_NAME, SETTING_RATE, SETTING_UPDATE_RATE,
+};
+use crate::{buffer_len, length, sys, CStr, FuncRef};
+
+/// Callback invoked with the client whose settings changed and
+/// the `SETTING_*` flags of the settings that changed
+pub type SettingsCallback = extern "C" fn(i32, i32);
+
+/// Callback invoked with the cookie of a query of a client variable,
+/// the client and the `QUERY_*` status of the query
+pub type QueryCallback = extern "C" fn(i32, i32, i32);
+
+/// Callback invoked with a client connecting to the server, returning a
+/// NUL-terminated rejection message to refuse the connection or null to accept it
+pub type ConnectCallback = extern "C" fn(i32) -> *const u8;
+
+/// Disconnect the client at `client` with `reason`
+pub fn kick(client: i32, reason: &CStr) -> bool {
+    unsafe { sys::client::kick(client, reason.as_ptr()) != 0 }
+}
+
+/// Print `text` to the client at `client`, or to all the clients for 0, at
+/// one of the `PRINT_*` destinations: the console, the notification area,
+/// the chat, the center of the screen or the hint box
+pub fn print(client: i32, destination: i32, text: &CStr) -> bool {
+    unsafe { sys::client::print(client, destination, text.as_ptr()) != 0 }
+}
+
+/// Ban the client at `client` for `duration` seconds, or permanently for 0,
+/// then kick it. `flags` is a combination of `BAN_BY_STEAMID` and `BAN_BY_IP`
+pub fn ban(client: i32, duration: u32, reason: &CStr, flags: i32) -> bool {
+    unsafe { sys::client::ban(client, duration as i32, reason.as_ptr(), flags) != 0 }
+}
+
+/// Ban a SteamID or an IP address for `duration` seconds, or permanently for 0
+pub fn ban_identity(identity: &CStr, duration: u32, reason: &CStr) -> bool {
+    unsafe { sys::client::ban_identity(identity.as_ptr(), duration as i32, reason.as_ptr()) != 0 }
+}
+
+/// Lift the ban of a SteamID or an IP address
+pub fn unban(identity: &CStr) -> bool {
+    unsafe { sys::client::unban(identity.as_ptr()) != 0 }
+}
+
+/// Copy the reason of the ban of a SteamID or an IP address into `buffer`
+/// truncated to its size, returning its full length or None if not banned
+pub fn ban_reason(identity: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe {
+        sys::client::ban_reason(identity.as_ptr(), buffer.as_mut_ptr(), buffer_len(buffer))
+    })
+}
+
+/// Register the callback invoked when the replicated settings of a client
+/// change, like its name or its rate settings
+pub fn on_settings_changed(callback: SettingsCallback) -> bool {
+    unsafe {
+        let callback = FuncRef::from_address(callback as usize);
+        sys::client::on_settings_changed(callback) != 0
+    }
+}
+
+/// Register the callback invoked when a client connects to the server
+///
+/// The rejection message returned by the callback must stay valid after it
+/// returns, like a static string, and is truncated to the size of the buffer
+/// of the engine. Bans are checked first, and the callbacks of the other
+/// modules aren't invoked once a module rejected the client
+pub fn on_connect(callback: ConnectCallback) -> bool {
+    unsafe {
+        let callback = FuncRef::from_address(callback as usize);
+        sys::client::on_connect(callback) != 0
+    }
+}
+
+/// Copy the value of one of the `SETTING_*` settings of the client at
+/// `client` to `buffer`, truncated to its size, returning its full length
+///
+/// With `previous`, the value before the last change is copied instead,
+/// like the old name of a client that was just renamed
+pub fn setting(client: i32, setting: i32, previous: bool, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe {
+        sys::client::setting(
+            client,
+            setting,
+            previous as i32,
+            buffer.as_mut_ptr(),
+            buffer_len(buffer),
+        )
+    })
+}
+
+/// Set the variable `name` of the client at `client` to `value` by running
+/// the command in its console, the clients only run the commands of the
+/// server for the variables that allow it. Bots have no console
+pub fn set_cvar(client: i32, name: &CStr, value: &CStr) -> bool {
+    unsafe { sys::client::set_cvar(client, name.as_ptr(), value.as_ptr()) != 0 }
+}
+
+/// Copy the value of the variable `name` replicated by the client at `client`
+/// to `buffer`, truncated to its size, returning its full length. Only the
+/// variables sent to the server with the user info have a value
+pub fn cvar(client: i32, name: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe {
+        sys::client::cvar(
+            client,
+            name.as_ptr(),
+            buffer.as_mut_ptr(),
+            buffer_len(buffer),
+        )
+    })
+}
+
+/// Ask the client at `client` for the value of any of its variables, returns
+/// the cookie passed to `callback` when the client answers with its value
+pub fn query_cvar(client: i32, name: &CStr, callback: QueryCallback) -> Option<i32> {
+    let cookie = unsafe {
+        let callback = FuncRef::from_address(callback as usize);
+        sys::client::query_cvar(client, name.as_ptr(), callback)
+    };
+
+    if cookie < 0 {
+        None
+    } else {
+        Some(cookie)
+    }
+}
+
+/// Copy the value sent by the client to `buffer` from a `query_cvar` callback,
+/// truncated to its size, returning its full length
+pub fn query_value(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::client::query_value(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
@@ -0,0 +1,77 @@
+//! Console commands handled by the module, through the `Command` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef, FuncRef, RateLimit};
+
+/// Arguments of a command, including its name, only valid for the duration of the callback
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct Args(ExternRef);
+
+/// Callback invoked on the game thread each time the command is executed
+pub type CommandCallback = extern "C" fn(Args);
+
+/// Register the console command `name`, the command can be executed from
+/// the server console, through RCON or typed by the clients in their console
+pub fn register(name: &CStr, help: &CStr, callback: CommandCallback) -> bool {
+    unsafe {
+        let callback = FuncRef::from_address(callback as usize);
+        sys::command::register(name.as_ptr(), help.as_ptr(), callback) != 0
+    }
+}
+
+/// Entity index of the client that issued the command being handled,
+/// 0 for the server console and RCON
+pub fn issuer() -> i32 {
+    unsafe { sys::command::issuer() }
+}
+
+/// Limit the clients to `rate` executions of the command `name` per second
+/// with bursts of `burst` executions, for all the modules. `"*"` sets the
+/// limit of the commands without a limit of their own, and a rate of 0
+/// removes the limit. The commands past their limit are blocked
+pub fn set_rate_limit(name: &CStr, rate: f32, burst: i32) -> bool {
+    unsafe { sys::command::set_rate_limit(name.as_ptr(), rate, burst) != 0 }
+}
+
+/// Restore the limit of the command `name` from the configuration
+pub fn reset_rate_limit(name: &CStr) -> bool {
+    unsafe { sys::command::reset_rate_limit(name.as_ptr()) != 0 }
+}
+
+/// Limit applying to the command `name`
+pub fn rate_limit(name: &CStr) -> Option<RateLimit> {
+    let mut limit = RateLimit::default();
+    if unsafe { sys::command::rate_limit(name.as_ptr(), &mut limit) } != 0 {
+        Some(limit)
+    } else {
+        None
+    }
+}
+
+/// Whether the client at `client` is currently blocked from issuing `name`
+pub fn is_limited(client: i32, name: &CStr) -> bool {
+    unsafe { sys::command::is_limited(client, name.as_ptr()) != 0 }
+}
+
+impl Args {
+    pub fn len(self) -> usize {
+        unsafe { sys::command::arg_count(self.0) as usize }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the argument at `index` to `buffer`, truncated to its size,
+    /// returning its full length or None if there is no such argument
+    pub fn get(self, index: usize, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::command::arg(
+                self.0,
+                index as i32,
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+}
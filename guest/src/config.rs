@@ -0,0 +1,20 @@
+//! Settings of the module from the configuration of the addon,
+//! through the `Config` host module
+
+use crate::{buffer_len, length, sys, CStr, FuncRef};
+
+/// Callback invoked when the configuration is reloaded
+pub type ChangedCallback = extern "C" fn();
+
+/// Copy the value of the setting `key` to `buffer`, truncated to its size,
+/// returning the full length of the value or None if it isn't set
+///
+/// Settings that aren't strings are written in the TOML format
+pub fn get(key: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::config::get(key.as_ptr(), buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// Call `callback` every time the configuration is reloaded
+pub fn on_changed(callback: ChangedCallback) -> bool {
+    unsafe { sys::config::on_changed(FuncRef::from_address(callback as usize)) != 0 }
+}
@@ -0,0 +1,83 @@
+//! SQLite database of the module, through the `Db` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef};
+
+/// Run one or more statements without parameters
+pub fn exec(sql: &CStr) -> bool {
+    unsafe { sys::db::exec(sql.as_ptr()) != 0 }
+}
+
+/// A prepared statement, finalized when dropped
+///
+/// Parameters are 1-indexed like in the SQLite API, columns are 0-indexed
+#[derive(Debug)]
+pub struct Statement(ExternRef);
+
+impl Statement {
+    pub fn prepare(sql: &CStr) -> Option<Statement> {
+        let statement = unsafe { sys::db::prepare(sql.as_ptr()) };
+        if statement.is_null() {
+            None
+        } else {
+            Some(Statement(statement))
+        }
+    }
+
+    pub fn bind_int(&mut self, index: u32, value: i64) -> bool {
+        unsafe { sys::db::bind_int(self.0, index as i32, value) != 0 }
+    }
+
+    pub fn bind_float(&mut self, index: u32, value: f64) -> bool {
+        unsafe { sys::db::bind_float(self.0, index as i32, value) != 0 }
+    }
+
+    pub fn bind_text(&mut self, index: u32, value: &CStr) -> bool {
+        unsafe { sys::db::bind_text(self.0, index as i32, value.as_ptr()) != 0 }
+    }
+
+    pub fn bind_blob(&mut self, index: u32, value: &[u8]) -> bool {
+        unsafe { sys::db::bind_blob(self.0, index as i32, value.as_ptr(), buffer_len(value)) != 0 }
+    }
+
+    /// Run the statement, returning the number of rows changed
+    pub fn execute(&mut self) -> Option<usize> {
+        length(unsafe { sys::db::execute(self.0) })
+    }
+
+    /// Run the statement, returning the number of rows read with `next_row`
+    pub fn query(&mut self) -> Option<usize> {
+        length(unsafe { sys::db::query(self.0) })
+    }
+
+    /// Advance to the next row of the query, returns false after the last row
+    pub fn next_row(&mut self) -> bool {
+        unsafe { sys::db::next(self.0) != 0 }
+    }
+
+    pub fn column_int(&self, index: u32) -> i64 {
+        unsafe { sys::db::column_int(self.0, index as i32) }
+    }
+
+    pub fn column_float(&self, index: u32) -> f64 {
+        unsafe { sys::db::column_float(self.0, index as i32) }
+    }
+
+    /// Copy a text or blob column to `buffer`, truncated to its
+    /// size, returning the full length of the value
+    pub fn column_bytes(&self, index: u32, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::db::column_bytes(
+                self.0,
+                index as i32,
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe { sys::db::finalize(self.0) }
+    }
+}
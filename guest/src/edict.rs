@@ -0,0 +1,62 @@
+//! Edicts of the server, through the `Edict` host module
+
+use crate::{buffer_len, length, sys, FuncRef};
+
+/// Callback invoked with the index of an edict
+pub type EdictCallback = extern "C" fn(i32);
+
+/// Serial number of the edict at `index`, incremented when it is reused
+pub fn serial_number(index: i32) -> Option<i32> {
+    match unsafe { sys::edict::serial_number(index) } {
+        -1 => None,
+        serial => Some(serial),
+    }
+}
+
+pub fn is_free(index: i32) -> bool {
+    unsafe { sys::edict::is_free(index) != 0 }
+}
+
+/// Iterate over the indices of the allocated edicts
+pub fn allocated() -> impl Iterator<Item = i32> {
+    let mut index = -1;
+    core::iter::from_fn(move || {
+        index = unsafe { sys::edict::next_allocated(index) };
+        if index < 0 {
+            None
+        } else {
+            Some(index)
+        }
+    })
+}
+
+/// Call `callback` every time an edict is allocated
+pub fn on_allocated(callback: EdictCallback) -> bool {
+    unsafe { sys::edict::on_allocated(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+/// Call `callback` every time an edict is freed
+pub fn on_freed(callback: EdictCallback) -> bool {
+    unsafe { sys::edict::on_freed(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+/// Call `callback` with the index of the entities once they are created
+///
+/// The creations are reported on the frames following them, a limited number
+/// on each frame, and an entity removed before that is never reported
+pub fn on_entity_created(callback: EdictCallback) -> bool {
+    unsafe { sys::edict::on_entity_created(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+/// Call `callback` with the index of the entities once they are removed,
+/// the entities of a map aren't reported as removed when it ends
+pub fn on_entity_removed(callback: EdictCallback) -> bool {
+    unsafe { sys::edict::on_entity_removed(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+/// Copy the class name of the entity of the current `on_entity_created` or
+/// `on_entity_removed` callback to `buffer`, truncated to its size, returning
+/// its full length or None outside of the callbacks or if it's unknown
+pub fn class_name(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::edict::class_name(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
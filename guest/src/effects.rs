@@ -0,0 +1,34 @@
+//! Temporary visual effects, through the `Effects` host module
+
+use crate::{sys, CStr, Vector};
+
+/// Precache `model` so it can be used by `beam` and `smoke`
+pub fn precache_model(model: &CStr) -> bool {
+    unsafe { sys::effects::precache_model(model.as_ptr()) != 0 }
+}
+
+/// `life` is in the range [0, 25.5], `width` in [0, 255]
+/// and `color` is packed as 0xRRGGBBAA
+pub fn beam(start: &Vector, end: &Vector, model: &CStr, life: f32, width: i32, color: u32) -> bool {
+    unsafe { sys::effects::beam(start, end, model.as_ptr(), life, width, color as i32) != 0 }
+}
+
+pub fn smoke(origin: &Vector, model: &CStr, scale: f32, frame_rate: f32) -> bool {
+    unsafe { sys::effects::smoke(origin, model.as_ptr(), scale, frame_rate) != 0 }
+}
+
+pub fn sparks(position: &Vector, magnitude: i32, trail_length: i32) -> bool {
+    unsafe { sys::effects::sparks(position, magnitude, trail_length) != 0 }
+}
+
+pub fn metal_sparks(position: &Vector, direction: &Vector) -> bool {
+    unsafe { sys::effects::metal_sparks(position, direction) != 0 }
+}
+
+pub fn dust(position: &Vector, direction: &Vector, size: f32, speed: f32) -> bool {
+    unsafe { sys::effects::dust(position, direction, size, speed) != 0 }
+}
+
+pub fn energy_splash(position: &Vector, direction: &Vector, explosive: bool) -> bool {
+    unsafe { sys::effects::energy_splash(position, direction, explosive as i32) != 0 }
+}
@@ -0,0 +1,74 @@
+//! Entities of the server, through the `Entity` host module
+//!
+//! Entities are referenced by index, and by handle for the references
+//! held across frames. The functions returning an entity return None
+//! if the entity doesn't exist
+
+use crate::{buffer_len, length, sys, CStr, Vector};
+
+fn entity(result: i32) -> Option<i32> {
+    if result < 0 {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+pub fn max_entities() -> i32 {
+    unsafe { sys::entity::max_entities() }
+}
+
+pub fn is_valid(index: i32) -> bool {
+    unsafe { sys::entity::is_valid(index) != 0 }
+}
+
+/// Handle to the entity at `index`
+pub fn handle(index: i32) -> Option<i32> {
+    entity(unsafe { sys::entity::handle(index) })
+}
+
+/// Index of the entity referenced by `handle`
+pub fn from_handle(handle: i32) -> Option<i32> {
+    entity(unsafe { sys::entity::from_handle(handle) })
+}
+
+/// Copy the class name of the entity to `buffer`, always null-terminated and
+/// truncated to the size of `buffer`, returning the full length of the name
+pub fn class_name(index: i32, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::entity::class_name(index, buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+pub fn get_prop_int(index: i32, name: &CStr) -> i32 {
+    unsafe { sys::entity::get_prop_int(index, name.as_ptr()) }
+}
+
+pub fn get_prop_float(index: i32, name: &CStr) -> f32 {
+    unsafe { sys::entity::get_prop_float(index, name.as_ptr()) }
+}
+
+pub fn get_prop_vector(index: i32, name: &CStr) -> Option<Vector> {
+    let mut result = Vector::default();
+    match unsafe { sys::entity::get_prop_vector(index, name.as_ptr(), &mut result) } {
+        0 => None,
+        _ => Some(result),
+    }
+}
+
+/// Create an entity of `class_name`, returning its handle. The entity
+/// is configured with `set_keyvalue` then added to the world with `spawn`
+pub fn create(class_name: &CStr) -> Option<i32> {
+    entity(unsafe { sys::entity::create(class_name.as_ptr()) })
+}
+
+pub fn set_keyvalue(handle: i32, key: &CStr, value: &CStr) -> bool {
+    unsafe { sys::entity::set_keyvalue(handle, key.as_ptr(), value.as_ptr()) != 0 }
+}
+
+pub fn spawn(handle: i32) -> bool {
+    unsafe { sys::entity::spawn(handle) != 0 }
+}
+
+/// Remove the entity at the end of the frame
+pub fn remove(handle: i32) -> bool {
+    unsafe { sys::entity::remove(handle) != 0 }
+}
@@ -0,0 +1,213 @@
+//! Game event listeners, through the `GameEventsManager` and `GameEvent` host modules
+
+use crate::{bitbuf::BitBuffer, buffer_len, length, sys, CStr, ExternRef, FieldRead, FuncRef};
+
+/// A game event, either received by a listener or unserialized from a buffer
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct Event(ExternRef);
+
+/// Field of an event declared in the event resource files of the game,
+/// resolved with `field_id` and only valid for the events of that name
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FieldId(i32);
+
+impl FieldId {
+    fn as_extern(self) -> ExternRef {
+        ExternRef::constant(self.0 as u32)
+    }
+}
+
+impl FieldRead {
+    pub fn int(field: FieldId) -> Self {
+        Self::new(field, sys::game_event::FIELD_INT)
+    }
+
+    pub fn bool(field: FieldId) -> Self {
+        Self::new(field, sys::game_event::FIELD_BOOL)
+    }
+
+    pub fn float(field: FieldId) -> Self {
+        Self::new(field, sys::game_event::FIELD_FLOAT)
+    }
+
+    fn new(field: FieldId, ty: i32) -> Self {
+        FieldRead {
+            field: field.0,
+            ty,
+            value: 0,
+        }
+    }
+
+    pub fn as_int(&self) -> i32 {
+        self.value as i32
+    }
+
+    pub fn as_bool(&self) -> bool {
+        self.value != 0
+    }
+
+    pub fn as_float(&self) -> f32 {
+        f32::from_bits(self.value)
+    }
+}
+
+/// Resolve the field `name`, written as `event::field`, the field can then
+/// be read by identifier without passing its name to the host on every access
+///
+/// The identifiers are the values of the `event::field` globals that
+/// can be imported from the `GameEvent` host module
+pub fn field_id(name: &CStr) -> Option<FieldId> {
+    let id = unsafe { sys::game_event::field_id(name.as_ptr()) };
+    if id < 0 {
+        None
+    } else {
+        Some(FieldId(id))
+    }
+}
+
+/// Listener called on the game thread with the events it was registered for,
+/// the event is only valid for the duration of the call
+pub type Listener = extern "C" fn(Event);
+
+/// Listener returning true to consume the event, the modules with a
+/// higher priority listening to the same event don't receive it
+pub type ConsumingListener = extern "C" fn(Event) -> bool;
+
+fn listener_flags(server_side: bool) -> i32 {
+    if server_side {
+        sys::game_events_manager::LISTENER_SERVER_SIDE
+    } else {
+        0
+    }
+}
+
+/// Call `listener` for every `event` fired by the game, on the server
+/// or the client side of the engine depending on `server_side`
+pub fn add_listener(event: &CStr, server_side: bool, listener: Listener) {
+    unsafe {
+        let listener = FuncRef::from_address(listener as usize);
+        let flags = listener_flags(server_side);
+        sys::game_events_manager::add_listener(listener, event.as_ptr(), flags);
+    }
+}
+
+/// Like `add_listener`, but `listener` can consume the events it receives
+pub fn add_consuming_listener(event: &CStr, server_side: bool, listener: ConsumingListener) {
+    unsafe {
+        let listener = FuncRef::from_address(listener as usize);
+        let flags = listener_flags(server_side) | sys::game_events_manager::LISTENER_CONSUME;
+        sys::game_events_manager::add_listener(listener, event.as_ptr(), flags);
+    }
+}
+
+impl Event {
+    /// Create the custom event `name`, fired to the listeners of
+    /// `module:name` where `module` is the name of the calling module
+    ///
+    /// The event must be fired or freed, `name` can't contain a colon
+    pub fn create(name: &CStr) -> Option<Event> {
+        let event = unsafe { sys::game_events_manager::create_event(name.as_ptr()) };
+        if event.is_null() {
+            None
+        } else {
+            Some(Event(event))
+        }
+    }
+
+    pub fn get_int(self, name: &CStr) -> i32 {
+        unsafe { sys::game_event::get_int(self.0, name.as_ptr()) }
+    }
+
+    pub fn get_bool(self, name: &CStr) -> bool {
+        unsafe { sys::game_event::get_bool(self.0, name.as_ptr()) != 0 }
+    }
+
+    pub fn get_float(self, name: &CStr) -> f32 {
+        unsafe { sys::game_event::get_float(self.0, name.as_ptr()) }
+    }
+
+    /// Copy the string field `name` to `buffer`, returns the length of the
+    /// value, which is truncated to the length of `buffer`
+    pub fn get_string(self, name: &CStr, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::game_event::get_string(
+                self.0,
+                name.as_ptr(),
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+
+    pub fn set_int(self, name: &CStr, value: i32) {
+        unsafe { sys::game_event::set_int(self.0, name.as_ptr(), value) }
+    }
+
+    pub fn set_bool(self, name: &CStr, value: bool) {
+        unsafe { sys::game_event::set_bool(self.0, name.as_ptr(), value as i32) }
+    }
+
+    pub fn set_float(self, name: &CStr, value: f32) {
+        unsafe { sys::game_event::set_float(self.0, name.as_ptr(), value) }
+    }
+
+    pub fn set_string(self, name: &CStr, value: &CStr) {
+        unsafe { sys::game_event::set_string(self.0, name.as_ptr(), value.as_ptr()) }
+    }
+
+    pub fn get_int_by_id(self, field: FieldId) -> i32 {
+        unsafe { sys::game_event::get_field_int(self.0, field.as_extern()) }
+    }
+
+    pub fn get_bool_by_id(self, field: FieldId) -> bool {
+        unsafe { sys::game_event::get_field_bool(self.0, field.as_extern()) != 0 }
+    }
+
+    pub fn get_float_by_id(self, field: FieldId) -> f32 {
+        unsafe { sys::game_event::get_field_float(self.0, field.as_extern()) }
+    }
+
+    /// Read all the `fields` of the event in a single host call, returns
+    /// false if the host could not access the fields
+    pub fn read_fields(self, fields: &mut [FieldRead]) -> bool {
+        let count = fields.len() as i32;
+        unsafe { sys::game_event::read_fields(self.0, fields.as_mut_ptr(), count) >= 0 }
+    }
+
+    /// Encode the name and the fields of the event to `buffer`, in the format
+    /// described in the README, returns the length of the encoded event or
+    /// None if it could not be written. The event is truncated to the length
+    /// of `buffer`
+    pub fn encode(self, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::game_event::serialize(self.0, buffer.as_mut_ptr(), buffer_len(buffer))
+        })
+    }
+
+    /// Write the event to `buffer`, returns false if it could not be serialized
+    pub fn serialize(self, buffer: &BitBuffer) -> bool {
+        unsafe { sys::game_events_manager::serialize_event(self.0, buffer.as_extern()) != 0 }
+    }
+
+    /// Read an event written by `serialize`, the event must be fired or freed
+    pub fn unserialize(buffer: &BitBuffer) -> Option<Event> {
+        let event = unsafe { sys::game_events_manager::unserialize_event(buffer.as_extern()) };
+        if event.is_null() {
+            None
+        } else {
+            Some(Event(event))
+        }
+    }
+
+    /// Fire an event created by `create` or `unserialize`, this consumes the event
+    pub fn fire(self, dont_broadcast: bool) -> bool {
+        unsafe { sys::game_events_manager::fire_event(self.0, dont_broadcast as i32) != 0 }
+    }
+
+    /// Free an event created by `create` or `unserialize` without firing it
+    pub fn free(self) -> bool {
+        unsafe { sys::game_events_manager::free_event(self.0) != 0 }
+    }
+}
@@ -0,0 +1,21 @@
+//! Files in the data directory of the module, through the `Fs` host module
+
+use crate::{buffer_len, length, sys, CStr};
+
+/// Copy the content of the file at `path` to `buffer`, truncated
+/// to its size, returning the full length of the file
+pub fn read(path: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::fs::read(path.as_ptr(), buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// Replace the content of the file at `path` with `data`
+pub fn write(path: &CStr, data: &[u8]) -> bool {
+    unsafe { sys::fs::write(path.as_ptr(), data.as_ptr(), buffer_len(data)) != 0 }
+}
+
+/// List the entries of the directory at `path` as names separated by newlines,
+/// with a trailing slash for the directories. The list is truncated to the size
+/// of `buffer` and its full length is returned
+pub fn list(path: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::fs::list(path.as_ptr(), buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
@@ -0,0 +1,21 @@
+//! Global variables of the server, through the `Globals` host module
+
+use crate::sys;
+
+/// Time of the current frame of the server in seconds
+pub fn curtime() -> f32 {
+    unsafe { sys::globals::curtime() }
+}
+
+/// Duration of the previous frame of the server in seconds
+pub fn frametime() -> f32 {
+    unsafe { sys::globals::frametime() }
+}
+
+pub fn tick_count() -> i32 {
+    unsafe { sys::globals::tick_count() }
+}
+
+pub fn max_clients() -> i32 {
+    unsafe { sys::globals::max_clients() }
+}
@@ -0,0 +1,64 @@
+//! HTTP requests run in the background, through the `Http` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef, FuncRef};
+
+/// Response to a request, only valid for the duration of the callback
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct Response(ExternRef);
+
+/// Callback invoked on the game thread once a request completes, with the
+/// HTTP status code of the response or a negative error code
+pub type ResponseCallback = extern "C" fn(i32, Response);
+
+/// Send a request to one of the hosts allowed in the configuration of the
+/// addon, `headers` is a list of `Name: value` lines separated by newlines
+pub fn request(
+    method: &CStr,
+    url: &CStr,
+    headers: &CStr,
+    body: &[u8],
+    callback: ResponseCallback,
+) -> bool {
+    unsafe {
+        let callback = FuncRef::from_address(callback as usize);
+        sys::http::request(
+            method.as_ptr(),
+            url.as_ptr(),
+            headers.as_ptr(),
+            body.as_ptr(),
+            buffer_len(body),
+            callback,
+        ) != 0
+    }
+}
+
+impl Response {
+    /// Returns true for the null response passed to the callback on errors
+    pub fn is_null(self) -> bool {
+        self.0.is_null()
+    }
+
+    pub fn body_length(self) -> usize {
+        unsafe { sys::http::body_length(self.0) as usize }
+    }
+
+    /// Copy the body of the response to `buffer`, truncated to its size,
+    /// returning the full length of the body
+    pub fn read_body(self, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe { sys::http::read_body(self.0, buffer.as_mut_ptr(), buffer_len(buffer)) })
+    }
+
+    /// Copy the value of the header `name` to `buffer`, truncated to its size,
+    /// returning the full length of the value or None if the header is absent
+    pub fn header(self, name: &CStr, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::http::header(
+                self.0,
+                name.as_ptr(),
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+}
@@ -0,0 +1,45 @@
+//! Long computations split across frames, through the `Job` host module
+//!
+//! The host calls the step of a job once per frame until it returns false.
+//! A step keeps its progress in its own state and returns as soon as
+//! `should_yield` is true, so the job doesn't exceed the execution budget of
+//! the module. A step running out of fuel anyway is aborted and called again
+//! on the next frame, resuming from the progress it saved last
+
+use crate::{sys, FuncRef};
+
+/// Handle to a running job
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Job(i32);
+
+/// Step of a job, invoked with its handle and the data it was spawned
+/// with, returns true while the job has more work to do
+pub type JobStep = extern "C" fn(Job, i32) -> bool;
+
+impl Job {
+    /// Run `step` with `data` on each frame, starting with the next one
+    pub fn spawn(step: JobStep, data: i32) -> Option<Job> {
+        let handle = unsafe {
+            let step = FuncRef::from_address(step as usize);
+            sys::job::spawn(step, data)
+        };
+
+        if handle > 0 {
+            Some(Job(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Returns false if the job already finished or was cancelled
+    pub fn cancel(self) -> bool {
+        unsafe { sys::job::cancel(self.0) != 0 }
+    }
+}
+
+/// Returns true once the module is running out of budget for the current
+/// frame, the step of a job should then save its progress and return true
+pub fn should_yield() -> bool {
+    unsafe { sys::job::should_yield() != 0 }
+}
@@ -0,0 +1,18 @@
+//! Persistent key-value store of the module, through the `Kv` host module
+
+use crate::{buffer_len, length, sys, CStr};
+
+pub fn set(key: &CStr, value: &[u8]) -> bool {
+    unsafe { sys::kv::set(key.as_ptr(), value.as_ptr(), buffer_len(value)) != 0 }
+}
+
+/// Copy the value of `key` to `buffer`, truncated to its size, returning
+/// the full length of the value or None if the key is absent
+pub fn get(key: &CStr, buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::kv::get(key.as_ptr(), buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// Returns false if the key was absent
+pub fn delete(key: &CStr) -> bool {
+    unsafe { sys::kv::delete(key.as_ptr()) != 0 }
+}
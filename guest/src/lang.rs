@@ -0,0 +1,22 @@
+//! Translated phrases of the module, through the `Lang` host module
+
+use crate::{buffer_len, length, sys, CStr};
+
+/// Format `phrase` in the language of the client at `client`, or in the
+/// default language for 0, into `buffer` truncated to its size, returning
+/// the full length of the text or None if the phrase isn't translated
+///
+/// `args` is a sequence of null-terminated strings replacing the `{1}`
+/// to `{9}` placeholders of the phrase, like `b"player\03\0"`
+pub fn format(client: i32, phrase: &CStr, args: &[u8], buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe {
+        sys::lang::format(
+            client,
+            phrase.as_ptr(),
+            args.as_ptr(),
+            buffer_len(args),
+            buffer.as_mut_ptr(),
+            buffer_len(buffer),
+        )
+    })
+}
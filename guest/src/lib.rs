@@ -0,0 +1,201 @@
+//! Bindings to the host modules of Fabric for guest modules written in
+//! Rust, compiled for the `wasm32-unknown-unknown` target
+//!
+//! The raw imports in `sys` are generated from the import list shared with
+//! the addon, the other modules wrap them in safe functions and types
+#![no_std]
+
+pub mod abort;
+pub mod bitbuf;
+pub mod bot;
+pub mod bus;
+pub mod client;
+pub mod command;
+pub mod config;
+pub mod db;
+pub mod edict;
+pub mod effects;
+pub mod entity;
+pub mod event;
+pub mod fs;
+pub mod globals;
+pub mod http;
+pub mod job;
+pub mod kv;
+pub mod lang;
+pub mod log;
+pub mod map;
+pub mod menu;
+pub mod random;
+pub mod server;
+pub mod shared;
+pub mod sound;
+pub mod state;
+pub mod target;
+pub mod timer;
+pub mod trace;
+pub mod usermessage;
+pub mod vote;
+
+/// Names of the host modules and their imports, shared with the addon
+pub mod names {
+    fabric_codegen::host_names!();
+}
+
+/// Raw imports of the host modules, one module for each host module
+pub mod sys {
+    use crate::{ExternRef, FieldRead, FuncRef, RateLimit, TraceResult, Vector};
+
+    fabric_codegen::guest_imports!();
+}
+
+/// Reference to a value owned by the host, such as an event or a buffer
+///
+/// Host modules compiled with reference types see these as `externref`,
+/// Rust passes them as the 64 bits value of the reference
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExternRef(u64);
+
+impl ExternRef {
+    /// A constant ExternRef, like the constants imported as globals
+    pub const fn constant(value: u32) -> Self {
+        ExternRef((value as u64) << 32)
+    }
+
+    /// The constant ExternRef returned by the host for an absent value
+    pub const fn null() -> Self {
+        Self::constant(0)
+    }
+
+    pub fn is_null(self) -> bool {
+        self == Self::null()
+    }
+}
+
+/// Reference to a guest function, passed to the host
+/// as the index of the function in the table of the module
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct FuncRef(u32);
+
+impl FuncRef {
+    /// # Safety
+    /// `func` must be the address of a function with
+    /// the signature expected by the host for this callback
+    pub unsafe fn from_address(func: usize) -> Self {
+        FuncRef(func as u32)
+    }
+}
+
+/// A position, direction or angles in the world
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Vector {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Vector { x, y, z }
+    }
+}
+
+/// Result of a trace, written by the host functions of `trace`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TraceResult {
+    /// Fraction of the ray traveled before hitting something
+    pub fraction: f32,
+    /// Index of the entity that was hit, or -1
+    pub entity: i32,
+    pub end_pos: Vector,
+    pub normal: Vector,
+    pub contents: i32,
+    pub surface_flags: i32,
+    pub surface_props: i32,
+    pub hitgroup: i32,
+    pub start_solid: i32,
+    pub all_solid: i32,
+}
+
+/// Limit of a command issued by the clients, written by `command::rate_limit`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RateLimit {
+    /// Commands per second each client can issue, 0 for no limit
+    pub rate: f32,
+    /// Commands a client can issue at once before being limited
+    pub burst: i32,
+}
+
+/// A field to read from an event with `Event::read_fields`, the host writes
+/// the value of the field in the type requested in `ty`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FieldRead {
+    /// Identifier of the field in the event schema, from `event::field_id`
+    pub field: i32,
+    /// One of the `FIELD_*` types of the `GameEvent` host module
+    pub ty: i32,
+    /// Bits of the value of the field, 0 if the field could not be read
+    pub value: u32,
+}
+
+/// A borrowed null-terminated string, the strings passed to the host
+///
+/// `core` doesn't provide a `CStr` type, these are usually created
+/// from literals with the `cstr!` macro
+#[repr(transparent)]
+pub struct CStr([u8]);
+
+impl CStr {
+    /// Returns None if `bytes` is not null-terminated or contains a null byte
+    pub fn from_bytes_with_nul(bytes: &[u8]) -> Option<&CStr> {
+        match bytes.split_last() {
+            Some((0, rest)) if !rest.contains(&0) => {
+                Some(unsafe { Self::from_bytes_with_nul_unchecked(bytes) })
+            }
+            _ => None,
+        }
+    }
+
+    /// # Safety
+    /// `bytes` must end with a null byte and contain no other
+    pub unsafe fn from_bytes_with_nul_unchecked(bytes: &[u8]) -> &CStr {
+        &*(bytes as *const [u8] as *const CStr)
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr()
+    }
+
+    /// Content of the string without the null terminator
+    pub fn to_bytes(&self) -> &[u8] {
+        &self.0[..self.0.len() - 1]
+    }
+}
+
+/// Create a `&CStr` from a string literal
+#[macro_export]
+macro_rules! cstr {
+    ( $value:expr ) => {
+        unsafe { $crate::CStr::from_bytes_with_nul_unchecked(concat!($value, "\0").as_bytes()) }
+    };
+}
+
+/// Size of a guest buffer passed to the host
+pub(crate) fn buffer_len(buffer: &[u8]) -> i32 {
+    buffer.len().min(i32::MAX as usize) as i32
+}
+
+/// Convert the result of a host function returning a length or -1
+pub(crate) fn length(result: i32) -> Option<usize> {
+    if result < 0 {
+        None
+    } else {
+        Some(result as usize)
+    }
+}
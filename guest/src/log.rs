@@ -0,0 +1,48 @@
+//! Logging to the console of the game, through the `LoggingSystem` host module
+
+use crate::{sys, CStr, ExternRef};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn as_extern(self) -> ExternRef {
+        match self {
+            Level::Error => sys::logging_system::LEVEL_ERROR,
+            Level::Warn => sys::logging_system::LEVEL_WARN,
+            Level::Info => sys::logging_system::LEVEL_INFO,
+            Level::Debug => sys::logging_system::LEVEL_DEBUG,
+            Level::Trace => sys::logging_system::LEVEL_TRACE,
+        }
+    }
+}
+
+pub fn log(level: Level, message: &CStr) {
+    unsafe { sys::logging_system::log(level.as_extern(), message.as_ptr()) }
+}
+
+pub fn error(message: &CStr) {
+    log(Level::Error, message)
+}
+
+pub fn warn(message: &CStr) {
+    log(Level::Warn, message)
+}
+
+pub fn info(message: &CStr) {
+    log(Level::Info, message)
+}
+
+pub fn debug(message: &CStr) {
+    log(Level::Debug, message)
+}
+
+pub fn trace(message: &CStr) {
+    log(Level::Trace, message)
+}
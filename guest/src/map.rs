@@ -0,0 +1,34 @@
+//! Level changes and next map, through the `Map` host module
+//!
+//! These are the primitives of the map cycle and vote modules, the next map
+//! is forgotten when a new map starts
+
+use crate::{buffer_len, length, sys, CStr};
+
+/// Change the level to `map` in `delay` seconds, replacing the change
+/// requested before. The level is changed on the next frame at the earliest
+pub fn change(map: &CStr, delay: f32) -> bool {
+    unsafe { sys::map::change(map.as_ptr(), delay) != 0 }
+}
+
+/// Cancel the pending level change, returns false if there is none
+pub fn cancel_change() -> bool {
+    unsafe { sys::map::cancel_change() != 0 }
+}
+
+/// Whether `map` is a map the server can load
+pub fn is_valid(map: &CStr) -> bool {
+    unsafe { sys::map::is_valid(map.as_ptr()) != 0 }
+}
+
+/// Choose `map` as the next map, the games with a `nextlevel` console
+/// variable change to it at the end of the current map
+pub fn set_next(map: &CStr) -> bool {
+    unsafe { sys::map::set_next(map.as_ptr()) != 0 }
+}
+
+/// Copy the name of the next map to `buffer`, truncated to its size,
+/// returning its full length or None if the next map isn't known
+pub fn next(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::map::next(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
@@ -0,0 +1,47 @@
+//! Paged menus displayed to the clients, through the `Menu` host module
+
+pub use crate::sys::menu::{MENU_EXIT, MENU_INTERRUPTED, MENU_TIMEOUT};
+use crate::{sys, CStr, FuncRef};
+
+/// Handle to a menu
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Menu(i32);
+
+/// Callback invoked with the menu, the client and the index of the selected
+/// item, or one of `MENU_EXIT`, `MENU_TIMEOUT` or `MENU_INTERRUPTED`
+pub type MenuCallback = extern "C" fn(Menu, i32, i32);
+
+impl Menu {
+    pub fn create(title: &CStr, callback: MenuCallback) -> Option<Menu> {
+        let handle = unsafe {
+            let callback = FuncRef::from_address(callback as usize);
+            sys::menu::create(title.as_ptr(), callback)
+        };
+
+        if handle > 0 {
+            Some(Menu(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Append an item to the menu, returning its index
+    pub fn add_item(self, text: &CStr) -> Option<usize> {
+        match unsafe { sys::menu::add_item(self.0, text.as_ptr()) } {
+            -1 => None,
+            index => Some(index as usize),
+        }
+    }
+
+    /// Display the menu to the client at `client` for `time`
+    /// seconds, or until it is closed if `time` is 0
+    pub fn display(self, client: i32, time: u32) -> bool {
+        unsafe { sys::menu::display(self.0, client, time as i32) != 0 }
+    }
+
+    /// Destroy the menu, closing it for the clients it is displayed to
+    pub fn destroy(self) -> bool {
+        unsafe { sys::menu::destroy(self.0) != 0 }
+    }
+}
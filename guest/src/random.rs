@@ -0,0 +1,17 @@
+//! Random number generator of the module, through the `Random` host module
+
+use crate::sys;
+
+/// A random integer between `min` and `max`, both inclusive
+pub fn int(min: i32, max: i32) -> i32 {
+    unsafe { sys::random::i32(min, max) }
+}
+
+/// A random float in the range [0, 1)
+pub fn float() -> f32 {
+    unsafe { sys::random::f32() }
+}
+
+pub fn seed(seed: i64) {
+    unsafe { sys::random::seed(seed) }
+}
@@ -0,0 +1,105 @@
+//! Information about the server, through the `Server` host module
+//!
+//! The string values are copied to the given buffer truncated to its size,
+//! and their full length is returned, or None if the value is unknown
+
+use crate::{buffer_len, length, sys, FuncRef};
+
+/// Name of the server, the `hostname` console variable
+pub fn hostname(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::server::hostname(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// Name of the current map, unknown until the first map is loaded
+pub fn map(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::server::map(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// IP address the server is bound to, the `ip` console variable,
+/// unknown for a server bound to all the network interfaces
+pub fn address(buffer: &mut [u8]) -> Option<usize> {
+    length(unsafe { sys::server::address(buffer.as_mut_ptr(), buffer_len(buffer)) })
+}
+
+/// Port the server listens on, the `hostport` console variable
+pub fn port() -> u16 {
+    unsafe { sys::server::port() as u16 }
+}
+
+/// Number of human players connected to the server
+pub fn player_count() -> i32 {
+    unsafe { sys::server::player_count() }
+}
+
+/// Number of bots in the server
+pub fn bot_count() -> i32 {
+    unsafe { sys::server::bot_count() }
+}
+
+/// Duration of a tick of the simulation in seconds
+pub fn tick_interval() -> f32 {
+    unsafe { sys::server::tick_interval() }
+}
+
+/// Feature depending on an interface of the engine, which may only become
+/// available after the module is loaded depending on the load order of the
+/// plugins of the server
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Capability {
+    GameEvents,
+    Trace,
+    Sound,
+    Effects,
+    Bots,
+    Tools,
+    Entities,
+    Globals,
+}
+
+impl Capability {
+    const ALL: [Capability; 8] = [
+        Capability::GameEvents,
+        Capability::Trace,
+        Capability::Sound,
+        Capability::Effects,
+        Capability::Bots,
+        Capability::Tools,
+        Capability::Entities,
+        Capability::Globals,
+    ];
+
+    fn as_raw(self) -> i32 {
+        match self {
+            Capability::GameEvents => sys::server::CAPABILITY_GAME_EVENTS,
+            Capability::Trace => sys::server::CAPABILITY_TRACE,
+            Capability::Sound => sys::server::CAPABILITY_SOUND,
+            Capability::Effects => sys::server::CAPABILITY_EFFECTS,
+            Capability::Bots => sys::server::CAPABILITY_BOTS,
+            Capability::Tools => sys::server::CAPABILITY_TOOLS,
+            Capability::Entities => sys::server::CAPABILITY_ENTITIES,
+            Capability::Globals => sys::server::CAPABILITY_GLOBALS,
+        }
+    }
+
+    /// Capability passed to a `CapabilityCallback`
+    pub fn from_raw(capability: i32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|candidate| candidate.as_raw() == capability)
+    }
+}
+
+/// Callback invoked with a capability that became available,
+/// converted with `Capability::from_raw`
+pub type CapabilityCallback = extern "C" fn(capability: i32);
+
+/// Whether the host functions depending on `capability` can be used
+pub fn has_capability(capability: Capability) -> bool {
+    unsafe { sys::server::has_capability(capability.as_raw()) != 0 }
+}
+
+/// Call `callback` every time a capability that was missing becomes available
+pub fn on_capability(callback: CapabilityCallback) -> bool {
+    unsafe { sys::server::on_capability(FuncRef::from_address(callback as usize)) != 0 }
+}
@@ -0,0 +1,77 @@
+//! Values shared between the modules, through the `Shared` host module
+
+use crate::{buffer_len, length, sys, CStr, ExternRef};
+
+/// Handle to a namespace of the shared store, closed when dropped
+#[derive(Debug)]
+pub struct Namespace(ExternRef);
+
+impl Namespace {
+    pub fn open(name: &CStr) -> Option<Namespace> {
+        let namespace = unsafe { sys::shared::open(name.as_ptr()) };
+        if namespace.is_null() {
+            None
+        } else {
+            Some(Namespace(namespace))
+        }
+    }
+
+    /// Copy the value of `key` to `buffer`, truncated to its size, returning
+    /// the full length of the value or None if the key is absent
+    pub fn get(&self, key: &CStr, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::shared::get(
+                self.0,
+                key.as_ptr(),
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+
+    pub fn set(&self, key: &CStr, value: &[u8]) -> bool {
+        unsafe { sys::shared::set(self.0, key.as_ptr(), value.as_ptr(), buffer_len(value)) != 0 }
+    }
+
+    /// Returns false if the key was absent
+    pub fn delete(&self, key: &CStr) -> bool {
+        unsafe { sys::shared::delete(self.0, key.as_ptr()) != 0 }
+    }
+
+    /// Replace the value of `key` with `value` only if it is currently
+    /// `expected`, None expecting the key to be absent. Returns None on
+    /// invalid arguments and Some(false) if the value didn't match
+    pub fn compare_and_swap(
+        &self,
+        key: &CStr,
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Option<bool> {
+        let (expected, expected_len) = match expected {
+            Some(expected) => (expected.as_ptr(), buffer_len(expected)),
+            None => (core::ptr::null(), -1),
+        };
+
+        let result = unsafe {
+            sys::shared::compare_and_swap(
+                self.0,
+                key.as_ptr(),
+                expected,
+                expected_len,
+                value.as_ptr(),
+                buffer_len(value),
+            )
+        };
+
+        match result {
+            -1 => None,
+            result => Some(result != 0),
+        }
+    }
+}
+
+impl Drop for Namespace {
+    fn drop(&mut self) {
+        unsafe { sys::shared::close(self.0) }
+    }
+}
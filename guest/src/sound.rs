@@ -0,0 +1,14 @@
+//! Sounds played on the clients, through the `Sound` host module
+
+use crate::{sys, CStr};
+
+/// Precache `sample` so it can be played with `emit`
+pub fn precache(sample: &CStr) -> bool {
+    unsafe { sys::sound::precache(sample.as_ptr()) != 0 }
+}
+
+/// Play `sample` on `client`, client 0 plays the sound
+/// from the world for all the clients
+pub fn emit(client: i32, sample: &CStr, volume: f32, pitch: i32) -> bool {
+    unsafe { sys::sound::emit(client, sample.as_ptr(), volume, pitch) != 0 }
+}
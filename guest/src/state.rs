@@ -0,0 +1,54 @@
+//! State of the module kept across map changes, through the `State` host module
+//!
+//! The addon asks the module for its state when the level shuts down and
+//! feeds it back once the next level is loaded, the state is kept in memory
+//! by the addon so it doesn't survive a restart of the server
+
+use crate::{buffer_len, length, sys, ExternRef, FuncRef};
+
+/// Buffer holding the state of the module, only valid for the duration of the callback
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug)]
+pub struct StateBuffer(ExternRef);
+
+/// Callback invoked with the buffer the state is written to or read from
+pub type StateCallback = extern "C" fn(StateBuffer);
+
+/// Call `callback` when the level shuts down so the module can write its state
+pub fn on_save(callback: StateCallback) -> bool {
+    unsafe { sys::state::on_save(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+/// Call `callback` after the next level is loaded with the state written on
+/// the last level shutdown, the callback isn't called if nothing was saved
+pub fn on_restore(callback: StateCallback) -> bool {
+    unsafe { sys::state::on_restore(FuncRef::from_address(callback as usize)) != 0 }
+}
+
+impl StateBuffer {
+    /// Append `data` to the saved state, fails if the state grows too large
+    pub fn write(self, data: &[u8]) -> bool {
+        unsafe { sys::state::write(self.0, data.as_ptr(), buffer_len(data)) != 0 }
+    }
+
+    /// Copy the saved state from `offset` to `buffer`, truncated to its size,
+    /// returning the number of bytes copied or None if `offset` is past the end
+    pub fn read(self, offset: usize, buffer: &mut [u8]) -> Option<usize> {
+        length(unsafe {
+            sys::state::read(
+                self.0,
+                offset as i32,
+                buffer.as_mut_ptr(),
+                buffer_len(buffer),
+            )
+        })
+    }
+
+    pub fn len(self) -> usize {
+        unsafe { sys::state::size(self.0) as usize }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,26 @@
+//! Clients designated by target patterns, through the `Target` host module
+
+pub use crate::sys::target::{
+    TARGET_AMBIGUOUS, TARGET_FILTERED, TARGET_FILTER_ALIVE, TARGET_FILTER_DEAD,
+    TARGET_FILTER_NO_BOTS, TARGET_FILTER_NO_MULTI, TARGET_MULTIPLE, TARGET_NO_MATCH,
+};
+use crate::{sys, CStr};
+
+/// Resolve `pattern` to the entity indices of the matching clients into
+/// `clients`, truncated to its size, returning the number of targets or
+/// one of the negative `TARGET_*` errors
+///
+/// The patterns follow the SourceMod targeting rules: `@all`, `@bots`,
+/// `@humans`, `@alive`, `@dead`, `@me`, `@!me`, `#<userid>`, a SteamID,
+/// `#<exact name>` or a part of a name. `issuer` is the client using the
+/// pattern, or 0 for the server console, and `flags` a combination of the
+/// `TARGET_FILTER_*` flags
+pub fn resolve(issuer: i32, pattern: &CStr, flags: i32, clients: &mut [i32]) -> Result<usize, i32> {
+    let len = clients.len().min(i32::MAX as usize) as i32;
+    match unsafe {
+        sys::target::resolve(issuer, pattern.as_ptr(), flags, clients.as_mut_ptr(), len)
+    } {
+        count if count >= 0 => Ok(count as usize),
+        err => Err(err),
+    }
+}
@@ -0,0 +1,36 @@
+//! Timers run on the game thread, through the `Timer` host module
+
+use crate::{sys, FuncRef};
+
+/// Handle to a timer
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timer(i32);
+
+/// Callback invoked with the handle of the timer once it elapses
+pub type TimerCallback = extern "C" fn(Timer);
+
+impl Timer {
+    /// Call `callback` after `delay` milliseconds, then every
+    /// `delay` milliseconds until cancelled if `repeat` is set
+    ///
+    /// The delay is measured in game time, rounded up to whole server
+    /// ticks, so the timers don't run while the game is paused
+    pub fn create(delay: u32, repeat: bool, callback: TimerCallback) -> Option<Timer> {
+        let handle = unsafe {
+            let callback = FuncRef::from_address(callback as usize);
+            sys::timer::create(delay as i32, repeat as i32, callback)
+        };
+
+        if handle > 0 {
+            Some(Timer(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Returns false if the timer already elapsed or was cancelled
+    pub fn cancel(self) -> bool {
+        unsafe { sys::timer::cancel(self.0) != 0 }
+    }
+}
@@ -0,0 +1,35 @@
+//! Rays and hulls traced through the world, through the `Trace` host module
+
+pub use crate::sys::trace::{MASK_ALL, MASK_PLAYERSOLID, MASK_SHOT, MASK_SOLID, MASK_VISIBLE};
+use crate::{sys, TraceResult, Vector};
+
+/// Trace a ray from `start` to `end` hitting the contents in `mask`,
+/// ignoring the entity at index `ignore` (-1 to ignore none)
+pub fn line(start: &Vector, end: &Vector, mask: i32, ignore: i32) -> Option<TraceResult> {
+    let mut result = TraceResult::default();
+    match unsafe { sys::trace::line(start, end, mask, ignore, &mut result) } {
+        0 => None,
+        _ => Some(result),
+    }
+}
+
+/// Trace a box with the bounds `mins` and `maxs` from `start` to `end`
+pub fn hull(
+    start: &Vector,
+    end: &Vector,
+    mins: &Vector,
+    maxs: &Vector,
+    mask: i32,
+    ignore: i32,
+) -> Option<TraceResult> {
+    let mut result = TraceResult::default();
+    match unsafe { sys::trace::hull(start, end, mins, maxs, mask, ignore, &mut result) } {
+        0 => None,
+        _ => Some(result),
+    }
+}
+
+/// Contents of the world at `position`, filtered by `mask`
+pub fn point_contents(position: &Vector, mask: i32) -> i32 {
+    unsafe { sys::trace::point_contents(position, mask) }
+}
@@ -0,0 +1,54 @@
+//! Messages sent to the clients, through the `UserMessage` host module
+//!
+//! Client 0 sends the message to all the clients
+
+pub use crate::sys::user_message::{MOTD_TEXT, MOTD_URL};
+use crate::{bitbuf::BitBuffer, sys, CStr};
+
+pub fn chat(client: i32, text: &CStr) -> bool {
+    unsafe { sys::user_message::chat(client, text.as_ptr()) != 0 }
+}
+
+pub fn hint_text(client: i32, text: &CStr) -> bool {
+    unsafe { sys::user_message::hint_text(client, text.as_ptr()) != 0 }
+}
+
+/// Display `text` on one of the 6 text channels of the HUD for `hold_time`
+/// seconds, `x` and `y` are in the range [0, 1] or -1 to center the text,
+/// `color` is packed as 0xRRGGBBAA
+pub fn hud_text(
+    client: i32,
+    channel: i32,
+    x: f32,
+    y: f32,
+    color: u32,
+    hold_time: f32,
+    text: &CStr,
+) -> bool {
+    unsafe {
+        sys::user_message::hud_text(
+            client,
+            channel,
+            x,
+            y,
+            color as i32,
+            hold_time,
+            text.as_ptr(),
+        ) != 0
+    }
+}
+
+/// Open the MOTD panel titled `title`, `content` being a text or HTML
+/// document for `MOTD_TEXT` or a URL loaded by the client for `MOTD_URL`
+pub fn motd(client: i32, title: &CStr, content: &CStr, kind: i32) -> bool {
+    unsafe { sys::user_message::motd(client, title.as_ptr(), content.as_ptr(), kind) != 0 }
+}
+
+/// Send the user message `name` with the content written in `buffer`
+pub fn send(client: i32, name: &CStr, buffer: &BitBuffer) -> bool {
+    unsafe { sys::user_message::send(client, name.as_ptr(), buffer.as_extern()) != 0 }
+}
+
+pub fn shake(client: i32, amplitude: f32, frequency: f32, duration: f32) -> bool {
+    unsafe { sys::user_message::shake(client, amplitude, frequency, duration) != 0 }
+}
@@ -0,0 +1,64 @@
+//! Votes of the clients on a question, through the `Vote` host module
+
+pub use crate::sys::vote::{
+    VOTE_CANCELLED, VOTE_NO_VOTES, VOTE_TIED, VOTE_TIE_FIRST, VOTE_TIE_NONE, VOTE_TIE_RANDOM,
+};
+use crate::{buffer_len, sys, CStr, FuncRef};
+
+/// Handle to a vote
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Vote(i32);
+
+/// Callback invoked with the vote, the index of the winning option or one
+/// of `VOTE_TIED`, `VOTE_NO_VOTES` or `VOTE_CANCELLED`, the number of votes
+/// for the winning option and the total number of votes
+pub type VoteCallback = extern "C" fn(Vote, i32, i32, i32);
+
+impl Vote {
+    /// Start a vote on `question` lasting `duration` seconds, a single vote
+    /// runs at a time on the server
+    ///
+    /// `options` is a sequence of 2 to 7 null-terminated strings, like
+    /// `b"yes\0no\0"`, `voters` the clients allowed to vote or all the
+    /// connected clients if empty, and `tie_policy` one of `VOTE_TIE_NONE`,
+    /// `VOTE_TIE_FIRST` or `VOTE_TIE_RANDOM`
+    pub fn start(
+        question: &CStr,
+        options: &[u8],
+        duration: u32,
+        voters: &[i32],
+        tie_policy: i32,
+        callback: VoteCallback,
+    ) -> Option<Vote> {
+        let handle = unsafe {
+            let callback = FuncRef::from_address(callback as usize);
+            sys::vote::start(
+                question.as_ptr(),
+                options.as_ptr(),
+                buffer_len(options),
+                duration as i32,
+                voters.as_ptr(),
+                voters.len() as i32,
+                tie_policy,
+                callback,
+            )
+        };
+
+        if handle > 0 {
+            Some(Vote(handle))
+        } else {
+            None
+        }
+    }
+
+    /// Cancel the vote, its callback is called with `VOTE_CANCELLED`
+    pub fn cancel(self) -> bool {
+        unsafe { sys::vote::cancel(self.0) != 0 }
+    }
+
+    /// Whether a vote started by any module is in progress
+    pub fn in_progress() -> bool {
+        unsafe { sys::vote::in_progress() != 0 }
+    }
+}
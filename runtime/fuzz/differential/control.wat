@@ -0,0 +1,50 @@
+;; Control flow, locals, globals and direct calls
+(module
+    (type $binary (func (param i32 i32) (result i32)))
+
+    (global $counter (mut i32) (i32.const 0))
+    (global $total (mut i64) (i64.const 0))
+
+    (func $add (type $binary) (i32.add (local.get 0) (local.get 1)))
+    (func $sub (type $binary) (i32.sub (local.get 0) (local.get 1)))
+    (func $mul (type $binary) (i32.mul (local.get 0) (local.get 1)))
+    (func $min (type $binary)
+        (select (local.get 0) (local.get 1) (i32.lt_s (local.get 0) (local.get 1))))
+
+    ;; Sum of a bounded sequence, the loop runs at most 255 times
+    (func (export "loop_sum") (param i32 i32) (result i64)
+        (local $count i32)
+        (local $sum i64)
+        (local.set $count (i32.and (local.get 0) (i32.const 0xff)))
+        (block $done
+            (loop $next
+                (br_if $done (i32.eqz (local.get $count)))
+                (local.set $sum
+                    (i64.add
+                        (i64.mul (local.get $sum) (i64.const 31))
+                        (i64.extend_i32_u (i32.mul (local.get $count) (local.get 1)))))
+                (local.set $count (i32.sub (local.get $count) (i32.const 1)))
+                (br $next)))
+        (local.get $sum))
+
+    (func (export "branch_table") (param i32) (result i32)
+        (block $c
+            (block $b
+                (block $a
+                    (br_table $a $b $c (local.get 0)))
+                (return (i32.const 10)))
+            (return (i32.const 20)))
+        (i32.const 30))
+
+    (func (export "nested_if") (param i32 i32) (result i32)
+        (if (result i32) (i32.gt_s (local.get 0) (local.get 1))
+            (then
+                (if (result i32) (i32.and (local.get 0) (i32.const 1))
+                    (then (call $sub (local.get 0) (local.get 1)))
+                    (else (call $mul (local.get 0) (local.get 1)))))
+            (else (call $min (call $add (local.get 0) (local.get 1)) (local.get 1)))))
+
+    (func (export "globals") (param i64) (result i64)
+        (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+        (global.set $total (i64.add (global.get $total) (local.get 0)))
+        (i64.xor (global.get $total) (i64.extend_i32_u (global.get $counter)))))
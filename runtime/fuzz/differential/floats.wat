@@ -0,0 +1,39 @@
+;; Floating point arithmetic and conversions, the NaN values produced
+;; by both engines may have different bits and are compared as equal
+(module
+    (func (export "f32_arith") (param f32 f32) (result f32)
+        (f32.div
+            (f32.add (local.get 0) (local.get 1))
+            (f32.sub (local.get 0) (f32.mul (local.get 1) (f32.const 0.5)))))
+
+    (func (export "f32_round") (param f32 f32) (result f32)
+        (f32.copysign
+            (f32.add (f32.nearest (local.get 0)) (f32.trunc (local.get 1)))
+            (f32.min (local.get 0) (local.get 1))))
+
+    (func (export "f64_arith") (param f64 f64) (result f64)
+        (f64.sqrt
+            (f64.add (f64.mul (local.get 0) (local.get 0)) (f64.mul (local.get 1) (local.get 1)))))
+
+    (func (export "f64_round") (param f64 f64) (result f64)
+        (f64.max
+            (f64.floor (local.get 0))
+            (f64.neg (f64.ceil (f64.abs (local.get 1))))))
+
+    (func (export "f64_cmp") (param f64 f64) (result i32)
+        (i32.add
+            (f64.lt (local.get 0) (local.get 1))
+            (i32.shl (f64.ne (local.get 0) (local.get 1)) (i32.const 1))))
+
+    (func (export "convert") (param i64) (result f64)
+        (f64.add
+            (f64.convert_i64_s (local.get 0))
+            (f64.promote_f32 (f32.convert_i32_u (i32.wrap_i64 (local.get 0))))))
+
+    (func (export "reinterpret") (param f64) (result i64)
+        (i64.xor
+            (i64.reinterpret_f64 (local.get 0))
+            (i64.extend_i32_u (i32.reinterpret_f32 (f32.demote_f64 (local.get 0))))))
+
+    (func (export "truncate") (param f64) (result i32)
+        (i32.trunc_f64_s (local.get 0))))
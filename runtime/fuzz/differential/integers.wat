@@ -0,0 +1,43 @@
+;; Integer arithmetic, comparisons and conversions
+(module
+    (func (export "i32_arith") (param i32 i32) (result i32)
+        (i32.add
+            (i32.mul (local.get 0) (local.get 1))
+            (i32.sub (local.get 1) (local.get 0))))
+
+    (func (export "i32_bits") (param i32 i32) (result i32)
+        (i32.xor
+            (i32.rotl (local.get 0) (local.get 1))
+            (i32.or
+                (i32.shr_s (local.get 0) (local.get 1))
+                (i32.and (i32.clz (local.get 1)) (i32.popcnt (local.get 0))))))
+
+    (func (export "i32_div_s") (param i32 i32) (result i32)
+        (i32.div_s (local.get 0) (local.get 1)))
+
+    (func (export "i32_rem_u") (param i32 i32) (result i32)
+        (i32.rem_u (local.get 0) (local.get 1)))
+
+    (func (export "i32_cmp") (param i32 i32 i32) (result i32)
+        (select
+            (i32.lt_s (local.get 0) (local.get 1))
+            (i32.ge_u (local.get 0) (local.get 1))
+            (local.get 2)))
+
+    (func (export "i64_arith") (param i64 i64) (result i64)
+        (i64.sub
+            (i64.mul (local.get 0) (local.get 1))
+            (i64.shl (local.get 1) (local.get 0))))
+
+    (func (export "i64_bits") (param i64 i64) (result i64)
+        (i64.or
+            (i64.rotr (local.get 0) (local.get 1))
+            (i64.add (i64.ctz (local.get 1)) (i64.shr_u (local.get 0) (local.get 1)))))
+
+    (func (export "i64_div_u") (param i64 i64) (result i64)
+        (i64.div_u (local.get 0) (local.get 1)))
+
+    (func (export "wrap_extend") (param i64) (result i64)
+        (i64.add
+            (i64.extend_i32_s (i32.wrap_i64 (local.get 0)))
+            (i64.extend_i32_u (i32.wrap_i64 (i64.shr_u (local.get 0) (i64.const 32)))))))
@@ -0,0 +1,41 @@
+;; Loads and stores of all widths, the memory of both
+;; engines is compared after every call
+(module
+    (memory (export "memory") 1 4)
+    (data (i32.const 16) "\01\02\03\04\05\06\07\08\f0\f1\f2\f3\f4\f5\f6\f7")
+
+    (func (export "store_i32") (param i32 i32)
+        (i32.store (i32.and (local.get 0) (i32.const 0xfff)) (local.get 1)))
+
+    (func (export "store_narrow") (param i32 i64)
+        (i64.store8 (local.get 0) (local.get 1))
+        (i64.store16 offset=2 (i32.and (local.get 0) (i32.const 0xff)) (local.get 1))
+        (i64.store32 offset=4 (i32.and (local.get 0) (i32.const 0xff)) (local.get 1)))
+
+    (func (export "store_float") (param i32 f64)
+        (f64.store offset=8 (i32.and (local.get 0) (i32.const 0xfff)) (local.get 1))
+        (f32.store (i32.and (local.get 0) (i32.const 0xfff)) (f32.demote_f64 (local.get 1))))
+
+    (func (export "load_i32") (param i32) (result i32)
+        (i32.add
+            (i32.load (i32.and (local.get 0) (i32.const 0xfff)))
+            (i32.load8_s offset=1 (i32.and (local.get 0) (i32.const 0xfff)))))
+
+    (func (export "load_narrow") (param i32) (result i64)
+        (i64.add
+            (i64.load16_u (local.get 0))
+            (i64.load32_s offset=3 (i32.and (local.get 0) (i32.const 0xff)))))
+
+    (func (export "load_i64") (param i32) (result i64)
+        (i64.load offset=5 (local.get 0)))
+
+    (func (export "size") (result i32)
+        (memory.size))
+
+    (func (export "grow") (param i32) (result i32)
+        (memory.grow (i32.and (local.get 0) (i32.const 3))))
+
+    ;; Traps until the memory was grown, the base of the memory
+    ;; must be loaded again after it was moved by `memory.grow`
+    (func (export "store_grown") (param i32 i32)
+        (i32.store offset=0x10000 (i32.and (local.get 0) (i32.const 0xfff)) (local.get 1))))
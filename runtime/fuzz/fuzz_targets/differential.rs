@@ -0,0 +1,296 @@
+#![no_main]
+//! Differential testing of the cranelift backend against wasmi
+//!
+//! The modules in `differential` are loaded in both engines, then the
+//! exported functions picked by the input are called in sequence with the
+//! same arguments, and the results and the linear memories are compared
+//! after every call. A call is only made to the runtime once it returned
+//! normally in wasmi: the code emitted by the runtime doesn't recover from
+//! traps, and the input stops at the first call trapping in the interpreter
+//!
+//! The modules are picked so their functions always terminate, random
+//! modules generated by wasm-smith may loop forever in both engines
+
+use fabric_runtime::{with_abi, Environment, Function, GlobalValue, VMContext};
+use libfuzzer_sys::fuzz_target;
+use wasmi::{ImportsBuilder, ModuleInstance, ModuleRef, NopExternals, RuntimeValue};
+use wasmparser::{ExternalKind, Parser, Payload};
+
+const MODULES: &[&str] = &[
+    include_str!("../differential/integers.wat"),
+    include_str!("../differential/floats.wat"),
+    include_str!("../differential/memory.wat"),
+    include_str!("../differential/control.wat"),
+];
+
+/// Host environment of the modules, without any import and without
+/// fuel metering since wasmi would run the functions to completion
+struct DiffEnvironment;
+
+impl Environment for DiffEnvironment {
+    fn import_function(&mut self, _module: &str, _name: &str) -> Option<Function> {
+        None
+    }
+
+    fn import_global(&mut self, _module: &str, _name: &str) -> Option<GlobalValue> {
+        None
+    }
+
+    fn deterministic(&self) -> bool {
+        true
+    }
+}
+
+type Context = VMContext<DiffEnvironment>;
+
+/// Parameter of an exported function, built from the raw bits of the input
+trait Param: Copy {
+    fn from_bits(bits: u64) -> Self;
+    fn to_wasmi(self) -> RuntimeValue;
+}
+
+impl Param for i32 {
+    fn from_bits(bits: u64) -> Self {
+        bits as i32
+    }
+
+    fn to_wasmi(self) -> RuntimeValue {
+        RuntimeValue::I32(self)
+    }
+}
+
+impl Param for i64 {
+    fn from_bits(bits: u64) -> Self {
+        bits as i64
+    }
+
+    fn to_wasmi(self) -> RuntimeValue {
+        RuntimeValue::I64(self)
+    }
+}
+
+impl Param for f32 {
+    fn from_bits(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+
+    fn to_wasmi(self) -> RuntimeValue {
+        RuntimeValue::decode_f32(self.to_bits())
+    }
+}
+
+impl Param for f64 {
+    fn from_bits(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+
+    fn to_wasmi(self) -> RuntimeValue {
+        RuntimeValue::decode_f64(self.to_bits())
+    }
+}
+
+/// Result of an exported function, compared with the result of wasmi
+trait Results: Sized + std::fmt::Debug {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self>;
+    fn same(&self, other: &Self) -> bool;
+}
+
+impl Results for () {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self> {
+        match value {
+            None => Some(()),
+            Some(_) => None,
+        }
+    }
+
+    fn same(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Results for i32 {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self> {
+        match value {
+            Some(RuntimeValue::I32(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Results for i64 {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self> {
+        match value {
+            Some(RuntimeValue::I64(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+// The NaN values are canonicalized by the runtime, but not by
+// wasmi: any two NaN values are considered the same result
+impl Results for f32 {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self> {
+        match value {
+            Some(RuntimeValue::F32(value)) => Some(value.to_float()),
+            _ => None,
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        (self.is_nan() && other.is_nan()) || self.to_bits() == other.to_bits()
+    }
+}
+
+impl Results for f64 {
+    fn from_wasmi(value: Option<RuntimeValue>) -> Option<Self> {
+        match value {
+            Some(RuntimeValue::F64(value)) => Some(value.to_float()),
+            _ => None,
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        (self.is_nan() && other.is_nan()) || self.to_bits() == other.to_bits()
+    }
+}
+
+/// Call the export `name` in both engines, returns false if it trapped in
+/// wasmi or if its signature is not supported by the harness
+fn call(context: &mut Context, reference: &ModuleRef, name: &str, bits: &[u64; 3]) -> bool {
+    let function = match context.export(name) {
+        Some(function) => function.clone(),
+        None => panic!("{} is not exported by the runtime", name),
+    };
+
+    macro_rules! signatures {
+        ( $( ( $( $param:ident ),* ) -> $result:ty; )* ) => {
+            $({
+                type Native = with_abi!(fn(*mut Context, $( $param ),*) -> $result);
+                if let Ok(func) = function.try_get::<Native>() {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut args = bits.iter();
+                    let values = [ $( <$param as Param>::from_bits(*args.next().unwrap()).to_wasmi() ),* ];
+
+                    let expected = match reference.invoke_export(name, &values, &mut NopExternals) {
+                        Ok(value) => match <$result as Results>::from_wasmi(value) {
+                            Some(value) => value,
+                            None => panic!("{} returned {:?} in wasmi", name, value),
+                        },
+                        Err(_) => return false,
+                    };
+
+                    #[allow(unused_mut, unused_variables)]
+                    let mut args = bits.iter();
+                    let result = func(context, $( <$param as Param>::from_bits(*args.next().unwrap()) ),*);
+
+                    assert!(
+                        result.same(&expected),
+                        "{}{:?} returned {:?}, expected {:?}",
+                        name,
+                        &values[..],
+                        result,
+                        expected
+                    );
+
+                    return true;
+                }
+            })*
+        };
+    }
+
+    signatures! {
+        () -> i32;
+        (i32) -> i32;
+        (i32) -> i64;
+        (i32, i32) -> ();
+        (i32, i64) -> ();
+        (i32, f64) -> ();
+        (i32, i32) -> i32;
+        (i32, i32) -> i64;
+        (i32, i32, i32) -> i32;
+        (i64) -> i64;
+        (i64) -> f64;
+        (i64, i64) -> i64;
+        (f32, f32) -> f32;
+        (f64) -> i32;
+        (f64) -> i64;
+        (f64, f64) -> i32;
+        (f64, f64) -> f64;
+    }
+
+    false
+}
+
+/// Compare the linear memories of both engines, up to the size of the memory in wasmi
+fn compare_memory(context: &Context, reference: &ModuleRef, name: &str) {
+    let memory = match reference.export_by_name("memory") {
+        Some(export) => match export.as_memory() {
+            Some(memory) => memory.clone(),
+            None => return,
+        },
+        None => return,
+    };
+
+    memory.with_direct_access(|expected| {
+        let actual = context
+            .memory
+            .bytes(0, expected.len())
+            .expect("the memory of the runtime is smaller than in wasmi");
+
+        if let Some(offset) = (0..expected.len()).find(|&index| actual[index] != expected[index]) {
+            panic!(
+                "memory differs at {} after {}: {:#04x}, expected {:#04x}",
+                offset, name, actual[offset], expected[offset]
+            );
+        }
+    });
+}
+
+/// Names of the functions exported by a module
+fn function_exports(bytes: &[u8]) -> Vec<String> {
+    let mut exports = Vec::new();
+
+    for payload in Parser::new(0).parse_all(bytes) {
+        if let Payload::ExportSection(reader) = payload.unwrap() {
+            for export in reader {
+                let export = export.unwrap();
+                if export.kind == ExternalKind::Function {
+                    exports.push(export.field.to_string());
+                }
+            }
+        }
+    }
+
+    exports
+}
+
+fuzz_target!(|input: (u8, Vec<(u8, [u64; 3])>)| {
+    let (module, calls) = input;
+    let bytes = wat::parse_str(MODULES[module as usize % MODULES.len()]).unwrap();
+    let exports = function_exports(&bytes);
+
+    let reference = wasmi::Module::from_buffer(&bytes).unwrap();
+    let reference = ModuleInstance::new(&reference, &ImportsBuilder::default())
+        .unwrap()
+        .assert_no_start();
+
+    let mut context = fabric_runtime::load_module_bytes(DiffEnvironment, &bytes)
+        .expect("the module was rejected by the runtime");
+
+    for (export, bits) in &calls {
+        let name = &exports[*export as usize % exports.len()];
+        if !call(&mut context, &reference, name, bits) {
+            break;
+        }
+
+        compare_memory(&context, &reference, name);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use fabric_runtime::NullEnvironment;
+use libfuzzer_sys::fuzz_target;
+
+// Modules that pass validation must be compiled or rejected without panicking
+fuzz_target!(|data: &[u8]| {
+    let is_valid = fabric_runtime::validate_module(data);
+    let module = fabric_runtime::load_module_bytes(NullEnvironment, data);
+    assert!(is_valid || module.is_none());
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use fabric_runtime::NullEnvironment;
+use libfuzzer_sys::fuzz_target;
+
+// Random byte strings rarely make it past the validator,
+// generate structurally valid modules to reach the translator
+fuzz_target!(|module: wasm_smith::Module| {
+    fabric_runtime::load_module_bytes(NullEnvironment, &module.to_bytes());
+});
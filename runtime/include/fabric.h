@@ -0,0 +1,79 @@
+/*
+ * C API of the fabric WASM runtime, built with the `capi` feature of the
+ * `fabric-runtime` crate. See `src/capi.rs` for the documentation of each
+ * function
+ */
+
+#ifndef FABRIC_H
+#define FABRIC_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Calling convention of the host functions imported by the modules */
+#if defined(_WIN64)
+#define FABRIC_ABI __fastcall
+#else
+#define FABRIC_ABI
+#endif
+
+typedef struct FabricModule FabricModule;
+
+typedef enum FabricType {
+    FABRIC_I32 = 0,
+    FABRIC_I64 = 1,
+    FABRIC_F32 = 2,
+    FABRIC_F64 = 3,
+    FABRIC_EXTERNREF = 4,
+    FABRIC_FUNCREF = 5,
+} FabricType;
+
+typedef enum FabricStatus {
+    FABRIC_OK = 0,
+    FABRIC_NOT_FOUND = 1,
+    FABRIC_INVALID_ARGUMENTS = 2,
+    FABRIC_ABORTED = 3,
+    FABRIC_ERROR = 4,
+} FabricStatus;
+
+/*
+ * Host function for an import: `pointer` is declared with FABRIC_ABI and
+ * takes the `void *ctx` of the module before its parameters. The types are
+ * arrays of FabricType codes, there is at most one result
+ */
+typedef struct FabricFunction {
+    const void *pointer;
+    const uint8_t *params;
+    size_t num_params;
+    const uint8_t *results;
+    size_t num_results;
+} FabricFunction;
+
+typedef bool (*FabricImport)(void *user_data, const char *module, const char *name,
+                             FabricFunction *function);
+
+const char *fabric_last_error(void);
+
+FabricModule *fabric_load_module(const uint8_t *source, size_t len, FabricImport import,
+                                 void *user_data);
+
+FabricStatus fabric_call(FabricModule *module, const char *name, const uint64_t *args,
+                         size_t num_args, uint64_t *results, size_t num_results);
+
+uint8_t *fabric_memory(FabricModule *module, size_t *len);
+
+void *fabric_context_user_data(void *ctx);
+uint8_t *fabric_context_memory(void *ctx, size_t *len);
+
+void fabric_free(FabricModule *module);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FABRIC_H */
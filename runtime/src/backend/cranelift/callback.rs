@@ -0,0 +1,122 @@
+use std::{
+    collections::{hash_map, HashMap},
+    fmt::{self, Debug, Formatter},
+    hash::Hash,
+};
+
+use super::{runtime::VMContext, signature::traits::NativeFunction};
+use crate::FuncRef;
+
+/// A guest function registered by a module as a callback
+///
+/// The callback is resolved from the FuncRef the module passed and its
+/// signature is checked against the native type `T` the host calls it
+/// as, so a module passing the wrong function is rejected on registration
+/// instead of being called with the wrong arguments
+pub struct Callback<T> {
+    function: FuncRef,
+    native: T,
+}
+
+impl<T: NativeFunction + Copy> Callback<T> {
+    /// Resolve `function` in the module of `ctx` as a callback of type `T`,
+    /// returns a description of the error if it doesn't have that signature
+    pub fn resolve<E>(ctx: &VMContext<E>, function: FuncRef) -> Result<Self, String> {
+        let native = ctx.typed_function(function)?;
+        Ok(Callback { function, native })
+    }
+
+    /// Funcref the callback was registered with
+    pub fn function(&self) -> FuncRef {
+        self.function
+    }
+
+    /// Native function pointer of the callback, called with the VMContext of its module
+    pub fn native(&self) -> T {
+        self.native
+    }
+}
+
+impl<T: Copy> Clone for Callback<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Copy> Copy for Callback<T> {}
+
+impl<T> Debug for Callback<T> {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_tuple("Callback").field(&self.function).finish()
+    }
+}
+
+/// Callbacks of type `T` registered by a module, keyed by a handle or a name
+///
+/// Only callbacks resolved with `Callback::resolve` can be registered,
+/// the host gets them back as native functions of the type they were
+/// checked against
+pub struct CallbackRegistry<K, T> {
+    callbacks: HashMap<K, Callback<T>>,
+}
+
+impl<K: Eq + Hash, T: NativeFunction + Copy> CallbackRegistry<K, T> {
+    pub fn new() -> Self {
+        CallbackRegistry {
+            callbacks: HashMap::new(),
+        }
+    }
+
+    /// Register `callback` under `key`, returning the callback it replaces
+    pub fn insert(&mut self, key: K, callback: Callback<T>) -> Option<Callback<T>> {
+        self.callbacks.insert(key, callback)
+    }
+
+    /// Get the native function registered under `key`
+    pub fn get(&self, key: &K) -> Option<T> {
+        self.callbacks.get(key).map(Callback::native)
+    }
+
+    /// Get the callback registered under `key`
+    pub fn callback(&self, key: &K) -> Option<&Callback<T>> {
+        self.callbacks.get(key)
+    }
+
+    /// Unregister the callback registered under `key`
+    pub fn remove(&mut self, key: &K) -> Option<Callback<T>> {
+        self.callbacks.remove(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.callbacks.contains_key(key)
+    }
+
+    /// Iterate over the registered callbacks, in no particular order
+    pub fn iter(&self) -> hash_map::Iter<'_, K, Callback<T>> {
+        self.callbacks.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.callbacks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+}
+
+impl<K: Eq + Hash, T: NativeFunction + Copy> Default for CallbackRegistry<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Debug, T> Debug for CallbackRegistry<K, T> {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_map().entries(self.callbacks.iter()).finish()
+    }
+}
@@ -1,21 +1,261 @@
 use cranelift_codegen::{
     cursor,
-    ir::{self, ExtFuncData, ExternalName, Function, InstBuilder},
+    ir::{
+        self, condcodes::IntCC, immediates::Offset32, AbiParam, ExtFuncData, ExternalName,
+        Function, InstBuilder, MemFlags,
+    },
     isa::TargetFrontendConfig,
 };
+use cranelift_frontend::FunctionBuilderContext;
 use cranelift_wasm::{
-    FuncEnvironment, FuncIndex, FunctionBuilder, GlobalIndex, GlobalVariable, MemoryIndex,
-    SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult,
+    FuncEnvironment, FuncIndex, FuncTranslationState, FunctionBuilder, GlobalIndex, GlobalVariable,
+    MemoryIndex, SignatureIndex, TableIndex, TargetEnvironment, WasmError, WasmResult, WasmType,
 };
+use wasmparser::Operator;
 
 use super::{
-    module::ModuleDefs,
-    signature::{ExternRef, CALL_CONV, POINTER_WIDTH},
+    module::{ModuleDefs, ModuleGlobal},
+    runtime::{
+        Builtins, FUEL_OFFSET, GLOBALS_OFFSET, MEMORY_BASE_OFFSET, MEMORY_SIZE_OFFSET,
+        WASM_PAGE_SIZE,
+    },
+    signature::{
+        reference_type, ExternRef, FuncRef, Signature, CALL_CONV, POINTER_TYPE, POINTER_WIDTH,
+        TABLE_FUNCREF,
+    },
     GlobalValue,
 };
 
 pub(crate) struct FunctionEnv<'module> {
     pub(crate) module: &'module ModuleDefs,
+    /// Signature of the function being translated
+    signature: &'module Signature,
+    builtins: &'module Builtins,
+
+    /// Emit fuel checks in the function
+    metering: bool,
+    /// Number of operators translated since the last fuel check
+    cost: i64,
+    /// The fuel check at the entry of the function has been emitted
+    entry_checked: bool,
+}
+
+impl<'module> FunctionEnv<'module> {
+    pub(crate) fn new(
+        module: &'module ModuleDefs,
+        signature: &'module Signature,
+        builtins: &'module Builtins,
+        metering: bool,
+    ) -> Self {
+        FunctionEnv {
+            module,
+            signature,
+            builtins,
+            metering,
+            cost: 0,
+            entry_checked: false,
+        }
+    }
+
+    /// Consume the fuel for the operators translated since the last check,
+    /// returning zeroes from the function if the fuel ran out
+    ///
+    /// The check is emitted at the entry of the function, at the start of the
+    /// loop bodies and after the calls, such that any long running code has
+    /// to go through a check regularly. This splits the current block and
+    /// leaves the builder at the start of the block where execution continues
+    fn emit_fuel_check(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        let ctx = match builder.func.special_param(ir::ArgumentPurpose::VMContext) {
+            Some(ctx) => ctx,
+            None => return Err(WasmError::User(String::from("missing vmtcx parameter"))),
+        };
+
+        // The cost of a check is accounted for so that
+        // an empty loop still consumes fuel
+        let cost = self.cost + 1;
+        self.cost = 0;
+
+        let flags = MemFlags::trusted();
+        let fuel = builder.ins().load(POINTER_TYPE, flags, ctx, FUEL_OFFSET);
+        let fuel = builder.ins().iadd_imm(fuel, -cost);
+        builder.ins().store(flags, fuel, ctx, FUEL_OFFSET);
+
+        let exhausted = builder.ins().icmp_imm(IntCC::SignedLessThan, fuel, 0);
+
+        let abort = builder.create_block();
+        let next = builder.create_block();
+        builder.ins().brnz(exhausted, abort, &[]);
+        builder.ins().jump(next, &[]);
+
+        builder.switch_to_block(abort);
+        builder.seal_block(abort);
+
+        let values = zero_values(builder, self.signature);
+        builder.ins().return_(&values);
+
+        builder.switch_to_block(next);
+        builder.seal_block(next);
+
+        Ok(())
+    }
+
+    /// Report the execution of an `unreachable` instruction to the
+    /// environment with the `unreachable` builtin and return zeroes
+    ///
+    /// The builder is left in a new block without predecessors, where
+    /// the trap of the instruction is translated as dead code
+    fn emit_unreachable(&mut self, builder: &mut FunctionBuilder) -> WasmResult<()> {
+        let ctx = match builder.func.special_param(ir::ArgumentPurpose::VMContext) {
+            Some(ctx) => ctx,
+            None => return Err(WasmError::User(String::from("missing vmtcx parameter"))),
+        };
+
+        let mut signature = ir::Signature::new(CALL_CONV);
+        signature.params = vec![AbiParam::special(
+            POINTER_TYPE,
+            ir::ArgumentPurpose::VMContext,
+        )];
+        let signature = builder.import_signature(signature);
+
+        let callee = builder
+            .ins()
+            .iconst(POINTER_TYPE, self.builtins.unreachable as i64);
+        builder.ins().call_indirect(signature, callee, &[ctx]);
+
+        let values = zero_values(builder, self.signature);
+        builder.ins().return_(&values);
+
+        let dead = builder.create_block();
+        builder.switch_to_block(dead);
+        builder.seal_block(dead);
+
+        Ok(())
+    }
+}
+
+/// Zero values for the results of a function of signature `signature`
+fn zero_values(builder: &mut FunctionBuilder, signature: &Signature) -> Vec<ir::Value> {
+    let returns: Vec<_> = signature
+        .clif
+        .returns
+        .iter()
+        .zip(signature.wasm.returns.iter())
+        .map(|(clif, wasm)| (clif.value_type, *wasm))
+        .collect();
+
+    returns
+        .into_iter()
+        .map(|(ty, wasm)| match wasm {
+            WasmType::F32 => builder.ins().f32const(0.0),
+            WasmType::F64 => builder.ins().f64const(0.0),
+            WasmType::ExternRef => builder.ins().iconst(ty, ExternRef::null().0 as i64),
+            _ => builder.ins().iconst(ty, 0),
+        })
+        .collect()
+}
+
+/// Build the stub of a function type of the module, returning zeroes. It is
+/// called by `call_indirect` in place of the targets that aren't functions
+/// of this type, once the `call_indirect` builtin reported the abort
+pub(crate) fn build_indirect_stub(
+    func: &mut Function,
+    context: &mut FunctionBuilderContext,
+    signature: &Signature,
+) {
+    let mut builder = FunctionBuilder::new(func, context);
+
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+
+    let values = zero_values(&mut builder, signature);
+    builder.ins().return_(&values);
+    builder.finalize();
+}
+
+/// Build the wrapper of the host function `index` at `pointer` used in place
+/// of the import when the linker of the module has middleware
+///
+/// The arguments are spilled to the stack for the middleware, which is run
+/// by `host_call_enter` and `host_call_exit` around the host function. If the
+/// call is skipped by the middleware the wrapper returns zeroes instead
+pub(crate) fn build_host_call(
+    func: &mut Function,
+    context: &mut FunctionBuilderContext,
+    index: u32,
+    pointer: *const u8,
+    signature: &Signature,
+    builtins: &Builtins,
+) {
+    let mut builder = FunctionBuilder::new(func, context);
+
+    let entry = builder.create_block();
+    let call = builder.create_block();
+    let skip = builder.create_block();
+
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+
+    // The first parameter is the vmctx, followed by the arguments of the call
+    let params = builder.block_params(entry).to_vec();
+    let ctx = params[0];
+
+    let slot = builder.create_stack_slot(ir::StackSlotData::new(
+        ir::StackSlotKind::ExplicitSlot,
+        (params.len() as u32 - 1) * 8,
+    ));
+    for (position, &arg) in params[1..].iter().enumerate() {
+        builder.ins().stack_store(arg, slot, position as i32 * 8);
+    }
+
+    let args = builder.ins().stack_addr(POINTER_TYPE, slot, 0);
+    let index = builder.ins().iconst(ir::types::I32, i64::from(index));
+
+    let mut hook = ir::Signature::new(CALL_CONV);
+    hook.params = vec![
+        AbiParam::special(POINTER_TYPE, ir::ArgumentPurpose::VMContext),
+        AbiParam::new(ir::types::I32),
+        AbiParam::new(POINTER_TYPE),
+    ];
+    let exit = builder.import_signature(hook.clone());
+    hook.returns = vec![AbiParam::new(ir::types::I32)];
+    let enter = builder.import_signature(hook);
+
+    let callee = builder
+        .ins()
+        .iconst(POINTER_TYPE, builtins.host_call_enter as i64);
+    let inst = builder
+        .ins()
+        .call_indirect(enter, callee, &[ctx, index, args]);
+    let proceed = builder.inst_results(inst)[0];
+    builder.ins().brz(proceed, skip, &[]);
+    builder.ins().jump(call, &[]);
+
+    builder.switch_to_block(call);
+    builder.seal_block(call);
+
+    let host = builder.import_signature(signature.clif.clone());
+    let callee = builder.ins().iconst(POINTER_TYPE, pointer as i64);
+    let inst = builder.ins().call_indirect(host, callee, &params);
+    let results = builder.inst_results(inst).to_vec();
+
+    let callee = builder
+        .ins()
+        .iconst(POINTER_TYPE, builtins.host_call_exit as i64);
+    builder
+        .ins()
+        .call_indirect(exit, callee, &[ctx, index, args]);
+    builder.ins().return_(&results);
+
+    builder.switch_to_block(skip);
+    builder.seal_block(skip);
+
+    let values = zero_values(&mut builder, signature);
+    builder.ins().return_(&values);
+
+    builder.finalize();
 }
 
 impl<'module> TargetEnvironment for FunctionEnv<'module> {
@@ -25,35 +265,151 @@ impl<'module> TargetEnvironment for FunctionEnv<'module> {
             pointer_width: POINTER_WIDTH,
         }
     }
+
+    fn reference_type(&self, ty: WasmType) -> ir::Type {
+        reference_type(ty)
+    }
 }
 
 impl<'module> FuncEnvironment for FunctionEnv<'module> {
+    fn before_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if let Operator::Unreachable = op {
+            if state.reachable() {
+                self.emit_unreachable(builder)?;
+            }
+        }
+
+        if !self.metering {
+            return Ok(());
+        }
+
+        if !self.entry_checked {
+            self.entry_checked = true;
+            if state.reachable() {
+                self.emit_fuel_check(builder)?;
+            }
+        }
+
+        self.cost += 1;
+        Ok(())
+    }
+
+    fn after_translate_operator(
+        &mut self,
+        op: &Operator,
+        builder: &mut FunctionBuilder,
+        state: &FuncTranslationState,
+    ) -> WasmResult<()> {
+        if !self.metering || !state.reachable() {
+            return Ok(());
+        }
+
+        match op {
+            Operator::Loop { .. } | Operator::Call { .. } | Operator::CallIndirect { .. } => {
+                self.emit_fuel_check(builder)
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn make_global(
         &mut self,
-        _func: &mut Function,
+        func: &mut Function,
         index: GlobalIndex,
     ) -> WasmResult<GlobalVariable> {
         match self.module.globals[index] {
             // Constants are declared as `Custom` so their value can be
             // defined inline in the emitted IR in `translate_custom_global_get`
-            GlobalValue::Const(_) => Ok(GlobalVariable::Custom),
+            ModuleGlobal::Imported(GlobalValue::Const(_)) => Ok(GlobalVariable::Custom),
+
+            // The globals area is allocated once when the module is loaded,
+            // so the load of its address can be marked as readonly
+            ModuleGlobal::Defined { slot, ty } => {
+                let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+                let gv = func.create_global_value(ir::GlobalValueData::Load {
+                    base: vmctx,
+                    offset: Offset32::new(GLOBALS_OFFSET),
+                    global_type: POINTER_TYPE,
+                    readonly: true,
+                });
+
+                Ok(GlobalVariable::Memory {
+                    gv,
+                    offset: Offset32::new(slot as i32 * 8),
+                    ty,
+                })
+            }
         }
     }
 
-    fn make_heap(&mut self, _func: &mut Function, _index: MemoryIndex) -> WasmResult<ir::Heap> {
-        panic!("make_heap")
+    fn make_heap(&mut self, func: &mut Function, index: MemoryIndex) -> WasmResult<ir::Heap> {
+        if index.as_u32() != 0 {
+            return Err(WasmError::Unsupported(format!(
+                "multiple memories ({:?})",
+                index
+            )));
+        }
+
+        // The base address and size of the linear memory are loaded from the
+        // header of the VMContext, since the memory may be reallocated by the
+        // host when it grows these loads can't be marked as readonly
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        let base = func.create_global_value(ir::GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(MEMORY_BASE_OFFSET),
+            global_type: POINTER_TYPE,
+            readonly: false,
+        });
+        // The bound must have the type of the heap index, only the low half of
+        // the size is read since the memory is smaller than `MAX_MEMORY_SIZE`
+        let bound = func.create_global_value(ir::GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(MEMORY_SIZE_OFFSET),
+            global_type: ir::types::I32,
+            readonly: false,
+        });
+
+        Ok(func.create_heap(ir::HeapData {
+            base,
+            min_size: 0.into(),
+            offset_guard_size: 0.into(),
+            style: ir::HeapStyle::Dynamic { bound_gv: bound },
+            index_type: ir::types::I32,
+        }))
     }
 
-    fn make_table(&mut self, _func: &mut Function, _index: TableIndex) -> WasmResult<ir::Table> {
-        panic!("make_table")
+    fn make_table(&mut self, func: &mut Function, index: TableIndex) -> WasmResult<ir::Table> {
+        if index.as_u32() != 0 {
+            return Err(WasmError::Unsupported(format!(
+                "multiple tables ({:?})",
+                index
+            )));
+        }
+
+        // The table is only accessed by the `call_indirect` builtin,
+        // the emitted code never loads its elements
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        Ok(func.create_table(ir::TableData {
+            base_gv: vmctx,
+            min_size: 0.into(),
+            bound_gv: vmctx,
+            element_size: 0.into(),
+            index_type: POINTER_TYPE,
+        }))
     }
 
     fn make_indirect_sig(
         &mut self,
-        _func: &mut Function,
-        _index: SignatureIndex,
+        func: &mut Function,
+        index: SignatureIndex,
     ) -> WasmResult<ir::SigRef> {
-        panic!("make_indirect_sig")
+        let sig = &self.module.signatures[index].clif;
+        Ok(func.import_signature(sig.clone()))
     }
 
     fn make_direct_func(
@@ -72,34 +428,93 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
 
     fn translate_call_indirect(
         &mut self,
-        _pos: cursor::FuncCursor,
+        mut pos: cursor::FuncCursor,
         _table_index: TableIndex,
         _table: ir::Table,
-        _sig_index: SignatureIndex,
-        _sig_ref: ir::SigRef,
-        _callee: ir::Value,
-        _call_args: &[ir::Value],
+        sig_index: SignatureIndex,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
     ) -> WasmResult<ir::Inst> {
-        panic!("translate_call_indirect")
+        let ctx = match pos.func.special_param(ir::ArgumentPurpose::VMContext) {
+            Some(ctx) => ctx,
+            None => return Err(WasmError::User(String::from("missing vmtcx parameter"))),
+        };
+
+        // The builtin resolves the element of the table and checks its
+        // signature, it returns the stub of the type for an invalid target
+        let mut sig = ir::Signature::new(CALL_CONV);
+        sig.params = vec![
+            AbiParam::special(POINTER_TYPE, ir::ArgumentPurpose::VMContext),
+            AbiParam::new(ir::types::I32),
+            AbiParam::new(ir::types::I32),
+        ];
+        sig.returns = vec![AbiParam::new(POINTER_TYPE)];
+        let sig = pos.func.import_signature(sig);
+
+        let builtin = pos
+            .ins()
+            .iconst(POINTER_TYPE, self.builtins.call_indirect as i64);
+        let ty = pos
+            .ins()
+            .iconst(ir::types::I32, i64::from(sig_index.as_u32()));
+        let call = pos.ins().call_indirect(sig, builtin, &[ctx, callee, ty]);
+        let target = pos.func.dfg.first_result(call);
+
+        let mut args = Vec::with_capacity(call_args.len() + 1);
+        args.push(ctx);
+        args.extend_from_slice(call_args);
+
+        Ok(pos.ins().call_indirect(sig_ref, target, &args))
     }
 
     fn translate_memory_grow(
         &mut self,
-        _pos: cursor::FuncCursor,
+        mut pos: cursor::FuncCursor,
         _index: MemoryIndex,
         _heap: ir::Heap,
-        _val: ir::Value,
+        val: ir::Value,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_memory_grow")
+        let ctx = match pos.func.special_param(ir::ArgumentPurpose::VMContext) {
+            Some(ctx) => ctx,
+            None => return Err(WasmError::User(String::from("missing vmtcx parameter"))),
+        };
+
+        // The memory is grown by the runtime, which may move it: the address
+        // of the heap is loaded again by the following memory accesses
+        let mut sig = ir::Signature::new(CALL_CONV);
+        sig.params = vec![
+            AbiParam::special(POINTER_TYPE, ir::ArgumentPurpose::VMContext),
+            AbiParam::new(ir::types::I32),
+        ];
+        sig.returns = vec![AbiParam::new(ir::types::I32)];
+        let sig = pos.func.import_signature(sig);
+
+        let callee = pos
+            .ins()
+            .iconst(POINTER_TYPE, self.builtins.memory_grow as i64);
+        let call = pos.ins().call_indirect(sig, callee, &[ctx, val]);
+        Ok(pos.func.dfg.first_result(call))
     }
 
     fn translate_memory_size(
         &mut self,
-        _pos: cursor::FuncCursor,
+        mut pos: cursor::FuncCursor,
         _index: MemoryIndex,
         _heap: ir::Heap,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_memory_size")
+        let ctx = match pos.func.special_param(ir::ArgumentPurpose::VMContext) {
+            Some(ctx) => ctx,
+            None => return Err(WasmError::User(String::from("missing vmtcx parameter"))),
+        };
+
+        // Only the low half of the size is read like for the heap bound
+        let size = pos
+            .ins()
+            .load(ir::types::I32, MemFlags::trusted(), ctx, MEMORY_SIZE_OFFSET);
+        Ok(pos
+            .ins()
+            .ushr_imm(size, WASM_PAGE_SIZE.trailing_zeros() as i64))
     }
 
     fn translate_memory_copy(
@@ -111,7 +526,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_memory_copy")
+        Err(WasmError::Unsupported("translate_memory_copy".into()))
     }
 
     fn translate_memory_fill(
@@ -123,7 +538,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _val: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_memory_fill")
+        Err(WasmError::Unsupported("translate_memory_fill".into()))
     }
 
     fn translate_memory_init(
@@ -136,11 +551,11 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_memory_init")
+        Err(WasmError::Unsupported("translate_memory_init".into()))
     }
 
     fn translate_data_drop(&mut self, _pos: cursor::FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        panic!("translate_data_drop")
+        Err(WasmError::Unsupported("translate_data_drop".into()))
     }
 
     fn translate_table_size(
@@ -149,7 +564,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _index: TableIndex,
         _table: ir::Table,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_table_size")
+        Err(WasmError::Unsupported("translate_table_size".into()))
     }
 
     fn translate_table_grow(
@@ -160,7 +575,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _delta: ir::Value,
         _init_value: ir::Value,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_table_grow")
+        Err(WasmError::Unsupported("translate_table_grow".into()))
     }
 
     fn translate_table_get(
@@ -170,7 +585,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _table: ir::Table,
         _index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_table_get")
+        Err(WasmError::Unsupported("translate_table_get".into()))
     }
 
     fn translate_table_set(
@@ -181,7 +596,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _value: ir::Value,
         _index: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_table_set")
+        Err(WasmError::Unsupported("translate_table_set".into()))
     }
 
     fn translate_table_copy(
@@ -195,7 +610,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_table_copy")
+        Err(WasmError::Unsupported("translate_table_copy".into()))
     }
 
     fn translate_table_fill(
@@ -206,7 +621,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _val: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_table_fill")
+        Err(WasmError::Unsupported("translate_table_fill".into()))
     }
 
     fn translate_table_init(
@@ -219,11 +634,39 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _src: ir::Value,
         _len: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_table_init")
+        Err(WasmError::Unsupported("translate_table_init".into()))
     }
 
     fn translate_elem_drop(&mut self, _pos: cursor::FuncCursor, _seg_index: u32) -> WasmResult<()> {
-        panic!("translate_elem_drop")
+        Err(WasmError::Unsupported("translate_elem_drop".into()))
+    }
+
+    fn translate_ref_null(
+        &mut self,
+        mut pos: cursor::FuncCursor,
+        ty: WasmType,
+    ) -> WasmResult<ir::Value> {
+        let null = match ty {
+            WasmType::ExternRef => ExternRef::null().0 as i64,
+            _ => i64::from(FuncRef::null().0),
+        };
+
+        Ok(pos.ins().iconst(reference_type(ty), null))
+    }
+
+    fn translate_ref_is_null(
+        &mut self,
+        mut pos: cursor::FuncCursor,
+        value: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        // The type of the value tells the externrefs from the funcrefs
+        let null = match pos.func.dfg.value_type(value) {
+            ir::types::I64 => ExternRef::null().0 as i64,
+            _ => i64::from(FuncRef::null().0),
+        };
+
+        let is_null = pos.ins().icmp_imm(IntCC::Equal, value, null);
+        Ok(pos.ins().bint(ir::types::I32, is_null))
     }
 
     fn translate_ref_func(
@@ -241,10 +684,14 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         index: GlobalIndex,
     ) -> WasmResult<ir::Value> {
         match self.module.globals[index] {
-            GlobalValue::Const(value) => {
+            ModuleGlobal::Imported(GlobalValue::Const(value)) => {
                 let value = ExternRef::from_const(value);
                 Ok(pos.ins().iconst(ir::types::I64, value.0 as i64))
             }
+            ModuleGlobal::Defined { .. } => Err(WasmError::User(format!(
+                "{:?} is not a custom global",
+                index
+            ))),
         }
     }
 
@@ -254,7 +701,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _global_index: GlobalIndex,
         _val: ir::Value,
     ) -> WasmResult<()> {
-        panic!("translate_custom_global_set")
+        Err(WasmError::Unsupported("translate_custom_global_set".into()))
     }
 
     fn translate_atomic_wait(
@@ -266,7 +713,7 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _expected: ir::Value,
         _timeout: ir::Value,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_atomic_wait")
+        Err(WasmError::Unsupported("translate_atomic_wait".into()))
     }
 
     fn translate_atomic_notify(
@@ -277,13 +724,13 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         _addr: ir::Value,
         _count: ir::Value,
     ) -> WasmResult<ir::Value> {
-        panic!("translate_atomic_notify")
+        Err(WasmError::Unsupported("translate_atomic_notify".into()))
     }
 
     fn translate_call(
         &mut self,
         mut pos: cursor::FuncCursor,
-        _callee_index: FuncIndex,
+        callee_index: FuncIndex,
         callee: ir::FuncRef,
         call_args: &[ir::Value],
     ) -> WasmResult<ir::Inst> {
@@ -297,6 +744,13 @@ impl<'module> FuncEnvironment for FunctionEnv<'module> {
         args.push(ctx);
         args.extend_from_slice(call_args);
 
+        // Flag the table indices passed in place of funcrefs
+        // so the host can tell them from function indices
+        for &index in &self.module.lowered_funcrefs[callee_index] {
+            let arg = &mut args[index + 1];
+            *arg = pos.ins().bor_imm(*arg, i64::from(TABLE_FUNCREF));
+        }
+
         Ok(pos.ins().call(callee, &args))
     }
 }
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display, Formatter},
+    time::{Duration, Instant},
+};
+
+use cranelift_wasm::WasmType;
+use log::{debug, warn};
+
+use super::{
+    runtime::{Externs, Memory},
+    signature::{ExternRef, FuncRef, Function},
+    GlobalValue,
+};
+
+/// Definition of an import in a layer of a `Linker`
+#[derive(Debug, Clone)]
+pub enum Import {
+    Function(Function),
+    Global(GlobalValue),
+    /// The import is revoked, the modules importing it fail to load
+    Denied,
+}
+
+/// A named set of import definitions, shadowing the
+/// definitions of the layers below it in a `Linker`
+#[derive(Debug)]
+pub struct Layer {
+    name: String,
+    imports: HashMap<(String, String), Import>,
+    /// Definitions applying to all the imports of a module
+    modules: HashMap<String, Import>,
+}
+
+impl Layer {
+    fn new(name: String) -> Self {
+        Layer {
+            name,
+            imports: HashMap::new(),
+            modules: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Define the function import `module::name`
+    pub fn define(&mut self, module: &str, name: &str, function: Function) -> &mut Self {
+        self.insert(module, name, Import::Function(function))
+    }
+
+    /// Define the global import `module::name`
+    pub fn define_global(&mut self, module: &str, name: &str, value: GlobalValue) -> &mut Self {
+        self.insert(module, name, Import::Global(value))
+    }
+
+    /// Revoke the import `module::name`
+    pub fn deny(&mut self, module: &str, name: &str) -> &mut Self {
+        self.insert(module, name, Import::Denied)
+    }
+
+    /// Revoke all the imports of `module`, the imports of
+    /// the module defined in this layer are still resolved
+    pub fn deny_module(&mut self, module: &str) -> &mut Self {
+        self.modules.insert(module.into(), Import::Denied);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.imports.is_empty() && self.modules.is_empty()
+    }
+
+    fn insert(&mut self, module: &str, name: &str, import: Import) -> &mut Self {
+        self.imports.insert((module.into(), name.into()), import);
+        self
+    }
+
+    fn get(&self, module: &str, name: &str) -> Option<&Import> {
+        self.imports
+            .get(&(module.into(), name.into()))
+            .or_else(|| self.modules.get(module))
+    }
+}
+
+/// Host function imported by a module, kept in its VMContext
+/// to describe the calls to the middleware
+#[derive(Debug)]
+pub(crate) struct HostImport {
+    /// Name of the import, as `module::name`
+    pub(crate) name: String,
+    pub(crate) params: Vec<WasmType>,
+}
+
+/// Value of an argument passed to a host function
+#[derive(Debug, Clone, Copy)]
+pub enum HostValue {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    ExternRef(ExternRef),
+    FuncRef(FuncRef),
+}
+
+impl Display for HostValue {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HostValue::I32(value) => write!(fmt, "{}", value),
+            HostValue::I64(value) => write!(fmt, "{}", value),
+            HostValue::F32(value) => write!(fmt, "{}", value),
+            HostValue::F64(value) => write!(fmt, "{}", value),
+            HostValue::ExternRef(value) => write!(fmt, "{:?}", value),
+            HostValue::FuncRef(value) => write!(fmt, "{:?}", value),
+        }
+    }
+}
+
+/// A call of a module to a host function, intercepted by a `Middleware`
+pub struct HostCall<'a> {
+    import: &'a HostImport,
+    /// Arguments spilled by the emitted code, each in a 64 bits slot
+    args: &'a [u64],
+    memory: &'a Memory,
+    externs: &'a Externs,
+}
+
+impl<'a> HostCall<'a> {
+    pub(crate) fn new(
+        import: &'a HostImport,
+        args: &'a [u64],
+        memory: &'a Memory,
+        externs: &'a Externs,
+    ) -> Self {
+        HostCall {
+            import,
+            args,
+            memory,
+            externs,
+        }
+    }
+
+    /// Name of the host function, as `module::name`
+    pub fn name(&self) -> &str {
+        &self.import.name
+    }
+
+    /// Linear memory of the module, the pointers passed
+    /// to the host function are offsets in this memory
+    pub fn memory(&self) -> &'a Memory {
+        self.memory
+    }
+
+    /// Externs arena of the module, holding the objects
+    /// designated by the externrefs passed to the host function
+    pub fn externs(&self) -> &'a Externs {
+        self.externs
+    }
+
+    /// Arguments passed by the module to the host function
+    pub fn args(&self) -> impl Iterator<Item = HostValue> + 'a {
+        // The arguments are stored with their own width
+        // at the start of their slot, the rest is undefined
+        let params = self.import.params.iter();
+        params.zip(self.args).map(|(ty, &bits)| match ty {
+            WasmType::I64 => HostValue::I64(bits as i64),
+            WasmType::F32 => HostValue::F32(f32::from_bits(bits as u32)),
+            WasmType::F64 => HostValue::F64(f64::from_bits(bits)),
+            WasmType::ExternRef => HostValue::ExternRef(ExternRef(bits)),
+            WasmType::FuncRef => HostValue::FuncRef(FuncRef(bits as u32)),
+            _ => HostValue::I32(bits as i32),
+        })
+    }
+}
+
+impl<'a> Debug for HostCall<'a> {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        let mut tuple = fmt.debug_tuple(self.name());
+        for arg in self.args() {
+            tuple.field(&arg);
+        }
+        tuple.finish()
+    }
+}
+
+/// Interception of the calls of a module to the host functions
+///
+/// The middleware registered on the `Linker` of a module when it is
+/// compiled wraps all its host calls, in the order they were added
+pub trait Middleware {
+    /// Called before the host function, returning false skips the
+    /// call and the module gets zeroes as the result of the function
+    fn before_call(&mut self, _call: &HostCall<'_>) -> bool {
+        true
+    }
+
+    /// Called once the host function returned, with its execution time
+    fn after_call(&mut self, _call: &HostCall<'_>, _elapsed: Duration) {}
+}
+
+/// Resolves the imports of a module through a stack of layers on top of
+/// the host environment
+///
+/// The layers pushed last shadow the ones below them, an import is only
+/// resolved by the environment if none of the layers defines it. This lets
+/// the embedder stub some host functions or revoke them without changing
+/// the environment
+#[derive(Default)]
+pub struct Linker {
+    layers: Vec<Layer>,
+    middleware: Vec<Box<dyn Middleware>>,
+    /// Start times of the host calls in progress, the host
+    /// functions may call the module which calls the host again
+    started: Vec<Instant>,
+}
+
+impl Debug for Linker {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Linker")
+            .field("layers", &self.layers)
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap the host calls of the module with `middleware`, it must
+    /// be added before the module is compiled to intercept its calls
+    pub fn add_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    pub fn has_middleware(&self) -> bool {
+        !self.middleware.is_empty()
+    }
+
+    /// Push a new layer named `name` on top of the others
+    pub fn layer(&mut self, name: &str) -> &mut Layer {
+        self.layers.push(Layer::new(name.into()));
+        self.layers.last_mut().unwrap()
+    }
+
+    /// Layers of the linker, from the bottom to the top
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Find the topmost definition of the import `module::name`
+    /// along with the layer defining it
+    pub fn lookup(&self, module: &str, name: &str) -> Option<(&Layer, &Import)> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| Some((layer, layer.get(module, name)?)))
+    }
+
+    /// Resolve the function import `module::name` with the layers, or with
+    /// `base` if no layer defines it. Returns None if the import is denied
+    pub fn resolve_function(
+        &self,
+        module: &str,
+        name: &str,
+        base: impl FnOnce() -> Option<Function>,
+    ) -> Option<Function> {
+        match self.lookup(module, name) {
+            Some((layer, Import::Function(function))) => {
+                debug!(
+                    "{}::{} is defined by the {} layer",
+                    module, name, layer.name
+                );
+                Some(function.clone())
+            }
+            Some((layer, Import::Global(_))) => {
+                warn!(
+                    "{}::{} is a global in the {} layer",
+                    module, name, layer.name
+                );
+                None
+            }
+            Some((layer, Import::Denied)) => {
+                warn!("{}::{} is denied by the {} layer", module, name, layer.name);
+                None
+            }
+            None => base(),
+        }
+    }
+
+    /// Resolve the global import `module::name` with the layers, or with
+    /// `base` if no layer defines it. Returns None if the import is denied
+    pub fn resolve_global(
+        &self,
+        module: &str,
+        name: &str,
+        base: impl FnOnce() -> Option<GlobalValue>,
+    ) -> Option<GlobalValue> {
+        match self.lookup(module, name) {
+            Some((layer, Import::Global(value))) => {
+                debug!(
+                    "{}::{} is defined by the {} layer",
+                    module, name, layer.name
+                );
+                Some(*value)
+            }
+            Some((layer, Import::Function(_))) => {
+                warn!(
+                    "{}::{} is a function in the {} layer",
+                    module, name, layer.name
+                );
+                None
+            }
+            Some((layer, Import::Denied)) => {
+                warn!("{}::{} is denied by the {} layer", module, name, layer.name);
+                None
+            }
+            None => base(),
+        }
+    }
+
+    /// Run the middleware before a host call, returns false if the call is skipped
+    pub(crate) fn enter(&mut self, call: &HostCall<'_>) -> bool {
+        for middleware in &mut self.middleware {
+            if !middleware.before_call(call) {
+                return false;
+            }
+        }
+
+        self.started.push(Instant::now());
+        true
+    }
+
+    /// Run the middleware after a host call that wasn't skipped
+    pub(crate) fn exit(&mut self, call: &HostCall<'_>) {
+        let elapsed = match self.started.pop() {
+            Some(started) => started.elapsed(),
+            None => Duration::default(),
+        };
+
+        for middleware in self.middleware.iter_mut().rev() {
+            middleware.after_call(call, elapsed);
+        }
+    }
+}
@@ -5,31 +5,54 @@ use cranelift_codegen::{
     ir::{self, ExternalName},
     settings::{self, Configurable},
 };
+use cranelift_frontend::FunctionBuilderContext;
 use cranelift_module::{default_libcall_names, Linkage, Module};
 use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
-use cranelift_wasm::{translate_module, DefinedFuncIndex, FuncTranslator};
+use cranelift_wasm::{translate_module, DefinedFuncIndex, FuncIndex, FuncTranslator};
 use log::{debug, trace, warn};
+use wasmparser::{Parser, Payload, Validator};
 
 #[macro_use]
 mod signature;
+mod callback;
 mod function;
+mod linker;
 mod module;
 mod runtime;
+#[cfg(feature = "capi")]
+mod trampoline;
 
+pub use self::{
+    callback::{Callback, CallbackRegistry},
+    linker::{HostCall, HostValue, Import, Layer, Linker, Middleware},
+    runtime::{Abort, Exports, Externs, LiveExtern, Loadable, Memory, VMContext},
+    signature::{
+        catch_host_panic, record_host_panic, take_host_calls, take_host_panic, ExternError,
+        ExternRef, FuncRef, Function, PanicDefault,
+    },
+};
+#[cfg(feature = "testing")]
+pub(crate) use self::signature::traits::NativeFunction;
+#[cfg(feature = "capi")]
+pub(crate) use self::trampoline::Trampolines;
 use self::{
-    function::FunctionEnv,
+    function::{build_host_call, build_indirect_stub, FunctionEnv},
+    linker::HostImport,
     module::ModuleEnv,
-    runtime::{Externs, Memory},
-};
-pub use self::{
-    runtime::{Loadable, VMContext},
-    signature::{ExternRef, FuncRef, Function},
+    runtime::{Builtins, MAX_MEMORY_SIZE, WASM_PAGE_SIZE},
+    signature::catch_builtin_panic,
 };
 
+/// Host module provided by the runtime to all the modules, in addition to
+/// the ones of the environment. It exports the `abort` function, called by
+/// the guest with a message, file and line (NUL-terminated strings and an
+/// i32) to report a panic before executing `unreachable`
+pub const RUNTIME_MODULE: &str = "fabric";
+
 /// A global value imported into a WASM module
 ///
 /// At the moment only constant values (integers) are supported
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum GlobalValue {
     Const(u32),
 }
@@ -39,11 +62,122 @@ pub enum GlobalValue {
 pub trait Environment {
     fn import_function(&mut self, module: &str, name: &str) -> Option<Function>;
     fn import_global(&mut self, module: &str, name: &str) -> Option<GlobalValue>;
+
+    /// Returns true if the module should be compiled for deterministic
+    /// execution, with all the floating point NaN values canonicalized
+    fn deterministic(&self) -> bool {
+        false
+    }
+
+    /// Returns the initial fuel of the module to compile it with fuel
+    /// metering, or None to let the guest code run without limits
+    fn initial_fuel(&self) -> Option<isize> {
+        None
+    }
+
+    /// Called when the module executes `memory.grow` to add `delta` pages
+    /// to its memory of `current` pages, the memory only grows if this
+    /// returns true. Requests exceeding the maximum size of the memory
+    /// fail without calling this
+    fn on_memory_grow(&mut self, _current: u32, _delta: u32) -> bool {
+        true
+    }
+
+    /// Called when an object of type `type_name` is moved to the externs
+    /// arena of the module with `VMContext::create_extern`, `live` is the
+    /// number of objects in the arena including the new one
+    fn on_extern_created(&mut self, _type_name: &'static str, _live: usize) {}
+
+    /// Called when the guest aborts with `fabric::abort` or executes an
+    /// `unreachable` instruction. Instead of trapping, the function being
+    /// executed returns zeroes to its caller, the embedder is expected to
+    /// stop calling into the module
+    fn on_abort(&mut self, abort: &Abort) {
+        warn!("guest aborted: {}", abort);
+    }
+
+    /// Called once the module is compiled, before its `start` function
+    /// runs, to let the embedder get the functions it exports
+    fn on_instantiate(&mut self, _exports: &Exports<'_>) {}
+
+    /// Linker of the module, if it has middleware when the module
+    /// is compiled all the host calls of the module go through it
+    fn linker(&mut self) -> Option<&mut Linker> {
+        None
+    }
+}
+
+/// Structure of a module that is available without translating it
+#[derive(Debug, Default)]
+pub struct ModuleInfo {
+    /// Imported items as (module, field) pairs
+    pub imports: Vec<(String, String)>,
+    /// Custom sections as (name, data) pairs
+    pub custom_sections: Vec<(String, Vec<u8>)>,
+}
+
+impl ModuleInfo {
+    /// Get the content of the first custom section named `name`
+    pub fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        self.custom_sections
+            .iter()
+            .find(|(section, _)| section == name)
+            .map(|(_, data)| data.as_slice())
+    }
+}
+
+/// Reads the imports and custom sections of a module from a WAT text source,
+/// without translating the module. Returns None if the source could not be parsed
+pub fn inspect_module(source: &str) -> Option<ModuleInfo> {
+    let source = match wat::parse_str(source) {
+        Ok(source) => source,
+        Err(err) => {
+            warn!("could not load source: {}", err);
+            return None;
+        }
+    };
+
+    let mut info = ModuleInfo::default();
+
+    for payload in Parser::new(0).parse_all(&source) {
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(err) => {
+                warn!("could not parse module: {}", err);
+                return None;
+            }
+        };
+
+        match payload {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = match import {
+                        Ok(import) => import,
+                        Err(err) => {
+                            warn!("could not parse import: {}", err);
+                            return None;
+                        }
+                    };
+
+                    let field = import.field.unwrap_or_default();
+                    info.imports.push((import.module.into(), field.into()));
+                }
+            }
+            Payload::CustomSection { name, data, .. } => {
+                info.custom_sections.push((name.into(), data.to_vec()));
+            }
+            _ => {}
+        }
+    }
+
+    Some(info)
 }
 
 /// Loads a module from a WAT text source: this will parse the module from
 /// source, translate it to machine code and execute the `start` function
 /// if there is one before returning the newly constructed VMContext
+///
+/// Panics if the module is invalid or uses an unsupported feature
 pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E> {
     // Parse the WAT source
     let source = match wat::parse_str(source) {
@@ -54,29 +188,155 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
         }
     };
 
+    match instantiate(environment, &source) {
+        Ok(context) => context,
+        Err(err) => {
+            warn!("could not compile module: {}", err);
+            panic!("{}", err)
+        }
+    }
+}
+
+/// Compile a WASM binary module and execute its `start` function if it has one
+pub(crate) fn instantiate<E: Environment>(
+    environment: E,
+    source: &[u8],
+) -> Result<VMContext<E>, String> {
+    let (mut context, start_func) = compile(environment, source)?;
+
+    let exports = Exports::new(&context.functions, &context.exports);
+    context.environment.on_instantiate(&exports);
+
+    type EntryFunc<E> = with_abi!(fn(*mut VMContext<E>));
+
+    // Execute the `start` function if the module has one
+    if let Some(index) = start_func {
+        if let Some(func) = &context.functions[index.as_u32() as usize] {
+            let func: EntryFunc<E> = func.get();
+            debug!("Calling start function at {:?}", func as *const c_void);
+            func(&mut context);
+        }
+    }
+
+    Ok(context)
+}
+
+/// Host environment without any import, for the modules loaded
+/// with `load_module_bytes` by the fuzzing targets
+#[cfg(feature = "fuzz")]
+#[derive(Debug, Default)]
+pub struct NullEnvironment;
+
+#[cfg(feature = "fuzz")]
+impl Environment for NullEnvironment {
+    fn import_function(&mut self, _module: &str, _name: &str) -> Option<Function> {
+        None
+    }
+
+    fn import_global(&mut self, _module: &str, _name: &str) -> Option<GlobalValue> {
+        None
+    }
+
+    fn deterministic(&self) -> bool {
+        true
+    }
+
+    // The fuel metering code is emitted in all the functions, so
+    // it is exercised by the fuzzer along with the translation
+    fn initial_fuel(&self) -> Option<isize> {
+        Some(0)
+    }
+}
+
+/// Returns true if `bytes` is a valid WASM binary module, with
+/// the proposals the runtime accepts the modules with
+#[cfg(feature = "fuzz")]
+pub fn validate_module(bytes: &[u8]) -> bool {
+    validator().validate_all(bytes).is_ok()
+}
+
+/// Compiles a WASM binary module without running its `start` function,
+/// returns None if the module is rejected by the runtime
+///
+/// Entry point of the fuzzing targets: any input must either be compiled or
+/// rejected, a panic or an abort is a bug of the loader
+#[cfg(feature = "fuzz")]
+pub fn load_module_bytes<E: Environment>(environment: E, bytes: &[u8]) -> Option<VMContext<E>> {
+    match compile(environment, bytes) {
+        Ok((context, _)) => Some(context),
+        Err(err) => {
+            debug!("could not compile module: {}", err);
+            None
+        }
+    }
+}
+
+/// Features of the WASM modules accepted by the runtime, the bulk memory
+/// and reference types proposals are needed to parse the externref and
+/// funcref values, most of their instructions are rejected when translating
+fn validator() -> Validator {
+    let mut validator = Validator::new();
+    validator.wasm_reference_types(true).wasm_bulk_memory(true);
+    validator
+}
+
+/// Validate and translate a WASM binary module to machine code, returning the
+/// VMContext of the module without running it and the index of its `start`
+/// function. Module bytes are untrusted: any error is returned instead of
+/// panicking, and the sizes of the memory and table are bounded
+fn compile<E: Environment>(
+    environment: E,
+    source: &[u8],
+) -> Result<(VMContext<E>, Option<FuncIndex>), String> {
+    // The translator expects a valid module, it doesn't check the function bodies
+    validator()
+        .validate_all(source)
+        .map_err(|err| format!("invalid module: {}", err))?;
+
     // Translate the module: this does NOT translate the function bodies yet,
     // it only load the general structure of the module into the `environment`
     let mut environment = ModuleEnv::new(environment);
-    let state = translate_module(&source, &mut environment).unwrap();
+    let state = translate_module(source, &mut environment).map_err(|err| err.to_string())?;
 
     let ModuleEnv {
-        env: environment,
+        env: mut environment,
         module: defs,
 
         memories,
         data_initializations,
 
         start_func,
+        exports,
         imported_functions,
         defined_functions,
+
+        mut globals,
+        table,
     } = environment;
 
+    // The start function is called without arguments, and so is `_start`
+    // which isn't checked by the validator since it is a regular export
+    if let Some(index) = start_func {
+        let signature = &defs.signatures[defs.functions[index]].wasm;
+        if !signature.params.is_empty() || !signature.returns.is_empty() {
+            return Err(format!("invalid signature for start function {:?}", index));
+        }
+    }
+
     // Initialize the JIT backend for the native ISA
     let mut flag_builder = settings::builder();
     flag_builder.set("enable_safepoints", "true").unwrap();
     flag_builder.set("use_colocated_libcalls", "false").unwrap();
+    if environment.deterministic() {
+        flag_builder.enable("enable_nan_canonicalization").unwrap();
+    }
+
+    let initial_fuel = environment.initial_fuel();
+    let intercept = environment
+        .linker()
+        .is_some_and(|linker| linker.has_middleware());
 
-    let isa_builder = cranelift_native::builder().unwrap();
+    let isa_builder = cranelift_native::builder()?;
     let isa = isa_builder.finish(settings::Flags::new(flag_builder));
 
     let mut builder = SimpleJITBuilder::with_isa(isa, default_libcall_names());
@@ -90,7 +350,10 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
     let mut module: Module<SimpleJITBackend> = Module::new(builder);
 
     let mut list = Vec::new();
+    let mut imports = Vec::new();
     let mut translator = FuncTranslator::new();
+    let mut builder_context = FunctionBuilderContext::new();
+    let builtins = Builtins::new::<E>();
 
     // Insert all the functions (imported and defined) in the module
     for (func_index, sig_index) in &defs.functions {
@@ -114,17 +377,47 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
             None => format!("func_{}", func_index.as_u32()),
         };
 
+        // With middleware the imports are replaced with wrappers calling
+        // the host function through the middleware, defined in the module
+        let linkage = match (defined_function, imported_function) {
+            (Some(_), _) => Linkage::Export,
+            (None, Some(_)) if intercept => Linkage::Local,
+            (None, _) => Linkage::Import,
+        };
+
         let id = module
-            .declare_function(
-                &name,
-                if defined_function.is_some() {
-                    Linkage::Export
-                } else {
-                    Linkage::Import
-                },
-                &signature.clif,
-            )
-            .unwrap();
+            .declare_function(&name, linkage, &signature.clif)
+            .map_err(|err| err.to_string())?;
+
+        if let Some((name, pointer)) = imported_function {
+            imports.push(HostImport {
+                name: name.clone(),
+                params: signature.wasm.params.to_vec(),
+            });
+
+            if intercept {
+                let mut context = module.make_context();
+                context.func = ir::Function::with_name_signature(
+                    ExternalName::user(0, func_index.as_u32()),
+                    signature.clif.clone(),
+                );
+
+                build_host_call(
+                    &mut context.func,
+                    &mut builder_context,
+                    func_index.as_u32(),
+                    *pointer,
+                    signature,
+                    &builtins,
+                );
+
+                debug!("{:?}", context.func);
+
+                module
+                    .define_function(id, &mut context, &mut NullTrapSink::default())
+                    .map_err(|err| format!("{:?}: {}", func_index, err))?;
+            }
+        }
 
         // If this is a defined function, run the translator on the WASM body
         // and register the result ir::Function in the module as a definition
@@ -142,15 +435,15 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
                     body.body_bytes,
                     body.body_offset,
                     &mut context.func,
-                    &mut FunctionEnv { module: &defs },
+                    &mut FunctionEnv::new(&defs, &signature, &builtins, initial_fuel.is_some()),
                 )
-                .unwrap();
+                .map_err(|err| format!("{:?}: {}", func_index, err))?;
 
             debug!("{:?}", context.func);
 
             module
                 .define_function(id, &mut context, &mut NullTrapSink::default())
-                .unwrap();
+                .map_err(|err| format!("{:?}: {}", func_index, err))?;
 
             list.push(Some((id, signature.clone())));
         } else {
@@ -158,6 +451,30 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
         }
     }
 
+    // Define the stubs returned by `call_indirect` for the invalid targets,
+    // one for each function type declared by the module
+    let mut stubs = Vec::with_capacity(defs.types);
+    for (sig_index, signature) in defs.signatures.iter().take(defs.types) {
+        let name = format!("stub_{}", sig_index.as_u32());
+        let id = module
+            .declare_function(&name, Linkage::Local, &signature.clif)
+            .map_err(|err| err.to_string())?;
+
+        let mut context = module.make_context();
+        context.func = ir::Function::with_name_signature(
+            ExternalName::user(1, sig_index.as_u32()),
+            signature.clif.clone(),
+        );
+
+        build_indirect_stub(&mut context.func, &mut builder_context, signature);
+
+        module
+            .define_function(id, &mut context, &mut NullTrapSink::default())
+            .map_err(|err| format!("{:?}: {}", sig_index, err))?;
+
+        stubs.push((id, signature.clone()));
+    }
+
     // Finalize the module generation and emit the machine code
     module.finalize_definitions();
 
@@ -174,45 +491,86 @@ pub fn load_module<E: Environment>(environment: E, source: &str) -> VMContext<E>
 
     trace!("functions {:?}", functions);
 
+    let types = stubs
+        .into_iter()
+        .map(|(id, signature)| Function {
+            signature,
+            pointer: module.get_finalized_function(id),
+        })
+        .collect();
+
     // Initialize the linear memory with the static data defined in the module
     let mut memory = Vec::new();
+    let mut max_size = 0;
 
-    for (index, _) in memories {
-        let init = &data_initializations[index];
+    for (index, desc) in memories {
+        let min_size = (desc.minimum as usize).saturating_mul(WASM_PAGE_SIZE);
+        if min_size > MAX_MEMORY_SIZE {
+            return Err(format!("memory of {} pages is too large", desc.minimum));
+        }
 
-        let init_len = init.data.len();
-        let init_end = init.offset + init_len;
-        if memory.len() < init_end {
-            memory.resize(init_end, 0);
+        max_size = match desc.maximum {
+            Some(maximum) => (maximum as usize).saturating_mul(WASM_PAGE_SIZE),
+            None => MAX_MEMORY_SIZE,
         }
+        .min(MAX_MEMORY_SIZE);
 
-        let memory = &mut memory[init.offset..];
-        let memory = &mut memory[..init_len];
-        memory.copy_from_slice(init.data);
+        if memory.len() < min_size {
+            memory.resize(min_size, 0);
+        }
+
+        for init in &data_initializations[index] {
+            let init_len = init.data.len();
+            let init_end = match init.offset.checked_add(init_len) {
+                Some(init_end) if init_end <= MAX_MEMORY_SIZE => init_end,
+                _ => {
+                    return Err(format!(
+                        "data segment at {}+{} is out of bounds",
+                        init.offset, init_len
+                    ))
+                }
+            };
+
+            if memory.len() < init_end {
+                memory.resize(init_end, 0);
+            }
+
+            let memory = &mut memory[init.offset..];
+            let memory = &mut memory[..init_len];
+            memory.copy_from_slice(init.data);
+        }
     }
 
+    let table = table
+        .into_iter()
+        .map(|element| element.map(|index| index.as_u32()))
+        .collect();
+
     // Create the VMContext object
-    let mut context = VMContext {
+    let context = VMContext {
         _handle: module.finish(),
 
+        compiled: functions.len(),
+        unloaded: false,
         functions,
-
-        memory: Memory::new(memory),
+        table,
+        types,
+        exports: exports
+            .into_iter()
+            .map(|(name, index)| (name, index.as_u32()))
+            .collect(),
+        imports,
+        aborted: false,
+
+        globals_base: globals.as_mut_ptr(),
+        globals,
+
+        memory: Memory::new(memory, max_size),
+        fuel: initial_fuel.unwrap_or(0),
         externs: Externs::default(),
 
         environment,
     };
 
-    type EntryFunc<E> = with_abi!(fn(*mut VMContext<E>));
-
-    // Execute the `start` function if the module has one
-    if let Some(index) = start_func {
-        if let Some(func) = &context.functions[index.as_u32() as usize] {
-            let func: EntryFunc<E> = func.get();
-            debug!("Calling start function at {:?}", func as *const c_void);
-            func(&mut context);
-        }
-    }
-
-    context
+    Ok((context, start_func))
 }
@@ -1,38 +1,70 @@
+use std::collections::HashMap;
+
 use cranelift_codegen::{
     ir::{self},
     isa::TargetFrontendConfig,
 };
 use cranelift_entity::{PrimaryMap, SecondaryMap};
 use cranelift_wasm::{
-    DataIndex, DefinedFuncIndex, ElemIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex,
-    ModuleEnvironment, ModuleTranslationState, SignatureIndex, Table, TableIndex,
+    DataIndex, DefinedFuncIndex, ElemIndex, FuncIndex, Global, GlobalIndex, GlobalInit, Memory,
+    MemoryIndex, ModuleEnvironment, ModuleTranslationState, SignatureIndex, Table, TableIndex,
     TargetEnvironment, WasmError, WasmFuncType, WasmResult, WasmType,
 };
 use log::trace;
 
 use super::{
-    signature::{Signature, CALL_CONV, POINTER_WIDTH},
-    Environment, GlobalValue,
+    runtime::abort_function,
+    signature::{reference_type, Signature, CALL_CONV, POINTER_WIDTH},
+    Environment, GlobalValue, RUNTIME_MODULE,
 };
 
+/// Maximum number of elements in the table of a module
+pub(crate) const MAX_TABLE_SIZE: usize = 0x10000;
+
 #[derive(Debug)]
 pub(crate) struct ModuleEnv<'data, E> {
     pub(crate) env: E,
     pub(crate) module: ModuleDefs,
     pub(crate) start_func: Option<FuncIndex>,
+    /// Functions exported by the module, by name
+    pub(crate) exports: HashMap<String, FuncIndex>,
 
     pub(crate) memories: PrimaryMap<MemoryIndex, Memory>,
-    pub(crate) data_initializations: SecondaryMap<MemoryIndex, DataInitialization<'data>>,
+    pub(crate) data_initializations: SecondaryMap<MemoryIndex, Vec<DataInitialization<'data>>>,
 
     pub(crate) imported_functions: PrimaryMap<DefinedFuncIndex, (String, *const u8)>,
     pub(crate) defined_functions: PrimaryMap<DefinedFuncIndex, FunctionBody<'data>>,
+
+    /// Initial values of the globals defined by the module
+    pub(crate) globals: Vec<u64>,
+    /// Functions referenced by the table of the module
+    pub(crate) table: Vec<Option<FuncIndex>>,
 }
 
 #[derive(Debug, Default)]
 pub(crate) struct ModuleDefs {
-    pub(crate) globals: PrimaryMap<GlobalIndex, GlobalValue>,
+    pub(crate) globals: PrimaryMap<GlobalIndex, ModuleGlobal>,
     pub(crate) functions: PrimaryMap<FuncIndex, SignatureIndex>,
     pub(crate) signatures: PrimaryMap<SignatureIndex, Signature>,
+    /// Number of function types declared by the module, they are the first
+    /// `signatures`, followed by the signatures of the imported functions
+    pub(crate) types: usize,
+    /// Positions of the funcref parameters of the imported
+    /// functions that are passed as table indices by the module
+    pub(crate) lowered_funcrefs: SecondaryMap<FuncIndex, Vec<usize>>,
+}
+
+/// A global of the module, either imported from the host
+/// environment or defined by the module itself
+#[derive(Debug)]
+pub(crate) enum ModuleGlobal {
+    Imported(GlobalValue),
+    /// The value of the global is stored in the `slot`-th
+    /// element of the globals area of the VMContext
+    Defined {
+        slot: u32,
+        ty: ir::Type,
+    },
 }
 
 #[derive(Debug)]
@@ -54,12 +86,16 @@ impl<'data, E: Environment> ModuleEnv<'data, E> {
             env,
             module: Default::default(),
             start_func: Default::default(),
+            exports: Default::default(),
 
             memories: Default::default(),
             data_initializations: Default::default(),
 
             imported_functions: Default::default(),
             defined_functions: Default::default(),
+
+            globals: Default::default(),
+            table: Default::default(),
         }
     }
 }
@@ -71,6 +107,10 @@ impl<'data, E> TargetEnvironment for ModuleEnv<'data, E> {
             pointer_width: POINTER_WIDTH,
         }
     }
+
+    fn reference_type(&self, ty: WasmType) -> ir::Type {
+        reference_type(ty)
+    }
 }
 
 impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
@@ -78,6 +118,7 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         self.module
             .signatures
             .push(Signature::from_wasm(wasm, clif));
+        self.module.types += 1;
         Ok(())
     }
 
@@ -87,15 +128,23 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         module: &'data str,
         field: &'data str,
     ) -> WasmResult<()> {
-        match self.env.import_function(module, field) {
+        let function = match (module, field) {
+            (RUNTIME_MODULE, "abort") => Some(abort_function::<E>()),
+            _ => self.env.import_function(module, field),
+        };
+
+        match function {
             Some(func) => {
                 // Check the returned Function signature matches the
                 // requested import type
-                func.signature
-                    .check_wasm(&self.module.signatures[sig_index].wasm);
+                let lowered = func
+                    .signature
+                    .check_wasm(&self.module.signatures[sig_index].wasm)
+                    .map_err(|err| WasmError::User(format!("{}:{}: {}", module, field, err)))?;
 
                 let index = self.module.signatures.push(func.signature);
-                self.module.functions.push(index);
+                let func_index = self.module.functions.push(index);
+                self.module.lowered_funcrefs[func_index] = lowered;
 
                 // Store the function name and pointer for the linker
                 self.imported_functions
@@ -117,10 +166,10 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         module: &'data str,
         field: &'data str,
     ) -> WasmResult<()> {
-        panic!(
-            "declare_table_import\n  {:?}\n  {:?}\n  {:?}",
+        Err(WasmError::Unsupported(format!(
+            "table import {:?} {}:{}",
             table, module, field
-        )
+        )))
     }
 
     fn declare_memory_import(
@@ -129,10 +178,10 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         module: &'data str,
         field: &'data str,
     ) -> WasmResult<()> {
-        panic!(
-            "declare_memory_import\n  {:?}\n  {:?}\n  {:?}",
+        Err(WasmError::Unsupported(format!(
+            "memory import {:?} {}:{}",
             memory, module, field
-        )
+        )))
     }
 
     fn declare_global_import(
@@ -162,7 +211,7 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
                     }
                 }
 
-                self.module.globals.push(value);
+                self.module.globals.push(ModuleGlobal::Imported(value));
                 Ok(())
             }
 
@@ -187,11 +236,41 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         Ok(())
     }
 
-    fn declare_global(&mut self, _global: Global) -> WasmResult<()> {
+    fn declare_global(&mut self, global: Global) -> WasmResult<()> {
+        // Numeric values are stored in 64 bits slots, the narrower
+        // types are loaded from the low bytes of their slot
+        let value = match global.initializer {
+            GlobalInit::I32Const(value) => u64::from(value as u32),
+            GlobalInit::I64Const(value) => value as u64,
+            GlobalInit::F32Const(value) => u64::from(value),
+            GlobalInit::F64Const(value) => value,
+            init => {
+                return Err(WasmError::Unsupported(format!(
+                    "global initializer {:?}",
+                    init
+                )))
+            }
+        };
+
+        let slot = self.globals.len() as u32;
+        self.globals.push(value);
+        self.module.globals.push(ModuleGlobal::Defined {
+            slot,
+            ty: global.ty,
+        });
+
         Ok(())
     }
 
-    fn declare_func_export(&mut self, _func_index: FuncIndex, _name: &'data str) -> WasmResult<()> {
+    fn declare_func_export(&mut self, func_index: FuncIndex, name: &'data str) -> WasmResult<()> {
+        // Toolchains that can't emit a start section (such as Rust) export
+        // an entry point instead, the start section is declared after the
+        // exports and takes precedence over it
+        if name == "_start" {
+            self.start_func = Some(func_index);
+        }
+
+        self.exports.insert(name.into(), func_index);
         Ok(())
     }
 
@@ -226,11 +305,39 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
 
     fn declare_table_elements(
         &mut self,
-        _table_index: TableIndex,
-        _base: Option<GlobalIndex>,
-        _offset: usize,
-        _elements: Box<[FuncIndex]>,
+        table_index: TableIndex,
+        base: Option<GlobalIndex>,
+        offset: usize,
+        elements: Box<[FuncIndex]>,
     ) -> WasmResult<()> {
+        // Only the table 0 is supported, it is used by `call_indirect` and
+        // to resolve the funcrefs passed as indices, see `TABLE_FUNCREF`
+        if table_index.as_u32() != 0 || base.is_some() {
+            return Err(WasmError::Unsupported(format!(
+                "table elements for {:?} at {:?}+{}",
+                table_index, base, offset
+            )));
+        }
+
+        let end = match offset.checked_add(elements.len()) {
+            Some(end) if end <= MAX_TABLE_SIZE => end,
+            _ => {
+                return Err(WasmError::Unsupported(format!(
+                    "table elements at {}+{}",
+                    offset,
+                    elements.len()
+                )))
+            }
+        };
+
+        if self.table.len() < end {
+            self.table.resize(end, None);
+        }
+
+        for (slot, element) in self.table[offset..end].iter_mut().zip(elements.iter()) {
+            *slot = Some(*element);
+        }
+
         Ok(())
     }
 
@@ -268,7 +375,7 @@ impl<'data, E: Environment> ModuleEnvironment<'data> for ModuleEnv<'data, E> {
         offset: usize,
         data: &'data [u8],
     ) -> WasmResult<()> {
-        self.data_initializations[memory_index] = DataInitialization { base, offset, data };
+        self.data_initializations[memory_index].push(DataInitialization { base, offset, data });
         Ok(())
     }
 }
@@ -1,26 +1,70 @@
+#[cfg(feature = "extern-backtraces")]
+use std::backtrace::Backtrace;
 use std::{
-    any::Any,
+    any::{type_name, Any},
+    collections::HashMap,
     ffi::CStr,
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
+    mem::size_of,
+    ptr, slice,
 };
 
 use cranelift_module::Backend;
 use cranelift_simplejit::SimpleJITBackend;
-
-use super::signature::Function;
+use log::warn;
+
+use super::{
+    linker::{HostCall, HostImport},
+    module::MAX_TABLE_SIZE,
+    signature::{
+        catch_builtin_panic, traits::NativeFunction, ExternError, Function, TABLE_FUNCREF,
+    },
+    Environment,
+};
 use crate::{ExternRef, FuncRef};
 
 /// A compiled module. It holds the functions table, linear
 /// memory, externs arena and host environment for the module,
 // and an exclusive (mut) reference to it must be passed as an
 // argument to all functions emitted from this
+//
+// The memory is the first field of this structure followed by the
+// remaining fuel and the address of the globals, this lets the emitted
+// code load the base address and size of the memory, the fuel and the
+// globals at a fixed offset
+#[repr(C)]
 pub struct VMContext<E> {
-    pub(crate) _handle: <SimpleJITBackend as Backend>::Product,
-    pub(crate) functions: Vec<Option<Function>>,
-
     /// Linear memory instance associated with this module
     pub memory: Memory,
 
+    /// Remaining fuel of the guest code, only consumed by the functions of
+    /// modules compiled with fuel metering. See `set_fuel`
+    pub(crate) fuel: isize,
+
+    /// Address of the values of the globals defined by the module, in `globals`
+    pub(crate) globals_base: *mut u64,
+
+    pub(crate) _handle: <SimpleJITBackend as Backend>::Product,
+    pub(crate) functions: Vec<Option<Function>>,
+    /// Number of elements of `functions` emitted by the compiler, the
+    /// functions following them were added by the host with `push_table`
+    pub(crate) compiled: usize,
+    /// The code of the module was released by `unload`
+    pub(crate) unloaded: bool,
+    /// Indices in `functions` of the elements of the table of the module
+    pub(crate) table: Vec<Option<u32>>,
+    /// Functions returning zeroes for each function type of the module, by
+    /// type index, called by `call_indirect` in place of the invalid targets
+    pub(crate) types: Vec<Function>,
+    /// Indices in `functions` of the functions exported by the module, by name
+    pub(crate) exports: HashMap<String, u32>,
+    /// Host functions imported by the module, by function index
+    pub(crate) imports: Vec<HostImport>,
+    /// The guest called `fabric::abort`, the `unreachable`
+    /// instruction following it isn't reported again
+    pub(crate) aborted: bool,
+    pub(crate) globals: Vec<u64>,
+
     /// Arena holding the managed externals for this instance
     pub externs: Externs,
 
@@ -33,6 +77,7 @@ impl<E: Debug> Debug for VMContext<E> {
         fmt.debug_struct("VMContext")
             .field("memory", &self.memory)
             .field("functions", &self.functions)
+            .field("unloaded", &self.unloaded)
             .field("environment", &self.environment)
             .finish()
     }
@@ -41,19 +86,491 @@ impl<E: Debug> Debug for VMContext<E> {
 impl<E> VMContext<E> {
     /// Get a function handle from a WASM function reference
     pub fn function(&self, index: FuncRef) -> Option<&Function> {
-        self.functions
-            .get(index.0 as usize)
-            .and_then(Option::as_ref)
+        let index = if index.0 & TABLE_FUNCREF != 0 {
+            let element = (index.0 & !TABLE_FUNCREF) as usize;
+            (*self.table.get(element)?)?
+        } else {
+            index.0
+        };
+
+        self.functions.get(index as usize).and_then(Option::as_ref)
+    }
+
+    /// Get a function from a WASM function reference as a native function
+    /// pointer of type `T`, checking the signature of the function
+    ///
+    /// Returns a description of the error if the reference doesn't resolve
+    /// to a function, or if the function has a different signature
+    pub fn typed_function<T: NativeFunction>(&self, index: FuncRef) -> Result<T, String> {
+        match self.function(index) {
+            Some(function) => function.try_get(),
+            None => Err(format!("{:?} is not a function of the module", index)),
+        }
+    }
+
+    /// Get a function exported by the module by name
+    pub fn export(&self, name: &str) -> Option<&Function> {
+        self.exports().get(name)
+    }
+
+    /// Functions exported by the module
+    pub fn exports(&self) -> Exports<'_> {
+        Exports::new(&self.functions, &self.exports)
+    }
+
+    /// Add `function` to the table of the module, returns the funcref of the
+    /// new element or an error if the table is full
+    ///
+    /// The guest calls the function with `call_indirect` on the index of
+    /// the element, `FuncRef::index`, like the functions it takes the address
+    /// of. The signature of the function is checked on each call
+    pub fn push_table(&mut self, function: Function) -> Result<FuncRef, String> {
+        if self.table.len() >= MAX_TABLE_SIZE {
+            return Err(format!(
+                "the table of the module is full ({} elements)",
+                self.table.len()
+            ));
+        }
+
+        let element = self.table.len() as u32;
+        self.table.push(Some(self.functions.len() as u32));
+        self.functions.push(Some(function));
+        Ok(FuncRef(element | TABLE_FUNCREF))
+    }
+
+    /// Set the fuel available to the guest code
+    ///
+    /// For modules compiled with fuel metering, every function call and
+    /// loop iteration consumes an amount of fuel proportional to the number
+    /// of operators since the previous check. Once the fuel runs out the
+    /// guest functions return zeroes immediately without running, aborting
+    /// the current call and all the following ones until the module is refueled
+    pub fn set_fuel(&mut self, fuel: isize) {
+        self.fuel = fuel;
+    }
+
+    /// Returns the remaining fuel of the guest code
+    pub fn fuel(&self) -> isize {
+        self.fuel
+    }
+
+    /// Returns true if the guest code ran out of fuel
+    pub fn is_out_of_fuel(&self) -> bool {
+        self.fuel < 0
+    }
+
+    /// Release the machine code of the module
+    ///
+    /// The functions, table and exports of the module are emptied so the
+    /// lookups return `None` afterwards. Dropping a VMContext without
+    /// unloading it leaks its code, as the JIT doesn't track which
+    /// function pointers handed out to the host are still reachable
+    ///
+    /// In debug builds the code pages stay mapped and the entry of each
+    /// function is overwritten with a trapping instruction, a stale function
+    /// pointer called after the unload crashes on the spot instead of running
+    /// released memory. In release builds the pages are freed, except on
+    /// Windows where SimpleJIT doesn't unmap them yet
+    ///
+    /// # Safety
+    /// No function of the module may be running, and the function pointers
+    /// obtained from it (including the ones stored by the host) must not be
+    /// called afterwards
+    pub unsafe fn unload(&mut self) {
+        if self.unloaded {
+            return;
+        }
+
+        let compiled = self.functions.len().min(self.compiled);
+        let functions = self.functions.drain(..).take(compiled).flatten();
+        let stubs = self.types.drain(..);
+        let code: Vec<_> = functions.chain(stubs).map(|func| func.pointer).collect();
+
+        if cfg!(debug_assertions) {
+            for pointer in code {
+                poison_function(pointer);
+            }
+        } else {
+            self._handle.free_memory();
+        }
+
+        self.table.clear();
+        self.exports.clear();
+        self.unloaded = true;
+    }
+
+    /// Returns true if the code of the module was released by `unload`
+    pub fn is_unloaded(&self) -> bool {
+        self.unloaded
+    }
+}
+
+/// Overwrite the entry of the function at `pointer` with `ud2`
+unsafe fn poison_function(pointer: *const u8) {
+    const UD2: [u8; 2] = [0x0f, 0x0b];
+
+    // The code pages are mapped as read-execute once finalized, the
+    // handle restores their protection once dropped
+    let _handle = match region::protect_with_handle(
+        pointer,
+        UD2.len(),
+        region::Protection::READ_WRITE_EXECUTE,
+    ) {
+        Ok(handle) => handle,
+        Err(err) => {
+            warn!("failed to poison function {:?}: {}", pointer, err);
+            return;
+        }
+    };
+
+    ptr::copy_nonoverlapping(UD2.as_ptr(), pointer as *mut u8, UD2.len());
+}
+
+/// The functions exported by a module, by name
+#[derive(Clone, Copy)]
+pub struct Exports<'a> {
+    functions: &'a [Option<Function>],
+    exports: &'a HashMap<String, u32>,
+}
+
+impl<'a> Exports<'a> {
+    pub(crate) fn new(
+        functions: &'a [Option<Function>],
+        exports: &'a HashMap<String, u32>,
+    ) -> Self {
+        Exports { functions, exports }
+    }
+
+    /// Get the function exported as `name`
+    pub fn get(&self, name: &str) -> Option<&'a Function> {
+        let index = *self.exports.get(name)?;
+        self.functions.get(index as usize).and_then(Option::as_ref)
+    }
+
+    /// Get the function exported as `name` as a native function pointer of
+    /// type `T`, checking the signature of the function
+    ///
+    /// Returns a description of the error if the module doesn't export
+    /// `name`, or if the function has a different signature
+    pub fn typed<T: NativeFunction>(&self, name: &str) -> Result<T, String> {
+        match self.get(name) {
+            Some(function) => function.try_get(),
+            None => Err(format!("{} is not exported by the module", name)),
+        }
+    }
+
+    /// Names of the exported functions, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + 'a {
+        let functions = self.functions;
+        self.exports
+            .iter()
+            .filter(move |(_, index)| matches!(functions.get(**index as usize), Some(Some(_))))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl<'a> Debug for Exports<'a> {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl<E: Environment> VMContext<E> {
+    /// Move `value` to the externs arena of the module, like `Externs::create_extern`,
+    /// and notify the environment with `Environment::on_extern_created`
+    pub fn create_extern<T: Any>(&mut self, value: T) -> ExternRef {
+        let extern_ref = self.externs.create_extern(value);
+        self.environment
+            .on_extern_created(type_name::<T>(), self.externs.len());
+        extern_ref
+    }
+}
+
+// Implementation of `memory.grow`, called by the emitted code with the
+// number of pages to add to the memory. Returns the previous size of
+// the memory in pages, or -1 if the memory can't grow or the growth
+// is refused by the environment
+with_abi! {
+    builtin fn memory_grow<E: Environment>(ctx: *mut VMContext<E>, delta: i32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        // The operand is an unsigned number of pages
+        let delta = delta as u32;
+        let current = ctx.memory.pages();
+        let size = match ctx.memory.grown_size(delta) {
+            Some(size) => size,
+            None => return -1,
+        };
+
+        if delta > 0 {
+            if !ctx.environment.on_memory_grow(current, delta) {
+                return -1;
+            }
+
+            ctx.memory.grow_to(size);
+        }
+
+        current as i32
+    }
+}
+
+// Called by the wrappers of the host functions of the modules compiled with
+// middleware before calling the host function `index`, with the arguments of
+// the call spilled in `args`. Returns 0 if the call must be skipped
+with_abi! {
+    builtin fn host_call_enter<E: Environment>(ctx: *mut VMContext<E>, index: i32, args: *const u64) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        let import = &ctx.imports[index as usize];
+        let args = unsafe { slice::from_raw_parts(args, import.params.len()) };
+        let call = HostCall::new(import, args, &ctx.memory, &ctx.externs);
+
+        match ctx.environment.linker() {
+            Some(linker) => linker.enter(&call) as i32,
+            None => 1,
+        }
+    }
+}
+
+// Called by the wrappers of the host functions once the host function
+// `index` returned, if the call wasn't skipped by `host_call_enter`
+with_abi! {
+    builtin fn host_call_exit<E: Environment>(ctx: *mut VMContext<E>, index: i32, args: *const u64) -> () {
+        let ctx = unsafe { &mut *ctx };
+        let import = &ctx.imports[index as usize];
+        let args = unsafe { slice::from_raw_parts(args, import.params.len()) };
+        let call = HostCall::new(import, args, &ctx.memory, &ctx.externs);
+
+        if let Some(linker) = ctx.environment.linker() {
+            linker.exit(&call);
+        }
+    }
+}
+
+/// Abnormal termination of the guest code, either reported by the guest with
+/// `fabric::abort` or caused by the execution of an `unreachable` instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Abort {
+    /// Message passed to `fabric::abort`, None for an `unreachable` instruction
+    pub message: Option<String>,
+    /// Source file and line of the guest code reporting the abort, if known
+    pub location: Option<(String, u32)>,
+}
+
+impl Display for Abort {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(fmt, "{}", message)?,
+            None => write!(fmt, "unreachable code executed")?,
+        }
+
+        match &self.location {
+            Some((file, line)) => write!(fmt, " at {}:{}", file, line),
+            None => Ok(()),
+        }
+    }
+}
+
+// Implementation of the `fabric::abort` import: `message` and `file` are
+// NUL-terminated strings in the memory of the module, either may be 0
+with_abi! {
+    builtin fn guest_abort<E: Environment>(ctx: *mut VMContext<E>, message: i32, file: i32, line: i32) -> () {
+        let ctx = unsafe { &mut *ctx };
+
+        let load = |offset: i32| match offset {
+            0 => None,
+            offset => ctx
+                .memory
+                .load::<CStr>(offset as u32 as usize)
+                .ok()
+                .map(|string| string.to_string_lossy().into_owned()),
+        };
+
+        let abort = Abort {
+            message: Some(load(message).unwrap_or_else(|| String::from("aborted"))),
+            location: load(file).map(|file| (file, line as u32)),
+        };
+
+        ctx.aborted = true;
+        ctx.environment.on_abort(&abort);
+    }
+}
+
+/// The `fabric::abort` import, provided by the runtime to all the modules
+pub(crate) fn abort_function<E: Environment>() -> Function {
+    Function::new(guest_abort::<E> as with_abi!(fn(*mut VMContext<E>, i32, i32, i32) -> ()))
+}
+
+// Called by the emitted code in place of the `unreachable` instruction,
+// the function executing it then returns zeroes instead of trapping
+with_abi! {
+    builtin fn unreachable<E: Environment>(ctx: *mut VMContext<E>) -> () {
+        let ctx = unsafe { &mut *ctx };
+
+        if !ctx.aborted {
+            ctx.environment.on_abort(&Abort {
+                message: None,
+                location: None,
+            });
+        }
+
+        ctx.aborted = false;
+    }
+}
+
+// Implementation of `call_indirect`, called by the emitted code with the
+// index of the element of the table and the function type the guest calls
+// it as. Returns the address of the function to call, or of the stub of the
+// function type returning zeroes if the element isn't a function of this type
+with_abi! {
+    builtin fn call_indirect<E: Environment>(ctx: *mut VMContext<E>, element: i32, ty: i32) -> *const u8 {
+        let ctx = unsafe { &mut *ctx };
+        let stub = &ctx.types[ty as usize];
+
+        let function = ctx
+            .table
+            .get(element as u32 as usize)
+            .copied()
+            .flatten()
+            .and_then(|index| ctx.functions.get(index as usize)?.as_ref());
+
+        let message = match function {
+            Some(function) => match function.signature.check_clif(&stub.signature.clif) {
+                Ok(()) => return function.pointer,
+                Err(_) => {
+                    let (expected, found) = (&stub.signature.wasm, &function.signature.wasm);
+                    format!(
+                        "indirect call to element {} as {:?} -> {:?}, found {:?} -> {:?}",
+                        element, expected.params, expected.returns, found.params, found.returns
+                    )
+                }
+            },
+            None => format!("indirect call to element {}, which isn't a function", element),
+        };
+
+        let stub = stub.pointer;
+        let abort = Abort {
+            message: Some(message),
+            location: None,
+        };
+
+        // The stub must be returned even if the environment panics
+        catch_builtin_panic(|| ctx.environment.on_abort(&abort));
+        stub
     }
 }
 
+/// Addresses of the runtime functions called by the emitted code,
+/// instantiated for the environment of the module being compiled
+pub(crate) struct Builtins {
+    pub(crate) memory_grow: *const u8,
+    pub(crate) host_call_enter: *const u8,
+    pub(crate) host_call_exit: *const u8,
+    pub(crate) unreachable: *const u8,
+    pub(crate) call_indirect: *const u8,
+}
+
+impl Builtins {
+    pub(crate) fn new<E: Environment>() -> Self {
+        Builtins {
+            memory_grow: memory_grow::<E> as with_abi!(fn(*mut VMContext<E>, i32) -> i32)
+                as *const u8,
+            host_call_enter: host_call_enter::<E>
+                as with_abi!(fn(*mut VMContext<E>, i32, *const u64) -> i32)
+                as *const u8,
+            host_call_exit: host_call_exit::<E>
+                as with_abi!(fn(*mut VMContext<E>, i32, *const u64) -> ())
+                as *const u8,
+            unreachable: unreachable::<E> as with_abi!(fn(*mut VMContext<E>) -> ()) as *const u8,
+            call_indirect: call_indirect::<E>
+                as with_abi!(fn(*mut VMContext<E>, i32, i32) -> *const u8)
+                as *const u8,
+        }
+    }
+}
+
+/// Size of a WASM memory page in bytes
+pub(crate) const WASM_PAGE_SIZE: usize = 0x10000;
+
+/// Maximum size of the linear memory of a module, when it is
+/// loaded or after growing it with `memory.grow`
+pub(crate) const MAX_MEMORY_SIZE: usize = 1024 * WASM_PAGE_SIZE;
+
+/// Offset of the linear memory base address in the VMContext
+pub(crate) const MEMORY_BASE_OFFSET: i32 = 0;
+/// Offset of the linear memory size in the VMContext
+pub(crate) const MEMORY_SIZE_OFFSET: i32 = size_of::<*mut u8>() as i32;
+/// Offset of the remaining fuel in the VMContext
+pub(crate) const FUEL_OFFSET: i32 = size_of::<Memory>() as i32;
+/// Offset of the address of the globals in the VMContext
+pub(crate) const GLOBALS_OFFSET: i32 = FUEL_OFFSET + size_of::<isize>() as i32;
+
 /// WASM linear memory instance
-#[derive(Debug)]
-pub struct Memory(Vec<u8>);
+///
+/// The base pointer and size of the data buffer are duplicated at the start
+/// of the structure, these are read directly by the emitted code and must be
+/// kept in sync with the buffer
+#[repr(C)]
+pub struct Memory {
+    base: *mut u8,
+    size: usize,
+    data: Vec<u8>,
+    /// Size in bytes the memory can grow up to
+    maximum: usize,
+}
+
+impl Debug for Memory {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Memory")
+            .field("base", &self.base)
+            .field("size", &self.size)
+            .field("maximum", &self.maximum)
+            .finish()
+    }
+}
 
 impl Memory {
-    pub(crate) fn new(data: Vec<u8>) -> Self {
-        Memory(data)
+    pub(crate) fn new(mut data: Vec<u8>, maximum: usize) -> Self {
+        Memory {
+            base: data.as_mut_ptr(),
+            size: data.len(),
+            data,
+            maximum,
+        }
+    }
+
+    /// Size of the memory in WASM pages
+    pub fn pages(&self) -> u32 {
+        (self.data.len() / WASM_PAGE_SIZE) as u32
+    }
+
+    /// Size of the memory in bytes after growing it by `delta` pages,
+    /// or None if the memory can't grow that much
+    fn grown_size(&self, delta: u32) -> Option<usize> {
+        let size = (self.pages() as usize)
+            .checked_add(delta as usize)?
+            .checked_mul(WASM_PAGE_SIZE)?;
+        if size <= self.maximum {
+            Some(size)
+        } else {
+            None
+        }
+    }
+
+    /// Grow the memory to `size` bytes, the memory may be moved and the
+    /// address and size read by the emitted code are updated
+    fn grow_to(&mut self, size: usize) {
+        self.data.resize(size, 0);
+        self.base = self.data.as_mut_ptr();
+        self.size = self.data.len();
+    }
+
+    /// Size of the memory in bytes
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
     }
 
     /// Load a value from memory
@@ -61,7 +578,27 @@ impl Memory {
     /// This is implemented with a separate Loadable trait so the turbofish syntax
     /// `memory.load::<T>(offset)` can be used with this method
     pub fn load<T: Loadable + ?Sized>(&self, offset: usize) -> Result<&T, T::Error> {
-        T::load(&self.0, offset)
+        T::load(&self.data, offset)
+    }
+
+    /// Get a slice of `len` bytes starting at `offset`
+    pub fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], ()> {
+        let end = offset.checked_add(len).ok_or(())?;
+        self.data.get(offset..end).ok_or(())
+    }
+
+    /// Get a mutable slice of `len` bytes starting at `offset`
+    pub fn bytes_mut(&mut self, offset: usize, len: usize) -> Result<&mut [u8], ()> {
+        let end = offset.checked_add(len).ok_or(())?;
+        self.data.get_mut(offset..end).ok_or(())
+    }
+
+    /// Copy `data` into memory at `offset`
+    ///
+    /// Fails without writing anything if the whole slice doesn't fit in memory
+    pub fn store(&mut self, offset: usize, data: &[u8]) -> Result<(), ()> {
+        self.bytes_mut(offset, data.len())?.copy_from_slice(data);
+        Ok(())
     }
 }
 
@@ -92,7 +629,7 @@ impl Loadable for CStr {
             None => return Err(()),
         };
 
-        match CStr::from_bytes_with_nul(&memory[..end]) {
+        match CStr::from_bytes_with_nul(&memory[..=end]) {
             Ok(value) => Ok(value),
             Err(_) => Err(()),
         }
@@ -106,6 +643,22 @@ pub struct Externs(Vec<ExternSlot>);
 pub(crate) struct ExternSlot {
     gen: u16,
     value: Option<Box<dyn Any>>,
+    /// Name of the type of `value`
+    type_name: &'static str,
+    /// Where `value` was created, with the `extern-backtraces` feature
+    #[cfg(feature = "extern-backtraces")]
+    backtrace: Option<Backtrace>,
+}
+
+/// An object removed from the arena by `Externs::drain`
+pub struct LiveExtern {
+    pub extern_ref: ExternRef,
+    /// Name of the type of `value`
+    pub type_name: &'static str,
+    pub value: Box<dyn Any>,
+    /// Where the object was created, only captured with
+    /// the `extern-backtraces` feature of the runtime
+    pub backtrace: Option<String>,
 }
 
 impl Externs {
@@ -115,8 +668,13 @@ impl Externs {
 
         for (index, slot) in self.0.iter_mut().enumerate() {
             if slot.value.is_none() {
-                slot.gen += 1;
+                slot.gen = slot.gen.wrapping_add(1);
                 slot.value = Some(value);
+                slot.type_name = type_name::<T>();
+                #[cfg(feature = "extern-backtraces")]
+                {
+                    slot.backtrace = Some(Backtrace::force_capture());
+                }
                 return ExternRef::from_index_gen(index as u32, slot.gen);
             }
         }
@@ -126,39 +684,154 @@ impl Externs {
         self.0.push(ExternSlot {
             gen: 0,
             value: Some(value),
+            type_name: type_name::<T>(),
+            #[cfg(feature = "extern-backtraces")]
+            backtrace: Some(Backtrace::force_capture()),
         });
 
         ExternRef::from_index_gen(index as u32, 0)
     }
 
     /// Get a reference to the object corresponding to a given ExternRef
+    ///
+    /// # Panic
+    /// Panics if the ExternRef is not a live object of type `T`, see `try_get_extern`
     pub fn get_extern<T: Any>(&self, index: ExternRef) -> &T {
-        let (index, gen) = index.index_gen();
-        let slot = &self.0[index as usize];
-
-        assert_eq!(slot.gen, gen);
-
-        slot.value.as_ref().unwrap().downcast_ref().unwrap()
+        match self.try_get_extern(index) {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
     }
 
     /// Get a mutable reference to the object corresponding to a given ExternRef
+    ///
+    /// # Panic
+    /// Panics if the ExternRef is not a live object of type `T`, see `try_get_extern_mut`
     pub fn get_extern_mut<T: Any>(&mut self, index: ExternRef) -> &mut T {
-        let (index, gen) = index.index_gen();
-        let slot = &mut self.0[index as usize];
-
-        assert_eq!(slot.gen, gen);
-
-        slot.value.as_mut().unwrap().downcast_mut().unwrap()
+        match self.try_get_extern_mut(index) {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
     }
 
     /// Take ownership of the object corresponding to a given ExternRef,
     // removing it from the arena
+    ///
+    /// # Panic
+    /// Panics if the ExternRef is not a live object of type `T`, see `try_take_extern`
     pub fn take_extern<T: Any>(&mut self, index: ExternRef) -> T {
-        let (index, gen) = index.index_gen();
-        let slot = &mut self.0[index as usize];
+        match self.try_take_extern(index) {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Get a reference to the object corresponding to a given ExternRef,
+    /// or the reason the ExternRef doesn't designate an object of type `T`
+    pub fn try_get_extern<T: Any>(&self, index: ExternRef) -> Result<&T, ExternError> {
+        let (index, value) = self.slot(index)?;
+        value.downcast_ref().ok_or(ExternError::WrongType { index })
+    }
+
+    /// Get a mutable reference to the object corresponding to a given ExternRef,
+    /// or the reason the ExternRef doesn't designate an object of type `T`
+    pub fn try_get_extern_mut<T: Any>(&mut self, index: ExternRef) -> Result<&mut T, ExternError> {
+        let (index, gen) = index.try_index_gen()?;
+        match self.0.get_mut(index as usize) {
+            Some(ExternSlot {
+                gen: slot_gen,
+                value: Some(value),
+                ..
+            }) if *slot_gen == gen => value.downcast_mut().ok_or(ExternError::WrongType { index }),
+            _ => Err(ExternError::Dangling {
+                index,
+                generation: gen,
+            }),
+        }
+    }
 
-        assert_eq!(slot.gen, gen);
+    /// Take ownership of the object corresponding to a given ExternRef, or
+    /// return the reason the ExternRef doesn't designate an object of type `T`.
+    /// The object is only removed from the arena if it has the requested type
+    pub fn try_take_extern<T: Any>(&mut self, index: ExternRef) -> Result<T, ExternError> {
+        if !self.slot(index)?.1.is::<T>() {
+            return Err(ExternError::WrongType {
+                index: index.try_index_gen()?.0,
+            });
+        }
+
+        let (index, _) = index.try_index_gen()?;
+        let value = self.0[index as usize].value.take().unwrap();
+        Ok(*value.downcast().unwrap())
+    }
+
+    /// Name of the type of the object corresponding to a given ExternRef,
+    /// or the reason the ExternRef doesn't designate a live object
+    pub fn extern_type(&self, index: ExternRef) -> Result<&'static str, ExternError> {
+        let (index, gen) = index.try_index_gen()?;
+        match self.0.get(index as usize) {
+            Some(ExternSlot {
+                gen: slot_gen,
+                value: Some(_),
+                type_name,
+                ..
+            }) if *slot_gen == gen => Ok(type_name),
+            _ => Err(ExternError::Dangling {
+                index,
+                generation: gen,
+            }),
+        }
+    }
+
+    /// Resolve a live object of the arena
+    fn slot(&self, index: ExternRef) -> Result<(u32, &dyn Any), ExternError> {
+        let (index, gen) = index.try_index_gen()?;
+        match self.0.get(index as usize) {
+            Some(ExternSlot {
+                gen: slot_gen,
+                value: Some(value),
+                ..
+            }) if *slot_gen == gen => Ok((index, &**value)),
+            _ => Err(ExternError::Dangling {
+                index,
+                generation: gen,
+            }),
+        }
+    }
+
+    /// Remove all the live objects from the arena, so the host can report
+    /// the externs a module didn't release before dropping them
+    pub fn drain(&mut self) -> Vec<LiveExtern> {
+        let mut live = Vec::new();
+
+        for (index, slot) in self.0.iter_mut().enumerate() {
+            let value = match slot.value.take() {
+                Some(value) => value,
+                None => continue,
+            };
+
+            #[cfg(feature = "extern-backtraces")]
+            let backtrace = slot.backtrace.take().map(|backtrace| backtrace.to_string());
+            #[cfg(not(feature = "extern-backtraces"))]
+            let backtrace = None;
+
+            live.push(LiveExtern {
+                extern_ref: ExternRef::from_index_gen(index as u32, slot.gen),
+                type_name: slot.type_name,
+                value,
+                backtrace,
+            });
+        }
+
+        live
+    }
+
+    /// Returns the number of objects currently held in the arena
+    pub fn len(&self) -> usize {
+        self.0.iter().filter(|slot| slot.value.is_some()).count()
+    }
 
-        *slot.value.take().unwrap().downcast().unwrap()
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 }
@@ -1,4 +1,8 @@
-use std::fmt::{self, Debug, Formatter};
+use std::{
+    cell::Cell,
+    fmt::{self, Debug, Formatter},
+    panic::{catch_unwind, AssertUnwindSafe},
+};
 
 use self::traits::NativeFunction;
 
@@ -26,19 +30,17 @@ pub(crate) const POINTER_WIDTH: PointerWidth = PointerWidth::U64;
 #[cfg(target_pointer_width = "32")]
 pub(crate) const POINTER_WIDTH: PointerWidth = PointerWidth::U32;
 
+// The VMContext pointer is passed as a plain integer so the emitted code can
+// use it as a base address to load the linear memory parameters
 #[cfg(target_pointer_width = "64")]
-const POINTER_TYPE: ir::Type = ir::types::R64;
+pub(crate) const POINTER_TYPE: ir::Type = ir::types::I64;
 #[cfg(target_pointer_width = "32")]
-const POINTER_TYPE: ir::Type = ir::types::R32;
+pub(crate) const POINTER_TYPE: ir::Type = ir::types::I32;
 
 impl Signature {
+    /// Create the signature of a function declared by a module, `clif` uses
+    /// the types of `reference_type` for the externref parameters and results
     pub(crate) fn from_wasm(wasm: WasmFuncType, mut clif: ir::Signature) -> Self {
-        for (clif, wasm) in clif.params.iter_mut().zip(wasm.params.iter()) {
-            if *wasm == WasmType::ExternRef {
-                clif.value_type = ir::types::I64;
-            }
-        }
-
         clif.params.insert(
             0,
             AbiParam::special(POINTER_TYPE, ir::ArgumentPurpose::VMContext),
@@ -47,38 +49,93 @@ impl Signature {
         Signature { wasm, clif }
     }
 
-    pub(crate) fn check_wasm(&self, against: &WasmFuncType) {
-        assert_eq!(
-            self.wasm.params.len(),
-            against.params.len(),
-            "expected a function with {} parameters, found {}",
-            self.wasm.params.len(),
-            against.params.len(),
-        );
+    /// Check this signature matches the type a function is imported with,
+    /// returning the positions of the funcref parameters lowered to `i32`
+    /// or a description of the mismatch
+    ///
+    /// Guests compiled without support for reference types (such as Rust
+    /// on wasm32) import externrefs as `i64` and funcrefs as `i32` indices
+    /// in their function table, see `TABLE_FUNCREF`
+    pub(crate) fn check_wasm(&self, against: &WasmFuncType) -> Result<Vec<usize>, String> {
+        if self.wasm.params.len() != against.params.len() {
+            return Err(format!(
+                "expected a function with {} parameters, found {}",
+                self.wasm.params.len(),
+                against.params.len(),
+            ));
+        }
 
-        for (lhs, rhs) in self.wasm.params.iter().zip(against.params.iter()) {
-            assert_eq!(lhs, rhs);
+        let mut lowered = Vec::new();
+
+        for (index, (lhs, rhs)) in self
+            .wasm
+            .params
+            .iter()
+            .zip(against.params.iter())
+            .enumerate()
+        {
+            match (lhs, rhs) {
+                (WasmType::ExternRef, WasmType::I64) => {}
+                (WasmType::FuncRef, WasmType::I32) => lowered.push(index),
+                _ if lhs == rhs => {}
+                _ => {
+                    return Err(format!(
+                        "expected {:?} for parameter {}, found {:?}",
+                        lhs, index, rhs
+                    ))
+                }
+            }
         }
 
-        assert_eq!(self.wasm.returns.len(), against.returns.len());
+        if self.wasm.returns.len() != against.returns.len() {
+            return Err(format!(
+                "expected a function with {} results, found {}",
+                self.wasm.returns.len(),
+                against.returns.len(),
+            ));
+        }
 
         for (lhs, rhs) in self.wasm.returns.iter().zip(against.returns.iter()) {
-            assert_eq!(lhs, rhs);
+            if lhs != rhs && (lhs, rhs) != (&WasmType::ExternRef, &WasmType::I64) {
+                return Err(format!("expected {:?} result, found {:?}", lhs, rhs));
+            }
         }
+
+        Ok(lowered)
     }
 
-    pub(crate) fn check_clif(&self, against: &ir::Signature) {
-        assert_eq!(self.clif.params.len(), against.params.len());
+    /// Check this signature matches the native type a function is requested
+    /// as, returning a description of the mismatch
+    pub(crate) fn check_clif(&self, against: &ir::Signature) -> Result<(), String> {
+        if self.clif.params != against.params {
+            return Err(format!(
+                "expected parameters {:?}, found {:?}",
+                self.clif.params, against.params
+            ));
+        }
 
-        for (lhs, rhs) in self.clif.params.iter().zip(against.params.iter()) {
-            assert_eq!(lhs, rhs);
+        if self.clif.returns != against.returns {
+            return Err(format!(
+                "expected results {:?}, found {:?}",
+                self.clif.returns, against.returns
+            ));
         }
 
-        assert_eq!(self.clif.returns.len(), against.returns.len());
+        Ok(())
+    }
+}
 
-        for (lhs, rhs) in self.clif.returns.iter().zip(against.returns.iter()) {
-            assert_eq!(lhs, rhs);
-        }
+/// Type of the reference values of type `ty` in the emitted code
+///
+/// The references are translated to integers like the `ExternRef` and
+/// `FuncRef` values of the host functions, `i64` for the externrefs and
+/// `i32` for the funcrefs, so they can be passed to the host, returned by it
+/// and kept in the locals, globals and results of the guest functions with
+/// the same type
+pub(crate) fn reference_type(ty: WasmType) -> ir::Type {
+    match ty {
+        WasmType::ExternRef => ir::types::I64,
+        _ => ir::types::I32,
     }
 }
 
@@ -105,28 +162,95 @@ impl Function {
         }
     }
 
+    /// Create a Function from a pointer to a native function with the
+    /// given parameters and results, for the embedders that only know the
+    /// signature at runtime. The function takes the VMContext pointer
+    /// before the parameters, and can have a single result
+    ///
+    /// # Safety
+    /// `pointer` must be a function with the ABI of `with_abi!` and this signature
+    pub unsafe fn from_types(
+        pointer: *const u8,
+        params: &[WasmType],
+        returns: &[WasmType],
+    ) -> Result<Self, String> {
+        if returns.len() > 1 {
+            return Err(format!(
+                "expected at most 1 result, found {}",
+                returns.len()
+            ));
+        }
+
+        let mut clif = ir::Signature::new(CALL_CONV);
+        clif.params.push(AbiParam::special(
+            POINTER_TYPE,
+            ir::ArgumentPurpose::VMContext,
+        ));
+        clif.params
+            .extend(params.iter().map(|ty| AbiParam::new(clif_type(*ty))));
+        clif.returns
+            .extend(returns.iter().map(|ty| AbiParam::new(clif_type(*ty))));
+
+        Ok(Function {
+            signature: Signature {
+                wasm: WasmFuncType {
+                    params: params.into(),
+                    returns: returns.into(),
+                },
+                clif,
+            },
+            pointer,
+        })
+    }
+
     /// Obtain the function as a native Rust function pointer
     ///
     /// # Panic
     /// Panics if the request type doesn't match the stored function signature
     pub fn get<T: NativeFunction>(&self) -> T {
-        self.signature.check_clif(&T::clif_signature());
-        T::from_pointer(self.pointer)
+        match self.try_get() {
+            Ok(func) => func,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Obtain the function as a native Rust function pointer, or
+    /// a description of the mismatch if the signature is different
+    pub fn try_get<T: NativeFunction>(&self) -> Result<T, String> {
+        self.signature.check_clif(&T::clif_signature())?;
+        Ok(T::from_pointer(self.pointer))
+    }
+}
+
+/// Type of the native values of type `ty`, see `NativeType`
+fn clif_type(ty: WasmType) -> ir::Type {
+    match ty {
+        WasmType::I64 | WasmType::ExternRef => ir::types::I64,
+        WasmType::F32 => ir::types::F32,
+        WasmType::F64 => ir::types::F64,
+        _ => ir::types::I32,
     }
 }
 
 #[derive(Debug, PartialEq)]
 enum ExternKind {
-    Const = 0,
-    Object = 1,
+    Const,
+    Object,
+    /// Any other tag, the value wasn't created by the host
+    Invalid(u8),
 }
 
+/// Tag of the constant ExternRefs, a zero value is the null constant
+const TAG_CONST: u8 = 0x00;
+/// Tag of the managed objects, unlikely to be found in an integer passed by mistake
+const TAG_OBJECT: u8 = 0xa7;
+
 impl From<u8> for ExternKind {
     fn from(value: u8) -> Self {
         match value {
-            0 => ExternKind::Const,
-            1 => ExternKind::Object,
-            _ => panic!("invalid ExternKind {}", value),
+            TAG_CONST => ExternKind::Const,
+            TAG_OBJECT => ExternKind::Object,
+            value => ExternKind::Invalid(value),
         }
     }
 }
@@ -134,8 +258,9 @@ impl From<u8> for ExternKind {
 impl From<ExternKind> for u8 {
     fn from(value: ExternKind) -> Self {
         match value {
-            ExternKind::Const => 0,
-            ExternKind::Object => 1,
+            ExternKind::Const => TAG_CONST,
+            ExternKind::Object => TAG_OBJECT,
+            ExternKind::Invalid(value) => value,
         }
     }
 }
@@ -144,15 +269,21 @@ bitfield! {
     /// Reference to a value outside of the module sandbox
     ///
     /// ExternRefs are represented in memory with a 64 bits bitfield where:
-    /// - The 8 low bits are used to store the type of external represented (a constant or a managed object)
-    /// - Constants are stored inline in the high 32 bits
+    /// - The 8 low bits are a tag for the type of external represented (a constant or a managed object)
+    /// - Constants are stored inline in the high 32 bits, the remaining bits are zero
     /// - Managed objects are adressed through a generational index with the generation id stored in the
-    /// middle 16 bits and the actual index stored in the high 32 bits
+    /// middle 16 bits and the actual index stored in the high 32 bits, the bits 8 to 15 hold a check
+    /// byte derived from the index and generation
+    ///
+    /// Guests compiled without reference types pass ExternRefs as plain
+    /// integers, the tag and check byte let the host reject the values that
+    /// were not created by it before using them as an index in the arena
     #[repr(transparent)]
-    #[derive(Copy, Clone)]
+    #[derive(Copy, Clone, PartialEq, Eq)]
     pub struct ExternRef(u64);
 
     u8, from into ExternKind, kind, set_kind: 7, 0;
+    u8, check, set_check: 15, 8;
 
     u32, index, set_index: 63, 32;
     u16, generation, set_generation: 31, 16;
@@ -172,10 +303,52 @@ impl Debug for ExternRef {
                 .field("index", &self.index())
                 .field("generation", &self.generation())
                 .finish(),
+            ExternKind::Invalid(_) => write!(fmt, "ExternRef::Invalid({:#018x})", self.0),
         }
     }
 }
 
+/// Error returned when an ExternRef passed by a guest can't be used as requested
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExternError {
+    /// The value is not an ExternRef created by the host
+    Malformed(u64),
+    /// A constant was passed where a managed object is expected
+    NotAnObject(u32),
+    /// A managed object was passed where a constant is expected
+    NotAConstant,
+    /// The object was released, or never existed in this module
+    Dangling { index: u32, generation: u16 },
+    /// The object is not of the requested type
+    WrongType { index: u32 },
+}
+
+impl fmt::Display for ExternError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExternError::Malformed(value) => write!(fmt, "malformed extern {:#018x}", value),
+            ExternError::NotAnObject(value) => {
+                write!(
+                    fmt,
+                    "expected an extern object, found the constant {}",
+                    value
+                )
+            }
+            ExternError::NotAConstant => write!(fmt, "expected a constant, found an extern object"),
+            ExternError::Dangling { index, generation } => write!(
+                fmt,
+                "extern object {} (generation {}) doesn't exist",
+                index, generation
+            ),
+            ExternError::WrongType { index } => {
+                write!(fmt, "extern object {} has a different type", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExternError {}
+
 impl ExternRef {
     pub(crate) fn from_const(value: u32) -> Self {
         let mut result = ExternRef(0);
@@ -187,36 +360,175 @@ impl ExternRef {
     pub(crate) fn from_index_gen(index: u32, generation: u16) -> Self {
         let mut result = ExternRef(0);
         result.set_kind(ExternKind::Object);
+        result.set_check(Self::check_byte(index, generation));
         result.set_index(index);
         result.set_generation(generation);
         result
     }
 
+    /// Check byte of the managed objects, catches the integers and
+    /// pointers that happen to have the object tag in their low byte
+    fn check_byte(index: u32, generation: u16) -> u8 {
+        let value = (index ^ u32::from(generation) ^ 0x5a5a).to_le_bytes();
+        value[0] ^ value[1] ^ value[2] ^ value[3]
+    }
+
+    /// A constant ExternRef with a value of 0, used to represent an absent value
+    pub fn null() -> Self {
+        Self::from_const(0)
+    }
+
+    /// Returns true if this ExternRef is a constant
+    pub fn is_const(&self) -> bool {
+        self.try_value().is_ok()
+    }
+
+    /// Returns true if this ExternRef is a managed object, alive or not
+    pub fn is_object(&self) -> bool {
+        self.try_index_gen().is_ok()
+    }
+
     /// If this ExternRef is a constant, returns its value
     ///
     /// # Panic
     /// Panics if the ExternRef is not a constant
     pub fn value(&self) -> u32 {
-        assert_eq!(self.kind(), ExternKind::Const);
-        self.const_value()
+        match self.try_value() {
+            Ok(value) => value,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Returns the value of this ExternRef if it is a constant
+    pub fn try_value(&self) -> Result<u32, ExternError> {
+        match self.kind() {
+            ExternKind::Const if self.check() == 0 && self.generation() == 0 => {
+                Ok(self.const_value())
+            }
+            ExternKind::Object if self.is_well_formed_object() => Err(ExternError::NotAConstant),
+            _ => Err(ExternError::Malformed(self.0)),
+        }
+    }
+
+    /// Returns the index and generation of this ExternRef if it is a managed object
+    pub(crate) fn try_index_gen(&self) -> Result<(u32, u16), ExternError> {
+        match self.kind() {
+            ExternKind::Object if self.is_well_formed_object() => {
+                Ok((self.index(), self.generation()))
+            }
+            ExternKind::Const if self.check() == 0 && self.generation() == 0 => {
+                Err(ExternError::NotAnObject(self.const_value()))
+            }
+            _ => Err(ExternError::Malformed(self.0)),
+        }
     }
 
-    pub(crate) fn index_gen(&self) -> (u32, u16) {
-        assert_eq!(self.kind(), ExternKind::Object);
-        (self.index(), self.generation())
+    fn is_well_formed_object(&self) -> bool {
+        self.check() == Self::check_byte(self.index(), self.generation())
     }
 }
 
+/// Value returned to the guest by a host function that panicked
+pub trait PanicDefault {
+    fn panic_default() -> Self;
+}
+
+impl PanicDefault for () {
+    fn panic_default() {}
+}
+
+macro_rules! impl_panic_default {
+    ( $ty:ty, $value:expr ) => {
+        impl PanicDefault for $ty {
+            fn panic_default() -> Self {
+                $value
+            }
+        }
+    };
+}
+
+impl_panic_default!(i32, 0);
+impl_panic_default!(i64, 0);
+impl_panic_default!(f32, 0.0);
+impl_panic_default!(f64, 0.0);
+impl_panic_default!(ExternRef, ExternRef::null());
+impl_panic_default!(FuncRef, FuncRef::null());
+impl_panic_default!(*const u8, std::ptr::null());
+
+thread_local! {
+    static HOST_PANICKED: Cell<bool> = Cell::new(false);
+    static HOST_CALLS: Cell<u64> = Cell::new(0);
+}
+
+/// Run the body of a host function defined with `with_abi!`
+///
+/// Unwinding into the JIT code is undefined behavior, so panics are caught
+/// here and the host function returns a default value to the guest instead.
+/// The panic is recorded until the embedder calls `take_host_panic`
+pub fn catch_host_panic<R: PanicDefault>(func: impl FnOnce() -> R) -> R {
+    HOST_CALLS.with(|calls| calls.set(calls.get() + 1));
+    catch_builtin_panic(func)
+}
+
+/// Run the body of a runtime function called by the emitted code, defined
+/// with `with_abi!(builtin fn ...)`. Panics are handled like for the host
+/// functions but the call isn't counted as a host call
+pub(crate) fn catch_builtin_panic<R: PanicDefault>(func: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(func)) {
+        Ok(value) => value,
+        Err(_) => {
+            HOST_PANICKED.with(|panicked| panicked.set(true));
+            R::panic_default()
+        }
+    }
+}
+
+/// Record a panic caught by the embedder outside of `catch_host_panic`, like
+/// the panic of a callback invoked by native code on behalf of a host function
+pub fn record_host_panic() {
+    HOST_PANICKED.with(|panicked| panicked.set(true));
+}
+
+/// Returns true if a host function panicked on this thread
+/// since the last call, and clear the panic flag
+pub fn take_host_panic() -> bool {
+    HOST_PANICKED.with(|panicked| panicked.replace(false))
+}
+
+/// Returns the number of host functions called on this
+/// thread since the last call, and reset the counter
+pub fn take_host_calls() -> u64 {
+    HOST_CALLS.with(|calls| calls.replace(0))
+}
+
 /// Helper macro for defining functions and function types with the correct
 /// ABI for the current compilation target
+///
+/// The body of the functions defined with this macro is run through
+/// `catch_host_panic`, so they can safely be called from guest code. The
+/// `builtin` form is only used by the runtime for its own functions
 #[cfg(target_pointer_width = "64")]
 #[macro_export]
 macro_rules! with_abi {
+    ( builtin fn $name:ident < $( $gen:ident : $bound:path ),* > $args:tt -> $res:ty $body:block ) => {
+        extern "fastcall" fn $name < $( $gen: $bound ),* > $args -> $res {
+            $crate::backend::cranelift::catch_builtin_panic(|| -> $res { $body })
+        }
+    };
+    ( $vis:vis fn $name:ident < $( $gen:ident : $bound:path ),* > $args:tt -> $res:ty $body:block ) => {
+        $vis extern "fastcall" fn $name < $( $gen: $bound ),* > $args -> $res {
+            $crate::catch_host_panic(|| -> $res { $body })
+        }
+    };
     ( $vis:vis fn $name:ident $args:tt -> $res:ty $body:block ) => {
-        $vis extern "fastcall" fn $name $args -> $res $body
+        $vis extern "fastcall" fn $name $args -> $res {
+            $crate::catch_host_panic(|| -> $res { $body })
+        }
     };
     ( $vis:vis fn $name:ident $args:tt $body:block ) => {
-        $vis extern "fastcall" fn $name $args $body
+        $vis extern "fastcall" fn $name $args {
+            $crate::catch_host_panic(|| $body)
+        }
     };
 
     ( fn $args:tt -> $res:ty ) => {
@@ -229,14 +541,32 @@ macro_rules! with_abi {
 
 /// Helper macro for defining functions and function types with the correct
 /// ABI for the current compilation target
+///
+/// The body of the functions defined with this macro is run through
+/// `catch_host_panic`, so they can safely be called from guest code. The
+/// `builtin` form is only used by the runtime for its own functions
 #[cfg(target_pointer_width = "32")]
 #[macro_export]
 macro_rules! with_abi {
+    ( builtin fn $name:ident < $( $gen:ident : $bound:path ),* > $args:tt -> $res:ty $body:block ) => {
+        extern "C" fn $name < $( $gen: $bound ),* > $args -> $res {
+            $crate::backend::cranelift::catch_builtin_panic(|| -> $res { $body })
+        }
+    };
+    ( $vis:vis fn $name:ident < $( $gen:ident : $bound:path ),* > $args:tt -> $res:ty $body:block ) => {
+        $vis extern "C" fn $name < $( $gen: $bound ),* > $args -> $res {
+            $crate::catch_host_panic(|| -> $res { $body })
+        }
+    };
     ( $vis:vis fn $name:ident $args:tt -> $res:ty $body:block ) => {
-        $vis extern "C" fn $name $args -> $res $body
+        $vis extern "C" fn $name $args -> $res {
+            $crate::catch_host_panic(|| -> $res { $body })
+        }
     };
     ( $vis:vis fn $name:ident $args:tt $body:block ) => {
-        $vis extern "C" fn $name $args $body
+        $vis extern "C" fn $name $args {
+            $crate::catch_host_panic(|| $body)
+        }
     };
 
     ( fn $args:tt -> $res:ty ) => {
@@ -247,15 +577,60 @@ macro_rules! with_abi {
     };
 }
 
-/// Reference to a function (generally defined in the module)
+/// Reference to a function of a module
+///
+/// FuncRefs are represented with a 32 bits value where:
+/// - `u32::MAX` is the null funcref, produced by `ref.null func`
+/// - Values with the high bit (`TABLE_FUNCREF`) set hold an index in the
+///   table of the module in the low 31 bits. The funcrefs passed as `i32` by
+///   the guests without reference types and the functions added to the table
+///   by the host with `VMContext::push_table` are flagged this way
+/// - Other values are an index in the functions of the module (its imports
+///   then its definitions), produced by `ref.func`
 ///
-/// The in-memory value of structure is a 32 bits index in
-/// the functions table of the module
+/// A FuncRef is only checked when it is resolved with `VMContext::function`,
+/// which fails for the null funcref, the indices out of bounds and the
+/// imported functions
 #[repr(transparent)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct FuncRef(pub(crate) u32);
 
-mod traits {
+/// Flag set on the funcrefs holding an index in the table of the module
+pub(crate) const TABLE_FUNCREF: u32 = 1 << 31;
+
+impl Debug for FuncRef {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_null() {
+            write!(fmt, "FuncRef(null)")
+        } else if self.is_table() {
+            write!(fmt, "FuncRef(table {})", self.index())
+        } else {
+            write!(fmt, "FuncRef({})", self.index())
+        }
+    }
+}
+
+impl FuncRef {
+    pub fn null() -> Self {
+        FuncRef(u32::MAX)
+    }
+
+    pub fn is_null(self) -> bool {
+        self.0 == u32::MAX
+    }
+
+    /// Index of the function in the module, or in its table if `is_table` is set
+    pub fn index(self) -> u32 {
+        self.0 & !TABLE_FUNCREF
+    }
+
+    /// Set if the funcref was passed by a guest as an index in its table
+    pub fn is_table(self) -> bool {
+        self.0 & TABLE_FUNCREF != 0
+    }
+}
+
+pub(crate) mod traits {
     use std::intrinsics::transmute;
 
     use cranelift_codegen::ir::{self, AbiParam};
@@ -322,6 +697,9 @@ mod traits {
     impl_native_function!(A1, A2, A3);
     impl_native_function!(A1, A2, A3, A4);
     impl_native_function!(A1, A2, A3, A4, A5);
+    impl_native_function!(A1, A2, A3, A4, A5, A6);
+    impl_native_function!(A1, A2, A3, A4, A5, A6, A7);
+    impl_native_function!(A1, A2, A3, A4, A5, A6, A7, A8);
 
     pub trait NativeType {
         fn wasm_type() -> WasmType;
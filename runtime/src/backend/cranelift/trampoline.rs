@@ -0,0 +1,127 @@
+use std::ffi::c_void;
+
+use cranelift_codegen::{
+    binemit::NullTrapSink,
+    ir::{self, AbiParam, ExternalName, InstBuilder, MemFlags},
+    settings,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+
+use super::signature::{Function, CALL_CONV, POINTER_TYPE};
+
+/// Native function calling `callee` with the VMContext `ctx`, the arguments
+/// read from `args` and the results written to `results`, one 64 bits slot
+/// for each value
+pub(crate) type TrampolineFunc = with_abi!(fn(*const u8, *mut c_void, *const u64, *mut u64));
+
+/// Calls the guest functions with their arguments and results in memory,
+/// for the embedders that only know their signatures at runtime
+///
+/// A trampoline is compiled for each signature the first time a function
+/// with this signature is called, then reused for all the functions of
+/// the module sharing it
+pub(crate) struct Trampolines {
+    module: Module<SimpleJITBackend>,
+    context: FunctionBuilderContext,
+    compiled: Vec<(ir::Signature, TrampolineFunc)>,
+}
+
+impl Trampolines {
+    pub(crate) fn new() -> Result<Self, String> {
+        let isa_builder = cranelift_native::builder()?;
+        let isa = isa_builder.finish(settings::Flags::new(settings::builder()));
+
+        Ok(Trampolines {
+            module: Module::new(SimpleJITBuilder::with_isa(isa, default_libcall_names())),
+            context: FunctionBuilderContext::new(),
+            compiled: Vec::new(),
+        })
+    }
+
+    /// Call `function` with the VMContext `ctx`
+    ///
+    /// # Safety
+    /// `ctx` must be the VMContext of the module defining `function`, `args`
+    /// must have a slot for each parameter and `results` for each result
+    pub(crate) unsafe fn call(
+        &mut self,
+        function: &Function,
+        ctx: *mut c_void,
+        args: &[u64],
+        results: &mut [u64],
+    ) -> Result<(), String> {
+        let trampoline = self.get(&function.signature.clif)?;
+        trampoline(function.pointer, ctx, args.as_ptr(), results.as_mut_ptr());
+        Ok(())
+    }
+
+    fn get(&mut self, signature: &ir::Signature) -> Result<TrampolineFunc, String> {
+        let compiled = self
+            .compiled
+            .iter()
+            .find(|(compiled, _)| compiled == signature);
+
+        if let Some((_, trampoline)) = compiled {
+            return Ok(*trampoline);
+        }
+
+        let trampoline = self.compile(signature)?;
+        self.compiled.push((signature.clone(), trampoline));
+        Ok(trampoline)
+    }
+
+    fn compile(&mut self, signature: &ir::Signature) -> Result<TrampolineFunc, String> {
+        let mut trampoline = ir::Signature::new(CALL_CONV);
+        trampoline.params = vec![AbiParam::new(POINTER_TYPE); 4];
+
+        let name = format!("trampoline_{}", self.compiled.len());
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &trampoline)
+            .map_err(|err| err.to_string())?;
+
+        let mut context = self.module.make_context();
+        context.func = ir::Function::with_name_signature(
+            ExternalName::user(0, self.compiled.len() as u32),
+            trampoline,
+        );
+
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut self.context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let params = builder.block_params(entry).to_vec();
+        let (callee, ctx, args, results) = (params[0], params[1], params[2], params[3]);
+        let flags = MemFlags::trusted();
+
+        // The first parameter of the callee is the VMContext
+        let mut values = vec![ctx];
+        for (index, param) in signature.params[1..].iter().enumerate() {
+            let offset = index as i32 * 8;
+            values.push(builder.ins().load(param.value_type, flags, args, offset));
+        }
+
+        let callee_ref = builder.import_signature(signature.clone());
+        let inst = builder.ins().call_indirect(callee_ref, callee, &values);
+
+        let returns = builder.inst_results(inst).to_vec();
+        for (index, value) in returns.into_iter().enumerate() {
+            builder.ins().store(flags, value, results, index as i32 * 8);
+        }
+
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        self.module
+            .define_function(id, &mut context, &mut NullTrapSink::default())
+            .map_err(|err| err.to_string())?;
+        self.module.finalize_definitions();
+
+        let pointer = self.module.get_finalized_function(id);
+        Ok(unsafe { std::mem::transmute::<*const u8, TrampolineFunc>(pointer) })
+    }
+}
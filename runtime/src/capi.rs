@@ -0,0 +1,406 @@
+//! Stable C API to embed the runtime in hosts that aren't written in Rust,
+//! enabled with the `capi` feature. See `include/fabric.h` for the
+//! declarations of these functions
+//!
+//! The modules are opaque `FabricModule` handles instead of `VMContext<E>`:
+//! their function imports are resolved by a callback of the host, and their
+//! exports are called with the arguments and results in arrays of 64 bits
+//! slots. The errors are reported by a status code or a NULL handle, with a
+//! description available from `fabric_last_error`
+
+use std::{
+    cell::RefCell,
+    ffi::{c_void, CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use cranelift_wasm::WasmType;
+use log::warn;
+
+use crate::{
+    backend::cranelift::{instantiate, Trampolines},
+    take_host_panic, Abort, Environment, Function, GlobalValue, VMContext,
+};
+
+/// Function provided by the host for an import, filled by the import callback
+///
+/// `pointer` is called with the VMContext pointer of the module followed by
+/// the parameters, with the ABI of `with_abi!` (`FABRIC_ABI` in the header).
+/// The types are codes of `FabricType` and the function has at most one result
+#[repr(C)]
+#[derive(Debug)]
+pub struct FabricFunction {
+    pub pointer: *const c_void,
+    pub params: *const u8,
+    pub num_params: usize,
+    pub results: *const u8,
+    pub num_results: usize,
+}
+
+/// Resolves the function import `module::name` by filling `function`,
+/// returns false if the host doesn't provide it
+pub type FabricImport = extern "C" fn(
+    user_data: *mut c_void,
+    module: *const c_char,
+    name: *const c_char,
+    function: *mut FabricFunction,
+) -> bool;
+
+/// Result of the functions of the API, the error is
+/// described by `fabric_last_error` unless it is `Ok`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FabricStatus {
+    Ok = 0,
+    /// The module doesn't export the function
+    NotFound = 1,
+    /// A pointer is NULL, or the arguments or results
+    /// don't match the signature of the function
+    InvalidArguments = 2,
+    /// The guest aborted during the call
+    Aborted = 3,
+    /// The call failed or a host function panicked
+    Error = 4,
+}
+
+/// A module loaded with `fabric_load_module`, freed with `fabric_free`
+pub struct FabricModule {
+    context: VMContext<CEnvironment>,
+    /// Trampolines calling the exports of the module with the arguments in
+    /// memory, since their signature is only known at runtime
+    trampolines: Trampolines,
+}
+
+/// Host environment of the modules loaded through the C API
+struct CEnvironment {
+    import: Option<FabricImport>,
+    user_data: *mut c_void,
+    /// Abort reported by the guest during the current call
+    abort: Option<String>,
+}
+
+impl Environment for CEnvironment {
+    fn import_function(&mut self, module: &str, name: &str) -> Option<Function> {
+        let import = self.import?;
+        let module = CString::new(module).ok()?;
+        let name = CString::new(name).ok()?;
+
+        let mut function = FabricFunction {
+            pointer: ptr::null(),
+            params: ptr::null(),
+            num_params: 0,
+            results: ptr::null(),
+            num_results: 0,
+        };
+
+        if !import(
+            self.user_data,
+            module.as_ptr(),
+            name.as_ptr(),
+            &mut function,
+        ) || function.pointer.is_null()
+        {
+            return None;
+        }
+
+        let params = unsafe { wasm_types(function.params, function.num_params) };
+        let results = unsafe { wasm_types(function.results, function.num_results) };
+        let (params, results) = match (params, results) {
+            (Some(params), Some(results)) => (params, results),
+            _ => {
+                warn!("invalid types for import {:?}::{:?}", module, name);
+                return None;
+            }
+        };
+
+        match unsafe { Function::from_types(function.pointer as *const u8, &params, &results) } {
+            Ok(function) => Some(function),
+            Err(err) => {
+                warn!(
+                    "invalid signature for import {:?}::{:?}: {}",
+                    module, name, err
+                );
+                None
+            }
+        }
+    }
+
+    fn import_global(&mut self, _module: &str, _name: &str) -> Option<GlobalValue> {
+        None
+    }
+
+    fn on_abort(&mut self, abort: &Abort) {
+        warn!("guest aborted: {}", abort);
+        self.abort = Some(abort.to_string());
+    }
+}
+
+/// Decode the `FabricType` codes of an array of `len` types
+unsafe fn wasm_types(types: *const u8, len: usize) -> Option<Vec<WasmType>> {
+    if len == 0 {
+        return Some(Vec::new());
+    }
+
+    if types.is_null() {
+        return None;
+    }
+
+    slice::from_raw_parts(types, len)
+        .iter()
+        .map(|code| match code {
+            0 => Some(WasmType::I32),
+            1 => Some(WasmType::I64),
+            2 => Some(WasmType::F32),
+            3 => Some(WasmType::F64),
+            4 => Some(WasmType::ExternRef),
+            5 => Some(WasmType::FuncRef),
+            _ => None,
+        })
+        .collect()
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(error: impl Into<Vec<u8>>) {
+    let mut error = error.into();
+    error.retain(|byte| *byte != 0);
+    let error = CString::new(error).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+/// Run an entry point of the API, unwinding into the host is undefined
+/// behavior so a panic is reported as an error instead
+fn guard<R>(error: R, func: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(func)) {
+        Ok(value) => value,
+        Err(_) => {
+            set_last_error("the runtime panicked");
+            error
+        }
+    }
+}
+
+/// Description of the last error of the API on this thread, or NULL. The
+/// string is valid until the next call to the API on this thread
+#[no_mangle]
+pub extern "C" fn fabric_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Load a module from its WAT or binary `source` of `len` bytes and run its
+/// `start` function. The imports are resolved with `import`, called with
+/// `user_data`, which may be NULL if the module doesn't import functions
+///
+/// Returns NULL if the module is invalid or an import isn't provided
+///
+/// # Safety
+/// `source` must point to `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn fabric_load_module(
+    source: *const u8,
+    len: usize,
+    import: Option<FabricImport>,
+    user_data: *mut c_void,
+) -> *mut FabricModule {
+    if source.is_null() {
+        set_last_error("source is NULL");
+        return ptr::null_mut();
+    }
+
+    guard(ptr::null_mut(), || {
+        let source = match wat::parse_bytes(slice::from_raw_parts(source, len)) {
+            Ok(source) => source,
+            Err(err) => {
+                set_last_error(err.to_string());
+                return ptr::null_mut();
+            }
+        };
+
+        let environment = CEnvironment {
+            import,
+            user_data,
+            abort: None,
+        };
+
+        let module = instantiate(environment, &source).and_then(|context| {
+            Ok(FabricModule {
+                context,
+                trampolines: Trampolines::new()?,
+            })
+        });
+
+        match module {
+            Ok(module) => Box::into_raw(Box::new(module)),
+            Err(err) => {
+                set_last_error(err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Call the function exported by `module` as `name`, with `num_args`
+/// arguments read from `args` and `num_results` results written to
+/// `results`, which must match the signature of the function
+///
+/// Each value is in a 64 bits slot: the integers are zero-extended, the
+/// floats are stored as their bits, the externrefs and funcrefs are their
+/// handle
+///
+/// # Safety
+/// `module` must be a handle returned by `fabric_load_module`, `name` a
+/// NUL-terminated string, `args` and `results` arrays of at least
+/// `num_args` and `num_results` slots
+#[no_mangle]
+pub unsafe extern "C" fn fabric_call(
+    module: *mut FabricModule,
+    name: *const c_char,
+    args: *const u64,
+    num_args: usize,
+    results: *mut u64,
+    num_results: usize,
+) -> FabricStatus {
+    let module = match module.as_mut() {
+        Some(module) => module,
+        None => {
+            set_last_error("module is NULL");
+            return FabricStatus::InvalidArguments;
+        }
+    };
+
+    if name.is_null() || (args.is_null() && num_args > 0) || (results.is_null() && num_results > 0)
+    {
+        set_last_error("name, args or results is NULL");
+        return FabricStatus::InvalidArguments;
+    }
+
+    guard(FabricStatus::Error, || {
+        let name = CStr::from_ptr(name).to_string_lossy();
+        let function = match module.context.export(&name) {
+            Some(function) => function.clone(),
+            None => {
+                set_last_error(format!("{} is not exported", name));
+                return FabricStatus::NotFound;
+            }
+        };
+
+        let signature = &function.signature.wasm;
+        if signature.params.len() != num_args || signature.returns.len() != num_results {
+            set_last_error(format!(
+                "{} takes {} arguments and returns {} results, got {} and {}",
+                name,
+                signature.params.len(),
+                signature.returns.len(),
+                num_args,
+                num_results
+            ));
+            return FabricStatus::InvalidArguments;
+        }
+
+        let args = match num_args {
+            0 => &[][..],
+            _ => slice::from_raw_parts(args, num_args),
+        };
+        let results = match num_results {
+            0 => &mut [][..],
+            _ => slice::from_raw_parts_mut(results, num_results),
+        };
+
+        let FabricModule {
+            context,
+            trampolines,
+        } = module;
+
+        context.environment.abort = None;
+        let ctx = context as *mut VMContext<CEnvironment> as *mut c_void;
+        if let Err(err) = trampolines.call(&function, ctx, args, results) {
+            set_last_error(err);
+            return FabricStatus::Error;
+        }
+
+        if let Some(abort) = context.environment.abort.take() {
+            set_last_error(abort);
+            return FabricStatus::Aborted;
+        }
+
+        if take_host_panic() {
+            set_last_error("a host function panicked");
+            return FabricStatus::Error;
+        }
+
+        FabricStatus::Ok
+    })
+}
+
+/// Linear memory of `module`, its size is written to `len`. The pointer is
+/// invalidated when the memory grows, it must be obtained again after
+/// each call to the module. Returns NULL if the module has no memory
+///
+/// # Safety
+/// `module` must be a handle returned by `fabric_load_module`
+/// and `len` NULL or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn fabric_memory(module: *mut FabricModule, len: *mut usize) -> *mut u8 {
+    match module.as_mut() {
+        Some(module) => memory(&mut module.context, len),
+        None => ptr::null_mut(),
+    }
+}
+
+/// User data of the module calling a host function, from the
+/// VMContext pointer received as the first parameter
+///
+/// # Safety
+/// `ctx` must be the VMContext pointer passed to a host function
+#[no_mangle]
+pub unsafe extern "C" fn fabric_context_user_data(ctx: *mut c_void) -> *mut c_void {
+    match (ctx as *mut VMContext<CEnvironment>).as_ref() {
+        Some(context) => context.environment.user_data,
+        None => ptr::null_mut(),
+    }
+}
+
+/// Linear memory of the module calling a host function, the pointers
+/// passed to the host function are offsets in this memory. See `fabric_memory`
+///
+/// # Safety
+/// `ctx` must be the VMContext pointer passed to a host
+/// function and `len` NULL or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn fabric_context_memory(ctx: *mut c_void, len: *mut usize) -> *mut u8 {
+    match (ctx as *mut VMContext<CEnvironment>).as_mut() {
+        Some(context) => memory(context, len),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe fn memory(context: &mut VMContext<CEnvironment>, len: *mut usize) -> *mut u8 {
+    let size = context.memory.len();
+    if let Some(len) = len.as_mut() {
+        *len = size;
+    }
+
+    match context.memory.bytes_mut(0, size) {
+        Ok(bytes) if size > 0 => bytes.as_mut_ptr(),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Free a module loaded with `fabric_load_module`, does nothing if `module` is NULL
+///
+/// # Safety
+/// `module` must be a handle returned by `fabric_load_module`, it must not
+/// be used afterwards and the module must not be running
+#[no_mangle]
+pub unsafe extern "C" fn fabric_free(module: *mut FabricModule) {
+    if !module.is_null() {
+        guard((), || Box::from_raw(module).context.unload());
+    }
+}
@@ -3,5 +3,16 @@
 mod backend;
 
 pub use crate::backend::cranelift::{
-    load_module, Environment, ExternRef, FuncRef, Function, GlobalValue, Loadable, VMContext,
+    catch_host_panic, inspect_module, load_module, record_host_panic, take_host_calls,
+    take_host_panic, Abort, Callback, CallbackRegistry, Environment, Exports, ExternError,
+    ExternRef, Externs, FuncRef, Function, GlobalValue, HostCall, HostValue, Import, Layer, Linker,
+    LiveExtern, Loadable, Memory, Middleware, ModuleInfo, PanicDefault, VMContext, RUNTIME_MODULE,
 };
+
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "fuzz")]
+pub use crate::backend::cranelift::{load_module_bytes, validate_module, NullEnvironment};
@@ -0,0 +1,305 @@
+//! Helpers to test the runtime and the host functions of an embedder on
+//! small WAT modules, enabled with the `testing` feature
+//!
+//! `MockEnvironment` resolves the imports from the functions and globals
+//! defined on it, or from closures, and records the hooks called by the
+//! runtime. `TestModule` loads a module in it, gets its exports and
+//! checks the state of its memory and externs:
+//!
+//! ```ignore
+//! let mut module = TestModule::load(
+//!     MockEnvironment::new(),
+//!     r#"(module (func (export "add") (param i32 i32) (result i32)
+//!         (i32.add (local.get 0) (local.get 1))))"#,
+//! );
+//!
+//! let add: with_abi!(fn(*mut MockContext, i32, i32) -> i32) = module.export("add");
+//! assert_eq!(add(&mut module.context, 2, 3), 5);
+//! ```
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    ffi::CStr,
+    fmt::{self, Debug, Formatter},
+};
+
+use crate::{
+    backend::cranelift::{instantiate, NativeFunction},
+    Abort, Environment, Exports, ExternRef, Function, GlobalValue, Linker, VMContext,
+};
+
+/// Closure resolving the imports not defined on the environment
+type ImportFunc<T> = Box<dyn FnMut(&str, &str) -> Option<T>>;
+
+/// Host environment of the modules under test
+#[derive(Default)]
+pub struct MockEnvironment {
+    functions: HashMap<(String, String), Function>,
+    globals: HashMap<(String, String), GlobalValue>,
+    import_function: Option<ImportFunc<Function>>,
+    import_global: Option<ImportFunc<GlobalValue>>,
+    memory_grow: Option<Box<dyn FnMut(u32, u32) -> bool>>,
+    linker: Option<Linker>,
+    fuel: Option<isize>,
+    deterministic: bool,
+
+    /// Aborts reported by the module, in order
+    pub aborts: Vec<Abort>,
+    /// Memory growths requested by the module as (current, delta) pairs
+    pub memory_grows: Vec<(u32, u32)>,
+    /// Types of the objects moved to the externs arena of the module, in order
+    pub externs_created: Vec<&'static str>,
+    /// Names of the functions exported by the module, sorted
+    pub exports: Vec<String>,
+}
+
+impl Debug for MockEnvironment {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("MockEnvironment")
+            .field("functions", &self.functions)
+            .field("globals", &self.globals)
+            .field("aborts", &self.aborts)
+            .finish()
+    }
+}
+
+impl MockEnvironment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the function import `module::name`
+    pub fn function(mut self, module: &str, name: &str, function: Function) -> Self {
+        self.functions
+            .insert((module.into(), name.into()), function);
+        self
+    }
+
+    /// Define the global import `module::name`
+    pub fn global(mut self, module: &str, name: &str, value: GlobalValue) -> Self {
+        self.globals.insert((module.into(), name.into()), value);
+        self
+    }
+
+    /// Resolve the function imports that aren't defined with `function`
+    pub fn import_function(
+        mut self,
+        func: impl FnMut(&str, &str) -> Option<Function> + 'static,
+    ) -> Self {
+        self.import_function = Some(Box::new(func));
+        self
+    }
+
+    /// Resolve the global imports that aren't defined with `global`
+    pub fn import_global(
+        mut self,
+        func: impl FnMut(&str, &str) -> Option<GlobalValue> + 'static,
+    ) -> Self {
+        self.import_global = Some(Box::new(func));
+        self
+    }
+
+    /// Decide if the memory grows, it always does by default
+    pub fn on_memory_grow(mut self, func: impl FnMut(u32, u32) -> bool + 'static) -> Self {
+        self.memory_grow = Some(Box::new(func));
+        self
+    }
+
+    /// Compile the module with the middleware of `linker`
+    pub fn linker(mut self, linker: Linker) -> Self {
+        self.linker = Some(linker);
+        self
+    }
+
+    /// Compile the module with fuel metering, starting with `fuel`
+    pub fn fuel(mut self, fuel: isize) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+}
+
+impl Environment for MockEnvironment {
+    fn import_function(&mut self, module: &str, name: &str) -> Option<Function> {
+        if let Some(function) = self.functions.get(&(module.into(), name.into())) {
+            return Some(function.clone());
+        }
+
+        self.import_function.as_mut()?(module, name)
+    }
+
+    fn import_global(&mut self, module: &str, name: &str) -> Option<GlobalValue> {
+        if let Some(value) = self.globals.get(&(module.into(), name.into())) {
+            return Some(*value);
+        }
+
+        self.import_global.as_mut()?(module, name)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    fn initial_fuel(&self) -> Option<isize> {
+        self.fuel
+    }
+
+    fn on_memory_grow(&mut self, current: u32, delta: u32) -> bool {
+        self.memory_grows.push((current, delta));
+        match &mut self.memory_grow {
+            Some(func) => func(current, delta),
+            None => true,
+        }
+    }
+
+    fn on_extern_created(&mut self, type_name: &'static str, _live: usize) {
+        self.externs_created.push(type_name);
+    }
+
+    fn on_abort(&mut self, abort: &Abort) {
+        self.aborts.push(abort.clone());
+    }
+
+    fn on_instantiate(&mut self, exports: &Exports<'_>) {
+        self.exports = exports.names().map(String::from).collect();
+        self.exports.sort();
+    }
+
+    fn linker(&mut self) -> Option<&mut Linker> {
+        self.linker.as_mut()
+    }
+}
+
+/// VMContext of the modules loaded in a `MockEnvironment`
+pub type MockContext = VMContext<MockEnvironment>;
+
+/// A module loaded from a WAT source for a test
+///
+/// The assertions panic with a description of the mismatch
+pub struct TestModule<E: Environment = MockEnvironment> {
+    pub context: VMContext<E>,
+}
+
+impl<E: Environment> TestModule<E> {
+    /// Load the module and run its `start` function
+    ///
+    /// Panics if the module is invalid or rejected by the runtime
+    pub fn load(environment: E, source: &str) -> Self {
+        match Self::try_load(environment, source) {
+            Ok(module) => module,
+            Err(err) => panic!("could not load module: {}", err),
+        }
+    }
+
+    /// Load the module and run its `start` function, returns the
+    /// error if the module is invalid or rejected by the runtime
+    pub fn try_load(environment: E, source: &str) -> Result<Self, String> {
+        let source = wat::parse_str(source).map_err(|err| err.to_string())?;
+        let context = instantiate(environment, &source)?;
+        Ok(TestModule { context })
+    }
+
+    /// Get the export `name` as a native function of type `T`
+    ///
+    /// Panics if the module doesn't export `name` with this signature
+    pub fn export<T: NativeFunction>(&self, name: &str) -> T {
+        match self.context.export(name) {
+            Some(function) => match function.try_get() {
+                Ok(function) => function,
+                Err(err) => panic!("export {}: {}", name, err),
+            },
+            None => panic!("{} is not exported", name),
+        }
+    }
+
+    /// Write `data` to the memory at `offset`
+    pub fn write_memory(&mut self, offset: usize, data: &[u8]) {
+        if self.context.memory.store(offset, data).is_err() {
+            panic!(
+                "can't write {} bytes at {:#x}, the memory is {} bytes",
+                data.len(),
+                offset,
+                self.context.memory.len()
+            );
+        }
+    }
+
+    /// Read `len` bytes of the memory at `offset`
+    pub fn read_memory(&self, offset: usize, len: usize) -> &[u8] {
+        match self.context.memory.bytes(offset, len) {
+            Ok(bytes) => bytes,
+            Err(()) => panic!(
+                "can't read {} bytes at {:#x}, the memory is {} bytes",
+                len,
+                offset,
+                self.context.memory.len()
+            ),
+        }
+    }
+
+    /// Read the NUL-terminated string at `offset`
+    pub fn read_string(&self, offset: usize) -> String {
+        match self.context.memory.load::<CStr>(offset) {
+            Ok(string) => string.to_string_lossy().into_owned(),
+            Err(()) => panic!("no string at {:#x}", offset),
+        }
+    }
+
+    /// Check the memory at `offset` holds `expected`
+    pub fn assert_memory(&self, offset: usize, expected: &[u8]) {
+        let actual = self.read_memory(offset, expected.len());
+        if let Some(index) = (0..expected.len()).find(|&index| actual[index] != expected[index]) {
+            panic!(
+                "memory differs at {:#x}: {:#04x}, expected {:#04x}",
+                offset + index,
+                actual[index],
+                expected[index]
+            );
+        }
+    }
+
+    /// Check the memory holds `pages` pages of 64 KiB
+    pub fn assert_pages(&self, pages: u32) {
+        assert_eq!(
+            self.context.memory.pages(),
+            pages,
+            "unexpected number of memory pages"
+        );
+    }
+
+    /// Check `index` designates a live extern of type `T` equal to `expected`
+    pub fn assert_extern<T: Any + PartialEq + Debug>(&self, index: ExternRef, expected: &T) {
+        match self.context.externs.try_get_extern::<T>(index) {
+            Ok(value) => assert_eq!(value, expected, "unexpected value of {:?}", index),
+            Err(err) => panic!("{:?}: {}", index, err),
+        }
+    }
+
+    /// Check the number of live objects in the externs arena
+    pub fn assert_live_externs(&self, live: usize) {
+        assert_eq!(
+            self.context.externs.len(),
+            live,
+            "unexpected number of live externs"
+        );
+    }
+}
+
+impl TestModule<MockEnvironment> {
+    /// Check the aborts reported by the module, formatted as with `Display`
+    pub fn assert_aborts(&self, expected: &[&str]) {
+        let aborts: Vec<_> = self
+            .context
+            .environment
+            .aborts
+            .iter()
+            .map(Abort::to_string)
+            .collect();
+        assert_eq!(aborts, expected, "unexpected aborts");
+    }
+}
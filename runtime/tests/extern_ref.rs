@@ -0,0 +1,343 @@
+//! Host functions returning externrefs to the guest
+#![cfg(feature = "testing")]
+
+use fabric_runtime::{
+    testing::{MockContext, MockEnvironment, TestModule},
+    with_abi, ExternError, ExternRef, Externs, Function, HostCall, Linker, Middleware,
+};
+
+with_abi! {
+    fn create(ctx: *mut MockContext, value: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+        ctx.create_extern(value)
+    }
+}
+
+with_abi! {
+    fn create_null(_ctx: *mut MockContext) -> ExternRef {
+        ExternRef::null()
+    }
+}
+
+with_abi! {
+    fn read(ctx: *mut MockContext, value: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        *ctx.externs.get_extern::<i32>(value)
+    }
+}
+
+with_abi! {
+    fn create_other(ctx: *mut MockContext, value: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+        ctx.create_extern(i64::from(value))
+    }
+}
+
+with_abi! {
+    fn free(ctx: *mut MockContext, value: ExternRef) {
+        let ctx = unsafe { &mut *ctx };
+        ctx.externs.take_extern::<i32>(value);
+    }
+}
+
+// Codes returned by `try_read` for the externs it rejects
+const MALFORMED: i32 = -1;
+const DANGLING: i32 = -2;
+const WRONG_TYPE: i32 = -3;
+const OTHER: i32 = -4;
+
+with_abi! {
+    fn try_read(ctx: *mut MockContext, value: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        match ctx.externs.try_get_extern::<i32>(value) {
+            Ok(value) => *value,
+            Err(ExternError::Malformed(_)) => MALFORMED,
+            Err(ExternError::Dangling { .. }) => DANGLING,
+            Err(ExternError::WrongType { .. }) => WRONG_TYPE,
+            Err(_) => OTHER,
+        }
+    }
+}
+
+fn environment() -> MockEnvironment {
+    MockEnvironment::new()
+        .function(
+            "env",
+            "create",
+            Function::new(create as with_abi!(fn(_, _) -> _)),
+        )
+        .function(
+            "env",
+            "create_null",
+            Function::new(create_null as with_abi!(fn(_) -> _)),
+        )
+        .function(
+            "env",
+            "read",
+            Function::new(read as with_abi!(fn(_, _) -> _)),
+        )
+        .function(
+            "env",
+            "create_other",
+            Function::new(create_other as with_abi!(fn(_, _) -> _)),
+        )
+        .function("env", "free", Function::new(free as with_abi!(fn(_, _))))
+        .function(
+            "env",
+            "try_read",
+            Function::new(try_read as with_abi!(fn(_, _) -> _)),
+        )
+}
+
+type CreateFunc = with_abi!(fn(*mut MockContext, i32) -> ExternRef);
+
+#[test]
+fn return_externref() {
+    let mut module = TestModule::load(
+        environment(),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (func (export "create") (param i32) (result externref)
+                (local externref)
+                (local.set 1 (call $create (local.get 0)))
+                (local.get 1)))"#,
+    );
+
+    let create: CreateFunc = module.export("create");
+    let value = create(&mut module.context, 42);
+
+    module.assert_extern(value, &42);
+    module.assert_live_externs(1);
+    assert_eq!(module.context.environment.externs_created, ["i32"]);
+}
+
+#[test]
+fn return_externref_as_i64() {
+    // Guests without reference types import the externrefs as i64
+    let mut module = TestModule::load(
+        environment(),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result i64)))
+            (import "env" "read" (func $read (param i64) (result i32)))
+            (func (export "roundtrip") (param i32) (result i32)
+                (call $read (call $create (local.get 0)))))"#,
+    );
+
+    let roundtrip: with_abi!(fn(*mut MockContext, i32) -> i32) = module.export("roundtrip");
+    assert_eq!(roundtrip(&mut module.context, 7), 7);
+    module.assert_live_externs(1);
+}
+
+#[test]
+fn pass_returned_externref() {
+    let mut module = TestModule::load(
+        environment(),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (import "env" "read" (func $read (param externref) (result i32)))
+            (func (export "roundtrip") (param i32) (result i32)
+                (block (result externref)
+                    (call $create (local.get 0)))
+                (call $read)))"#,
+    );
+
+    let roundtrip: with_abi!(fn(*mut MockContext, i32) -> i32) = module.export("roundtrip");
+    assert_eq!(roundtrip(&mut module.context, 13), 13);
+}
+
+#[test]
+fn null_externref() {
+    let mut module = TestModule::load(
+        environment(),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (import "env" "create_null" (func $create_null (result externref)))
+            (func (export "is_null") (param i32) (result i32)
+                (if (result externref) (local.get 0)
+                    (then (call $create_null))
+                    (else (call $create (i32.const 1))))
+                (ref.is_null))
+            (func (export "null") (result externref)
+                (ref.null extern)))"#,
+    );
+
+    let is_null: with_abi!(fn(*mut MockContext, i32) -> i32) = module.export("is_null");
+    assert_eq!(is_null(&mut module.context, 1), 1);
+    assert_eq!(is_null(&mut module.context, 0), 0);
+
+    let null: with_abi!(fn(*mut MockContext) -> ExternRef) = module.export("null");
+    assert_eq!(null(&mut module.context), ExternRef::null());
+}
+
+#[test]
+fn check_externref_result() {
+    let err = TestModule::try_load(
+        environment(),
+        r#"(module (import "env" "create" (func (param i32) (result i32))))"#,
+    )
+    .err()
+    .expect("the import has the wrong result type");
+
+    assert!(
+        err.contains("expected ExternRef result, found I32"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn check_externref_export() {
+    let module = TestModule::load(
+        environment(),
+        r#"(module (func (export "null") (result externref) (ref.null extern)))"#,
+    );
+
+    let err = module
+        .context
+        .export("null")
+        .unwrap()
+        .try_get::<with_abi!(fn(*mut MockContext) -> i32)>()
+        .expect_err("the export has the wrong result type");
+
+    assert!(err.contains("expected results"), "{}", err);
+}
+
+struct Skip;
+
+impl Middleware for Skip {
+    fn before_call(&mut self, _call: &HostCall<'_>) -> bool {
+        false
+    }
+}
+
+#[test]
+fn skipped_call_returns_null() {
+    let mut linker = Linker::new();
+    linker.add_middleware(Skip);
+
+    let mut module = TestModule::load(
+        environment().linker(linker),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (func (export "create") (param i32) (result externref)
+                (call $create (local.get 0))))"#,
+    );
+
+    let create: CreateFunc = module.export("create");
+    assert_eq!(create(&mut module.context, 42), ExternRef::null());
+    module.assert_live_externs(0);
+}
+
+#[test]
+fn drain_live_externs() {
+    let mut module = TestModule::load(
+        environment(),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (func (export "create") (param i32) (result externref)
+                (call $create (local.get 0))))"#,
+    );
+
+    let create: CreateFunc = module.export("create");
+    let first = create(&mut module.context, 1);
+    let second = create(&mut module.context, 2);
+    module.context.externs.take_extern::<i32>(first);
+
+    let live = module.context.externs.drain();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].extern_ref, second);
+    assert_eq!(live[0].type_name, "i32");
+    assert_eq!(live[0].value.downcast_ref::<i32>(), Some(&2));
+    assert_eq!(
+        live[0].backtrace.is_some(),
+        cfg!(feature = "extern-backtraces")
+    );
+
+    module.assert_live_externs(0);
+}
+
+/// Load a guest passing the externs as i64, its `run` export
+/// returns the result of `try_read` on the extern it built
+fn run_i64_guest(body: &str) -> i32 {
+    let mut module = TestModule::load(
+        environment(),
+        &format!(
+            r#"(module
+                (import "env" "create" (func $create (param i32) (result i64)))
+                (import "env" "create_other" (func $create_other (param i32) (result i64)))
+                (import "env" "free" (func $free (param i64)))
+                (import "env" "try_read" (func $try_read (param i64) (result i32)))
+                (func (export "run") (result i32)
+                    (local i64 i64)
+                    {}))"#,
+            body
+        ),
+    );
+
+    let run: with_abi!(fn(*mut MockContext) -> i32) = module.export("run");
+    run(&mut module.context)
+}
+
+#[test]
+fn reject_mismatched_tag() {
+    // The tag of the managed objects is the low byte of the value
+    let result =
+        run_i64_guest("(call $try_read (i64.xor (call $create (i32.const 7)) (i64.const 0x01)))");
+    assert_eq!(result, MALFORMED);
+
+    // A constant tag with the check byte of an object, the check byte
+    // of the first slot is 0 so the extern is read from the second one
+    let result = run_i64_guest(
+        "(drop (call $create (i32.const 1)))
+        (call $try_read (i64.and (call $create (i32.const 7)) (i64.const -256)))",
+    );
+    assert_eq!(result, MALFORMED);
+}
+
+#[test]
+fn reject_corrupted_check_byte() {
+    let result =
+        run_i64_guest("(call $try_read (i64.xor (call $create (i32.const 7)) (i64.const 0x100)))");
+    assert_eq!(result, MALFORMED);
+
+    // The check byte is derived from the index, changing the index alone is caught
+    let result = run_i64_guest(
+        "(call $try_read (i64.add (call $create (i32.const 7)) (i64.const 0x100000000)))",
+    );
+    assert_eq!(result, MALFORMED);
+}
+
+#[test]
+fn reject_stale_index() {
+    // The second object reuses the slot of the first one with a new generation
+    let result = run_i64_guest(
+        "(local.set 0 (call $create (i32.const 1)))
+        (call $free (local.get 0))
+        (local.set 1 (call $create (i32.const 2)))
+        (if (i32.ne (call $try_read (local.get 1)) (i32.const 2))
+            (then (return (i32.const 0))))
+        (call $try_read (local.get 0))",
+    );
+    assert_eq!(result, DANGLING);
+}
+
+#[test]
+fn reuse_slot_past_max_generation() {
+    let mut externs = Externs::default();
+
+    // The generation of the slot wraps around instead of overflowing
+    for value in 0..=u32::from(u16::MAX) + 1 {
+        let handle = externs.create_extern(value);
+        assert_eq!(externs.take_extern::<u32>(handle), value);
+    }
+
+    let handle = externs.create_extern(7u32);
+    assert_eq!(externs.try_get_extern::<u32>(handle).ok(), Some(&7));
+    assert_eq!(externs.len(), 1);
+}
+
+#[test]
+fn reject_wrong_type() {
+    let result = run_i64_guest("(call $try_read (call $create_other (i32.const 7)))");
+    assert_eq!(result, WRONG_TYPE);
+}
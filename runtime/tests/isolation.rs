@@ -0,0 +1,146 @@
+//! Two modules loaded from the same source don't share any state
+#![cfg(feature = "testing")]
+
+use fabric_runtime::{
+    testing::{MockContext, MockEnvironment, TestModule},
+    with_abi, ExternError, ExternRef, Function,
+};
+
+with_abi! {
+    fn create(ctx: *mut MockContext, value: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+        ctx.create_extern(value)
+    }
+}
+
+// Returned by `try_read` for the externs that aren't live in the module
+const DANGLING: i32 = -1;
+const INVALID: i32 = -2;
+
+with_abi! {
+    fn try_read(ctx: *mut MockContext, value: ExternRef) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+        match ctx.externs.try_get_extern::<i32>(value) {
+            Ok(value) => *value,
+            Err(ExternError::Dangling { .. }) => DANGLING,
+            Err(_) => INVALID,
+        }
+    }
+}
+
+/// Module with a counter at address 16, and exports
+/// creating and reading externs in its arena
+const SOURCE: &str = r#"(module
+    (import "env" "create" (func $create (param i32) (result externref)))
+    (import "env" "try_read" (func $try_read (param externref) (result i32)))
+    (memory (export "memory") 1)
+    (func (export "increment") (result i32)
+        (i32.store (i32.const 16) (i32.add (i32.load (i32.const 16)) (i32.const 1)))
+        (i32.load (i32.const 16)))
+    (func (export "create") (param i32) (result externref)
+        (call $create (local.get 0)))
+    (func (export "read") (param externref) (result i32)
+        (call $try_read (local.get 0))))"#;
+
+type IncrementFunc = with_abi!(fn(*mut MockContext) -> i32);
+type CreateFunc = with_abi!(fn(*mut MockContext, i32) -> ExternRef);
+type ReadFunc = with_abi!(fn(*mut MockContext, ExternRef) -> i32);
+
+fn load_pair() -> (TestModule, TestModule) {
+    let environment = || {
+        MockEnvironment::new()
+            .function(
+                "env",
+                "create",
+                Function::new(create as with_abi!(fn(_, _) -> _)),
+            )
+            .function(
+                "env",
+                "try_read",
+                Function::new(try_read as with_abi!(fn(_, _) -> _)),
+            )
+    };
+
+    (
+        TestModule::load(environment(), SOURCE),
+        TestModule::load(environment(), SOURCE),
+    )
+}
+
+#[test]
+fn separate_memories() {
+    let (mut first, mut second) = load_pair();
+
+    let increment: IncrementFunc = first.export("increment");
+    assert_eq!(increment(&mut first.context), 1);
+    assert_eq!(increment(&mut first.context), 2);
+    first.assert_memory(16, &[2, 0, 0, 0]);
+    second.assert_memory(16, &[0, 0, 0, 0]);
+
+    let increment: IncrementFunc = second.export("increment");
+    assert_eq!(increment(&mut second.context), 1);
+    first.assert_memory(16, &[2, 0, 0, 0]);
+
+    second.write_memory(32, b"second\0");
+    assert_eq!(second.read_string(32), "second");
+    first.assert_memory(32, &[0; 7]);
+}
+
+#[test]
+fn separate_extern_arenas() {
+    let (mut first, mut second) = load_pair();
+
+    let create: CreateFunc = first.export("create");
+    let value = create(&mut first.context, 42);
+
+    first.assert_extern(value, &42);
+    first.assert_live_externs(1);
+    second.assert_live_externs(0);
+
+    let create: CreateFunc = second.export("create");
+    let other = create(&mut second.context, 7);
+
+    first.assert_extern(value, &42);
+    second.assert_extern(other, &7);
+    first.assert_live_externs(1);
+    second.assert_live_externs(1);
+
+    // Freeing the extern of a module leaves the arena of the other untouched
+    first.context.externs.take_extern::<i32>(value);
+    first.assert_live_externs(0);
+    second.assert_extern(other, &7);
+}
+
+#[test]
+fn foreign_handle_is_dangling() {
+    let (mut first, mut second) = load_pair();
+
+    let create: CreateFunc = first.export("create");
+    let value = create(&mut first.context, 42);
+
+    // The arena of the second module has no object in that slot
+    let read: ReadFunc = second.export("read");
+    assert_eq!(read(&mut second.context, value), DANGLING);
+    second.assert_live_externs(0);
+
+    let read: ReadFunc = first.export("read");
+    assert_eq!(read(&mut first.context, value), 42);
+}
+
+#[test]
+fn foreign_handle_resolves_in_own_arena() {
+    let (mut first, mut second) = load_pair();
+
+    // Both modules create an extern in the first slot of their arena,
+    // the handles are equal but each one only reaches its own object
+    let create: CreateFunc = first.export("create");
+    let value = create(&mut first.context, 42);
+    let create: CreateFunc = second.export("create");
+    let other = create(&mut second.context, 7);
+    assert_eq!(value, other);
+
+    let read: ReadFunc = second.export("read");
+    assert_eq!(read(&mut second.context, value), 7);
+    let read: ReadFunc = first.export("read");
+    assert_eq!(read(&mut first.context, other), 42);
+}
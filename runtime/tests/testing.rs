@@ -0,0 +1,190 @@
+//! The mock environment and the helpers of the `testing` module
+#![cfg(feature = "testing")]
+
+use std::{cell::RefCell, rc::Rc};
+
+use fabric_runtime::{
+    testing::{MockContext, MockEnvironment, TestModule},
+    with_abi, ExternRef, Function, GlobalValue,
+};
+
+with_abi! {
+    fn double(_ctx: *mut MockContext, value: i32) -> i32 {
+        value * 2
+    }
+}
+
+with_abi! {
+    fn create(ctx: *mut MockContext, value: i32) -> ExternRef {
+        let ctx = unsafe { &mut *ctx };
+        ctx.create_extern(value)
+    }
+}
+
+#[test]
+fn resolve_imports() {
+    let resolved = Rc::new(RefCell::new(Vec::new()));
+    let environment = {
+        let resolved = resolved.clone();
+        MockEnvironment::new()
+            .function(
+                "env",
+                "double",
+                Function::new(double as with_abi!(fn(_, _) -> _)),
+            )
+            .global("env", "base", GlobalValue::Const(100))
+            .import_function(move |module, name| {
+                resolved.borrow_mut().push(format!("{}::{}", module, name));
+                match name {
+                    "triple" => Some(Function::new(double as with_abi!(fn(_, _) -> _))),
+                    _ => None,
+                }
+            })
+            .import_global(|_, name| match name {
+                "offset" => Some(GlobalValue::Const(7)),
+                _ => None,
+            })
+    };
+
+    let mut module = TestModule::load(
+        environment,
+        r#"(module
+            (import "env" "double" (func $double (param i32) (result i32)))
+            (import "env" "triple" (func $triple (param i32) (result i32)))
+            (import "env" "base" (global $base externref))
+            (import "env" "offset" (global $offset externref))
+            (func (export "run") (param i32) (result i32)
+                (i32.add (call $double (local.get 0)) (call $triple (local.get 0))))
+            (func (export "base") (result externref)
+                (global.get $base))
+            (func (export "offset") (result externref)
+                (global.get $offset)))"#,
+    );
+
+    // The functions defined on the environment take precedence over the closure
+    assert_eq!(*resolved.borrow(), ["env::triple"]);
+    assert_eq!(
+        module.context.environment.exports,
+        ["base", "offset", "run"]
+    );
+
+    let run: with_abi!(fn(*mut MockContext, i32) -> i32) = module.export("run");
+    assert_eq!(run(&mut module.context, 5), 20);
+
+    // The global imports are constant externrefs
+    let base: with_abi!(fn(*mut MockContext) -> ExternRef) = module.export("base");
+    assert_eq!(base(&mut module.context).value(), 100);
+    let offset: with_abi!(fn(*mut MockContext) -> ExternRef) = module.export("offset");
+    assert_eq!(offset(&mut module.context).value(), 7);
+}
+
+#[test]
+fn reject_unresolved_import() {
+    let err = TestModule::try_load(
+        MockEnvironment::new(),
+        r#"(module (import "env" "missing" (func)))"#,
+    )
+    .err()
+    .expect("the import is not defined");
+
+    assert!(err.contains("missing"), "{}", err);
+}
+
+#[test]
+fn access_memory() {
+    let mut module = TestModule::load(
+        MockEnvironment::new(),
+        r#"(module
+            (memory 1)
+            (data (i32.const 8) "fabric\00")
+            (func (export "sum") (param i32 i32) (result i32)
+                (i32.add (i32.load8_u (local.get 0)) (i32.load8_u (local.get 1)))))"#,
+    );
+
+    module.assert_pages(1);
+    assert_eq!(module.read_string(8), "fabric");
+    module.assert_memory(8, b"fab");
+
+    module.write_memory(0, &[3, 4]);
+    assert_eq!(module.read_memory(0, 3), [3, 4, 0]);
+
+    let sum: with_abi!(fn(*mut MockContext, i32, i32) -> i32) = module.export("sum");
+    assert_eq!(sum(&mut module.context, 0, 1), 7);
+}
+
+#[test]
+fn record_memory_grows() {
+    let mut module = TestModule::load(
+        MockEnvironment::new().on_memory_grow(|current, delta| current + delta <= 2),
+        r#"(module
+            (memory 1)
+            (func (export "grow") (param i32) (result i32)
+                (memory.grow (local.get 0))))"#,
+    );
+
+    let grow: with_abi!(fn(*mut MockContext, i32) -> i32) = module.export("grow");
+    assert_eq!(grow(&mut module.context, 1), 1);
+    assert_eq!(grow(&mut module.context, 1), -1);
+
+    module.assert_pages(2);
+    assert_eq!(module.context.environment.memory_grows, [(1, 1), (2, 1)]);
+}
+
+#[test]
+fn record_aborts() {
+    let mut module = TestModule::load(
+        MockEnvironment::new(),
+        r#"(module
+            (import "fabric" "abort" (func $abort (param i32 i32 i32)))
+            (memory 1)
+            (data (i32.const 16) "failed\00")
+            (data (i32.const 32) "lib.rs\00")
+            (func (export "abort")
+                (call $abort (i32.const 16) (i32.const 32) (i32.const 12))
+                (unreachable))
+            (func (export "trap")
+                (unreachable)))"#,
+    );
+
+    let abort: with_abi!(fn(*mut MockContext)) = module.export("abort");
+    abort(&mut module.context);
+    let trap: with_abi!(fn(*mut MockContext)) = module.export("trap");
+    trap(&mut module.context);
+
+    module.assert_aborts(&["failed at lib.rs:12", "unreachable code executed"]);
+}
+
+#[test]
+fn record_externs() {
+    let mut module = TestModule::load(
+        MockEnvironment::new().function(
+            "env",
+            "create",
+            Function::new(create as with_abi!(fn(_, _) -> _)),
+        ),
+        r#"(module
+            (import "env" "create" (func $create (param i32) (result externref)))
+            (func (export "create") (param i32) (result externref)
+                (call $create (local.get 0))))"#,
+    );
+
+    let create: with_abi!(fn(*mut MockContext, i32) -> ExternRef) = module.export("create");
+    let first = create(&mut module.context, 1);
+    let second = create(&mut module.context, 2);
+
+    module.assert_extern(first, &1);
+    module.assert_extern(second, &2);
+    module.assert_live_externs(2);
+    assert_eq!(module.context.environment.externs_created, ["i32", "i32"]);
+}
+
+#[test]
+#[should_panic(expected = "memory differs at 0x9")]
+fn report_memory_mismatch() {
+    let module = TestModule::load(
+        MockEnvironment::new(),
+        r#"(module (memory 1) (data (i32.const 8) "fabric"))"#,
+    );
+
+    module.assert_memory(8, b"fun");
+}
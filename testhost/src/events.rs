@@ -0,0 +1,342 @@
+//! Scriptable game event manager handed to the addon as GAMEEVENTSMANAGER002
+//!
+//! The events are flat maps of strings converted on access, like the KeyValues
+//! of the engine. The events fired by the script and by the modules are
+//! dispatched synchronously to the listeners registered for their name
+
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CStr, CString},
+    os::raw::c_int,
+    ptr::null_mut,
+};
+
+use crate::foreign::Foreign;
+
+#[allow(non_camel_case_types)]
+pub(crate) type bf_write = c_void;
+#[allow(non_camel_case_types)]
+pub(crate) type bf_read = c_void;
+
+#[fabric_codegen::interface]
+pub(crate) trait GameEvent {
+    fn destructor(&self);
+    fn get_name(&self) -> &CStr;
+    fn is_reliable(&self) -> bool;
+    fn is_local(&self) -> bool;
+    fn is_empty(&mut self, name: &CStr) -> bool;
+    fn get_bool(&mut self, name: &CStr, default: bool) -> bool;
+    fn get_int(&mut self, name: &CStr, default: c_int) -> c_int;
+    fn get_uint64(&mut self, name: &CStr, default: u64) -> u64;
+    fn get_float(&mut self, name: &CStr, default: f32) -> f32;
+    fn get_string(&mut self, name: &CStr, default: &CStr) -> &CStr;
+    fn set_bool(&mut self, name: &CStr, value: bool);
+    fn set_int(&mut self, name: &CStr, value: c_int);
+    fn set_uint64(&mut self, name: &CStr, value: u64);
+    fn set_float(&mut self, name: &CStr, value: f32);
+    fn set_string(&mut self, name: &CStr, value: &CStr);
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait GameEventManager2 {
+    fn destructor(&self);
+    fn load_events_from_file(&mut self, file_name: &CStr) -> c_int;
+    fn reset(&mut self);
+    fn add_listener(&mut self, listener: *mut c_void, name: &CStr, server_side: bool) -> bool;
+    fn find_listener(&mut self, listener: *mut c_void, name: &CStr) -> bool;
+    fn remove_listener(&mut self, listener: *mut c_void);
+    fn create_event(&mut self, name: &CStr, force: bool, cookie: *mut c_int) -> *mut c_void;
+    fn fire_event(&mut self, event: *mut c_void, dont_broadcast: bool) -> bool;
+    fn fire_event_client_side(&mut self, event: &mut dyn GameEvent) -> bool;
+    fn duplicate_event(&mut self, event: *mut c_void) -> *mut c_void;
+    fn free_event(&mut self, event: *mut c_void);
+    fn serialize_event(&mut self, event: *mut c_void, buf: *mut bf_write) -> bool;
+    fn unserialize_event(&mut self, buf: *mut bf_read) -> *mut c_void;
+}
+
+#[fabric_codegen::interface]
+pub(crate) trait GameEventListener2 {
+    fn destructor(&self);
+    fn fire_game_event(&mut self, event: *mut c_void);
+    fn get_event_debug_id(&mut self) -> c_int;
+}
+
+/// Event created by the script or by a module
+#[derive(Clone)]
+pub(crate) struct FakeEvent {
+    name: CString,
+    keys: HashMap<CString, CString>,
+}
+
+impl FakeEvent {
+    /// Create an event from its name and `key=value` pairs
+    pub(crate) fn new(name: &str, pairs: &[(&str, &str)]) -> Option<Self> {
+        let mut keys = HashMap::new();
+        for (key, value) in pairs {
+            keys.insert(CString::new(*key).ok()?, CString::new(*value).ok()?);
+        }
+
+        Some(FakeEvent {
+            name: CString::new(name).ok()?,
+            keys,
+        })
+    }
+
+    fn value(&self, name: &CStr) -> Option<&str> {
+        self.keys.get(name)?.to_str().ok()
+    }
+
+    fn set(&mut self, name: &CStr, value: String) {
+        if let Ok(value) = CString::new(value) {
+            self.keys.insert(name.to_owned(), value);
+        }
+    }
+
+    /// Move the event to the heap behind the IGameEvent vtable,
+    /// it must be released with `free_event`
+    pub(crate) fn into_raw(self) -> *mut c_void {
+        let event = Box::new(CGameEvent {
+            vtable: &EVENT_VTABLE,
+            instance: Box::new(self),
+        });
+
+        Box::into_raw(event) as *mut c_void
+    }
+}
+
+impl GameEvent for FakeEvent {
+    fn destructor(&self) {}
+
+    fn get_name(&self) -> &CStr {
+        &self.name
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn is_empty(&mut self, name: &CStr) -> bool {
+        !self.keys.contains_key(name)
+    }
+
+    fn get_bool(&mut self, name: &CStr, default: bool) -> bool {
+        match self.value(name) {
+            Some("true") => true,
+            Some("false") => false,
+            _ => self.get_int(name, default as c_int) != 0,
+        }
+    }
+
+    fn get_int(&mut self, name: &CStr, default: c_int) -> c_int {
+        self.value(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn get_uint64(&mut self, name: &CStr, default: u64) -> u64 {
+        self.value(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn get_float(&mut self, name: &CStr, default: f32) -> f32 {
+        self.value(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn get_string(&mut self, name: &CStr, default: &CStr) -> &CStr {
+        match self.keys.get(name) {
+            Some(value) => value.as_c_str(),
+            None => unsafe { CStr::from_ptr(default.as_ptr()) },
+        }
+    }
+
+    fn set_bool(&mut self, name: &CStr, value: bool) {
+        self.set(name, (value as c_int).to_string());
+    }
+
+    fn set_int(&mut self, name: &CStr, value: c_int) {
+        self.set(name, value.to_string());
+    }
+
+    fn set_uint64(&mut self, name: &CStr, value: u64) {
+        self.set(name, value.to_string());
+    }
+
+    fn set_float(&mut self, name: &CStr, value: f32) {
+        self.set(name, value.to_string());
+    }
+
+    fn set_string(&mut self, name: &CStr, value: &CStr) {
+        self.set(name, value.to_string_lossy().into_owned());
+    }
+}
+
+static EVENT_VTABLE: IGameEvent = <dyn GameEvent>::vtable::<Box<FakeEvent>, FakeEvent>();
+
+/// Listener registered by the addon
+/// Listener registered by the addon, identified by its address like in the engine
+struct Listener {
+    name: CString,
+    listener: *mut c_void,
+}
+
+/// Implementation of GameEventManager2 dispatching the
+/// events to the listeners of the addon
+pub(crate) struct FakeManager {
+    listeners: Vec<Listener>,
+    /// Events fired since the start of the script, by name
+    fired: HashMap<String, usize>,
+}
+
+impl FakeManager {
+    /// Number of listeners registered for `name`
+    pub(crate) fn listener_count(&self, name: &str) -> usize {
+        self.listeners
+            .iter()
+            .filter(|listener| listener.name.to_bytes() == name.as_bytes())
+            .count()
+    }
+
+    /// Number of times `name` was fired, by the script or by the modules
+    pub(crate) fn fired_count(&self, name: &str) -> usize {
+        self.fired.get(name).copied().unwrap_or(0)
+    }
+}
+
+impl GameEventManager2 for FakeManager {
+    fn destructor(&self) {}
+
+    fn load_events_from_file(&mut self, _file_name: &CStr) -> c_int {
+        0
+    }
+
+    fn reset(&mut self) {
+        self.listeners.clear();
+    }
+
+    fn add_listener(&mut self, listener: *mut c_void, name: &CStr, _server_side: bool) -> bool {
+        if !self.find_listener(listener, name) {
+            self.listeners.push(Listener {
+                name: name.to_owned(),
+                listener,
+            });
+        }
+
+        true
+    }
+
+    fn find_listener(&mut self, listener: *mut c_void, name: &CStr) -> bool {
+        self.listeners
+            .iter()
+            .any(|other| other.listener == listener && *other.name == *name)
+    }
+
+    fn remove_listener(&mut self, listener: *mut c_void) {
+        self.listeners.retain(|other| other.listener != listener);
+    }
+
+    fn create_event(&mut self, name: &CStr, _force: bool, _cookie: *mut c_int) -> *mut c_void {
+        match FakeEvent::new(&name.to_string_lossy(), &[]) {
+            Some(event) => event.into_raw(),
+            None => null_mut(),
+        }
+    }
+
+    fn fire_event(&mut self, event: *mut c_void, _dont_broadcast: bool) -> bool {
+        if event.is_null() {
+            return false;
+        }
+
+        let name = {
+            let event = unsafe { &*(event as *mut CGameEvent<Box<FakeEvent>>) };
+            event.instance.name.clone()
+        };
+
+        *self
+            .fired
+            .entry(name.to_string_lossy().into_owned())
+            .or_insert(0) += 1;
+
+        // The listeners may fire or create events from the callback
+        // and reenter the manager, they are collected beforehand
+        let listeners: Vec<_> = self
+            .listeners
+            .iter()
+            .filter(|listener| listener.name == name)
+            .map(|listener| listener.listener)
+            .collect();
+
+        for listener in listeners {
+            Foreign::<dyn GameEventListener2>::with(listener).fire_game_event(event);
+        }
+
+        self.free_event(event);
+        true
+    }
+
+    fn fire_event_client_side(&mut self, _event: &mut dyn GameEvent) -> bool {
+        false
+    }
+
+    fn duplicate_event(&mut self, event: *mut c_void) -> *mut c_void {
+        if event.is_null() {
+            return null_mut();
+        }
+
+        let event = unsafe { &*(event as *mut CGameEvent<Box<FakeEvent>>) };
+        FakeEvent::clone(&event.instance).into_raw()
+    }
+
+    fn free_event(&mut self, event: *mut c_void) {
+        if !event.is_null() {
+            drop(unsafe { Box::from_raw(event as *mut CGameEvent<Box<FakeEvent>>) });
+        }
+    }
+
+    fn serialize_event(&mut self, _event: *mut c_void, _buf: *mut bf_write) -> bool {
+        false
+    }
+
+    fn unserialize_event(&mut self, _buf: *mut bf_read) -> *mut c_void {
+        null_mut()
+    }
+}
+
+static MANAGER_VTABLE: IGameEventManager2 =
+    <dyn GameEventManager2>::vtable::<Box<FakeManager>, FakeManager>();
+
+static mut MANAGER: Option<Box<CGameEventManager2<Box<FakeManager>>>> = None;
+
+/// Pointer to the manager given to the addon, created on first use
+pub(crate) fn manager_ptr() -> *mut c_void {
+    let manager = unsafe {
+        MANAGER.get_or_insert_with(|| {
+            Box::new(CGameEventManager2 {
+                vtable: &MANAGER_VTABLE,
+                instance: Box::new(FakeManager {
+                    listeners: Vec::new(),
+                    fired: HashMap::new(),
+                }),
+            })
+        })
+    };
+
+    &mut **manager as *mut CGameEventManager2<Box<FakeManager>> as *mut c_void
+}
+
+/// The manager, through its vtable like the addon calls it
+pub(crate) fn manager() -> Foreign<dyn GameEventManager2> {
+    Foreign::with(manager_ptr())
+}
+
+/// State of the manager, for the expectations of the script
+pub(crate) fn state() -> &'static FakeManager {
+    manager_ptr();
+    unsafe { &MANAGER.as_ref().unwrap().instance }
+}
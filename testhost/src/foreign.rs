@@ -0,0 +1,25 @@
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    os::raw::{c_char, c_int},
+};
+
+use log::error;
+
+#[repr(transparent)]
+pub(crate) struct Foreign<T: ?Sized>(pub(crate) *mut c_void, PhantomData<*mut T>);
+
+impl<T: ?Sized> Foreign<T> {
+    /// Wrap an object of the addon, the test host only has a single thread
+    pub(crate) fn with(ptr: *mut c_void) -> Self {
+        Foreign(ptr, PhantomData)
+    }
+}
+
+/// Called by the vtable shims when the addon's implementation of `method`
+/// panicked, there is no module to disable in the test host
+pub(crate) fn shim_panicked(method: &str) {
+    error!("panic in {}", method);
+}
+
+pub(crate) type CreateInterfaceFn = extern "C" fn(*const c_char, *mut c_int) -> *mut c_void;
@@ -0,0 +1,316 @@
+//! Headless host running the addon without a Source server
+//!
+//! The test host loads `fabric.dll` like the engine does and drives it through
+//! the plugin callbacks, following a scenario script. The engine factory only
+//! provides a scriptable GAMEEVENTSMANAGER002 and the logging system comes from
+//! the fake `tier0.dll` built with the host, the other interfaces are missing
+//! and the addon runs as it would on an engine that doesn't export them.
+//!
+//! ```text
+//! fabric-testhost target/debug/fabric.dll testhost/scenarios/example.txt
+//! ```
+//!
+//! The directory of the scenario is the working directory of the addon, the
+//! modules are discovered in its `modules` subdirectory and the data files of
+//! the addon are written there
+
+#![feature(abi_thiscall)]
+#![feature(const_fn)]
+#![feature(const_fn_fn_ptr_basics)]
+
+use std::{
+    env,
+    ffi::{c_void, CStr, CString},
+    fs,
+    os::raw::{c_char, c_int},
+    path::{Path, PathBuf},
+    process,
+    ptr::null_mut,
+};
+
+use fabric_codegen::cstr;
+
+mod events;
+mod foreign;
+mod plugin;
+
+use crate::{
+    events::{FakeEvent, GameEventManager2},
+    foreign::Foreign,
+    plugin::ServerPluginCallbacks,
+};
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn LoadLibraryA(lpLibFileName: *const c_char) -> *mut c_void;
+    fn GetProcAddress(hModule: *mut c_void, lpProcName: *const c_char) -> *mut c_void;
+}
+
+type FindLogFn = extern "C" fn(*const c_char) -> bool;
+type ClearLogFn = extern "C" fn();
+
+/// Functions of the fake tier0 inspecting the log of the addon
+struct Log {
+    find: FindLogFn,
+    clear: ClearLogFn,
+}
+
+impl Log {
+    fn contains(&self, needle: &str) -> bool {
+        match CString::new(needle) {
+            Ok(needle) => (self.find)(needle.as_ptr()),
+            Err(_) => false,
+        }
+    }
+}
+
+fn load_library(path: &Path) -> Result<*mut c_void, String> {
+    let name = CString::new(path.to_string_lossy().into_owned()).map_err(|err| err.to_string())?;
+    let library = unsafe { LoadLibraryA(name.as_ptr()) };
+    if library.is_null() {
+        Err(format!("could not load {}", path.display()))
+    } else {
+        Ok(library)
+    }
+}
+
+fn find_symbol(library: *mut c_void, symbol: &CStr) -> Result<*mut c_void, String> {
+    let address = unsafe { GetProcAddress(library, symbol.as_ptr()) };
+    if address.is_null() {
+        Err(format!("{} not found", symbol.to_string_lossy()))
+    } else {
+        Ok(address)
+    }
+}
+
+/// Engine factory of the test host
+extern "C" fn engine_factory(name: *const c_char, return_code: *mut c_int) -> *mut c_void {
+    let name = unsafe { CStr::from_ptr(name) };
+    let instance = if name.to_bytes() == b"GAMEEVENTSMANAGER002" {
+        events::manager_ptr()
+    } else {
+        null_mut()
+    };
+
+    if let Some(return_code) = unsafe { return_code.as_mut() } {
+        *return_code = instance.is_null() as c_int;
+    }
+
+    instance
+}
+
+/// Server factory of the test host, none of the interfaces of the game are provided
+extern "C" fn server_factory(_name: *const c_char, return_code: *mut c_int) -> *mut c_void {
+    if let Some(return_code) = unsafe { return_code.as_mut() } {
+        *return_code = 1;
+    }
+
+    null_mut()
+}
+
+/// Split `key=value` arguments of a directive
+fn parse_pairs<'a>(args: &[&'a str]) -> Result<Vec<(&'a str, &'a str)>, String> {
+    args.iter()
+        .map(|arg| {
+            let mut parts = arg.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => Ok((key, value)),
+                _ => Err(format!("expected key=value, got {}", arg)),
+            }
+        })
+        .collect()
+}
+
+fn parse_count(value: Option<&&str>, default: usize) -> Result<usize, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid count {}", value)),
+        None => Ok(default),
+    }
+}
+
+/// Execute a directive of the scenario
+fn execute(
+    plugin: &mut Foreign<dyn ServerPluginCallbacks>,
+    log: &Log,
+    directive: &str,
+    args: &[&str],
+    rest: &str,
+) -> Result<(), String> {
+    match directive {
+        "load" => {
+            if plugin.load(engine_factory, server_factory) {
+                Ok(())
+            } else {
+                Err("the addon failed to load".into())
+            }
+        }
+        "unload" => {
+            plugin.unload();
+            Ok(())
+        }
+        "pause" => {
+            plugin.pause();
+            Ok(())
+        }
+        "unpause" => {
+            plugin.unpause();
+            Ok(())
+        }
+        "level_init" => {
+            let map = CString::new(*args.first().ok_or("missing map name")?)
+                .map_err(|err| err.to_string())?;
+            plugin.level_init(&map);
+            Ok(())
+        }
+        "frame" => {
+            for _ in 0..parse_count(args.first(), 1)? {
+                plugin.game_frame(true);
+            }
+            Ok(())
+        }
+        "level_shutdown" => {
+            plugin.level_shutdown();
+            Ok(())
+        }
+        "fire" => {
+            let (name, pairs) = args.split_first().ok_or("missing event name")?;
+            let pairs = parse_pairs(pairs)?;
+            let event = FakeEvent::new(name, &pairs).ok_or("invalid event")?;
+            events::manager().fire_event(event.into_raw(), false);
+            Ok(())
+        }
+        "clear_log" => {
+            (log.clear)();
+            Ok(())
+        }
+        "expect_log" => {
+            if log.contains(rest) {
+                Ok(())
+            } else {
+                Err(format!("no line containing {:?} was logged", rest))
+            }
+        }
+        "expect_no_log" => {
+            if log.contains(rest) {
+                Err(format!("a line containing {:?} was logged", rest))
+            } else {
+                Ok(())
+            }
+        }
+        "expect_listeners" => {
+            let name = args.first().ok_or("missing event name")?;
+            let expected = parse_count(args.get(1), 1)?;
+            let count = events::state().listener_count(name);
+            if count == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} listeners for {}, expected {}",
+                    count, name, expected
+                ))
+            }
+        }
+        "expect_fired" => {
+            let name = args.first().ok_or("missing event name")?;
+            let expected = parse_count(args.get(1), 1)?;
+            let count = events::state().fired_count(name);
+            if count == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} fired {} times, expected {}",
+                    name, count, expected
+                ))
+            }
+        }
+        directive => Err(format!("unknown directive {}", directive)),
+    }
+}
+
+fn run(addon: &Path, scenario: &Path) -> Result<usize, String> {
+    let script = fs::read_to_string(scenario)
+        .map_err(|err| format!("could not read {}: {}", scenario.display(), err))?;
+
+    // The addon resolves tier0 by name when it is loaded, the fake
+    // must be in the process before the addon library
+    let tier0_path = env::current_exe()
+        .map_err(|err| err.to_string())?
+        .with_file_name("tier0.dll");
+    let tier0 = load_library(&tier0_path)?;
+    let log = unsafe {
+        Log {
+            find: std::mem::transmute(find_symbol(tier0, cstr!("TestHost_FindLog"))?),
+            clear: std::mem::transmute(find_symbol(tier0, cstr!("TestHost_ClearLog"))?),
+        }
+    };
+
+    // The paths given on the command line are resolved before
+    // moving to the directory of the scenario
+    let addon = fs::canonicalize(addon)
+        .map_err(|err| format!("could not find {}: {}", addon.display(), err))?;
+    if let Some(dir) = scenario.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        env::set_current_dir(dir)
+            .map_err(|err| format!("could not enter {}: {}", dir.display(), err))?;
+    }
+
+    let library = load_library(&addon)?;
+    let create_interface: foreign::CreateInterfaceFn =
+        unsafe { std::mem::transmute(find_symbol(library, cstr!("CreateInterface"))?) };
+
+    let mut return_code = 0;
+    let instance = create_interface(
+        cstr!("ISERVERPLUGINCALLBACKS003").as_ptr(),
+        &mut return_code,
+    );
+    if instance.is_null() || return_code != 0 {
+        return Err("the addon doesn't provide ISERVERPLUGINCALLBACKS003".into());
+    }
+
+    let mut plugin = Foreign::<dyn ServerPluginCallbacks>::with(instance);
+
+    let mut failures = 0;
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let directive = parts.next().unwrap_or_default();
+        let rest = parts.next().unwrap_or_default().trim();
+        let args: Vec<_> = rest.split_whitespace().collect();
+
+        println!("[testhost] {}", line);
+        if let Err(err) = execute(&mut plugin, &log, directive, &args, rest) {
+            println!("[testhost] line {}: {}", index + 1, err);
+            failures += 1;
+        }
+    }
+
+    Ok(failures)
+}
+
+fn main() {
+    let args: Vec<PathBuf> = env::args_os().skip(1).map(PathBuf::from).collect();
+    let (addon, scenario) = match args.as_slice() {
+        [addon, scenario] => (addon, scenario),
+        _ => {
+            eprintln!("usage: fabric-testhost <fabric.dll> <scenario>");
+            process::exit(2);
+        }
+    };
+
+    match run(addon, scenario) {
+        Ok(0) => println!("[testhost] scenario passed"),
+        Ok(failures) => {
+            println!("[testhost] {} directives failed", failures);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("[testhost] {}", err);
+            process::exit(2);
+        }
+    }
+}
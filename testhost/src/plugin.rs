@@ -0,0 +1,76 @@
+//! Binding for IServerPluginCallbacks, as the engine sees the addon
+//!
+//! The layout follows the declaration of the addon, the edicts and commands
+//! are opaque since the test host never passes any
+
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int},
+};
+
+use crate::foreign::CreateInterfaceFn;
+
+pub(crate) type Edict = c_void;
+pub(crate) type CCommand = c_void;
+
+#[repr(C)]
+#[derive(Debug)]
+#[allow(dead_code)]
+pub(crate) enum PluginResult {
+    Continue = 0,
+    Override,
+    Stop,
+}
+
+impl Default for PluginResult {
+    fn default() -> Self {
+        PluginResult::Continue
+    }
+}
+
+pub(crate) type QueryCvarCookie = c_int;
+pub(crate) type QueryCvarValueStatus = c_int;
+
+#[fabric_codegen::interface]
+pub(crate) trait ServerPluginCallbacks {
+    fn load(
+        &mut self,
+        interface_factory: CreateInterfaceFn,
+        game_server_factory: CreateInterfaceFn,
+    ) -> bool;
+    fn unload(&mut self);
+    fn pause(&mut self);
+    fn unpause(&mut self);
+    fn get_plugin_description(&mut self) -> &CStr;
+    fn level_init(&mut self, map_name: &CStr);
+    fn server_activate(&mut self, edict_list: *mut Edict, edict_count: c_int, client_max: c_int);
+    fn game_frame(&mut self, simulating: bool);
+    fn level_shutdown(&mut self);
+    fn client_active(&mut self, entity: *mut Edict);
+    fn client_fully_connect(&mut self, entity: *mut Edict);
+    fn client_disconnect(&mut self, entity: *mut Edict);
+    fn client_put_in_server(&mut self, entity: *mut Edict, player_name: &CStr);
+    fn set_command_client(&mut self, index: c_int);
+    fn client_settings_changed(&mut self, entity: *mut Edict);
+    fn client_connect(
+        &mut self,
+        allow_connect: *mut bool,
+        entity: *mut Edict,
+        name: &CStr,
+        address: &CStr,
+        reject: *mut c_char,
+        max_reject_len: c_int,
+    ) -> PluginResult;
+    fn client_command(&mut self, entity: *mut Edict, args: *const CCommand) -> PluginResult;
+    fn network_id_validated(&mut self, user_name: &CStr, network_id: &CStr) -> PluginResult;
+    fn on_query_cvar_value_finished(
+        &mut self,
+        cookie: QueryCvarCookie,
+        entity: *mut Edict,
+        status: QueryCvarValueStatus,
+        cvar_name: *mut c_char,
+        cvar_value: *mut c_char,
+    );
+    fn on_edict_allocated(&mut self, edict: *mut Edict);
+    fn on_edict_freed(&mut self, edict: *const Edict);
+}
@@ -0,0 +1,89 @@
+//! Fake tier0 library for the test host
+//!
+//! The addon resolves the logging system from `tier0.dll` when it is loaded,
+//! this library exports the same functions so the log of the addon is printed
+//! on the standard output and recorded for the `expect_log` directives
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+    sync::Mutex,
+};
+
+type LoggingChannelID = c_int;
+
+type RegisterTagsFunc = extern "C" fn();
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Color {
+    color: [u8; 4],
+}
+
+/// Lines logged since the start of the process, or since the last `TestHost_ClearLog`,
+/// created when the addon registers its channel before starting any thread
+static mut LINES: Option<Mutex<Vec<String>>> = None;
+
+fn lines() -> Option<&'static Mutex<Vec<String>>> {
+    unsafe { LINES.as_ref() }
+}
+
+const SEVERITIES: [&str; 4] = ["message", "warning", "assert", "error"];
+
+#[no_mangle]
+pub extern "C" fn LoggingSystem_RegisterLoggingChannel(
+    name: *const c_char,
+    _register_tags_func: RegisterTagsFunc,
+    _flags: c_int,
+    _severity: c_int,
+    _color: Color,
+) -> LoggingChannelID {
+    unsafe {
+        LINES.get_or_insert_with(|| Mutex::new(Vec::new()));
+    }
+
+    let name = unsafe { CStr::from_ptr(name) };
+    println!("[tier0] registered channel {}", name.to_string_lossy());
+    0
+}
+
+/// Print and record a line, the response is always `LR_CONTINUE`
+#[no_mangle]
+pub extern "C" fn LoggingSystem_LogDirect(
+    _channel_id: LoggingChannelID,
+    severity: c_int,
+    _color: Color,
+    message: *const c_char,
+) -> c_int {
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    let severity = SEVERITIES
+        .get(severity.max(0) as usize)
+        .copied()
+        .unwrap_or("unknown");
+
+    print!("[{}] {}", severity, message);
+
+    if let Some(Ok(mut lines)) = lines().map(Mutex::lock) {
+        lines.push(message.into_owned());
+    }
+
+    0
+}
+
+/// Whether a line containing `needle` was logged
+#[no_mangle]
+pub extern "C" fn TestHost_FindLog(needle: *const c_char) -> bool {
+    let needle = unsafe { CStr::from_ptr(needle) }.to_string_lossy();
+    match lines().map(Mutex::lock) {
+        Some(Ok(lines)) => lines.iter().any(|line| line.contains(&*needle)),
+        _ => false,
+    }
+}
+
+/// Forget the lines logged so far
+#[no_mangle]
+pub extern "C" fn TestHost_ClearLog() {
+    if let Some(Ok(mut lines)) = lines().map(Mutex::lock) {
+        lines.clear();
+    }
+}